@@ -2,6 +2,7 @@
 
 pub mod store;
 
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 use crate::core::VelocityResult;
@@ -14,9 +15,12 @@ pub struct CacheManager {
     /// Cache root directory
     cache_dir: PathBuf,
 
-    /// Content store for tarballs
+    /// Store holding each distinct package file once
     content_store: ContentStore,
 
+    /// Store holding registry metadata bodies by hash
+    metadata_store: ContentStore,
+
     /// Configuration
     config: CacheConfig,
 }
@@ -32,19 +36,170 @@ impl CacheManager {
         std::fs::create_dir_all(cache_dir.join("content"))?;
         std::fs::create_dir_all(cache_dir.join("metadata"))?;
 
-        let content_store = ContentStore::new(cache_dir.join("content"))?;
+        let content_store = ContentStore::new(cache_dir.join("files"))?;
+        let metadata_store = ContentStore::new(cache_dir.join("metadata").join("content"))?;
 
         Ok(Self {
             cache_dir,
             content_store,
+            metadata_store,
             config: config.clone(),
         })
     }
 
+    /// Root directory of the cache
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
     /// Check if a package is cached
+    ///
+    /// Only completed extractions count: a directory left behind by an
+    /// interrupted extraction has no marker and is extracted again.
     pub fn has_package(&self, name: &str, version: &str) -> VelocityResult<bool> {
         let package_dir = self.get_package_dir(name, version);
-        Ok(package_dir.exists())
+        Ok(package_dir.is_dir() && self.extraction_marker(name, version)?.is_some())
+    }
+
+    /// Integrity of the tarball a package was extracted from, if extraction completed
+    pub fn extraction_marker(&self, name: &str, version: &str) -> VelocityResult<Option<String>> {
+        let marker_path = self.marker_path(name, version);
+        if !marker_path.exists() {
+            return Ok(None);
+        }
+
+        let integrity = std::fs::read_to_string(&marker_path)?.trim().to_string();
+        Ok(if integrity.is_empty() { None } else { Some(integrity) })
+    }
+
+    /// Create a fresh temporary directory to extract a package into
+    pub fn temp_extract_dir(&self, name: &str, version: &str) -> VelocityResult<PathBuf> {
+        let safe_name = name.replace('/', "+").replace('@', "");
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+
+        let dir = self.cache_dir.join("tmp").join(format!(
+            "{}-{}-{}-{}",
+            safe_name,
+            version,
+            std::process::id(),
+            nanos
+        ));
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// Move a finished extraction into place and record its completion marker
+    pub fn commit_extraction(
+        &self,
+        name: &str,
+        version: &str,
+        temp_dir: &Path,
+        integrity: &str,
+    ) -> VelocityResult<PathBuf> {
+        let package_dir = self.get_package_dir(name, version);
+
+        // Leftovers of an interrupted extraction are replaced
+        if package_dir.exists() {
+            self.remove_package(name, version)?;
+        }
+        if let Some(parent) = package_dir.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Files already stored for another package become links to the same data
+        let files = self.content_store.import_dir(temp_dir)?;
+
+        if let Err(e) = std::fs::rename(temp_dir, &package_dir) {
+            // Another process finished the same package first
+            std::fs::remove_dir_all(temp_dir).ok();
+            if self.has_package(name, version)? {
+                return Ok(package_dir);
+            }
+            return Err(e.into());
+        }
+
+        // The file index goes first: a marker always has one to verify against
+        let index_path = self.file_index_path(name, version);
+        let temp_index = index_path.with_extension(format!("tmp-{}", std::process::id()));
+        std::fs::write(&temp_index, serde_json::to_vec(&files)?)?;
+        std::fs::rename(&temp_index, &index_path)?;
+
+        // Write the marker atomically so it is never observed half-written
+        let marker_path = self.marker_path(name, version);
+        let temp_marker = marker_path.with_extension(format!("tmp-{}", std::process::id()));
+        std::fs::write(&temp_marker, integrity)?;
+        std::fs::rename(&temp_marker, &marker_path)?;
+
+        Ok(package_dir)
+    }
+
+    /// Why a cached package cannot be trusted, if it cannot
+    ///
+    /// The package must have been extracted from the tarball `integrity` names
+    /// (when it is a sha512 hash, which the marker records) and its files must
+    /// still be the ones extracted: same content, none added or removed. Stored
+    /// copies found modified are dropped, so extracting again restores them.
+    pub fn verify_package(&self, name: &str, version: &str, integrity: &str) -> VelocityResult<Option<String>> {
+        let Some(marker) = self.extraction_marker(name, version)? else {
+            return Ok(Some("it is not fully extracted".to_string()));
+        };
+        if integrity.starts_with("sha512-") && marker != integrity {
+            return Ok(Some(format!("it was extracted from {}, not {}", marker, integrity)));
+        }
+
+        // Packages extracted before file indexes were recorded cannot be checked
+        let Ok(index) = std::fs::read(self.file_index_path(name, version)) else {
+            return Ok(Some("it has no file index".to_string()));
+        };
+        let mut expected: BTreeMap<String, String> = serde_json::from_slice(&index)?;
+
+        let package_dir = self.get_package_dir(name, version);
+        for entry in walkdir::WalkDir::new(&package_dir) {
+            let entry = entry.map_err(std::io::Error::from)?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = store::relative_key(&package_dir, entry.path());
+            // Linking can mark bin files executable, so only the content is compared
+            let Some(key) = expected.remove(&path) else {
+                return Ok(Some(format!("{} was added", path)));
+            };
+            if ContentStore::content_hash(entry.path())? != key.trim_end_matches("-exec") {
+                // An edit in place also changed the shared stored copy
+                self.content_store.evict_if_corrupt(&key)?;
+                return Ok(Some(format!("{} was modified", path)));
+            }
+        }
+        if let Some(path) = expected.keys().next() {
+            return Ok(Some(format!("{} was removed", path)));
+        }
+        Ok(None)
+    }
+
+    /// Drop a package's extraction so it is extracted again
+    pub fn remove_package(&self, name: &str, version: &str) -> VelocityResult<()> {
+        std::fs::remove_file(self.marker_path(name, version)).ok();
+        std::fs::remove_file(self.file_index_path(name, version)).ok();
+        let package_dir = self.get_package_dir(name, version);
+        if package_dir.exists() {
+            std::fs::remove_dir_all(&package_dir)?;
+        }
+        Ok(())
+    }
+
+    /// Completion marker path, stored next to the package directory
+    fn marker_path(&self, name: &str, version: &str) -> PathBuf {
+        let package_dir = self.get_package_dir(name, version);
+        package_dir.with_file_name(format!("{}.extracted", version))
+    }
+
+    /// Index of the files extracted for a package, with their store keys
+    fn file_index_path(&self, name: &str, version: &str) -> PathBuf {
+        let package_dir = self.get_package_dir(name, version);
+        package_dir.with_file_name(format!("{}.files", version))
     }
 
     /// Get the path to a package's extracted directory
@@ -61,6 +216,19 @@ impl CacheManager {
             .join(format!("{}-{}.tgz", safe_name, version))
     }
 
+    /// Where a tarball is written while it downloads, so an interrupted download can resume
+    pub fn get_partial_tarball_path(&self, name: &str, version: &str) -> PathBuf {
+        let mut path = self.get_tarball_path(name, version).into_os_string();
+        path.push(".part");
+        PathBuf::from(path)
+    }
+
+    /// Move a completed download into place as the package's tarball
+    pub fn commit_partial_tarball(&self, name: &str, version: &str) -> VelocityResult<()> {
+        std::fs::rename(self.get_partial_tarball_path(name, version), self.get_tarball_path(name, version))?;
+        Ok(())
+    }
+
     /// Store a tarball in the cache
     pub fn store_tarball(&self, name: &str, version: &str, data: &[u8]) -> VelocityResult<()> {
         let tarball_path = self.get_tarball_path(name, version);
@@ -76,24 +244,18 @@ impl CacheManager {
 
     /// Get cached metadata for a package
     pub fn get_metadata(&self, name: &str) -> VelocityResult<Option<CachedMetadata>> {
-        let safe_name = name.replace('/', "+").replace('@', "");
-        let metadata_path = self.cache_dir.join("metadata").join(format!("{}.json", safe_name));
+        self.get_metadata_with_ttl(name, self.config.metadata_ttl)
+    }
 
-        if !metadata_path.exists() {
+    /// Get cached metadata no older than `ttl` seconds (0 always misses)
+    pub fn get_metadata_with_ttl(&self, name: &str, ttl: u64) -> VelocityResult<Option<CachedMetadata>> {
+        let Some(cached) = self.get_stale_metadata(name)? else {
             return Ok(None);
-        }
-
-        let content = std::fs::read_to_string(&metadata_path)?;
-        let cached: CachedMetadata = serde_json::from_str(&content)?;
+        };
 
         // Check TTL
-        let age = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            - cached.cached_at;
-
-        if age > self.config.metadata_ttl {
+        let age = now_secs().saturating_sub(cached.cached_at);
+        if ttl == 0 || age > ttl {
             // Expired
             return Ok(None);
         }
@@ -101,25 +263,81 @@ impl CacheManager {
         Ok(Some(cached))
     }
 
-    /// Store metadata for a package
-    pub fn store_metadata(&self, name: &str, data: &str) -> VelocityResult<()> {
-        let safe_name = name.replace('/', "+").replace('@', "");
-        let metadata_path = self.cache_dir.join("metadata").join(format!("{}.json", safe_name));
-
-        let cached = CachedMetadata {
-            data: data.to_string(),
-            cached_at: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+    /// Get cached metadata of any age, to revalidate with the registry
+    pub fn get_stale_metadata(&self, name: &str) -> VelocityResult<Option<CachedMetadata>> {
+        let index_path = self.metadata_index_path(name);
+        if !index_path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&index_path)?;
+        let index: MetadataIndex = serde_json::from_str(&content)?;
+        let data = match index.content {
+            Some(ref hash) => match self.metadata_store.get(hash)? {
+                Some(body) => body,
+                // The body was removed underneath the index
+                None => return Ok(None),
+            },
+            None => index.data.into_bytes(),
+        };
+
+        Ok(Some(CachedMetadata {
+            data,
+            cached_at: index.cached_at,
+            etag: index.etag,
+            last_modified: index.last_modified,
+        }))
+    }
+
+    /// Store metadata for a package with the `ETag` and `Last-Modified` it was served with
+    ///
+    /// The body is kept in a content-addressed store and the per-package index
+    /// only points at it, so revalidating never rewrites the body.
+    pub fn store_metadata(
+        &self,
+        name: &str,
+        data: &[u8],
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> VelocityResult<()> {
+        let previous = self.read_metadata_index(name);
+        let hash = self.metadata_store.store(data)?;
+
+        let index = MetadataIndex {
+            content: Some(hash.clone()),
+            data: String::new(),
+            cached_at: now_secs(),
+            etag: etag.map(str::to_string),
+            last_modified: last_modified.map(str::to_string),
         };
+        std::fs::write(self.metadata_index_path(name), serde_json::to_string(&index)?)?;
 
-        let content = serde_json::to_string(&cached)?;
-        std::fs::write(&metadata_path, content)?;
+        if let Some(old) = previous.and_then(|previous| previous.content).filter(|old| *old != hash) {
+            self.metadata_store.remove(&old)?;
+        }
+
+        Ok(())
+    }
 
+    /// Mark cached metadata fresh again after the registry answered 304 Not Modified
+    pub fn touch_metadata(&self, name: &str) -> VelocityResult<()> {
+        if let Some(mut index) = self.read_metadata_index(name) {
+            index.cached_at = now_secs();
+            std::fs::write(self.metadata_index_path(name), serde_json::to_string(&index)?)?;
+        }
         Ok(())
     }
 
+    fn read_metadata_index(&self, name: &str) -> Option<MetadataIndex> {
+        let content = std::fs::read_to_string(self.metadata_index_path(name)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn metadata_index_path(&self, name: &str) -> PathBuf {
+        let safe_name = name.replace('/', "+").replace('@', "");
+        self.cache_dir.join("metadata").join(format!("{}.json", safe_name))
+    }
+
     /// Clear the entire cache
     pub fn clear(&self) -> VelocityResult<()> {
         if self.cache_dir.exists() {
@@ -128,6 +346,7 @@ impl CacheManager {
             std::fs::create_dir_all(self.cache_dir.join("tarballs"))?;
             std::fs::create_dir_all(self.cache_dir.join("content"))?;
             std::fs::create_dir_all(self.cache_dir.join("metadata"))?;
+            std::fs::create_dir_all(self.cache_dir.join("files"))?;
         }
         Ok(())
     }
@@ -179,10 +398,38 @@ impl CacheManager {
 }
 
 /// Cached metadata entry
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug)]
 pub struct CachedMetadata {
-    pub data: String,
+    /// Encoded metadata: MessagePack, or JSON for entries from older versions
+    pub data: Vec<u8>,
     pub cached_at: u64,
+    /// Validators for conditional requests
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Per-package metadata index file
+///
+/// Entries written before bodies moved to the content store carry the body
+/// inline in `data`.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct MetadataIndex {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    data: String,
+    cached_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
 }
 
 /// Cache statistics
@@ -192,3 +439,120 @@ pub struct CacheStats {
     pub package_count: usize,
     pub tarball_count: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_partial_extraction_is_not_cached() {
+        let dir = tempdir().unwrap();
+        let cache = CacheManager::new(dir.path(), &CacheConfig::default()).unwrap();
+
+        // A directory without a completion marker looks like an interrupted extraction
+        let package_dir = cache.get_package_dir("@scope/pkg", "1.0.0");
+        std::fs::create_dir_all(&package_dir).unwrap();
+        std::fs::write(package_dir.join("partial.js"), "").unwrap();
+        assert!(!cache.has_package("@scope/pkg", "1.0.0").unwrap());
+
+        let temp = cache.temp_extract_dir("@scope/pkg", "1.0.0").unwrap();
+        std::fs::write(temp.join("index.js"), "module.exports = 1;").unwrap();
+        cache.commit_extraction("@scope/pkg", "1.0.0", &temp, "sha512-abc").unwrap();
+
+        assert!(cache.has_package("@scope/pkg", "1.0.0").unwrap());
+        assert!(package_dir.join("index.js").exists());
+        assert!(!package_dir.join("partial.js").exists());
+        assert!(!temp.exists());
+        assert_eq!(
+            cache.extraction_marker("@scope/pkg", "1.0.0").unwrap().as_deref(),
+            Some("sha512-abc")
+        );
+    }
+
+    #[test]
+    fn test_verify_package() {
+        let dir = tempdir().unwrap();
+        let cache = CacheManager::new(dir.path(), &CacheConfig::default()).unwrap();
+
+        let temp = cache.temp_extract_dir("pkg", "1.0.0").unwrap();
+        std::fs::create_dir_all(temp.join("lib")).unwrap();
+        std::fs::write(temp.join("lib/index.js"), "module.exports = 1;").unwrap();
+        std::fs::write(temp.join("package.json"), "{}").unwrap();
+        let package_dir = cache.commit_extraction("pkg", "1.0.0", &temp, "sha512-abc").unwrap();
+
+        assert_eq!(cache.verify_package("pkg", "1.0.0", "sha512-abc").unwrap(), None);
+        // Older hash formats can't be compared with the marker, but the files still are
+        assert_eq!(cache.verify_package("pkg", "1.0.0", "sha1-xyz").unwrap(), None);
+        assert!(cache.verify_package("pkg", "1.0.0", "sha512-other").unwrap().unwrap().contains("sha512-other"));
+
+        // Writing through the hard link changes the stored copy too, which is dropped
+        let key = ContentStore::file_key(&package_dir.join("lib/index.js")).unwrap();
+        std::fs::write(package_dir.join("lib/index.js"), "evil();").unwrap();
+        assert_eq!(
+            cache.verify_package("pkg", "1.0.0", "sha512-abc").unwrap().as_deref(),
+            Some("lib/index.js was modified")
+        );
+        assert!(!cache.content_store.has(&key));
+
+        std::fs::write(package_dir.join("extra.js"), "").unwrap();
+        std::fs::write(package_dir.join("lib/index.js"), "module.exports = 1;").unwrap();
+        assert_eq!(
+            cache.verify_package("pkg", "1.0.0", "sha512-abc").unwrap().as_deref(),
+            Some("extra.js was added")
+        );
+        std::fs::remove_file(package_dir.join("extra.js")).unwrap();
+
+        std::fs::remove_file(package_dir.join("lib/index.js")).unwrap();
+        assert_eq!(
+            cache.verify_package("pkg", "1.0.0", "sha512-abc").unwrap().as_deref(),
+            Some("lib/index.js was removed")
+        );
+
+        cache.remove_package("pkg", "1.0.0").unwrap();
+        assert!(!cache.has_package("pkg", "1.0.0").unwrap());
+        assert!(cache.verify_package("pkg", "1.0.0", "sha512-abc").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_metadata_validators_and_revalidation() {
+        let dir = tempdir().unwrap();
+        let cache = CacheManager::new(dir.path(), &CacheConfig::default()).unwrap();
+
+        cache.store_metadata("@scope/pkg", b"v1", Some("\"abc\""), None).unwrap();
+        let cached = cache.get_stale_metadata("@scope/pkg").unwrap().unwrap();
+        assert_eq!(cached.data, b"v1");
+        assert_eq!(cached.etag.as_deref(), Some("\"abc\""));
+
+        // A 304 only refreshes the index
+        let index_path = cache.metadata_index_path("@scope/pkg");
+        let mut index: MetadataIndex = serde_json::from_str(&std::fs::read_to_string(&index_path).unwrap()).unwrap();
+        index.cached_at = 0;
+        std::fs::write(&index_path, serde_json::to_string(&index).unwrap()).unwrap();
+        assert!(cache.get_metadata("@scope/pkg").unwrap().is_none());
+        cache.touch_metadata("@scope/pkg").unwrap();
+        assert_eq!(cache.get_metadata("@scope/pkg").unwrap().unwrap().data, b"v1");
+
+        // Replaced bodies leave the store
+        let old = index.content.unwrap();
+        cache.store_metadata("@scope/pkg", b"v2", None, None).unwrap();
+        assert!(!cache.metadata_store.has(&old));
+        assert_eq!(cache.get_stale_metadata("@scope/pkg").unwrap().unwrap().etag, None);
+
+        // Index files from before the content store still read
+        std::fs::write(cache.metadata_index_path("legacy"), r#"{"data":"{}","cached_at":1}"#).unwrap();
+        assert_eq!(cache.get_stale_metadata("legacy").unwrap().unwrap().data, b"{}");
+    }
+
+    #[test]
+    fn test_metadata_ttl_override() {
+        let dir = tempdir().unwrap();
+        let cache = CacheManager::new(dir.path(), &CacheConfig { metadata_ttl: 0, ..Default::default() }).unwrap();
+        cache.store_metadata("pkg", b"{}", None, None).unwrap();
+
+        // --metadata-ttl overrides the configured TTL, and 0 always goes to the registry
+        assert!(cache.get_metadata("pkg").unwrap().is_none());
+        assert_eq!(cache.get_metadata_with_ttl("pkg", 3600).unwrap().unwrap().data, b"{}");
+        assert!(cache.get_metadata_with_ttl("pkg", 0).unwrap().is_none());
+    }
+}
@@ -1,6 +1,11 @@
 //! Content-addressable store for cached data
+//!
+//! Package files are kept here once per distinct content, whichever package
+//! and project they belong to. Extracted packages and installed node_modules
+//! trees hard-link to the stored copies instead of duplicating them.
 
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use sha2::{Sha256, Digest};
 
 use crate::core::VelocityResult;
@@ -36,6 +41,73 @@ impl ContentStore {
         Ok(hash)
     }
 
+    /// Move a file into the store and leave a hard link to the stored copy in its place
+    ///
+    /// Returns the store key. Executable files are keyed separately so that
+    /// linking never changes the mode of a file shared with another package.
+    pub fn import_file(&self, path: &Path) -> VelocityResult<String> {
+        let key = Self::file_key(path)?;
+        let stored = self.hash_path(&key);
+        if stored.exists() {
+            std::fs::remove_file(path)?;
+        } else {
+            if let Some(parent) = stored.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            // Renaming is atomic, so a concurrent import of the same content is harmless
+            std::fs::rename(path, &stored)?;
+        }
+
+        link_or_copy_file(&stored, path)?;
+        Ok(key)
+    }
+
+    /// Hex SHA-256 of a file's content
+    pub fn content_hash(path: &Path) -> VelocityResult<String> {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    /// Store key of a file: its [content hash](Self::content_hash), marked when it is executable
+    pub fn file_key(path: &Path) -> VelocityResult<String> {
+        let mut key = Self::content_hash(path)?;
+        if is_executable(path)? {
+            key.push_str("-exec");
+        }
+        Ok(key)
+    }
+
+    /// Import every regular file below a directory
+    ///
+    /// Returns each file's store key by its `/`-separated path relative to `dir`.
+    pub fn import_dir(&self, dir: &Path) -> VelocityResult<BTreeMap<String, String>> {
+        let mut keys = BTreeMap::new();
+        for entry in walkdir::WalkDir::new(dir) {
+            let entry = entry.map_err(std::io::Error::from)?;
+            if entry.file_type().is_file() {
+                let key = self.import_file(entry.path())?;
+                keys.insert(relative_key(dir, entry.path()), key);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Remove a stored file whose content no longer matches its key
+    ///
+    /// Returns whether it was removed. Files are hard-linked into packages, so
+    /// an edit in place changes the stored copy too, and a later import would
+    /// link to the edited content.
+    pub fn evict_if_corrupt(&self, key: &str) -> VelocityResult<bool> {
+        let path = self.hash_path(key);
+        let intact = Self::content_hash(&path).map_or(true, |hash| hash == key.trim_end_matches("-exec"));
+        if intact {
+            return Ok(false);
+        }
+        std::fs::remove_file(&path)?;
+        Ok(true)
+    }
+
     /// Get content by hash
     pub fn get(&self, hash: &str) -> VelocityResult<Option<Vec<u8>>> {
         let path = self.hash_path(hash);
@@ -99,6 +171,38 @@ impl ContentStore {
     }
 }
 
+/// `path` relative to `dir`, with `/` separators on every platform
+pub fn relative_key(dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Hard-link `source` at `target`, copying when the filesystem can't link
+///
+/// Returns whether a hard link was made.
+pub fn link_or_copy_file(source: &Path, target: &Path) -> VelocityResult<bool> {
+    if std::fs::hard_link(source, target).is_ok() {
+        return Ok(true);
+    }
+    std::fs::copy(source, target)?;
+    Ok(false)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> std::io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(std::fs::metadata(path)?.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> std::io::Result<bool> {
+    Ok(false)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,4 +231,30 @@ mod tests {
 
         assert_eq!(hash1, hash2);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_import_shares_files() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempdir().unwrap();
+        let store = ContentStore::new(dir.path().join("files")).unwrap();
+
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        std::fs::create_dir_all(a.join("lib")).unwrap();
+        std::fs::create_dir_all(&b).unwrap();
+        std::fs::write(a.join("lib/index.js"), "module.exports = 1;").unwrap();
+        std::fs::write(b.join("index.js"), "module.exports = 1;").unwrap();
+
+        let keys = store.import_dir(&a).unwrap();
+        assert_eq!(keys.keys().collect::<Vec<_>>(), ["lib/index.js"]);
+        let key = store.import_file(&b.join("index.js")).unwrap();
+        assert!(store.has(&key));
+
+        let first = std::fs::metadata(a.join("lib/index.js")).unwrap();
+        let second = std::fs::metadata(b.join("index.js")).unwrap();
+        assert_eq!(first.ino(), second.ino());
+        assert_eq!(std::fs::read_to_string(b.join("index.js")).unwrap(), "module.exports = 1;");
+    }
 }
@@ -1,16 +1,25 @@
 //! velocity add - Add packages
 
-use std::env;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use clap::Args;
+use futures::stream::{self, StreamExt};
 
 use crate::cli::output;
-use crate::core::{Engine, VelocityResult};
+use crate::core::project;
+use crate::core::config::SavePrefix;
+use crate::core::lockfile::{LockedPackage, WorkspacePackage};
+use crate::core::{Engine, Lockfile, PackageJson, VelocityError, VelocityResult};
+use crate::installer::Installer;
+use crate::resolver::tarball::{self, TarballPackage};
+use crate::resolver::version::parse_alias;
+use crate::resolver::{ResolveOptions, VersionConstraint};
+use crate::security::{AddedPackage, BudgetReport, ReleaseCooldown, SupplyChainGuard};
 
 #[derive(Args)]
 pub struct AddArgs {
-    /// Packages to add (name or name@version)
+    /// Packages to add (name, name@version, alias@npm:name@version, or a .tgz path or URL)
     #[arg(required = true)]
     pub packages: Vec<String>,
 
@@ -34,54 +43,146 @@ pub struct AddArgs {
     #[arg(short = 'E', long)]
     pub exact: bool,
 
-    /// Project directory
-    #[arg(long, default_value = ".")]
-    pub cwd: PathBuf,
+    /// Range operator to save with: ^, ~ or exact (default: install.save_prefix in velocity.toml)
+    #[arg(long, value_name = "PREFIX", conflicts_with = "exact")]
+    pub save_prefix: Option<SavePrefix>,
+
+    /// Always add from the registry, even if a workspace package has the same name
+    #[arg(long)]
+    pub registry: bool,
+
+    /// Keep lockfile entries that are no longer reachable from any package.json
+    #[arg(long)]
+    pub no_prune: bool,
+
+    /// Add even if the dependency budget in velocity.toml is exceeded
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Allow versions published within security.release_cooldown_hours
+    #[arg(long)]
+    pub ignore_cooldown: bool,
 }
 
+/// (name, spec) pairs declared by the package.json files sharing a lockfile
+type Importers = Vec<(String, String)>;
+
 pub async fn execute(args: AddArgs, json_output: bool) -> VelocityResult<()> {
     let start_time = Instant::now();
 
-    let project_dir = if args.cwd.is_absolute() {
-        args.cwd.clone()
-    } else {
-        env::current_dir()?.join(&args.cwd)
-    };
+    let project_dir = project::current_project_root()?;
 
     let engine = Engine::new(&project_dir).await?;
     engine.ensure_initialized()?;
 
-    let mut package_json = engine.package_json()?;
+    // Workspace members by package name
+    let members: HashMap<String, PathBuf> = match engine.workspace {
+        Some(ref workspace) => workspace
+            .package_jsons()?
+            .into_iter()
+            .map(|(path, pkg)| (pkg.name, path))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    // The package.json being edited: the root, or the member given with --workspace
+    let target_dir = match args.workspace {
+        Some(ref member) => find_workspace_member(&engine, &members, member)?,
+        None => project_dir.clone(),
+    };
+
+    let mut package_json = PackageJson::load(&target_dir)?;
+    let original_package_json = package_json.clone();
 
     if !json_output {
         output::info(&format!("Adding {} package(s)...", args.packages.len()));
     }
 
+    // Registry packages named after Node.js core modules are almost always a typo
+    let cwd = std::env::current_dir()?;
+    let requested: Vec<String> = args
+        .packages
+        .iter()
+        .filter(|spec| tarball_spec(spec, &cwd, &target_dir).is_none())
+        .map(|spec| parse_package_spec(spec).0)
+        .filter(|name| args.registry || !members.contains_key(name))
+        .collect();
+    for name in engine.security.check_core_shadowing(requested.iter().map(String::as_str))? {
+        if !json_output {
+            output::warning(&format!(
+                "'{}' shadows the Node.js core module of the same name. Check that this is the package you meant.",
+                name
+            ));
+        }
+    }
+
     let progress = if !json_output {
         Some(output::spinner("Resolving packages..."))
     } else {
         None
     };
 
+    let cooldown = if args.ignore_cooldown { None } else { engine.security.release_cooldown() };
+    let save_prefix = match args.save_prefix {
+        _ if args.exact => SavePrefix::Exact,
+        Some(prefix) => prefix,
+        None => engine.config.install.save_prefix,
+    };
     let mut added_packages = Vec::new();
 
     for package_spec in &args.packages {
+        // A tarball is added under the name in its package.json
+        if let Some(spec) = tarball_spec(package_spec, &cwd, &target_dir) {
+            let package = TarballPackage::load(&spec, &target_dir, &engine.registry).await?;
+            let name = package.manifest.name.clone();
+            if name.is_empty() {
+                return Err(VelocityError::other(format!("{} has no package name", package_spec)));
+            }
+            if args.dev {
+                package_json.dev_dependencies.insert(name.clone(), spec.clone());
+            } else if args.peer {
+                package_json.peer_dependencies.insert(name.clone(), spec.clone());
+            } else if args.optional {
+                package_json.optional_dependencies.insert(name.clone(), spec.clone());
+            } else {
+                package_json.dependencies.insert(name.clone(), spec.clone());
+            }
+            added_packages.push((name, spec));
+            continue;
+        }
+
         // Parse package@version format
         let (name, version_spec) = parse_package_spec(package_spec);
 
+        let is_member = members.contains_key(&name) && !args.registry;
+        if is_member && name == package_json.name {
+            return Err(VelocityError::workspace(format!(
+                "Cannot add workspace package '{}' to itself",
+                name
+            )));
+        }
+
         // Resolve the package version
-        let resolved_version = if let Some(v) = version_spec {
-            v.to_string()
+        let resolved_version = if is_member {
+            // Workspace packages are linked locally instead of fetched
+            workspace_spec(version_spec, save_prefix)
         } else {
-            // Fetch latest version from registry
-            let metadata = engine.registry.get_package_metadata(&name).await?;
-            let latest = metadata.dist_tags.get("latest")
-                .ok_or_else(|| crate::core::VelocityError::PackageNotFound(name.clone()))?;
-            
-            if args.exact {
-                latest.clone()
-            } else {
-                format!("^{}", latest)
+            // Dist-tags (a bare name means latest) are saved as a range on the
+            // version they point at; aliases keep their npm: prefix
+            let alias = version_spec.and_then(parse_alias);
+            let (source, range) = match alias {
+                Some((target, range)) => (target, Some(range)),
+                None => (name.as_str(), version_spec),
+            };
+            match range.map_or(Some("latest".to_string()), dist_tag) {
+                Some(tag) => {
+                    let spec = tagged_spec(&engine, source, &tag, save_prefix, cooldown.as_ref()).await?;
+                    match alias {
+                        Some(_) => format!("npm:{}@{}", source, spec),
+                        None => spec,
+                    }
+                }
+                None => version_spec.unwrap_or_default().to_string(),
             }
         };
 
@@ -99,29 +200,139 @@ pub async fn execute(args: AddArgs, json_output: bool) -> VelocityResult<()> {
         added_packages.push((name, resolved_version));
     }
 
+    // Resolve before touching package.json, so a blown budget changes nothing;
+    // workspace: dependencies resolve to links to the members
+    let deps = package_json.all_dependencies();
+
+    let is_member = target_dir != project_dir;
+    let shared = engine.workspace.as_ref().map(|w| w.shared_lockfile()).unwrap_or(false);
+    let lockfile_dir = if is_member && !shared { &target_dir } else { &project_dir };
+    let lockfile_path = engine.config.lockfile_path(lockfile_dir);
+    let existing = Lockfile::load(&lockfile_path)?;
+
+    // Keep every other dependency at its locked version
+    let resolver = engine
+        .resolver()
+        .with_options(ResolveOptions::default().with_lockfile(existing.as_ref()))
+        .with_cooldown(cooldown)
+        .with_base_dir(target_dir.clone());
+    let resolution = resolver.resolve(&deps).await?;
+
+    let budget = if engine.config.budget.is_set() {
+        if let Some(ref pb) = progress {
+            pb.set_message("Checking dependency budget...");
+        }
+        let added = added_packages_info(&engine, &resolution.lockfile, existing.as_ref()).await?;
+        Some(BudgetReport::check(&engine.config.budget, &added))
+    } else {
+        None
+    };
+    if let Some(ref report) = budget {
+        if !report.within_budget() {
+            let details: String = report.violations.iter().map(|v| format!("\n  {}", v)).collect();
+            if !args.force {
+                if let Some(ref pb) = progress {
+                    pb.finish_and_clear();
+                }
+                return Err(VelocityError::other(format!(
+                    "Adding {} exceeds the dependency budget:{}\nUse --force to add anyway",
+                    args.packages.join(", "),
+                    details
+                )));
+            }
+            if !json_output {
+                output::warning(&format!("Dependency budget exceeded (--force):{}", details));
+            }
+        }
+    }
+
     if let Some(ref pb) = progress {
         pb.set_message("Saving package.json...");
     }
 
     // Save package.json
-    package_json.save(&project_dir)?;
+    package_json.save(&target_dir)?;
+
+    // Internal dependencies must keep the workspace graph acyclic
+    if let Some(ref workspace) = engine.workspace {
+        if workspace.build_graph()?.has_cycle() {
+            original_package_json.save(&target_dir)?;
+            return Err(VelocityError::workspace(format!(
+                "Adding {} to '{}' would create a dependency cycle in the workspace",
+                args.packages.join(", "),
+                package_json.name
+            )));
+        }
+    }
 
     if let Some(ref pb) = progress {
         pb.set_message("Installing packages...");
     }
 
-    // Install the new packages
-    let deps = package_json.all_dependencies();
-    let resolver = engine.resolver();
-    let resolution = resolver.resolve(&deps).await?;
-
-    let installer = engine.installer();
-    let install_result = installer.install(&resolution, false, false).await?;
-    installer.link(&resolution).await?;
+    // Install registry packages; workspace packages are linked below
+    let installer = Installer::new(
+        target_dir.clone(),
+        engine.cache.clone(),
+        engine.security.clone(),
+        engine.config.network.download_concurrency,
+    )
+    .with_install_config(engine.config.install.clone())
+    .with_network_config(engine.config.network.clone())
+    .with_registry_config(engine.config.registry.clone())
+    .with_http_client(engine.http.clone());
+    installer.install(&resolution, false, false).await?;
+    match existing {
+        Some(ref previous) => installer.link_changes(&resolution, previous).await?,
+        None => installer.link(&resolution).await?,
+    }
 
     // Save lockfile
-    let mut lockfile = resolution.lockfile;
-    lockfile.save(&project_dir)?;
+    let mut lockfile = match existing.clone() {
+        Some(mut existing) if is_member && shared => {
+            existing.merge(resolution.lockfile);
+            existing
+        }
+        Some(existing) => {
+            let mut lockfile = resolution.lockfile;
+            for (name, workspace) in existing.workspaces {
+                lockfile.workspaces.entry(name).or_insert(workspace);
+            }
+            lockfile
+        }
+        None => resolution.lockfile,
+    };
+
+    if is_member && shared {
+        let relative = target_dir.strip_prefix(&project_dir).unwrap_or(&target_dir);
+        let mut dependencies: Vec<String> = package_json
+            .all_dependencies()
+            .into_iter()
+            .map(|(name, spec)| format!("{}@{}", name, spec))
+            .collect();
+        dependencies.sort();
+        lockfile.workspaces.insert(package_json.name.clone(), WorkspacePackage {
+            path: relative.to_string_lossy().replace('\\', "/"),
+            version: package_json.version.clone(),
+            dependencies,
+        });
+    }
+
+    if let Some(ref previous) = existing {
+        lockfile.keep_script_approvals(previous);
+    }
+
+    let (importers, production_importers): (Importers, Importers) =
+        if lockfile_dir == &project_dir {
+            (engine.importers()?, engine.production_importers()?)
+        } else {
+            (
+                package_json.all_dependencies().into_iter().collect(),
+                package_json.production_dependencies().into_iter().collect(),
+            )
+        };
+    lockfile.mark_dev(&production_importers);
+    let pruned = if args.no_prune { 0 } else { lockfile.prune(lockfile_dir, &importers).total() };
+    lockfile.save(&lockfile_path)?;
 
     if let Some(pb) = progress {
         pb.finish_and_clear();
@@ -132,15 +343,30 @@ pub async fn execute(args: AddArgs, json_output: bool) -> VelocityResult<()> {
     if json_output {
         output::json(&serde_json::json!({
             "success": true,
+            "package": package_json.name,
             "added": added_packages.iter().map(|(n, v)| serde_json::json!({
                 "name": n,
-                "version": v
+                "version": v,
+                "workspace": v.starts_with("workspace:")
             })).collect::<Vec<_>>(),
+            "pruned": pruned,
+            "budget": budget,
             "duration_ms": duration.as_millis()
         }))?;
     } else {
         for (name, version) in &added_packages {
-            output::success(&format!("Added {}", output::package_version(name, version)));
+            if version.starts_with("workspace:") {
+                output::success(&format!(
+                    "Linked workspace package {}",
+                    output::package_version(name, version)
+                ));
+            } else {
+                output::success(&format!("Added {}", output::package_version(name, version)));
+            }
+        }
+
+        if pruned > 0 {
+            output::info(&format!("Pruned {} unreachable lockfile entries", pruned));
         }
 
         output::info(&format!(
@@ -152,6 +378,146 @@ pub async fn execute(args: AddArgs, json_output: bool) -> VelocityResult<()> {
     Ok(())
 }
 
+/// Size, license and risk of every package version the resolution adds to the lockfile
+async fn added_packages_info(
+    engine: &Engine,
+    resolved: &Lockfile,
+    existing: Option<&Lockfile>,
+) -> VelocityResult<Vec<AddedPackage>> {
+    let added: Vec<&LockedPackage> = resolved
+        .packages
+        .iter()
+        .filter(|p| existing.is_none_or(|lockfile| lockfile.find_package(&p.name, &p.version).is_none()))
+        .collect();
+
+    let registry = &engine.registry;
+    let infos: Vec<VelocityResult<AddedPackage>> = stream::iter(added)
+        .map(|locked| async move {
+            let real_name = locked.alias_of.as_deref().unwrap_or(&locked.name);
+            // Tarballs added by path or URL have no registry entry to describe them
+            if tarball::is_tarball_spec(&locked.resolved) {
+                return Ok(AddedPackage {
+                    name: locked.name.clone(),
+                    version: locked.version.clone(),
+                    license: None,
+                    size: None,
+                    risk: SupplyChainGuard::analyze(real_name).risk_level,
+                });
+            }
+            let metadata = registry.get_package_metadata(real_name).await?;
+            let version = metadata.versions.get(&locked.version);
+            Ok(AddedPackage {
+                name: locked.name.clone(),
+                version: locked.version.clone(),
+                license: version.and_then(|v| v.license_name()).map(str::to_string),
+                size: version.and_then(|v| v.dist.unpacked_size),
+                risk: SupplyChainGuard::analyze(real_name).risk_level,
+            })
+        })
+        .buffer_unordered(engine.config.network.metadata_concurrency.max(1))
+        .collect()
+        .await;
+
+    infos.into_iter().collect()
+}
+
+/// The `workspace:` spec saved for a member added with an optional range
+fn workspace_spec(version_spec: Option<&str>, save_prefix: SavePrefix) -> String {
+    match version_spec {
+        Some(v) => format!("workspace:{}", v),
+        None if save_prefix == SavePrefix::Exact => "workspace:*".to_string(),
+        None => format!("workspace:{}", save_prefix.operator()),
+    }
+}
+
+/// Find a workspace member by package name or path relative to the workspace root
+fn find_workspace_member(
+    engine: &Engine,
+    members: &HashMap<String, PathBuf>,
+    member: &str,
+) -> VelocityResult<PathBuf> {
+    if engine.workspace.is_none() {
+        return Err(VelocityError::workspace(
+            "--workspace can only be used from a workspace root",
+        ));
+    }
+
+    if let Some(path) = members.get(member) {
+        return Ok(path.clone());
+    }
+
+    let by_path = engine.project_dir.join(member);
+    members
+        .values()
+        .find(|path| **path == by_path || path.file_name().map(|n| n == member).unwrap_or(false))
+        .cloned()
+        .ok_or_else(|| VelocityError::workspace(format!("No workspace package named '{}'", member)))
+}
+
+/// The dependency spec for a tarball argument, or `None` for a registry package
+///
+/// URLs and `file:` specs are kept as given. A path to a .tgz, relative to `cwd`,
+/// becomes a `file:` spec relative to `package_dir`, where package.json lives.
+fn tarball_spec(arg: &str, cwd: &Path, package_dir: &Path) -> Option<String> {
+    if tarball::is_tarball_spec(arg) {
+        return Some(arg.to_string());
+    }
+    if !tarball::is_tarball_path(arg) {
+        return None;
+    }
+
+    let path = cwd.join(arg);
+    let relative = pathdiff::diff_paths(&path, package_dir).unwrap_or(path);
+    Some(format!("file:{}", relative.to_string_lossy().replace('\\', "/")))
+}
+
+/// The dist-tag a spec names, if any; an empty spec or `*` means latest
+fn dist_tag(spec: &str) -> Option<String> {
+    match VersionConstraint::parse(spec) {
+        _ if spec.is_empty() || spec == "*" => Some("latest".to_string()),
+        Ok(VersionConstraint::Tag(tag)) => Some(tag),
+        _ => None,
+    }
+}
+
+/// Range on the version a dist-tag points at, written with the save prefix;
+/// prereleases are always saved exactly
+async fn tagged_spec(
+    engine: &Engine,
+    name: &str,
+    tag: &str,
+    save_prefix: SavePrefix,
+    cooldown: Option<&ReleaseCooldown>,
+) -> VelocityResult<String> {
+    let mut metadata = engine.registry.get_package_metadata(name).await?;
+    // A tag on a version still in its cooldown stands for the newest older one
+    if let Some(cooldown) = cooldown {
+        cooldown.apply(&mut metadata, &[]);
+    }
+    let version = match metadata.dist_tags.get(tag) {
+        Some(version) => version,
+        None if tag == "latest" => return Err(VelocityError::PackageNotFound(name.to_string())),
+        None => {
+            let mut tags: Vec<&String> = metadata.dist_tags.keys().collect();
+            tags.sort();
+            return Err(VelocityError::other(format!(
+                "{} has no dist-tag '{}' (available: {})",
+                name,
+                tag,
+                tags.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", ")
+            )));
+        }
+    };
+
+    // Prereleases are pinned: a range on one would drift to the next prerelease
+    let prerelease = semver::Version::parse(version).map(|v| !v.pre.is_empty()).unwrap_or(false);
+    if prerelease {
+        Ok(version.clone())
+    } else {
+        Ok(save_prefix.apply(version))
+    }
+}
+
 /// Parse a package specification (name@version)
 fn parse_package_spec(spec: &str) -> (String, Option<&str>) {
     // Handle scoped packages (@org/name@version)
@@ -182,4 +548,28 @@ mod tests {
         assert_eq!(parse_package_spec("@types/node"), ("@types/node".to_string(), None));
         assert_eq!(parse_package_spec("@types/node@18.0.0"), ("@types/node".to_string(), Some("18.0.0")));
     }
+
+    #[test]
+    fn test_workspace_spec() {
+        assert_eq!(workspace_spec(None, SavePrefix::Caret), "workspace:^");
+        assert_eq!(workspace_spec(None, SavePrefix::Tilde), "workspace:~");
+        assert_eq!(workspace_spec(None, SavePrefix::Exact), "workspace:*");
+        assert_eq!(workspace_spec(Some("^2.0.0"), SavePrefix::Exact), "workspace:^2.0.0");
+    }
+
+    #[test]
+    fn test_tarball_spec() {
+        let root = Path::new("/work/app");
+        assert_eq!(tarball_spec("./libs/foo-1.2.3.tgz", root, root).as_deref(), Some("file:libs/foo-1.2.3.tgz"));
+        assert_eq!(
+            tarball_spec("foo-1.2.3.tgz", &root.join("packages/web"), root).as_deref(),
+            Some("file:packages/web/foo-1.2.3.tgz")
+        );
+        assert_eq!(tarball_spec("../vendor/bar.tar.gz", root, &root.join("packages/web")).as_deref(), Some("file:../../../vendor/bar.tar.gz"));
+        assert_eq!(
+            tarball_spec("https://example.com/pkg.tgz", root, root).as_deref(),
+            Some("https://example.com/pkg.tgz")
+        );
+        assert_eq!(tarball_spec("react@18", root, root), None);
+    }
 }
@@ -1,10 +1,16 @@
 //! velocity audit - Security audit command
 
+use std::collections::{BTreeMap, HashSet};
 use std::path::Path;
 use clap::Args;
 
+use crate::cli::commands::install::{self, InstallArgs};
 use crate::cli::output;
-use crate::core::{VelocityResult, VelocityError, PackageJson};
+use crate::core::project;
+use crate::core::{Engine, VelocityResult, VelocityError, PackageJson};
+use crate::resolver::VersionConstraint;
+use crate::security::advisories::{AdvisoryDatabase, Severity, Vulnerability};
+use crate::security::licenses::{self, LicensePolicy, LicenseViolation};
 use crate::security::{EcosystemAnalyzer, SupplyChainGuard, SecurityAnalysis, RiskLevel, SecurityLevel};
 
 #[derive(Args)]
@@ -17,31 +23,59 @@ pub struct AuditArgs {
     #[arg(long)]
     pub high_only: bool,
 
-    /// Fix vulnerabilities automatically (where possible)
+    /// Upgrade vulnerable packages to their fixes where semver allows, then reinstall
     #[arg(long)]
     pub fix: bool,
 
     /// Include dev dependencies
     #[arg(long)]
     pub include_dev: bool,
+
+    /// Audit the workspace root and every member
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Only audit these workspace projects (name, path, directory or glob); implies --recursive
+    #[arg(short = 'F', long = "filter", value_name = "SELECTOR")]
+    pub filters: Vec<String>,
 }
 
 pub async fn execute(args: AuditArgs, json_output: bool) -> VelocityResult<()> {
-    let cwd = std::env::current_dir()?;
-    
-    // Load package.json
-    let pkg_json_path = cwd.join("package.json");
-    if !pkg_json_path.exists() {
-        if json_output {
-            println!(r#"{{"error": "No package.json found"}}"#);
-        } else {
-            output::error("No package.json found. Run 'velocity init' first.");
+    let recursive = args.recursive || !args.filters.is_empty();
+
+    // (directory, package.json) of every project to scan
+    let (engine, projects, members) = if recursive {
+        let root = project::current_workspace_root()?;
+        let engine = Engine::new(&root).await?;
+        engine.ensure_initialized()?;
+
+        let members: HashSet<String> = match engine.workspace {
+            Some(ref workspace) => workspace.package_jsons()?.into_iter().map(|(_, pkg)| pkg.name).collect(),
+            None => HashSet::new(),
+        };
+        let projects = engine.select_projects(&args.filters)?;
+        (engine, projects, members)
+    } else {
+        let cwd = project::current_project_root()?;
+
+        // Load package.json
+        let pkg_json_path = cwd.join("package.json");
+        if !pkg_json_path.exists() {
+            if output::is_json_stream() {
+                // Reported as the stream's error event
+            } else if json_output {
+                println!(r#"{{"error": "No package.json found"}}"#);
+            } else {
+                output::error("No package.json found. Run 'velocity init' first.");
+            }
+            return Err(VelocityError::NotInitialized);
         }
-        return Err(VelocityError::NotInitialized);
-    }
 
-    let pkg = PackageJson::load(&cwd)?;
-    
+        let pkg = PackageJson::load(&cwd)?;
+        let engine = Engine::new(&cwd).await?;
+        (engine, vec![(cwd, pkg)], HashSet::new())
+    };
+
     if !json_output {
         output::info("Velocity Security Audit");
         output::divider();
@@ -52,76 +86,209 @@ pub async fn execute(args: AuditArgs, json_output: bool) -> VelocityResult<()> {
 
     // Audit direct dependencies
     if !json_output {
-        println!("📦 Scanning dependencies...\n");
+        if recursive {
+            println!("📦 Scanning dependencies of {} projects...\n", projects.len());
+        } else {
+            println!("📦 Scanning dependencies...\n");
+        }
     }
 
-    // Collect all dependencies
-    let mut deps: Vec<(String, String, bool)> = pkg.dependencies
-        .iter()
-        .map(|(k, v)| (k.clone(), v.clone(), false))
-        .collect();
+    // Collect all dependencies, once per name and version with the projects declaring them
+    let mut deps: BTreeMap<(String, String), (bool, Vec<String>)> = BTreeMap::new();
+    for (_, pkg) in &projects {
+        let mut declared: Vec<(String, String, bool)> = pkg.dependencies
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone(), false))
+            .collect();
 
-    if args.include_dev {
-        deps.extend(
-            pkg.dev_dependencies
-                .iter()
-                .map(|(k, v)| (k.clone(), v.clone(), true))
-        );
+        if args.include_dev {
+            declared.extend(
+                pkg.dev_dependencies
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone(), true))
+            );
+        }
+
+        for (name, version, is_dev) in declared {
+            // Workspace members are linked, not fetched
+            if members.contains(&name) {
+                continue;
+            }
+            let entry = deps.entry((name, version)).or_insert((true, Vec::new()));
+            entry.0 &= is_dev;
+            if recursive {
+                entry.1.push(pkg.name.clone());
+            }
+        }
     }
 
-    for (name, version, is_dev) in &deps {
+    // Findings are printed grouped by the projects they affect
+    let mut deps: Vec<_> = deps.into_iter().collect();
+    deps.sort_by(|a, b| (&a.1 .1, &a.0).cmp(&(&b.1 .1, &b.0)));
+    let mut last_group: Option<Vec<String>> = None;
+
+    for ((name, version), (is_dev, dep_projects)) in deps {
         // Supply chain analysis
-        let analysis = SupplyChainGuard::analyze(name);
+        let analysis = SupplyChainGuard::analyze(&name);
         
         // Ecosystem categorization
-        let category = EcosystemAnalyzer::categorize(name);
-        let security_level = EcosystemAnalyzer::security_level(name);
-        
+        let category = EcosystemAnalyzer::categorize(&name);
+        let security_level = EcosystemAnalyzer::security_level(&name);
+
+        let mut warnings = Vec::new();
+        if let Some(ref warning) = analysis.typosquat_warning {
+            results.typosquat_warnings += 1;
+            warnings.push(format!("  🚨 {} - Possible typosquat of '{}'", name, warning.similar_to));
+        }
+
+        if analysis.risk_level == RiskLevel::High {
+            results.high_risk += 1;
+            if !args.high_only {
+                warnings.push(format!("  ⚠️  {} - High risk package", name));
+            }
+        } else if analysis.risk_level == RiskLevel::Medium {
+            results.medium_risk += 1;
+            if !args.high_only {
+                warnings.push(format!("  ⚡ {} - Medium risk package", name));
+            }
+        }
+
+        // Ecosystem warnings
+        if let Some(warning) = EcosystemAnalyzer::security_warning(&name) {
+            if security_level >= SecurityLevel::Elevated {
+                warnings.push(format!("  {}", warning));
+            }
+        }
+
+        // Show warnings
+        if !json_output && !warnings.is_empty() {
+            if recursive && last_group.as_ref() != Some(&dep_projects) {
+                let labels: Vec<&str> = dep_projects.iter().map(String::as_str).collect();
+                output::project_group(&labels);
+                last_group = Some(dep_projects.clone());
+            }
+            for warning in &warnings {
+                println!("{}", warning);
+            }
+        }
+
         // Record results
-        let pkg_result = PackageAuditResult {
-            name: name.clone(),
-            version: version.clone(),
-            is_dev: *is_dev,
+        results.packages.push(PackageAuditResult {
+            requires_script_confirmation: EcosystemAnalyzer::requires_script_confirmation(&name),
+            name,
+            version,
+            is_dev,
             category: format!("{:?}", category),
             security_level: format!("{:?}", security_level),
             risk_level: analysis.risk_level,
             typosquat_warning: analysis.typosquat_warning.as_ref().map(|w| w.similar_to.clone()),
             recommendations: analysis.recommendations.clone(),
-            requires_script_confirmation: EcosystemAnalyzer::requires_script_confirmation(name),
-        };
+            projects: dep_projects,
+        });
+    }
 
-        // Show warnings
-        if !json_output {
-            if let Some(ref warning) = analysis.typosquat_warning {
-                results.typosquat_warnings += 1;
-                println!("  🚨 {} - Possible typosquat of '{}'", 
-                    name, warning.similar_to);
-            }
+    // Known vulnerabilities of every locked package
+    match engine.lockfile()? {
+        Some(lockfile) => {
+            let mut locked: Vec<(String, String)> = lockfile
+                .packages
+                .iter()
+                .filter(|pkg| args.include_dev || !pkg.dev)
+                .map(|pkg| (pkg.alias_of.clone().unwrap_or_else(|| pkg.name.clone()), pkg.version.clone()))
+                .collect();
+            locked.sort();
+            locked.dedup();
+            results.locked_packages = locked.len();
 
-            if analysis.risk_level == RiskLevel::High {
-                results.high_risk += 1;
-                if !args.high_only {
-                    println!("  ⚠️  {} - High risk package", name);
+            let spinner = (!json_output).then(|| output::spinner("Checking known vulnerabilities..."));
+            let scan = match AdvisoryDatabase::new(&engine.config) {
+                Ok(database) => database.scan(&locked).await,
+                Err(e) => Err(e),
+            };
+            if let Some(spinner) = spinner {
+                spinner.finish_and_clear();
+            }
+            match scan {
+                Ok(vulnerabilities) => results.vulnerabilities = vulnerabilities,
+                Err(e) => {
+                    if !json_output {
+                        output::warning(&format!("Could not check known vulnerabilities: {}", e));
+                    }
+                    results.advisory_error = Some(e.to_string());
                 }
-            } else if analysis.risk_level == RiskLevel::Medium && !args.high_only {
-                results.medium_risk += 1;
-                println!("  ⚡ {} - Medium risk package", name);
             }
 
-            // Ecosystem warnings
-            if let Some(warning) = EcosystemAnalyzer::security_warning(name) {
-                if security_level >= SecurityLevel::Elevated {
-                    println!("  {}", warning);
+            if engine.config.licenses.is_set() {
+                let spinner = (!json_output).then(|| output::spinner("Checking licenses..."));
+                let packages = lockfile.packages.iter().filter(|pkg| args.include_dev || !pkg.dev);
+                let concurrency = engine.config.network.metadata_concurrency;
+                let collected = licenses::collect(&engine.registry, &engine.cache, packages, concurrency).await;
+                if let Some(spinner) = spinner {
+                    spinner.finish_and_clear();
                 }
+                results.license_violations = Some(LicensePolicy::new(&engine.config.licenses).violations(&collected));
+            }
+        }
+        None => {
+            if !json_output {
+                output::warning("No lockfile; run 'velocity install' to check the resolved packages for known vulnerabilities");
+            }
+        }
+    }
+    if args.high_only {
+        results.vulnerabilities.retain(|vuln| vuln.severity >= Severity::High);
+    }
+    for vuln in &results.vulnerabilities {
+        *results.severities.entry(vuln.severity).or_default() += 1;
+    }
+
+    if !json_output && !results.vulnerabilities.is_empty() {
+        println!();
+        println!("🛡  Known vulnerabilities:");
+        for vuln in &results.vulnerabilities {
+            let label = match vuln.severity {
+                Severity::Critical => output::bad(vuln.severity.as_str()).bold(),
+                Severity::High => output::bad(vuln.severity.as_str()),
+                Severity::Moderate => output::caution(vuln.severity.as_str()),
+                Severity::Low => output::muted(vuln.severity.as_str()),
+            };
+            let cves: Vec<&str> = vuln.cves().collect();
+            let ids = if cves.is_empty() { vuln.id.clone() } else { format!("{} ({})", vuln.id, cves.join(", ")) };
+            println!("  {:<10} {}  {}", label, output::package_version(&vuln.package, &vuln.version), ids);
+            println!("             {}", vuln.summary);
+            match vuln.fixed_in {
+                Some(ref fixed) => println!("             Fixed in {}  {}", output::good(fixed), output::muted(&vuln.url)),
+                None => println!("             No fix available  {}", output::muted(&vuln.url)),
+            }
+        }
+    }
+
+    if let Some(ref violations) = results.license_violations {
+        if !json_output && !violations.is_empty() {
+            println!();
+            println!("⚖️  License violations:");
+            for violation in violations {
+                println!(
+                    "  {}  {}  {}",
+                    output::package_version(&violation.package, &violation.version),
+                    output::caution(violation.license.as_deref().unwrap_or("(none)")),
+                    output::muted(&violation.reason)
+                );
             }
         }
+    }
 
-        results.packages.push(pkg_result);
+    if args.fix && !results.vulnerabilities.is_empty() {
+        let report = fix_vulnerabilities(&engine, &projects, &results.vulnerabilities, recursive, json_output).await?;
+        if !json_output {
+            print_fix_report(&report);
+        }
+        results.fix = Some(report);
     }
 
     // Summary
     if json_output {
-        println!("{}", serde_json::to_string_pretty(&results)?);
+        output::json(&results)?;
     } else {
         println!();
         output::divider();
@@ -131,6 +298,19 @@ pub async fn execute(args: AuditArgs, json_output: bool) -> VelocityResult<()> {
         println!("   High risk:              {}", results.high_risk);
         println!("   Medium risk:            {}", results.medium_risk);
         println!("   Typosquat warnings:     {}", results.typosquat_warnings);
+        if results.locked_packages > 0 {
+            println!("   Locked packages:        {}", results.locked_packages);
+        }
+        println!("   Vulnerabilities:        {}", results.vulnerabilities.len());
+        for (severity, count) in results.severities.iter().rev() {
+            println!("     {:<21} {}", format!("{}:", severity), count);
+        }
+        if let Some(ref fix) = results.fix {
+            println!("   Fixed:                  {}", fix.fixed.len());
+        }
+        if let Some(ref violations) = results.license_violations {
+            println!("   License violations:     {}", violations.len());
+        }
         println!();
 
         // Ecosystem breakdown
@@ -152,6 +332,31 @@ pub async fn execute(args: AuditArgs, json_output: bool) -> VelocityResult<()> {
             println!();
         }
 
+        // Vulnerabilities --fix took care of no longer need attention
+        let unresolved: Vec<&Vulnerability> = results
+            .vulnerabilities
+            .iter()
+            .filter(|vuln| {
+                results.fix.as_ref().is_none_or(|fix| {
+                    fix.unfixed.iter().any(|u| u.package == vuln.package && u.version == vuln.version && u.id == vuln.id)
+                })
+            })
+            .collect();
+        let severe = unresolved.iter().filter(|vuln| vuln.severity >= Severity::High).count();
+        if severe > 0 {
+            output::warning(&format!(
+                "{} high or critical vulnerabilit{} found. Upgrade to the fixed versions listed above.",
+                severe,
+                if severe == 1 { "y" } else { "ies" }
+            ));
+        }
+        let license_violations = results.license_violations.as_ref().map_or(0, Vec::len);
+        if license_violations > 0 {
+            output::warning(&format!(
+                "{} package(s) break the license policy in velocity.toml.",
+                license_violations
+            ));
+        }
         if results.high_risk > 0 {
             output::warning(&format!(
                 "{} high-risk package(s) detected. Review carefully before deployment.",
@@ -162,8 +367,8 @@ pub async fn execute(args: AuditArgs, json_output: bool) -> VelocityResult<()> {
                 "{} medium-risk package(s). Consider reviewing.",
                 results.medium_risk
             ));
-        } else {
-            output::success("No high-risk packages detected.");
+        } else if unresolved.is_empty() && license_violations == 0 {
+            output::success("No high-risk packages or known vulnerabilities detected.");
         }
     }
 
@@ -176,6 +381,242 @@ struct AuditResults {
     high_risk: usize,
     medium_risk: usize,
     typosquat_warnings: usize,
+    /// Packages in the lockfile checked against the vulnerability database
+    locked_packages: usize,
+    vulnerabilities: Vec<Vulnerability>,
+    /// Vulnerability count per severity
+    severities: BTreeMap<Severity, usize>,
+    /// Why the vulnerability database could not be checked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    advisory_error: Option<String>,
+    /// Packages breaking the `[licenses]` policy, when one is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license_violations: Option<Vec<LicenseViolation>>,
+    /// What `--fix` changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fix: Option<FixReport>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+struct FixReport {
+    /// package.json ranges raised to take a fix
+    updated_ranges: Vec<UpdatedRange>,
+    fixed: Vec<FixOutcome>,
+    unfixed: Vec<FixOutcome>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct UpdatedRange {
+    project: String,
+    package: String,
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct FixOutcome {
+    package: String,
+    version: String,
+    id: String,
+    fixed_in: Option<String>,
+    /// Version installed after the fix
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upgraded_to: Option<String>,
+    /// Why it could not be fixed automatically
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+/// Upgrade vulnerable packages to their fixes where semver allows
+///
+/// A package.json range that excludes the fix is raised to it when the fix
+/// is semver-compatible with the installed version; a major upgrade is left to
+/// the user. The vulnerable packages are then re-resolved (everything else
+/// stays locked), reinstalled, and checked again.
+async fn fix_vulnerabilities(
+    engine: &Engine,
+    projects: &[(std::path::PathBuf, PackageJson)],
+    vulnerabilities: &[Vulnerability],
+    recursive: bool,
+    json_output: bool,
+) -> VelocityResult<FixReport> {
+    let mut report = FixReport::default();
+    // Package -> why its fix cannot be applied
+    let mut blocked: BTreeMap<String, String> = BTreeMap::new();
+
+    for (dir, original) in projects {
+        let mut pkg = original.clone();
+        let mut changed = false;
+        for vuln in vulnerabilities {
+            let Some(ref fixed) = vuln.fixed_in else {
+                continue;
+            };
+            let fields = [&mut pkg.dependencies, &mut pkg.dev_dependencies, &mut pkg.optional_dependencies];
+            for deps in fields {
+                let Some(spec) = deps.get(&vuln.package).cloned() else {
+                    continue;
+                };
+                match fixed_spec(&spec, &vuln.version, fixed) {
+                    Ok(None) => {}
+                    Ok(Some(updated)) => {
+                        report.updated_ranges.push(UpdatedRange {
+                            project: original.name.clone(),
+                            package: vuln.package.clone(),
+                            from: spec,
+                            to: updated.clone(),
+                        });
+                        deps.insert(vuln.package.clone(), updated);
+                        changed = true;
+                    }
+                    Err(reason) => {
+                        blocked.insert(vuln.package.clone(), reason);
+                    }
+                }
+            }
+        }
+        if changed {
+            pkg.save(dir)?;
+        }
+    }
+
+    let mut names: Vec<String> = vulnerabilities
+        .iter()
+        .filter(|vuln| vuln.fixed_in.is_some() && !blocked.contains_key(&vuln.package))
+        .map(|vuln| vuln.package.clone())
+        .collect();
+    names.sort();
+    names.dedup();
+
+    if !names.is_empty() {
+        let mut args = InstallArgs::for_project(engine.project_dir.clone());
+        args.force_resolve = Some(names);
+        args.workspace = recursive && engine.installs_workspace();
+        let summary = install::run(&engine.project_dir, &args, json_output).await?;
+        if !json_output {
+            install::report(&summary, false)?;
+        }
+    }
+
+    // Whatever is still reported after the reinstall was not fixed
+    let lockfile = engine.lockfile()?.unwrap_or_default();
+    let mut locked: Vec<(String, String)> = lockfile
+        .packages
+        .iter()
+        .map(|pkg| (pkg.alias_of.clone().unwrap_or_else(|| pkg.name.clone()), pkg.version.clone()))
+        .collect();
+    locked.sort();
+    locked.dedup();
+    let remaining = AdvisoryDatabase::new(&engine.config)?.scan(&locked).await?;
+
+    for vuln in vulnerabilities {
+        let still_affected = remaining.iter().any(|r| r.package == vuln.package && r.id == vuln.id);
+        let mut outcome = FixOutcome {
+            package: vuln.package.clone(),
+            version: vuln.version.clone(),
+            id: vuln.id.clone(),
+            fixed_in: vuln.fixed_in.clone(),
+            upgraded_to: None,
+            reason: None,
+        };
+        if still_affected {
+            outcome.reason = Some(match (&vuln.fixed_in, blocked.get(&vuln.package)) {
+                (None, _) => "no fixed version has been published".to_string(),
+                (Some(_), Some(reason)) => reason.clone(),
+                (Some(fixed), None) => format!("a package depending on {} does not allow {} yet", vuln.package, fixed),
+            });
+            report.unfixed.push(outcome);
+        } else {
+            outcome.upgraded_to = locked
+                .iter()
+                .filter(|(name, _)| *name == vuln.package)
+                .filter_map(|(_, version)| semver::Version::parse(version).ok())
+                .max()
+                .map(|version| version.to_string());
+            report.fixed.push(outcome);
+        }
+    }
+
+    Ok(report)
+}
+
+/// A range for `spec` that takes `fixed`: None when it already does, an
+/// error saying why when the fix is out of semver reach
+fn fixed_spec(spec: &str, current: &str, fixed: &str) -> Result<Option<String>, String> {
+    let not_a_range = || format!("declared as '{}', which is not a registry version range", spec);
+    if spec.contains(':') || spec.contains('/') {
+        return Err(not_a_range());
+    }
+    let constraint = VersionConstraint::parse(spec).map_err(|_| not_a_range())?;
+    let fixed_version = semver::Version::parse(fixed).map_err(|_| format!("the fixed version '{}' is not valid semver", fixed))?;
+    if constraint.matches(&fixed_version) {
+        return Ok(None);
+    }
+
+    let compatible = semver::Version::parse(current).is_ok_and(|current| {
+        current.major == fixed_version.major && (current.major != 0 || current.minor == fixed_version.minor)
+    });
+    if !compatible {
+        return Err(format!("the fix ({}) is a breaking upgrade from {}; update it by hand", fixed, current));
+    }
+
+    let spec = spec.trim();
+    let prefix = if spec.starts_with('~') {
+        "~"
+    } else if semver::Version::parse(spec.trim_start_matches(['=', 'v'])).is_ok() {
+        ""
+    } else {
+        "^"
+    };
+    Ok(Some(format!("{}{}", prefix, fixed)))
+}
+
+fn print_fix_report(report: &FixReport) {
+    println!();
+    println!("🔧 Fixes:");
+    for range in &report.updated_ranges {
+        println!("   {} {}: {} → {}", output::muted(&range.project), range.package, output::bad(&range.from), output::good(&range.to));
+    }
+    for outcome in &report.fixed {
+        println!(
+            "   {} {} {} → {} ({})",
+            output::good("✓"),
+            outcome.package,
+            outcome.version,
+            outcome.upgraded_to.as_deref().unwrap_or("?"),
+            outcome.id
+        );
+    }
+    for outcome in &report.unfixed {
+        println!(
+            "   {} {}@{} ({}): {}",
+            output::bad("✗"),
+            outcome.package,
+            outcome.version,
+            outcome.id,
+            outcome.reason.as_deref().unwrap_or_default()
+        );
+    }
+    if report.unfixed.is_empty() {
+        output::success(&format!("Fixed all {} vulnerabilit{}", report.fixed.len(), if report.fixed.len() == 1 { "y" } else { "ies" }));
+    } else {
+        output::warning(&format!("{} vulnerabilit{} need manual attention", report.unfixed.len(), if report.unfixed.len() == 1 { "y" } else { "ies" }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_spec() {
+        assert_eq!(fixed_spec("^4.17.0", "4.17.20", "4.17.21"), Ok(None));
+        assert_eq!(fixed_spec("4.17.20", "4.17.20", "4.17.21"), Ok(Some("4.17.21".to_string())));
+        assert_eq!(fixed_spec("~1.2.3", "1.2.3", "1.3.0"), Ok(Some("~1.3.0".to_string())));
+        assert_eq!(fixed_spec(">=1.0.0 <1.2.0", "1.1.0", "1.2.0"), Ok(Some("^1.2.0".to_string())));
+        assert!(fixed_spec("^0.2.0", "0.2.5", "0.3.0").is_err());
+        assert!(fixed_spec("^1.0.0", "1.4.0", "2.0.0").is_err());
+        assert!(fixed_spec("npm:other@^1.0.0", "1.0.0", "1.0.1").is_err());
+    }
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -189,4 +630,7 @@ struct PackageAuditResult {
     typosquat_warning: Option<String>,
     recommendations: Vec<String>,
     requires_script_confirmation: bool,
+    /// Workspace projects declaring the package (recursive audits only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    projects: Vec<String>,
 }
@@ -1,10 +1,10 @@
 //! velocity cache - Manage the package cache
 
-use std::env;
 use std::path::PathBuf;
 use clap::{Args, Subcommand};
 
 use crate::cli::output;
+use crate::core::project;
 use crate::core::{Config, VelocityResult};
 
 #[derive(Args)]
@@ -37,7 +37,7 @@ pub enum CacheCommands {
 }
 
 pub async fn execute(args: CacheArgs, json_output: bool) -> VelocityResult<()> {
-    let project_dir = env::current_dir()?;
+    let project_dir = project::current_project_root()?;
     let config = Config::load(&project_dir)?;
     let cache_dir = config.cache_dir()?;
 
@@ -138,7 +138,7 @@ async fn list(cache_dir: &PathBuf, filter: Option<String>, json_output: bool) ->
             for (name, versions) in &packages {
                 println!(
                     "  {} ({})",
-                    console::style(name).cyan(),
+                    output::highlight(name),
                     versions.join(", ")
                 );
             }
@@ -199,7 +199,7 @@ async fn verify(cache_dir: &PathBuf, json_output: bool) -> VelocityResult<()> {
                 verified, failed
             ));
             for error in errors.iter().take(10) {
-                println!("  {}", console::style(error).red());
+                println!("  {}", output::bad(error));
             }
             if errors.len() > 10 {
                 println!("  ... and {} more", errors.len() - 10);
@@ -211,12 +211,26 @@ async fn verify(cache_dir: &PathBuf, json_output: bool) -> VelocityResult<()> {
 }
 
 fn calculate_dir_size(path: &PathBuf) -> std::io::Result<u64> {
+    // Extracted packages hard-link into the content store; count each file once
+    #[cfg(unix)]
+    let mut seen = std::collections::HashSet::new();
+
     let mut size = 0;
     if path.exists() && path.is_dir() {
         for entry in walkdir::WalkDir::new(path) {
             if let Ok(entry) = entry {
                 if entry.file_type().is_file() {
-                    size += entry.metadata().map(|m| m.len()).unwrap_or(0);
+                    let Ok(metadata) = entry.metadata() else {
+                        continue;
+                    };
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::fs::MetadataExt;
+                        if !seen.insert((metadata.dev(), metadata.ino())) {
+                            continue;
+                        }
+                    }
+                    size += metadata.len();
                 }
             }
         }
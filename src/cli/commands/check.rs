@@ -0,0 +1,89 @@
+//! velocity check - Verify node_modules against the lockfile
+
+use clap::Args;
+
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{Engine, VelocityError, VelocityResult};
+use crate::installer::LayoutIssue;
+
+#[derive(Args)]
+pub struct CheckArgs {}
+
+/// node_modules compared against the lockfile
+pub struct NodeModulesReport {
+    /// Packages the lockfile places in node_modules
+    pub packages: usize,
+
+    /// Whether node_modules exists at all
+    pub installed: bool,
+
+    pub issues: Vec<LayoutIssue>,
+}
+
+impl NodeModulesReport {
+    pub fn passed(&self) -> bool {
+        self.installed && self.issues.is_empty()
+    }
+}
+
+/// Compare node_modules with the lockfile; `None` without a lockfile
+pub fn verify_node_modules(engine: &Engine) -> VelocityResult<Option<NodeModulesReport>> {
+    let Some(lockfile) = engine.lockfile()? else {
+        return Ok(None);
+    };
+    let resolution = engine.resolver().resolve_from_lockfile(&lockfile)?;
+    let packages = resolution.to_install.len() + resolution.from_cache.len();
+
+    // Without node_modules every package would be reported missing
+    if !engine.node_modules_path().exists() {
+        return Ok(Some(NodeModulesReport {
+            packages,
+            installed: packages == 0,
+            issues: Vec::new(),
+        }));
+    }
+
+    Ok(Some(NodeModulesReport {
+        packages,
+        installed: true,
+        issues: engine.installer().verify(&resolution)?,
+    }))
+}
+
+pub async fn execute(_args: CheckArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+    let engine = Engine::new(&project_dir).await?;
+    engine.ensure_initialized()?;
+
+    let report = verify_node_modules(&engine)?.ok_or_else(|| {
+        VelocityError::other(format!(
+            "No lockfile found at {}. Run 'velocity install' first.",
+            engine.lockfile_path().display()
+        ))
+    })?;
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "success": report.passed(),
+            "installed": report.installed,
+            "packages": report.packages,
+            "issues": report.issues.iter().map(|issue| issue.to_json(&project_dir)).collect::<Vec<_>>(),
+        }))?;
+    } else if report.passed() {
+        output::success(&format!("node_modules matches the lockfile ({} packages)", report.packages));
+    } else if !report.installed {
+        output::error("node_modules is missing");
+    } else {
+        output::error(&format!("node_modules differs from the lockfile in {} place(s):", report.issues.len()));
+        for issue in &report.issues {
+            let issue = issue.to_string().replace(&format!("{}/", project_dir.display()), "");
+            println!("  {}", output::bad(issue));
+        }
+    }
+
+    if !report.passed() {
+        return Err(VelocityError::other("node_modules does not match the lockfile. Run 'velocity install'."));
+    }
+    Ok(())
+}
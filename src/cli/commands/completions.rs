@@ -0,0 +1,300 @@
+//! velocity completions - Generate shell completion scripts
+//!
+//! The static part of each script comes from clap_complete. Script names and
+//! workspace members are only known at completion time, so the scripts call
+//! back into `velocity completions --dynamic -- <words>` and fall back to the
+//! static completions when that prints nothing.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, CommandFactory};
+use clap_complete::Shell;
+
+use crate::cli::Cli;
+use crate::core::project;
+use crate::core::{Config, PackageJson, VelocityError, VelocityResult};
+use crate::workspace::WorkspaceManager;
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate the completion script for
+    #[arg(value_enum, required_unless_present = "dynamic")]
+    pub shell: Option<Shell>,
+
+    /// Print completions for the words typed so far, one per line
+    #[arg(long, hide = true)]
+    pub dynamic: bool,
+
+    /// Words after `velocity`, up to but not including the one being completed
+    #[arg(last = true, hide = true)]
+    pub words: Vec<String>,
+}
+
+pub async fn execute(args: CompletionsArgs, _json_output: bool) -> VelocityResult<()> {
+    if args.dynamic {
+        // Completion must never print errors into the user's prompt
+        let candidates = dynamic_candidates(&std::env::current_dir()?, &args.words).unwrap_or_default();
+        for candidate in candidates {
+            println!("{}", candidate);
+        }
+        return Ok(());
+    }
+
+    let shell = args.shell.ok_or_else(|| VelocityError::other("Specify a shell"))?;
+    print!("{}", script(shell)?);
+    Ok(())
+}
+
+/// The completion script for `shell`
+fn script(shell: Shell) -> VelocityResult<String> {
+    let mut buf = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), "velocity", &mut buf);
+    let generated = String::from_utf8(buf).map_err(|e| VelocityError::other(e.to_string()))?;
+
+    Ok(match shell {
+        Shell::Bash => format!("{}\n{}", generated, BASH_DYNAMIC),
+        Shell::Zsh => format!("{}\n{}", generated, ZSH_DYNAMIC),
+        Shell::Fish => format!("{}\n{}", generated, FISH_DYNAMIC),
+        // Keep the generated completer callable so the dynamic one can defer to it
+        Shell::PowerShell => format!(
+            "{}\n{}",
+            generated.replacen(
+                "Register-ArgumentCompleter -Native -CommandName 'velocity' -ScriptBlock {",
+                "$global:VelocityStaticCompleter = {",
+                1,
+            ),
+            POWERSHELL_DYNAMIC
+        ),
+        _ => generated,
+    })
+}
+
+const BASH_DYNAMIC: &str = r#"_velocity_dynamic() {
+    local candidates
+    mapfile -t candidates < <(velocity completions --dynamic -- "${COMP_WORDS[@]:1:COMP_CWORD-1}" 2>/dev/null)
+    if [[ ${#candidates[@]} -gt 0 ]]; then
+        mapfile -t COMPREPLY < <(compgen -W "${candidates[*]}" -- "${COMP_WORDS[COMP_CWORD]}")
+        return 0
+    fi
+    _velocity "$@"
+}
+
+complete -F _velocity_dynamic -o nosort -o bashdefault -o default velocity
+"#;
+
+const ZSH_DYNAMIC: &str = r#"_velocity_dynamic() {
+    local -a candidates
+    candidates=("${(@f)$(velocity completions --dynamic -- "${(@)words[2,CURRENT-1]}" 2>/dev/null)}")
+    if [[ -n "${candidates[1]}" ]]; then
+        compadd -a candidates
+        return
+    fi
+    _velocity "$@"
+}
+
+compdef _velocity_dynamic velocity
+"#;
+
+const FISH_DYNAMIC: &str = r#"function __velocity_dynamic
+    velocity completions --dynamic -- (commandline -opc)[2..-1] 2>/dev/null
+end
+
+complete -c velocity -f -n '__velocity_dynamic | string length -q' -a '(__velocity_dynamic)'
+"#;
+
+const POWERSHELL_DYNAMIC: &str = r#"Register-ArgumentCompleter -Native -CommandName 'velocity' -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+
+    $words = @($commandAst.CommandElements | Select-Object -Skip 1 |
+        Where-Object { $_.Extent.EndOffset -lt $cursorPosition } |
+        ForEach-Object { $_.ToString() })
+    $candidates = @(velocity completions --dynamic -- @words 2>$null)
+    if ($candidates.Count -gt 0) {
+        $candidates | Where-Object { $_ -like "$wordToComplete*" } | ForEach-Object {
+            [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+        }
+        return
+    }
+    & $global:VelocityStaticCompleter $wordToComplete $commandAst $cursorPosition
+}
+"#;
+
+/// What the word being completed names
+#[derive(Debug, PartialEq, Eq)]
+enum Query {
+    /// Scripts of the project, or of the workspace member `member`
+    Scripts { member: Option<String> },
+
+    /// Scripts of workspace members whose name contains `filter`
+    WorkspaceScripts { filter: Option<String> },
+
+    /// Workspace member names
+    Members,
+}
+
+/// Global options that take a value
+const GLOBAL_VALUE_OPTIONS: &[&str] = &["-C", "--cwd", "--trace-output"];
+
+/// Work out what is being completed from the words before it, and the `--cwd` given
+fn parse_words(words: &[String]) -> (Option<PathBuf>, Option<Query>) {
+    let mut cwd = None;
+    let mut rest = words.iter();
+
+    let command = loop {
+        let Some(word) = rest.next() else {
+            return (cwd, None);
+        };
+        if let Some(dir) = word.strip_prefix("--cwd=") {
+            cwd = Some(PathBuf::from(dir));
+        } else if GLOBAL_VALUE_OPTIONS.contains(&word.as_str()) {
+            let value = rest.next();
+            if matches!(word.as_str(), "-C" | "--cwd") {
+                cwd = value.map(PathBuf::from);
+            }
+        } else if !word.starts_with('-') {
+            break word.as_str();
+        }
+    };
+
+    let (workspace, filter_short) = match command {
+        "run" | "r" => (false, "-F"),
+        "workspace" | "ws" if rest.next().map(String::as_str) == Some("run") => (true, "-f"),
+        _ => return (cwd, None),
+    };
+
+    let mut filter = None;
+    let mut positionals = 0;
+    let mut words = rest;
+    while let Some(word) = words.next() {
+        if word == filter_short || word == "--filter" {
+            match words.next() {
+                Some(value) => filter = Some(value.clone()),
+                None => return (cwd, Some(Query::Members)),
+            }
+        } else if let Some(value) = word.strip_prefix("--filter=") {
+            filter = Some(value.to_string());
+        } else if word == "--" {
+            break;
+        } else if !word.starts_with('-') {
+            positionals += 1;
+        }
+    }
+
+    // Only the first positional is a script name; the rest are its arguments
+    let query = match (positionals, workspace) {
+        (0, false) => Some(Query::Scripts { member: filter }),
+        (0, true) => Some(Query::WorkspaceScripts { filter }),
+        _ => None,
+    };
+    (cwd, query)
+}
+
+/// Completions for the word after `words`, run from `cwd`; empty when none apply
+pub fn dynamic_candidates(cwd: &Path, words: &[String]) -> VelocityResult<Vec<String>> {
+    let (dir, query) = parse_words(words);
+    let Some(query) = query else {
+        return Ok(Vec::new());
+    };
+    let start = dir.map(|dir| cwd.join(dir)).unwrap_or_else(|| cwd.to_path_buf());
+
+    let candidates: BTreeSet<String> = match query {
+        Query::Scripts { member: None } => {
+            PackageJson::load(&project::find_project_root(&start))?.scripts.into_keys().collect()
+        }
+        Query::Scripts { member: Some(member) } => match workspace(&start)? {
+            Some(workspace) => workspace
+                .find_member(&member)?
+                .map(|(_, pkg)| pkg.scripts.into_keys().collect())
+                .unwrap_or_default(),
+            None => BTreeSet::new(),
+        },
+        Query::WorkspaceScripts { filter } => workspace(&start)?
+            .map(|workspace| workspace.package_jsons())
+            .transpose()?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, pkg)| filter.as_ref().map(|f| pkg.name.contains(f.as_str())).unwrap_or(true))
+            .flat_map(|(_, pkg)| pkg.scripts.into_keys())
+            .collect(),
+        Query::Members => workspace(&start)?
+            .map(|workspace| workspace.package_jsons())
+            .transpose()?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(_, pkg)| pkg.name)
+            .collect(),
+    };
+
+    Ok(candidates.into_iter().collect())
+}
+
+/// The workspace enclosing `start`, as `Engine` would load it
+fn workspace(start: &Path) -> VelocityResult<Option<WorkspaceManager>> {
+    let Some(root) = project::find_workspace_root(start) else {
+        return Ok(None);
+    };
+    let config = Config::load(&root)?;
+    Ok(Some(WorkspaceManager::new(&root, &config.workspace)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_words() {
+        assert_eq!(parse_words(&words("run")), (None, Some(Query::Scripts { member: None })));
+        assert_eq!(
+            parse_words(&words("-C apps/web r -F web")),
+            (Some(PathBuf::from("apps/web")), Some(Query::Scripts { member: Some("web".to_string()) }))
+        );
+        assert_eq!(parse_words(&words("run --filter")), (None, Some(Query::Members)));
+        assert_eq!(parse_words(&words("run build")), (None, None));
+        assert_eq!(parse_words(&words("ws run -f")), (None, Some(Query::Members)));
+        assert_eq!(
+            parse_words(&words("--verbose workspace run --filter=ui")),
+            (None, Some(Query::WorkspaceScripts { filter: Some("ui".to_string()) }))
+        );
+        assert_eq!(parse_words(&words("install")), (None, None));
+    }
+
+    #[test]
+    fn test_dynamic_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(
+            root.join("package.json"),
+            r#"{"name": "repo", "version": "1.0.0", "workspaces": ["packages/*"], "scripts": {"lint": "eslint ."}}"#,
+        ).unwrap();
+        for (name, script) in [("ui", "build"), ("api", "start")] {
+            let member = root.join("packages").join(name);
+            std::fs::create_dir_all(&member).unwrap();
+            std::fs::write(
+                member.join("package.json"),
+                format!(r#"{{"name": "@repo/{}", "version": "1.0.0", "scripts": {{"{}": "x", "test": "y"}}}}"#, name, script),
+            ).unwrap();
+        }
+
+        assert_eq!(dynamic_candidates(root, &words("run")).unwrap(), ["lint"]);
+        assert_eq!(dynamic_candidates(root, &words("run -F @repo/ui")).unwrap(), ["build", "test"]);
+        assert_eq!(dynamic_candidates(root, &words("run -F")).unwrap(), ["@repo/api", "@repo/ui"]);
+        assert_eq!(dynamic_candidates(root, &words("ws run")).unwrap(), ["build", "start", "test"]);
+        assert_eq!(dynamic_candidates(root, &words("ws run -f api")).unwrap(), ["start", "test"]);
+        assert_eq!(dynamic_candidates(&root.join("packages/api"), &words("run")).unwrap(), ["start", "test"]);
+        assert!(dynamic_candidates(root, &words("add")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scripts_defer_to_generated_completions() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let script = script(shell).unwrap();
+            assert!(script.contains("velocity completions --dynamic --"), "{}", shell);
+        }
+        assert!(script(Shell::PowerShell).unwrap().contains("$global:VelocityStaticCompleter = {"));
+    }
+}
@@ -0,0 +1,555 @@
+//! velocity config - Read and change velocity.toml settings
+
+use std::path::Path;
+use std::process::Command;
+
+use clap::{Args, Subcommand};
+use serde::Deserialize;
+use toml_edit::{DocumentMut, Item, Table, TableLike};
+
+use crate::cli::output;
+use crate::core::config::{ConfigSource, UpgradeConfig};
+use crate::core::project;
+use crate::core::{Config, VelocityError, VelocityResult};
+use crate::registry::http::redact_credentials;
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommands,
+
+    /// Use the user config file instead of the project's velocity.toml
+    #[arg(short, long, global = true)]
+    pub global: bool,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print a setting, e.g. `network.timeout`
+    Get {
+        /// Dotted key; quote parts containing dots, e.g. `network.tls."npm.corp.example".ca_file`
+        key: String,
+    },
+
+    /// Change a setting, e.g. `velocity config set network.concurrency 32`
+    Set {
+        /// Dotted key
+        key: String,
+
+        /// New value: a TOML literal (`true`, `30`, `["a", "b"]`) or plain text
+        value: String,
+    },
+
+    /// Remove a setting from the file so its default applies again
+    #[command(visible_alias = "rm")]
+    Delete {
+        /// Dotted key
+        key: String,
+    },
+
+    /// Print every setting
+    #[command(visible_alias = "ls")]
+    List {
+        /// Show where each value comes from: a config file, the environment or the default
+        #[arg(long)]
+        show_origin: bool,
+    },
+
+    /// Open the config file in $VISUAL or $EDITOR
+    Edit,
+}
+
+pub async fn execute(args: ConfigArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+    let file = if args.global {
+        Config::user_config_path()?
+    } else {
+        project_dir.join("velocity.toml")
+    };
+
+    match args.command {
+        ConfigCommands::Get { key } => get(&project_dir, &file, &key, args.global, json_output),
+        ConfigCommands::Set { key, value } => set(&project_dir, &file, &key, &value, args.global, json_output),
+        ConfigCommands::Delete { key } => delete(&file, &key, args.global, json_output),
+        ConfigCommands::List { show_origin } => list(&project_dir, &file, args.global, show_origin, json_output),
+        ConfigCommands::Edit => edit(&file, args.global, json_output),
+    }
+}
+
+/// Settings to read from: the effective project config, or what the user file itself sets
+fn current_settings(project_dir: &Path, file: &Path, global: bool) -> VelocityResult<toml::Value> {
+    let mut settings = if global {
+        match std::fs::read_to_string(file) {
+            Ok(content) => toml::Value::Table(toml::from_str(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::Value::Table(toml::Table::new()),
+            Err(e) => return Err(e.into()),
+        }
+    } else {
+        toml::Value::try_from(Config::load(project_dir)?)?
+    };
+    redact_secrets(&mut settings);
+    Ok(settings)
+}
+
+fn get(project_dir: &Path, file: &Path, key: &str, global: bool, json_output: bool) -> VelocityResult<()> {
+    let keys = parse_key(key)?;
+    let settings = current_settings(project_dir, file, global)?;
+    let value = lookup(&settings, &keys).ok_or_else(|| {
+        let place = if global { file.display().to_string() } else { "the project config".to_string() };
+        VelocityError::config(format!("'{}' is not set in {}", key, place))
+    })?;
+
+    if json_output {
+        output::json(value)?;
+    } else if let toml::Value::Table(_) = value {
+        print_settings(&join_key(&keys), value);
+    } else {
+        println!("{}", display_value(value));
+    }
+    Ok(())
+}
+
+fn set(project_dir: &Path, file: &Path, key: &str, value: &str, global: bool, json_output: bool) -> VelocityResult<()> {
+    let keys = parse_key(key)?;
+    let content = read_file(file)?;
+    let (updated, written) = set_in(&content, &keys, value, global)?;
+
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(file, updated)?;
+
+    let key = join_key(&keys);
+    if json_output {
+        output::json(&serde_json::json!({
+            "key": key,
+            "value": written,
+            "file": file,
+        }))?;
+        return Ok(());
+    }
+
+    output::success(&format!("Set {} = {} in {}", key, written, file.display()));
+    // Sources applied later may keep the new value from taking effect
+    let layers = match load_layers(project_dir) {
+        Ok(layers) => layers,
+        Err(e) => {
+            output::warning(&format!("Could not load the project config: {}", e));
+            return Ok(());
+        }
+    };
+    let (_, effective) = layers.last().expect("the environment layer is always traced");
+    if let Some(effective) = lookup(effective, &keys).filter(|effective| **effective != written) {
+        output::warning(&format!(
+            "{} is overridden by {} (effective value: {})",
+            key,
+            origin(&layers, &keys),
+            effective
+        ));
+    }
+    Ok(())
+}
+
+fn delete(file: &Path, key: &str, global: bool, json_output: bool) -> VelocityResult<()> {
+    let keys = parse_key(key)?;
+    let content = read_file(file)?;
+    let updated = delete_in(&content, &keys, global)?
+        .ok_or_else(|| VelocityError::config(format!("'{}' is not set in {}", key, file.display())))?;
+    std::fs::write(file, updated)?;
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "key": join_key(&keys),
+            "file": file,
+        }))?;
+    } else {
+        output::success(&format!("Removed {} from {}", join_key(&keys), file.display()));
+    }
+    Ok(())
+}
+
+fn list(project_dir: &Path, file: &Path, global: bool, show_origin: bool, json_output: bool) -> VelocityResult<()> {
+    if show_origin {
+        if global {
+            return Err(VelocityError::config("--show-origin lists the effective config; drop --global"));
+        }
+        return list_origins(project_dir, json_output);
+    }
+
+    let settings = current_settings(project_dir, file, global)?;
+    if json_output {
+        output::json(&settings)?;
+    } else {
+        print_settings("", &settings);
+    }
+    Ok(())
+}
+
+fn list_origins(project_dir: &Path, json_output: bool) -> VelocityResult<()> {
+    let layers = load_layers(project_dir)?;
+    let (_, effective) = layers.last().expect("the environment layer is always traced");
+    let mut settings = effective.clone();
+    redact_secrets(&mut settings);
+
+    let entries = leaves(&settings);
+    if json_output {
+        let entries: serde_json::Map<String, serde_json::Value> = entries
+            .into_iter()
+            .map(|(keys, value)| {
+                let entry = serde_json::json!({
+                    "value": value,
+                    "origin": origin(&layers, &keys).to_string(),
+                });
+                (join_key(&keys), entry)
+            })
+            .collect();
+        output::json(&entries)?;
+        return Ok(());
+    }
+
+    for (keys, value) in entries {
+        let origin = output::muted(format!("({})", origin(&layers, &keys)));
+        println!("{} = {}  {}", join_key(&keys), value, origin);
+    }
+    Ok(())
+}
+
+/// The project config after each of its sources applies, lowest precedence first
+fn load_layers(project_dir: &Path) -> VelocityResult<Vec<(ConfigSource, toml::Value)>> {
+    let mut layers = Vec::new();
+    let mut failed = None;
+    Config::load_traced(project_dir, |source, config| match toml::Value::try_from(config) {
+        Ok(settings) => layers.push((source, settings)),
+        Err(e) => failed = Some(e),
+    })?;
+    match failed {
+        Some(e) => Err(e.into()),
+        None => Ok(layers),
+    }
+}
+
+/// The source that last changed a setting
+fn origin<'a>(layers: &'a [(ConfigSource, toml::Value)], keys: &[String]) -> &'a ConfigSource {
+    layers
+        .windows(2)
+        .rev()
+        .find(|pair| lookup(&pair[0].1, keys) != lookup(&pair[1].1, keys))
+        .map_or(&layers[0].0, |pair| &pair[1].0)
+}
+
+fn edit(file: &Path, global: bool, json_output: bool) -> VelocityResult<()> {
+    if !file.exists() {
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(file, "")?;
+    }
+
+    let default_editor = if cfg!(windows) { "notepad" } else { "vi" };
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| default_editor.to_string());
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| VelocityError::config("$VISUAL / $EDITOR is empty"))?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(file)
+        .status()
+        .map_err(|e| VelocityError::config(format!("Could not start editor '{}': {}", editor, e)))?;
+    if !status.success() {
+        return Err(VelocityError::config(format!("Editor '{}' exited with {}", editor, status)));
+    }
+
+    validate(&std::fs::read_to_string(file)?, global)
+        .map_err(|e| VelocityError::config(format!("{} is not a valid config: {}", file.display(), e)))?;
+    if json_output {
+        output::json(&serde_json::json!({ "file": file }))?;
+    } else {
+        output::success(&format!("Saved {}", file.display()));
+    }
+    Ok(())
+}
+
+/// The file's content, or nothing when it does not exist yet
+fn read_file(file: &Path) -> VelocityResult<String> {
+    match std::fs::read_to_string(file) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn parse_document(content: &str) -> VelocityResult<DocumentMut> {
+    content
+        .parse()
+        .map_err(|e| VelocityError::config(format!("Could not parse the config file: {}", e)))
+}
+
+/// Split a dotted key, keeping quoted parts whole, and spell serde aliases the canonical way
+fn parse_key(key: &str) -> VelocityResult<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in key.trim().chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '.' if !quoted => keys.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    keys.push(current);
+    if quoted || keys.iter().any(String::is_empty) {
+        return Err(VelocityError::config(format!("Invalid config key '{}'", key)));
+    }
+
+    if keys[0] == "network" {
+        if keys.len() == 2 && keys[1] == "concurrency" {
+            keys[1] = "download_concurrency".to_string();
+        }
+        if let Some(last) = keys.last_mut().filter(|last| *last == "cafile") {
+            *last = "ca_file".to_string();
+        }
+    }
+    Ok(keys)
+}
+
+/// A dotted key as `parse_key` reads it back
+fn join_key(keys: &[String]) -> String {
+    keys.iter()
+        .map(|key| {
+            if key.contains(['.', '"']) || key.contains(char::is_whitespace) {
+                format!("\"{}\"", key)
+            } else {
+                key.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn lookup<'a>(settings: &'a toml::Value, keys: &[String]) -> Option<&'a toml::Value> {
+    keys.iter().try_fold(settings, |value, key| value.as_table()?.get(key))
+}
+
+/// Every setting a file holds, with defaults filled in; fails on values of the wrong type
+fn validate(content: &str, global: bool) -> VelocityResult<toml::Value> {
+    let config: Config = toml::from_str(content)?;
+    let mut settings = toml::Value::try_from(config)?;
+
+    // `[upgrade]` is read from the user config only
+    if global {
+        #[derive(Deserialize, Default)]
+        #[serde(default)]
+        struct UserConfig {
+            upgrade: UpgradeConfig,
+        }
+
+        let user: UserConfig = toml::from_str(content)?;
+        if let toml::Value::Table(ref mut table) = settings {
+            table.insert("upgrade".to_string(), toml::Value::try_from(user.upgrade)?);
+        }
+    }
+    Ok(settings)
+}
+
+/// `content` with `keys` set to `value`, and the value as the config reads it
+///
+/// The value is tried as a TOML literal first and as a plain string when that does not fit
+/// the setting, so `registry.url https://...` and `network.min_tls_version 1.3` need no quotes.
+fn set_in(content: &str, keys: &[String], value: &str, global: bool) -> VelocityResult<(String, toml::Value)> {
+    let document = parse_document(content)?;
+    let attempt = |value: toml_edit::Value| -> VelocityResult<(String, toml::Value)> {
+        let mut document = document.clone();
+        insert(document.as_table_mut(), keys, value)?;
+        let updated = document.to_string();
+        let settings = validate(&updated, global)
+            .map_err(|e| VelocityError::config(format!("Invalid value for {}: {}", join_key(keys), e)))?;
+        let written = lookup(&settings, keys).cloned().ok_or_else(|| unknown_key(keys, global))?;
+        Ok((updated, written))
+    };
+
+    match value.parse::<toml_edit::Value>() {
+        Ok(mut literal) if !literal.is_str() => {
+            literal.decor_mut().clear();
+            attempt(literal).or_else(|e| attempt(value.into()).map_err(|_| e))
+        }
+        _ => attempt(value.into()),
+    }
+}
+
+/// `content` without `keys`, or `None` when the file does not set it
+fn delete_in(content: &str, keys: &[String], global: bool) -> VelocityResult<Option<String>> {
+    let mut document = parse_document(content)?;
+    let (last, parents) = keys.split_last().expect("parse_key returns at least one key");
+    let table = parents
+        .iter()
+        .try_fold(document.as_table_mut() as &mut dyn TableLike, |table, key| {
+            table.get_mut(key)?.as_table_like_mut()
+        });
+    if table.and_then(|table| table.remove(last)).is_none() {
+        return Ok(None);
+    }
+
+    let updated = document.to_string();
+    validate(&updated, global)?;
+    Ok(Some(updated))
+}
+
+/// Set a value, creating missing tables and keeping the comments around an existing value
+fn insert(root: &mut Table, keys: &[String], value: toml_edit::Value) -> VelocityResult<()> {
+    let (last, parents) = keys.split_last().expect("parse_key returns at least one key");
+    let mut table: &mut dyn TableLike = root;
+    for (depth, key) in parents.iter().enumerate() {
+        table = table
+            .entry(key)
+            .or_insert_with(|| {
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_like_mut()
+            .ok_or_else(|| VelocityError::config(format!("'{}' is not a table", join_key(&keys[..=depth]))))?;
+    }
+
+    match table.get_mut(last).and_then(Item::as_value_mut) {
+        Some(existing) => {
+            let decor = existing.decor().clone();
+            *existing = value;
+            *existing.decor_mut() = decor;
+        }
+        None => {
+            table.insert(last, Item::Value(value));
+        }
+    }
+    Ok(())
+}
+
+fn unknown_key(keys: &[String], global: bool) -> VelocityError {
+    if keys[0] == "upgrade" && !global {
+        return VelocityError::config("Upgrade settings belong in the user config; use --global");
+    }
+    VelocityError::config(format!("Unknown config key '{}'", join_key(keys)))
+}
+
+/// Hide registry tokens and proxy passwords from printed settings
+fn redact_secrets(settings: &mut toml::Value) {
+    let Some(table) = settings.as_table_mut() else {
+        return;
+    };
+    if let Some(tokens) = table
+        .get_mut("registry")
+        .and_then(|registry| registry.get_mut("auth_tokens"))
+        .and_then(toml::Value::as_table_mut)
+    {
+        for (_, token) in tokens.iter_mut() {
+            *token = toml::Value::String("***".to_string());
+        }
+    }
+    if let Some(network) = table.get_mut("network").and_then(toml::Value::as_table_mut) {
+        for key in ["proxy", "https_proxy"] {
+            if let Some(toml::Value::String(url)) = network.get_mut(key) {
+                *url = redact_credentials(url);
+            }
+        }
+    }
+}
+
+/// A value as `get` prints it: strings bare, everything else as TOML
+fn display_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// `key = value` lines for every setting below `prefix`
+fn print_settings(prefix: &str, value: &toml::Value) {
+    for (keys, value) in leaves(value) {
+        let key: Vec<String> = [prefix.to_string(), join_key(&keys)]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect();
+        println!("{} = {}", key.join("."), value);
+    }
+}
+
+/// Every value that is not a non-empty table, with its key path
+fn leaves(value: &toml::Value) -> Vec<(Vec<String>, &toml::Value)> {
+    match value {
+        toml::Value::Table(table) if !table.is_empty() => table
+            .iter()
+            .flat_map(|(key, value)| {
+                leaves(value).into_iter().map(move |(mut keys, value)| {
+                    keys.insert(0, key.clone());
+                    (keys, value)
+                })
+            })
+            .collect(),
+        other => vec![(Vec::new(), other)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_delete_preserve_the_file() {
+        let keys = |key: &str| parse_key(key).unwrap();
+        assert_eq!(keys("network.concurrency"), ["network", "download_concurrency"]);
+        assert_eq!(keys(r#"network.tls."npm.corp.example".cafile"#), ["network", "tls", "npm.corp.example", "ca_file"]);
+        assert_eq!(join_key(&keys(r#"registry.scopes."@acme""#)), "registry.scopes.@acme");
+        assert!(parse_key("network..timeout").is_err());
+
+        let content = "# Project settings\n[network]\ntimeout = 30 # seconds\n";
+        let (updated, written) = set_in(content, &keys("network.timeout"), "60", false).unwrap();
+        assert_eq!(updated, "# Project settings\n[network]\ntimeout = 60 # seconds\n");
+        assert_eq!(written, toml::Value::Integer(60));
+
+        // Plain text where the setting is a string, even when it looks like a number
+        let (updated, written) = set_in(&updated, &keys("network.min_tls_version"), "1.3", false).unwrap();
+        assert_eq!(written, toml::Value::String("1.3".to_string()));
+        let (updated, _) = set_in(&updated, &keys(r#"registry.scopes."@acme""#), "https://npm.acme.dev", false).unwrap();
+        assert!(updated.contains("\"@acme\" = \"https://npm.acme.dev\""));
+
+        assert!(set_in(&updated, &keys("network.timeout"), "soon", false).is_err());
+        assert!(set_in(&updated, &keys("network.timeout.seconds"), "1", false).is_err());
+        assert!(set_in(&updated, &keys("network.tiemout"), "1", false).unwrap_err().to_string().contains("Unknown config key"));
+        assert!(set_in("", &keys("upgrade.channel"), "beta", false).is_err());
+        assert!(set_in("", &keys("upgrade.channel"), "beta", true).is_ok());
+
+        let deleted = delete_in(&updated, &keys("network.timeout"), false).unwrap().unwrap();
+        assert!(deleted.starts_with("# Project settings\n[network]\n"));
+        assert!(!deleted.contains("timeout"));
+        assert_eq!(delete_in(&deleted, &keys("network.timeout"), false).unwrap(), None);
+    }
+
+    #[test]
+    fn test_origin() {
+        let layer = |content: &str| toml::from_str::<toml::Value>(content).unwrap();
+        let layers = vec![
+            (ConfigSource::Default, layer("[network]\ntimeout = 30\nretries = 2\n")),
+            (ConfigSource::User("/home/config.toml".into()), layer("[network]\ntimeout = 60\nretries = 2\n")),
+            (ConfigSource::Project("/app/velocity.toml".into()), layer("[network]\ntimeout = 60\nretries = 2\n")),
+            (ConfigSource::Env, layer("[network]\ntimeout = 60\nretries = 5\n")),
+        ];
+        let keys = |key: &str| parse_key(key).unwrap();
+        assert_eq!(origin(&layers, &keys("network.timeout")).to_string(), "/home/config.toml");
+        assert_eq!(origin(&layers, &keys("network.retries")), &ConfigSource::Env);
+        assert_eq!(origin(&layers, &keys("network.proxy")), &ConfigSource::Default);
+        assert_eq!(leaves(&layers[0].1).len(), 2);
+    }
+
+    #[test]
+    fn test_redact_secrets() {
+        let mut settings: toml::Value = toml::from_str(
+            "[registry.auth_tokens]\n\"registry.npmjs.org\" = \"npm_secret\"\n[network]\nproxy = \"http://alice:pw@proxy:8080\"\n",
+        )
+        .unwrap();
+        redact_secrets(&mut settings);
+        assert_eq!(lookup(&settings, &parse_key(r#"registry.auth_tokens."registry.npmjs.org""#).unwrap()).unwrap().as_str(), Some("***"));
+        assert_eq!(settings["network"]["proxy"].as_str(), Some("http://***@proxy:8080"));
+    }
+}
@@ -6,6 +6,7 @@ use std::time::Instant;
 use clap::Args;
 use dialoguer::{Input, Select};
 
+use crate::cli::commands::install::{self, InstallArgs};
 use crate::cli::output;
 use crate::core::{VelocityResult, VelocityError};
 use crate::templates::TemplateManager;
@@ -43,6 +44,11 @@ pub struct CreateArgs {
     /// Use default options (no prompts)
     #[arg(short, long)]
     pub yes: bool,
+
+    /// Package manager to use; npm and pnpm are recorded in package.json and
+    /// install the dependencies themselves
+    #[arg(long, default_value = "velocity")]
+    pub pm: String,
 }
 
 const SUPPORTED_FRAMEWORKS: &[(&str, &str)] = &[
@@ -54,9 +60,17 @@ const SUPPORTED_FRAMEWORKS: &[(&str, &str)] = &[
     ("astro", "Astro - Build fast websites, faster"),
 ];
 
+const SUPPORTED_PACKAGE_MANAGERS: &[&str] = &["velocity", "npm", "pnpm"];
+
+/// Package managers corepack accepts in the `packageManager` field
+const COREPACK_PACKAGE_MANAGERS: &[&str] = &["npm", "pnpm", "yarn"];
+
 pub async fn execute(args: CreateArgs, json_output: bool) -> VelocityResult<()> {
     let start_time = Instant::now();
 
+    let package_manager = args.pm.to_lowercase();
+    validate_package_manager(&package_manager)?;
+
     // Get framework
     let framework = if let Some(f) = args.framework {
         validate_framework(&f)?;
@@ -128,8 +142,8 @@ pub async fn execute(args: CreateArgs, json_output: bool) -> VelocityResult<()>
 
         output::info(&format!(
             "Creating {} project '{}'{}...",
-            console::style(&framework).cyan(),
-            console::style(&project_name).green(),
+            output::highlight(&framework),
+            output::good(&project_name),
             extra_str
         ));
     }
@@ -154,6 +168,9 @@ pub async fn execute(args: CreateArgs, json_output: bool) -> VelocityResult<()>
         add_ecosystem_deps(&project_dir, &template_flags)?;
     }
 
+    // Record the package manager the project is meant to be used with
+    let package_manager_skipped = set_package_manager(&project_dir, &package_manager).await?;
+
     if let Some(ref pb) = progress {
         pb.set_message("Initializing git...");
     }
@@ -163,18 +180,26 @@ pub async fn execute(args: CreateArgs, json_output: bool) -> VelocityResult<()>
         init_git(&project_dir).await?;
     }
 
-    // Install dependencies
-    if !args.no_install {
-        if let Some(ref pb) = progress {
-            pb.set_message("Installing dependencies...");
-        }
-
-        install_dependencies(&project_dir).await?;
-    }
-
     if let Some(pb) = progress {
         pb.finish_and_clear();
     }
+    if let (Some(reason), false) = (&package_manager_skipped, json_output) {
+        output::warning(&format!("Left packageManager unset: {}", reason));
+    }
+
+    // Install dependencies through the same pipeline as `velocity install`; another
+    // package manager writes its own lockfile when the user runs it
+    let install_with_velocity = !args.no_install && package_manager == "velocity";
+    let install_summary = if install_with_velocity {
+        let install_args = InstallArgs::for_project(project_dir.clone());
+        let summary = install::run(&project_dir, &install_args, json_output).await?;
+        if !json_output {
+            install::report(&summary, json_output)?;
+        }
+        Some(summary)
+    } else {
+        None
+    };
 
     let duration = start_time.elapsed();
 
@@ -187,23 +212,26 @@ pub async fn execute(args: CreateArgs, json_output: bool) -> VelocityResult<()>
             "typescript": use_typescript,
             "web3": args.web3,
             "ai": args.ai,
+            "package_manager": package_manager,
+            "package_manager_skipped": package_manager_skipped,
+            "install": install_summary.as_ref().map(|s| s.to_json()),
             "duration_ms": duration.as_millis()
         }))?;
     } else {
         println!();
         output::success(&format!(
             "Created {} project in {}",
-            console::style(&framework).cyan(),
+            output::highlight(&framework),
             output::format_duration(duration.as_millis())
         ));
 
         println!();
         output::info("Next steps:");
         println!("  cd {}", project_name);
-        if args.no_install {
-            println!("  velocity install");
+        if !install_with_velocity {
+            println!("  {} install", package_manager);
         }
-        println!("  velocity run dev");
+        println!("  {} run dev", package_manager);
         println!();
     }
 
@@ -239,28 +267,59 @@ async fn init_git(project_dir: &PathBuf) -> VelocityResult<()> {
     Ok(())
 }
 
-async fn install_dependencies(project_dir: &PathBuf) -> VelocityResult<()> {
-    use crate::core::Engine;
-
-    let engine = Engine::new(project_dir).await?;
-    let package_json = engine.package_json()?;
-    let deps = package_json.all_dependencies();
+fn validate_package_manager(package_manager: &str) -> VelocityResult<()> {
+    if !SUPPORTED_PACKAGE_MANAGERS.contains(&package_manager) {
+        return Err(VelocityError::template(format!(
+            "Unknown package manager '{}'. Supported: {}",
+            package_manager,
+            SUPPORTED_PACKAGE_MANAGERS.join(", ")
+        )));
+    }
+    Ok(())
+}
 
-    if deps.is_empty() {
-        return Ok(());
+/// Write the `packageManager` field so tooling like corepack picks the right manager
+///
+/// Managers corepack does not know (velocity itself) leave the field unset.
+/// Returns why the field could not be written for one it does know.
+async fn set_package_manager(project_dir: &PathBuf, package_manager: &str) -> VelocityResult<Option<String>> {
+    if !COREPACK_PACKAGE_MANAGERS.contains(&package_manager) {
+        return Ok(None);
     }
+    let Some(version) = detect_version(package_manager).await else {
+        return Ok(Some(format!("{} was not found on PATH", package_manager)));
+    };
+
+    let pkg_json_path = project_dir.join("package.json");
+    let content = std::fs::read_to_string(&pkg_json_path)?;
+    let mut pkg: serde_json::Value = serde_json::from_str(&content)?;
 
-    let resolver = engine.resolver();
-    let resolution = resolver.resolve(&deps).await?;
+    pkg["packageManager"] = serde_json::json!(format!("{}@{}", package_manager, version));
 
-    let installer = engine.installer();
-    installer.install(&resolution, false, false).await?;
-    installer.link(&resolution).await?;
+    let updated = serde_json::to_string_pretty(&pkg)?;
+    std::fs::write(&pkg_json_path, updated)?;
 
-    let mut lockfile = resolution.lockfile;
-    lockfile.save(project_dir)?;
+    Ok(None)
+}
 
-    Ok(())
+/// Get the installed version of another package manager
+async fn detect_version(program: &str) -> Option<String> {
+    let output = tokio::process::Command::new(program)
+        .arg("--version")
+        .output()
+        .await
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if version.is_empty() {
+        None
+    } else {
+        Some(version)
+    }
 }
 
 fn add_ecosystem_deps(project_dir: &PathBuf, flags: &TemplateFlags) -> VelocityResult<()> {
@@ -294,3 +353,19 @@ fn add_ecosystem_deps(project_dir: &PathBuf, flags: &TemplateFlags) -> VelocityR
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_package_manager_field_skipped_for_velocity() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg_json = r#"{"name": "app", "version": "1.0.0"}"#;
+        std::fs::write(dir.path().join("package.json"), pkg_json).unwrap();
+
+        let skipped = set_package_manager(&dir.path().to_path_buf(), "velocity").await.unwrap();
+        assert_eq!(skipped, None);
+        assert_eq!(std::fs::read_to_string(dir.path().join("package.json")).unwrap(), pkg_json);
+    }
+}
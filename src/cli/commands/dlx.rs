@@ -0,0 +1,124 @@
+//! velocity dlx - Fetch a package into a temporary directory and run its binary
+
+use std::env;
+use clap::Args;
+
+use crate::cli::commands::exec::{find_binary, finish, run_binary};
+use crate::cli::commands::install::{self, InstallArgs};
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{VelocityError, VelocityResult};
+use crate::utils::parse_package_spec;
+
+#[derive(Args)]
+pub struct DlxArgs {
+    /// Package to run, with an optional version or tag (with --package, the binary to run)
+    pub command: String,
+
+    /// Arguments to pass to the binary
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+
+    /// Install these packages and run COMMAND as one of their binaries
+    #[arg(short, long = "package", value_name = "PACKAGE")]
+    pub packages: Vec<String>,
+}
+
+pub async fn execute(args: DlxArgs, json_output: bool) -> VelocityResult<()> {
+    let specs = if args.packages.is_empty() {
+        vec![args.command.clone()]
+    } else {
+        args.packages.clone()
+    };
+
+    // A throwaway project; packages still come from (and go to) the shared cache
+    let dir = tempfile::Builder::new().prefix("velocity-dlx-").tempdir()?;
+    let mut dependencies = serde_json::Map::new();
+    for spec in &specs {
+        let (name, range) = parse_package_spec(spec);
+        dependencies.insert(name, range.unwrap_or_else(|| "latest".to_string()).into());
+    }
+    let manifest = serde_json::json!({
+        "name": "velocity-dlx",
+        "version": "0.0.0",
+        "private": true,
+        "dependencies": dependencies,
+    });
+    std::fs::write(dir.path().join("package.json"), serde_json::to_string_pretty(&manifest)?)?;
+
+    // Registry and security settings follow the project dlx was run from
+    let config = project::current_project_root()?.join("velocity.toml");
+    if config.exists() {
+        std::fs::copy(&config, dir.path().join("velocity.toml"))?;
+    }
+
+    let progress = (!json_output).then(|| output::spinner(&format!("Fetching {}...", specs.join(", "))));
+    let install_args = InstallArgs::for_project(dir.path().to_path_buf());
+    let installed = install::run(dir.path(), &install_args, true).await;
+    if let Some(pb) = progress {
+        pb.finish_and_clear();
+    }
+    installed?;
+
+    let bin = if args.packages.is_empty() {
+        let (name, _) = parse_package_spec(&args.command);
+        let manifest_path = dir.path().join("node_modules").join(&name).join("package.json");
+        let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(manifest_path)?)?;
+        package_bin(&name, &manifest)?
+    } else {
+        args.command.clone()
+    };
+
+    let bin_dir = dir.path().join("node_modules").join(".bin");
+    let binary = find_binary(&bin, std::slice::from_ref(&bin_dir)).ok_or_else(|| {
+        VelocityError::other(format!("No binary named '{}' in {}", bin, specs.join(", ")))
+    })?;
+
+    let mut paths = vec![bin_dir];
+    if let Some(path_env) = env::var_os("PATH") {
+        paths.extend(env::split_paths(&path_env));
+    }
+    let status = run_binary(&binary, &args.args, &paths).await?;
+
+    // Exiting with the binary's code skips destructors, so clean up first
+    drop(dir);
+    finish(&bin, status, json_output)
+}
+
+/// The binary to run for a package: its only one, or the one named after it
+fn package_bin(name: &str, manifest: &serde_json::Value) -> VelocityResult<String> {
+    let unscoped = name.rsplit('/').next().unwrap_or(name);
+    match manifest.get("bin") {
+        Some(serde_json::Value::String(_)) => Ok(unscoped.to_string()),
+        Some(serde_json::Value::Object(bins)) if bins.len() == 1 => Ok(bins.keys().next().cloned().unwrap_or_default()),
+        Some(serde_json::Value::Object(bins)) if bins.contains_key(unscoped) => Ok(unscoped.to_string()),
+        Some(serde_json::Value::Object(bins)) => Err(VelocityError::other(format!(
+            "{} has several binaries ({}). Pick one with 'velocity dlx --package {} <binary>'",
+            name,
+            bins.keys().cloned().collect::<Vec<_>>().join(", "),
+            name
+        ))),
+        _ => Err(VelocityError::other(format!("{} does not provide a binary", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_bin() {
+        let single = serde_json::json!({ "bin": "./index.js" });
+        assert_eq!(package_bin("@scope/create-app", &single).unwrap(), "create-app");
+
+        let only = serde_json::json!({ "bin": { "cra": "./cli.js" } });
+        assert_eq!(package_bin("create-react-app", &only).unwrap(), "cra");
+
+        let named = serde_json::json!({ "bin": { "tsc": "./tsc", "typescript": "./ts" } });
+        assert_eq!(package_bin("typescript", &named).unwrap(), "typescript");
+
+        let several = serde_json::json!({ "bin": { "tsc": "./tsc", "tsserver": "./tsserver" } });
+        assert!(package_bin("typescript", &several).is_err());
+        assert!(package_bin("lodash", &serde_json::json!({})).is_err());
+    }
+}
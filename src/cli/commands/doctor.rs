@@ -1,26 +1,32 @@
 //! velocity doctor - Diagnose issues
 
-use std::env;
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use clap::Args;
 use which::which;
 
+use crate::cli::commands::check;
 use crate::cli::output;
-use crate::core::VelocityResult;
+use crate::core::project;
+use crate::core::{Engine, PackageJson, VelocityError, VelocityResult};
+use crate::registry::http;
 
 #[derive(Args)]
 pub struct DoctorArgs {
-    /// Project directory
-    #[arg(long, default_value = ".")]
-    pub cwd: PathBuf,
+    /// Remove other package managers' lockfiles, directories and packageManager field
+    #[arg(long)]
+    pub fix: bool,
+
+    /// Apply --fix without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
 }
 
 pub async fn execute(args: DoctorArgs, json_output: bool) -> VelocityResult<()> {
-    let project_dir = if args.cwd.is_absolute() {
-        args.cwd.clone()
-    } else {
-        env::current_dir()?.join(&args.cwd)
-    };
+    let project_dir = project::current_project_root()?;
+    let lockfile_path = crate::core::Config::load(&project_dir)
+        .unwrap_or_default()
+        .lockfile_path(&project_dir);
 
     let mut checks: Vec<DiagnosticCheck> = Vec::new();
 
@@ -41,7 +47,7 @@ pub async fn execute(args: DoctorArgs, json_output: bool) -> VelocityResult<()>
     checks.push(cache_check);
 
     // Check network
-    let network_check = check_network().await;
+    let network_check = check_network(&project_dir).await;
     checks.push(network_check);
 
     // Check node_modules
@@ -49,9 +55,19 @@ pub async fn execute(args: DoctorArgs, json_output: bool) -> VelocityResult<()>
     checks.push(nm_check);
 
     // Check lockfile
-    let lockfile_check = check_lockfile(&project_dir).await;
+    let lockfile_check = check_lockfile(&lockfile_path).await;
     checks.push(lockfile_check);
 
+    // Check for other package managers' leftovers
+    let artifacts = find_artifacts(&project_dir, &lockfile_path);
+    let fixed = if args.fix {
+        fix_artifacts(&project_dir, lockfile_path.exists(), &artifacts, args.yes, json_output)?
+    } else {
+        Vec::new()
+    };
+    let remaining: Vec<&Artifact> = artifacts.iter().filter(|a| !fixed.contains(a)).collect();
+    checks.push(check_artifacts(lockfile_path.exists(), &remaining, fixed.len()));
+
     let all_passed = checks.iter().all(|c| c.passed);
 
     if json_output {
@@ -62,7 +78,8 @@ pub async fn execute(args: DoctorArgs, json_output: bool) -> VelocityResult<()>
                 "passed": c.passed,
                 "message": c.message,
                 "details": c.details
-            })).collect::<Vec<_>>()
+            })).collect::<Vec<_>>(),
+            "fixed": fixed.iter().map(|a| a.describe()).collect::<Vec<_>>()
         }))?;
     } else {
         println!();
@@ -72,20 +89,20 @@ pub async fn execute(args: DoctorArgs, json_output: bool) -> VelocityResult<()>
 
         for check in &checks {
             let status = if check.passed {
-                console::style("✓").green().bold()
+                output::good("✓").bold()
             } else {
-                console::style("✗").red().bold()
+                output::bad("✗").bold()
             };
 
             println!(
                 "{} {} - {}",
                 status,
-                console::style(&check.name).bold(),
+                output::strong(&check.name),
                 check.message
             );
 
             if let Some(ref details) = check.details {
-                println!("  {}", console::style(details).dim());
+                println!("  {}", output::muted(details));
             }
         }
 
@@ -232,62 +249,97 @@ async fn check_cache(project_dir: &PathBuf) -> DiagnosticCheck {
     }
 }
 
-async fn check_network() -> DiagnosticCheck {
-    let client = reqwest::Client::new();
-    let result = client
-        .get("https://registry.npmjs.org")
-        .timeout(std::time::Duration::from_secs(5))
-        .send()
-        .await;
+async fn check_network(project_dir: &Path) -> DiagnosticCheck {
+    let config = crate::core::Config::load(project_dir).unwrap_or_default();
+    let registry = config.registry.url.clone();
+    let failed = |message: String, details: Option<String>| DiagnosticCheck {
+        name: "Network".to_string(),
+        passed: false,
+        message,
+        details,
+    };
 
-    match result {
-        Ok(response) if response.status().is_success() => DiagnosticCheck {
+    // Go through the configured proxy and CA certificates, as installs do
+    let client = match http::client_builder_for(&config.network, std::time::Duration::from_secs(5), &registry).and_then(|builder| {
+        builder.build().map_err(|e| VelocityError::Network(e.to_string()))
+    }) {
+        Ok(client) => client,
+        Err(e) => return failed("Invalid network settings".to_string(), Some(e.to_string())),
+    };
+    let via = reqwest::Url::parse(&registry)
+        .ok()
+        .and_then(|url| http::Proxies::from_config(&config.network).ok()?.for_url(&url))
+        .map(|proxy| format!(" via proxy {}", http::redact_credentials(proxy.as_str())))
+        .unwrap_or_default();
+
+    // Any answer but a server or proxy error means the registry can be reached;
+    // private registries often refuse anonymous requests for their root
+    let reachable = |status: reqwest::StatusCode| {
+        !status.is_server_error() && status != reqwest::StatusCode::PROXY_AUTHENTICATION_REQUIRED
+    };
+    match client.get(&registry).send().await {
+        Ok(response) if reachable(response.status()) => DiagnosticCheck {
             name: "Network".to_string(),
             passed: true,
-            message: "npm registry is reachable".to_string(),
+            message: format!("{} is reachable{}", registry, via),
             details: None,
         },
-        Ok(response) => DiagnosticCheck {
-            name: "Network".to_string(),
-            passed: false,
-            message: format!("Registry returned status {}", response.status()),
-            details: None,
-        },
-        Err(e) => DiagnosticCheck {
-            name: "Network".to_string(),
-            passed: false,
-            message: "Cannot reach npm registry".to_string(),
-            details: Some(format!("Error: {}", e)),
-        },
+        Ok(response) => failed(format!("{} returned status {}{}", registry, response.status(), via), None),
+        Err(e) => failed(format!("Cannot reach {}{}", registry, via), Some(format!("Error: {}", e))),
     }
 }
 
-async fn check_node_modules(project_dir: &PathBuf) -> DiagnosticCheck {
-    let node_modules = project_dir.join("node_modules");
-    if node_modules.exists() {
-        let count = std::fs::read_dir(&node_modules)
-            .map(|entries| entries.count())
-            .unwrap_or(0);
-        DiagnosticCheck {
+async fn check_node_modules(project_dir: &Path) -> DiagnosticCheck {
+    let failed = |message: String, details: Option<String>| DiagnosticCheck {
+        name: "node_modules".to_string(),
+        passed: false,
+        message,
+        details,
+    };
+    let report = match Engine::new(project_dir).await {
+        Ok(engine) => check::verify_node_modules(&engine),
+        Err(e) => Err(e),
+    };
+
+    match report {
+        Ok(Some(report)) if report.passed() => DiagnosticCheck {
             name: "node_modules".to_string(),
             passed: true,
-            message: format!("{} packages installed", count),
+            message: format!("{} packages match the lockfile", report.packages),
             details: None,
+        },
+        Ok(Some(report)) if report.installed => {
+            let details = report
+                .issues
+                .iter()
+                .map(|issue| issue.to_string().replace(&format!("{}/", project_dir.display()), ""))
+                .collect::<Vec<_>>()
+                .join("\n  ");
+            failed(
+                format!("{} difference(s) from the lockfile", report.issues.len()),
+                Some(format!("{}\n  Run 'velocity install' to fix them", details)),
+            )
         }
-    } else {
-        DiagnosticCheck {
+        // Nothing installed yet is not an error
+        Ok(Some(_)) => DiagnosticCheck {
             name: "node_modules".to_string(),
-            passed: true, // Not an error if not present
+            passed: true,
             message: "Not installed".to_string(),
             details: Some("Run 'velocity install' to install dependencies".to_string()),
-        }
+        },
+        Ok(None) => DiagnosticCheck {
+            name: "node_modules".to_string(),
+            passed: true,
+            message: "No lockfile to compare against".to_string(),
+            details: None,
+        },
+        Err(e) => failed("Could not compare with the lockfile".to_string(), Some(e.to_string())),
     }
 }
 
-async fn check_lockfile(project_dir: &PathBuf) -> DiagnosticCheck {
-    let lockfile_path = project_dir.join("velocity.lock");
+async fn check_lockfile(lockfile_path: &Path) -> DiagnosticCheck {
     if lockfile_path.exists() {
-        match crate::core::Lockfile::load(project_dir) {
+        match crate::core::Lockfile::load(lockfile_path) {
             Ok(Some(lockfile)) => DiagnosticCheck {
                 name: "Lockfile".to_string(),
                 passed: true,
@@ -304,7 +356,7 @@ async fn check_lockfile(project_dir: &PathBuf) -> DiagnosticCheck {
                 name: "Lockfile".to_string(),
                 passed: false,
                 message: "Lockfile is corrupted".to_string(),
-                details: Some("Delete velocity.lock and run 'velocity install'".to_string()),
+                details: Some(format!("Delete {} and run 'velocity install'", lockfile_path.display())),
             },
         }
     } else {
@@ -317,6 +369,200 @@ async fn check_lockfile(project_dir: &PathBuf) -> DiagnosticCheck {
     }
 }
 
+/// Files and settings left behind by another package manager
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Artifact {
+    /// A lockfile written by another package manager
+    Lockfile { path: PathBuf, manager: &'static str },
+
+    /// A directory another package manager keeps its state in
+    Directory { path: PathBuf, manager: &'static str },
+
+    /// A `packageManager` field naming a package manager other than the one
+    /// whose lockfile the project has
+    PackageManagerField { value: String },
+}
+
+/// Lockfiles of other package managers, relative to the project root
+const FOREIGN_LOCKFILES: &[(&str, &str)] = &[
+    ("package-lock.json", "npm"),
+    ("npm-shrinkwrap.json", "npm"),
+    ("yarn.lock", "yarn"),
+    ("pnpm-lock.yaml", "pnpm"),
+    ("bun.lockb", "bun"),
+    ("bun.lock", "bun"),
+];
+
+/// State directories and files of other package managers, relative to the project root
+const FOREIGN_PATHS: &[(&str, &str)] = &[
+    (".yarn", "yarn"),
+    (".pnp.cjs", "yarn"),
+    (".pnp.loader.mjs", "yarn"),
+    ("node_modules/.pnpm", "pnpm"),
+    ("node_modules/.yarn-state.yml", "yarn"),
+    ("node_modules/.package-lock.json", "npm"),
+];
+
+impl Artifact {
+    /// One-line description for reports
+    fn describe(&self) -> String {
+        match self {
+            Artifact::Lockfile { path, manager } | Artifact::Directory { path, manager } => {
+                format!("{} ({})", path.display(), manager)
+            }
+            Artifact::PackageManagerField { value } => format!("packageManager: \"{}\"", value),
+        }
+    }
+
+    /// Remove the artifact
+    ///
+    /// corepack rejects `velocity@...`, so packageManager is removed rather than repointed.
+    fn fix(&self, project_dir: &Path) -> VelocityResult<()> {
+        match self {
+            Artifact::Lockfile { path, .. } => std::fs::remove_file(project_dir.join(path))?,
+            Artifact::Directory { path, .. } => {
+                let full = project_dir.join(path);
+                if full.is_dir() {
+                    std::fs::remove_dir_all(full)?;
+                } else {
+                    std::fs::remove_file(full)?;
+                }
+            }
+            Artifact::PackageManagerField { .. } => {
+                let mut pkg = PackageJson::load(project_dir)?;
+                pkg.package_manager = None;
+                pkg.save(project_dir)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find other package managers' lockfiles, directories and packageManager claims
+///
+/// A packageManager field only conflicts with a lockfile another manager wrote:
+/// a project created with `--pm npm` has none until npm installs it.
+fn find_artifacts(project_dir: &Path, lockfile_path: &Path) -> Vec<Artifact> {
+    let mut artifacts = Vec::new();
+    let mut lockfile_managers = Vec::new();
+    if lockfile_path.is_file() {
+        lockfile_managers.push("velocity");
+    }
+
+    for (name, manager) in FOREIGN_LOCKFILES {
+        if project_dir.join(name).is_file() {
+            artifacts.push(Artifact::Lockfile { path: PathBuf::from(name), manager });
+            lockfile_managers.push(manager);
+        }
+    }
+
+    for (name, manager) in FOREIGN_PATHS {
+        if project_dir.join(name).exists() {
+            artifacts.push(Artifact::Directory { path: PathBuf::from(name), manager });
+        }
+    }
+
+    let package_manager = PackageJson::load(project_dir).ok().and_then(|pkg| pkg.package_manager);
+    if let Some(value) = package_manager {
+        let declared = value.split('@').next().unwrap_or_default();
+        if lockfile_managers.iter().any(|manager| *manager != declared) {
+            artifacts.push(Artifact::PackageManagerField { value });
+        }
+    }
+
+    artifacts
+}
+
+/// Report leftovers; they conflict once velocity.lock exists
+fn check_artifacts(has_lockfile: bool, artifacts: &[&Artifact], fixed: usize) -> DiagnosticCheck {
+    let name = "Package managers".to_string();
+    let fixed_note = if fixed > 0 { format!(" ({} fixed)", fixed) } else { String::new() };
+
+    if artifacts.is_empty() {
+        return DiagnosticCheck {
+            name,
+            passed: true,
+            message: format!("No other package manager artifacts{}", fixed_note),
+            details: None,
+        };
+    }
+
+    let listed = artifacts.iter().map(|a| a.describe()).collect::<Vec<_>>().join(", ");
+
+    // Before the first velocity install, another lockfile is a migration source, not a conflict
+    let foreign_lockfile = artifacts.iter().find_map(|a| match a {
+        Artifact::Lockfile { manager, .. } => Some(*manager),
+        _ => None,
+    });
+    if let (false, Some(manager)) = (has_lockfile, foreign_lockfile) {
+        return DiagnosticCheck {
+            name,
+            passed: true,
+            message: format!("Found {}", listed),
+            details: Some(format!("Run 'velocity migrate --from {}' to carry over locked versions", manager)),
+        };
+    }
+
+    DiagnosticCheck {
+        name,
+        passed: false,
+        message: format!("Conflicting artifacts{}: {}", fixed_note, listed),
+        details: Some("Installs may diverge between tools. Run 'velocity doctor --fix' to clean up".to_string()),
+    }
+}
+
+/// Apply fixes after confirmation; returns the artifacts fixed
+///
+/// Without velocity.lock, other lockfiles are kept: they are the only record of locked versions.
+/// With no one to ask (--json, or stdin is not a terminal) nothing changes unless `yes` is set.
+fn fix_artifacts(
+    project_dir: &Path,
+    has_lockfile: bool,
+    artifacts: &[Artifact],
+    yes: bool,
+    json_output: bool,
+) -> VelocityResult<Vec<Artifact>> {
+    let fixable: Vec<&Artifact> = artifacts
+        .iter()
+        .filter(|a| has_lockfile || !matches!(a, Artifact::Lockfile { .. }))
+        .collect();
+
+    if fixable.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    if !yes && (json_output || !std::io::stdin().is_terminal()) {
+        return Err(VelocityError::config(
+            "doctor --fix deletes files and cannot ask for confirmation here; pass --yes to apply it",
+        ));
+    }
+
+    if !yes {
+        output::info("velocity doctor --fix will:");
+        for artifact in &fixable {
+            println!("  remove {}", artifact.describe());
+        }
+
+        let confirm = dialoguer::Confirm::new()
+            .with_prompt("Apply these changes?")
+            .default(false)
+            .interact()?;
+
+        if !confirm {
+            output::info("Cancelled");
+            return Ok(Vec::new());
+        }
+    }
+
+    let mut fixed = Vec::new();
+    for artifact in fixable {
+        artifact.fix(project_dir)?;
+        fixed.push(artifact.clone());
+    }
+
+    Ok(fixed)
+}
+
 fn calculate_dir_size(path: &PathBuf) -> std::io::Result<u64> {
     let mut size = 0;
     if path.is_dir() {
@@ -332,3 +578,62 @@ fn calculate_dir_size(path: &PathBuf) -> std::io::Result<u64> {
     }
     Ok(size)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_and_fix_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(
+            root.join("package.json"),
+            r#"{"name": "app", "version": "1.0.0", "packageManager": "yarn@4.1.0"}"#,
+        ).unwrap();
+        std::fs::write(root.join("yarn.lock"), "").unwrap();
+        std::fs::create_dir_all(root.join("node_modules/.pnpm")).unwrap();
+
+        let lockfile_path = root.join("velocity.lock");
+
+        // Without velocity.lock the yarn lockfile is a migration source, and
+        // the packageManager field agrees with it
+        let artifacts = find_artifacts(root, &lockfile_path);
+        assert_eq!(artifacts.len(), 2);
+        let remaining: Vec<&Artifact> = artifacts.iter().filter(|a| matches!(a, Artifact::Lockfile { .. })).collect();
+        assert!(check_artifacts(false, &remaining, 0).passed);
+
+        std::fs::write(&lockfile_path, "").unwrap();
+        let artifacts = find_artifacts(root, &lockfile_path);
+        assert_eq!(artifacts.len(), 3);
+        assert!(artifacts.contains(&Artifact::PackageManagerField { value: "yarn@4.1.0".to_string() }));
+        let all: Vec<&Artifact> = artifacts.iter().collect();
+        assert!(!check_artifacts(true, &all, 0).passed);
+
+        // --json cannot prompt, so it needs --yes
+        assert!(fix_artifacts(root, true, &artifacts, false, true).is_err());
+        assert!(root.join("yarn.lock").exists());
+
+        let fixed = fix_artifacts(root, true, &artifacts, true, true).unwrap();
+        assert_eq!(fixed.len(), 3);
+        assert!(!root.join("yarn.lock").exists());
+        assert!(!root.join("node_modules/.pnpm").exists());
+        assert!(find_artifacts(root, &lockfile_path).is_empty());
+        assert!(PackageJson::load(root).unwrap().package_manager.is_none());
+    }
+
+    #[test]
+    fn test_package_manager_field_without_lockfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(
+            root.join("package.json"),
+            r#"{"name": "app", "version": "1.0.0", "packageManager": "pnpm@9.1.0"}"#,
+        ).unwrap();
+        let lockfile_path = root.join("velocity.lock");
+
+        assert!(find_artifacts(root, &lockfile_path).is_empty());
+        std::fs::write(root.join("pnpm-lock.yaml"), "").unwrap();
+        assert!(find_artifacts(root, &lockfile_path).iter().all(|a| matches!(a, Artifact::Lockfile { .. })));
+    }
+}
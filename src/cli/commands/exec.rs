@@ -0,0 +1,105 @@
+//! velocity exec - Run a binary from node_modules/.bin
+
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use clap::Args;
+use tokio::process::Command;
+
+use crate::cli::commands::run::bin_paths;
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{VelocityError, VelocityResult};
+
+#[derive(Args)]
+pub struct ExecArgs {
+    /// Binary to run
+    pub command: String,
+
+    /// Arguments to pass to the binary
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+pub async fn execute(args: ExecArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+
+    // The package's .bin, the workspace root's .bin, then PATH
+    let paths = bin_paths(&project_dir);
+    let binary = find_binary(&args.command, &paths).ok_or_else(|| {
+        VelocityError::other(format!(
+            "'{}' is not in node_modules/.bin or on PATH. Run 'velocity dlx {}' to fetch and run it.",
+            args.command, args.command
+        ))
+    })?;
+
+    let status = run_binary(&binary, &args.args, &paths).await?;
+    finish(&args.command, status, json_output)
+}
+
+/// First `name` found in `paths`, trying Windows executable extensions too
+pub(crate) fn find_binary(name: &str, paths: &[PathBuf]) -> Option<PathBuf> {
+    let candidates: Vec<String> = if cfg!(windows) {
+        ["", ".cmd", ".exe", ".bat"].iter().map(|ext| format!("{}{}", name, ext)).collect()
+    } else {
+        vec![name.to_string()]
+    };
+    paths
+        .iter()
+        .flat_map(|dir| candidates.iter().map(move |candidate| dir.join(candidate)))
+        .find(|path| path.is_file())
+}
+
+/// Run `binary` in the current directory with `paths` as PATH, wired to this terminal
+pub(crate) async fn run_binary(binary: &Path, args: &[String], paths: &[PathBuf]) -> VelocityResult<ExitStatus> {
+    let path: OsString = env::join_paths(paths).map_err(|e| VelocityError::other(format!("Invalid PATH: {}", e)))?;
+    let status = Command::new(binary)
+        .args(args)
+        .current_dir(env::current_dir()?)
+        .env("PATH", path)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .await?;
+    Ok(status)
+}
+
+/// Report how the binary exited; a failing binary's exit code becomes velocity's own
+pub(crate) fn finish(command: &str, status: ExitStatus, json_output: bool) -> VelocityResult<()> {
+    if json_output {
+        output::json(&serde_json::json!({
+            "command": command,
+            "success": status.success(),
+            "exit_code": status.code()
+        }))?;
+    }
+
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_binary_prefers_earlier_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let (local, global) = (dir.path().join("local"), dir.path().join("global"));
+        for bin in [&local, &global] {
+            std::fs::create_dir_all(bin).unwrap();
+            std::fs::write(bin.join("tool"), "").unwrap();
+        }
+        std::fs::write(global.join("other"), "").unwrap();
+
+        let paths = vec![local.clone(), global.clone()];
+        assert_eq!(find_binary("tool", &paths), Some(local.join("tool")));
+        assert_eq!(find_binary("other", &paths), Some(global.join("other")));
+        assert_eq!(find_binary("missing", &paths), None);
+    }
+}
@@ -0,0 +1,322 @@
+//! velocity export - Write velocity.lock in another package manager's format
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{Engine, Lockfile, PackageJson, VelocityError, VelocityResult};
+use crate::installer::layout::{hoisted_layout, MemberDependencies, PackageIndex};
+use crate::resolver::ResolvedPackage;
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Lockfile format to write
+    #[arg(long, value_enum, default_value_t = ExportFormat::Npm)]
+    pub format: ExportFormat,
+
+    /// File to write (default: package-lock.json in the project; `-` for stdout)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// package-lock.json, lockfileVersion 3
+    Npm,
+}
+
+pub async fn execute(args: ExportArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+    let engine = Engine::new(&project_dir).await?;
+    engine.ensure_initialized()?;
+
+    let package_json = engine.package_json()?;
+    let lockfile = engine.lockfile()?.ok_or_else(|| {
+        VelocityError::other("No lockfile found. Run 'velocity install' first.")
+    })?;
+    let members = match engine.workspace {
+        Some(ref workspace) if engine.installs_workspace() => workspace
+            .package_jsons()?
+            .into_iter()
+            .map(|(dir, pkg)| (dir.strip_prefix(&engine.project_dir).unwrap_or(&dir).to_path_buf(), pkg))
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let resolution = engine.resolver().resolve_from_lockfile(&lockfile)?;
+    let resolved: Vec<&ResolvedPackage> = resolution.to_install.iter().chain(resolution.from_cache.iter()).collect();
+    let exported = match args.format {
+        ExportFormat::Npm => npm_lockfile(&package_json, &lockfile, &resolved, &members),
+    };
+    let content = format!("{}\n", serde_json::to_string_pretty(&exported)?);
+
+    let path = match args.output {
+        Some(ref path) if path == Path::new("-") => {
+            print!("{}", content);
+            return Ok(());
+        }
+        Some(path) => path,
+        None => engine.project_dir.join("package-lock.json"),
+    };
+    std::fs::write(&path, content)?;
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "success": true,
+            "format": "npm",
+            "path": path,
+            "packages": exported.packages.len(),
+        }))?;
+    } else {
+        output::success(&format!("Wrote {} ({} entries)", path.display(), exported.packages.len()));
+    }
+
+    Ok(())
+}
+
+/// A package-lock.json, version 3
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NpmLockfile {
+    pub name: String,
+    pub version: String,
+    pub lockfile_version: u32,
+    pub requires: bool,
+
+    /// Entries keyed by install path; `""` is the project itself
+    pub packages: BTreeMap<String, NpmPackage>,
+}
+
+/// One `packages` entry of a package-lock.json
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NpmPackage {
+    /// Real package name, when it differs from the install path's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub link: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dev: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub has_install_script: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspaces: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub dev_dependencies: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub optional_dependencies: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub peer_dependencies: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub os: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cpu: Vec<String>,
+}
+
+impl NpmPackage {
+    /// The entry for a project or workspace member, as its package.json declares it
+    fn project(pkg: &PackageJson) -> Self {
+        let sorted = |deps: &HashMap<String, String>| deps.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        Self {
+            name: Some(pkg.name.clone()).filter(|name| !name.is_empty()),
+            version: Some(pkg.version.clone()).filter(|version| !version.is_empty()),
+            workspaces: pkg.workspaces.as_ref().and_then(|w| serde_json::to_value(w).ok()),
+            dependencies: sorted(&pkg.dependencies),
+            dev_dependencies: sorted(&pkg.dev_dependencies),
+            optional_dependencies: sorted(&pkg.optional_dependencies),
+            peer_dependencies: sorted(&pkg.peer_dependencies),
+            ..Default::default()
+        }
+    }
+}
+
+/// Lay the locked packages out the way npm would install them
+///
+/// npm hoists, so the tree is the hoisted layout velocity itself uses in that
+/// mode. velocity.lock records peer dependency names but not their ranges, so
+/// installed packages list no `peerDependencies`.
+pub fn npm_lockfile(
+    package_json: &PackageJson,
+    lockfile: &Lockfile,
+    resolved: &[&ResolvedPackage],
+    members: &[(PathBuf, PackageJson)],
+) -> NpmLockfile {
+    let mut packages = BTreeMap::new();
+    packages.insert(String::new(), NpmPackage::project(package_json));
+
+    // Members are entries of their own, linked into the root node_modules
+    let member_deps: Vec<HashMap<String, String>> = members
+        .iter()
+        .map(|(_, pkg)| pkg.all_dependencies().into_iter().filter(|(_, spec)| !spec.starts_with("workspace:")).collect())
+        .collect();
+    let member_layout: Vec<MemberDependencies> = members
+        .iter()
+        .zip(&member_deps)
+        .map(|((_, pkg), dependencies)| MemberDependencies { name: &pkg.name, dependencies })
+        .collect();
+    for (dir, pkg) in members {
+        let dir = npm_path(dir);
+        packages.insert(format!("node_modules/{}", pkg.name), NpmPackage {
+            resolved: Some(dir.clone()),
+            link: true,
+            ..Default::default()
+        });
+        packages.insert(dir, NpmPackage::project(pkg));
+    }
+
+    let root_deps: HashMap<String, String> = package_json
+        .all_dependencies()
+        .into_iter()
+        .filter(|(_, spec)| !spec.starts_with("workspace:"))
+        .collect();
+    let index = PackageIndex::new(resolved);
+    for placement in hoisted_layout(&index, &root_deps, &member_layout, true) {
+        let package = placement.package;
+        let member_dir = placement
+            .workspace
+            .as_ref()
+            .and_then(|name| members.iter().find(|(_, pkg)| &pkg.name == name))
+            .map(|(dir, _)| npm_path(dir));
+        let mut key = member_dir.map(|dir| format!("{}/", dir)).unwrap_or_default();
+        for parent in &placement.parents {
+            key.push_str(&format!("node_modules/{}/", parent));
+        }
+        key.push_str(&format!("node_modules/{}", package.name));
+
+        let locked = lockfile.find_package(&package.name, &package.version);
+        let (optional, dependencies): (BTreeMap<String, String>, BTreeMap<String, String>) = package
+            .dependencies
+            .iter()
+            .map(|(dep, spec)| (dep.clone(), spec.clone()))
+            .partition(|(dep, _)| package.optional_dependencies.contains_key(dep));
+
+        packages.insert(key, NpmPackage {
+            name: package.alias_of.clone(),
+            version: Some(package.version.clone()),
+            resolved: Some(package.tarball_url.clone()).filter(|url| !url.is_empty()),
+            integrity: Some(package.integrity.clone()).filter(|integrity| !integrity.is_empty()),
+            dev: locked.is_some_and(|locked| locked.dev),
+            has_install_script: package.has_scripts,
+            dependencies,
+            optional_dependencies: optional,
+            os: locked.map(|locked| locked.os.clone()).unwrap_or_default(),
+            cpu: locked.map(|locked| locked.cpu.clone()).unwrap_or_default(),
+            ..Default::default()
+        });
+    }
+
+    NpmLockfile {
+        name: package_json.name.clone(),
+        version: package_json.version.clone(),
+        lockfile_version: 3,
+        requires: true,
+        packages,
+    }
+}
+
+/// A relative path with forward slashes, as package-lock.json writes them on every platform
+fn npm_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::lockfile::LockedPackage;
+    use crate::resolver::test_support;
+
+    /// The shared fixture with the tarball and integrity npm's lockfile records
+    fn package(name: &str, version: &str, deps: &[(&str, &str)]) -> ResolvedPackage {
+        ResolvedPackage {
+            tarball_url: format!("https://registry.npmjs.org/{0}/-/{0}-{1}.tgz", name, version),
+            integrity: format!("sha512-{}", name),
+            ..test_support::package(name, version, deps)
+        }
+    }
+
+    #[test]
+    fn test_npm_lockfile() {
+        let package_json: PackageJson = serde_json::from_str(
+            r#"{"name": "app", "version": "1.0.0", "dependencies": {"a": "^1.0.0", "b": "^2.0.0"}, "devDependencies": {"c": "^1.0.0"}}"#,
+        ).unwrap();
+
+        // a needs b@1 while the project wants b@2, so b@1 nests under a
+        let mut a = package("a", "1.0.0", &[("b", "^1.0.0"), ("fsevents", "^2.0.0")]);
+        a.optional_dependencies.insert("fsevents".to_string(), "^2.0.0".to_string());
+        let packages = [
+            a,
+            package("b", "1.0.0", &[]),
+            package("b", "2.0.0", &[]),
+            package("c", "1.0.0", &[]),
+            package("fsevents", "2.3.3", &[]),
+        ];
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package(LockedPackage {
+            name: "c".to_string(),
+            alias_of: None,
+            version: "1.0.0".to_string(),
+            resolved: String::new(),
+            integrity: String::new(),
+            dependencies: vec![],
+            peer_dependencies: vec![],
+            optional_dependencies: vec![],
+            has_scripts: false,
+            approved_scripts: None,
+            cpu: vec![],
+            os: vec![],
+            dev: true,
+            provenance: None,
+        });
+
+        let resolved: Vec<&ResolvedPackage> = packages.iter().collect();
+        let exported = npm_lockfile(&package_json, &lockfile, &resolved, &[]);
+        let keys: Vec<&str> = exported.packages.keys().map(String::as_str).collect();
+        assert_eq!(
+            keys,
+            ["", "node_modules/a", "node_modules/a/node_modules/b", "node_modules/b", "node_modules/c", "node_modules/fsevents"]
+        );
+
+        let json = serde_json::to_value(&exported).unwrap();
+        assert_eq!(json["lockfileVersion"], 3);
+        assert_eq!(json["packages"][""]["devDependencies"]["c"], "^1.0.0");
+        assert_eq!(json["packages"]["node_modules/a"]["dependencies"], serde_json::json!({"b": "^1.0.0"}));
+        assert_eq!(json["packages"]["node_modules/a"]["optionalDependencies"]["fsevents"], "^2.0.0");
+        assert_eq!(json["packages"]["node_modules/a/node_modules/b"]["version"], "1.0.0");
+        assert_eq!(json["packages"]["node_modules/b"]["version"], "2.0.0");
+        assert_eq!(json["packages"]["node_modules/c"]["dev"], true);
+        assert!(json["packages"]["node_modules/b"].get("dev").is_none());
+    }
+
+    #[test]
+    fn test_npm_lockfile_workspace_members() {
+        let root: PackageJson = serde_json::from_str(r#"{"name": "repo", "version": "1.0.0", "workspaces": ["packages/*"]}"#).unwrap();
+        let member: PackageJson = serde_json::from_str(
+            r#"{"name": "@repo/ui", "version": "0.1.0", "dependencies": {"a": "^1.0.0", "@repo/core": "workspace:*"}}"#,
+        ).unwrap();
+        let packages = [package("a", "1.0.0", &[])];
+        let resolved: Vec<&ResolvedPackage> = packages.iter().collect();
+
+        let exported = npm_lockfile(&root, &Lockfile::new(), &resolved, &[(PathBuf::from("packages/ui"), member)]);
+        let json = serde_json::to_value(&exported).unwrap();
+        assert_eq!(json["packages"][""]["workspaces"], serde_json::json!(["packages/*"]));
+        assert_eq!(json["packages"]["node_modules/@repo/ui"], serde_json::json!({"resolved": "packages/ui", "link": true}));
+        assert_eq!(json["packages"]["packages/ui"]["name"], "@repo/ui");
+        assert_eq!(json["packages"]["node_modules/a"]["version"], "1.0.0");
+    }
+}
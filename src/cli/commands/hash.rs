@@ -0,0 +1,63 @@
+//! velocity hash - Print a digest of the resolved dependency set
+
+use clap::Args;
+
+use crate::cli::output;
+use crate::core::lockfile::Platform;
+use crate::core::project;
+use crate::core::{Engine, VelocityError, VelocityResult};
+
+#[derive(Args)]
+pub struct HashArgs {
+    /// Hash the packages installed on this OS (Node.js naming, e.g. linux, darwin, win32)
+    #[arg(long, conflicts_with = "all_platforms")]
+    pub os: Option<String>,
+
+    /// Hash the packages installed on this CPU (Node.js naming, e.g. x64, arm64)
+    #[arg(long, conflicts_with = "all_platforms")]
+    pub cpu: Option<String>,
+
+    /// Hash every locked package regardless of os/cpu restrictions
+    #[arg(long)]
+    pub all_platforms: bool,
+}
+
+pub async fn execute(args: HashArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+    let engine = Engine::new(&project_dir).await?;
+    engine.ensure_initialized()?;
+
+    let lockfile_path = engine.lockfile_path();
+    let lockfile = engine.lockfile()?.ok_or_else(|| {
+        VelocityError::other(format!(
+            "No lockfile found at {}. Run 'velocity install' first.",
+            lockfile_path.display()
+        ))
+    })?;
+
+    let platform = (!args.all_platforms).then(|| {
+        let current = Platform::current();
+        Platform {
+            os: args.os.unwrap_or(current.os),
+            cpu: args.cpu.unwrap_or(current.cpu),
+        }
+    });
+    let (digest, packages) = lockfile.digest(platform.as_ref());
+
+    if json_output {
+        let (algorithm, value) = digest.split_once('-').unwrap_or(("sha256", &digest));
+        output::json(&serde_json::json!({
+            "digest": digest,
+            "algorithm": algorithm,
+            "value": value,
+            "platform": platform.as_ref().map(|p| p.to_string()),
+            "packages": packages,
+            "lockfile": lockfile_path,
+        }))?;
+    } else {
+        // Bare digest on stdout so it can be captured by scripts
+        println!("{}", digest);
+    }
+
+    Ok(())
+}
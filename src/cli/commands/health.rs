@@ -0,0 +1,414 @@
+//! velocity health - Dependency freshness and project health report
+
+use std::collections::BTreeMap;
+use clap::Args;
+use futures::stream::{self, StreamExt};
+
+use crate::cli::output;
+use crate::core::lockfile::LockedPackage;
+use crate::core::project;
+use crate::core::{Engine, VelocityError, VelocityResult};
+use crate::registry::types::PackageMetadata;
+use crate::security::{RiskLevel, SupplyChainGuard};
+
+#[derive(Args)]
+pub struct HealthArgs {
+    /// Years without a publish after which a package counts as unmaintained
+    #[arg(long, default_value_t = 2, value_name = "YEARS")]
+    pub unmaintained_years: u32,
+
+    /// List every package with a finding, not just the totals
+    #[arg(long)]
+    pub details: bool,
+}
+
+/// How far a locked version trails the latest release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Lag {
+    Current,
+    Patch,
+    Minor,
+    Major,
+}
+
+impl Lag {
+    /// Compare a locked version with the registry's latest
+    fn between(locked: &semver::Version, latest: &semver::Version) -> Self {
+        if locked >= latest {
+            Lag::Current
+        } else if locked.major != latest.major {
+            Lag::Major
+        } else if locked.minor != latest.minor {
+            Lag::Minor
+        } else {
+            Lag::Patch
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Lag::Current => "current",
+            Lag::Patch => "patch",
+            Lag::Minor => "minor",
+            Lag::Major => "major",
+        }
+    }
+}
+
+/// Signals gathered for one locked package
+#[derive(Debug, Clone)]
+struct PackageHealth {
+    name: String,
+    version: String,
+    latest: Option<String>,
+    lag: Option<Lag>,
+    deprecated: Option<String>,
+    /// Years since the latest publish
+    idle_years: Option<f64>,
+    risk: RiskLevel,
+    has_scripts: bool,
+}
+
+/// Aggregated counts the score is computed from
+#[derive(Debug, Default, Clone, PartialEq)]
+struct HealthTotals {
+    packages: usize,
+    /// Packages whose registry metadata could not be fetched
+    unknown: usize,
+    current: usize,
+    patch_behind: usize,
+    minor_behind: usize,
+    major_behind: usize,
+    deprecated: usize,
+    unmaintained: usize,
+    medium_risk: usize,
+    high_risk: usize,
+    /// Packages locked at more than one version
+    duplicated: usize,
+    install_scripts: usize,
+}
+
+impl HealthTotals {
+    /// Score from 0 to 100; each signal's penalty is capped so no single one dominates
+    fn score(&self) -> u32 {
+        let penalty = |count: usize, each: f64, cap: f64| (count as f64 * each).min(cap);
+
+        let freshness = penalty(self.major_behind, 3.0, 30.0)
+            + penalty(self.minor_behind, 1.0, 10.0)
+            + penalty(self.patch_behind, 0.25, 5.0);
+        let total = freshness
+            + penalty(self.deprecated, 5.0, 20.0)
+            + penalty(self.unmaintained, 2.0, 10.0)
+            + penalty(self.high_risk, 10.0, 20.0)
+            + penalty(self.medium_risk, 2.0, 10.0)
+            + penalty(self.duplicated, 1.0, 5.0)
+            + penalty(self.install_scripts, 1.0, 5.0);
+
+        (100.0 - total).clamp(0.0, 100.0).round() as u32
+    }
+
+    /// Share of checked packages on their latest release, as a percentage
+    fn freshness(&self) -> u32 {
+        let checked = self.packages - self.unknown;
+        if checked == 0 {
+            return 100;
+        }
+        ((self.current as f64 / checked as f64) * 100.0).round() as u32
+    }
+}
+
+/// Letter grade for a score
+fn grade(score: u32) -> char {
+    match score {
+        90..=100 => 'A',
+        80..=89 => 'B',
+        70..=79 => 'C',
+        60..=69 => 'D',
+        _ => 'F',
+    }
+}
+
+pub async fn execute(args: HealthArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+
+    let engine = Engine::new(&project_dir).await?;
+    engine.ensure_initialized()?;
+
+    let lockfile = engine.lockfile()?.ok_or_else(|| {
+        VelocityError::other("No lockfile found. Run 'velocity install' first.")
+    })?;
+
+    let progress = if !json_output {
+        Some(output::spinner(&format!("Checking {} packages...", lockfile.packages.len())))
+    } else {
+        None
+    };
+
+    // Metadata per name; one lookup covers every locked version
+    let mut names: Vec<&str> = lockfile.packages.iter().map(|p| p.name.as_str()).collect();
+    names.sort();
+    names.dedup();
+
+    let registry = &engine.registry;
+    let metadata: BTreeMap<&str, PackageMetadata> = stream::iter(names)
+        .map(|name| async move { (name, registry.get_package_metadata(name).await) })
+        .buffer_unordered(engine.config.network.metadata_concurrency.max(1))
+        .filter_map(|(name, result)| async move {
+            match result {
+                Ok(meta) => Some((name, meta)),
+                Err(e) => {
+                    tracing::debug!("No metadata for {}: {}", name, e);
+                    None
+                }
+            }
+        })
+        .collect()
+        .await;
+
+    if let Some(pb) = progress {
+        pb.finish_and_clear();
+    }
+
+    let now = chrono::Utc::now();
+    let packages: Vec<PackageHealth> = lockfile
+        .packages
+        .iter()
+        .map(|locked| package_health(locked, metadata.get(locked.name.as_str()), now))
+        .collect();
+
+    let mut versions_per_name: BTreeMap<&str, usize> = BTreeMap::new();
+    for package in &lockfile.packages {
+        *versions_per_name.entry(package.name.as_str()).or_default() += 1;
+    }
+    let duplicates: Vec<(&str, usize)> = versions_per_name
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .collect();
+
+    let totals = summarize(&packages, duplicates.len(), args.unmaintained_years);
+    let score = totals.score();
+    let unmaintained = |p: &&PackageHealth| {
+        p.idle_years.map(|y| y >= args.unmaintained_years as f64).unwrap_or(false)
+    };
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "score": score,
+            "grade": grade(score).to_string(),
+            "freshness": totals.freshness(),
+            "totals": {
+                "packages": totals.packages,
+                "unknown": totals.unknown,
+                "current": totals.current,
+                "behind": {
+                    "patch": totals.patch_behind,
+                    "minor": totals.minor_behind,
+                    "major": totals.major_behind,
+                },
+                "deprecated": totals.deprecated,
+                "unmaintained": totals.unmaintained,
+                "risk": {
+                    "medium": totals.medium_risk,
+                    "high": totals.high_risk,
+                },
+                "duplicated": totals.duplicated,
+                "install_scripts": totals.install_scripts,
+            },
+            "unmaintained_years": args.unmaintained_years,
+            "packages": packages.iter().map(|p| serde_json::json!({
+                "name": p.name,
+                "version": p.version,
+                "latest": p.latest,
+                "lag": p.lag.map(|l| l.as_str()),
+                "deprecated": p.deprecated,
+                "idle_years": p.idle_years.map(|y| (y * 10.0).round() / 10.0),
+                "unmaintained": unmaintained(&p),
+                "risk": format!("{:?}", p.risk).to_lowercase(),
+                "has_scripts": p.has_scripts,
+            })).collect::<Vec<_>>(),
+            "duplicates": duplicates.iter().map(|(name, count)| serde_json::json!({
+                "name": name,
+                "versions": count,
+            })).collect::<Vec<_>>(),
+        }))?;
+        return Ok(());
+    }
+
+    println!();
+    output::info("Velocity Health Report");
+    output::divider();
+    println!();
+
+    let styled_score = match score {
+        80..=100 => output::good(format!("{} ({})", score, grade(score))).bold(),
+        60..=79 => output::caution(format!("{} ({})", score, grade(score))).bold(),
+        _ => output::bad(format!("{} ({})", score, grade(score))).bold(),
+    };
+    println!("  Health score:      {}", styled_score);
+    println!("  Freshness:         {}% of packages on latest", totals.freshness());
+    println!();
+    println!("  Packages:          {}", totals.packages);
+    println!(
+        "  Behind latest:     {} major, {} minor, {} patch",
+        totals.major_behind, totals.minor_behind, totals.patch_behind
+    );
+    println!("  Deprecated:        {}", totals.deprecated);
+    println!("  Unmaintained:      {} (no publish in {}+ years)", totals.unmaintained, args.unmaintained_years);
+    println!("  Risk:              {} high, {} medium", totals.high_risk, totals.medium_risk);
+    println!("  Duplicate names:   {}", totals.duplicated);
+    println!("  Install scripts:   {}", totals.install_scripts);
+    if totals.unknown > 0 {
+        println!("  Not checked:       {} (registry metadata unavailable)", totals.unknown);
+    }
+
+    if args.details {
+        println!();
+        for package in &packages {
+            let mut findings = Vec::new();
+            if let (Some(lag), Some(latest)) = (package.lag, &package.latest) {
+                if lag != Lag::Current {
+                    findings.push(format!("{} behind ({})", lag.as_str(), latest));
+                }
+            }
+            if package.deprecated.is_some() {
+                findings.push("deprecated".to_string());
+            }
+            if unmaintained(&package) {
+                findings.push(format!("idle {:.1} years", package.idle_years.unwrap_or_default()));
+            }
+            if package.risk != RiskLevel::Low {
+                findings.push(format!("{:?} risk", package.risk).to_lowercase());
+            }
+            if package.has_scripts {
+                findings.push("install scripts".to_string());
+            }
+
+            if !findings.is_empty() {
+                println!(
+                    "  {} {}",
+                    output::package_version(&package.name, &package.version),
+                    output::muted(findings.join(", "))
+                );
+            }
+        }
+        for (name, count) in &duplicates {
+            println!("  {} {}", name, output::muted(format!("{} versions locked", count)));
+        }
+    }
+
+    println!();
+    output::divider();
+    if totals.deprecated > 0 || totals.high_risk > 0 {
+        output::warning("Replace deprecated and high-risk packages first. Run with --details for the list.");
+    } else if totals.major_behind > 0 {
+        output::info("Run 'velocity update' to pick up newer releases.");
+    } else {
+        output::success("Dependencies look healthy.");
+    }
+
+    Ok(())
+}
+
+/// Signals for one locked package; `metadata` is absent when the registry could not be reached
+fn package_health(
+    locked: &LockedPackage,
+    metadata: Option<&PackageMetadata>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> PackageHealth {
+    let latest = metadata.and_then(|m| m.dist_tags.get("latest")).cloned();
+    let lag = match (semver::Version::parse(&locked.version), latest.as_deref().map(semver::Version::parse)) {
+        (Ok(locked), Some(Ok(latest))) => Some(Lag::between(&locked, &latest)),
+        _ => None,
+    };
+
+    let deprecated = metadata
+        .and_then(|m| m.versions.get(&locked.version))
+        .and_then(|v| v.deprecated.clone())
+        .filter(|message| !message.is_empty());
+
+    // The newest publish of any version, not the registry's "modified" stamp
+    let last_publish = metadata.and_then(|m| {
+        m.time
+            .iter()
+            .filter(|(key, _)| key.as_str() != "created" && key.as_str() != "modified")
+            .filter_map(|(_, time)| chrono::DateTime::parse_from_rfc3339(time).ok())
+            .max()
+    });
+    let idle_years = last_publish.map(|time| (now - time.with_timezone(&chrono::Utc)).num_days() as f64 / 365.25);
+
+    PackageHealth {
+        name: locked.name.clone(),
+        version: locked.version.clone(),
+        latest,
+        lag,
+        deprecated,
+        idle_years,
+        risk: SupplyChainGuard::analyze(&locked.name).risk_level,
+        has_scripts: locked.has_scripts,
+    }
+}
+
+/// Count the signals across packages
+fn summarize(packages: &[PackageHealth], duplicated: usize, unmaintained_years: u32) -> HealthTotals {
+    let mut totals = HealthTotals {
+        packages: packages.len(),
+        duplicated,
+        ..Default::default()
+    };
+
+    for package in packages {
+        match package.lag {
+            None => totals.unknown += 1,
+            Some(Lag::Current) => totals.current += 1,
+            Some(Lag::Patch) => totals.patch_behind += 1,
+            Some(Lag::Minor) => totals.minor_behind += 1,
+            Some(Lag::Major) => totals.major_behind += 1,
+        }
+        if package.deprecated.is_some() {
+            totals.deprecated += 1;
+        }
+        if package.idle_years.map(|y| y >= unmaintained_years as f64).unwrap_or(false) {
+            totals.unmaintained += 1;
+        }
+        match package.risk {
+            RiskLevel::High => totals.high_risk += 1,
+            RiskLevel::Medium => totals.medium_risk += 1,
+            RiskLevel::Low => {}
+        }
+        if package.has_scripts {
+            totals.install_scripts += 1;
+        }
+    }
+
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(v: &str) -> semver::Version {
+        semver::Version::parse(v).unwrap()
+    }
+
+    #[test]
+    fn test_lag_and_score() {
+        assert_eq!(Lag::between(&version("1.2.3"), &version("1.2.3")), Lag::Current);
+        assert_eq!(Lag::between(&version("1.2.3"), &version("1.2.9")), Lag::Patch);
+        assert_eq!(Lag::between(&version("1.2.3"), &version("1.4.0")), Lag::Minor);
+        assert_eq!(Lag::between(&version("1.2.3"), &version("3.0.0")), Lag::Major);
+
+        let healthy = HealthTotals { packages: 10, current: 10, ..Default::default() };
+        assert_eq!(healthy.score(), 100);
+        assert_eq!(healthy.freshness(), 100);
+
+        // Penalties are capped per signal
+        let stale = HealthTotals { packages: 50, major_behind: 50, ..Default::default() };
+        assert_eq!(stale.score(), 70);
+        assert_eq!(grade(stale.score()), 'C');
+
+        let risky = HealthTotals { packages: 4, current: 2, unknown: 2, deprecated: 1, high_risk: 1, ..Default::default() };
+        assert_eq!(risky.score(), 85);
+        assert_eq!(risky.freshness(), 100);
+    }
+}
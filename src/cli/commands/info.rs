@@ -0,0 +1,294 @@
+//! velocity info - Show a package's registry metadata
+
+use clap::Args;
+use serde_json::{Map, Value};
+
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{Engine, VelocityError, VelocityResult};
+use crate::resolver::VersionConstraint;
+use crate::utils::parse_package_spec;
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// Package, optionally with a version, range or dist-tag (e.g. react@18)
+    pub package: String,
+
+    /// Fields to print instead of the summary, e.g. `version`, `dist-tags.latest` or `dependencies`
+    pub fields: Vec<String>,
+}
+
+pub async fn execute(args: InfoArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root().unwrap_or_else(|_| std::path::PathBuf::from("."));
+    let engine = Engine::new(&project_dir).await?;
+
+    let (name, spec) = parse_package_spec(&args.package);
+    let progress = if !json_output {
+        Some(output::spinner(&format!("Fetching {}...", name)))
+    } else {
+        None
+    };
+    let (packument, downloads) = tokio::join!(engine.registry.get_packument(&name), engine.registry.weekly_downloads(&name));
+    if let Some(pb) = progress {
+        pb.finish_and_clear();
+    }
+    let packument = packument?;
+    let version = pick_version(&packument, spec.as_deref()).ok_or_else(|| VelocityError::VersionNotFound {
+        package: name.clone(),
+        version: spec.clone().unwrap_or_else(|| "latest".to_string()),
+    })?;
+    let view = build_view(&packument, &version, downloads);
+
+    if !args.fields.is_empty() {
+        return print_fields(&view, &args.fields, json_output);
+    }
+    if json_output {
+        output::json(&view)?;
+        return Ok(());
+    }
+
+    print_summary(&view);
+    Ok(())
+}
+
+/// The version a spec names: a dist-tag, the newest version in a range, or `latest`
+fn pick_version(packument: &Value, spec: Option<&str>) -> Option<String> {
+    let tags = packument.get("dist-tags");
+    let spec = spec.filter(|spec| !spec.is_empty()).unwrap_or("latest");
+    if let Some(version) = tags.and_then(|tags| tags.get(spec)).and_then(Value::as_str) {
+        return Some(version.to_string());
+    }
+
+    let range = VersionConstraint::parse_strict(spec).ok()?;
+    packument
+        .get("versions")?
+        .as_object()?
+        .keys()
+        .filter_map(|version| semver::Version::parse(version).ok())
+        .filter(|version| range.matches(version))
+        .max()
+        .map(|version| version.to_string())
+}
+
+/// One version's manifest, with the package-wide fields `npm view` shows alongside it
+fn build_view(packument: &Value, version: &str, downloads: Option<u64>) -> Value {
+    let mut view = packument
+        .get("versions")
+        .and_then(|versions| versions.get(version))
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+
+    // Package-wide values fill in what the version manifest leaves out
+    for field in ["name", "description", "license", "homepage", "repository", "maintainers", "dist-tags"] {
+        if let (false, Some(value)) = (view.contains_key(field), packument.get(field)) {
+            view.insert(field.to_string(), value.clone());
+        }
+    }
+
+    let mut versions: Vec<semver::Version> = packument
+        .get("versions")
+        .and_then(Value::as_object)
+        .map(|versions| versions.keys().filter_map(|v| semver::Version::parse(v).ok()).collect())
+        .unwrap_or_default();
+    versions.sort();
+    view.insert(
+        "versions".to_string(),
+        versions.iter().map(|v| Value::String(v.to_string())).collect(),
+    );
+    if let Some(published) = packument.get("time").and_then(|time| time.get(version)) {
+        view.insert("published".to_string(), published.clone());
+    }
+    if let Some(downloads) = downloads {
+        view.insert("downloads".to_string(), downloads.into());
+    }
+
+    Value::Object(view)
+}
+
+/// A field of the view by dotted path (`dist.tarball`, `versions.0`)
+fn select<'a>(view: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(view, |value, key| match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Print selected fields: a lone field as its bare value, several as `field = value` lines
+fn print_fields(view: &Value, fields: &[String], json_output: bool) -> VelocityResult<()> {
+    if json_output {
+        if let [field] = fields {
+            output::json(&select(view, field).cloned().unwrap_or(Value::Null))?;
+        } else {
+            let selected: Map<String, Value> = fields
+                .iter()
+                .filter_map(|field| Some((field.clone(), select(view, field)?.clone())))
+                .collect();
+            output::json(&selected)?;
+        }
+        return Ok(());
+    }
+
+    for field in fields {
+        let Some(value) = select(view, field) else {
+            continue;
+        };
+        let text = match value {
+            Value::String(text) => text.clone(),
+            other => serde_json::to_string_pretty(other)?,
+        };
+        if fields.len() == 1 {
+            println!("{}", text);
+        } else {
+            println!("{} = {}", field, text);
+        }
+    }
+    Ok(())
+}
+
+fn print_summary(view: &Value) {
+    let text = |field: &str| select(view, field).and_then(Value::as_str).unwrap_or_default();
+    let dependencies = select(view, "dependencies").and_then(Value::as_object);
+    let versions = select(view, "versions").and_then(Value::as_array).map_or(0, Vec::len);
+
+    let mut headline = vec![output::package_version(text("name"), text("version"))];
+    headline.push(license(view).unwrap_or_else(|| "no license".to_string()));
+    headline.push(format!("deps: {}", dependencies.map_or(0, Map::len)));
+    headline.push(format!("versions: {}", versions));
+    println!("{}", headline.join(" | "));
+
+    if !text("description").is_empty() {
+        println!("{}", text("description"));
+    }
+    if !text("homepage").is_empty() {
+        println!("{}", output::highlight(text("homepage")));
+    }
+    if let Some(repository) = repository_url(view) {
+        println!("repository: {}", repository);
+    }
+
+    if !text("deprecated").is_empty() {
+        println!();
+        output::warning(&format!("Deprecated: {}", text("deprecated")));
+    }
+
+    if let Some(tags) = select(view, "dist-tags").and_then(Value::as_object) {
+        println!();
+        println!("{}", output::strong("dist-tags:"));
+        let mut tags: Vec<(&String, &Value)> = tags.iter().collect();
+        tags.sort_by(|a, b| (a.0 != "latest", a.0).cmp(&(b.0 != "latest", b.0)));
+        for (tag, version) in tags {
+            println!("  {}: {}", tag, version.as_str().unwrap_or_default());
+        }
+    }
+
+    if let Some(dependencies) = dependencies.filter(|deps| !deps.is_empty()) {
+        println!();
+        println!("{}", output::strong("dependencies:"));
+        let mut dependencies: Vec<(&String, &Value)> = dependencies.iter().collect();
+        dependencies.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, range) in dependencies {
+            println!("  {} {}", name, output::muted(range.as_str().unwrap_or_default()));
+        }
+    }
+
+    if let Some(maintainers) = select(view, "maintainers").and_then(Value::as_array).filter(|m| !m.is_empty()) {
+        println!();
+        println!("{}", output::strong("maintainers:"));
+        for maintainer in maintainers {
+            println!("  {}", person(maintainer));
+        }
+    }
+
+    println!();
+    if let Some(tarball) = select(view, "dist.tarball").and_then(Value::as_str) {
+        println!("tarball: {}", tarball);
+    }
+    if let Some(size) = select(view, "dist.unpackedSize").and_then(Value::as_u64) {
+        println!("unpacked size: {}", output::format_bytes(size));
+    }
+    if let Some(published) = select(view, "published").and_then(Value::as_str) {
+        println!("published: {}", published.get(..10).unwrap_or(published));
+    }
+    if let Some(downloads) = select(view, "downloads").and_then(Value::as_u64) {
+        println!("weekly downloads: {}", downloads);
+    }
+}
+
+/// SPDX license, from the string or the legacy `{ type, url }` form
+fn license(view: &Value) -> Option<String> {
+    match select(view, "license")? {
+        Value::String(license) => Some(license.clone()),
+        Value::Object(license) => license.get("type")?.as_str().map(str::to_string),
+        _ => None,
+    }
+}
+
+/// Repository URL from either the string or the `{ type, url }` form
+fn repository_url(view: &Value) -> Option<String> {
+    match select(view, "repository")? {
+        Value::String(url) => Some(url.clone()),
+        repository => repository.get("url")?.as_str().map(str::to_string),
+    }
+}
+
+/// `name <email>` from a person object, or the string as given
+fn person(value: &Value) -> String {
+    match value {
+        Value::String(person) => person.clone(),
+        person => {
+            let name = person.get("name").and_then(Value::as_str).unwrap_or_default();
+            match person.get("email").and_then(Value::as_str) {
+                Some(email) => format!("{} <{}>", name, email),
+                None => name.to_string(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_and_field_selection() {
+        let packument = serde_json::json!({
+            "name": "lib",
+            "description": "A library",
+            "dist-tags": { "latest": "1.2.0", "next": "2.0.0-beta.1" },
+            "maintainers": [{ "name": "ada", "email": "ada@example.com" }],
+            "repository": { "type": "git", "url": "git+https://example.com/lib.git" },
+            "time": { "1.2.0": "2024-06-01T00:00:00.000Z" },
+            "versions": {
+                "1.0.0": { "name": "lib", "version": "1.0.0", "deprecated": "use 1.2" },
+                "1.2.0": { "name": "lib", "version": "1.2.0", "license": "MIT",
+                           "dependencies": { "dep": "^1.0.0" }, "dist": { "tarball": "https://r/lib-1.2.0.tgz" } },
+                "2.0.0-beta.1": { "name": "lib", "version": "2.0.0-beta.1" },
+            },
+        });
+
+        assert_eq!(pick_version(&packument, None).as_deref(), Some("1.2.0"));
+        assert_eq!(pick_version(&packument, Some("next")).as_deref(), Some("2.0.0-beta.1"));
+        assert_eq!(pick_version(&packument, Some("~1.0")).as_deref(), Some("1.0.0"));
+        assert_eq!(pick_version(&packument, Some("^3")), None);
+        assert_eq!(pick_version(&packument, Some("nope")), None);
+
+        let view = build_view(&packument, "1.2.0", Some(42));
+        assert_eq!(select(&view, "version"), Some(&Value::from("1.2.0")));
+        assert_eq!(select(&view, "dist-tags.latest"), Some(&Value::from("1.2.0")));
+        assert_eq!(select(&view, "dist.tarball"), Some(&Value::from("https://r/lib-1.2.0.tgz")));
+        assert_eq!(select(&view, "versions.0"), Some(&Value::from("1.0.0")));
+        assert_eq!(select(&view, "published"), Some(&Value::from("2024-06-01T00:00:00.000Z")));
+        assert_eq!(select(&view, "downloads"), Some(&Value::from(42)));
+        assert_eq!(select(&view, "missing.field"), None);
+        assert_eq!(repository_url(&view).as_deref(), Some("git+https://example.com/lib.git"));
+        assert_eq!(person(select(&view, "maintainers.0").unwrap()), "ada <ada@example.com>");
+
+        // Deprecation is per version
+        let old = build_view(&packument, "1.0.0", None);
+        assert_eq!(select(&old, "deprecated"), Some(&Value::from("use 1.2")));
+        assert_eq!(select(&old, "description"), Some(&Value::from("A library")));
+        assert_eq!(select(&old, "downloads"), None);
+    }
+}
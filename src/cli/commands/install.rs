@@ -1,12 +1,22 @@
 //! velocity install - Install all dependencies
 
-use std::env;
-use std::path::PathBuf;
-use std::time::Instant;
-use clap::Args;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use clap::{Args, ValueEnum};
 
-use crate::cli::output;
-use crate::core::{Engine, VelocityResult};
+use crate::cli::output::{self, Reporter};
+use crate::core::node::NodeVersion;
+use crate::core::project;
+use crate::core::{Config, Engine, VelocityError, VelocityResult};
+use crate::installer::vendor::VENDOR_DIR;
+use crate::installer::{EngineIssue, InstallState, ScriptApprovals, ScriptOutcome, SkipReason, SkippedScript};
+use crate::resolver::{ForceResolve, PeerIssue, ResolveOptions};
+use crate::core::lockfile::Lockfile;
+use crate::security::licenses::LicensePolicy;
+use crate::security::provenance::{self, ProvenanceOutcome};
+use crate::security::SupplyChainGuard;
+use crate::utils::{MetricsSummary, NoProgress, Phase, Progress};
 
 #[derive(Args)]
 pub struct InstallArgs {
@@ -14,19 +24,35 @@ pub struct InstallArgs {
     #[arg(default_value = ".")]
     pub path: PathBuf,
 
-    /// Only install production dependencies
-    #[arg(long)]
+    /// Only install production dependencies, removing dev-only packages from node_modules
+    #[arg(long, visible_alias = "prod")]
     pub production: bool,
 
+    /// Dependency types to leave out of node_modules (`--omit=dev` is `--production`)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "TYPE")]
+    pub omit: Vec<Omit>,
+
     /// Skip running install scripts
     #[arg(long)]
     pub ignore_scripts: bool,
 
-    /// Force reinstall all packages
+    /// Approve these packages' install scripts, including ones that changed since their last approval
+    #[arg(long, value_delimiter = ',', value_name = "PACKAGES")]
+    pub approve_scripts: Vec<String>,
+
+    /// Force reinstall and re-resolve all packages, ignoring cached metadata and the lockfile
     #[arg(short, long)]
     pub force: bool,
 
-    /// Install in workspace mode
+    /// Re-resolve the given packages (all if none given) fresh from the registry
+    #[arg(long, num_args = 0.., value_delimiter = ',', value_name = "PACKAGES")]
+    pub force_resolve: Option<Vec<String>>,
+
+    /// Override the metadata cache TTL for this run, in seconds
+    #[arg(long, value_name = "SECONDS")]
+    pub metadata_ttl: Option<u64>,
+
+    /// Install the whole workspace from its root, even when run inside a member
     #[arg(short, long)]
     pub workspace: bool,
 
@@ -34,69 +60,363 @@ pub struct InstallArgs {
     #[arg(long)]
     pub prefer_offline: bool,
 
-    /// Frozen lockfile mode (fail if lockfile needs update)
-    #[arg(long)]
+    /// Never touch the network: use cached metadata of any age and cached tarballs
+    #[arg(long, conflicts_with = "prefer_offline")]
+    pub offline: bool,
+
+    /// Install exactly what velocity.lock records; fail instead of resolving if it is out of date
+    #[arg(long, conflicts_with_all = ["force", "force_resolve"])]
     pub frozen_lockfile: bool,
+
+    /// Install from the vendor directory only (see `velocity vendor`)
+    #[arg(long)]
+    pub vendored: bool,
+
+    /// Resolve and write velocity.lock without downloading or linking anything
+    #[arg(long, conflicts_with_all = ["vendored", "frozen_lockfile"])]
+    pub lockfile_only: bool,
+
+    /// Install into this node_modules directory instead of the project's own
+    #[arg(long, value_name = "DIR")]
+    pub modules_dir: Option<PathBuf>,
+
+    /// Keep lockfile entries that are no longer reachable from any package.json
+    #[arg(long)]
+    pub no_prune: bool,
+
+    /// Allow versions published within security.release_cooldown_hours
+    #[arg(long)]
+    pub ignore_cooldown: bool,
+
+    /// Skip re-checking cached packages against the lockfile's integrity hashes
+    #[arg(long)]
+    pub no_verify: bool,
+
+    /// Progress output style (summary-only is used automatically in CI)
+    #[arg(long, value_enum, value_name = "REPORTER")]
+    pub reporter: Option<Reporter>,
+
+    /// Report how long each phase took and the concurrency it ran with (implied by --verbose)
+    #[arg(long)]
+    pub timing: bool,
+}
+
+/// Dependency types `--omit` can leave out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Omit {
+    /// devDependencies and packages only they need
+    Dev,
+}
+
+impl InstallArgs {
+    /// Default install options for a project directory
+    pub fn for_project(path: PathBuf) -> Self {
+        Self {
+            path,
+            production: false,
+            omit: Vec::new(),
+            ignore_scripts: false,
+            approve_scripts: Vec::new(),
+            force: false,
+            force_resolve: None,
+            metadata_ttl: None,
+            workspace: false,
+            prefer_offline: false,
+            offline: false,
+            frozen_lockfile: false,
+            vendored: false,
+            lockfile_only: false,
+            modules_dir: None,
+            no_prune: false,
+            ignore_cooldown: false,
+            no_verify: false,
+            reporter: None,
+            timing: false,
+        }
+    }
+
+    /// Whether dev-only packages stay out of node_modules
+    pub fn omits_dev(&self) -> bool {
+        self.production || self.omit.contains(&Omit::Dev)
+    }
+}
+
+/// Outcome of the install pipeline
+pub struct InstallSummary {
+    /// Number of packages installed
+    pub installed: usize,
+
+    /// Number of packages restored from cache
+    pub cached: usize,
+
+    /// Number of packages flagged by the install-time audit
+    pub audit_warnings: usize,
+
+    /// Lifecycle scripts executed, in completion order
+    pub scripts: Vec<ScriptOutcome>,
+
+    /// Lifecycle scripts the security policy kept from running
+    pub skipped_scripts: Vec<SkippedScript>,
+
+    /// Number of unreachable lockfile entries dropped
+    pub pruned: usize,
+
+    /// Number of dev-only packages left out of node_modules
+    pub omitted: usize,
+
+    /// Unmet or conflicting peer dependencies
+    pub peer_issues: Vec<PeerIssue>,
+
+    /// Packages whose engines.node excludes the project's Node.js version
+    pub engine_issues: Vec<EngineIssue>,
+
+    /// Whether only the lockfile was written (--lockfile-only)
+    pub lockfile_only: bool,
+
+    /// Whether node_modules already matched the lockfile, so nothing was done
+    pub up_to_date: bool,
+
+    /// Whether progress was reported one line per phase
+    pub summary_only: bool,
+
+    /// Per-phase timings, when requested with --timing or --verbose
+    pub timing: Option<Vec<PhaseTiming>>,
+
+    /// Requests and bytes over the shared HTTP client, with --timing
+    pub network: Option<MetricsSummary>,
+
+    /// Total time spent
+    pub duration: Duration,
+}
+
+/// How long one install phase took
+pub struct PhaseTiming {
+    pub phase: &'static str,
+    pub duration: Duration,
+    /// Concurrency limit for phases that run in parallel
+    pub concurrency: Option<usize>,
 }
 
 pub async fn execute(args: InstallArgs, json_output: bool) -> VelocityResult<()> {
-    let start_time = Instant::now();
+    let mut project_dir = project::project_root_for(&args.path)?;
+    if args.workspace {
+        project_dir = project::find_workspace_root(&project_dir).ok_or_else(|| {
+            VelocityError::workspace(format!("{} is not inside a workspace", project_dir.display()))
+        })?;
+    }
 
-    let project_dir = if args.path.is_absolute() {
-        args.path.clone()
-    } else {
-        env::current_dir()?.join(&args.path)
-    };
+    let summary = run(&project_dir, &args, json_output).await?;
+    report(&summary, json_output)
+}
 
-    let engine = Engine::new(&project_dir).await?;
+/// Run the install pipeline for a project without printing the final summary
+///
+/// Shared by `velocity install` and every command that installs on the user's
+/// behalf (e.g. `velocity create`), so they resolve, audit, link and lock identically.
+pub async fn run(project_dir: &Path, args: &InstallArgs, json_output: bool) -> VelocityResult<InstallSummary> {
+    let start_time = Instant::now();
+    let summary_only = !json_output && Reporter::select(args.reporter) == Reporter::SummaryOnly;
+    let show_timing = args.timing || output::is_verbose();
+
+    let mut config = Config::load(project_dir)?;
+    if args.offline {
+        config.cache.offline = true;
+    }
+    if let Some(ref modules_dir) = args.modules_dir {
+        config.install.modules_dir = Some(modules_dir.clone());
+    }
+    let engine = Engine::with_config(project_dir, config).await?;
     engine.ensure_initialized()?;
 
     let package_json = engine.package_json()?;
+
+    // A lockfile git left conflict markers in is merged before anything reads it
+    if args.frozen_lockfile {
+        let conflicted = std::fs::read_to_string(engine.lockfile_path())
+            .is_ok_and(|content| Lockfile::has_conflict_markers(&content));
+        if conflicted {
+            return Err(VelocityError::other(
+                "velocity.lock has merge conflicts. Run 'velocity lockfile merge' and commit the result.",
+            ));
+        }
+    } else if let Some(merge) = super::lockfile::merge_conflicts(&engine).await? {
+        if !json_output && !summary_only {
+            output::info(&format!(
+                "Merged conflicts in velocity.lock ({} disputed package(s) re-resolved)",
+                merge.disputed.len()
+            ));
+        }
+    }
     let existing_lockfile = engine.lockfile()?;
 
-    if !json_output {
+    if !json_output && !summary_only {
         output::info(&format!("Installing dependencies for '{}'...", package_json.name));
     }
 
-    // Get dependencies to install
-    let deps = if args.production {
-        package_json.production_dependencies()
-    } else {
-        package_json.all_dependencies()
+    // The whole tree is resolved and locked even when dev packages are omitted,
+    // so production and full installs agree on every version. At a workspace
+    // root that tree covers every member as well.
+    let deps = package_json.all_dependencies();
+    let importers = engine.importers()?;
+    let workspace_install = engine.installs_workspace();
+
+    if importers.is_empty() && !workspace_install {
+        return Ok(InstallSummary {
+            installed: 0,
+            cached: 0,
+            audit_warnings: 0,
+            scripts: Vec::new(),
+            skipped_scripts: Vec::new(),
+            pruned: 0,
+            omitted: 0,
+            peer_issues: Vec::new(),
+            engine_issues: Vec::new(),
+            lockfile_only: args.lockfile_only,
+            up_to_date: false,
+            summary_only,
+            timing: None,
+            network: None,
+            duration: start_time.elapsed(),
+        });
+    }
+
+    // A lockfile that still matches every package.json (and workspace member) is installed as-is
+    let members = match engine.workspace {
+        Some(ref workspace) if workspace_install => workspace.package_jsons()?,
+        _ => Vec::new(),
     };
+    let stale = existing_lockfile.as_ref().map(|lockfile| {
+        lockfile.stale_reason(&engine.project_dir, &importers).or_else(|| {
+            members
+                .iter()
+                .find(|(_, pkg)| !lockfile.workspaces.contains_key(&pkg.name))
+                .map(|(_, pkg)| format!("workspace package {} is not in the lockfile", pkg.name))
+        })
+    });
 
-    if deps.is_empty() {
-        if json_output {
-            output::json(&serde_json::json!({
-                "success": true,
-                "installed": 0,
-                "duration_ms": start_time.elapsed().as_millis()
-            }))?;
-        } else {
-            output::success("No dependencies to install");
+    // node_modules installed from this exact lockfile and settings needs nothing more
+    let modules_dir = engine.config.install.modules_dir(&engine.project_dir);
+    let settings = install_settings(&engine, args);
+    let unforced = !args.force && args.force_resolve.is_none() && args.approve_scripts.is_empty();
+    if stale == Some(None) && unforced && !args.vendored && !args.lockfile_only {
+        let lockfile_bytes = std::fs::read(engine.lockfile_path())?;
+        if InstallState::load(&modules_dir)
+            .is_some_and(|state| state.is_current(&lockfile_bytes, &settings, &engine.project_dir))
+        {
+            // The policy may have changed since; what is installed must still satisfy it
+            if let Some(ref lockfile) = existing_lockfile {
+                engine.policy.check_lockfile(lockfile)?;
+                for locked in &lockfile.packages {
+                    engine.config.registry.check_association(locked.package_name(), &locked.resolved)?;
+                }
+            }
+            tracing::debug!("node_modules matches the install state; nothing to do");
+            return Ok(InstallSummary {
+                installed: 0,
+                cached: 0,
+                audit_warnings: 0,
+                scripts: Vec::new(),
+                skipped_scripts: Vec::new(),
+                pruned: 0,
+                omitted: 0,
+                peer_issues: Vec::new(),
+                engine_issues: Vec::new(),
+                lockfile_only: false,
+                up_to_date: true,
+                summary_only,
+                timing: None,
+                network: None,
+                duration: start_time.elapsed(),
+            });
         }
-        return Ok(());
     }
 
     // Show progress
-    let progress = if !json_output {
-        Some(output::spinner("Resolving dependencies..."))
+    let progress = if !json_output && !summary_only {
+        Some(Arc::new(output::InstallProgress::new("Resolving dependencies...")))
     } else {
         None
     };
+    let sink: Arc<dyn Progress> = match progress {
+        Some(ref progress) => progress.clone(),
+        None if output::is_json_stream() => Arc::new(output::EventStream::new()),
+        None => Arc::new(NoProgress),
+    };
 
-    // Resolve dependencies
-    let resolver = engine.resolver();
-    let resolution = resolver.resolve(&deps).await?;
+    if args.frozen_lockfile {
+        let problem = match stale {
+            None => Some("No lockfile found. Run 'velocity install' without --frozen-lockfile to generate one.".to_string()),
+            Some(Some(ref reason)) => Some(format!(
+                "Lockfile is out of date ({}). Run 'velocity install' without --frozen-lockfile to update.",
+                reason
+            )),
+            Some(None) => None,
+        };
+        if let Some(problem) = problem {
+            if let Some(pb) = progress {
+                pb.finish_and_clear();
+            }
+            return Err(crate::core::VelocityError::other(problem));
+        }
+    }
+    let from_lockfile = args.vendored
+        || (stale == Some(None) && !args.force && args.force_resolve.is_none());
+    if from_lockfile && !args.vendored {
+        tracing::debug!("Lockfile is up to date; skipping resolution");
+    }
 
-    if let Some(ref pb) = progress {
-        pb.set_message("Downloading packages...");
+    // Resolve dependencies; vendored and up-to-date installs take the lockfile as-is
+    let options = if args.force {
+        ResolveOptions {
+            force: ForceResolve::All,
+            metadata_ttl: args.metadata_ttl,
+            auto_install_peers: engine.config.install.auto_install_peers,
+            ..Default::default()
+        }
+    } else {
+        ResolveOptions {
+            force: ForceResolve::from_flag(args.force_resolve.clone()),
+            metadata_ttl: args.metadata_ttl,
+            auto_install_peers: engine.config.install.auto_install_peers,
+            ..Default::default()
+        }
+        .with_lockfile(existing_lockfile.as_ref())
+    };
+
+    let mut resolver = engine.resolver().with_options(options).with_progress(sink.clone());
+    if args.ignore_cooldown {
+        resolver = resolver.with_cooldown(None);
     }
+    sink.phase_started(Phase::Resolve, None);
+    let mut resolution = if from_lockfile {
+        let lockfile = existing_lockfile.as_ref().ok_or_else(|| {
+            crate::core::VelocityError::other(
+                "No lockfile found. Vendored installs require velocity.lock and a 'velocity vendor' directory."
+            )
+        })?;
+        resolver.resolve_from_lockfile(lockfile)?
+    } else if workspace_install {
+        resolver.resolve_workspace(&deps).await?
+    } else {
+        resolver.resolve(&deps).await?
+    };
+    sink.phase_finished(Phase::Resolve);
+    let resolve_time = start_time.elapsed();
+    resolution.lockfile.mark_dev(&engine.production_importers()?);
 
-    // Check frozen lockfile mode
-    if args.frozen_lockfile {
+    if summary_only {
+        output::phase("resolve done", &format!(
+            "{} pkgs in {}",
+            resolution.lockfile.packages.len(),
+            output::format_duration(start_time.elapsed().as_millis())
+        ));
+    }
+
+    // A frozen install must still pick only locked versions
+    if args.frozen_lockfile && !from_lockfile {
         if let Some(ref existing) = existing_lockfile {
-            let diff = existing.diff(&resolution.lockfile);
+            let mut diff = existing.diff(&resolution.lockfile);
+            diff.removed.clear();
             if !diff.is_empty() {
                 if let Some(pb) = progress {
                     pb.finish_and_clear();
@@ -105,67 +425,638 @@ pub async fn execute(args: InstallArgs, json_output: bool) -> VelocityResult<()>
                     "Lockfile is out of date. Run 'velocity install' without --frozen-lockfile to update."
                 ));
             }
-        } else {
+        }
+    }
+
+    // Direct dependencies named after Node.js core modules are a typo or an attack;
+    // transitive ones were chosen by their dependents' authors
+    let shadowing = engine
+        .security
+        .check_core_shadowing(importers.iter().map(|(name, _)| name.as_str()));
+    let shadowing = match shadowing {
+        Ok(names) => names,
+        Err(e) => {
             if let Some(pb) = progress {
                 pb.finish_and_clear();
             }
-            return Err(crate::core::VelocityError::other(
-                "No lockfile found. Run 'velocity install' without --frozen-lockfile to generate one."
-            ));
+            return Err(e);
+        }
+    };
+    for name in &shadowing {
+        let message = format!(
+            "'{}' shadows the Node.js core module of the same name. Check that this dependency is intended.",
+            name
+        );
+        match progress {
+            _ if json_output => output::stream_warning(&message),
+            Some(ref pb) => pb.suspend(|| output::warning(&message)),
+            None => output::warning(&message),
+        }
+    }
+
+    // Audit direct dependencies before anything touches disk
+    let mut audit_warnings = 0;
+    if engine.security.audit_on_install() {
+        let mut names: Vec<&String> = importers.iter().map(|(name, _)| name).collect();
+        names.sort();
+        names.dedup();
+
+        for name in names {
+            let analysis = SupplyChainGuard::analyze(name);
+            if analysis.should_warn() {
+                audit_warnings += 1;
+                let message = format!(
+                    "{} flagged as {:?} risk. Run 'velocity audit' for details.",
+                    name, analysis.risk_level
+                );
+                match progress {
+                    _ if json_output => output::stream_warning(&message),
+                    Some(ref pb) => pb.suspend(|| output::warning(&message)),
+                    None => output::warning(&message),
+                }
+            }
+        }
+    }
+
+    // Strict license policies reject the install before anything touches disk
+    let licenses = &engine.config.licenses;
+    if licenses.strict && licenses.is_set() {
+        let packages = resolution.lockfile.packages.iter().filter(|p| !(args.omits_dev() && p.dev));
+        let concurrency = engine.config.network.metadata_concurrency;
+        let collected = crate::security::licenses::collect(&engine.registry, &engine.cache, packages, concurrency).await;
+        let violations = LicensePolicy::new(licenses).violations(&collected);
+        if !violations.is_empty() {
+            if let Some(pb) = progress {
+                pb.finish_and_clear();
+            }
+            let details: String = violations.iter().map(|v| format!("\n  {}", v)).collect();
+            return Err(VelocityError::other(format!(
+                "Dependencies break the license policy (licenses.strict):{}\nAdd them to licenses.exceptions to allow them",
+                details
+            )));
         }
     }
 
-    // Install packages
-    let installer = engine.installer();
-    let install_result = installer.install(
-        &resolution,
-        args.force,
-        args.prefer_offline,
-    ).await?;
+    // Attestations are checked once per tarball, and every time for required scopes
+    if let Err(e) = check_provenance(&engine, &mut resolution.lockfile, existing_lockfile.as_ref()).await {
+        if let Some(pb) = progress {
+            pb.finish_and_clear();
+        }
+        return Err(e);
+    }
+
+    // Lockfile-only runs stop once the lockfile is written
+    if args.lockfile_only {
+        if let Some(pb) = progress {
+            pb.finish_and_clear();
+        }
+        let peer_issues = resolution.peer_issues;
+        let mut lockfile = resolution.lockfile;
+        if let Some(ref previous) = existing_lockfile {
+            lockfile.keep_script_approvals(previous);
+        }
+        let pruned = if args.no_prune { 0 } else { lockfile.prune(project_dir, &importers).total() };
+        lockfile.save(&engine.lockfile_path())?;
+
+        return Ok(InstallSummary {
+            installed: 0,
+            cached: 0,
+            audit_warnings,
+            scripts: Vec::new(),
+            skipped_scripts: Vec::new(),
+            pruned,
+            omitted: 0,
+            peer_issues,
+            engine_issues: Vec::new(),
+            lockfile_only: true,
+            up_to_date: false,
+            summary_only,
+            timing: show_timing.then(|| {
+                let concurrency = Some(engine.config.network.metadata_concurrency);
+                vec![PhaseTiming { phase: "resolve", duration: resolve_time, concurrency }]
+            }),
+            network: show_timing.then(|| engine.http.metrics().summary()),
+            duration: start_time.elapsed(),
+        });
+    }
+
+    // The complete lockfile is saved; only what production code reaches is installed
+    let mut locked = None;
+    let mut omitted = 0;
+    if args.omits_dev() {
+        let complete = resolution.lockfile.clone();
+        resolution.omit_dev();
+        omitted = complete.packages.len() - resolution.lockfile.packages.len();
+        locked = Some(complete);
+    }
+
+    if let Some(ref pb) = progress {
+        if args.vendored {
+            pb.set_message("Installing from vendor directory...");
+        } else {
+            pb.set_message("Downloading packages...");
+        }
+    }
+
+    // Install packages; the install state is only trusted again once this run completes
+    InstallState::clear(&modules_dir);
+    let phase_start = Instant::now();
+    let installer = engine.installer().with_progress(sink).with_cache_verification(!args.no_verify);
+    let install_result = if args.vendored {
+        installer.install_vendored(&resolution, project_dir.join(VENDOR_DIR)).await?
+    } else {
+        installer.install(
+            &resolution,
+            args.force,
+            args.prefer_offline,
+        ).await?
+    };
+
+    if summary_only {
+        output::phase("download", &format!(
+            "{} in {} ({} from cache)",
+            output::format_bytes(install_result.bytes_downloaded),
+            output::format_duration(phase_start.elapsed().as_millis()),
+            install_result.cached_count
+        ));
+    }
+
+    // Manifests are in the cache now, so engines.node can be checked before linking
+    let node = NodeVersion::detect(project_dir, &package_json);
+    let engine_issues = match node {
+        Some(ref node) => installer.check_engines(&resolution, &node.version),
+        None => {
+            tracing::debug!("No Node.js version found; skipping the engines check");
+            Vec::new()
+        }
+    };
+    if let (Some(node), false) = (&node, engine_issues.is_empty()) {
+        if engine.config.install.engine_strict {
+            if let Some(pb) = progress {
+                pb.finish_and_clear();
+            }
+            let lines: Vec<String> = engine_issues.iter().map(|issue| format!("  {}", issue)).collect();
+            return Err(crate::core::VelocityError::other(format!(
+                "{} package(s) do not support Node.js {} (from {}):\n{}",
+                engine_issues.len(),
+                node.version,
+                node.source.as_str(),
+                lines.join("\n")
+            )));
+        }
+    }
 
     if let Some(ref pb) = progress {
         pb.set_message("Linking packages...");
     }
 
     // Link packages to node_modules
-    installer.link(&resolution).await?;
+    let phase_start = Instant::now();
+    // Only packages the lockfile diff touches are relinked; --force relinks everything
+    match existing_lockfile {
+        Some(ref previous) if !args.force => installer.link_changes(&resolution, previous).await?,
+        _ => installer.link(&resolution).await?,
+    }
+    let link_time = phase_start.elapsed();
+
+    if summary_only {
+        output::phase("link", &output::format_duration(phase_start.elapsed().as_millis()));
+    }
+
+    // Save the lockfile before scripts run, so a failing script still leaves it
+    // describing the linked tree; vendored installs keep it exactly as vendored
+    let layout = installer.layout(&resolution)?;
+    let mut lockfile = locked.unwrap_or_else(|| resolution.lockfile.clone());
+    if let Some(ref previous) = existing_lockfile {
+        lockfile.keep_script_approvals(previous);
+    }
+    let pruned = if args.no_prune || args.vendored || args.frozen_lockfile {
+        0
+    } else {
+        lockfile.prune(project_dir, &importers).total()
+    };
+    if !args.frozen_lockfile {
+        lockfile.save(&engine.lockfile_path())?;
+    }
+
+    // Run install scripts if not ignored; the security policy and the approvals
+    // recorded in the lockfile decide per package
+    let mut scripts = Vec::new();
+    let mut skipped_scripts = Vec::new();
+    let mut approved_scripts = None;
+    let phase_start = Instant::now();
+    if !args.ignore_scripts {
+        if let Some(ref pb) = progress {
+            pb.set_message("Running install scripts...");
+        }
+        let approvals = ScriptApprovals::from_lockfile(existing_lockfile.as_ref()).grant(args.approve_scripts.clone());
+        let report = installer.run_scripts(&resolution, approvals).await?;
+        scripts = report.outcomes;
+        skipped_scripts = report.skipped;
+        approved_scripts = Some(report.approved);
+        for outcome in &scripts {
+            output::event("script_run", serde_json::json!({ "script": outcome.to_json() }));
+        }
+        for skipped in &skipped_scripts {
+            output::event("script_skipped", serde_json::json!({ "script": skipped.to_json() }));
+        }
+
+        if let Some(error) = scripts.iter().find_map(|o| o.error(engine.security.script_timeout())) {
+            if let Some(pb) = progress {
+                pb.finish_and_clear();
+            }
+            if !json_output {
+                report_scripts(&scripts);
+            }
+            return Err(error);
+        }
+    }
+    let scripts_time = phase_start.elapsed();
+
+    if summary_only {
+        if !args.ignore_scripts {
+            output::phase("scripts", &format!(
+                "{} run, {} skipped by policy in {}",
+                scripts.len(),
+                skipped_scripts.len(),
+                output::format_duration(phase_start.elapsed().as_millis())
+            ));
+        } else {
+            let mut skipped: Vec<&str> = resolution
+                .lockfile
+                .packages
+                .iter()
+                .filter(|p| p.has_scripts)
+                .map(|p| p.name.as_str())
+                .collect();
+            skipped.sort();
+            skipped.dedup();
+            let detail = match skipped.is_empty() {
+                true => "0".to_string(),
+                false => format!("{} ({})", skipped.len(), skipped.join(", ")),
+            };
+            output::phase("scripts skipped", &detail);
+        }
+    }
 
     if let Some(pb) = progress {
         pb.finish_and_clear();
     }
 
-    // Save lockfile
-    let mut lockfile = resolution.lockfile;
-    lockfile.save(&project_dir)?;
+    let peer_issues = resolution.peer_issues;
 
-    // Run install scripts if not ignored
-    if !args.ignore_scripts && !engine.config.security.allow_scripts {
-        // Scripts are disabled by default for security
-        if !json_output {
-            output::warning("Install scripts are disabled by default. Use --ignore-scripts=false to enable.");
+    // Record the approvals granted while running scripts
+    if let Some(mut approved) = approved_scripts {
+        // Dev packages a production install left out keep their approvals
+        for pkg in lockfile.packages.iter().filter(|p| p.dev) {
+            let recorded = existing_lockfile
+                .as_ref()
+                .and_then(|previous| previous.find_package(&pkg.name, &pkg.version))
+                .and_then(|old| old.approved_scripts.clone());
+            if let Some(hash) = recorded {
+                approved.entry((pkg.name.clone(), pkg.version.clone())).or_insert(hash);
+            }
+        }
+        lockfile.record_script_approvals(&approved);
+        if !args.frozen_lockfile {
+            lockfile.save(&engine.lockfile_path())?;
         }
     }
+    InstallState::new(&std::fs::read(engine.lockfile_path())?, &settings, layout).save(&modules_dir)?;
 
-    let duration = start_time.elapsed();
+    let timing = show_timing.then(|| {
+        let network = &engine.config.network;
+        let install = &engine.config.install;
+        vec![
+            PhaseTiming { phase: "resolve", duration: resolve_time, concurrency: Some(network.metadata_concurrency) },
+            PhaseTiming {
+                phase: "download",
+                duration: install_result.download_time,
+                concurrency: Some(network.download_concurrency),
+            },
+            PhaseTiming {
+                phase: "extract",
+                duration: install_result.extract_time,
+                concurrency: Some(install.extract_concurrency),
+            },
+            PhaseTiming { phase: "link", duration: link_time, concurrency: Some(install.link_concurrency) },
+            PhaseTiming { phase: "scripts", duration: scripts_time, concurrency: None },
+        ]
+    });
 
-    if json_output {
-        output::json(&serde_json::json!({
-            "success": true,
-            "installed": install_result.installed_count,
-            "cached": install_result.cached_count,
-            "duration_ms": duration.as_millis()
-        }))?;
+    Ok(InstallSummary {
+        installed: install_result.installed_count,
+        cached: install_result.cached_count,
+        audit_warnings,
+        scripts,
+        skipped_scripts,
+        pruned,
+        omitted,
+        peer_issues,
+        engine_issues,
+        lockfile_only: false,
+        up_to_date: false,
+        summary_only,
+        timing,
+        network: show_timing.then(|| engine.http.metrics().summary()),
+        duration: start_time.elapsed(),
+    })
+}
+
+/// Record the attested source in `lockfile` for packages new since `previous`
+///
+/// Attestations that do not match their tarball fail the install.
+async fn check_provenance(engine: &Engine, lockfile: &mut Lockfile, previous: Option<&Lockfile>) -> VelocityResult<()> {
+    if let Some(previous) = previous {
+        lockfile.keep_provenance(previous);
+    }
+
+    let security = &engine.security;
+    let pending = lockfile.packages.iter().filter(|p| {
+        let is_new = previous.is_none_or(|previous| previous.find_package(&p.name, &p.version).is_none());
+        p.provenance.is_none() && security.verify_provenance() && is_new
+    });
+    let checked = provenance::check(&engine.registry, pending, engine.config.network.metadata_concurrency).await;
+
+    for (name, version, outcome) in checked {
+        match outcome {
+            ProvenanceOutcome::Invalid(reason) => {
+                return Err(VelocityError::ProvenanceInvalid { package: format!("{}@{}", name, version), reason });
+            }
+            ProvenanceOutcome::Unverified(source) => {
+                if let Some(locked) = lockfile.packages.iter_mut().find(|p| p.name == name && p.version == version) {
+                    locked.provenance = Some(source);
+                }
+            }
+            ProvenanceOutcome::Unavailable(reason) => {
+                tracing::debug!("Could not check provenance of {}@{}: {}", name, version, reason);
+            }
+            ProvenanceOutcome::Unsigned => {}
+        }
+    }
+    Ok(())
+}
+
+/// Settings that shape node_modules; an install with different ones is never skipped
+fn install_settings(engine: &Engine, args: &InstallArgs) -> serde_json::Value {
+    serde_json::json!({
+        "omit": args.omit.iter().map(|omit| format!("{:?}", omit)).collect::<Vec<_>>(),
+        "omit_dev": args.omits_dev(),
+        "ignore_scripts": args.ignore_scripts,
+        "install": engine.config.install,
+        "security": engine.config.security,
+    })
+}
+
+/// Print the summary of an install run
+pub fn report(summary: &InstallSummary, json_output: bool) -> VelocityResult<()> {
+    if output::is_json_stream() {
+        for issue in &summary.peer_issues {
+            output::stream_warning(&issue.to_string());
+        }
+        for issue in &summary.engine_issues {
+            output::stream_warning(&issue.to_string());
+        }
+        output::event("install_complete", summary.to_json());
+    } else if json_output {
+        output::json(&summary.to_json())?;
+    } else if summary.up_to_date {
+        match summary.summary_only {
+            true => output::phase("done", "already up to date"),
+            false => output::success("Already up to date"),
+        }
+    } else if summary.lockfile_only {
+        output::success(&format!(
+            "Lockfile written in {}",
+            output::format_duration(summary.duration.as_millis())
+        ));
+        if summary.pruned > 0 {
+            output::info(&format!("Pruned {} unreachable lockfile entries", summary.pruned));
+        }
+        if !summary.peer_issues.is_empty() {
+            output::warning(&format!("Unmet peer dependencies ({}):", summary.peer_issues.len()));
+            report_peers(&summary.peer_issues);
+        }
+    } else if summary.summary_only {
+        output::phase("done", &format!(
+            "{} installed, {} from cache, {} lockfile entries pruned, {} audit warnings in {}",
+            summary.installed,
+            summary.cached,
+            summary.pruned,
+            summary.audit_warnings,
+            output::format_duration(summary.duration.as_millis())
+        ));
+
+        if !summary.skipped_scripts.is_empty() {
+            report_skipped_scripts(&summary.skipped_scripts);
+        }
+
+        if !summary.peer_issues.is_empty() {
+            output::phase("peers", &format!("{} unmet", summary.peer_issues.len()));
+            report_peers(&summary.peer_issues);
+        }
+
+        if !summary.engine_issues.is_empty() {
+            output::phase("engines", &format!("{} unsupported", summary.engine_issues.len()));
+            for issue in &summary.engine_issues {
+                println!("  {}", issue);
+            }
+        }
+    } else if summary.installed == 0 && summary.cached == 0 {
+        output::success("No dependencies to install");
     } else {
         output::success(&format!(
             "Installed {} packages in {}",
-            install_result.installed_count,
-            output::format_duration(duration.as_millis())
+            summary.installed,
+            output::format_duration(summary.duration.as_millis())
         ));
 
-        if install_result.cached_count > 0 {
-            output::info(&format!("{} packages restored from cache", install_result.cached_count));
+        if summary.cached > 0 {
+            output::info(&format!("{} packages restored from cache", summary.cached));
+        }
+
+        if summary.pruned > 0 {
+            output::info(&format!("Pruned {} unreachable lockfile entries", summary.pruned));
+        }
+
+        if summary.omitted > 0 {
+            output::info(&format!("Left out {} dev-only packages", summary.omitted));
+        }
+
+        if !summary.scripts.is_empty() {
+            report_scripts(&summary.scripts);
+        }
+
+        if !summary.skipped_scripts.is_empty() {
+            report_skipped_scripts(&summary.skipped_scripts);
         }
+
+        if !summary.peer_issues.is_empty() {
+            output::warning(&format!("Unmet peer dependencies ({}):", summary.peer_issues.len()));
+            report_peers(&summary.peer_issues);
+        }
+
+        if !summary.engine_issues.is_empty() {
+            output::warning(&format!("Unsupported Node.js version ({}):", summary.engine_issues.len()));
+            for issue in &summary.engine_issues {
+                println!("  {}", issue);
+            }
+            output::info("Set install.engine_strict = true in velocity.toml to fail instead");
+        }
+    }
+
+    if let (false, false, Some(timing)) = (json_output, output::is_quiet(), &summary.timing) {
+        report_timing(timing, summary.network.as_ref());
     }
 
     Ok(())
 }
+
+/// Print each phase's duration and concurrency limit, then the run's HTTP activity
+fn report_timing(timing: &[PhaseTiming], network: Option<&MetricsSummary>) {
+    output::info("Timing:");
+    for phase in timing {
+        let concurrency = phase
+            .concurrency
+            .map(|n| format!("{} at once", n))
+            .unwrap_or_default();
+        println!(
+            "  {:<9} {:>9}  {}",
+            phase.phase,
+            output::format_duration(phase.duration.as_millis()),
+            output::muted(concurrency)
+        );
+    }
+    if let Some(network) = network {
+        let mut details = vec![
+            format!("{} requests ({} over HTTP/2)", network.http_requests, network.http2_requests),
+            format!("{} downloaded", output::format_bytes(network.bytes_downloaded)),
+        ];
+        if network.cache_lookups > 0 {
+            details.push(format!("{:.0}% metadata from cache", network.cache_hit_rate));
+        }
+        println!("  {:<9} {}", "network", details.join(", "));
+    }
+}
+
+/// Print the packages whose install scripts the security policy skipped
+fn report_skipped_scripts(skipped: &[SkippedScript]) {
+    let (changed, skipped): (Vec<&SkippedScript>, Vec<&SkippedScript>) =
+        skipped.iter().partition(|s| s.reason == SkipReason::Changed);
+
+    if !changed.is_empty() {
+        let mut names: Vec<&str> = changed.iter().map(|s| s.package.as_str()).collect();
+        names.sort();
+        names.dedup();
+        output::warning(&format!(
+            "Install scripts changed since they were approved, so they did not run: {}",
+            names.join(", ")
+        ));
+        output::info(&format!(
+            "Review them, then approve with 'velocity install --approve-scripts {}'",
+            names.join(",")
+        ));
+    }
+    if skipped.is_empty() {
+        return;
+    }
+
+    let mut packages: Vec<(&str, &str, Vec<&str>)> = Vec::new();
+    for script in &skipped {
+        match packages.iter_mut().find(|(name, version, _)| *name == script.package && *version == script.version) {
+            Some((_, _, events)) => events.push(&script.event),
+            None => packages.push((&script.package, &script.version, vec![&script.event])),
+        }
+    }
+    packages.sort();
+
+    output::warning(&format!("Skipped install scripts for {} package(s):", packages.len()));
+    for (name, version, events) in &packages {
+        println!(
+            "  {} {}",
+            output::package_version(name, version),
+            output::muted(events.join(", "))
+        );
+    }
+
+    if skipped.iter().any(|s| s.reason == SkipReason::Disabled) {
+        output::info("Install scripts are disabled by default. Set security.allow_scripts = true in velocity.toml to enable.");
+    } else {
+        output::info("Add packages to security.trusted_packages in velocity.toml to run their scripts.");
+    }
+}
+
+/// Print each unmet peer dependency, with a hint when some are missing outright
+fn report_peers(issues: &[PeerIssue]) {
+    for issue in issues {
+        println!("  {}", issue);
+    }
+
+    if issues.iter().any(|i| i.is_missing()) {
+        output::info("Add missing peers to package.json, or set install.auto_install_peers = true");
+    }
+}
+
+/// Print the duration and exit status of each install script
+fn report_scripts(outcomes: &[ScriptOutcome]) {
+    output::info(&format!("Install scripts ({}):", outcomes.len()));
+
+    for outcome in outcomes {
+        let status = if outcome.success {
+            output::good("✓".to_string())
+        } else if outcome.timed_out {
+            output::bad("timed out".to_string())
+        } else {
+            output::bad(format!("exit {}", outcome.exit_code.unwrap_or(-1)))
+        };
+
+        println!(
+            "  {} {} {} {}",
+            status,
+            output::package_version(&outcome.package, &outcome.version),
+            output::muted(&outcome.event),
+            output::muted(output::format_duration(outcome.duration.as_millis()))
+        );
+
+        if !outcome.success {
+            for line in outcome.stderr.lines().rev().take(10).collect::<Vec<_>>().into_iter().rev() {
+                println!("      {}", output::muted(line));
+            }
+            if outcome.truncated {
+                println!("      {}", output::muted("(output truncated)"));
+            }
+        }
+    }
+}
+
+impl InstallSummary {
+    /// JSON representation used by `--json` output
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "success": true,
+            "installed": self.installed,
+            "cached": self.cached,
+            "audit_warnings": self.audit_warnings,
+            "pruned": self.pruned,
+            "omitted": self.omitted,
+            "lockfile_only": self.lockfile_only,
+            "up_to_date": self.up_to_date,
+            "peer_issues": self.peer_issues.iter().map(|i| i.to_json()).collect::<Vec<_>>(),
+            "engine_issues": self.engine_issues.iter().map(|i| i.to_json()).collect::<Vec<_>>(),
+            "scripts": self.scripts.iter().map(|s| s.to_json()).collect::<Vec<_>>(),
+            "skipped_scripts": self.skipped_scripts.iter().map(|s| s.to_json()).collect::<Vec<_>>(),
+            "duration_ms": self.duration.as_millis(),
+            "timing": self.timing.as_ref().map(|timing| timing.iter().map(|phase| serde_json::json!({
+                "phase": phase.phase,
+                "duration_ms": phase.duration.as_millis(),
+                "concurrency": phase.concurrency,
+            })).collect::<Vec<_>>()),
+            "network": self.network.as_ref().map(|network| serde_json::json!({
+                "http_requests": network.http_requests,
+                "http2_requests": network.http2_requests,
+                "bytes_downloaded": network.bytes_downloaded,
+                "metadata_cache_hit_rate": network.cache_hit_rate,
+            })),
+        })
+    }
+}
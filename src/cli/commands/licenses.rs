@@ -0,0 +1,128 @@
+//! velocity licenses - Report the licenses of locked dependencies
+
+use std::collections::BTreeMap;
+
+use clap::{Args, ValueEnum};
+
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{Engine, VelocityError, VelocityResult};
+use crate::security::licenses::{self, LicensePolicy, LicenseViolation, PackageLicense};
+
+#[derive(Args)]
+pub struct LicensesArgs {
+    /// Report layout
+    #[arg(long, value_enum, default_value_t = LicenseFormat::Table)]
+    pub format: LicenseFormat,
+
+    /// Leave out packages only devDependencies need
+    #[arg(long, visible_alias = "prod")]
+    pub production: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LicenseFormat {
+    /// One line per package
+    Table,
+    /// Package count per license
+    Summary,
+    /// SPDX 2.3 JSON document
+    Spdx,
+}
+
+pub async fn execute(args: LicensesArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+    let engine = Engine::new(&project_dir).await?;
+    engine.ensure_initialized()?;
+
+    let package_json = engine.package_json()?;
+    let lockfile = engine.lockfile()?.ok_or_else(|| {
+        VelocityError::other("No lockfile found. Run 'velocity install' first.")
+    })?;
+
+    let spinner = (!json_output && args.format != LicenseFormat::Spdx).then(|| output::spinner("Collecting licenses..."));
+    let packages = lockfile.packages.iter().filter(|pkg| !(args.production && pkg.dev));
+    let concurrency = engine.config.network.metadata_concurrency;
+    let collected = licenses::collect(&engine.registry, &engine.cache, packages, concurrency).await;
+    if let Some(spinner) = spinner {
+        spinner.finish_and_clear();
+    }
+
+    if args.format == LicenseFormat::Spdx {
+        let document = licenses::spdx_document(&package_json.name, &package_json.version, &collected);
+        output::json(&document)?;
+        return Ok(());
+    }
+
+    let policy = LicensePolicy::new(&engine.config.licenses);
+    let violations = policy.violations(&collected);
+    let violation_of = |package: &PackageLicense| -> Option<&LicenseViolation> {
+        violations.iter().find(|v| v.package == package.name && v.version == package.version)
+    };
+
+    // Package count per declared license, unlicensed packages last
+    let mut summary: BTreeMap<Option<&str>, usize> = BTreeMap::new();
+    for package in &collected {
+        *summary.entry(package.license.as_deref()).or_default() += 1;
+    }
+    let mut summary: Vec<(Option<&str>, usize)> = summary.into_iter().collect();
+    summary.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.is_none().cmp(&b.0.is_none())).then_with(|| a.0.cmp(&b.0)));
+
+    if json_output {
+        let packages: Vec<serde_json::Value> = collected
+            .iter()
+            .map(|package| serde_json::json!({
+                "name": package.name,
+                "version": package.version,
+                "license": package.license,
+                "dev": package.dev,
+                "violation": violation_of(package).map(|v| &v.reason),
+            }))
+            .collect();
+        let licenses: serde_json::Map<String, serde_json::Value> = summary
+            .iter()
+            .map(|(license, count)| (license.unwrap_or("UNKNOWN").to_string(), (*count).into()))
+            .collect();
+        output::json(&serde_json::json!({
+            "packages": match args.format {
+                LicenseFormat::Summary => serde_json::Value::Null,
+                _ => packages.into(),
+            },
+            "licenses": licenses,
+            "violations": violations,
+        }))?;
+        return Ok(());
+    }
+
+    match args.format {
+        LicenseFormat::Summary => {
+            for (license, count) in &summary {
+                let label = license.map_or_else(|| output::caution("(none)"), output::plain);
+                println!("  {:>5}  {}", count, label);
+            }
+        }
+        _ => {
+            let width = collected.iter().map(|p| p.name.len() + p.version.len() + 1).max().unwrap_or(0);
+            for package in &collected {
+                let id = format!("{}@{}", package.name, package.version);
+                let license = package.license.as_deref().map_or_else(|| output::caution("(none)"), output::plain);
+                match violation_of(package) {
+                    Some(violation) => println!("  {:<width$}  {}  {}", id, output::bad(license), output::bad(&violation.reason).dim()),
+                    None => println!("  {:<width$}  {}", id, license),
+                }
+            }
+        }
+    }
+
+    println!();
+    println!("{} packages, {} licenses", collected.len(), summary.len());
+    if !engine.config.licenses.is_set() {
+        output::info("No license policy set; add a [licenses] table to velocity.toml to check one");
+    } else if violations.is_empty() {
+        output::success("All packages comply with the license policy");
+    } else {
+        output::warning(&format!("{} package(s) break the license policy", violations.len()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,333 @@
+//! velocity list - Show the installed dependency tree
+
+use std::collections::HashSet;
+use std::path::Path;
+use clap::Args;
+
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{Engine, VelocityError, VelocityResult};
+use crate::installer::layout::PackageIndex;
+use crate::installer::linker::matches_patterns;
+use crate::installer::Linker;
+use crate::resolver::ResolvedPackage;
+
+#[derive(Args)]
+pub struct ListArgs {
+    /// Levels of transitive dependencies to show (0 = direct only)
+    #[arg(long, default_value_t = 0)]
+    pub depth: usize,
+
+    /// Show the whole tree
+    #[arg(long, conflicts_with = "depth")]
+    pub all: bool,
+
+    /// Only production dependencies
+    #[arg(long)]
+    pub prod: bool,
+
+    /// Only show paths to packages matching this glob (searches the whole tree)
+    #[arg(long, value_name = "GLOB")]
+    pub pattern: Option<String>,
+}
+
+/// A package in the printed tree
+#[derive(Debug, Clone)]
+struct TreeNode {
+    name: String,
+    /// Locked version, or the spec when nothing in the lockfile matches
+    version: String,
+    /// Declared as a dev dependency of the project
+    dev: bool,
+    /// Linked from the workspace instead of installed
+    workspace: bool,
+    /// Already expanded elsewhere in the tree
+    deduped: bool,
+    /// Locked but not present in node_modules
+    missing: bool,
+    children: Vec<TreeNode>,
+}
+
+pub async fn execute(args: ListArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+
+    let engine = Engine::new(&project_dir).await?;
+    engine.ensure_initialized()?;
+
+    let package_json = engine.package_json()?;
+    let lockfile = engine.lockfile()?.ok_or_else(|| {
+        VelocityError::other("No lockfile found. Run 'velocity install' first.")
+    })?;
+
+    let resolution = engine.resolver().resolve_from_lockfile(&lockfile)?;
+    let packages: Vec<&ResolvedPackage> = resolution.to_install.iter()
+        .chain(resolution.from_cache.iter())
+        .collect();
+    // Not DependencyGraph: it keys nodes by name alone, so a tree holding two
+    // versions of a package would lose one. PackageIndex resolves every edge to
+    // the version the installer links for it.
+    let index = PackageIndex::new(&packages);
+
+    let linker = Linker::new(project_dir.clone(), engine.cache.clone())
+        .with_install_config(engine.config.install.clone());
+    let installed: HashSet<(String, String)> = linker
+        .package_dirs(&packages)?
+        .into_iter()
+        .filter(|(_, dir)| dir.join("package.json").exists())
+        .map(|(key, _)| key)
+        .collect();
+
+    let mut roots: Vec<(String, String, bool)> = package_json.dependencies.iter()
+        .chain(package_json.optional_dependencies.iter())
+        .map(|(name, spec)| (name.clone(), spec.clone(), false))
+        .collect();
+    if !args.prod {
+        roots.extend(package_json.dev_dependencies.iter().map(|(name, spec)| (name.clone(), spec.clone(), true)));
+    }
+    roots.sort();
+    roots.dedup_by(|a, b| a.0 == b.0);
+
+    let max_depth = if args.all || args.pattern.is_some() { usize::MAX } else { args.depth };
+    let mut tree = build_tree(&index, &roots, &installed, max_depth);
+    if let Some(ref pattern) = args.pattern {
+        let patterns = [pattern.clone()];
+        tree = filter_tree(tree, &|name| matches_patterns(&patterns, name));
+    }
+
+    let extraneous = if args.pattern.is_none() {
+        let locked: HashSet<&str> = index.names().chain(roots.iter().map(|(name, _, _)| name.as_str())).collect();
+        extraneous_packages(&project_dir.join("node_modules"), &locked)
+    } else {
+        Vec::new()
+    };
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "name": package_json.name,
+            "version": package_json.version,
+            "dependencies": tree.iter().map(node_json).collect::<Vec<_>>(),
+            "extraneous": extraneous,
+        }))?;
+        return Ok(());
+    }
+
+    println!("{}", output::package_version(&package_json.name, &package_json.version));
+    if tree.is_empty() {
+        match args.pattern {
+            Some(ref pattern) => println!("└── (no packages match '{}')", pattern),
+            None => println!("└── (empty)"),
+        }
+    }
+    print_nodes(&tree, "");
+
+    for name in &extraneous {
+        output::warning(&format!("{} is in node_modules but not in the lockfile (extraneous)", name));
+    }
+    if tree.iter().any(has_missing) {
+        output::warning("Some locked packages are missing from node_modules. Run 'velocity install'.");
+    }
+
+    Ok(())
+}
+
+/// (name, version) of a locked package
+type PackageKey = (String, String);
+
+/// Packages expanded so far, packages present in node_modules, and the depth limit
+type TreeContext<'a> = (HashSet<PackageKey>, &'a HashSet<PackageKey>, usize);
+
+/// Build the tree below the project's direct dependencies, up to `max_depth` levels
+fn build_tree(
+    index: &PackageIndex<'_>,
+    roots: &[(String, String, bool)],
+    installed: &HashSet<(String, String)>,
+    max_depth: usize,
+) -> Vec<TreeNode> {
+    fn node(
+        index: &PackageIndex<'_>,
+        package: &ResolvedPackage,
+        dev: bool,
+        depth: usize,
+        ctx: &mut TreeContext<'_>,
+    ) -> TreeNode {
+        let key = (package.name.clone(), package.version.clone());
+        let missing = !ctx.1.contains(&key);
+        let deduped = !ctx.0.insert(key);
+
+        let children = if deduped || depth >= ctx.2 {
+            Vec::new()
+        } else {
+            index
+                .dependencies(package)
+                .into_iter()
+                .map(|dep| node(index, dep, dev, depth + 1, ctx))
+                .collect()
+        };
+
+        TreeNode {
+            name: package.name.clone(),
+            version: package.version.clone(),
+            dev,
+            workspace: false,
+            deduped: deduped && !index.dependencies(package).is_empty(),
+            missing,
+            children,
+        }
+    }
+
+    let mut ctx = (HashSet::new(), installed, max_depth);
+    roots
+        .iter()
+        .map(|(name, spec, dev)| {
+            if spec.starts_with("workspace:") {
+                return TreeNode {
+                    name: name.clone(),
+                    version: spec.clone(),
+                    dev: *dev,
+                    workspace: true,
+                    deduped: false,
+                    missing: false,
+                    children: Vec::new(),
+                };
+            }
+
+            match index.resolve(name, spec) {
+                Some(package) => node(index, package, *dev, 0, &mut ctx),
+                None => TreeNode {
+                    name: name.clone(),
+                    version: spec.clone(),
+                    dev: *dev,
+                    workspace: false,
+                    deduped: false,
+                    missing: true,
+                    children: Vec::new(),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Keep only nodes that match, or lead to a match
+fn filter_tree(nodes: Vec<TreeNode>, matches: &dyn Fn(&str) -> bool) -> Vec<TreeNode> {
+    nodes
+        .into_iter()
+        .filter_map(|mut node| {
+            node.children = filter_tree(node.children, matches);
+            (matches(&node.name) || !node.children.is_empty()).then_some(node)
+        })
+        .collect()
+}
+
+/// Top-level node_modules entries that are not locked
+///
+/// Hoisted layouts put transitive packages at the top level too, so anything
+/// the lockfile knows about counts as expected.
+fn extraneous_packages(node_modules: &Path, declared: &HashSet<&str>) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(node_modules) else {
+        return Vec::new();
+    };
+
+    let mut names = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        if name.starts_with('@') {
+            if let Ok(scoped) = std::fs::read_dir(entry.path()) {
+                names.extend(scoped.flatten().map(|e| format!("{}/{}", name, e.file_name().to_string_lossy())));
+            }
+        } else {
+            names.push(name);
+        }
+    }
+
+    let mut extraneous: Vec<String> = names
+        .into_iter()
+        .filter(|name| !declared.contains(name.as_str()))
+        .collect();
+    extraneous.sort();
+    extraneous
+}
+
+fn has_missing(node: &TreeNode) -> bool {
+    node.missing || node.children.iter().any(has_missing)
+}
+
+fn print_nodes(nodes: &[TreeNode], prefix: &str) {
+    for (i, node) in nodes.iter().enumerate() {
+        let last = i + 1 == nodes.len();
+        let branch = if last { "└── " } else { "├── " };
+
+        let mut label = output::package_version(&node.name, &node.version);
+        if node.dev {
+            label = format!("{} {}", label, output::muted("(dev)"));
+        }
+        if node.workspace {
+            label = format!("{} {}", label, output::highlight("(workspace)"));
+        }
+        if node.deduped {
+            label = format!("{} {}", label, output::muted("deduped"));
+        }
+        if node.missing {
+            label = format!("{} {}", label, output::bad("MISSING").bold());
+        }
+
+        println!("{}{}{}", prefix, branch, label);
+
+        let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        print_nodes(&node.children, &child_prefix);
+    }
+}
+
+fn node_json(node: &TreeNode) -> serde_json::Value {
+    serde_json::json!({
+        "name": node.name,
+        "version": node.version,
+        "dev": node.dev,
+        "workspace": node.workspace,
+        "deduped": node.deduped,
+        "missing": node.missing,
+        "dependencies": node.children.iter().map(node_json).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::test_support::package;
+
+    #[test]
+    fn test_tree_depth_dedupe_and_pattern() {
+        let react = package("react", "18.2.0", &[("loose-envify", "^1.1.0")]);
+        let dom = package("react-dom", "18.2.0", &[("react", "^18.2.0"), ("scheduler", "^0.23.0")]);
+        let envify = package("loose-envify", "1.4.0", &[]);
+        let scheduler = package("scheduler", "0.23.0", &[("loose-envify", "^1.1.0")]);
+        let index = PackageIndex::new(&[&react, &dom, &envify, &scheduler]);
+
+        let installed: HashSet<(String, String)> = [&react, &dom, &scheduler]
+            .iter()
+            .map(|p| (p.name.clone(), p.version.clone()))
+            .collect();
+        let roots = vec![
+            ("react".to_string(), "^18.2.0".to_string(), false),
+            ("react-dom".to_string(), "^18.2.0".to_string(), false),
+        ];
+
+        let direct = build_tree(&index, &roots, &installed, 0);
+        assert_eq!(direct.len(), 2);
+        assert!(direct.iter().all(|n| n.children.is_empty()));
+
+        let full = build_tree(&index, &roots, &installed, usize::MAX);
+        assert_eq!(full[0].children[0].name, "loose-envify");
+        assert!(full[0].children[0].missing);
+        // react under react-dom was already expanded at the top level
+        assert!(full[1].children[0].deduped);
+
+        let filtered = filter_tree(full, &|name| name == "scheduler");
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].name, "react-dom");
+        assert_eq!(filtered[0].children.len(), 1);
+    }
+}
@@ -0,0 +1,250 @@
+//! velocity lockfile - Inspect and validate velocity.lock
+
+use std::path::PathBuf;
+use clap::{Args, Subcommand};
+
+use crate::cli::output;
+use crate::core::project;
+use crate::core::lockfile::{LockfileIssue, LOCKFILE_NAME, LOCKFILE_VERSION};
+use crate::core::{Engine, Lockfile, VelocityError, VelocityResult};
+use crate::resolver::{ForceResolve, ResolveOptions};
+
+#[derive(Args)]
+pub struct LockfileArgs {
+    #[command(subcommand)]
+    pub command: LockfileCommands,
+}
+
+#[derive(Subcommand)]
+pub enum LockfileCommands {
+    /// Print the JSON Schema describing velocity.lock
+    Schema {
+        /// Write the schema to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Check structural validity and referential integrity of velocity.lock
+    Validate {
+        /// Lockfile to validate (default: the project's configured lockfile)
+        path: Option<PathBuf>,
+    },
+
+    /// Rewrite velocity.lock in the current format, without resolving again
+    Upgrade {
+        /// Lockfile to upgrade (default: the project's configured lockfile)
+        path: Option<PathBuf>,
+    },
+
+    /// Resolve git merge conflicts in velocity.lock, re-resolving only disputed packages
+    Merge,
+}
+
+pub async fn execute(args: LockfileArgs, json_output: bool) -> VelocityResult<()> {
+    match args.command {
+        LockfileCommands::Schema { output } => schema(output, json_output).await,
+        LockfileCommands::Validate { path } => validate(path, json_output).await,
+        LockfileCommands::Upgrade { path } => upgrade(path, json_output).await,
+        LockfileCommands::Merge => merge(json_output).await,
+    }
+}
+
+/// The lockfile a subcommand works on: `path`, a directory holding one, or the project's
+fn lockfile_path(path: Option<PathBuf>) -> VelocityResult<PathBuf> {
+    let path = match path {
+        Some(p) if p.is_dir() => p.join(LOCKFILE_NAME),
+        Some(p) => p,
+        None => {
+            let project_dir = project::current_project_root()?;
+            crate::core::Config::load(&project_dir)?.lockfile_path(&project_dir)
+        }
+    };
+
+    if !path.exists() {
+        return Err(VelocityError::other(format!("No lockfile found at {}", path.display())));
+    }
+    Ok(path)
+}
+
+async fn schema(output_path: Option<PathBuf>, json_output: bool) -> VelocityResult<()> {
+    let schema = Lockfile::json_schema();
+
+    match output_path {
+        Some(path) => {
+            std::fs::write(&path, serde_json::to_string_pretty(&schema)?)?;
+            if json_output {
+                output::json(&serde_json::json!({
+                    "success": true,
+                    "path": path
+                }))?;
+            } else {
+                output::success(&format!("Wrote lockfile schema to {}", path.display()));
+            }
+        }
+        None => output::json(&schema)?,
+    }
+
+    Ok(())
+}
+
+async fn validate(path: Option<PathBuf>, json_output: bool) -> VelocityResult<()> {
+    let path = lockfile_path(path)?;
+
+    let content = std::fs::read_to_string(&path)?;
+    let issues = match toml::from_str::<Lockfile>(&content) {
+        Ok(lockfile) => lockfile.validate(),
+        Err(e) => vec![LockfileIssue {
+            package: None,
+            message: format!("invalid structure: {}", e.message()),
+        }],
+    };
+
+    let valid = issues.is_empty();
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "valid": valid,
+            "path": path,
+            "issues": issues
+        }))?;
+    } else if valid {
+        output::success(&format!("{} is valid", path.display()));
+    } else {
+        output::error(&format!("{} has {} issue(s):", path.display(), issues.len()));
+        for issue in &issues {
+            println!("  {}", output::bad(issue));
+        }
+    }
+
+    if !valid {
+        return Err(VelocityError::InvalidLockfile);
+    }
+
+    Ok(())
+}
+
+async fn upgrade(path: Option<PathBuf>, json_output: bool) -> VelocityResult<()> {
+    let path = lockfile_path(path)?;
+    let mut lockfile = Lockfile::load(&path)?.ok_or(VelocityError::InvalidLockfile)?;
+    let from = lockfile.version;
+    if from > LOCKFILE_VERSION {
+        return Err(VelocityError::other(format!(
+            "{} is lockfile version {}, newer than this release supports ({}). Upgrade velocity instead.",
+            path.display(), from, LOCKFILE_VERSION
+        )));
+    }
+
+    let upgraded = lockfile.upgrade();
+    if upgraded {
+        lockfile.save(&path)?;
+    }
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "success": true,
+            "path": path,
+            "from": from,
+            "version": lockfile.version,
+            "upgraded": upgraded
+        }))?;
+    } else if upgraded {
+        output::success(&format!("Upgraded {} from version {} to {}", path.display(), from, lockfile.version));
+    } else {
+        output::info(&format!("{} is already version {}", path.display(), lockfile.version));
+    }
+
+    Ok(())
+}
+
+/// What merging a conflicted lockfile did
+#[derive(Debug)]
+pub struct LockfileMerge {
+    /// Packages the two sides disagreed on, which were resolved again
+    pub disputed: Vec<String>,
+
+    /// Packages in the merged lockfile
+    pub packages: usize,
+}
+
+/// Merge the project's lockfile in place if git left conflict markers in it
+///
+/// Returns `None` when there is nothing to merge. Packages only one side changed
+/// keep that side's entry; disputed packages are resolved again, and so is
+/// whatever the union of both sides no longer covers in package.json.
+pub async fn merge_conflicts(engine: &Engine) -> VelocityResult<Option<LockfileMerge>> {
+    let path = engine.lockfile_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(&path)?;
+    if !Lockfile::has_conflict_markers(&content) {
+        return Ok(None);
+    }
+
+    let sides = Lockfile::parse_conflicted(&content)?;
+    let (mut merged, disputed) = Lockfile::merge_conflict(&sides);
+    let importers = engine.importers()?;
+    merged.prune(&engine.project_dir, &importers);
+
+    if !disputed.is_empty() || merged.stale_reason(&engine.project_dir, &importers).is_some() {
+        let options = ResolveOptions {
+            force: ForceResolve::Packages(disputed.iter().cloned().collect()),
+            auto_install_peers: engine.config.install.auto_install_peers,
+            ..Default::default()
+        }
+        .with_lockfile(Some(&merged));
+        let resolver = engine.resolver().with_options(options);
+        let deps = engine.package_json()?.all_dependencies();
+        let resolution = if engine.installs_workspace() {
+            resolver.resolve_workspace(&deps).await?
+        } else {
+            resolver.resolve(&deps).await?
+        };
+
+        let mut resolved = resolution.lockfile;
+        resolved.keep_script_approvals(&merged);
+        resolved.keep_provenance(&merged);
+        merged = resolved;
+    }
+    merged.mark_dev(&engine.production_importers()?);
+    merged.save(&path)?;
+
+    Ok(Some(LockfileMerge {
+        disputed: disputed.into_iter().collect(),
+        packages: merged.packages.len(),
+    }))
+}
+
+async fn merge(json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+    let engine = Engine::new(&project_dir).await?;
+    engine.ensure_initialized()?;
+    let path = engine.lockfile_path();
+    if !path.exists() {
+        return Err(VelocityError::other(format!("No lockfile found at {}", path.display())));
+    }
+
+    let merge = merge_conflicts(&engine).await?;
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "success": true,
+            "path": path,
+            "merged": merge.is_some(),
+            "disputed": merge.as_ref().map(|m| m.disputed.clone()).unwrap_or_default(),
+            "packages": merge.as_ref().map(|m| m.packages)
+        }))?;
+    } else {
+        match merge {
+            None => output::info(&format!("{} has no merge conflicts", path.display())),
+            Some(merge) => {
+                output::success(&format!("Merged {} ({} packages)", path.display(), merge.packages));
+                if !merge.disputed.is_empty() {
+                    output::info(&format!("Re-resolved {}", merge.disputed.join(", ")));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
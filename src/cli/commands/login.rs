@@ -0,0 +1,280 @@
+//! velocity login - Authenticate with a registry and store the token
+
+use std::io::{IsTerminal, Read};
+use std::time::{Duration, Instant};
+
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+
+use crate::cli::output;
+use crate::core::credentials::{self, Credentials};
+use crate::core::project;
+use crate::core::{Config, VelocityError, VelocityResult};
+use crate::registry::http::{self, InsecureOrigins};
+
+/// How long to wait for the browser half of a web login
+const WEB_LOGIN_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Args)]
+pub struct LoginArgs {
+    /// Registry to log in to (default: the scope's registry, else registry.url)
+    #[arg(long)]
+    pub registry: Option<String>,
+
+    /// Use the registry for this scope from now on (e.g. @myorg)
+    #[arg(long)]
+    pub scope: Option<String>,
+
+    /// How to authenticate
+    #[arg(long, value_enum, default_value_t = AuthType::Web)]
+    pub auth_type: AuthType,
+
+    /// Keep the token in the OS keychain instead of the credentials file
+    #[arg(long)]
+    pub keychain: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AuthType {
+    /// Open a login page in the browser (npm's web flow)
+    Web,
+    /// Username and password
+    Legacy,
+    /// Paste an existing access token (read from stdin when piped)
+    Token,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WebLoginStart {
+    login_url: String,
+    done_url: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: String,
+}
+
+pub async fn execute(args: LoginArgs, json_output: bool) -> VelocityResult<()> {
+    let config = load_config()?;
+    let scope = args.scope.as_deref().map(normalize_scope);
+    let registry = credentials::normalize(&target_registry(&config, args.registry.as_deref(), scope.as_deref()));
+    let client = registry_client(&config, &registry)?;
+
+    let token = match args.auth_type {
+        AuthType::Web => match web_login(&client, &registry, json_output).await? {
+            Some(token) => token,
+            None => {
+                if !json_output {
+                    output::warning("The registry does not support web login; falling back to username and password");
+                }
+                legacy_login(&client, &registry).await?
+            }
+        },
+        AuthType::Legacy => legacy_login(&client, &registry).await?,
+        AuthType::Token => read_token()?,
+    };
+
+    let username = whoami(&client, &registry, &token).await?;
+
+    let mut credentials = Credentials::load()?;
+    credentials.set(&registry, &token, username.clone(), args.keychain)?;
+    if let Some(ref scope) = scope {
+        credentials.scopes.insert(scope.clone(), registry.clone());
+    }
+    credentials.save()?;
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "success": true,
+            "registry": registry,
+            "scope": scope,
+            "username": username,
+            "keychain": args.keychain,
+        }))?;
+    } else {
+        match username {
+            Some(ref user) => output::success(&format!("Logged in to {} as {}", registry, user)),
+            None => output::success(&format!("Logged in to {}", registry)),
+        }
+        if let Some(ref scope) = scope {
+            output::info(&format!("Packages in {} will be installed from {}", scope, registry));
+        }
+    }
+
+    Ok(())
+}
+
+/// Config of the current project, or the defaults outside one
+pub(crate) fn load_config() -> VelocityResult<Config> {
+    let project_dir = project::current_project_root().unwrap_or_else(|_| std::path::PathBuf::from("."));
+    Config::load(&project_dir)
+}
+
+/// Registry named on the command line, else the scope's, else the default
+pub(crate) fn target_registry(config: &Config, registry: Option<&str>, scope: Option<&str>) -> String {
+    registry
+        .map(str::to_string)
+        .or_else(|| scope.and_then(|scope| config.registry.scopes.get(scope).cloned()))
+        .unwrap_or_else(|| config.registry.url.clone())
+}
+
+pub(crate) fn normalize_scope(scope: &str) -> String {
+    format!("@{}", scope.trim_start_matches('@'))
+}
+
+/// HTTP client for talking to a registry, refusing plain HTTP unless allowed
+pub(crate) fn registry_client(config: &Config, registry: &str) -> VelocityResult<reqwest::Client> {
+    InsecureOrigins::from_config(&config.network.allow_insecure_registries).check(registry)?;
+    http::client_builder_for(&config.network, Duration::from_secs(config.network.timeout), registry)?
+        .build()
+        .map_err(|e| VelocityError::Network(e.to_string()))
+}
+
+/// npm's web login: the user approves in a browser while we poll for the token.
+/// `None` when the registry does not offer it.
+async fn web_login(client: &reqwest::Client, registry: &str, json_output: bool) -> VelocityResult<Option<String>> {
+    let hostname = std::env::var("HOSTNAME").unwrap_or_else(|_| "velocity".to_string());
+    let response = client
+        .post(format!("{}/-/v1/login", registry))
+        .json(&serde_json::json!({ "hostname": hostname }))
+        .send()
+        .await
+        .map_err(http::send_error)?;
+
+    let status = response.status();
+    if matches!(status.as_u16(), 400 | 404 | 405 | 501) {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(VelocityError::registry(format!("Web login to {} failed: HTTP {}", registry, status)));
+    }
+    let start: WebLoginStart = response.json().await?;
+
+    if json_output {
+        eprintln!("{}", serde_json::json!({ "loginUrl": start.login_url }));
+    } else {
+        output::info(&format!("Open this URL to log in:\n  {}", start.login_url));
+    }
+
+    let spinner = (!json_output).then(|| output::spinner("Waiting for the login to complete..."));
+    let started = Instant::now();
+    let token = loop {
+        let response = client.get(&start.done_url).send().await.map_err(http::send_error)?;
+        match response.status().as_u16() {
+            200 => break response.json::<TokenResponse>().await?.token,
+            202 => {
+                if started.elapsed() > WEB_LOGIN_TIMEOUT {
+                    return Err(VelocityError::Timeout);
+                }
+                let wait = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(1);
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+            }
+            status => {
+                return Err(VelocityError::registry(format!("Web login to {} failed: HTTP {}", registry, status)));
+            }
+        }
+    };
+
+    if let Some(spinner) = spinner {
+        spinner.finish_and_clear();
+    }
+    Ok(Some(token))
+}
+
+/// CouchDB-style login with a username and password
+async fn legacy_login(client: &reqwest::Client, registry: &str) -> VelocityResult<String> {
+    if !std::io::stdin().is_terminal() {
+        return Err(VelocityError::config(
+            "Username and password login needs a terminal; use --auth-type token and pipe the token instead",
+        ));
+    }
+    let username: String = dialoguer::Input::new().with_prompt("Username").interact_text()?;
+    let password = dialoguer::Password::new().with_prompt("Password").interact()?;
+
+    let response = client
+        .put(format!("{}/-/user/org.couchdb.user:{}", registry, username))
+        .json(&serde_json::json!({
+            "_id": format!("org.couchdb.user:{}", username),
+            "name": username,
+            "password": password,
+            "type": "user",
+            "roles": [],
+            "date": chrono::Utc::now().to_rfc3339(),
+        }))
+        .send()
+        .await
+        .map_err(http::send_error)?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        return Err(VelocityError::registry(format!("{} rejected the username or password", registry)));
+    }
+    if !status.is_success() {
+        return Err(VelocityError::registry(format!("Login to {} failed: HTTP {}", registry, status)));
+    }
+    Ok(response.json::<TokenResponse>().await?.token)
+}
+
+/// A token typed at a hidden prompt, or piped on stdin
+fn read_token() -> VelocityResult<String> {
+    let token = if std::io::stdin().is_terminal() {
+        dialoguer::Password::new().with_prompt("Access token").interact()?
+    } else {
+        let mut token = String::new();
+        std::io::stdin().read_to_string(&mut token)?;
+        token
+    };
+
+    let token = token.trim().to_string();
+    if token.is_empty() {
+        return Err(VelocityError::config("No token given"));
+    }
+    Ok(token)
+}
+
+/// Check the token against the registry and return the user it belongs to.
+/// Registries without a whoami endpoint accept the token unchecked.
+async fn whoami(client: &reqwest::Client, registry: &str, token: &str) -> VelocityResult<Option<String>> {
+    #[derive(Deserialize)]
+    struct WhoAmI {
+        username: Option<String>,
+    }
+
+    let response = client
+        .get(format!("{}/-/whoami", registry))
+        .bearer_auth(token)
+        .send()
+        .await
+        .map_err(http::send_error)?;
+
+    match response.status() {
+        status if status.is_success() => Ok(response.json::<WhoAmI>().await.ok().and_then(|who| who.username)),
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+            Err(VelocityError::registry(format!("{} rejected the token", registry)))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_registry() {
+        let mut config = Config::default();
+        config.registry.scopes.insert("@corp".to_string(), "https://npm.corp.example".to_string());
+
+        assert_eq!(target_registry(&config, None, Some("@corp")), "https://npm.corp.example");
+        assert_eq!(target_registry(&config, Some("https://other.example"), Some("@corp")), "https://other.example");
+        assert_eq!(target_registry(&config, None, None), config.registry.url);
+        assert_eq!(normalize_scope("corp"), "@corp");
+    }
+}
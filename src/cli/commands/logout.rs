@@ -0,0 +1,66 @@
+//! velocity logout - Forget a registry's stored token
+
+use clap::Args;
+
+use super::login::{load_config, normalize_scope, registry_client, target_registry};
+use crate::cli::output;
+use crate::core::credentials::{self, Credentials};
+use crate::core::VelocityResult;
+use crate::registry::http;
+
+#[derive(Args)]
+pub struct LogoutArgs {
+    /// Registry to log out of (default: the scope's registry, else registry.url)
+    #[arg(long)]
+    pub registry: Option<String>,
+
+    /// Log out of the registry used for this scope
+    #[arg(long)]
+    pub scope: Option<String>,
+}
+
+pub async fn execute(args: LogoutArgs, json_output: bool) -> VelocityResult<()> {
+    let config = load_config()?;
+    let scope = args.scope.as_deref().map(normalize_scope);
+    let registry = credentials::normalize(&target_registry(&config, args.registry.as_deref(), scope.as_deref()));
+
+    let mut credentials = Credentials::load()?;
+    let token = credentials.remove(&registry)?;
+    let logged_in = token.is_some();
+    credentials.save()?;
+
+    // Revoke the token too; a stale local copy is already gone either way
+    let mut revoked = false;
+    if let Some(token) = token {
+        let client = registry_client(&config, &registry)?;
+        let response = client
+            .delete(format!("{}/-/user/token/{}", registry, token))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .map_err(http::send_error);
+        match response {
+            Ok(response) if response.status().is_success() => revoked = true,
+            Ok(response) => tracing::debug!("Token revocation returned HTTP {}", response.status()),
+            Err(e) => tracing::debug!("Token revocation failed: {}", e),
+        }
+    }
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "success": true,
+            "registry": registry,
+            "loggedOut": logged_in,
+            "revoked": revoked,
+        }))?;
+    } else if !logged_in {
+        output::info(&format!("Not logged in to {}", registry));
+    } else {
+        output::success(&format!("Logged out of {}", registry));
+        if !revoked {
+            output::warning("The registry did not confirm the token was revoked; revoke it there if it may be exposed");
+        }
+    }
+
+    Ok(())
+}
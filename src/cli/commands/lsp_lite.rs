@@ -0,0 +1,325 @@
+//! velocity lsp-lite - Answer package.json queries from editor plugins
+//!
+//! A long-running JSON-RPC 2.0 server on stdin/stdout. Messages use the
+//! Language Server Protocol framing (`Content-Length` headers); plugins that
+//! prefer one JSON object per line may send that instead and get line-delimited
+//! replies. Requests are handled concurrently and registry metadata is kept in
+//! memory for a few minutes, so suggestions stay cheap while the user types.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clap::Args;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::core::project;
+use crate::core::{Config, Engine, Lockfile, VelocityResult};
+use crate::registry::{PackageMetadata, RegistryClient};
+use crate::resolver::version::VersionConstraint;
+
+/// How long fetched metadata answers queries before it is fetched again
+const METADATA_TTL: Duration = Duration::from_secs(300);
+
+/// Most versions returned by a suggestion query
+const MAX_SUGGESTIONS: usize = 50;
+
+#[derive(Args)]
+pub struct LspLiteArgs {}
+
+/// How a message was framed; replies use the same framing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    Headers,
+    Line,
+}
+
+/// A JSON-RPC failure: code and message
+type RpcError = (i64, String);
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REGISTRY_ERROR: i64 = -32000;
+
+struct Server {
+    registry: Arc<RegistryClient>,
+    /// Project used when a query names no document
+    default_root: PathBuf,
+    metadata: Mutex<HashMap<String, (Instant, Arc<PackageMetadata>)>>,
+}
+
+pub async fn execute(_args: LspLiteArgs, _json_output: bool) -> VelocityResult<()> {
+    let default_root = project::current_project_root().unwrap_or_else(|_| PathBuf::from("."));
+    let engine = Engine::new(&default_root).await?;
+
+    let server = Arc::new(Server {
+        registry: engine.registry.clone(),
+        default_root,
+        metadata: Mutex::new(HashMap::new()),
+    });
+
+    // A single writer keeps concurrent replies from interleaving
+    let (tx, mut rx) = mpsc::unbounded_channel::<(Framing, Value)>();
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some((framing, message)) = rx.recv().await {
+            if stdout.write_all(&encode(framing, &message)).await.is_err() {
+                break;
+            }
+            stdout.flush().await.ok();
+        }
+    });
+
+    let mut stdin = BufReader::new(tokio::io::stdin());
+    while let Some((framing, body)) = read_message(&mut stdin).await? {
+        let message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                let reply = error_reply(Value::Null, (PARSE_ERROR, e.to_string()));
+                tx.send((framing, reply)).ok();
+                continue;
+            }
+        };
+
+        let method = message.get("method").and_then(Value::as_str).unwrap_or_default().to_string();
+        if method == "exit" {
+            break;
+        }
+
+        // Notifications get no reply
+        let Some(id) = message.get("id").cloned() else {
+            continue;
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let server = server.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let reply = match server.handle(&method, &params).await {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err(error) => error_reply(id, error),
+            };
+            tx.send((framing, reply)).ok();
+        });
+    }
+
+    drop(tx);
+    writer.await.ok();
+    Ok(())
+}
+
+impl Server {
+    async fn handle(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => Ok(json!({
+                "serverInfo": { "name": "velocity", "version": env!("CARGO_PKG_VERSION") },
+                "methods": ["velocity/versions", "velocity/distTags", "velocity/resolve", "velocity/installed"],
+            })),
+            "shutdown" => Ok(Value::Null),
+            "velocity/versions" => {
+                let metadata = self.metadata(param(params, "name")?).await?;
+                let prefix = params.get("prefix").and_then(Value::as_str).unwrap_or_default();
+                Ok(json!(version_suggestions(&metadata, prefix)))
+            }
+            "velocity/distTags" => {
+                let metadata = self.metadata(param(params, "name")?).await?;
+                Ok(json!(metadata.dist_tags))
+            }
+            "velocity/resolve" => {
+                let spec = param(params, "spec")?;
+                let metadata = self.metadata(param(params, "name")?).await?;
+                let version = resolve_spec(&metadata, spec);
+                Ok(json!({ "resolves": version.is_some(), "version": version }))
+            }
+            "velocity/installed" => {
+                let name = param(params, "name")?;
+                let root = match params.get("path").and_then(Value::as_str) {
+                    Some(path) => project::find_project_root(Path::new(path)),
+                    None => self.default_root.clone(),
+                };
+                Ok(json!({ "versions": installed_versions(&root, name) }))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        }
+    }
+
+    /// Registry metadata, served from memory while fresh
+    async fn metadata(&self, name: &str) -> Result<Arc<PackageMetadata>, RpcError> {
+        if let Some((fetched, metadata)) = self.metadata.lock().await.get(name) {
+            if fetched.elapsed() < METADATA_TTL {
+                return Ok(metadata.clone());
+            }
+        }
+
+        let metadata = self
+            .registry
+            .get_package_metadata(name)
+            .await
+            .map(Arc::new)
+            .map_err(|e| (REGISTRY_ERROR, e.to_string()))?;
+
+        self.metadata.lock().await.insert(name.to_string(), (Instant::now(), metadata.clone()));
+        Ok(metadata)
+    }
+}
+
+/// A required string parameter
+fn param<'a>(params: &'a Value, key: &str) -> Result<&'a str, RpcError> {
+    params
+        .get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| (INVALID_PARAMS, format!("Missing string parameter '{}'", key)))
+}
+
+fn error_reply(id: Value, (code, message): RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Versions starting with `prefix` (ignoring a leading range operator), newest first
+fn version_suggestions(metadata: &PackageMetadata, prefix: &str) -> Vec<Value> {
+    let prefix = prefix.trim_start_matches(['^', '~', '=', '>', '<', 'v']);
+
+    let mut versions: Vec<semver::Version> = metadata
+        .versions
+        .keys()
+        .filter(|v| v.starts_with(prefix))
+        .filter_map(|v| semver::Version::parse(v).ok())
+        .collect();
+    versions.sort_by(|a, b| b.cmp(a));
+    versions.truncate(MAX_SUGGESTIONS);
+
+    versions
+        .iter()
+        .map(|version| {
+            let version = version.to_string();
+            let mut tags: Vec<&String> = metadata
+                .dist_tags
+                .iter()
+                .filter(|(_, tagged)| **tagged == version)
+                .map(|(tag, _)| tag)
+                .collect();
+            tags.sort();
+            json!({ "version": version, "tags": tags, "time": metadata.time.get(&version) })
+        })
+        .collect()
+}
+
+/// The version a spec installs: a dist-tag's target or the newest matching version
+fn resolve_spec(metadata: &PackageMetadata, spec: &str) -> Option<String> {
+    let spec = spec.trim();
+    if let Some(version) = metadata.dist_tags.get(spec) {
+        return Some(version.clone());
+    }
+    if spec.is_empty() || spec == "*" {
+        return metadata.dist_tags.get("latest").cloned();
+    }
+
+    let constraint = VersionConstraint::parse(spec).ok()?;
+    metadata
+        .versions
+        .keys()
+        .filter_map(|v| semver::Version::parse(v).ok())
+        .filter(|v| v.pre.is_empty() && constraint.matches(v))
+        .max()
+        .map(|v| v.to_string())
+}
+
+/// Locked versions of a package in the project's lockfile
+fn installed_versions(root: &Path, name: &str) -> Vec<String> {
+    let lockfile_path = Config::load(root).unwrap_or_default().lockfile_path(root);
+    let Ok(Some(lockfile)) = Lockfile::load(&lockfile_path) else {
+        return Vec::new();
+    };
+    lockfile
+        .find_package_versions(name)
+        .into_iter()
+        .map(|package| package.version.clone())
+        .collect()
+}
+
+/// Read one message; `None` at end of input
+async fn read_message<R>(reader: &mut R) -> VelocityResult<Option<(Framing, Vec<u8>)>>
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+{
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if !trimmed.to_ascii_lowercase().starts_with("content-length:") {
+            return Ok(Some((Framing::Line, trimmed.as_bytes().to_vec())));
+        }
+
+        let length: usize = trimmed["content-length:".len()..].trim().parse().unwrap_or(0);
+        // Skip any other headers up to the blank separator line
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                return Ok(None);
+            }
+            if line.trim().is_empty() {
+                break;
+            }
+        }
+
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await?;
+        return Ok(Some((Framing::Headers, body)));
+    }
+}
+
+fn encode(framing: Framing, message: &Value) -> Vec<u8> {
+    let body = message.to_string();
+    match framing {
+        Framing::Headers => format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes(),
+        Framing::Line => format!("{}\n", body).into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_framing_and_queries() {
+        let input = b"Content-Length: 17\r\n\r\n{\"method\":\"exit\"}{\"id\":1}\n";
+        let mut reader = BufReader::new(&input[..]);
+        assert_eq!(
+            read_message(&mut reader).await.unwrap(),
+            Some((Framing::Headers, b"{\"method\":\"exit\"}".to_vec()))
+        );
+        assert_eq!(read_message(&mut reader).await.unwrap(), Some((Framing::Line, b"{\"id\":1}".to_vec())));
+        assert_eq!(read_message(&mut reader).await.unwrap(), None);
+
+        let metadata: PackageMetadata = serde_json::from_value(json!({
+            "name": "react",
+            "dist-tags": { "latest": "18.2.0", "next": "19.0.0-rc.1" },
+            "versions": {
+                "17.0.2": { "name": "react", "version": "17.0.2", "dist": { "tarball": "" } },
+                "18.1.0": { "name": "react", "version": "18.1.0", "dist": { "tarball": "" } },
+                "18.2.0": { "name": "react", "version": "18.2.0", "dist": { "tarball": "" } },
+                "19.0.0-rc.1": { "name": "react", "version": "19.0.0-rc.1", "dist": { "tarball": "" } },
+            },
+        }))
+        .unwrap();
+
+        assert_eq!(resolve_spec(&metadata, "^18.0.0").as_deref(), Some("18.2.0"));
+        assert_eq!(resolve_spec(&metadata, "next").as_deref(), Some("19.0.0-rc.1"));
+        assert_eq!(resolve_spec(&metadata, "^20.0.0"), None);
+
+        let suggestions = version_suggestions(&metadata, "^18");
+        assert_eq!(suggestions.len(), 2);
+        assert_eq!(suggestions[0]["version"], "18.2.0");
+        assert_eq!(suggestions[0]["tags"], json!(["latest"]));
+    }
+}
@@ -1,21 +1,21 @@
-//! velocity migrate - Migrate from npm/pnpm
+//! velocity migrate - Migrate from npm/pnpm/yarn/bun
 
-use std::env;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use clap::Args;
 
 use crate::cli::output;
-use crate::core::{VelocityResult, VelocityError};
+use crate::core::config::RegistryConfig;
+use crate::core::project;
+use crate::core::{Config, Engine, Lockfile, VelocityResult, VelocityError};
+use crate::resolver::ResolveOptions;
 
 #[derive(Args)]
 pub struct MigrateArgs {
-    /// Source package manager (npm, pnpm, yarn)
+    /// Source package manager (npm, pnpm, yarn, bun)
     pub from: String,
 
-    /// Project directory
-    #[arg(long, default_value = ".")]
-    pub cwd: PathBuf,
 
     /// Remove old lockfile after migration
     #[arg(long)]
@@ -24,23 +24,23 @@ pub struct MigrateArgs {
     /// Dry run - show what would be migrated
     #[arg(long)]
     pub dry_run: bool,
+
+    /// Skip re-resolving dependencies to check the migrated lockfile
+    #[arg(long)]
+    pub no_verify: bool,
 }
 
 pub async fn execute(args: MigrateArgs, json_output: bool) -> VelocityResult<()> {
     let start_time = Instant::now();
 
-    let project_dir = if args.cwd.is_absolute() {
-        args.cwd.clone()
-    } else {
-        env::current_dir()?.join(&args.cwd)
-    };
+    let project_dir = project::current_project_root()?;
 
     let from = args.from.to_lowercase();
     
     // Validate source
-    if !["npm", "pnpm", "yarn"].contains(&from.as_str()) {
+    if !["npm", "pnpm", "yarn", "bun"].contains(&from.as_str()) {
         return Err(VelocityError::migration(format!(
-            "Unsupported package manager '{}'. Supported: npm, pnpm, yarn",
+            "Unsupported package manager '{}'. Supported: npm, pnpm, yarn, bun",
             from
         )));
     }
@@ -66,12 +66,53 @@ pub async fn execute(args: MigrateArgs, json_output: bool) -> VelocityResult<()>
     };
 
     // Parse the source lockfile
-    let migration_info = parse_source_lockfile(&source_lockfile, &from)?;
+    let config = Config::load(&project_dir)?;
+    let mut migration_info = parse_source_lockfile(&source_lockfile, &from, &config.registry)?;
 
     if let Some(ref pb) = progress {
         pb.set_message("Converting dependencies...");
     }
 
+    // Create Velocity lockfile
+    let mut lockfile = Lockfile::new();
+
+    for pkg in &migration_info.packages {
+        lockfile.add_package(crate::core::lockfile::LockedPackage {
+            name: pkg.name.clone(),
+            alias_of: pkg.alias_of.clone(),
+            version: pkg.version.clone(),
+            resolved: pkg.resolved.clone(),
+            integrity: pkg.integrity.clone(),
+            dependencies: pkg.dependencies.iter().map(|dep| dep.as_str().into()).collect(),
+            peer_dependencies: pkg.peer_dependencies.clone(),
+            optional_dependencies: pkg.optional_dependencies.clone(),
+            has_scripts: false,
+            approved_scripts: None,
+            cpu: pkg.cpu.clone(),
+            os: pkg.os.clone(),
+            dev: false,
+            provenance: None,
+        });
+    }
+
+    lockfile.pin_dependencies();
+
+    // Resolve again, preferring the migrated versions, to see what the lockfile misses
+    let drift = if args.no_verify {
+        None
+    } else {
+        if let Some(ref pb) = progress {
+            pb.set_message("Verifying migrated lockfile...");
+        }
+        match verify_migration(&project_dir, config.clone(), &lockfile).await {
+            Ok(drift) => Some(drift),
+            Err(e) => {
+                migration_info.warnings.push(format!("Could not verify the migrated lockfile: {}", e));
+                None
+            }
+        }
+    };
+
     if args.dry_run {
         if let Some(pb) = progress {
             pb.finish_and_clear();
@@ -82,7 +123,9 @@ pub async fn execute(args: MigrateArgs, json_output: bool) -> VelocityResult<()>
                 "dry_run": true,
                 "from": from,
                 "packages": migration_info.packages.len(),
-                "source_lockfile": source_lockfile
+                "source_lockfile": source_lockfile,
+                "warnings": migration_info.warnings,
+                "drift": drift.as_ref().map(MigrationDrift::to_json)
             }))?;
         } else {
             output::info("Dry run - no changes will be made");
@@ -90,35 +133,21 @@ pub async fn execute(args: MigrateArgs, json_output: bool) -> VelocityResult<()>
             println!("  Source: {}", source_lockfile.display());
             println!("  Packages: {}", migration_info.packages.len());
             println!();
+            for warning in &migration_info.warnings {
+                output::warning(warning);
+            }
+            print_drift(drift.as_ref());
             output::info("Run without --dry-run to perform migration");
         }
         return Ok(());
     }
 
-    // Create Velocity lockfile
-    let mut lockfile = crate::core::Lockfile::new();
-
-    for pkg in &migration_info.packages {
-        lockfile.add_package(crate::core::lockfile::LockedPackage {
-            name: pkg.name.clone(),
-            version: pkg.version.clone(),
-            resolved: pkg.resolved.clone(),
-            integrity: pkg.integrity.clone(),
-            dependencies: pkg.dependencies.clone(),
-            peer_dependencies: Vec::new(),
-            optional_dependencies: Vec::new(),
-            has_scripts: false,
-            cpu: Vec::new(),
-            os: Vec::new(),
-        });
-    }
-
     if let Some(ref pb) = progress {
         pb.set_message("Saving lockfile...");
     }
 
     // Save Velocity lockfile
-    lockfile.save(&project_dir)?;
+    lockfile.save(&config.lockfile_path(&project_dir))?;
 
     // Update package.json to use Velocity
     let mut package_json = crate::core::PackageJson::load(&project_dir)?;
@@ -141,6 +170,8 @@ pub async fn execute(args: MigrateArgs, json_output: bool) -> VelocityResult<()>
             "success": true,
             "from": from,
             "packages": migration_info.packages.len(),
+            "warnings": migration_info.warnings,
+            "drift": drift.as_ref().map(MigrationDrift::to_json),
             "duration_ms": duration.as_millis()
         }))?;
     } else {
@@ -150,6 +181,10 @@ pub async fn execute(args: MigrateArgs, json_output: bool) -> VelocityResult<()>
             from,
             output::format_duration(duration.as_millis())
         ));
+        for warning in &migration_info.warnings {
+            output::warning(warning);
+        }
+        print_drift(drift.as_ref());
 
         println!();
         output::info("Next steps:");
@@ -164,236 +199,1288 @@ pub async fn execute(args: MigrateArgs, json_output: bool) -> VelocityResult<()>
     Ok(())
 }
 
+/// How a fresh resolution of package.json differs from the migrated lockfile
+#[derive(Debug, Default, PartialEq)]
+struct MigrationDrift {
+    /// Packages (name@version) the resolution needs but the migration lacks
+    missing: Vec<String>,
+
+    /// Migrated packages (name@version) the resolution does not use
+    unused: Vec<String>,
+
+    /// Packages in both whose dependency names differ, with the names only
+    /// the resolution has and the names only the migration has
+    edges: Vec<(String, Vec<String>, Vec<String>)>,
+}
+
+impl MigrationDrift {
+    /// Compare the migrated lockfile with a fresh resolution
+    fn between(migrated: &Lockfile, fresh: &Lockfile) -> Self {
+        let index = |lockfile: &Lockfile| -> BTreeMap<String, BTreeSet<String>> {
+            lockfile
+                .packages
+                .iter()
+                .map(|p| {
+                    let deps = p.dependencies.iter().map(|dep| dep.name.clone()).collect();
+                    (format!("{}@{}", p.name, p.version), deps)
+                })
+                .collect()
+        };
+        let (migrated, fresh) = (index(migrated), index(fresh));
+
+        let mut drift = MigrationDrift::default();
+        for (package, deps) in &fresh {
+            match migrated.get(package) {
+                None => drift.missing.push(package.clone()),
+                Some(migrated_deps) if migrated_deps != deps => drift.edges.push((
+                    package.clone(),
+                    deps.difference(migrated_deps).cloned().collect(),
+                    migrated_deps.difference(deps).cloned().collect(),
+                )),
+                Some(_) => {}
+            }
+        }
+        drift.unused = migrated.keys().filter(|p| !fresh.contains_key(*p)).cloned().collect();
+        drift
+    }
+
+    fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unused.is_empty() && self.edges.is_empty()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "missing": self.missing,
+            "unused": self.unused,
+            "dependencies": self.edges.iter().map(|(package, added, dropped)| serde_json::json!({
+                "package": package,
+                "missing": added,
+                "unused": dropped,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Resolve package.json again, preferring the migrated versions, and compare
+///
+/// Versions the source lockfile pinned are kept wherever they still satisfy the
+/// ranges, so any drift is something the migration lost or got wrong.
+async fn verify_migration(project_dir: &Path, config: Config, migrated: &Lockfile) -> VelocityResult<MigrationDrift> {
+    let engine = Engine::with_config(project_dir, config).await?;
+    let options = ResolveOptions {
+        auto_install_peers: engine.config.install.auto_install_peers,
+        ..Default::default()
+    }
+    .with_lockfile(Some(migrated));
+
+    let resolver = engine.resolver().with_options(options);
+    let dependencies = engine.package_json()?.all_dependencies();
+    let resolution = if engine.installs_workspace() {
+        resolver.resolve_workspace(&dependencies).await?
+    } else {
+        resolver.resolve(&dependencies).await?
+    };
+
+    Ok(MigrationDrift::between(migrated, &resolution.lockfile))
+}
+
+/// Report drift found by the verification resolution
+fn print_drift(drift: Option<&MigrationDrift>) {
+    let Some(drift) = drift else { return };
+    if drift.is_empty() {
+        output::success("A fresh resolution matches the migrated lockfile");
+        return;
+    }
+
+    output::warning("A fresh resolution differs from the migrated lockfile:");
+    for package in &drift.missing {
+        println!("  {} {}", output::good("+"), package);
+    }
+    for package in &drift.unused {
+        println!("  {} {}", output::bad("-"), package);
+    }
+    for (package, missing, unused) in &drift.edges {
+        let changes: Vec<String> = missing
+            .iter()
+            .map(|dep| format!("+{}", dep))
+            .chain(unused.iter().map(|dep| format!("-{}", dep)))
+            .collect();
+        println!("  {} {} ({})", output::caution("~"), package, changes.join(", "));
+    }
+    output::info("Run 'velocity install' to bring the lockfile in line");
+}
+
 fn get_source_lockfile(project_dir: &PathBuf, from: &str) -> PathBuf {
     match from {
         "npm" => project_dir.join("package-lock.json"),
         "pnpm" => project_dir.join("pnpm-lock.yaml"),
         "yarn" => project_dir.join("yarn.lock"),
+        // Bun 1.2+ writes the text bun.lock; older versions only the binary bun.lockb
+        "bun" if !project_dir.join("bun.lock").exists() => project_dir.join("bun.lockb"),
+        "bun" => project_dir.join("bun.lock"),
         _ => project_dir.join("package-lock.json"),
     }
 }
 
+#[derive(Default)]
 struct MigrationInfo {
     packages: Vec<MigratedPackage>,
+
+    /// Entries that could not be carried over as they were
+    warnings: Vec<String>,
 }
 
+#[derive(Debug, Default, PartialEq)]
 struct MigratedPackage {
     name: String,
+    alias_of: Option<String>,
     version: String,
     resolved: String,
     integrity: String,
     dependencies: Vec<String>,
+    peer_dependencies: Vec<String>,
+    optional_dependencies: Vec<String>,
+    os: Vec<String>,
+    cpu: Vec<String>,
 }
 
-fn parse_source_lockfile(path: &PathBuf, from: &str) -> VelocityResult<MigrationInfo> {
+fn parse_source_lockfile(path: &PathBuf, from: &str, registry: &RegistryConfig) -> VelocityResult<MigrationInfo> {
+    if path.extension().is_some_and(|ext| ext == "lockb") {
+        return parse_node_modules(path.parent().unwrap_or(Path::new(".")), registry);
+    }
     let content = std::fs::read_to_string(path)?;
 
     match from {
-        "npm" => parse_npm_lockfile(&content),
-        "pnpm" => parse_pnpm_lockfile(&content),
-        "yarn" => parse_yarn_lockfile(&content),
+        "npm" => parse_npm_lockfile(&content, registry),
+        "pnpm" => parse_pnpm_lockfile(&content, registry),
+        // Yarn 2+ (Berry) lockfiles are YAML with a __metadata block
+        "yarn" if content.lines().any(|line| line == "__metadata:") => parse_yarn_berry_lockfile(&content, registry),
+        "yarn" => parse_yarn_lockfile(&content, registry),
+        "bun" => parse_bun_lockfile(&content, registry),
         _ => Err(VelocityError::migration("Unsupported lockfile format")),
     }
 }
 
-fn parse_npm_lockfile(content: &str) -> VelocityResult<MigrationInfo> {
+/// Parse an npm v2/v3 lockfile from its flat `packages` map
+///
+/// Keys are install paths, so a package nested under several parents appears
+/// once per copy; one entry is kept per name and version.
+fn parse_npm_lockfile(content: &str, registry: &RegistryConfig) -> VelocityResult<MigrationInfo> {
     let lockfile: serde_json::Value = serde_json::from_str(content)?;
-    let mut packages = Vec::new();
+    let entries = lockfile.get("packages").and_then(|p| p.as_object()).ok_or_else(|| {
+        VelocityError::migration(
+            "package-lock.json has no 'packages' map. Run 'npm install' with npm 7 or later to upgrade it first.",
+        )
+    })?;
+
+    let mut packages = BTreeMap::new();
+    let mut warnings = Vec::new();
 
-    // npm v2/v3 format
-    if let Some(deps) = lockfile.get("packages").and_then(|p| p.as_object()) {
-        for (key, value) in deps {
-            // Skip root package
-            if key.is_empty() || key == "." {
+    for (path, entry) in entries {
+        // The root and workspace sources have no node_modules segment
+        let Some((_, installed)) = path.rsplit_once("node_modules/") else {
+            continue;
+        };
+        let field = |key: &str| entry.get(key).and_then(|v| v.as_str());
+        let names = |key: &str| -> Vec<String> {
+            entry.get(key).and_then(|v| v.as_object()).map(|m| m.keys().cloned().collect()).unwrap_or_default()
+        };
+        let list = |key: &str| -> Vec<String> {
+            entry
+                .get(key)
+                .and_then(|v| v.as_array())
+                .map(|values| values.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+                .unwrap_or_default()
+        };
+
+        // Workspace symlinks and bundled copies come with their owner
+        if entry.get("link").and_then(|v| v.as_bool()) == Some(true)
+            || entry.get("inBundle").and_then(|v| v.as_bool()) == Some(true)
+        {
+            continue;
+        }
+        let Some(version) = field("version") else {
+            warnings.push(format!("Skipped '{}': no version", path));
+            continue;
+        };
+
+        let alias_of = field("name").filter(|real| *real != installed).map(String::from);
+        let real = alias_of.as_deref().unwrap_or(installed);
+        let resolved = match field("resolved") {
+            None => registry_tarball(registry, real, version),
+            Some(url) if url.starts_with("http://") || url.starts_with("https://") => url.to_string(),
+            Some(source) => {
+                warnings.push(format!("{} resolves to {} and was not migrated; add it again with 'velocity add'", installed, source));
                 continue;
             }
+        };
 
-            // Extract package name from path
-            let name = key
-                .trim_start_matches("node_modules/")
-                .to_string();
-
-            if let Some(obj) = value.as_object() {
-                let version = obj.get("version")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("0.0.0")
-                    .to_string();
-
-                let resolved = obj.get("resolved")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-
-                let integrity = obj.get("integrity")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-
-                let mut dependencies = Vec::new();
-                if let Some(deps) = obj.get("dependencies").and_then(|d| d.as_object()) {
-                    for (dep_name, dep_version) in deps {
-                        dependencies.push(format!("{}@{}", dep_name, dep_version.as_str().unwrap_or("*")));
-                    }
+        let mut dependencies = Vec::new();
+        for section in ["dependencies", "optionalDependencies"] {
+            if let Some(deps) = entry.get(section).and_then(|d| d.as_object()) {
+                for (dep_name, range) in deps {
+                    dependencies.push(format!("{}@{}", dep_name, range.as_str().unwrap_or("*")));
                 }
+            }
+        }
 
-                packages.push(MigratedPackage {
-                    name,
-                    version,
-                    resolved,
-                    integrity,
-                    dependencies,
-                });
+        packages.entry((installed.to_string(), version.to_string())).or_insert(MigratedPackage {
+            name: installed.to_string(),
+            alias_of,
+            version: version.to_string(),
+            resolved,
+            integrity: field("integrity").unwrap_or_default().to_string(),
+            dependencies,
+            peer_dependencies: names("peerDependencies"),
+            optional_dependencies: names("optionalDependencies"),
+            os: list("os"),
+            cpu: list("cpu"),
+        });
+    }
+
+    Ok(MigrationInfo { packages: packages.into_values().collect(), warnings })
+}
+
+/// Parse a pnpm lockfile (v5, v6 or v9)
+///
+/// Dependencies are recorded as the exact versions pnpm chose, which serve as
+/// ranges that only those versions satisfy.
+fn parse_pnpm_lockfile(content: &str, registry: &RegistryConfig) -> VelocityResult<MigrationInfo> {
+    let document: serde_yaml::Value = serde_yaml::from_str(content)
+        .map_err(|e| VelocityError::migration(format!("Invalid pnpm-lock.yaml: {}", e)))?;
+
+    // v5 writes `/name/1.0.0_peer@1.0.0` where later versions write `/name@1.0.0(peer@1.0.0)`
+    let legacy = document
+        .get("lockfileVersion")
+        .and_then(yaml_scalar)
+        .and_then(|v| v.split('.').next().and_then(|major| major.parse::<u32>().ok()))
+        .is_some_and(|major| major < 6);
+
+    let metadata = document.get("packages").and_then(|p| p.as_mapping());
+    // v9 moves dependencies from `packages` into per-peer-set `snapshots`
+    let snapshots = document.get("snapshots").and_then(|s| s.as_mapping()).or(metadata);
+
+    let mut packages: BTreeMap<(String, String), MigratedPackage> = BTreeMap::new();
+    let mut aliases = BTreeSet::new();
+    let mut warnings = Vec::new();
+
+    for (key, snapshot) in snapshots.into_iter().flatten() {
+        let Some(key) = key.as_str() else { continue };
+        let Some((name, version)) = pnpm_key(key, legacy) else {
+            warnings.push(format!("Skipped '{}': cannot read package key", key));
+            continue;
+        };
+        let base = key.split('(').next().unwrap_or(key);
+        let entry = metadata.and_then(|m| m.get(base)).unwrap_or(snapshot);
+        let field = |key: &str| entry.get(key).and_then(yaml_scalar);
+        let list = |key: &str| -> Vec<String> {
+            entry
+                .get(key)
+                .and_then(|v| v.as_sequence())
+                .map(|values| values.iter().filter_map(yaml_scalar).collect())
+                .unwrap_or_default()
+        };
+
+        // Non-registry packages carry their real name and version in the entry
+        let name = field("name").unwrap_or(name);
+        let version = field("version").unwrap_or(version);
+        if semver::Version::parse(&version).is_err() {
+            warnings.push(format!("{} comes from '{}' and was not migrated; add it again with 'velocity add'", name, key));
+            continue;
+        }
+
+        let resolution = entry.get("resolution");
+        let tarball = resolution.and_then(|r| r.get("tarball")).and_then(yaml_scalar);
+        let resolved = match tarball {
+            Some(url) if url.starts_with("http://") || url.starts_with("https://") => url,
+            None if resolution.and_then(|r| r.get("integrity")).is_some() => registry_tarball(registry, &name, &version),
+            _ => {
+                warnings.push(format!("{}@{} is not from a registry and was not migrated; add it again with 'velocity add'", name, version));
+                continue;
+            }
+        };
+
+        let mut dependencies = Vec::new();
+        let mut optional_dependencies = Vec::new();
+        for section in ["dependencies", "optionalDependencies"] {
+            for (dep, reference) in yaml_map(snapshot.get(section)) {
+                let Some(range) = pnpm_reference(&reference, legacy) else { continue };
+                if let Some((real, version)) = range.strip_prefix("npm:").and_then(split_descriptor) {
+                    aliases.insert((dep.clone(), real.to_string(), version.to_string()));
+                }
+                if section == "optionalDependencies" {
+                    optional_dependencies.push(dep.clone());
+                }
+                dependencies.push(format!("{}@{}", dep, range));
             }
         }
+
+        // Peer variants of one version collapse into a single entry
+        packages.entry((name.clone(), version.clone())).or_insert(MigratedPackage {
+            name,
+            alias_of: None,
+            integrity: resolution.and_then(|r| r.get("integrity")).and_then(yaml_scalar).unwrap_or_default(),
+            resolved,
+            version,
+            dependencies,
+            peer_dependencies: yaml_map(entry.get("peerDependencies")).into_keys().collect(),
+            optional_dependencies,
+            os: list("os"),
+            cpu: list("cpu"),
+        });
     }
 
-    Ok(MigrationInfo { packages })
+    // Aliases the project itself depends on; v9 and workspaces list them per importer
+    let importers: Vec<&serde_yaml::Value> = match document.get("importers").and_then(|i| i.as_mapping()) {
+        Some(importers) => importers.values().collect(),
+        None => vec![&document],
+    };
+    for importer in importers {
+        for section in ["dependencies", "devDependencies", "optionalDependencies"] {
+            let Some(deps) = importer.get(section).and_then(|d| d.as_mapping()) else { continue };
+            for (dep, value) in deps {
+                let reference = value.get("version").unwrap_or(value);
+                let (Some(dep), Some(reference)) = (yaml_scalar(dep), yaml_scalar(reference)) else { continue };
+                let alias = pnpm_reference(&reference, legacy);
+                if let Some((real, version)) = alias.as_deref().and_then(|r| r.strip_prefix("npm:")).and_then(split_descriptor) {
+                    aliases.insert((dep, real.to_string(), version.to_string()));
+                }
+            }
+        }
+    }
+
+    for (alias, real, version) in aliases {
+        let Some(target) = packages.get(&(real.clone(), version.clone())) else { continue };
+        let aliased = MigratedPackage {
+            name: alias.clone(),
+            alias_of: Some(real),
+            version: version.clone(),
+            resolved: target.resolved.clone(),
+            integrity: target.integrity.clone(),
+            dependencies: target.dependencies.clone(),
+            peer_dependencies: target.peer_dependencies.clone(),
+            optional_dependencies: target.optional_dependencies.clone(),
+            os: target.os.clone(),
+            cpu: target.cpu.clone(),
+        };
+        packages.entry((alias, version)).or_insert(aliased);
+    }
+
+    Ok(MigrationInfo { packages: packages.into_values().collect(), warnings })
+}
+
+/// Name and version from a pnpm package key: `/name/1.0.0_peer@1.0.0` (v5),
+/// `/name@1.0.0(peer@1.0.0)` (v6) or `name@1.0.0(peer@1.0.0)` (v9)
+fn pnpm_key(key: &str, legacy: bool) -> Option<(String, String)> {
+    let key = key.strip_prefix('/').unwrap_or(key);
+    if legacy {
+        let (name, version) = key.rsplit_once('/')?;
+        let version = version.split('_').next()?;
+        return Some((name.to_string(), version.to_string()));
+    }
+    let (name, version) = split_descriptor(key.split('(').next()?)?;
+    Some((name.to_string(), version.to_string()))
 }
 
-fn parse_pnpm_lockfile(content: &str) -> VelocityResult<MigrationInfo> {
-    // Basic YAML parsing for pnpm lockfile
-    // In production, use a proper YAML parser
-    let mut packages = Vec::new();
+/// A pnpm dependency reference as a range: `1.0.0(react@18.2.0)` becomes `1.0.0`,
+/// and a reference to another package name (an alias) becomes `npm:real@1.0.0`
+///
+/// Workspace links and local paths have no range and yield `None`.
+fn pnpm_reference(reference: &str, legacy: bool) -> Option<String> {
+    if reference.starts_with(|c: char| c.is_ascii_digit()) {
+        let end = if legacy { ['(', '_'].as_slice() } else { ['('].as_slice() };
+        return reference.split(end).next().map(String::from);
+    }
+    if reference.starts_with("link:") || reference.starts_with("file:") {
+        return None;
+    }
+    let (name, version) = pnpm_key(reference, legacy)?;
+    Some(format!("npm:{}@{}", name, version))
+}
 
-    let mut current_package: Option<String> = None;
-    let mut current_version = String::new();
-    let mut current_resolved = String::new();
-    let mut current_integrity = String::new();
+/// Parse a Yarn Classic (v1) lockfile
+///
+/// Entries are blocks keyed by every descriptor they satisfy, with
+/// `dependencies` and `optionalDependencies` listed as indented sub-blocks.
+fn parse_yarn_lockfile(content: &str, registry: &RegistryConfig) -> VelocityResult<MigrationInfo> {
+    let mut packages: BTreeMap<(String, String), MigratedPackage> = BTreeMap::new();
+    let mut warnings = Vec::new();
 
+    let mut blocks: Vec<(&str, Vec<&str>)> = Vec::new();
     for line in content.lines() {
-        let trimmed = line.trim();
-        
-        // Package entry
-        if !line.starts_with(' ') && !line.starts_with('\t') && trimmed.ends_with(':') {
-            // Save previous package
-            if let Some(ref name) = current_package {
-                if !current_version.is_empty() {
-                    packages.push(MigratedPackage {
-                        name: name.clone(),
-                        version: current_version.clone(),
-                        resolved: current_resolved.clone(),
-                        integrity: current_integrity.clone(),
-                        dependencies: Vec::new(),
-                    });
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(' ') {
+            blocks.push((line.trim_end_matches(':'), Vec::new()));
+        } else if let Some((_, body)) = blocks.last_mut() {
+            body.push(line);
+        }
+    }
+
+    for (key, body) in blocks {
+        let mut version = None;
+        let mut resolved = None;
+        let mut integrity = String::new();
+        let mut dependencies = Vec::new();
+        let mut optional_dependencies = Vec::new();
+        let mut section = "";
+
+        for line in body {
+            let (field, value) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            let value = unquote(value.trim());
+            if !line.starts_with("    ") {
+                section = field.trim_end_matches(':');
+                match section {
+                    "version" => version = Some(value.to_string()),
+                    "resolved" => resolved = Some(value.to_string()),
+                    "integrity" => integrity = value.to_string(),
+                    _ => {}
                 }
+                continue;
             }
 
-            let entry = trimmed.trim_end_matches(':');
-            // Parse package@version format
-            if let Some(at_idx) = entry.rfind('@') {
-                current_package = Some(entry[..at_idx].to_string());
-                current_version = entry[at_idx + 1..].to_string();
-            } else {
-                current_package = Some(entry.to_string());
-                current_version.clear();
+            let dep = unquote(field);
+            match section {
+                "dependencies" => dependencies.push(format!("{}@{}", dep, value)),
+                "optionalDependencies" => {
+                    dependencies.push(format!("{}@{}", dep, value));
+                    optional_dependencies.push(dep.to_string());
+                }
+                _ => {}
             }
-            current_resolved.clear();
-            current_integrity.clear();
         }
 
-        // Parse properties
-        if trimmed.starts_with("resolution:") {
-            current_resolved = trimmed
-                .trim_start_matches("resolution:")
-                .trim()
-                .trim_matches('{')
-                .trim_matches('}')
-                .to_string();
+        // `alias@npm:real@range` descriptors install the real package under the alias
+        let descriptors: Vec<(&str, &str)> = key
+            .split(", ")
+            .filter_map(|descriptor| split_descriptor(unquote(descriptor.trim())))
+            .collect();
+        let Some(version) = version else {
+            warnings.push(format!("Skipped '{}': no version", key));
+            continue;
+        };
+        let Some(&(first, range)) = descriptors.first() else {
+            warnings.push(format!("Skipped '{}': cannot read package key", key));
+            continue;
+        };
+        let real = range
+            .strip_prefix("npm:")
+            .and_then(split_descriptor)
+            .map(|(real, _)| real)
+            .unwrap_or(first);
+
+        let resolved = match resolved {
+            None => registry_tarball(registry, real, &version),
+            // Yarn appends the tarball's sha1 as a URL fragment
+            Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+                url.split('#').next().unwrap_or_default().to_string()
+            }
+            Some(source) => {
+                warnings.push(format!("{} resolves to {} and was not migrated; add it again with 'velocity add'", first, source));
+                continue;
+            }
+        };
+
+        let names: BTreeSet<&str> = descriptors.iter().map(|(name, _)| *name).collect();
+        for name in names {
+            packages.entry((name.to_string(), version.clone())).or_insert_with(|| MigratedPackage {
+                name: name.to_string(),
+                alias_of: (name != real).then(|| real.to_string()),
+                version: version.clone(),
+                resolved: resolved.clone(),
+                integrity: integrity.clone(),
+                dependencies: dependencies.clone(),
+                optional_dependencies: optional_dependencies.clone(),
+                ..Default::default()
+            });
         }
+    }
+
+    Ok(MigrationInfo { packages: packages.into_values().collect(), warnings })
+}
+
+/// Strip the double quotes Yarn Classic puts around some names and values
+fn unquote(value: &str) -> &str {
+    value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value)
+}
+
+/// The registry tarball URL for a package, for lockfiles that do not record one
+fn registry_tarball(registry: &RegistryConfig, name: &str, version: &str) -> String {
+    tarball_url(registry.registry_for(name), name, version)
+}
+
+/// The conventional tarball URL for a package on the registry at `base`
+fn tarball_url(base: &str, name: &str, version: &str) -> String {
+    let unscoped = name.rsplit('/').next().unwrap_or(name);
+    format!("{}/{}/-/{}-{}.tgz", base.trim_end_matches('/'), name, unscoped, version)
+}
 
-        if trimmed.starts_with("integrity:") {
-            current_integrity = trimmed
-                .trim_start_matches("integrity:")
-                .trim()
-                .to_string();
+/// Parse Bun's text lockfile (bun.lock), which is JSON with trailing commas
+///
+/// `packages` is keyed by install path; each entry is `[name@version, registry,
+/// info, integrity]`, with shorter entries for workspace, git and file packages.
+fn parse_bun_lockfile(content: &str, registry: &RegistryConfig) -> VelocityResult<MigrationInfo> {
+    let lockfile: serde_json::Value = serde_json::from_str(&strip_trailing_commas(content))
+        .map_err(|e| VelocityError::migration(format!("Invalid bun.lock: {}", e)))?;
+    let entries = lockfile
+        .get("packages")
+        .and_then(|p| p.as_object())
+        .ok_or_else(|| VelocityError::migration("bun.lock has no 'packages' map"))?;
+
+    let mut packages = BTreeMap::new();
+    let mut warnings = Vec::new();
+
+    for (path, entry) in entries {
+        let installed = bun_install_name(path);
+        let Some((name, version)) = entry.get(0).and_then(|v| v.as_str()).and_then(split_descriptor) else {
+            warnings.push(format!("Skipped '{}': cannot read resolution", path));
+            continue;
+        };
+        if version.starts_with("workspace:") {
+            continue;
+        }
+        if semver::Version::parse(version).is_err() {
+            warnings.push(format!("{} comes from '{}' and was not migrated; add it again with 'velocity add'", installed, version));
+            continue;
         }
+
+        // An empty registry means the default one
+        let resolved = match entry.get(1).and_then(|v| v.as_str()).unwrap_or_default() {
+            "" => registry_tarball(registry, name, version),
+            url if url.ends_with(".tgz") => url.to_string(),
+            url => tarball_url(url, name, version),
+        };
+        let info = entry.get(2);
+        let section = |key: &str| info.and_then(|i| i.get(key)).and_then(|v| v.as_object());
+        let names = |key: &str| -> Vec<String> { section(key).map(|m| m.keys().cloned().collect()).unwrap_or_default() };
+
+        let mut dependencies = Vec::new();
+        for key in ["dependencies", "optionalDependencies"] {
+            for (dep, range) in section(key).into_iter().flatten() {
+                dependencies.push(format!("{}@{}", dep, range.as_str().unwrap_or("*")));
+            }
+        }
+
+        packages.entry((installed.to_string(), version.to_string())).or_insert(MigratedPackage {
+            name: installed.to_string(),
+            alias_of: (installed != name).then(|| name.to_string()),
+            version: version.to_string(),
+            resolved,
+            integrity: entry.get(3).and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+            dependencies,
+            peer_dependencies: names("peerDependencies"),
+            optional_dependencies: names("optionalDependencies"),
+            os: string_list(info.and_then(|i| i.get("os"))),
+            cpu: string_list(info.and_then(|i| i.get("cpu"))),
+        });
     }
 
-    // Don't forget the last package
-    if let Some(ref name) = current_package {
-        if !current_version.is_empty() {
-            packages.push(MigratedPackage {
-                name: name.clone(),
-                version: current_version,
-                resolved: current_resolved,
-                integrity: current_integrity,
-                dependencies: Vec::new(),
-            });
+    Ok(MigrationInfo { packages: packages.into_values().collect(), warnings })
+}
+
+/// Read the packages installed in node_modules
+///
+/// bun.lockb is a binary format, so a Bun project is migrated from the tree
+/// Bun installed. Installed manifests carry no integrity hashes.
+fn parse_node_modules(project_dir: &Path, registry: &RegistryConfig) -> VelocityResult<MigrationInfo> {
+    let node_modules = project_dir.join("node_modules");
+    if !node_modules.is_dir() {
+        return Err(VelocityError::migration(
+            "bun.lockb is a binary lockfile and node_modules is missing. Run 'bun install' first, or 'bun install --save-text-lockfile' to write bun.lock.",
+        ));
+    }
+
+    let mut packages = BTreeMap::new();
+    for dir in crate::installer::linker::installed_package_dirs(&node_modules)? {
+        // Workspace members are linked, not installed
+        if dir.is_symlink() {
+            continue;
         }
+        let Ok(content) = std::fs::read_to_string(dir.join("package.json")) else {
+            continue;
+        };
+        let Ok(manifest) = serde_json::from_str::<serde_json::Value>(&content) else {
+            continue;
+        };
+        let field = |key: &str| manifest.get(key).and_then(|v| v.as_str());
+        let section = |key: &str| manifest.get(key).and_then(|v| v.as_object());
+        let (Some(name), Some(version)) = (field("name"), field("version")) else {
+            continue;
+        };
+
+        let installed = crate::installer::linker::install_name(&dir);
+        let mut dependencies = Vec::new();
+        for key in ["dependencies", "optionalDependencies"] {
+            for (dep, range) in section(key).into_iter().flatten() {
+                dependencies.push(format!("{}@{}", dep, range.as_str().unwrap_or("*")));
+            }
+        }
+
+        packages.entry((installed.clone(), version.to_string())).or_insert(MigratedPackage {
+            alias_of: (installed != name).then(|| name.to_string()),
+            resolved: registry_tarball(registry, name, version),
+            version: version.to_string(),
+            integrity: String::new(),
+            dependencies,
+            peer_dependencies: section("peerDependencies").map(|m| m.keys().cloned().collect()).unwrap_or_default(),
+            optional_dependencies: section("optionalDependencies").map(|m| m.keys().cloned().collect()).unwrap_or_default(),
+            os: string_list(manifest.get("os")),
+            cpu: string_list(manifest.get("cpu")),
+            name: installed,
+        });
     }
 
-    Ok(MigrationInfo { packages })
+    Ok(MigrationInfo {
+        packages: packages.into_values().collect(),
+        warnings: vec![
+            "bun.lockb is binary; packages were read from node_modules and have no integrity hashes".to_string(),
+        ],
+    })
 }
 
-fn parse_yarn_lockfile(content: &str) -> VelocityResult<MigrationInfo> {
-    let mut packages = Vec::new();
-    let mut current_name = String::new();
-    let mut current_version = String::new();
-    let mut current_resolved = String::new();
-    let mut current_integrity = String::new();
+/// Name a bun.lock path installs under: `parent/@scope/name` installs `@scope/name`
+fn bun_install_name(path: &str) -> &str {
+    let mut separators = path.rmatch_indices('/').map(|(i, _)| i);
+    let Some(last) = separators.next() else {
+        return path;
+    };
+    let start = separators.next().map(|i| i + 1).unwrap_or(0);
+    if path[start..].starts_with('@') {
+        &path[start..]
+    } else {
+        &path[last + 1..]
+    }
+}
 
-    for line in content.lines() {
-        // Package entry (e.g., "package-name@^1.0.0:")
-        if !line.starts_with(' ') && !line.starts_with('\t') && !line.starts_with('#') && line.contains('@') {
-            // Save previous
-            if !current_name.is_empty() && !current_version.is_empty() {
-                packages.push(MigratedPackage {
-                    name: current_name.clone(),
-                    version: current_version.clone(),
-                    resolved: current_resolved.clone(),
-                    integrity: current_integrity.clone(),
-                    dependencies: Vec::new(),
-                });
+/// A JSON string or array of strings as a list
+fn string_list(value: Option<&serde_json::Value>) -> Vec<String> {
+    match value {
+        Some(serde_json::Value::String(s)) => vec![s.clone()],
+        Some(serde_json::Value::Array(values)) => values.iter().filter_map(|v| v.as_str().map(String::from)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Drop the commas before `}` and `]` that JSON does not allow
+fn strip_trailing_commas(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let (mut in_string, mut escaped) = (false, false);
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let rest = chars.clone().find(|c| !c.is_whitespace());
+            if matches!(rest, Some('}') | Some(']')) {
+                continue;
             }
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Parse a Yarn Berry (v2+) lockfile: YAML entries keyed by the descriptors they resolve
+///
+/// Berry records no tarball URLs, so they point at the configured registry. Its
+/// checksums cover Yarn's zip archives rather than npm tarballs and are not kept.
+fn parse_yarn_berry_lockfile(content: &str, registry: &RegistryConfig) -> VelocityResult<MigrationInfo> {
+    let document: serde_yaml::Mapping = serde_yaml::from_str(content)
+        .map_err(|e| VelocityError::migration(format!("Invalid yarn.lock: {}", e)))?;
+
+    let mut packages: BTreeMap<(String, String), MigratedPackage> = BTreeMap::new();
+    let mut warnings = Vec::new();
+
+    for (key, entry) in &document {
+        let Some(key) = key.as_str() else { continue };
+        if key == "__metadata" {
+            continue;
+        }
+
+        let field = |name: &str| entry.get(name).and_then(yaml_scalar);
+        let (Some(resolution), Some(version)) = (field("resolution"), field("version")) else {
+            warnings.push(format!("Skipped '{}': no resolution or version", key));
+            continue;
+        };
+        let Some((name, reference)) = split_descriptor(&resolution) else {
+            warnings.push(format!("Skipped '{}': cannot read resolution '{}'", key, resolution));
+            continue;
+        };
 
-            // Parse entry
-            let entry = line.trim().trim_end_matches(':');
-            // Handle quoted entries
-            let entry = entry.trim_matches('"');
-            
-            if let Some(at_idx) = entry.rfind('@') {
-                if at_idx > 0 {
-                    current_name = entry[..at_idx].to_string();
+        let resolved = match berry_source(reference) {
+            BerrySource::Registry { patch } => {
+                if let Some(patch) = patch {
+                    warnings.push(format!("{}@{} is patched by {}; the patch is not applied", name, version, patch));
                 }
+                registry_tarball(registry, name, &version)
+            }
+            BerrySource::Url(url) => url,
+            BerrySource::Workspace => continue,
+            BerrySource::Unsupported(protocol) => {
+                warnings.push(format!(
+                    "{} comes from a {} dependency and was not migrated; add it again with 'velocity add'",
+                    name, protocol
+                ));
+                continue;
+            }
+        };
+
+        let dependencies = yaml_map(entry.get("dependencies"));
+        let optional: BTreeSet<String> = yaml_map(entry.get("dependenciesMeta"))
+            .into_iter()
+            .filter(|(dep, _)| {
+                entry["dependenciesMeta"][dep.as_str()].get("optional").and_then(|v| v.as_bool()) == Some(true)
+            })
+            .map(|(dep, _)| dep)
+            .collect();
+        let (mut os, mut cpu) = (Vec::new(), Vec::new());
+        for condition in field("conditions").iter().flat_map(|c| c.split('&')) {
+            match condition.trim().split_once('=') {
+                Some(("os", value)) => os.push(value.to_string()),
+                Some(("cpu", value)) => cpu.push(value.to_string()),
+                _ => {}
             }
-            current_version.clear();
-            current_resolved.clear();
-            current_integrity.clear();
         }
 
-        let trimmed = line.trim();
+        let package = MigratedPackage {
+            name: name.to_string(),
+            alias_of: None,
+            version: version.clone(),
+            resolved,
+            integrity: String::new(),
+            dependencies: dependencies.iter().map(|(dep, spec)| format!("{}@{}", dep, berry_range(spec))).collect(),
+            peer_dependencies: yaml_map(entry.get("peerDependencies")).into_keys().collect(),
+            optional_dependencies: dependencies.keys().filter(|dep| optional.contains(*dep)).cloned().collect(),
+            os,
+            cpu,
+        };
 
-        if trimmed.starts_with("version ") {
-            current_version = trimmed
-                .trim_start_matches("version ")
-                .trim_matches('"')
-                .to_string();
+        // One entry serves every descriptor in its key, including `npm:` aliases
+        let names: BTreeSet<&str> = key
+            .split(',')
+            .filter_map(|descriptor| split_descriptor(descriptor.trim().trim_matches('"')))
+            .map(|(descriptor_name, _)| descriptor_name)
+            .collect();
+        for alias in names.iter().filter(|n| **n != name) {
+            packages.entry((alias.to_string(), version.clone())).or_insert_with(|| MigratedPackage {
+                name: alias.to_string(),
+                alias_of: Some(name.to_string()),
+                resolved: package.resolved.clone(),
+                dependencies: package.dependencies.clone(),
+                peer_dependencies: package.peer_dependencies.clone(),
+                optional_dependencies: package.optional_dependencies.clone(),
+                os: package.os.clone(),
+                cpu: package.cpu.clone(),
+                version: version.clone(),
+                integrity: String::new(),
+            });
+        }
+        // A patched entry and its original resolve to the same registry package
+        if names.contains(name) || names.is_empty() {
+            packages.entry((name.to_string(), version)).or_insert(package);
         }
+    }
+
+    Ok(MigrationInfo { packages: packages.into_values().collect(), warnings })
+}
+
+/// Where a Berry resolution's package comes from
+#[derive(Debug, PartialEq)]
+enum BerrySource {
+    /// The registry, optionally with a user patch applied on top
+    Registry { patch: Option<String> },
 
-        if trimmed.starts_with("resolved ") {
-            current_resolved = trimmed
-                .trim_start_matches("resolved ")
-                .trim_matches('"')
-                .to_string();
+    /// A tarball URL
+    Url(String),
+
+    /// A member of this workspace
+    Workspace,
+
+    /// A protocol velocity.lock cannot express
+    Unsupported(String),
+}
+
+/// Classify the reference of a Berry locator (the part after `name@`)
+fn berry_source(reference: &str) -> BerrySource {
+    if reference.starts_with("npm:") {
+        return BerrySource::Registry { patch: None };
+    }
+    if reference.starts_with("workspace:") {
+        return BerrySource::Workspace;
+    }
+    if let Some(patched) = reference.strip_prefix("patch:") {
+        // patch:<url-encoded locator>#<patch path>::<parameters>
+        let (locator, patch) = patched.split_once('#').unwrap_or((patched, ""));
+        let patch = patch.split("::").next().unwrap_or_default();
+        let locator = percent_decode(locator);
+        return match split_descriptor(&locator).map(|(_, reference)| berry_source(reference)) {
+            // Yarn's built-in compatibility patches (fsevents, resolve, typescript) only matter to Yarn
+            Some(BerrySource::Registry { .. }) => BerrySource::Registry {
+                patch: (!patch.contains("builtin<")).then(|| patch.to_string()),
+            },
+            Some(source) => source,
+            None => BerrySource::Unsupported("patch".to_string()),
+        };
+    }
+    if reference.starts_with("http://") || reference.starts_with("https://") {
+        return BerrySource::Url(reference.to_string());
+    }
+
+    let protocol = reference.split_once(':').map(|(protocol, _)| protocol).unwrap_or(reference);
+    BerrySource::Unsupported(protocol.to_string())
+}
+
+/// A Berry dependency range as package.json would write it: `npm:^1.0.0` becomes
+/// `^1.0.0`, patched ranges become the range they patch, and aliases stay `npm:name@range`
+fn berry_range(spec: &str) -> String {
+    if let Some(range) = spec.strip_prefix("npm:") {
+        if split_descriptor(range).is_none() {
+            return range.to_string();
+        }
+    }
+    if let Some(patched) = spec.strip_prefix("patch:") {
+        let descriptor = percent_decode(patched.split('#').next().unwrap_or_default());
+        if let Some((_, range)) = split_descriptor(&descriptor) {
+            return berry_range(range);
         }
+    }
+    spec.to_string()
+}
+
+/// Split `name@range` (or a locator) at the `@` after the name, which may be scoped
+fn split_descriptor(descriptor: &str) -> Option<(&str, &str)> {
+    let at = descriptor.get(1..)?.find('@')? + 1;
+    Some((&descriptor[..at], &descriptor[at + 1..]))
+}
 
-        if trimmed.starts_with("integrity ") {
-            current_integrity = trimmed
-                .trim_start_matches("integrity ")
-                .to_string();
+/// Decode the `%XX` escapes Berry uses inside patch locators
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
         }
     }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
 
-    // Last package
-    if !current_name.is_empty() && !current_version.is_empty() {
-        packages.push(MigratedPackage {
-            name: current_name,
-            version: current_version,
-            resolved: current_resolved,
-            integrity: current_integrity,
-            dependencies: Vec::new(),
-        });
+/// A YAML scalar as a string; Berry leaves many values unquoted
+fn yaml_scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
     }
+}
+
+/// The entries of a YAML mapping, with scalar values as strings
+fn yaml_map(value: Option<&serde_yaml::Value>) -> BTreeMap<String, String> {
+    value
+        .and_then(|v| v.as_mapping())
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| Some((yaml_scalar(key)?, yaml_scalar(value).unwrap_or_default())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BERRY_LOCKFILE: &str = r#"# This file is generated by running "yarn install" inside your project.
+# Manual changes might be lost - proceed with caution!
+
+__metadata:
+  version: 8
+  cacheKey: 10c0
+
+"@babel/code-frame@npm:^7.0.0, @babel/code-frame@npm:^7.22.13":
+  version: 7.22.13
+  resolution: "@babel/code-frame@npm:7.22.13"
+  dependencies:
+    "@babel/highlight": "npm:^7.22.13"
+    chalk: "npm:^2.4.2"
+  checksum: 10c0/f4cc8ae1000265677daf4845083b72f88d00d311adb1a93c94eb4b07bf0ed6828a81ae4ac43ee7d476775000b93a28a9
+  languageName: node
+  linkType: hard
+
+"app@workspace:.":
+  version: 0.0.0-use.local
+  resolution: "app@workspace:."
+  dependencies:
+    local-lib: "portal:../local-lib"
+    resolve: "patch:resolve@npm%3A^1.20.0#optional!builtin<compat/resolve>"
+    string-width-cjs: "npm:string-width@^4.2.0"
+  languageName: unknown
+  linkType: soft
+
+"chokidar@npm:^3.5.0":
+  version: 3.5.3
+  resolution: "chokidar@npm:3.5.3"
+  dependencies:
+    fsevents: "npm:~2.3.2"
+  dependenciesMeta:
+    fsevents:
+      optional: true
+  languageName: node
+  linkType: hard
+
+"fsevents@npm:~2.3.2":
+  version: 2.3.3
+  resolution: "fsevents@npm:2.3.3"
+  conditions: os=darwin
+  languageName: node
+  linkType: hard
+
+"local-lib@portal:../local-lib::locator=app%40workspace%3A.":
+  version: 0.0.0-use.local
+  resolution: "local-lib@portal:../local-lib::locator=app%40workspace%3A."
+  languageName: node
+  linkType: soft
+
+"resolve@npm:^1.20.0":
+  version: 1.22.8
+  resolution: "resolve@npm:1.22.8"
+  languageName: node
+  linkType: hard
+
+"resolve@patch:resolve@npm%3A^1.20.0#optional!builtin<compat/resolve>":
+  version: 1.22.8
+  resolution: "resolve@patch:resolve@npm%3A1.22.8#optional!builtin<compat/resolve>::version=1.22.8&hash=c3c19d"
+  languageName: node
+  linkType: hard
+
+"lodash@patch:lodash@npm%3A4.17.21#~/.yarn/patches/lodash-npm-4.17.21-6382451519.patch":
+  version: 4.17.21
+  resolution: "lodash@patch:lodash@npm%3A4.17.21#~/.yarn/patches/lodash-npm-4.17.21-6382451519.patch::version=4.17.21&hash=a1b2c3"
+  languageName: node
+  linkType: hard
+
+"string-width-cjs@npm:string-width@^4.2.0, string-width@npm:^4.1.0":
+  version: 4.2.3
+  resolution: "string-width@npm:4.2.3"
+  languageName: node
+  linkType: hard
+"#;
+
+    #[test]
+    fn test_parse_yarn_berry_lockfile() {
+        let registry = RegistryConfig::default();
+        let info = parse_yarn_berry_lockfile(BERRY_LOCKFILE, &registry).unwrap();
+        let find = |name: &str| info.packages.iter().find(|p| p.name == name).unwrap();
+
+        let names: Vec<&str> = info.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["@babel/code-frame", "chokidar", "fsevents", "lodash", "resolve", "string-width", "string-width-cjs"]
+        );
+
+        let code_frame = find("@babel/code-frame");
+        assert_eq!(code_frame.version, "7.22.13");
+        assert!(code_frame.resolved.ends_with("/@babel/code-frame/-/code-frame-7.22.13.tgz"));
+        assert_eq!(code_frame.dependencies, ["@babel/highlight@^7.22.13", "chalk@^2.4.2"]);
+        assert!(code_frame.integrity.is_empty());
+
+        assert_eq!(find("chokidar").optional_dependencies, ["fsevents"]);
+        assert_eq!(find("fsevents").os, ["darwin"]);
+        assert_eq!(find("string-width-cjs").alias_of.as_deref(), Some("string-width"));
+        assert_eq!(find("string-width-cjs").version, "4.2.3");
+
+        assert_eq!(info.warnings.len(), 2);
+        assert!(info.warnings.iter().any(|w| w.contains("lodash@4.17.21 is patched by ~/.yarn/patches/")));
+        assert!(info.warnings.iter().any(|w| w.starts_with("local-lib comes from a portal dependency")));
+    }
+
+    #[test]
+    fn test_berry_ranges() {
+        assert_eq!(berry_range("npm:^7.22.13"), "^7.22.13");
+        assert_eq!(berry_range("npm:string-width@^4.2.0"), "npm:string-width@^4.2.0");
+        assert_eq!(berry_range("patch:resolve@npm%3A^1.20.0#optional!builtin<compat/resolve>"), "^1.20.0");
+        assert_eq!(berry_range("workspace:^"), "workspace:^");
+        assert_eq!(berry_range("^2.0.0"), "^2.0.0");
+        assert_eq!(split_descriptor("@scope/pkg@npm:1.0.0"), Some(("@scope/pkg", "npm:1.0.0")));
+        assert_eq!(berry_source("git@github.com:x/y.git"), BerrySource::Unsupported("git@github.com".to_string()));
+    }
+
+    #[test]
+    fn test_parse_npm_lockfile() {
+        let content = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": {"name": "app", "dependencies": {"chokidar": "^3.5.0", "string-width-cjs": "npm:string-width@^4.2.0"}},
+                "node_modules/chokidar": {
+                    "version": "3.5.3",
+                    "resolved": "https://registry.npmjs.org/chokidar/-/chokidar-3.5.3.tgz",
+                    "integrity": "sha512-chokidar",
+                    "dependencies": {"anymatch": "~3.1.2"},
+                    "optionalDependencies": {"fsevents": "~2.3.2"}
+                },
+                "node_modules/chokidar/node_modules/anymatch": {"version": "3.1.3", "integrity": "sha512-anymatch"},
+                "node_modules/fsevents": {"version": "2.3.3", "optional": true, "os": ["darwin"]},
+                "node_modules/string-width-cjs": {"name": "string-width", "version": "4.2.3"},
+                "node_modules/local": {"resolved": "packages/local", "link": true},
+                "node_modules/git-dep": {"version": "1.0.0", "resolved": "git+ssh://git@github.com/x/y.git#abc"}
+            }
+        }"#;
+        let info = parse_npm_lockfile(content, &RegistryConfig::default()).unwrap();
+        let find = |name: &str| info.packages.iter().find(|p| p.name == name).unwrap();
+
+        assert_eq!(info.packages.len(), 4);
+        assert_eq!(find("chokidar").dependencies, ["anymatch@~3.1.2", "fsevents@~2.3.2"]);
+        assert_eq!(find("chokidar").optional_dependencies, ["fsevents"]);
+        assert_eq!(find("anymatch").version, "3.1.3");
+        assert!(find("anymatch").resolved.ends_with("/anymatch/-/anymatch-3.1.3.tgz"));
+        assert_eq!(find("fsevents").os, ["darwin"]);
+        assert_eq!(find("string-width-cjs").alias_of.as_deref(), Some("string-width"));
+        assert!(find("string-width-cjs").resolved.ends_with("/string-width/-/string-width-4.2.3.tgz"));
+        assert_eq!(info.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_pnpm_lockfile() {
+        let v9 = r#"lockfileVersion: '9.0'
 
-    Ok(MigrationInfo { packages })
+importers:
+  .:
+    dependencies:
+      react-dom:
+        specifier: ^18.2.0
+        version: 18.2.0(react@18.2.0)
+      string-width-cjs:
+        specifier: npm:string-width@^4.2.0
+        version: string-width@4.2.3
+
+packages:
+  loose-envify@1.4.0:
+    resolution: {integrity: sha512-loose}
+    hasBin: true
+  react-dom@18.2.0:
+    resolution: {integrity: sha512-dom}
+    peerDependencies:
+      react: ^18.2.0
+  react@18.2.0:
+    resolution: {integrity: sha512-react}
+  string-width@4.2.3:
+    resolution: {integrity: sha512-width}
+  local@file:vendor/local.tgz:
+    resolution: {integrity: sha512-local, tarball: file:vendor/local.tgz}
+    version: 1.0.0
+
+snapshots:
+  loose-envify@1.4.0: {}
+  react-dom@18.2.0(react@18.2.0):
+    dependencies:
+      loose-envify: 1.4.0
+      react: 18.2.0
+  react@18.2.0:
+    dependencies:
+      loose-envify: 1.4.0
+  string-width@4.2.3: {}
+  local@file:vendor/local.tgz: {}
+"#;
+        let info = parse_pnpm_lockfile(v9, &RegistryConfig::default()).unwrap();
+        let find = |name: &str| info.packages.iter().find(|p| p.name == name).unwrap();
+
+        let names: Vec<&str> = info.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["loose-envify", "react", "react-dom", "string-width", "string-width-cjs"]);
+        assert_eq!(find("react-dom").dependencies, ["loose-envify@1.4.0", "react@18.2.0"]);
+        assert_eq!(find("react-dom").peer_dependencies, ["react"]);
+        assert_eq!(find("react-dom").integrity, "sha512-dom");
+        assert_eq!(find("string-width-cjs").alias_of.as_deref(), Some("string-width"));
+        assert_eq!(info.warnings.len(), 1);
+
+        let v5 = r#"lockfileVersion: 5.4
+
+specifiers:
+  '@types/react': ^18.0.0
+
+dependencies:
+  '@types/react': 18.0.0
+
+packages:
+
+  /@types/react/18.0.0:
+    resolution: {integrity: sha512-types}
+    dependencies:
+      csstype: 3.1.0
+    dev: false
+
+  /csstype/3.1.0:
+    resolution: {integrity: sha512-csstype}
+    dev: false
+"#;
+        let info = parse_pnpm_lockfile(v5, &RegistryConfig::default()).unwrap();
+        assert_eq!(info.packages.len(), 2);
+        assert_eq!(info.packages[0].name, "@types/react");
+        assert_eq!(info.packages[0].dependencies, ["csstype@3.1.0"]);
+        assert_eq!(pnpm_reference("1.0.0_react@18.0.0", true).as_deref(), Some("1.0.0"));
+        assert_eq!(pnpm_reference("/string-width@4.2.3", false).as_deref(), Some("npm:string-width@4.2.3"));
+        assert_eq!(pnpm_reference("link:../lib", false), None);
+    }
+
+    #[test]
+    fn test_parse_yarn_lockfile() {
+        let content = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+"@babel/code-frame@^7.0.0", "@babel/code-frame@^7.10.4":
+  version "7.12.13"
+  resolved "https://registry.yarnpkg.com/@babel/code-frame/-/code-frame-7.12.13.tgz#dcfc826beef65e75c50e21d3837d7d95798dd658"
+  integrity sha512-frame
+  dependencies:
+    "@babel/highlight" "^7.10.4"
+
+chokidar@^3.5.0:
+  version "3.5.3"
+  resolved "https://registry.yarnpkg.com/chokidar/-/chokidar-3.5.3.tgz#1cf37c8707b932bd1af1ae22c0432e2acd1903bd"
+  integrity sha512-chokidar
+  dependencies:
+    anymatch "~3.1.2"
+  optionalDependencies:
+    fsevents "~2.3.2"
+
+"string-width-cjs@npm:string-width@^4.2.0", string-width@^4.1.0:
+  version "4.2.3"
+  resolved "https://registry.yarnpkg.com/string-width/-/string-width-4.2.3.tgz#269c7117d27b05ad2e536830a8ec895ef9c6d010"
+  integrity sha512-width
+"#;
+        let info = parse_yarn_lockfile(content, &RegistryConfig::default()).unwrap();
+        let find = |name: &str| info.packages.iter().find(|p| p.name == name).unwrap();
+
+        assert_eq!(info.packages.len(), 4);
+        assert_eq!(find("@babel/code-frame").version, "7.12.13");
+        assert_eq!(find("@babel/code-frame").dependencies, ["@babel/highlight@^7.10.4"]);
+        assert_eq!(
+            find("@babel/code-frame").resolved,
+            "https://registry.yarnpkg.com/@babel/code-frame/-/code-frame-7.12.13.tgz"
+        );
+        assert_eq!(find("chokidar").dependencies, ["anymatch@~3.1.2", "fsevents@~2.3.2"]);
+        assert_eq!(find("chokidar").optional_dependencies, ["fsevents"]);
+        assert_eq!(find("string-width-cjs").alias_of.as_deref(), Some("string-width"));
+        assert_eq!(find("string-width").alias_of, None);
+    }
+
+    #[test]
+    fn test_migration_drift() {
+        let lockfile = |packages: &[(&str, &str, &[&str])]| {
+            let mut lockfile = Lockfile::new();
+            for (name, version, deps) in packages {
+                lockfile.add_package(crate::core::lockfile::LockedPackage {
+                    name: name.to_string(),
+                    alias_of: None,
+                    version: version.to_string(),
+                    resolved: String::new(),
+                    integrity: String::new(),
+                    dependencies: deps.iter().map(|d| (*d).into()).collect(),
+                    peer_dependencies: vec![],
+                    optional_dependencies: vec![],
+                    has_scripts: false,
+                    approved_scripts: None,
+                    cpu: vec![],
+                    os: vec![],
+                    dev: false,
+                    provenance: None,
+                });
+            }
+            lockfile
+        };
+
+        let migrated = lockfile(&[("a", "1.0.0", &["b@^1.0.0"]), ("b", "1.0.0", &[]), ("old", "1.0.0", &[])]);
+        assert!(MigrationDrift::between(&migrated, &migrated).is_empty());
+
+        let fresh = lockfile(&[("a", "1.0.0", &["b@^1.0.0", "c@^2.0.0"]), ("b", "1.0.0", &[]), ("c", "2.0.0", &[])]);
+        assert_eq!(
+            MigrationDrift::between(&migrated, &fresh),
+            MigrationDrift {
+                missing: vec!["c@2.0.0".to_string()],
+                unused: vec!["old@1.0.0".to_string()],
+                edges: vec![("a@1.0.0".to_string(), vec!["c".to_string()], vec![])],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_bun_lockfile() {
+        let content = r#"{
+  "lockfileVersion": 1,
+  "workspaces": {
+    "": {
+      "name": "app",
+      "dependencies": {
+        "chokidar": "^3.5.0",
+        "string-width-cjs": "npm:string-width@^4.2.0",
+      },
+    },
+  },
+  "packages": {
+    "@babel/code-frame": ["@babel/code-frame@7.22.13", "", { "dependencies": { "chalk": "^2.4.2" } }, "sha512-frame"],
+    "chokidar": ["chokidar@3.5.3", "", { "dependencies": { "anymatch": "~3.1.2" }, "optionalDependencies": { "fsevents": "~2.3.2" } }, "sha512-chokidar"],
+    "chokidar/@types/node": ["@types/node@20.0.0", "https://npm.example.com/", {}, "sha512-node"],
+    "fsevents": ["fsevents@2.3.3", "", { "os": "darwin" }, "sha512-fsevents"],
+    "local": ["local@workspace:packages/local"],
+    "string-width-cjs": ["string-width@4.2.3", "", {}, "sha512-width"],
+    "tool": ["tool@github:user/tool#abc123", {}, "user-tool-abc123"],
+  }
+}
+"#;
+        let info = parse_bun_lockfile(content, &RegistryConfig::default()).unwrap();
+        let find = |name: &str| info.packages.iter().find(|p| p.name == name).unwrap();
+
+        assert_eq!(info.packages.len(), 5);
+        assert_eq!(find("@babel/code-frame").dependencies, ["chalk@^2.4.2"]);
+        assert!(find("@babel/code-frame").resolved.ends_with("/@babel/code-frame/-/code-frame-7.22.13.tgz"));
+        assert_eq!(find("chokidar").optional_dependencies, ["fsevents"]);
+        assert_eq!(find("chokidar").integrity, "sha512-chokidar");
+        assert_eq!(find("@types/node").resolved, "https://npm.example.com/@types/node/-/node-20.0.0.tgz");
+        assert_eq!(find("fsevents").os, ["darwin"]);
+        assert_eq!(find("string-width-cjs").alias_of.as_deref(), Some("string-width"));
+        assert_eq!(info.warnings.len(), 1);
+
+        assert_eq!(strip_trailing_commas(r#"{"a": [1, 2,], "b": "x,}",}"#), r#"{"a": [1, 2], "b": "x,}"}"#);
+    }
+
+    #[test]
+    fn test_parse_node_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, manifest: &str| {
+            let package = dir.path().join("node_modules").join(path);
+            std::fs::create_dir_all(&package).unwrap();
+            std::fs::write(package.join("package.json"), manifest).unwrap();
+        };
+        write("react", r#"{"name": "react", "version": "18.2.0", "dependencies": {"loose-envify": "^1.1.0"}}"#);
+        write("loose-envify", r#"{"name": "loose-envify", "version": "1.4.0"}"#);
+        write("@scope/alias", r#"{"name": "real", "version": "1.0.0", "os": ["linux"]}"#);
+        write("react/node_modules/loose-envify", r#"{"name": "loose-envify", "version": "1.3.0"}"#);
+
+        let info = parse_node_modules(dir.path(), &RegistryConfig::default()).unwrap();
+        let found: Vec<(&str, &str)> = info.packages.iter().map(|p| (p.name.as_str(), p.version.as_str())).collect();
+        assert_eq!(
+            found,
+            [("@scope/alias", "1.0.0"), ("loose-envify", "1.3.0"), ("loose-envify", "1.4.0"), ("react", "18.2.0")]
+        );
+        assert_eq!(info.packages[0].alias_of.as_deref(), Some("real"));
+        assert_eq!(info.packages[0].os, ["linux"]);
+        assert_eq!(info.packages[3].dependencies, ["loose-envify@^1.1.0"]);
+    }
 }
@@ -3,13 +3,35 @@
 pub mod add;
 pub mod audit;
 pub mod cache;
+pub mod check;
+pub mod completions;
+pub mod config;
 pub mod create;
+pub mod dlx;
 pub mod doctor;
+pub mod exec;
+pub mod export;
+pub mod hash;
+pub mod health;
+pub mod info;
 pub mod init;
 pub mod install;
+pub mod licenses;
+pub mod list;
+pub mod lockfile;
+pub mod login;
+pub mod logout;
+pub mod lsp_lite;
 pub mod migrate;
+pub mod outdated;
+pub mod pack;
+pub mod prune;
+pub mod publish;
 pub mod remove;
 pub mod run;
+pub mod search;
 pub mod update;
 pub mod upgrade;
+pub mod vendor;
+pub mod why;
 pub mod workspace;
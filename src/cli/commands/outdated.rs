@@ -0,0 +1,319 @@
+//! velocity outdated - Show dependencies with newer versions available
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{Engine, Lockfile, VelocityResult};
+use crate::registry::types::PackageMetadata;
+use crate::resolver::version::VersionConstraint;
+
+#[derive(Args)]
+pub struct OutdatedArgs {
+    /// Only check these packages
+    pub packages: Vec<String>,
+
+    /// Check the workspace root and every member
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Only check these workspace projects (name, path, directory or glob); implies --recursive
+    #[arg(short = 'F', long = "filter", value_name = "SELECTOR")]
+    pub filters: Vec<String>,
+}
+
+/// A dependency declared by one project
+struct Declared {
+    project: String,
+    name: String,
+    spec: String,
+    dev: bool,
+    current: Option<String>,
+}
+
+/// One outdated dependency, shared by every project that declares it at the same versions
+#[derive(Debug, Serialize)]
+struct Finding {
+    name: String,
+    current: Option<String>,
+    wanted: Option<String>,
+    latest: String,
+    projects: Vec<Attribution>,
+}
+
+/// A project declaring an outdated dependency
+#[derive(Debug, Serialize)]
+struct Attribution {
+    project: String,
+    spec: String,
+    dev: bool,
+}
+
+pub async fn execute(args: OutdatedArgs, json_output: bool) -> VelocityResult<()> {
+    let recursive = args.recursive || !args.filters.is_empty();
+    let root = if recursive {
+        project::current_workspace_root()?
+    } else {
+        project::current_project_root()?
+    };
+    let engine = Engine::new(&root).await?;
+    engine.ensure_initialized()?;
+
+    let projects = if recursive {
+        engine.select_projects(&args.filters)?
+    } else {
+        vec![(engine.project_dir.clone(), engine.package_json()?)]
+    };
+
+    // Workspace members depend on each other through links, not the registry
+    let members: HashSet<String> = match engine.workspace {
+        Some(ref workspace) => workspace.package_jsons()?.into_iter().map(|(_, pkg)| pkg.name).collect(),
+        None => HashSet::new(),
+    };
+
+    let mut lockfiles: HashMap<PathBuf, Option<Lockfile>> = HashMap::new();
+    let mut declared = Vec::new();
+    for (dir, pkg) in &projects {
+        let project = project_label(&engine.project_dir, dir, &pkg.name);
+        let lockfile_path = lockfile_for(&engine, dir);
+        if !lockfiles.contains_key(&lockfile_path) {
+            lockfiles.insert(lockfile_path.clone(), Lockfile::load(&lockfile_path)?);
+        }
+        let lockfile = lockfiles[&lockfile_path].as_ref();
+
+        let deps = pkg
+            .production_dependencies()
+            .into_iter()
+            .map(|dep| (dep, false))
+            .chain(pkg.dev_dependencies.clone().into_iter().map(|dep| (dep, true)));
+        for ((name, spec), dev) in deps {
+            if members.contains(&name) || (!args.packages.is_empty() && !args.packages.contains(&name)) {
+                continue;
+            }
+            // file:, link:, git and tarball specs have no registry versions to compare
+            let Ok(constraint) = VersionConstraint::parse(&spec) else {
+                continue;
+            };
+            let current = lockfile.and_then(|lockfile| {
+                lockfile
+                    .find_package_versions(&name)
+                    .into_iter()
+                    .filter_map(|locked| semver::Version::parse(&locked.version).ok())
+                    .filter(|version| constraint.matches(version))
+                    .max()
+                    .map(|version| version.to_string())
+            });
+            declared.push(Declared { project: project.clone(), name, spec, dev, current });
+        }
+    }
+
+    let names: Vec<String> = declared
+        .iter()
+        .map(|d| d.name.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    let progress = (!json_output).then(|| output::spinner(&format!("Checking {} packages...", names.len())));
+    let registry = &engine.registry;
+    let metadata: HashMap<String, PackageMetadata> = stream::iter(names)
+        .map(|name| async move {
+            let result = registry.get_package_metadata(&name).await;
+            (name, result)
+        })
+        .buffer_unordered(engine.config.network.metadata_concurrency.max(1))
+        .filter_map(|(name, result)| async move {
+            match result {
+                Ok(meta) => Some((name, meta)),
+                Err(e) => {
+                    tracing::debug!("No metadata for {}: {}", name, e);
+                    None
+                }
+            }
+        })
+        .collect()
+        .await;
+    if let Some(pb) = progress {
+        pb.finish_and_clear();
+    }
+
+    let mut unchecked: Vec<&str> = declared
+        .iter()
+        .map(|d| d.name.as_str())
+        .filter(|name| !metadata.contains_key(*name))
+        .collect();
+    unchecked.sort();
+    unchecked.dedup();
+    let unchecked: Vec<String> = unchecked.into_iter().map(str::to_string).collect();
+
+    let findings = collect_findings(declared, &metadata);
+
+    if json_output {
+        output::json(&serde_json::json!({ "outdated": findings, "unchecked": unchecked }))?;
+        return Ok(());
+    }
+
+    if !unchecked.is_empty() {
+        output::warning(&format!("Could not fetch registry metadata for: {}", unchecked.join(", ")));
+    }
+
+    if findings.is_empty() && unchecked.is_empty() {
+        output::success("All dependencies are up to date");
+        return Ok(());
+    }
+    if findings.is_empty() {
+        return Ok(());
+    }
+
+    // One group per project, plus one per set of projects sharing findings
+    let mut groups: BTreeMap<Vec<usize>, Vec<&Finding>> = BTreeMap::new();
+    let labels: Vec<String> = projects
+        .iter()
+        .map(|(dir, pkg)| project_label(&engine.project_dir, dir, &pkg.name))
+        .collect();
+    let order: HashMap<&str, usize> = labels.iter().enumerate().map(|(i, label)| (label.as_str(), i)).collect();
+    for finding in &findings {
+        let mut key: Vec<usize> = finding.projects.iter().map(|a| order[a.project.as_str()]).collect();
+        key.sort();
+        key.dedup();
+        groups.entry(key).or_default().push(finding);
+    }
+
+    let name_width = findings.iter().map(|f| f.name.len()).max().unwrap_or(0).max(7);
+    for (key, group) in groups {
+        if recursive {
+            let group_labels: Vec<&str> = key.iter().map(|&i| labels[i].as_str()).collect();
+            println!();
+            output::project_group(&group_labels);
+        }
+        output::table_header(&[&format!("{:<name_width$}", "Package"), "Current   ", "Wanted    ", "Latest"]);
+        for finding in group {
+            let dev = if finding.projects.iter().all(|a| a.dev) { " (dev)" } else { "" };
+            println!(
+                "{:<name_width$}  {:<10}  {:<10}  {}{}",
+                finding.name,
+                finding.current.as_deref().unwrap_or("missing"),
+                finding.wanted.as_deref().unwrap_or("-"),
+                output::good(&finding.latest),
+                output::muted(dev),
+            );
+        }
+    }
+
+    println!();
+    output::info(&format!("{} outdated dependencies", findings.len()));
+    Ok(())
+}
+
+/// What identical findings share: name, current, wanted and latest version
+type FindingKey = (String, Option<String>, Option<String>, String);
+
+/// Compare declared dependencies with the registry, merging identical findings across projects
+fn collect_findings(declared: Vec<Declared>, metadata: &HashMap<String, PackageMetadata>) -> Vec<Finding> {
+    let mut findings: BTreeMap<FindingKey, Vec<Attribution>> = BTreeMap::new();
+
+    for dep in declared {
+        let Some(meta) = metadata.get(&dep.name) else {
+            continue;
+        };
+        let Some(latest) = meta.dist_tags.get("latest") else {
+            continue;
+        };
+        let Ok(latest_version) = semver::Version::parse(latest) else {
+            continue;
+        };
+
+        let up_to_date = dep
+            .current
+            .as_deref()
+            .and_then(|current| semver::Version::parse(current).ok())
+            .map(|current| current >= latest_version)
+            .unwrap_or(false);
+        if up_to_date {
+            continue;
+        }
+
+        let wanted = VersionConstraint::parse(&dep.spec).ok().and_then(|constraint| {
+            meta.versions
+                .keys()
+                .filter_map(|v| semver::Version::parse(v).ok())
+                .filter(|v| v.pre.is_empty() && constraint.matches(v))
+                .max()
+                .map(|v| v.to_string())
+        });
+
+        findings
+            .entry((dep.name, dep.current, wanted, latest.clone()))
+            .or_default()
+            .push(Attribution { project: dep.project, spec: dep.spec, dev: dep.dev });
+    }
+
+    findings
+        .into_iter()
+        .map(|((name, current, wanted, latest), projects)| Finding { name, current, wanted, latest, projects })
+        .collect()
+}
+
+/// Name a project by its package name, or its path when it has none
+fn project_label(root: &Path, dir: &Path, name: &str) -> String {
+    if !name.is_empty() {
+        return name.to_string();
+    }
+    match dir.strip_prefix(root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative.display().to_string(),
+        _ => ".".to_string(),
+    }
+}
+
+/// Lockfile recording a project's versions: its own for workspaces without a
+/// shared lockfile, otherwise the root's
+fn lockfile_for(engine: &Engine, dir: &Path) -> PathBuf {
+    let shared = engine.workspace.as_ref().map(|w| w.shared_lockfile()).unwrap_or(true);
+    let own = engine.config.lockfile_path(dir);
+    if !shared && own.exists() {
+        own
+    } else {
+        engine.lockfile_path()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_findings_are_shared_across_projects() {
+        let metadata: PackageMetadata = serde_json::from_value(serde_json::json!({
+            "name": "lodash",
+            "dist-tags": { "latest": "4.17.21" },
+            "versions": {
+                "4.17.20": { "name": "lodash", "version": "4.17.20", "dist": { "tarball": "" } },
+                "4.17.21": { "name": "lodash", "version": "4.17.21", "dist": { "tarball": "" } },
+            },
+        }))
+        .unwrap();
+        let metadata = HashMap::from([("lodash".to_string(), metadata)]);
+
+        let declared = |project: &str, current: &str| Declared {
+            project: project.to_string(),
+            name: "lodash".to_string(),
+            spec: "~4.17.0".to_string(),
+            dev: false,
+            current: Some(current.to_string()),
+        };
+        let findings = collect_findings(
+            vec![declared("web", "4.17.20"), declared("api", "4.17.20"), declared("cli", "4.17.21")],
+            &metadata,
+        );
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].wanted.as_deref(), Some("4.17.21"));
+        let projects: Vec<&str> = findings[0].projects.iter().map(|a| a.project.as_str()).collect();
+        assert_eq!(projects, ["web", "api"]);
+    }
+}
@@ -0,0 +1,56 @@
+//! velocity pack - Build the tarball `velocity publish` would upload
+
+use std::path::PathBuf;
+
+use clap::Args;
+
+use super::publish::{print_tarball, publish_manifest, tarball_json};
+use crate::cli::output;
+use crate::core::pack;
+use crate::core::project;
+use crate::core::{VelocityError, VelocityResult};
+
+#[derive(Args)]
+pub struct PackArgs {
+    /// Directory to write the tarball to (default: the package directory)
+    #[arg(long, value_name = "DIR")]
+    pub pack_destination: Option<PathBuf>,
+
+    /// List the contents without writing the tarball
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+pub async fn execute(args: PackArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+    if !project_dir.join("package.json").is_file() {
+        return Err(VelocityError::PackageJsonNotFound(project_dir));
+    }
+
+    let manifest = publish_manifest(&project_dir).await?;
+    let tarball = pack::pack(&project_dir, &manifest)?;
+
+    let destination = args.pack_destination.unwrap_or_else(|| project_dir.clone());
+    let path = destination.join(tarball.filename());
+    if !args.dry_run {
+        std::fs::create_dir_all(&destination)?;
+        std::fs::write(&path, &tarball.data)?;
+    }
+
+    if json_output {
+        let mut json = tarball_json(&tarball);
+        json["path"] = serde_json::json!((!args.dry_run).then(|| path.display().to_string()));
+        output::json(&serde_json::json!([json]))?;
+        return Ok(());
+    }
+
+    print_tarball(&tarball);
+    println!();
+    if args.dry_run {
+        output::info(&format!("Dry run: {} was not written", tarball.filename()));
+    } else {
+        output::success(&format!("Wrote {}", path.display()));
+    }
+
+    Ok(())
+}
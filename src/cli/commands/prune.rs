@@ -0,0 +1,62 @@
+//! velocity prune - Remove node_modules entries the lockfile does not account for
+
+use clap::Args;
+
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{Engine, VelocityError, VelocityResult};
+
+#[derive(Args)]
+pub struct PruneArgs {
+    /// Only report the extraneous packages, without removing them
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+pub async fn execute(args: PruneArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+    let engine = Engine::new(&project_dir).await?;
+    engine.ensure_initialized()?;
+
+    let lockfile_path = engine.lockfile_path();
+    let lockfile = engine.lockfile()?.ok_or_else(|| {
+        VelocityError::other(format!(
+            "No lockfile found at {}. Run 'velocity install' first.",
+            lockfile_path.display()
+        ))
+    })?;
+
+    // The tree the lockfile describes, compared against what is on disk
+    let resolution = engine.resolver().resolve_from_lockfile(&lockfile)?;
+    let installer = engine.installer();
+    let extraneous = installer.extraneous(&resolution)?;
+    if !args.dry_run {
+        installer.remove_extraneous(&extraneous)?;
+    }
+
+    let paths: Vec<String> = extraneous
+        .iter()
+        .map(|dir| dir.strip_prefix(&project_dir).unwrap_or(dir).display().to_string())
+        .collect();
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "success": true,
+            "dry_run": args.dry_run,
+            "extraneous": paths,
+        }))?;
+    } else if paths.is_empty() {
+        output::success("node_modules matches the lockfile");
+    } else {
+        for path in &paths {
+            println!("  {} {}", output::bad("-"), path);
+        }
+        if args.dry_run {
+            output::info(&format!("{} extraneous package(s) would be removed (dry run)", paths.len()));
+        } else {
+            output::success(&format!("Removed {} extraneous package(s)", paths.len()));
+        }
+    }
+
+    Ok(())
+}
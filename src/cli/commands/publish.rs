@@ -0,0 +1,279 @@
+//! velocity publish - Pack the current package and upload it to its registry
+//!
+//! There is no `--provenance` like npm's. The registry only accepts a Sigstore
+//! bundle: a SLSA statement signed with a short-lived Fulcio certificate issued
+//! for the CI job's OIDC identity and logged in Rekor. Velocity has no Sigstore
+//! client, and an unsigned statement in `_attachments` is rejected, so the flag
+//! is refused with a pointer to `npm publish --provenance`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use base64::Engine as _;
+use clap::{Args, ValueEnum};
+
+use crate::cli::output;
+use crate::core::pack::{self, Tarball};
+use crate::core::project;
+use crate::core::{Config, Engine, VelocityError, VelocityResult};
+use crate::workspace::release;
+
+/// Dependency fields whose `workspace:` ranges are rewritten before packing
+const DEPENDENCY_FIELDS: &[&str] = &["dependencies", "devDependencies", "peerDependencies", "optionalDependencies"];
+
+#[derive(Args)]
+pub struct PublishArgs {
+    /// Dist-tag to point at the published version (default: publishConfig.tag, else latest)
+    #[arg(long)]
+    pub tag: Option<String>,
+
+    /// Who can install a scoped package (default: publishConfig.access, else the registry's default)
+    #[arg(long, value_enum)]
+    pub access: Option<Access>,
+
+    /// Pack and report what would be published without uploading
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// One-time password for accounts with two-factor authentication
+    #[arg(long)]
+    pub otp: Option<String>,
+
+    /// Not supported; accepted only to explain why (see the module docs)
+    #[arg(long, hide = true)]
+    pub provenance: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Access {
+    Public,
+    Restricted,
+}
+
+impl Access {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Access::Public => "public",
+            Access::Restricted => "restricted",
+        }
+    }
+}
+
+pub async fn execute(args: PublishArgs, json_output: bool) -> VelocityResult<()> {
+    if args.provenance {
+        return Err(VelocityError::other(
+            "Publishing with provenance needs a Sigstore-signed attestation, which velocity \
+             cannot produce; use `npm publish --provenance` in CI instead",
+        ));
+    }
+
+    let project_dir = project::current_project_root()?;
+    let config = Config::load(&config_dir(&project_dir))?;
+    let engine = Engine::with_config(&project_dir, config).await?;
+    engine.ensure_initialized()?;
+
+    let manifest = publish_manifest(&project_dir).await?;
+    if manifest.get("private").and_then(|p| p.as_bool()).unwrap_or(false) {
+        return Err(VelocityError::other("This package is private (\"private\": true in package.json) and cannot be published"));
+    }
+    let publish_config = |key: &str| {
+        manifest.get("publishConfig").and_then(|c| c.get(key)).and_then(|v| v.as_str()).map(str::to_string)
+    };
+
+    let tag = args.tag.clone().or_else(|| publish_config("tag")).unwrap_or_else(|| "latest".to_string());
+    if semver::VersionReq::parse(&tag).is_ok() {
+        return Err(VelocityError::other(format!("Tag '{}' looks like a version range; use a name such as 'next'", tag)));
+    }
+    let access = match args.access {
+        Some(access) => Some(access.as_str().to_string()),
+        None => publish_config("access"),
+    };
+    if let Some(ref access) = access {
+        if access != "public" && access != "restricted" {
+            return Err(VelocityError::config(format!("publishConfig.access must be 'public' or 'restricted', not '{}'", access)));
+        }
+    }
+
+    let tarball = pack::pack(&project_dir, &manifest)?;
+    let registry = engine.config.registry.registry_for(&tarball.name).trim_end_matches('/').to_string();
+    if !args.dry_run && engine.config.registry.auth_token_for(&tarball.name).is_none() {
+        return Err(VelocityError::config(format!(
+            "Not logged in to {}; run `velocity login --registry {}` first",
+            registry, registry
+        )));
+    }
+
+    let document = publish_document(&manifest, &tarball, &registry, &tag, access.as_deref());
+
+    if !args.dry_run {
+        let spinner = (!json_output).then(|| output::spinner(&format!("Publishing {}@{}...", tarball.name, tarball.version)));
+        let result = engine.registry.publish(&tarball.name, &document, args.otp.as_deref()).await;
+        if let Some(spinner) = spinner {
+            spinner.finish_and_clear();
+        }
+        result?;
+    }
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "success": true,
+            "dry_run": args.dry_run,
+            "registry": registry,
+            "tag": tag,
+            "access": access,
+            "package": tarball_json(&tarball),
+        }))?;
+        return Ok(());
+    }
+
+    print_tarball(&tarball);
+    println!("  {:<15}{}", "tag:", tag);
+    println!("  {:<15}{}", "access:", access.as_deref().unwrap_or("default"));
+    println!();
+    if args.dry_run {
+        output::info(&format!("Dry run: {}@{} was not published to {}", tarball.name, tarball.version, registry));
+    } else {
+        output::success(&format!("Published {} to {} with tag {}", output::package_version(&tarball.name, &tarball.version), registry, tag));
+    }
+
+    Ok(())
+}
+
+/// Where a package's registry settings come from: its own velocity.toml or
+/// .velocityrc, else the enclosing workspace's
+fn config_dir(project_dir: &Path) -> PathBuf {
+    let has_config = ["velocity.toml", ".velocityrc"].iter().any(|file| project_dir.join(file).is_file());
+    if has_config {
+        return project_dir.to_path_buf();
+    }
+    project::find_workspace_root(project_dir).unwrap_or_else(|| project_dir.to_path_buf())
+}
+
+/// package.json as it gets packed: `workspace:` ranges resolved against the
+/// members' versions, the commit it was built from recorded as `gitHead`, and
+/// paths normalized
+pub(crate) async fn publish_manifest(project_dir: &Path) -> VelocityResult<serde_json::Value> {
+    let content = std::fs::read_to_string(project_dir.join("package.json"))?;
+    let mut manifest: serde_json::Value = serde_json::from_str(&content)?;
+    let Some(object) = manifest.as_object_mut() else {
+        return Err(VelocityError::other("package.json is not a JSON object"));
+    };
+
+    let uses_workspace = DEPENDENCY_FIELDS.iter().any(|field| {
+        object.get(*field).and_then(|deps| deps.as_object()).is_some_and(|deps| {
+            deps.values().any(|spec| spec.as_str().is_some_and(|spec| spec.starts_with("workspace:")))
+        })
+    });
+    if uses_workspace {
+        let versions = member_versions(project_dir).await?;
+        for field in DEPENDENCY_FIELDS {
+            let Some(deps) = object.get_mut(*field).and_then(|deps| deps.as_object_mut()) else {
+                continue;
+            };
+            for (name, spec) in deps.iter_mut() {
+                let Some(range) = spec.as_str().filter(|s| s.starts_with("workspace:")) else {
+                    continue;
+                };
+                let version = versions.get(name).ok_or_else(|| {
+                    VelocityError::workspace(format!("{}@{} does not name a workspace package", name, range))
+                })?;
+                *spec = serde_json::Value::String(release::publish_range(range, version));
+            }
+        }
+    }
+
+    if let Some(head) = git_head(project_dir) {
+        object.insert("gitHead".to_string(), serde_json::Value::String(head));
+    }
+
+    pack::normalize_manifest(&mut manifest);
+    Ok(manifest)
+}
+
+/// Name -> version of every member of the workspace around `project_dir`
+async fn member_versions(project_dir: &Path) -> VelocityResult<HashMap<String, String>> {
+    let root = project::find_workspace_root(project_dir)
+        .ok_or_else(|| VelocityError::workspace("workspace: dependencies can only be published from inside a workspace"))?;
+    let engine = Engine::new(&root).await?;
+    let workspace = engine.workspace.as_ref().ok_or_else(|| VelocityError::workspace("Not in a workspace"))?;
+    Ok(workspace.package_jsons()?.into_iter().map(|(_, pkg)| (pkg.name, pkg.version)).collect())
+}
+
+/// Commit the package is built from, when it lives in a git repository
+fn git_head(dir: &Path) -> Option<String> {
+    let output = Command::new("git").args(["rev-parse", "HEAD"]).current_dir(dir).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Body of the npm publish API's PUT: the version manifest plus base64 attachments
+fn publish_document(
+    manifest: &serde_json::Value,
+    tarball: &Tarball,
+    registry: &str,
+    tag: &str,
+    access: Option<&str>,
+) -> serde_json::Value {
+    let base64 = base64::engine::general_purpose::STANDARD;
+    let unscoped = tarball.name.rsplit('/').next().unwrap_or(&tarball.name);
+    let tarball_url = format!("{}/{}/-/{}-{}.tgz", registry, tarball.name, unscoped, tarball.version);
+
+    let mut version = manifest.clone();
+    if let Some(object) = version.as_object_mut() {
+        object.insert("_id".to_string(), format!("{}@{}", tarball.name, tarball.version).into());
+        object.insert("dist".to_string(), serde_json::json!({
+            "integrity": tarball.integrity,
+            "tarball": tarball_url,
+            "fileCount": tarball.files.len(),
+            "unpackedSize": tarball.unpacked_size(),
+        }));
+    }
+
+    let mut attachments = serde_json::Map::new();
+    attachments.insert(format!("{}-{}.tgz", tarball.name, tarball.version), serde_json::json!({
+        "content_type": "application/octet-stream",
+        "data": base64.encode(&tarball.data),
+        "length": tarball.data.len(),
+    }));
+
+    serde_json::json!({
+        "_id": tarball.name,
+        "name": tarball.name,
+        "description": manifest.get("description").cloned().unwrap_or(serde_json::Value::Null),
+        "access": access,
+        "dist-tags": { tag: tarball.version },
+        "versions": { tarball.version.clone(): version },
+        "_attachments": attachments,
+    })
+}
+
+/// JSON description of a packed tarball used by `--json` output
+pub(crate) fn tarball_json(tarball: &Tarball) -> serde_json::Value {
+    serde_json::json!({
+        "name": tarball.name,
+        "version": tarball.version,
+        "filename": tarball.filename(),
+        "size": tarball.data.len(),
+        "unpacked_size": tarball.unpacked_size(),
+        "integrity": tarball.integrity,
+        "sha512": tarball.sha512_hex(),
+        "files": tarball.files.iter().map(|file| serde_json::json!({ "path": file.path, "size": file.size })).collect::<Vec<_>>(),
+    })
+}
+
+/// Print a packed tarball's contents and details
+pub(crate) fn print_tarball(tarball: &Tarball) {
+    println!("{}", output::strong(format!("📦 {}@{}", tarball.name, tarball.version)));
+    println!("{}", output::strong("Tarball contents"));
+    for file in &tarball.files {
+        println!("  {:>9}  {}", output::format_bytes(file.size), file.path);
+    }
+    println!("{}", output::strong("Tarball details"));
+    println!("  {:<15}{}", "name:", tarball.name);
+    println!("  {:<15}{}", "version:", tarball.version);
+    println!("  {:<15}{}", "filename:", tarball.filename());
+    println!("  {:<15}{}", "package size:", output::format_bytes(tarball.data.len() as u64));
+    println!("  {:<15}{}", "unpacked size:", output::format_bytes(tarball.unpacked_size()));
+    println!("  {:<15}{}", "integrity:", tarball.integrity);
+    println!("  {:<15}{}", "total files:", tarball.files.len());
+}
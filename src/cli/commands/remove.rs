@@ -1,12 +1,14 @@
 //! velocity remove - Remove packages
 
-use std::env;
-use std::path::PathBuf;
+use std::collections::BTreeSet;
+use std::path::Path;
 use std::time::Instant;
 use clap::Args;
 
 use crate::cli::output;
-use crate::core::{Engine, VelocityResult};
+use crate::core::project;
+use crate::core::{Engine, Lockfile, VelocityResult};
+use crate::resolver::ResolveOptions;
 
 #[derive(Args)]
 pub struct RemoveArgs {
@@ -14,19 +16,16 @@ pub struct RemoveArgs {
     #[arg(required = true)]
     pub packages: Vec<String>,
 
-    /// Project directory
-    #[arg(long, default_value = ".")]
-    pub cwd: PathBuf,
+    /// Keep lockfile entries that are no longer reachable from any package.json
+    #[arg(long)]
+    pub no_prune: bool,
+
 }
 
 pub async fn execute(args: RemoveArgs, json_output: bool) -> VelocityResult<()> {
     let start_time = Instant::now();
 
-    let project_dir = if args.cwd.is_absolute() {
-        args.cwd.clone()
-    } else {
-        env::current_dir()?.join(&args.cwd)
-    };
+    let project_dir = project::current_project_root()?;
 
     let engine = Engine::new(&project_dir).await?;
     engine.ensure_initialized()?;
@@ -70,30 +69,60 @@ pub async fn execute(args: RemoveArgs, json_output: bool) -> VelocityResult<()>
     };
 
     let deps = package_json.all_dependencies();
-    
-    if !deps.is_empty() {
-        let resolver = engine.resolver();
-        let resolution = resolver.resolve(&deps).await?;
+    let mut pruned = 0;
+    let existing = engine.lockfile()?;
+    let bin_dir = engine.node_modules_path().join(".bin");
+    let bins_before = bin_names(&bin_dir);
+
+    // What the remaining roots reach is resolved again; everything else is dropped
+    let lockfile = if !deps.is_empty() || engine.installs_workspace() {
+        // Keep the remaining dependencies at their locked versions
+        let resolver = engine
+            .resolver()
+            .with_options(ResolveOptions::default().with_lockfile(existing.as_ref()));
+        let resolution = if engine.installs_workspace() {
+            resolver.resolve_workspace(&deps).await?
+        } else {
+            resolver.resolve(&deps).await?
+        };
 
         let installer = engine.installer();
         installer.install(&resolution, false, false).await?;
-        installer.link(&resolution).await?;
+        match existing {
+            Some(ref previous) => installer.link_changes(&resolution, previous).await?,
+            None => installer.link(&resolution).await?,
+        }
 
         let mut lockfile = resolution.lockfile;
-        lockfile.save(&project_dir)?;
+        if let Some(ref previous) = existing {
+            lockfile.keep_script_approvals(previous);
+        }
+        lockfile.mark_dev(&engine.production_importers()?);
+        if !args.no_prune {
+            pruned = lockfile.prune(&project_dir, &engine.importers()?).total();
+        }
+        lockfile.save(&engine.lockfile_path())?;
+        Some(lockfile)
     } else {
         // Remove lockfile if no deps remain
-        let lockfile_path = project_dir.join("velocity.lock");
+        let lockfile_path = engine.lockfile_path();
         if lockfile_path.exists() {
             std::fs::remove_file(&lockfile_path)?;
         }
 
         // Clean node_modules
-        let node_modules = project_dir.join("node_modules");
+        let node_modules = engine.node_modules_path();
         if node_modules.exists() {
             std::fs::remove_dir_all(&node_modules)?;
         }
-    }
+        None
+    };
+
+    let orphaned = match existing {
+        Some(ref previous) => orphaned_packages(previous, lockfile.as_ref(), &removed_packages),
+        None => Vec::new(),
+    };
+    let stale_bins: Vec<String> = bins_before.difference(&bin_names(&bin_dir)).cloned().collect();
 
     if let Some(pb) = progress {
         pb.finish_and_clear();
@@ -105,11 +134,29 @@ pub async fn execute(args: RemoveArgs, json_output: bool) -> VelocityResult<()>
         output::json(&serde_json::json!({
             "success": true,
             "removed": removed_packages,
+            "pruned": pruned,
+            "orphaned": orphaned,
+            "stale_bins": stale_bins,
             "duration_ms": duration.as_millis()
         }))?;
     } else {
         for name in &removed_packages {
-            output::success(&format!("Removed {}", console::style(name).cyan()));
+            output::success(&format!("Removed {}", output::highlight(name)));
+        }
+
+        if !orphaned.is_empty() {
+            output::info(&format!(
+                "Cleaned up {} orphaned package(s): {}",
+                orphaned.len(),
+                orphaned.join(", ")
+            ));
+        }
+        if !stale_bins.is_empty() {
+            output::info(&format!("Removed stale binaries: {}", stale_bins.join(", ")));
+        }
+
+        if pruned > 0 {
+            output::info(&format!("Pruned {} unreachable lockfile entries", pruned));
         }
 
         output::info(&format!(
@@ -120,3 +167,71 @@ pub async fn execute(args: RemoveArgs, json_output: bool) -> VelocityResult<()>
 
     Ok(())
 }
+
+/// Locked `name@version`s nothing reaches anymore, besides the removed packages themselves
+///
+/// These are the transitive dependencies only the removed packages needed; `current`
+/// is the lockfile written afterwards (`None` when it was deleted).
+fn orphaned_packages(previous: &Lockfile, current: Option<&Lockfile>, removed: &[String]) -> Vec<String> {
+    let mut orphaned: Vec<String> = previous
+        .packages
+        .iter()
+        .filter(|p| !removed.contains(&p.name))
+        .filter(|p| current.and_then(|lockfile| lockfile.find_package(&p.name, &p.version)).is_none())
+        .map(|p| format!("{}@{}", p.name, p.version))
+        .collect();
+    orphaned.sort();
+    orphaned
+}
+
+/// Entries of node_modules/.bin
+fn bin_names(bin_dir: &Path) -> BTreeSet<String> {
+    std::fs::read_dir(bin_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::lockfile::LockedPackage;
+
+    fn locked(name: &str, version: &str) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            alias_of: None,
+            version: version.to_string(),
+            resolved: format!("https://example.com/{}-{}.tgz", name, version),
+            integrity: "sha512-abc".to_string(),
+            dependencies: vec![],
+            peer_dependencies: vec![],
+            optional_dependencies: vec![],
+            has_scripts: false,
+            approved_scripts: None,
+            cpu: vec![],
+            os: vec![],
+            dev: false,
+            provenance: None,
+        }
+    }
+
+    #[test]
+    fn test_orphaned_packages() {
+        let mut previous = Lockfile::new();
+        for (name, version) in [("express", "4.18.2"), ("accepts", "1.3.8"), ("ms", "2.0.0"), ("ms", "2.1.3"), ("debug", "4.3.4")] {
+            previous.add_package(locked(name, version));
+        }
+        let mut current = Lockfile::new();
+        current.add_package(locked("debug", "4.3.4"));
+        current.add_package(locked("ms", "2.1.3"));
+
+        let removed = vec!["express".to_string()];
+        assert_eq!(orphaned_packages(&previous, Some(&current), &removed), ["accepts@1.3.8", "ms@2.0.0"]);
+        assert_eq!(orphaned_packages(&previous, None, &removed).len(), 4);
+    }
+}
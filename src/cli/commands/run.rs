@@ -1,26 +1,33 @@
 //! velocity run - Run scripts
 
+use std::collections::HashMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use clap::Args;
 use tokio::process::Command;
 
 use crate::cli::output;
+use crate::core::project;
 use crate::core::{Engine, VelocityResult, VelocityError};
+use crate::security::sandbox::NpmEnv;
 
 #[derive(Args)]
 pub struct RunArgs {
     /// Script name to run
     pub script: Option<String>,
 
-    /// Arguments to pass to the script
-    #[arg(trailing_var_arg = true)]
+    /// Arguments to pass to the script (after `--` if they look like velocity options)
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     pub args: Vec<String>,
 
-    /// Project directory
-    #[arg(long, default_value = ".")]
-    pub cwd: PathBuf,
+    /// Succeed without doing anything when the script is not defined
+    #[arg(long)]
+    pub if_present: bool,
+
+    /// Run the script of a workspace member (package name or path) from the root
+    #[arg(short = 'F', long, value_name = "MEMBER")]
+    pub filter: Option<String>,
 
     /// List available scripts
     #[arg(short, long)]
@@ -28,16 +35,29 @@ pub struct RunArgs {
 }
 
 pub async fn execute(args: RunArgs, json_output: bool) -> VelocityResult<()> {
-    let project_dir = if args.cwd.is_absolute() {
-        args.cwd.clone()
-    } else {
-        env::current_dir()?.join(&args.cwd)
-    };
+    let (project_dir, package_json, engine) = match args.filter {
+        Some(ref member) => {
+            let root = project::current_workspace_root()?;
+            let engine = Engine::new(&root).await?;
+            engine.ensure_initialized()?;
 
-    let engine = Engine::new(&project_dir).await?;
-    engine.ensure_initialized()?;
+            let workspace = engine.workspace.as_ref().ok_or_else(|| {
+                VelocityError::workspace("--filter can only be used inside a workspace")
+            })?;
+            let (dir, package_json) = workspace
+                .find_member(member)?
+                .ok_or_else(|| VelocityError::workspace(format!("No workspace package named '{}'", member)))?;
+            (dir, package_json, engine)
+        }
+        None => {
+            let dir = project::current_project_root()?;
+            let engine = Engine::new(&dir).await?;
+            engine.ensure_initialized()?;
 
-    let package_json = engine.package_json()?;
+            let package_json = engine.package_json()?;
+            (dir, package_json, engine)
+        }
+    };
 
     // List scripts
     if args.list || args.script.is_none() {
@@ -53,8 +73,8 @@ pub async fn execute(args: RunArgs, json_output: bool) -> VelocityResult<()> {
                 for (name, command) in &package_json.scripts {
                     println!(
                         "  {} → {}",
-                        console::style(name).cyan().bold(),
-                        console::style(command).dim()
+                        output::highlight(name).bold(),
+                        output::muted(command)
                     );
                 }
             }
@@ -65,72 +85,160 @@ pub async fn execute(args: RunArgs, json_output: bool) -> VelocityResult<()> {
     let script_name = args.script.unwrap();
 
     // Find the script
-    let script_command = package_json.scripts.get(&script_name)
-        .ok_or_else(|| VelocityError::other(format!(
+    let Some(script_command) = package_json.scripts.get(&script_name) else {
+        if args.if_present {
+            if json_output {
+                output::json(&serde_json::json!({
+                    "script": script_name,
+                    "package": package_json.name,
+                    "skipped": true
+                }))?;
+            }
+            return Ok(());
+        }
+        return Err(VelocityError::other(format!(
             "Script '{}' not found. Available scripts: {}",
             script_name,
             package_json.scripts.keys().cloned().collect::<Vec<_>>().join(", ")
-        )))?;
+        )));
+    };
 
-    if !json_output {
-        output::info(&format!("Running script '{}'...", script_name));
-        println!("{} {}", console::style("$").dim(), console::style(script_command).dim());
-        println!();
-    }
+    // Add node_modules/.bin of the package (and its workspace root) to PATH
+    let new_path = env::join_paths(bin_paths(&project_dir))
+        .map_err(|e| VelocityError::other(format!("Invalid PATH: {}", e)))?;
 
-    // Build the command
-    let shell = get_shell();
-    let shell_arg = get_shell_arg();
-
-    // Add node_modules/.bin to PATH
-    let node_modules_bin = project_dir.join("node_modules").join(".bin");
-    let path_env = env::var("PATH").unwrap_or_default();
-    let new_path = format!(
-        "{}{}{}",
-        node_modules_bin.display(),
-        if cfg!(windows) { ";" } else { ":" },
-        path_env
-    );
-
-    // Build command with args
-    let full_command = if args.args.is_empty() {
-        script_command.clone()
-    } else {
-        format!("{} {}", script_command, args.args.join(" "))
-    };
+    // The variables npm sets, so scripts written for npm run unchanged
+    let manifest_path = project_dir.join("package.json");
+    let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&manifest_path)?)?;
+    let npm_env = NpmEnv::new()
+        .with_package(&manifest_path, &manifest)
+        .with_config(&engine.config.registry.url, engine.cache.cache_dir());
 
-    // Execute
-    let status = Command::new(&shell)
-        .arg(&shell_arg)
-        .arg(&full_command)
-        .current_dir(&project_dir)
-        .env("PATH", &new_path)
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()
-        .await?;
+    // pre<script> and post<script> run around it as in npm; only the script itself gets the arguments
+    let mut steps = Vec::new();
+    for (name, command) in lifecycle(&package_json.scripts, &script_name, script_command) {
+        let full_command = if name == script_name && !args.args.is_empty() {
+            let quoted: Vec<String> = args.args.iter().map(|arg| quote_arg(arg)).collect();
+            format!("{} {}", command, quoted.join(" "))
+        } else {
+            command.to_string()
+        };
 
-    if json_output {
-        output::json(&serde_json::json!({
-            "script": script_name,
-            "command": script_command,
+        if !json_output {
+            if args.filter.is_some() {
+                output::info(&format!("Running script '{}' in {}...", name, package_json.name));
+            } else {
+                output::info(&format!("Running script '{}'...", name));
+            }
+            println!("{} {}", output::muted("$"), output::muted(&full_command));
+            println!();
+        }
+
+        let status = Command::new(get_shell())
+            .arg(get_shell_arg())
+            .arg(&full_command)
+            .current_dir(&project_dir)
+            .envs(npm_env.for_script(&name, command))
+            .env("PATH", &new_path)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .await?;
+
+        steps.push(serde_json::json!({
+            "script": name,
+            "command": full_command,
             "success": status.success(),
             "exit_code": status.code()
-        }))?;
+        }));
+        if !status.success() {
+            if json_output {
+                report_steps(&script_name, &package_json.name, script_command, &steps, false)?;
+            }
+            return Err(VelocityError::ScriptFailed {
+                package: package_json.name,
+                script: name,
+            });
+        }
     }
 
-    if !status.success() {
-        let exit_code = status.code().unwrap_or(1);
-        return Err(VelocityError::ScriptFailed {
-            package: package_json.name,
-            script: script_name,
-        });
+    if json_output {
+        report_steps(&script_name, &package_json.name, script_command, &steps, true)?;
     }
 
     Ok(())
 }
 
+/// Print the `--json` result of a script run and its hooks
+fn report_steps(
+    script: &str,
+    package: &str,
+    command: &str,
+    steps: &[serde_json::Value],
+    success: bool,
+) -> VelocityResult<()> {
+    let exit_code = steps.last().and_then(|step| step.get("exit_code").cloned());
+    output::json(&serde_json::json!({
+        "script": script,
+        "package": package,
+        "command": command,
+        "success": success,
+        "exit_code": exit_code,
+        "steps": steps
+    }))?;
+    Ok(())
+}
+
+/// The scripts `velocity run <name>` executes, in order: pre<name>, <name>, post<name>
+fn lifecycle<'a>(
+    scripts: &'a HashMap<String, String>,
+    name: &str,
+    command: &'a str,
+) -> Vec<(String, &'a str)> {
+    let pre = format!("pre{}", name);
+    let post = format!("post{}", name);
+    let mut steps = Vec::new();
+    if let Some(command) = scripts.get(&pre) {
+        steps.push((pre, command.as_str()));
+    }
+    steps.push((name.to_string(), command));
+    if let Some(command) = scripts.get(&post) {
+        steps.push((post, command.as_str()));
+    }
+    steps
+}
+
+/// Quote an argument for the script's shell when it holds anything the shell would interpret
+fn quote_arg(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c));
+    if plain {
+        arg.to_string()
+    } else if cfg!(windows) {
+        format!("\"{}\"", arg.replace('"', "\"\""))
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// PATH entries for a script: the package's .bin, then the workspace root's .bin, then PATH
+pub(crate) fn bin_paths(package_dir: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![package_dir.join("node_modules").join(".bin")];
+
+    if let Some(root) = project::find_workspace_root(package_dir) {
+        if root != package_dir {
+            paths.push(root.join("node_modules").join(".bin"));
+        }
+    }
+
+    if let Some(path_env) = env::var_os("PATH") {
+        paths.extend(env::split_paths(&path_env));
+    }
+
+    paths
+}
+
 /// Get the shell to use for running scripts
 fn get_shell() -> String {
     if cfg!(windows) {
@@ -148,3 +256,32 @@ fn get_shell_arg() -> String {
         "-c".to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle_runs_hooks_around_script() {
+        let scripts = HashMap::from([
+            ("prebuild".to_string(), "clean".to_string()),
+            ("build".to_string(), "tsc".to_string()),
+            ("postbuild".to_string(), "bundle".to_string()),
+            ("test".to_string(), "jest".to_string()),
+        ]);
+        let names = |name: &str| -> Vec<String> {
+            lifecycle(&scripts, name, &scripts[name]).into_iter().map(|(step, _)| step).collect()
+        };
+        assert_eq!(names("build"), ["prebuild", "build", "postbuild"]);
+        assert_eq!(names("test"), ["test"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_quote_arg() {
+        assert_eq!(quote_arg("--watch"), "--watch");
+        assert_eq!(quote_arg("src/*.ts"), "'src/*.ts'");
+        assert_eq!(quote_arg("it's"), "'it'\\''s'");
+        assert_eq!(quote_arg(""), "''");
+    }
+}
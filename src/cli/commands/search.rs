@@ -0,0 +1,295 @@
+//! velocity search - Search the registry
+
+use std::io::IsTerminal;
+
+use clap::{Args, ValueEnum};
+
+use crate::cli::commands::add::{self, AddArgs};
+use crate::cli::output;
+use crate::core::config::RegistryConfig;
+use crate::core::project;
+use crate::core::{Engine, VelocityError, VelocityResult};
+use crate::registry::search::SearchObject;
+
+/// Largest page the registry serves
+const PAGE_SIZE: usize = 250;
+
+/// Upper bound on hits fetched by --all
+const MAX_RESULTS: usize = 10_000;
+
+#[derive(Args)]
+pub struct SearchArgs {
+    /// Search terms
+    #[arg(required = true)]
+    pub query: Vec<String>,
+
+    /// Maximum results to show (default 20, or every result with --all)
+    #[arg(short, long)]
+    pub limit: Option<usize>,
+
+    /// Fetch every page of results instead of the first
+    #[arg(long)]
+    pub all: bool,
+
+    /// Order of results; sorting covers the fetched results only
+    #[arg(long, value_enum, default_value_t = SearchSort::Relevance)]
+    pub sort: SearchSort,
+
+    /// Only packages in this scope (e.g. @types)
+    #[arg(long)]
+    pub scope: Option<String>,
+
+    /// Only packages with at least this many weekly downloads
+    #[arg(long, value_name = "N")]
+    pub min_downloads: Option<u64>,
+
+    /// Registry to search (default: the --scope's registry, else registry.url)
+    #[arg(long, value_name = "URL")]
+    pub registry: Option<String>,
+
+    /// Pick packages from the results and add them to the project
+    #[arg(short, long)]
+    pub interactive: bool,
+
+    /// With --interactive, add the picked packages as dev dependencies
+    #[arg(short = 'D', long, requires = "interactive")]
+    pub dev: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SearchSort {
+    /// Registry ranking
+    Relevance,
+    /// Weekly downloads, most first
+    Downloads,
+    /// Publish date of the latest version, newest first
+    Recent,
+    /// Package name
+    Name,
+}
+
+pub async fn execute(args: SearchArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root().unwrap_or_else(|_| std::path::PathBuf::from("."));
+    let engine = Engine::new(&project_dir).await?;
+
+    if args.interactive && (json_output || !std::io::stdin().is_terminal()) {
+        return Err(VelocityError::config("--interactive needs a terminal and cannot be combined with --json"));
+    }
+
+    let query = args.query.join(" ");
+    let limit = args.limit.unwrap_or(if args.all { MAX_RESULTS } else { 20 });
+    let scope = args.scope.as_deref().map(|s| s.trim_start_matches('@').to_string());
+    let registry = search_registry(args.registry.as_deref(), scope.as_deref(), &engine.config.registry);
+
+    let progress = if !json_output {
+        Some(output::spinner(&format!("Searching for '{}'...", query)))
+    } else {
+        None
+    };
+
+    // Filters run as hits stream in, so only the kept ones are held in memory
+    let mut hits: Vec<SearchObject> = Vec::new();
+    let mut fetched = 0;
+    loop {
+        let size = if args.all { PAGE_SIZE } else { limit.clamp(1, PAGE_SIZE) };
+        let count = engine.registry.search_page(&registry, &query, fetched, size, |hit| {
+            if keep(&hit, scope.as_deref(), args.min_downloads) {
+                hits.push(hit);
+            }
+        }).await?;
+        fetched += count;
+
+        if let Some(ref pb) = progress {
+            pb.set_message(format!("Searching for '{}'... {} results", query, fetched));
+        }
+
+        let enough = !args.all && hits.len() >= limit;
+        if count < size || enough || fetched >= MAX_RESULTS {
+            break;
+        }
+    }
+
+    if let Some(pb) = progress {
+        pb.finish_and_clear();
+    }
+
+    sort_hits(&mut hits, args.sort);
+    hits.truncate(limit);
+
+    if json_output {
+        output::json(&hits.iter().map(|hit| serde_json::json!({
+            "name": hit.package.name,
+            "version": hit.package.version,
+            "description": hit.package.description,
+            "date": hit.package.date,
+            "keywords": hit.package.keywords,
+            "weekly_downloads": hit.downloads.as_ref().map(|d| d.weekly),
+            "monthly_downloads": hit.downloads.as_ref().map(|d| d.monthly),
+            "score": hit.score.overall,
+        })).collect::<Vec<_>>())?;
+        return Ok(());
+    }
+
+    if hits.is_empty() {
+        output::info(&format!("No packages found for '{}'", query));
+        return Ok(());
+    }
+
+    for hit in &hits {
+        let mut meta = Vec::new();
+        if let Some(ref downloads) = hit.downloads {
+            meta.push(format!("{} weekly", downloads.weekly));
+        }
+        if let Some(date) = hit.package.date.as_deref().and_then(|d| d.get(..10)) {
+            meta.push(date.to_string());
+        }
+        meta.push(format!("score {:.2}", hit.score.overall));
+
+        println!(
+            "{} {}",
+            output::package_version(&hit.package.name, &hit.package.version),
+            output::muted(meta.join(", "))
+        );
+        if !hit.package.description.is_empty() {
+            println!("  {}", truncate(&hit.package.description, 100));
+        }
+    }
+
+    println!();
+    output::info(&format!("{} of {} fetched results shown", hits.len(), fetched));
+
+    if args.interactive {
+        return add_picked(&hits, args.dev).await;
+    }
+
+    Ok(())
+}
+
+/// Let the user pick results and add them to the current project
+async fn add_picked(hits: &[SearchObject], dev: bool) -> VelocityResult<()> {
+    let width = hits.iter().map(|hit| hit.package.name.len()).max().unwrap_or(0);
+    let items: Vec<String> = hits
+        .iter()
+        .map(|hit| {
+            format!(
+                "{:<width$}  {}",
+                hit.package.name,
+                output::muted(truncate(&hit.package.description, 60)),
+                width = width
+            )
+        })
+        .collect();
+    let picked = dialoguer::MultiSelect::new()
+        .with_prompt("Select packages to add (space to toggle, enter to confirm)")
+        .items(&items)
+        .interact()?;
+    if picked.is_empty() {
+        return Ok(());
+    }
+
+    println!();
+    add::execute(
+        AddArgs {
+            packages: picked.into_iter().map(|i| hits[i].package.name.clone()).collect(),
+            dev,
+            peer: false,
+            optional: false,
+            workspace: None,
+            exact: false,
+            save_prefix: None,
+            registry: true,
+            no_prune: false,
+            force: false,
+            ignore_cooldown: false,
+        },
+        false,
+    )
+    .await
+}
+
+/// Registry to search: the requested one, else the scope's, else the default
+fn search_registry(requested: Option<&str>, scope: Option<&str>, config: &RegistryConfig) -> String {
+    match (requested, scope) {
+        (Some(registry), _) => registry.to_string(),
+        (None, Some(scope)) => config.registry_for(&format!("@{}/_", scope)).to_string(),
+        (None, None) => config.url.clone(),
+    }
+}
+
+/// Apply the scope and download filters
+fn keep(hit: &SearchObject, scope: Option<&str>, min_downloads: Option<u64>) -> bool {
+    if let Some(scope) = scope {
+        if hit.package.scope() != Some(scope) {
+            return false;
+        }
+    }
+    if let Some(min) = min_downloads {
+        if hit.downloads.as_ref().map(|d| d.weekly).unwrap_or(0) < min {
+            return false;
+        }
+    }
+    true
+}
+
+/// Order hits in place; relevance keeps the registry's order
+fn sort_hits(hits: &mut [SearchObject], sort: SearchSort) {
+    match sort {
+        SearchSort::Relevance => {}
+        SearchSort::Downloads => {
+            hits.sort_by_key(|h| std::cmp::Reverse(h.downloads.as_ref().map(|d| d.weekly).unwrap_or(0)))
+        }
+        // RFC 3339 timestamps sort chronologically as strings
+        SearchSort::Recent => hits.sort_by(|a, b| b.package.date.cmp(&a.package.date)),
+        SearchSort::Name => hits.sort_by(|a, b| a.package.name.cmp(&b.package.name)),
+    }
+}
+
+/// Shorten text to at most `max` characters
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max.saturating_sub(1)).collect();
+    format!("{}…", cut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_registry() {
+        let mut config = RegistryConfig::default();
+        config.scopes.insert("@corp".to_string(), "https://npm.corp.example".to_string());
+
+        assert_eq!(search_registry(None, None, &config), "https://registry.npmjs.org");
+        assert_eq!(search_registry(None, Some("corp"), &config), "https://npm.corp.example");
+        assert_eq!(search_registry(None, Some("other"), &config), "https://registry.npmjs.org");
+        assert_eq!(
+            search_registry(Some("https://mirror.example"), Some("corp"), &config),
+            "https://mirror.example"
+        );
+    }
+
+    #[test]
+    fn test_keep_and_sort_by_downloads() {
+        let hit = |name: &str, weekly: u64| -> SearchObject {
+            serde_json::from_value(serde_json::json!({
+                "package": {"name": name, "version": "1.0.0"},
+                "score": {"final": 0.5},
+                "downloads": {"weekly": weekly}
+            }))
+            .unwrap()
+        };
+        let mut hits = vec![hit("@corp/a", 10), hit("b", 500), hit("@corp/c", 90)];
+
+        assert_eq!(hits[0].score.overall, 0.5);
+        assert!(keep(&hits[0], Some("corp"), None));
+        assert!(!keep(&hits[1], Some("corp"), None));
+        assert!(!keep(&hits[0], None, Some(50)));
+
+        sort_hits(&mut hits, SearchSort::Downloads);
+        let names: Vec<&str> = hits.iter().map(|h| h.package.name.as_str()).collect();
+        assert_eq!(names, ["b", "@corp/c", "@corp/a"]);
+    }
+}
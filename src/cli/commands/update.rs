@@ -1,12 +1,14 @@
 //! velocity update - Update packages
 
-use std::env;
-use std::path::PathBuf;
+use std::io::IsTerminal;
 use std::time::Instant;
 use clap::Args;
 
 use crate::cli::output;
-use crate::core::{Engine, VelocityResult};
+use crate::core::config::SavePrefix;
+use crate::core::project;
+use crate::core::{Engine, Lockfile, VelocityError, VelocityResult};
+use crate::resolver::{ForceResolve, ResolveOptions};
 
 #[derive(Args)]
 pub struct UpdateArgs {
@@ -17,29 +19,61 @@ pub struct UpdateArgs {
     #[arg(long)]
     pub latest: bool,
 
-    /// Project directory
-    #[arg(long, default_value = ".")]
-    pub cwd: PathBuf,
 
     /// Dry run - show what would be updated
     #[arg(long)]
     pub dry_run: bool,
+
+    /// Pick the updates to apply from a list grouped by patch, minor and major
+    #[arg(short, long, conflicts_with_all = ["latest", "dry_run"])]
+    pub interactive: bool,
+
+    /// Range operator to save updated versions with: ^, ~ or exact (default: keep the
+    /// current one, or install.save_prefix in velocity.toml with --latest)
+    #[arg(long, value_name = "PREFIX")]
+    pub save_prefix: Option<SavePrefix>,
+
+    /// Ignore cached metadata and re-resolve every package fresh from the registry
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Re-resolve the given packages (all if none given) fresh from the registry
+    #[arg(long, num_args = 0.., value_delimiter = ',', value_name = "PACKAGES")]
+    pub force_resolve: Option<Vec<String>>,
+
+    /// Override the metadata cache TTL for this run, in seconds
+    #[arg(long, value_name = "SECONDS")]
+    pub metadata_ttl: Option<u64>,
+
+    /// Allow versions published within security.release_cooldown_hours
+    #[arg(long)]
+    pub ignore_cooldown: bool,
+
+    /// Keep lockfile entries that are no longer reachable from any package.json
+    #[arg(long)]
+    pub no_prune: bool,
 }
 
 pub async fn execute(args: UpdateArgs, json_output: bool) -> VelocityResult<()> {
     let start_time = Instant::now();
 
-    let project_dir = if args.cwd.is_absolute() {
-        args.cwd.clone()
-    } else {
-        env::current_dir()?.join(&args.cwd)
-    };
+    let project_dir = project::current_project_root()?;
 
     let engine = Engine::new(&project_dir).await?;
     engine.ensure_initialized()?;
 
     let mut package_json = engine.package_json()?;
-    let existing_lockfile = engine.lockfile()?;
+    let previous = engine.lockfile()?;
+
+    if args.interactive && (json_output || !std::io::stdin().is_terminal()) {
+        return Err(VelocityError::config("--interactive needs a terminal and cannot be combined with --json"));
+    }
+
+    let force = if args.force {
+        ForceResolve::All
+    } else {
+        ForceResolve::from_flag(args.force_resolve.clone())
+    };
 
     if !json_output {
         if args.packages.is_empty() {
@@ -55,6 +89,7 @@ pub async fn execute(args: UpdateArgs, json_output: bool) -> VelocityResult<()>
         None
     };
 
+    let cooldown = if args.ignore_cooldown { None } else { engine.security.release_cooldown() };
     let mut updates = Vec::new();
 
     // Get packages to update
@@ -71,7 +106,14 @@ pub async fn execute(args: UpdateArgs, json_output: bool) -> VelocityResult<()>
             .or_else(|| package_json.optional_dependencies.get(name));
 
         if let Some(current) = current_version {
-            let metadata = engine.registry.get_package_metadata(name).await?;
+            let mut metadata = match (force.includes(name), args.metadata_ttl) {
+                (true, _) => engine.registry.get_package_metadata_with_ttl(name, 0).await?,
+                (false, Some(ttl)) => engine.registry.get_package_metadata_with_ttl(name, ttl).await?,
+                (false, None) => engine.registry.get_package_metadata(name).await?,
+            };
+            if let Some(ref cooldown) = cooldown {
+                cooldown.apply(&mut metadata, &[]);
+            }
             let latest = metadata.dist_tags.get("latest").cloned().unwrap_or_default();
 
             // Check if update is available
@@ -81,7 +123,7 @@ pub async fn execute(args: UpdateArgs, json_output: bool) -> VelocityResult<()>
 
                 if args.latest {
                     // Update to latest
-                    let new_version = format!("^{}", latest);
+                    let new_version = args.save_prefix.unwrap_or(engine.config.install.save_prefix).apply(&latest);
                     if package_json.dependencies.contains_key(name) {
                         package_json.dependencies.insert(name.clone(), new_version);
                     } else if package_json.dev_dependencies.contains_key(name) {
@@ -111,6 +153,30 @@ pub async fn execute(args: UpdateArgs, json_output: bool) -> VelocityResult<()>
         return Ok(());
     }
 
+    // Only the picked packages move; everything else stays at its locked version
+    if args.interactive {
+        updates = select_updates(updates, previous.as_ref())?;
+        if updates.is_empty() {
+            output::info("No updates selected");
+            return Ok(());
+        }
+        for (name, from, to) in &updates {
+            let spec = match args.save_prefix {
+                Some(prefix) => prefix.apply(to),
+                None => bump_spec(from, to, engine.config.install.save_prefix),
+            };
+            for deps in [
+                &mut package_json.dependencies,
+                &mut package_json.dev_dependencies,
+                &mut package_json.optional_dependencies,
+            ] {
+                if let Some(current) = deps.get_mut(name) {
+                    *current = spec.clone();
+                }
+            }
+        }
+    }
+
     if args.dry_run {
         if json_output {
             output::json(&serde_json::json!({
@@ -127,9 +193,9 @@ pub async fn execute(args: UpdateArgs, json_output: bool) -> VelocityResult<()>
             for (name, from, to) in &updates {
                 println!(
                     "  {} {} → {}",
-                    console::style(name).cyan(),
-                    console::style(from).red(),
-                    console::style(to).green()
+                    output::highlight(name),
+                    output::bad(from),
+                    output::good(to)
                 );
             }
         }
@@ -147,15 +213,36 @@ pub async fn execute(args: UpdateArgs, json_output: bool) -> VelocityResult<()>
 
     // Reinstall
     let deps = package_json.all_dependencies();
-    let resolver = engine.resolver();
+    let options = ResolveOptions {
+        force,
+        metadata_ttl: args.metadata_ttl,
+        ..Default::default()
+    };
+    let options = match args.interactive {
+        true => options.with_lockfile(previous.as_ref()),
+        false => options,
+    };
+    let resolver = engine.resolver().with_options(options).with_cooldown(cooldown);
     let resolution = resolver.resolve(&deps).await?;
 
     let installer = engine.installer();
     installer.install(&resolution, false, false).await?;
-    installer.link(&resolution).await?;
+    match previous {
+        Some(ref previous) => installer.link_changes(&resolution, previous).await?,
+        None => installer.link(&resolution).await?,
+    }
 
     let mut lockfile = resolution.lockfile;
-    lockfile.save(&project_dir)?;
+    if let Some(ref previous) = previous {
+        lockfile.keep_script_approvals(previous);
+    }
+    lockfile.mark_dev(&engine.production_importers()?);
+    let pruned = if args.no_prune {
+        0
+    } else {
+        lockfile.prune(&project_dir, &engine.importers()?).total()
+    };
+    lockfile.save(&engine.lockfile_path())?;
 
     if let Some(pb) = progress {
         pb.finish_and_clear();
@@ -171,6 +258,7 @@ pub async fn execute(args: UpdateArgs, json_output: bool) -> VelocityResult<()>
                 "from": from,
                 "to": to
             })).collect::<Vec<_>>(),
+            "pruned": pruned,
             "duration_ms": duration.as_millis()
         }))?;
     } else {
@@ -178,11 +266,14 @@ pub async fn execute(args: UpdateArgs, json_output: bool) -> VelocityResult<()>
         for (name, from, to) in &updates {
             println!(
                 "  {} {} → {}",
-                console::style(name).cyan(),
-                console::style(from).red(),
-                console::style(to).green()
+                output::highlight(name),
+                output::bad(from),
+                output::good(to)
             );
         }
+        if pruned > 0 {
+            output::info(&format!("Pruned {} unreachable lockfile entries", pruned));
+        }
         output::info(&format!(
             "Completed in {}",
             output::format_duration(duration.as_millis())
@@ -192,6 +283,100 @@ pub async fn execute(args: UpdateArgs, json_output: bool) -> VelocityResult<()>
     Ok(())
 }
 
+/// How far an update moves a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum UpdateKind {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl UpdateKind {
+    /// Classify a move between two versions; anything unparsable is treated as major.
+    /// Below 1.0.0 a minor bump is breaking, as caret ranges treat it
+    fn between(from: &str, to: &str) -> Self {
+        let (Ok(from), Ok(to)) = (semver::Version::parse(from), semver::Version::parse(to)) else {
+            return UpdateKind::Major;
+        };
+        if from.major != to.major || (from.major == 0 && from.minor != to.minor) {
+            UpdateKind::Major
+        } else if from.minor != to.minor {
+            UpdateKind::Minor
+        } else {
+            UpdateKind::Patch
+        }
+    }
+
+    fn label(self) -> console::StyledObject<&'static str> {
+        match self {
+            UpdateKind::Patch => output::good("patch"),
+            UpdateKind::Minor => output::caution("minor"),
+            UpdateKind::Major => output::bad("major"),
+        }
+    }
+}
+
+/// Let the user pick which updates to apply, grouped patch, minor, then major.
+/// Packages already installed at the latest version are left out
+fn select_updates(
+    updates: Vec<(String, String, String)>,
+    lockfile: Option<&Lockfile>,
+) -> VelocityResult<Vec<(String, String, String)>> {
+    let mut candidates: Vec<(UpdateKind, (String, String, String))> = updates
+        .into_iter()
+        .filter_map(|update| {
+            let (name, spec, latest) = &update;
+            let installed = lockfile
+                .and_then(|lockfile| {
+                    lockfile
+                        .find_package_versions(name)
+                        .into_iter()
+                        .filter_map(|locked| semver::Version::parse(&locked.version).ok())
+                        .max()
+                        .map(|version| version.to_string())
+                })
+                .unwrap_or_else(|| extract_version(spec));
+            let kind = UpdateKind::between(&installed, latest);
+            (installed != *latest).then_some((kind, update))
+        })
+        .collect();
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+    candidates.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| (a.1).0.cmp(&(b.1).0)));
+
+    let width = candidates.iter().map(|(_, (name, _, _))| name.len()).max().unwrap_or(0);
+    let items: Vec<String> = candidates
+        .iter()
+        .map(|(kind, (name, from, to))| {
+            format!(
+                "{}  {:<width$}  {} → {}",
+                kind.label(),
+                name,
+                output::muted(from),
+                output::good(to),
+                width = width
+            )
+        })
+        .collect();
+    let picked = dialoguer::MultiSelect::new()
+        .with_prompt("Select updates to apply (space to toggle, enter to confirm)")
+        .items(&items)
+        .interact()?;
+
+    Ok(picked.into_iter().map(|i| candidates[i].1.clone()).collect())
+}
+
+/// The new spec for an update, keeping the range operator of the old one (~1.2.0 -> ~1.3.0)
+/// and falling back to the save prefix for anything more complex
+fn bump_spec(spec: &str, version: &str, save_prefix: SavePrefix) -> String {
+    match spec.chars().next() {
+        Some(prefix @ ('^' | '~')) => format!("{}{}", prefix, version),
+        Some(c) if c.is_ascii_digit() => version.to_string(),
+        _ => save_prefix.apply(version),
+    }
+}
+
 /// Extract the actual version from a constraint (^1.0.0 -> 1.0.0)
 fn extract_version(constraint: &str) -> String {
     constraint
@@ -202,3 +387,23 @@ fn extract_version(constraint: &str) -> String {
         .trim_start_matches('<')
         .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_kind_and_spec() {
+        assert_eq!(UpdateKind::between("1.2.3", "1.2.9"), UpdateKind::Patch);
+        assert_eq!(UpdateKind::between("1.2.3", "1.4.0"), UpdateKind::Minor);
+        assert_eq!(UpdateKind::between("1.2.3", "2.0.0"), UpdateKind::Major);
+        assert_eq!(UpdateKind::between("0.2.3", "0.3.0"), UpdateKind::Major);
+        assert_eq!(UpdateKind::between("latest", "2.0.0"), UpdateKind::Major);
+
+        assert_eq!(bump_spec("~1.2.0", "1.3.0", SavePrefix::Caret), "~1.3.0");
+        assert_eq!(bump_spec("^1.2.0", "2.0.0", SavePrefix::Tilde), "^2.0.0");
+        assert_eq!(bump_spec("1.2.0", "1.3.0", SavePrefix::Caret), "1.3.0");
+        assert_eq!(bump_spec(">=1.2.0", "1.3.0", SavePrefix::Caret), "^1.3.0");
+        assert_eq!(bump_spec(">=1.2.0", "1.3.0", SavePrefix::Tilde), "~1.3.0");
+    }
+}
@@ -1,12 +1,25 @@
 //! velocity upgrade - Self-update Velocity
 
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
 use clap::Args;
+use directories::ProjectDirs;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
+use super::login::load_config;
 use crate::cli::output;
-use crate::core::{VelocityResult, VelocityError};
+use crate::core::config::{ReleaseChannel, UpgradeConfig};
+use crate::core::{VelocityError, VelocityResult};
+use crate::registry::http::{self, OptimizedHttpClient};
 
 const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
-const RELEASES_URL: &str = "https://api.github.com/repos/nicholaspalmer/velocity/releases/latest";
+const RELEASES_URL: &str = "https://api.github.com/repos/nicholaspalmer/velocity/releases?per_page=100";
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+const SIGNATURE_ASSET: &str = "SHA256SUMS.minisig";
+const BINARY_NAME: &str = if cfg!(windows) { "velocity.exe" } else { "velocity" };
 
 #[derive(Args)]
 pub struct UpgradeArgs {
@@ -17,57 +30,81 @@ pub struct UpgradeArgs {
     /// Force upgrade even if on latest version
     #[arg(short, long)]
     pub force: bool,
+
+    /// Release channel to follow: stable, beta or nightly (default: upgrade.channel in the user config)
+    #[arg(long)]
+    pub channel: Option<ReleaseChannel>,
+
+    /// List the versions available on the channel
+    #[arg(long, conflicts_with_all = ["check", "force", "rollback"])]
+    pub list: bool,
+
+    /// Restore the binary replaced by the last upgrade
+    #[arg(long, conflicts_with_all = ["check", "force", "channel"])]
+    pub rollback: bool,
+
+    /// Install this exact release, newer or older than the current one
+    #[arg(long = "version", value_name = "VERSION", conflicts_with_all = ["list", "rollback", "channel"])]
+    pub version: Option<String>,
+}
+
+/// A published release
+#[derive(Debug, Clone)]
+struct Release {
+    version: semver::Version,
+    channel: ReleaseChannel,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+    /// "sha256:<hex>", published by GitHub for newer uploads
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiRelease {
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
 }
 
 pub async fn execute(args: UpgradeArgs, json_output: bool) -> VelocityResult<()> {
-    if !json_output {
-        output::info(&format!("Current version: v{}", CURRENT_VERSION));
+    let upgrade_config = UpgradeConfig::load_user()?;
+    remove_replaced_exe();
+
+    if args.rollback {
+        return rollback(json_output);
     }
 
-    let progress = if !json_output {
-        Some(output::spinner("Checking for updates..."))
-    } else {
-        None
-    };
+    let channel = args.channel.unwrap_or(upgrade_config.channel);
+    let current = semver::Version::parse(CURRENT_VERSION)
+        .map_err(|e| VelocityError::other(format!("Invalid current version: {}", e)))?;
 
-    // Check for latest version
-    let latest_version = check_latest_version().await;
+    if !json_output {
+        output::info(&format!("Current version: v{} ({} channel)", CURRENT_VERSION, channel.as_str()));
+    }
 
+    let progress = (!json_output).then(|| output::spinner("Checking for updates..."));
+    let client = upgrade_client()?;
+    let releases = fetch_releases(&client).await;
     if let Some(pb) = progress {
         pb.finish_and_clear();
     }
 
-    match latest_version {
-        Ok(latest) => {
-            let is_newer = is_version_newer(&latest, CURRENT_VERSION);
-
-            if json_output {
-                output::json(&serde_json::json!({
-                    "current_version": CURRENT_VERSION,
-                    "latest_version": latest,
-                    "update_available": is_newer,
-                    "check_only": args.check
-                }))?;
-            } else if is_newer {
-                output::info(&format!("New version available: v{}", latest));
-
-                if args.check {
-                    println!();
-                    output::info("Run 'velocity upgrade' to update");
-                } else {
-                    println!();
-                    perform_upgrade(&latest, json_output).await?;
-                }
-            } else {
-                output::success("You're already on the latest version!");
-
-                if args.force && !args.check {
-                    println!();
-                    output::info("Force reinstalling...");
-                    perform_upgrade(&latest, json_output).await?;
-                }
-            }
-        }
+    let releases = match releases {
+        Ok(releases) => releases,
         Err(e) => {
             if json_output {
                 output::json(&serde_json::json!({
@@ -81,22 +118,107 @@ pub async fn execute(args: UpgradeArgs, json_output: bool) -> VelocityResult<()>
                 output::info("You can manually download the latest version from:");
                 println!("  https://github.com/nicholaspalmer/velocity/releases");
             }
+            return Ok(());
+        }
+    };
+
+    let available = on_channel(&releases, channel);
+
+    if args.list {
+        if json_output {
+            let versions: Vec<_> = available
+                .iter()
+                .map(|r| serde_json::json!({ "version": r.version.to_string(), "channel": r.channel.as_str() }))
+                .collect();
+            output::json(&serde_json::json!({
+                "channel": channel.as_str(),
+                "current_version": CURRENT_VERSION,
+                "versions": versions,
+            }))?;
+        } else if available.is_empty() {
+            output::info(&format!("No releases on the {} channel", channel.as_str()));
+        } else {
+            for release in &available {
+                let marker = if release.version == current { " (current)" } else { "" };
+                println!(
+                    "  v{:<24} {}{}",
+                    release.version,
+                    output::muted(release.channel.as_str()),
+                    output::good(marker)
+                );
+            }
         }
+        return Ok(());
+    }
+
+    let target = match args.version {
+        Some(ref version) => Some(pinned_release(&releases, version)?),
+        None => available.first().copied(),
+    };
+    let Some(target) = target else {
+        return Err(VelocityError::other(format!("No releases found on the {} channel", channel.as_str())));
+    };
+    let is_newer = target.version > current;
+    let wanted = if args.version.is_some() { target.version != current } else { is_newer };
+    let install = !args.check && (wanted || args.force);
+
+    if !json_output {
+        if !wanted {
+            if args.version.is_some() {
+                output::success(&format!("Already on v{}", current));
+            } else {
+                output::success("You're already on the latest version!");
+            }
+        } else if args.version.is_some() {
+            output::info(&format!("Requested version: v{}", target.version));
+        } else {
+            output::info(&format!("New version available: v{}", target.version));
+        }
+        if wanted && args.check {
+            println!();
+            match args.version {
+                Some(_) => output::info(&format!("Run 'velocity upgrade --version {}' to install it", target.version)),
+                None => output::info("Run 'velocity upgrade' to update"),
+            }
+        }
+    }
+
+    let mut upgraded = false;
+    if install {
+        if !json_output {
+            println!();
+            if !is_newer {
+                output::info("Force reinstalling...");
+            }
+        }
+        perform_upgrade(&client, target, &upgrade_config, json_output).await?;
+        upgraded = true;
+    }
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "current_version": CURRENT_VERSION,
+            "latest_version": available.first().map(|r| r.version.to_string()),
+            "target_version": target.version.to_string(),
+            "channel": channel.as_str(),
+            "update_available": is_newer,
+            "check_only": args.check,
+            "upgraded": upgraded,
+        }))?;
     }
 
     Ok(())
 }
 
-async fn check_latest_version() -> VelocityResult<String> {
-    let client = reqwest::Client::new();
+fn upgrade_client() -> VelocityResult<OptimizedHttpClient> {
+    let config = load_config()?;
+    OptimizedHttpClient::new(&config.network, Default::default())
+}
 
-    let response = client
-        .get(RELEASES_URL)
-        .header("User-Agent", format!("velocity/{}", CURRENT_VERSION))
-        .timeout(std::time::Duration::from_secs(10))
-        .send()
-        .await
-        .map_err(|e| VelocityError::Network(e.to_string()))?;
+/// Published releases, newest first; drafts and non-semver tags are skipped
+async fn fetch_releases(client: &OptimizedHttpClient) -> VelocityResult<Vec<Release>> {
+    let request = client.client().get(RELEASES_URL).header("Accept", "application/vnd.github+json");
+    let response = client.send(request).await.map_err(http::send_error)?;
 
     if !response.status().is_success() {
         return Err(VelocityError::Network(format!(
@@ -105,44 +227,36 @@ async fn check_latest_version() -> VelocityResult<String> {
         )));
     }
 
-    let release: serde_json::Value = response.json().await
-        .map_err(|e| VelocityError::Network(e.to_string()))?;
-
-    let tag_name = release
-        .get("tag_name")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| VelocityError::Network("Invalid release format".to_string()))?;
-
-    // Remove 'v' prefix if present
-    let version = tag_name.trim_start_matches('v').to_string();
-
-    Ok(version)
+    let releases: Vec<ApiRelease> = response.json().await.map_err(|e| VelocityError::Network(e.to_string()))?;
+    let mut releases: Vec<Release> = releases
+        .into_iter()
+        .filter(|release| !release.draft)
+        .filter_map(|release| {
+            let version = semver::Version::parse(release.tag_name.trim_start_matches('v')).ok()?;
+            Some(Release { channel: ReleaseChannel::of(&version), version, assets: release.assets })
+        })
+        .collect();
+    releases.sort_by(|a, b| b.version.cmp(&a.version));
+    Ok(releases)
 }
 
-fn is_version_newer(latest: &str, current: &str) -> bool {
-    let parse_version = |v: &str| -> (u32, u32, u32) {
-        let parts: Vec<&str> = v.split('.').collect();
-        (
-            parts.first().and_then(|s| s.parse().ok()).unwrap_or(0),
-            parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(0),
-            parts.get(2).and_then(|s| s.parse().ok()).unwrap_or(0),
-        )
-    };
-
-    let latest_v = parse_version(latest);
-    let current_v = parse_version(current);
-
-    latest_v > current_v
+/// Releases a channel offers: its own plus every more stable channel's
+fn on_channel(releases: &[Release], channel: ReleaseChannel) -> Vec<&Release> {
+    releases.iter().filter(|release| release.channel <= channel).collect()
 }
 
-async fn perform_upgrade(version: &str, json_output: bool) -> VelocityResult<()> {
-    let progress = if !json_output {
-        Some(output::spinner("Downloading new version..."))
-    } else {
-        None
-    };
+/// The release `--version` asks for, whatever its channel
+fn pinned_release<'a>(releases: &'a [Release], version: &str) -> VelocityResult<&'a Release> {
+    let version = semver::Version::parse(version.trim_start_matches('v'))
+        .map_err(|e| VelocityError::other(format!("Invalid version '{}': {}", version, e)))?;
+    releases
+        .iter()
+        .find(|release| release.version == version)
+        .ok_or_else(|| VelocityError::other(format!("Release v{} not found", version)))
+}
 
-    // Determine platform
+/// Release archive name for this platform
+fn archive_name() -> String {
     let (os, ext) = if cfg!(target_os = "windows") {
         ("windows", "zip")
     } else if cfg!(target_os = "macos") {
@@ -151,42 +265,344 @@ async fn perform_upgrade(version: &str, json_output: bool) -> VelocityResult<()>
         ("linux", "tar.gz")
     };
 
-    let arch = if cfg!(target_arch = "x86_64") {
-        "x86_64"
-    } else if cfg!(target_arch = "aarch64") {
-        "aarch64"
-    } else {
-        "x86_64" // fallback
+    let arch = if cfg!(target_arch = "aarch64") { "aarch64" } else { "x86_64" };
+
+    format!("velocity-{}-{}.{}", os, arch, ext)
+}
+
+/// Download, verify and swap in a release
+async fn perform_upgrade(
+    client: &OptimizedHttpClient,
+    release: &Release,
+    config: &UpgradeConfig,
+    json_output: bool,
+) -> VelocityResult<()> {
+    let name = archive_name();
+    let asset = release.asset(&name).ok_or_else(|| {
+        VelocityError::other(format!("Release v{} has no {} archive", release.version, name))
+    })?;
+
+    let progress = (!json_output).then(|| output::spinner(&format!("Downloading v{}...", release.version)));
+    let archive = download(client, &asset.browser_download_url).await;
+    let checksums = match release.asset(CHECKSUMS_ASSET) {
+        Some(sums) => Some(download(client, &sums.browser_download_url).await),
+        None => None,
+    };
+    let signature = match release.asset(SIGNATURE_ASSET) {
+        Some(sig) => Some(download(client, &sig.browser_download_url).await?),
+        None => None,
     };
+    if let Some(pb) = progress {
+        pb.finish_and_clear();
+    }
+    let archive = archive?;
+    let checksums = checksums.transpose()?.ok_or_else(|| {
+        VelocityError::other(format!("Release v{} publishes no {}; refusing to install it", release.version, CHECKSUMS_ASSET))
+    })?;
+
+    let actual = hex::encode(Sha256::digest(&archive));
+    let checksums = String::from_utf8_lossy(&checksums).into_owned();
+    let expected = parse_checksums(&checksums).remove(&name).ok_or_else(|| {
+        VelocityError::other(format!("{} does not list {}", CHECKSUMS_ASSET, name))
+    })?;
+    if !actual.eq_ignore_ascii_case(&expected) {
+        return Err(VelocityError::other(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            name, expected, actual
+        )));
+    }
+    if let Some(digest) = asset.digest.as_deref().and_then(|d| d.strip_prefix("sha256:")) {
+        if !actual.eq_ignore_ascii_case(digest) {
+            return Err(VelocityError::other(format!(
+                "Checksum mismatch for {}: GitHub reports {}, got {}",
+                name, digest, actual
+            )));
+        }
+    }
 
-    let download_url = format!(
-        "https://github.com/nicholaspalmer/velocity/releases/download/v{}/velocity-{}-{}.{}",
-        version, os, arch, ext
-    );
+    verify_signature(&checksums, signature.as_deref(), config, json_output)?;
 
-    if let Some(ref pb) = progress {
-        pb.set_message(format!("Downloading from {}", download_url));
+    let binary = extract_binary(&name, &archive)?;
+    let exe = current_exe()?;
+    backup_current(&exe)?;
+    replace_exe(&exe, &binary)?;
+
+    if !json_output {
+        let older = semver::Version::parse(CURRENT_VERSION).is_ok_and(|current| release.version < current);
+        let verb = if older { "Downgraded" } else { "Upgraded" };
+        output::success(&format!("{} to v{} (checksum verified)", verb, release.version));
+        output::info("Run 'velocity upgrade --rollback' to restore the previous version");
     }
+    Ok(())
+}
 
-    // In a real implementation, we would:
-    // 1. Download the binary
-    // 2. Verify checksum
-    // 3. Replace the current executable
-    // 4. Handle permissions
+async fn download(client: &OptimizedHttpClient, url: &str) -> VelocityResult<Vec<u8>> {
+    let response = client.send(client.client().get(url)).await.map_err(http::send_error)?;
+    if !response.status().is_success() {
+        return Err(VelocityError::Network(format!("{} returned status {}", url, response.status())));
+    }
+    let bytes = response.bytes().await.map_err(http::send_error)?;
+    Ok(bytes.to_vec())
+}
 
-    // For now, provide manual instructions
-    if let Some(pb) = progress {
-        pb.finish_and_clear();
+/// Parse `sha256sum` output into file name -> hex digest
+fn parse_checksums(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (digest, file) = line.trim().split_once(char::is_whitespace)?;
+            // Binary-mode entries mark the file name with '*'
+            let file = file.trim_start().trim_start_matches('*');
+            Some((file.to_string(), digest.to_lowercase()))
+        })
+        .collect()
+}
+
+/// Check the checksums file's minisign signature with the configured public key
+fn verify_signature(
+    checksums: &str,
+    signature: Option<&[u8]>,
+    config: &UpgradeConfig,
+    json_output: bool,
+) -> VelocityResult<()> {
+    let minisign = which::which("minisign").ok();
+    let unverifiable = match (signature, config.public_key.as_deref(), minisign.as_ref()) {
+        (None, _, _) => Some(format!("the release publishes no {}", SIGNATURE_ASSET)),
+        (_, None, _) => Some("no upgrade.public_key is configured".to_string()),
+        (_, _, None) => Some("minisign is not installed".to_string()),
+        (Some(signature), Some(key), Some(minisign)) => {
+            let dir = tempfile::tempdir()?;
+            let sums_path = dir.path().join(CHECKSUMS_ASSET);
+            let sig_path = dir.path().join(SIGNATURE_ASSET);
+            std::fs::write(&sums_path, checksums)?;
+            std::fs::write(&sig_path, signature)?;
+
+            let status = std::process::Command::new(minisign)
+                .arg("-Vq")
+                .arg("-P")
+                .arg(key)
+                .arg("-m")
+                .arg(&sums_path)
+                .arg("-x")
+                .arg(&sig_path)
+                .status()?;
+            if !status.success() {
+                return Err(VelocityError::other(format!(
+                    "Signature verification of {} failed; refusing to install",
+                    CHECKSUMS_ASSET
+                )));
+            }
+            None
+        }
+    };
+
+    match unverifiable {
+        Some(reason) if config.require_signature => Err(VelocityError::other(format!(
+            "Cannot verify the release signature: {} (upgrade.require_signature is set)",
+            reason
+        ))),
+        Some(reason) => {
+            if !json_output {
+                output::warning(&format!("Release signature not verified: {}", reason));
+            }
+            Ok(())
+        }
+        None => Ok(()),
     }
+}
 
-    if !json_output {
-        output::info("Automatic updates are not yet implemented.");
-        println!();
-        output::info("To upgrade manually:");
-        println!("  1. Download from: {}", download_url);
-        println!("  2. Replace the current velocity binary");
-        println!("  3. Run 'velocity --version' to verify");
+/// The velocity executable from a release archive: a zip on Windows, a tarball elsewhere
+fn extract_binary(name: &str, archive: &[u8]) -> VelocityResult<Vec<u8>> {
+    if name.ends_with(".zip") {
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(archive))
+            .map_err(|e| VelocityError::other(format!("Invalid release archive: {}", e)))?;
+        for i in 0..zip.len() {
+            let mut entry = zip
+                .by_index(i)
+                .map_err(|e| VelocityError::other(format!("Invalid release archive: {}", e)))?;
+            let is_binary = entry.is_file()
+                && entry.enclosed_name().and_then(|path| path.file_name()).is_some_and(|file| file == BINARY_NAME);
+            if is_binary {
+                let mut binary = Vec::new();
+                entry.read_to_end(&mut binary)?;
+                return Ok(binary);
+            }
+        }
+        return Err(VelocityError::other("Release archive does not contain a velocity binary"));
+    }
+
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let is_binary = entry.header().entry_type().is_file()
+            && entry.path()?.file_name().map(|name| name == BINARY_NAME).unwrap_or(false);
+        if is_binary {
+            let mut binary = Vec::new();
+            entry.read_to_end(&mut binary)?;
+            return Ok(binary);
+        }
     }
+    Err(VelocityError::other("Release archive does not contain a velocity binary"))
+}
+
+fn current_exe() -> VelocityResult<PathBuf> {
+    let exe = std::env::current_exe()?;
+    Ok(std::fs::canonicalize(&exe).unwrap_or(exe))
+}
 
+/// Where the binary replaced by the last upgrade is kept
+fn backup_dir() -> VelocityResult<PathBuf> {
+    let project_dirs = ProjectDirs::from("com", "velocity", "velocity")
+        .ok_or_else(|| VelocityError::config("Could not determine data directory"))?;
+    Ok(project_dirs.data_dir().join("previous"))
+}
+
+/// Keep the running binary so `--rollback` can restore it
+fn backup_current(exe: &Path) -> VelocityResult<()> {
+    let dir = backup_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    std::fs::copy(exe, dir.join("velocity"))?;
+    std::fs::write(dir.join("version"), CURRENT_VERSION)?;
     Ok(())
 }
+
+/// Where Windows keeps the binary an upgrade moved aside
+fn replaced_path(exe: &Path) -> PathBuf {
+    let mut name = exe.as_os_str().to_owned();
+    name.push(".old");
+    PathBuf::from(name)
+}
+
+/// Delete the binary the last upgrade moved aside, now that it is no longer running
+fn remove_replaced_exe() {
+    if let Ok(exe) = current_exe() {
+        let _ = std::fs::remove_file(replaced_path(&exe));
+    }
+}
+
+/// Atomically swap `binary` in for `exe` via a rename within its directory
+///
+/// Windows will not overwrite a running executable but does let it be renamed,
+/// so there the old binary is moved aside first, and moved back if the new one
+/// cannot take its place. The next upgrade deletes it.
+fn replace_exe(exe: &Path, binary: &[u8]) -> VelocityResult<()> {
+    let dir = exe
+        .parent()
+        .ok_or_else(|| VelocityError::other("Could not determine the install directory"))?;
+    let mut staged = tempfile::Builder::new().prefix(".velocity-upgrade").tempfile_in(dir)?;
+    std::io::Write::write_all(&mut staged, binary)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(staged.path(), std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    if cfg!(windows) {
+        let aside = replaced_path(exe);
+        let _ = std::fs::remove_file(&aside);
+        std::fs::rename(exe, &aside)?;
+        if let Err(e) = staged.persist(exe) {
+            let _ = std::fs::rename(&aside, exe);
+            return Err(VelocityError::Io(e.error));
+        }
+        return Ok(());
+    }
+
+    staged.persist(exe).map_err(|e| VelocityError::Io(e.error))?;
+    Ok(())
+}
+
+/// Swap the running binary with the one kept by the last upgrade
+fn rollback(json_output: bool) -> VelocityResult<()> {
+    let dir = backup_dir()?;
+    let previous = dir.join("velocity");
+    let previous_version = std::fs::read_to_string(dir.join("version")).ok();
+    let Some(previous_version) = previous_version.filter(|_| previous.exists()) else {
+        return Err(VelocityError::other("No previous version to roll back to"));
+    };
+    let previous_version = previous_version.trim().to_string();
+
+    // Read the old binary before the current one takes its place
+    let binary = std::fs::read(&previous)?;
+    let exe = current_exe()?;
+    backup_current(&exe)?;
+    replace_exe(&exe, &binary)?;
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "rolled_back": true,
+            "from_version": CURRENT_VERSION,
+            "to_version": previous_version,
+        }))?;
+    } else {
+        output::success(&format!("Rolled back from v{} to v{}", CURRENT_VERSION, previous_version));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(version: &str) -> Release {
+        let version = semver::Version::parse(version).unwrap();
+        Release { channel: ReleaseChannel::of(&version), version, assets: Vec::new() }
+    }
+
+    #[test]
+    fn test_channels_include_more_stable_releases() {
+        let releases = vec![
+            release("1.3.0-nightly.20261001"),
+            release("1.3.0-beta.1"),
+            release("1.2.0"),
+        ];
+        let versions = |channel| -> Vec<String> {
+            on_channel(&releases, channel).iter().map(|r| r.version.to_string()).collect()
+        };
+
+        assert_eq!(versions(ReleaseChannel::Stable), ["1.2.0"]);
+        assert_eq!(versions(ReleaseChannel::Beta), ["1.3.0-beta.1", "1.2.0"]);
+        assert_eq!(versions(ReleaseChannel::Nightly).len(), 3);
+        assert_eq!(ReleaseChannel::of(&semver::Version::parse("2.0.0-rc.1").unwrap()), ReleaseChannel::Beta);
+    }
+
+    #[test]
+    fn test_parse_checksums() {
+        let sums = parse_checksums(
+            "ABC123  velocity-linux-x86_64.tar.gz\ndef456 *velocity-macos-aarch64.tar.gz\n\n",
+        );
+        assert_eq!(sums["velocity-linux-x86_64.tar.gz"], "abc123");
+        assert_eq!(sums["velocity-macos-aarch64.tar.gz"], "def456");
+        assert_eq!(sums.len(), 2);
+    }
+
+    #[test]
+    fn test_pinned_release() {
+        let releases = vec![release("1.3.0-beta.1"), release("1.2.0"), release("1.1.0")];
+        assert_eq!(pinned_release(&releases, "1.1.0").unwrap().version.to_string(), "1.1.0");
+        assert_eq!(pinned_release(&releases, "v1.3.0-beta.1").unwrap().channel, ReleaseChannel::Beta);
+        assert!(pinned_release(&releases, "1.0.0").is_err());
+        assert!(pinned_release(&releases, "latest").is_err());
+    }
+
+    #[test]
+    fn test_extract_binary() {
+        let mut tarball = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_mode(0o755);
+        tarball.append_data(&mut header, format!("velocity/{}", BINARY_NAME), &b"bin"[..]).unwrap();
+        let tarball = tarball.into_inner().unwrap().finish().unwrap();
+        assert_eq!(extract_binary("velocity-linux-x86_64.tar.gz", &tarball).unwrap(), b"bin");
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("README.md", zip::write::FileOptions::default()).unwrap();
+        std::io::Write::write_all(&mut zip, b"docs").unwrap();
+        zip.start_file(BINARY_NAME, zip::write::FileOptions::default()).unwrap();
+        std::io::Write::write_all(&mut zip, b"exe").unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+        assert_eq!(extract_binary("velocity-windows-x86_64.zip", &zip).unwrap(), b"exe");
+        assert!(extract_binary("velocity-windows-x86_64.zip", &tarball).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+//! velocity vendor - Copy locked tarballs into the repository for offline builds
+
+use std::path::PathBuf;
+use std::time::Instant;
+use clap::Args;
+
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{Engine, VelocityError, VelocityResult};
+use crate::installer::vendor::{VENDOR_DIR, VENDOR_MANIFEST};
+use crate::installer::{Downloader, Vendor};
+
+#[derive(Args)]
+pub struct VendorArgs {
+    /// Project directory (default: current directory)
+    #[arg(default_value = ".")]
+    pub path: PathBuf,
+
+    /// Directory to write tarballs to, relative to the project
+    #[arg(short, long, default_value = VENDOR_DIR)]
+    pub dir: PathBuf,
+}
+
+pub async fn execute(args: VendorArgs, json_output: bool) -> VelocityResult<()> {
+    let start_time = Instant::now();
+
+    let project_dir = project::project_root_for(&args.path)?;
+
+    let engine = Engine::new(&project_dir).await?;
+    engine.ensure_initialized()?;
+
+    let lockfile = engine.lockfile()?.ok_or_else(|| {
+        VelocityError::other("No lockfile found. Run 'velocity install' first.")
+    })?;
+
+    let vendor_dir = if args.dir.is_absolute() {
+        args.dir.clone()
+    } else {
+        project_dir.join(&args.dir)
+    };
+
+    let progress = if !json_output {
+        Some(output::spinner("Vendoring packages..."))
+    } else {
+        None
+    };
+
+    let downloader = Downloader::new(engine.cache.clone(), engine.http.clone(), engine.config.network.download_concurrency, &engine.config.network)?
+        .with_registry_config(engine.config.registry.clone())
+        .with_base_dir(engine.project_dir.clone());
+    let vendor = Vendor::new(vendor_dir, engine.cache.clone());
+    let result = vendor.populate(&lockfile, &downloader).await?;
+
+    if let Some(pb) = progress {
+        pb.finish_and_clear();
+    }
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "success": true,
+            "path": vendor.dir(),
+            "manifest": vendor.dir().join(VENDOR_MANIFEST),
+            "vendored": result.vendored,
+            "downloaded": result.downloaded,
+            "removed": result.removed,
+            "duration_ms": start_time.elapsed().as_millis()
+        }))?;
+    } else {
+        output::success(&format!(
+            "Vendored {} packages into {} in {}",
+            result.vendored,
+            vendor.dir().display(),
+            output::format_duration(start_time.elapsed().as_millis())
+        ));
+
+        if result.removed > 0 {
+            output::info(&format!("Removed {} stale tarballs", result.removed));
+        }
+
+        output::info("Install without registry access using 'velocity install --vendored'");
+    }
+
+    Ok(())
+}
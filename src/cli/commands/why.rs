@@ -0,0 +1,488 @@
+//! velocity why - Explain why a package is installed
+//!
+//! Everything is computed from the lockfile graph, so answers match what the
+//! next install would link and never need the registry.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use clap::Args;
+use serde_json::{json, Value};
+
+use crate::cli::output;
+use crate::core::project;
+use crate::core::{Engine, VelocityError, VelocityResult};
+use crate::installer::layout::PackageIndex;
+use crate::resolver::{ResolvedPackage, VersionConstraint};
+
+/// Most paths listed by --all-paths before the output is cut short
+const MAX_PATHS: usize = 1000;
+
+#[derive(Args)]
+pub struct WhyArgs {
+    /// Package to explain, optionally with a version or range (e.g. lodash@^4)
+    pub package: String,
+
+    /// List every dependency path instead of the shortest one per version
+    #[arg(long)]
+    pub all_paths: bool,
+
+    /// Show everything that depends on the package, i.e. what removing it would break
+    #[arg(long)]
+    pub reverse: bool,
+
+    /// Levels of transitive dependencies to follow (0 = direct dependencies only)
+    #[arg(long, value_name = "N")]
+    pub depth: Option<usize>,
+}
+
+/// A direct dependency of the project or of a workspace member
+struct Root {
+    /// Project declaring it
+    importer: String,
+    name: String,
+    spec: String,
+    /// package.json field it is declared in
+    kind: &'static str,
+}
+
+/// A chain of packages from a root dependency down to the target
+struct WhyPath<'a> {
+    root: usize,
+    packages: Vec<&'a ResolvedPackage>,
+}
+
+/// A dependent of the target in --reverse output: a package, or a project
+/// declaring it directly
+struct Dependent {
+    name: String,
+    /// Locked version; `None` for projects
+    version: Option<String>,
+    /// package.json field, for projects
+    kind: Option<&'static str>,
+    /// Already listed elsewhere in the tree
+    deduped: bool,
+    children: Vec<Dependent>,
+}
+
+type Key<'a> = (&'a str, &'a str);
+
+fn key(package: &ResolvedPackage) -> Key<'_> {
+    (package.name.as_str(), package.version.as_str())
+}
+
+pub async fn execute(args: WhyArgs, json_output: bool) -> VelocityResult<()> {
+    let project_dir = project::current_project_root()?;
+    let engine = Engine::new(&project_dir).await?;
+    engine.ensure_initialized()?;
+
+    let lockfile = engine.lockfile()?.ok_or_else(|| {
+        VelocityError::other("No lockfile found. Run 'velocity install' first.")
+    })?;
+    let resolution = engine.resolver().resolve_from_lockfile(&lockfile)?;
+    let packages: Vec<&ResolvedPackage> = resolution.to_install.iter()
+        .chain(resolution.from_cache.iter())
+        .collect();
+    let index = PackageIndex::new(&packages);
+
+    // A shared workspace lockfile is reached from every member too
+    let shared = engine.workspace.as_ref().map(|w| w.shared_lockfile()).unwrap_or(false);
+    let projects = if shared {
+        engine.select_projects(&[])?
+    } else {
+        vec![(project_dir.clone(), engine.package_json()?)]
+    };
+    let mut roots = Vec::new();
+    for (_, pkg) in &projects {
+        let fields = [
+            ("dependencies", &pkg.dependencies),
+            ("optionalDependencies", &pkg.optional_dependencies),
+            ("devDependencies", &pkg.dev_dependencies),
+        ];
+        for (kind, deps) in fields {
+            let mut deps: Vec<_> = deps.iter().filter(|(_, spec)| !spec.starts_with("workspace:")).collect();
+            deps.sort();
+            roots.extend(deps.into_iter().map(|(name, spec)| Root {
+                importer: pkg.name.clone(),
+                name: name.clone(),
+                spec: spec.clone(),
+                kind,
+            }));
+        }
+    }
+
+    let (name, range) = crate::utils::parse_package_spec(&args.package);
+    let constraint = match range {
+        Some(ref range) => Some(VersionConstraint::parse(range)?),
+        None => None,
+    };
+    let targets: Vec<&ResolvedPackage> = index
+        .versions(&name)
+        .into_iter()
+        .filter(|p| match constraint {
+            Some(ref c) => semver::Version::parse(&p.version).map(|v| c.matches(&v)).unwrap_or(false),
+            None => true,
+        })
+        .collect();
+    if targets.is_empty() {
+        return Err(VelocityError::other(format!("'{}' is not in the lockfile", args.package)));
+    }
+
+    let max_depth = args.depth.unwrap_or(usize::MAX);
+
+    if args.reverse {
+        let dependents = dependents_of(&index, &packages);
+        let trees: Vec<(&ResolvedPackage, Vec<Dependent>)> = targets
+            .iter()
+            .map(|target| (*target, reverse_tree(target, &dependents, &index, &roots, max_depth)))
+            .collect();
+        let affected = transitive_dependents(&targets, &dependents);
+        let direct: Vec<&Root> = roots
+            .iter()
+            .filter(|root| match index.resolve(&root.name, &root.spec) {
+                Some(p) => affected.contains(&key(p)) || targets.iter().any(|t| key(t) == key(p)),
+                None => false,
+            })
+            .collect();
+
+        if json_output {
+            output::json(&json!({
+                "package": name,
+                "versions": trees.iter().map(|(target, tree)| json!({
+                    "version": target.version,
+                    "dependents": tree.iter().map(dependent_json).collect::<Vec<_>>(),
+                })).collect::<Vec<_>>(),
+                "affectedPackages": affected.len(),
+                "affectedDirectDependencies": direct.iter().map(|root| json!({
+                    "project": root.importer,
+                    "name": root.name,
+                    "type": root.kind,
+                })).collect::<Vec<_>>(),
+            }))?;
+            return Ok(());
+        }
+
+        for (target, tree) in &trees {
+            println!("{}", output::package_version(&target.name, &target.version));
+            print_dependents(tree, "");
+        }
+        println!();
+        output::info(&format!(
+            "Removing {} would affect {} packages and {} direct dependencies",
+            name,
+            affected.len(),
+            direct.len()
+        ));
+        return Ok(());
+    }
+
+    let (paths, truncated) = find_paths(&index, &roots, &targets, args.all_paths, max_depth);
+
+    if json_output {
+        output::json(&json!({
+            "package": name,
+            "paths": paths.iter().map(|path| {
+                let root = &roots[path.root];
+                json!({
+                    "project": root.importer,
+                    "type": root.kind,
+                    "path": path.packages.iter().map(|p| json!({ "name": p.name, "version": p.version })).collect::<Vec<_>>(),
+                })
+            }).collect::<Vec<_>>(),
+            "truncated": truncated,
+        }))?;
+        return Ok(());
+    }
+
+    if paths.is_empty() {
+        output::warning(&format!(
+            "{} is locked but no dependency path reaches it{}",
+            name,
+            if args.depth.is_some() { " within the depth limit" } else { "" }
+        ));
+        return Ok(());
+    }
+
+    for path in &paths {
+        let root = &roots[path.root];
+        let chain: Vec<String> = path.packages.iter().map(|p| output::package_version(&p.name, &p.version)).collect();
+        println!(
+            "{} {} {}",
+            output::strong(&root.importer),
+            output::muted(format!("({})", root.kind)),
+            chain.join(&output::muted(" > ").to_string())
+        );
+    }
+    if truncated {
+        output::warning(&format!("Only the first {} paths are shown; use --depth to narrow them", MAX_PATHS));
+    }
+
+    Ok(())
+}
+
+/// Packages depending on each package, keyed by the dependency
+fn dependents_of<'a>(
+    index: &PackageIndex<'a>,
+    packages: &[&'a ResolvedPackage],
+) -> HashMap<Key<'a>, Vec<&'a ResolvedPackage>> {
+    let mut dependents: HashMap<Key<'a>, Vec<&'a ResolvedPackage>> = HashMap::new();
+    for package in packages {
+        for dep in index.dependencies(package) {
+            dependents.entry(key(dep)).or_default().push(package);
+        }
+    }
+    for list in dependents.values_mut() {
+        list.sort_by_key(|p| key(p));
+        list.dedup_by_key(|p| key(p));
+    }
+    dependents
+}
+
+/// Paths from root dependencies to any target, at most `max_depth` levels below the root
+///
+/// Without `all`, each target version gets its shortest path. With it, every
+/// cycle-free path is listed, up to [`MAX_PATHS`]; the flag reports a cut.
+fn find_paths<'a>(
+    index: &PackageIndex<'a>,
+    roots: &[Root],
+    targets: &[&'a ResolvedPackage],
+    all: bool,
+    max_depth: usize,
+) -> (Vec<WhyPath<'a>>, bool) {
+    let is_target: HashSet<Key<'a>> = targets.iter().map(|t| key(t)).collect();
+    let starts: Vec<(usize, &'a ResolvedPackage)> = roots
+        .iter()
+        .enumerate()
+        .filter_map(|(i, root)| index.resolve(&root.name, &root.spec).map(|p| (i, p)))
+        .collect();
+
+    if !all {
+        // Breadth-first from every root at once: the first visit is the shortest path
+        let mut parent: HashMap<Key<'a>, (Option<&'a ResolvedPackage>, usize)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        for &(root, package) in &starts {
+            parent.entry(key(package)).or_insert_with(|| {
+                queue.push_back((package, 0));
+                (None, root)
+            });
+        }
+
+        let mut paths = Vec::new();
+        while let Some((package, depth)) = queue.pop_front() {
+            if is_target.contains(&key(package)) {
+                let root = parent[&key(package)].1;
+                let mut chain = vec![package];
+                while let Some(up) = parent[&key(chain[chain.len() - 1])].0 {
+                    chain.push(up);
+                }
+                chain.reverse();
+                paths.push(WhyPath { root, packages: chain });
+            }
+            if depth >= max_depth {
+                continue;
+            }
+            for dep in index.dependencies(package) {
+                if !parent.contains_key(&key(dep)) {
+                    parent.insert(key(dep), (Some(package), parent[&key(package)].1));
+                    queue.push_back((dep, depth + 1));
+                }
+            }
+        }
+        return (paths, false);
+    }
+
+    // Only packages that can reach a target are worth walking into
+    let dependents = {
+        let all_packages: Vec<&'a ResolvedPackage> = index.names().flat_map(|name| index.versions(name)).collect();
+        dependents_of(index, &all_packages)
+    };
+    let mut leads_to_target: HashSet<Key<'a>> = is_target.clone();
+    let mut queue: Vec<Key<'a>> = is_target.iter().copied().collect();
+    while let Some(current) = queue.pop() {
+        for dependent in dependents.get(&current).into_iter().flatten() {
+            if leads_to_target.insert(key(dependent)) {
+                queue.push(key(dependent));
+            }
+        }
+    }
+
+    fn walk<'a>(
+        index: &PackageIndex<'a>,
+        chain: &mut Vec<&'a ResolvedPackage>,
+        ctx: (&HashSet<Key<'a>>, &HashSet<Key<'a>>, usize, usize),
+        paths: &mut Vec<WhyPath<'a>>,
+    ) -> bool {
+        let (is_target, leads_to_target, max_depth, root) = ctx;
+        let package = chain[chain.len() - 1];
+        if is_target.contains(&key(package)) {
+            if paths.len() == MAX_PATHS {
+                return false;
+            }
+            paths.push(WhyPath { root, packages: chain.clone() });
+        }
+        if chain.len() > max_depth {
+            return true;
+        }
+        for dep in index.dependencies(package) {
+            if !leads_to_target.contains(&key(dep)) || chain.iter().any(|p| key(p) == key(dep)) {
+                continue;
+            }
+            chain.push(dep);
+            let more = walk(index, chain, ctx, paths);
+            chain.pop();
+            if !more {
+                return false;
+            }
+        }
+        true
+    }
+
+    let mut paths = Vec::new();
+    for &(root, package) in &starts {
+        if !leads_to_target.contains(&key(package)) {
+            continue;
+        }
+        let mut chain = vec![package];
+        if !walk(index, &mut chain, (&is_target, &leads_to_target, max_depth, root), &mut paths) {
+            return (paths, true);
+        }
+    }
+    (paths, false)
+}
+
+/// Everything that depends on the target, up to the projects declaring it
+fn reverse_tree<'a>(
+    target: &'a ResolvedPackage,
+    dependents: &HashMap<Key<'a>, Vec<&'a ResolvedPackage>>,
+    index: &PackageIndex<'a>,
+    roots: &[Root],
+    max_depth: usize,
+) -> Vec<Dependent> {
+    fn expand<'a>(
+        package: &'a ResolvedPackage,
+        depth: usize,
+        ctx: (&HashMap<Key<'a>, Vec<&'a ResolvedPackage>>, &PackageIndex<'a>, &[Root], usize),
+        seen: &mut HashSet<Key<'a>>,
+    ) -> Vec<Dependent> {
+        let (dependents, index, roots, max_depth) = ctx;
+        let mut children: Vec<Dependent> = roots
+            .iter()
+            .filter(|root| index.resolve(&root.name, &root.spec).map(|p| key(p) == key(package)).unwrap_or(false))
+            .map(|root| Dependent {
+                name: root.importer.clone(),
+                version: None,
+                kind: Some(root.kind),
+                deduped: false,
+                children: Vec::new(),
+            })
+            .collect();
+
+        if depth >= max_depth {
+            return children;
+        }
+        for dependent in dependents.get(&key(package)).into_iter().flatten() {
+            let deduped = !seen.insert(key(dependent));
+            children.push(Dependent {
+                name: dependent.name.clone(),
+                version: Some(dependent.version.clone()),
+                kind: None,
+                deduped,
+                children: if deduped { Vec::new() } else { expand(dependent, depth + 1, ctx, seen) },
+            });
+        }
+        children
+    }
+
+    let mut seen = HashSet::from([key(target)]);
+    expand(target, 0, (dependents, index, roots, max_depth), &mut seen)
+}
+
+/// Every package that depends on a target, directly or transitively
+fn transitive_dependents<'a>(
+    targets: &[&'a ResolvedPackage],
+    dependents: &HashMap<Key<'a>, Vec<&'a ResolvedPackage>>,
+) -> HashSet<Key<'a>> {
+    let mut affected = HashSet::new();
+    let mut queue: Vec<Key<'a>> = targets.iter().map(|t| key(t)).collect();
+    while let Some(current) = queue.pop() {
+        for dependent in dependents.get(&current).into_iter().flatten() {
+            if affected.insert(key(dependent)) {
+                queue.push(key(dependent));
+            }
+        }
+    }
+    for target in targets {
+        affected.remove(&key(target));
+    }
+    affected
+}
+
+fn print_dependents(nodes: &[Dependent], prefix: &str) {
+    for (i, node) in nodes.iter().enumerate() {
+        let last = i + 1 == nodes.len();
+        let branch = if last { "└── " } else { "├── " };
+
+        let mut label = match (&node.version, node.kind) {
+            (Some(version), _) => output::package_version(&node.name, version),
+            (None, kind) => format!(
+                "{} {}",
+                output::strong(&node.name),
+                output::muted(format!("({})", kind.unwrap_or_default()))
+            ),
+        };
+        if node.deduped {
+            label = format!("{} {}", label, output::muted("deduped"));
+        }
+        println!("{}{}{}", prefix, branch, label);
+
+        let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        print_dependents(&node.children, &child_prefix);
+    }
+}
+
+fn dependent_json(node: &Dependent) -> Value {
+    match node.version {
+        None => json!({ "project": node.name, "type": node.kind }),
+        Some(ref version) => json!({
+            "name": node.name,
+            "version": version,
+            "deduped": node.deduped,
+            "dependents": node.children.iter().map(dependent_json).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::test_support::package;
+
+    fn root(name: &str) -> Root {
+        Root { importer: "app".to_string(), name: name.to_string(), spec: "*".to_string(), kind: "dependencies" }
+    }
+
+    #[test]
+    fn test_paths_and_dependents() {
+        let a = package("a", "1.0.0", &[("b", "*"), ("c", "*")]);
+        let b = package("b", "1.0.0", &[("c", "*")]);
+        let c = package("c", "1.0.0", &[]);
+        let packages = [&a, &b, &c];
+        let index = PackageIndex::new(&packages);
+        let roots = vec![root("a"), root("b")];
+
+        let names = |path: &WhyPath| path.packages.iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join(">");
+
+        let (shortest, _) = find_paths(&index, &roots, &[&c], false, usize::MAX);
+        assert_eq!(shortest.iter().map(names).collect::<Vec<_>>(), ["a>c"]);
+
+        let (all, truncated) = find_paths(&index, &roots, &[&c], true, usize::MAX);
+        assert!(!truncated);
+        assert_eq!(all.iter().map(names).collect::<Vec<_>>(), ["a>b>c", "a>c", "b>c"]);
+
+        let (shallow, _) = find_paths(&index, &roots, &[&c], true, 1);
+        assert_eq!(shallow.iter().map(names).collect::<Vec<_>>(), ["a>c", "b>c"]);
+
+        let dependents = dependents_of(&index, &packages);
+        let affected = transitive_dependents(&[&c], &dependents);
+        assert_eq!(affected, HashSet::from([("a", "1.0.0"), ("b", "1.0.0")]));
+    }
+}
@@ -5,7 +5,10 @@ use std::env;
 use clap::{Args, Subcommand};
 
 use crate::cli::output;
-use crate::core::{Engine, PackageJson, VelocityResult};
+use crate::core::project;
+use crate::core::{Engine, PackageJson, VelocityError, VelocityResult};
+use crate::workspace::release::{self, Bump, ReleaseReason};
+use crate::workspace::runner::{TaskResult, TaskStatus, WorkspaceRunner, WorkspaceTask};
 
 
 #[derive(Args)]
@@ -26,7 +29,7 @@ pub enum WorkspaceCommands {
     /// List all packages in the workspace
     List,
 
-    /// Run a command in all packages
+    /// Run a script in all packages, dependencies first
     Run {
         /// Command to run
         command: String,
@@ -38,6 +41,18 @@ pub enum WorkspaceCommands {
         /// Filter by package name
         #[arg(short, long)]
         filter: Option<String>,
+
+        /// Packages to run at once (default: number of CPUs)
+        #[arg(short = 'j', long, value_name = "N")]
+        concurrency: Option<usize>,
+
+        /// Stop every running script at the first failure (the default)
+        #[arg(long, conflicts_with = "continue_on_error")]
+        fail_fast: bool,
+
+        /// Keep running packages that do not depend on a failed one
+        #[arg(long)]
+        continue_on_error: bool,
     },
 
     /// Add a new package to the workspace
@@ -52,17 +67,45 @@ pub enum WorkspaceCommands {
 
     /// Show dependency graph
     Graph,
+
+    /// Bump package versions and their dependents, write changelogs and tag the release
+    Version {
+        /// Bump every selected package by this much: patch, minor or major
+        /// (default: derived from conventional commits since each package's last tag)
+        bump: Option<Bump>,
+
+        /// Only bump packages whose name contains this (dependents still follow)
+        #[arg(short, long)]
+        filter: Option<String>,
+
+        /// Show the planned releases without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Leave the changes uncommitted and untagged
+        #[arg(long)]
+        no_git: bool,
+    },
 }
 
 pub async fn execute(args: WorkspaceArgs, json_output: bool) -> VelocityResult<()> {
     match args.command {
         WorkspaceCommands::Init { yes } => init_workspace(yes, json_output).await,
         WorkspaceCommands::List => list_packages(json_output).await,
-        WorkspaceCommands::Run { command, args, filter } => {
-            run_in_packages(&command, &args, filter, json_output).await
+        WorkspaceCommands::Run { command, args, filter, concurrency, fail_fast: _, continue_on_error } => {
+            let concurrency = concurrency
+                .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4));
+            let runner = WorkspaceRunner::new(concurrency)
+                .with_fail_fast(!continue_on_error)
+                .with_args(args)
+                .with_output_to_stderr(json_output);
+            run_in_packages(&command, runner, filter, json_output).await
         }
         WorkspaceCommands::Add { name, dir } => add_package(&name, dir, json_output).await,
         WorkspaceCommands::Graph => show_graph(json_output).await,
+        WorkspaceCommands::Version { bump, filter, dry_run, no_git } => {
+            version_packages(bump, filter, dry_run, no_git, json_output).await
+        }
     }
 }
 
@@ -125,7 +168,7 @@ async fn init_workspace(yes: bool, json_output: bool) -> VelocityResult<()> {
 }
 
 async fn list_packages(json_output: bool) -> VelocityResult<()> {
-    let project_dir = env::current_dir()?;
+    let project_dir = project::current_workspace_root()?;
     let engine = Engine::new(&project_dir).await?;
 
     let packages = engine.workspace_packages()?;
@@ -171,9 +214,9 @@ async fn list_packages(json_output: bool) -> VelocityResult<()> {
         for (name, version, path) in &package_info {
             println!(
                 "  {} {} ({})",
-                console::style(name).cyan().bold(),
-                console::style(format!("v{}", version)).dim(),
-                console::style(path.display()).dim()
+                output::highlight(name).bold(),
+                output::muted(format!("v{}", version)),
+                output::muted(path.display())
             );
         }
     }
@@ -183,11 +226,11 @@ async fn list_packages(json_output: bool) -> VelocityResult<()> {
 
 async fn run_in_packages(
     command: &str,
-    args: &[String],
+    runner: WorkspaceRunner,
     filter: Option<String>,
     json_output: bool,
 ) -> VelocityResult<()> {
-    let project_dir = env::current_dir()?;
+    let project_dir = project::current_workspace_root()?;
     let engine = Engine::new(&project_dir).await?;
 
     let packages = engine.workspace_packages()?;
@@ -199,14 +242,13 @@ async fn run_in_packages(
         return Ok(());
     }
 
-    let mut results = Vec::new();
-
-    for pkg_path in &packages {
-        let pkg = match PackageJson::load(pkg_path) {
-            Ok(p) => p,
-            Err(_) => continue,
-        };
+    let Some(ref workspace) = engine.workspace else {
+        return Ok(());
+    };
+    let graph = workspace.build_graph()?;
 
+    let mut tasks = Vec::new();
+    for (pkg_path, pkg) in workspace.package_jsons()? {
         // Apply filter
         if let Some(ref f) = filter {
             if !pkg.name.contains(f) {
@@ -214,53 +256,55 @@ async fn run_in_packages(
             }
         }
 
-        if !json_output {
-            output::info(&format!("Running in {}...", console::style(&pkg.name).cyan()));
-        }
-
-        // Check if script exists
-        if let Some(script) = pkg.scripts.get(command) {
-            let full_args: Vec<String> = args.to_vec();
-
-            let shell = if cfg!(windows) { "cmd" } else { "sh" };
-            let shell_arg = if cfg!(windows) { "/c" } else { "-c" };
-
-            let full_command = if full_args.is_empty() {
-                script.clone()
-            } else {
-                format!("{} {}", script, full_args.join(" "))
-            };
-
-            let status = tokio::process::Command::new(shell)
-                .arg(shell_arg)
-                .arg(&full_command)
-                .current_dir(pkg_path)
-                .status()
-                .await?;
-
-            results.push((pkg.name.clone(), status.success()));
+        tasks.push(WorkspaceTask {
+            command: pkg.scripts.get(command).cloned(),
+            name: pkg.name,
+            dir: pkg_path,
+        });
+    }
 
-            if !json_output && !status.success() {
-                output::warning(&format!("Command failed in {}", pkg.name));
-            }
-        } else if !json_output {
-            output::warning(&format!("Script '{}' not found in {}", command, pkg.name));
-        }
+    if !json_output {
+        output::info(&format!(
+            "Running '{}' in {} packages...",
+            command,
+            tasks.iter().filter(|task| task.command.is_some()).count()
+        ));
     }
 
+    let results = runner.run(&graph, &tasks).await?;
+    let ran: Vec<&TaskResult> = results.iter().filter(|r| r.status != TaskStatus::NoScript).collect();
+    let failed: Vec<&str> = results
+        .iter()
+        .filter(|r| matches!(r.status, TaskStatus::Failed { .. }))
+        .map(|r| r.name.as_str())
+        .collect();
+
     if json_output {
         output::json(&serde_json::json!({
             "command": command,
-            "results": results.iter().map(|(name, success)| {
-                serde_json::json!({
-                    "package": name,
-                    "success": success
-                })
-            }).collect::<Vec<_>>()
+            "results": results.iter().map(TaskResult::to_json).collect::<Vec<_>>()
         }))?;
     } else {
-        let success_count = results.iter().filter(|(_, s)| *s).count();
-        let total = results.len();
+        for result in &results {
+            match result.status {
+                TaskStatus::Failed { exit_code } => output::warning(&format!(
+                    "Command failed in {} (exit code {})",
+                    result.name,
+                    exit_code.map_or_else(|| "unknown".to_string(), |code| code.to_string())
+                )),
+                TaskStatus::Skipped => {
+                    output::warning(&format!("Skipped {}: a package it depends on failed", result.name))
+                }
+                TaskStatus::Cancelled => output::warning(&format!("Cancelled {}", result.name)),
+                TaskStatus::NoScript => {
+                    tracing::debug!("Script '{}' not found in {}", command, result.name)
+                }
+                TaskStatus::Succeeded => {}
+            }
+        }
+
+        let success_count = ran.iter().filter(|r| r.status.is_success()).count();
+        let total = ran.len();
 
         if success_count == total {
             output::success(&format!("Completed in all {} packages", total));
@@ -272,11 +316,19 @@ async fn run_in_packages(
         }
     }
 
+    if !failed.is_empty() {
+        return Err(VelocityError::workspace(format!(
+            "'{}' failed in {}",
+            command,
+            failed.join(", ")
+        )));
+    }
+
     Ok(())
 }
 
 async fn add_package(name: &str, dir: Option<String>, json_output: bool) -> VelocityResult<()> {
-    let project_dir = env::current_dir()?;
+    let project_dir = project::current_workspace_root()?;
 
     // Ensure we're in a workspace
     let root_pkg = PackageJson::load(&project_dir)?;
@@ -327,7 +379,7 @@ async fn add_package(name: &str, dir: Option<String>, json_output: bool) -> Velo
     } else {
         output::success(&format!(
             "Created package '{}' at packages/{}",
-            console::style(name).cyan(),
+            output::highlight(name),
             package_dir_name
         ));
     }
@@ -336,7 +388,7 @@ async fn add_package(name: &str, dir: Option<String>, json_output: bool) -> Velo
 }
 
 async fn show_graph(json_output: bool) -> VelocityResult<()> {
-    let project_dir = env::current_dir()?;
+    let project_dir = project::current_workspace_root()?;
     let engine = Engine::new(&project_dir).await?;
 
     let packages = engine.workspace_packages()?;
@@ -384,12 +436,12 @@ async fn show_graph(json_output: bool) -> VelocityResult<()> {
 
         for (name, deps) in &graph {
             if deps.is_empty() {
-                println!("  {} (no workspace dependencies)", console::style(name).cyan());
+                println!("  {} (no workspace dependencies)", output::highlight(name));
             } else {
                 println!("  {} → {}", 
-                    console::style(name).cyan(),
+                    output::highlight(name),
                     deps.iter()
-                        .map(|d| console::style(d).green().to_string())
+                        .map(|d| output::good(d).to_string())
                         .collect::<Vec<_>>()
                         .join(", ")
                 );
@@ -399,3 +451,97 @@ async fn show_graph(json_output: bool) -> VelocityResult<()> {
 
     Ok(())
 }
+
+async fn version_packages(
+    bump: Option<Bump>,
+    filter: Option<String>,
+    dry_run: bool,
+    no_git: bool,
+    json_output: bool,
+) -> VelocityResult<()> {
+    let project_dir = project::current_workspace_root()?;
+    let engine = Engine::new(&project_dir).await?;
+    let workspace = engine
+        .workspace
+        .as_ref()
+        .ok_or_else(|| VelocityError::workspace("Not in a workspace"))?;
+
+    let members = workspace.package_jsons()?;
+    let graph = workspace.build_graph()?;
+
+    // What each selected member asks for, from the command line or its commits
+    let mut bumps = std::collections::BTreeMap::new();
+    for (dir, pkg) in &members {
+        if filter.as_ref().is_some_and(|f| !pkg.name.contains(f.as_str())) {
+            continue;
+        }
+        let tag = format!("{}@{}", pkg.name, pkg.version);
+        let commits = match release::commits_since(&project_dir, dir, &tag) {
+            Ok(commits) => commits,
+            // Without git history an explicit bump still works, just without changelog lines
+            Err(e) if bump.is_some() => {
+                tracing::debug!("No commits for {}: {}", pkg.name, e);
+                Vec::new()
+            }
+            Err(e) => return Err(e),
+        };
+
+        let planned = match bump {
+            Some(bump) => Some((bump, ReleaseReason::Requested)),
+            None => commits.iter().filter_map(|c| c.bump()).max().map(|bump| (bump, ReleaseReason::Commits)),
+        };
+        if let Some((bump, reason)) = planned {
+            bumps.insert(pkg.name.clone(), (bump, reason, commits));
+        }
+    }
+
+    let releases = release::plan_releases(&members, &graph, bumps)?;
+
+    if releases.is_empty() {
+        if json_output {
+            output::json(&serde_json::json!({ "releases": [], "dry_run": dry_run }))?;
+        } else {
+            output::info("Nothing to release");
+        }
+        return Ok(());
+    }
+
+    if !dry_run {
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let mut written = Vec::new();
+        for planned in &releases {
+            written.extend(release::apply_release(planned, &releases, &date)?);
+        }
+        if !no_git {
+            release::commit_and_tag(&project_dir, &written, &releases)?;
+        }
+    }
+
+    if json_output {
+        output::json(&serde_json::json!({
+            "releases": releases.iter().map(release::Release::to_json).collect::<Vec<_>>(),
+            "dry_run": dry_run,
+            "tagged": !dry_run && !no_git,
+        }))?;
+    } else {
+        output::info(if dry_run { "Planned releases (dry run):" } else { "Released:" });
+        for planned in &releases {
+            println!(
+                "  {} {} → {} {}",
+                output::highlight(&planned.name).bold(),
+                planned.current,
+                output::good(&planned.next),
+                output::muted(format!("({})", planned.reason.as_str()))
+            );
+        }
+        if !dry_run {
+            if no_git {
+                output::success("Versions and changelogs updated; nothing committed");
+            } else {
+                output::success(&format!("Committed and tagged {} releases", releases.len()));
+            }
+        }
+    }
+
+    Ok(())
+}
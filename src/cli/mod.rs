@@ -5,6 +5,7 @@
 pub mod commands;
 pub mod output;
 
+use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 
 use commands::*;
@@ -21,14 +22,30 @@ pub struct Cli {
     #[arg(long, global = true)]
     pub json: bool,
 
-    /// Enable verbose output
+    /// Stream newline-delimited JSON events as work happens
+    #[arg(long, global = true, conflicts_with = "json")]
+    pub json_stream: bool,
+
+    /// Print debug logs and how long each step takes
     #[arg(short, long, global = true)]
     pub verbose: bool,
 
-    /// Suppress all output
-    #[arg(short, long, global = true)]
+    /// Print nothing but errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
     pub quiet: bool,
 
+    /// When to color output: auto (terminals, honoring NO_COLOR and CLICOLOR), always or never
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t = output::ColorChoice::Auto)]
+    pub color: output::ColorChoice,
+
+    /// Run as if velocity was started in this directory
+    #[arg(short = 'C', long, global = true, value_name = "DIR")]
+    pub cwd: Option<PathBuf>,
+
+    /// Write a Chrome trace of the run (open in chrome://tracing or Perfetto)
+    #[arg(long, global = true, value_name = "FILE")]
+    pub trace_output: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -50,27 +67,74 @@ pub enum Commands {
     #[command(visible_aliases = ["rm", "uninstall"])]
     Remove(remove::RemoveArgs),
 
+    /// Remove node_modules entries the lockfile does not account for
+    Prune(prune::PruneArgs),
+
+    /// Verify node_modules matches the lockfile
+    Check(check::CheckArgs),
+
     /// Update packages to their latest versions
     #[command(visible_alias = "up")]
     Update(update::UpdateArgs),
 
+    /// Show the installed dependency tree
+    #[command(visible_alias = "ls")]
+    List(list::ListArgs),
+
+    /// Show why a package is installed, or what depends on it
+    Why(why::WhyArgs),
+
     /// Run a script defined in package.json
     #[command(visible_alias = "r")]
     Run(run::RunArgs),
 
+    /// Run a binary from node_modules/.bin
+    #[command(visible_alias = "x")]
+    Exec(exec::ExecArgs),
+
+    /// Fetch a package into a temporary directory and run its binary
+    Dlx(dlx::DlxArgs),
+
+    /// Show a package's registry metadata
+    #[command(visible_alias = "view")]
+    Info(info::InfoArgs),
+
+    /// Search the registry for packages
+    #[command(visible_alias = "s")]
+    Search(search::SearchArgs),
+
     /// Diagnose environment and configuration issues
     Doctor(doctor::DoctorArgs),
 
     /// Security audit for dependencies
     Audit(audit::AuditArgs),
 
+    /// Report dependency licenses and check them against the license policy
+    Licenses(licenses::LicensesArgs),
+
+    /// Show dependencies with newer versions available
+    Outdated(outdated::OutdatedArgs),
+
+    /// Score dependency freshness and project health
+    Health(health::HealthArgs),
+
     /// Manage the package cache
     Cache(cache::CacheArgs),
 
+    /// Read and change velocity.toml settings
+    Config(config::ConfigArgs),
+
+    /// Print a shell completion script (bash, zsh, fish, powershell)
+    Completions(completions::CompletionsArgs),
+
     /// Migrate from another package manager
     Migrate(migrate::MigrateArgs),
 
+    /// Write velocity.lock as another package manager's lockfile
+    Export(export::ExportArgs),
+
     /// Upgrade Velocity to the latest version
+    #[command(disable_version_flag = true)]
     Upgrade(upgrade::UpgradeArgs),
 
     /// Create a new project from a template
@@ -80,5 +144,30 @@ pub enum Commands {
     /// Workspace commands
     #[command(visible_alias = "ws")]
     Workspace(workspace::WorkspaceArgs),
+
+    /// Inspect and validate the lockfile
+    Lockfile(lockfile::LockfileArgs),
+
+    /// Print a digest of the resolved dependency set for build attestations
+    Hash(hash::HashArgs),
+
+    /// Copy locked package tarballs into the repository for offline installs
+    Vendor(vendor::VendorArgs),
+
+    /// Serve package.json queries to editor plugins over JSON-RPC (stdio)
+    LspLite(lsp_lite::LspLiteArgs),
+
+    /// Log in to a registry and store its token
+    #[command(visible_alias = "adduser")]
+    Login(login::LoginArgs),
+
+    /// Forget (and revoke) a registry's stored token
+    Logout(logout::LogoutArgs),
+
+    /// Write the tarball `publish` would upload
+    Pack(pack::PackArgs),
+
+    /// Pack the current package and publish it to its registry
+    Publish(publish::PublishArgs),
 }
 
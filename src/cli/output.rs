@@ -1,57 +1,307 @@
 //! Output formatting for CLI
 
-use console::style;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use console::{style, StyledObject};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use parking_lot::{Mutex, RwLock};
 use serde::Serialize;
 
+use crate::utils::{Phase, Progress};
+
+/// How much a run prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Verbosity {
+    /// `--quiet`: errors only
+    Quiet,
+    #[default]
+    Normal,
+    /// `--verbose`: debug logs and per-step timing as well
+    Verbose,
+}
+
+impl Verbosity {
+    /// The verbosity `--quiet` and `--verbose` ask for; `--quiet` wins
+    pub fn from_flags(quiet: bool, verbose: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (_, true) => Verbosity::Verbose,
+            _ => Verbosity::Normal,
+        }
+    }
+
+    /// Log filter for the console when RUST_LOG is not set
+    pub fn log_filter(self) -> &'static str {
+        match self {
+            Verbosity::Quiet => "error",
+            Verbosity::Normal => "warn",
+            Verbosity::Verbose => "warn,velocity=debug",
+        }
+    }
+}
+
+/// Output settings from the global flags, fixed for the rest of the run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputContext {
+    pub verbosity: Verbosity,
+
+    /// `--json`: commands print one JSON document
+    pub json: bool,
+
+    /// `--json-stream`: every line on stdout is one JSON event
+    pub json_stream: bool,
+
+    /// `--color`: when output is styled
+    pub color: ColorChoice,
+}
+
+impl OutputContext {
+    const DEFAULT: OutputContext = OutputContext {
+        verbosity: Verbosity::Normal,
+        json: false,
+        json_stream: false,
+        color: ColorChoice::Auto,
+    };
+
+    /// Whether output meant for `level` and above is printed
+    pub fn prints(self, level: Verbosity) -> bool {
+        self.verbosity >= level
+    }
+}
+
+impl Default for OutputContext {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static CONTEXT: RwLock<OutputContext> = parking_lot::const_rwlock(OutputContext::DEFAULT);
+
+/// When to style output with colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Color terminals, unless NO_COLOR or CLICOLOR=0 say otherwise
+    #[default]
+    Auto,
+    /// Color even when piped
+    Always,
+    /// Never color
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color a stream, given whether it is a terminal and a way to read
+    /// the environment
+    ///
+    /// With `auto`, a non-empty NO_COLOR turns colors off, then CLICOLOR_FORCE
+    /// (other than `0`) turns them on even when piped, and CLICOLOR=0 turns them off.
+    pub fn enabled(self, is_term: bool, env: impl Fn(&str) -> Option<String>) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let set = |name: &str| env(name).filter(|value| !value.is_empty());
+                if set("NO_COLOR").is_some() {
+                    return false;
+                }
+                if set("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+                    return true;
+                }
+                is_term
+                    && set("CLICOLOR").as_deref() != Some("0")
+                    && set("TERM").as_deref() != Some("dumb")
+            }
+        }
+    }
+}
+
+/// Apply the output settings for the rest of the run
+pub fn init(context: OutputContext) {
+    let env = |name: &str| std::env::var(name).ok();
+    console::set_colors_enabled(context.color.enabled(console::Term::stdout().is_term(), env));
+    console::set_colors_enabled_stderr(context.color.enabled(console::Term::stderr().is_term(), env));
+    *CONTEXT.write() = context;
+}
+
+/// The output settings of this run
+pub fn context() -> OutputContext {
+    *CONTEXT.read()
+}
+
+/// Whether `--quiet` was given: nothing but errors is printed
+pub fn is_quiet() -> bool {
+    !context().prints(Verbosity::Normal)
+}
+
+/// Whether `--verbose` was given
+pub fn is_verbose() -> bool {
+    context().prints(Verbosity::Verbose)
+}
+
+/// Whether `--json-stream` was given
+pub fn is_json_stream() -> bool {
+    context().json_stream
+}
+
+/// Text without styling, for places that take a styled value
+pub fn plain<D>(value: D) -> StyledObject<D> {
+    style(value)
+}
+
+/// Package, project and script names
+pub fn highlight<D>(value: D) -> StyledObject<D> {
+    style(value).cyan()
+}
+
+/// New versions, fixes and passed checks
+pub fn good<D>(value: D) -> StyledObject<D> {
+    style(value).green()
+}
+
+/// Old versions, failures and missing packages
+pub fn bad<D>(value: D) -> StyledObject<D> {
+    style(value).red()
+}
+
+/// What deserves a look without being a failure
+pub fn caution<D>(value: D) -> StyledObject<D> {
+    style(value).yellow()
+}
+
+/// Secondary details: paths, commands, annotations
+pub fn muted<D>(value: D) -> StyledObject<D> {
+    style(value).dim()
+}
+
+/// Headings and labels
+pub fn strong<D>(value: D) -> StyledObject<D> {
+    style(value).bold()
+}
+
+/// Build one event line: `{"event": name, "timestamp": ..., ...fields}`
+fn event_line(name: &str, fields: serde_json::Value) -> String {
+    let mut event = serde_json::Map::new();
+    event.insert("event".into(), name.into());
+    event.insert(
+        "timestamp".into(),
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true).into(),
+    );
+    match fields {
+        serde_json::Value::Object(fields) => event.extend(fields),
+        serde_json::Value::Null => {}
+        other => {
+            event.insert("data".into(), other);
+        }
+    }
+    serde_json::Value::Object(event).to_string()
+}
+
+/// Emit an event on the `--json-stream` output; does nothing otherwise
+pub fn event(name: &str, fields: serde_json::Value) {
+    if !is_json_stream() {
+        return;
+    }
+    use std::io::Write;
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", event_line(name, fields));
+    let _ = stdout.flush();
+}
+
+/// Emit a `warning` event; for warnings that are otherwise hidden under `--json`
+pub fn stream_warning(message: &str) {
+    event("warning", serde_json::json!({ "message": message }));
+}
+
 /// Print a success message
 pub fn success(message: &str) {
-    println!("{} {}", style("✓").green().bold(), message);
+    if is_json_stream() {
+        return event("success", serde_json::json!({ "message": message }));
+    }
+    if is_quiet() {
+        return;
+    }
+    println!("{} {}", good("✓").bold(), message);
 }
 
 /// Print an info message
 pub fn info(message: &str) {
+    if is_json_stream() {
+        return event("info", serde_json::json!({ "message": message }));
+    }
+    if is_quiet() {
+        return;
+    }
     println!("{} {}", style("ℹ").blue().bold(), message);
 }
 
 /// Print a warning message
 pub fn warning(message: &str) {
-    println!("{} {}", style("⚠").yellow().bold(), message);
+    if is_json_stream() {
+        return stream_warning(message);
+    }
+    if is_quiet() {
+        return;
+    }
+    println!("{} {}", caution("⚠").bold(), message);
 }
 
 /// Print an error message
 pub fn error(message: &str) {
-    eprintln!("{} {}", style("✗").red().bold(), message);
+    eprintln!("{} {}", bad("✗").for_stderr().bold(), message);
+}
+
+/// Print a dimmed diagnostic line to stderr with `--verbose`
+pub fn debug(message: &str) {
+    if is_verbose() && !is_json_stream() {
+        eprintln!("{}", muted(message).for_stderr());
+    }
 }
 
 /// Print a step in a process
 pub fn step(number: usize, total: usize, message: &str) {
+    if is_quiet() {
+        return;
+    }
     println!(
         "{} {}",
-        style(format!("[{}/{}]", number, total)).dim(),
+        muted(format!("[{}/{}]", number, total)),
         message
     );
 }
 
-/// Print JSON output
+/// Print JSON output; under `--json-stream` it becomes the final `result` event
 pub fn json<T: Serialize>(data: &T) -> Result<(), serde_json::Error> {
+    if is_json_stream() {
+        event("result", serde_json::to_value(data)?);
+        return Ok(());
+    }
     println!("{}", serde_json::to_string_pretty(data)?);
     Ok(())
 }
 
 /// Print a table header
 pub fn table_header(columns: &[&str]) {
-    let header: Vec<String> = columns.iter().map(|c| style(*c).bold().to_string()).collect();
+    let header: Vec<String> = columns.iter().map(|c| strong(*c).to_string()).collect();
     println!("{}", header.join("  "));
 }
 
+/// Print the heading of results belonging to one or more workspace projects
+pub fn project_group(projects: &[&str]) {
+    match projects {
+        [project] => println!("{}", highlight(project).bold()),
+        _ => println!("{} {}", strong("Shared by"), highlight(projects.join(", "))),
+    }
+}
+
 /// Print a divider line
 pub fn divider() {
-    println!("{}", style("─".repeat(60)).dim());
+    println!("{}", muted("─".repeat(60)));
 }
 
 /// Format a package name with version
 pub fn package_version(name: &str, version: &str) -> String {
-    format!("{}@{}", style(name).cyan(), style(version).green())
+    format!("{}@{}", highlight(name), good(version))
 }
 
 /// Format a duration in human-readable form
@@ -85,9 +335,50 @@ pub fn format_bytes(bytes: u64) -> String {
     }
 }
 
+/// How long-running commands report progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Reporter {
+    /// Spinners and step messages
+    Default,
+    /// One timestamped line per finished phase, for CI logs
+    SummaryOnly,
+}
+
+impl Reporter {
+    /// The requested reporter, or summary-only in CI and when output is not a terminal
+    pub fn select(requested: Option<Reporter>) -> Self {
+        Self::choose(requested, crate::utils::is_ci(), console::Term::stdout().is_term())
+    }
+
+    /// The requested reporter, or the one suited to where output goes
+    fn choose(requested: Option<Reporter>, is_ci: bool, is_term: bool) -> Self {
+        requested.unwrap_or(if is_ci || !is_term {
+            Reporter::SummaryOnly
+        } else {
+            Reporter::Default
+        })
+    }
+}
+
+/// Print a timestamped line for a finished phase
+pub fn phase(name: &str, detail: &str) {
+    if is_quiet() {
+        return;
+    }
+    println!(
+        "{} {}: {}",
+        muted(chrono::Local::now().format("[%H:%M:%S]")),
+        name,
+        detail
+    );
+}
+
 /// Create a progress spinner
 pub fn spinner(message: &str) -> indicatif::ProgressBar {
     let spinner = indicatif::ProgressBar::new_spinner();
+    if is_quiet() || is_json_stream() {
+        spinner.set_draw_target(ProgressDrawTarget::hidden());
+    }
     spinner.set_style(
         indicatif::ProgressStyle::default_spinner()
             .template("{spinner:.green} {msg}")
@@ -115,10 +406,277 @@ pub fn multi_progress() -> indicatif::MultiProgress {
     indicatif::MultiProgress::new()
 }
 
+/// Live install progress: a status line, a bar for the current phase and one
+/// bar per download in flight
+///
+/// Bars draw to stderr and disappear when it is not a terminal or with `--quiet`.
+pub struct InstallProgress {
+    multi: MultiProgress,
+    status: ProgressBar,
+    /// Bar of the running download or extract phase
+    phase: Mutex<Option<ProgressBar>>,
+    downloads: Mutex<HashMap<String, ProgressBar>>,
+    /// Bytes received across all downloads
+    received: AtomicU64,
+}
+
+impl InstallProgress {
+    pub fn new(message: &str) -> Self {
+        let target = if is_quiet() { ProgressDrawTarget::hidden() } else { ProgressDrawTarget::stderr() };
+        let multi = MultiProgress::with_draw_target(target);
+        let status = multi.add(spinner(message));
+        Self {
+            multi,
+            status,
+            phase: Mutex::new(None),
+            downloads: Mutex::new(HashMap::new()),
+            received: AtomicU64::new(0),
+        }
+    }
+
+    /// Replace the status line
+    pub fn set_message(&self, message: &str) {
+        self.status.set_message(message.to_string());
+    }
+
+    /// Hide the bars while `f` prints
+    pub fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+        self.multi.suspend(f)
+    }
+
+    /// Remove every bar
+    pub fn finish_and_clear(&self) {
+        for (_, bar) in self.downloads.lock().drain() {
+            bar.finish_and_clear();
+        }
+        if let Some(bar) = self.phase.lock().take() {
+            bar.finish_and_clear();
+        }
+        self.status.finish_and_clear();
+    }
+}
+
+impl Progress for InstallProgress {
+    fn phase_started(&self, phase: Phase, total: Option<usize>) {
+        let (message, template) = match phase {
+            Phase::Resolve => ("Resolving dependencies...", None),
+            Phase::Download => ("Downloading packages...", Some("  [{bar:30.cyan/blue}] {pos}/{len} packages  {msg}")),
+            Phase::Extract => ("Extracting packages...", Some("  [{bar:30.cyan/blue}] {pos}/{len} packages")),
+        };
+        self.set_message(message);
+
+        let Some(template) = template else { return };
+        let bar = self.multi.add(ProgressBar::new(total.unwrap_or(0) as u64));
+        bar.set_style(ProgressStyle::default_bar().template(template).unwrap().progress_chars("█▓▒░"));
+        if let Some(previous) = self.phase.lock().replace(bar) {
+            previous.finish_and_clear();
+        }
+    }
+
+    fn package_done(&self, phase: Phase, package: &str) {
+        match phase {
+            Phase::Resolve => {
+                self.status.inc(1);
+                self.set_message(&format!("Resolving dependencies... {} packages", self.status.position()));
+            }
+            Phase::Download | Phase::Extract => {
+                if let Some(bar) = self.downloads.lock().remove(package) {
+                    bar.finish_and_clear();
+                    self.multi.remove(&bar);
+                }
+                if let Some(ref bar) = *self.phase.lock() {
+                    bar.inc(1);
+                }
+            }
+        }
+    }
+
+    fn download_started(&self, package: &str, size: Option<u64>) {
+        let bar = self.multi.add(ProgressBar::new(size.unwrap_or(0)));
+        let template = match size {
+            Some(_) => "    {msg:40!} {bytes:>10}/{total_bytes:<10}",
+            None => "    {msg:40!} {bytes:>10}",
+        };
+        bar.set_style(ProgressStyle::default_bar().template(template).unwrap());
+        bar.set_message(package.to_string());
+        self.downloads.lock().insert(package.to_string(), bar);
+    }
+
+    fn download_progress(&self, package: &str, received: u64) {
+        let downloads = self.downloads.lock();
+        let Some(bar) = downloads.get(package) else { return };
+        let delta = received.saturating_sub(bar.position());
+        bar.set_position(received);
+        let total = self.received.fetch_add(delta, Ordering::Relaxed) + delta;
+        if let Some(ref phase) = *self.phase.lock() {
+            phase.set_message(format_bytes(total));
+        }
+    }
+
+    fn phase_finished(&self, phase: Phase) {
+        if phase == Phase::Resolve {
+            return;
+        }
+        if let Some(bar) = self.phase.lock().take() {
+            bar.finish_and_clear();
+            self.multi.remove(&bar);
+        }
+    }
+}
+
+/// Emit a `download_progress` event at most once per this many bytes per package
+const STREAM_PROGRESS_STEP: u64 = 64 * 1024;
+
+/// Install progress reported as `--json-stream` events
+#[derive(Default)]
+pub struct EventStream {
+    /// Bytes received per package at its last `download_progress` event
+    reported: Mutex<HashMap<String, u64>>,
+}
+
+impl EventStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `received` has moved far enough past the last event for `package`
+    fn should_report(&self, package: &str, received: u64) -> bool {
+        let mut reported = self.reported.lock();
+        let last = reported.entry(package.to_string()).or_insert(0);
+        if received < *last + STREAM_PROGRESS_STEP {
+            return false;
+        }
+        *last = received;
+        true
+    }
+}
+
+fn phase_name(phase: Phase) -> &'static str {
+    match phase {
+        Phase::Resolve => "resolve",
+        Phase::Download => "download",
+        Phase::Extract => "extract",
+    }
+}
+
+impl Progress for EventStream {
+    fn phase_started(&self, phase: Phase, total: Option<usize>) {
+        event(&format!("{}_start", phase_name(phase)), serde_json::json!({ "total": total }));
+    }
+
+    fn package_done(&self, phase: Phase, package: &str) {
+        let name = match phase {
+            Phase::Resolve => "package_resolved",
+            Phase::Download => {
+                self.reported.lock().remove(package);
+                "package_downloaded"
+            }
+            Phase::Extract => "package_extracted",
+        };
+        event(name, serde_json::json!({ "package": package }));
+    }
+
+    fn download_started(&self, package: &str, size: Option<u64>) {
+        event(
+            "download_progress",
+            serde_json::json!({ "package": package, "received": 0, "size": size }),
+        );
+    }
+
+    fn download_progress(&self, package: &str, received: u64) {
+        if self.should_report(package, received) {
+            event(
+                "download_progress",
+                serde_json::json!({ "package": package, "received": received }),
+            );
+        }
+    }
+
+    fn phase_finished(&self, phase: Phase) {
+        event(&format!("{}_complete", phase_name(phase)), serde_json::Value::Null);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_color_choice() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+        };
+
+        assert!(ColorChoice::Auto.enabled(true, env(&[])));
+        assert!(!ColorChoice::Auto.enabled(false, env(&[])));
+        assert!(!ColorChoice::Auto.enabled(true, env(&[("NO_COLOR", "1")])));
+        assert!(ColorChoice::Auto.enabled(true, env(&[("NO_COLOR", "")])));
+        assert!(!ColorChoice::Auto.enabled(true, env(&[("CLICOLOR", "0")])));
+        assert!(!ColorChoice::Auto.enabled(true, env(&[("TERM", "dumb")])));
+        assert!(ColorChoice::Auto.enabled(false, env(&[("CLICOLOR_FORCE", "1")])));
+        assert!(!ColorChoice::Auto.enabled(false, env(&[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")])));
+        assert!(ColorChoice::Always.enabled(false, env(&[("NO_COLOR", "1")])));
+        assert!(!ColorChoice::Never.enabled(true, env(&[])));
+    }
+
+    #[test]
+    fn test_verbosity_gating() {
+        let context = |quiet, verbose| OutputContext {
+            verbosity: Verbosity::from_flags(quiet, verbose),
+            ..Default::default()
+        };
+
+        let quiet = context(true, true);
+        assert_eq!(quiet.verbosity, Verbosity::Quiet);
+        assert!(!quiet.prints(Verbosity::Normal) && !quiet.prints(Verbosity::Verbose));
+        assert_eq!(quiet.verbosity.log_filter(), "error");
+
+        let normal = context(false, false);
+        assert!(normal.prints(Verbosity::Normal) && !normal.prints(Verbosity::Verbose));
+        assert_eq!(normal.verbosity.log_filter(), "warn");
+
+        let verbose = context(false, true);
+        assert!(verbose.prints(Verbosity::Normal) && verbose.prints(Verbosity::Verbose));
+        assert_eq!(verbose.verbosity.log_filter(), "warn,velocity=debug");
+    }
+
+    #[test]
+    fn test_reporter_choice() {
+        assert_eq!(Reporter::choose(None, false, true), Reporter::Default);
+        assert_eq!(Reporter::choose(None, true, true), Reporter::SummaryOnly);
+        assert_eq!(Reporter::choose(None, false, false), Reporter::SummaryOnly);
+        assert_eq!(Reporter::choose(Some(Reporter::Default), true, false), Reporter::Default);
+        assert_eq!(Reporter::choose(Some(Reporter::SummaryOnly), false, true), Reporter::SummaryOnly);
+    }
+
+    #[test]
+    fn test_install_progress_tracks_downloads() {
+        let progress = InstallProgress::new("Installing...");
+
+        progress.phase_started(Phase::Resolve, None);
+        progress.package_done(Phase::Resolve, "left");
+        progress.package_done(Phase::Resolve, "right");
+        assert_eq!(progress.status.position(), 2);
+
+        progress.phase_started(Phase::Download, Some(2));
+        progress.download_started("left", Some(100));
+        progress.download_progress("left", 40);
+        progress.download_progress("left", 100);
+        progress.download_started("right", None);
+        progress.download_progress("right", 25);
+        assert_eq!(progress.received.load(Ordering::Relaxed), 125);
+        assert_eq!(progress.phase.lock().as_ref().unwrap().message(), "125 B");
+
+        progress.package_done(Phase::Download, "left");
+        assert!(!progress.downloads.lock().contains_key("left"));
+        assert_eq!(progress.phase.lock().as_ref().unwrap().position(), 1);
+
+        progress.phase_finished(Phase::Download);
+        assert!(progress.phase.lock().is_none());
+        progress.finish_and_clear();
+        assert!(progress.downloads.lock().is_empty());
+    }
+
     #[test]
     fn test_format_duration() {
         assert_eq!(format_duration(500), "500ms");
@@ -132,4 +690,27 @@ mod tests {
         assert_eq!(format_bytes(1500), "1.5 KB");
         assert_eq!(format_bytes(1500000), "1.4 MB");
     }
+
+    #[test]
+    fn test_event_line() {
+        let line = event_line("package_resolved", serde_json::json!({ "package": "react" }));
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["event"], "package_resolved");
+        assert_eq!(value["package"], "react");
+        assert!(value["timestamp"].is_string());
+
+        let wrapped: serde_json::Value =
+            serde_json::from_str(&event_line("result", serde_json::json!([1, 2]))).unwrap();
+        assert_eq!(wrapped["data"], serde_json::json!([1, 2]));
+    }
+
+    #[test]
+    fn test_event_stream_throttles_download_progress() {
+        let stream = EventStream::new();
+        assert!(!stream.should_report("a@1.0.0", 1024));
+        assert!(stream.should_report("a@1.0.0", STREAM_PROGRESS_STEP));
+        assert!(!stream.should_report("a@1.0.0", STREAM_PROGRESS_STEP + 1024));
+        assert!(stream.should_report("b@1.0.0", 2 * STREAM_PROGRESS_STEP));
+    }
 }
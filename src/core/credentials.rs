@@ -0,0 +1,288 @@
+//! Stored registry credentials
+//!
+//! `velocity login` saves one token per registry URL in `credentials.toml`
+//! under the user config directory, readable only by the owner. With
+//! `--keychain` the token itself goes to the OS keychain (macOS `security`,
+//! or `secret-tool` on Linux) and the file only records that it is there.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, OnceLock};
+
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use super::{VelocityError, VelocityResult};
+
+/// Keychain service name tokens are filed under
+const KEYCHAIN_SERVICE: &str = "velocity";
+
+/// A stored credential for one registry
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Credential {
+    /// The token, unless it lives in the OS keychain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+
+    /// Whether the token is stored in the OS keychain
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keychain: bool,
+
+    /// User the token belongs to, when the registry reported it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Credentials file contents
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Credentials {
+    /// Credentials keyed by registry URL (without trailing slash)
+    #[serde(default)]
+    pub registries: BTreeMap<String, Credential>,
+
+    /// Scope registries chosen at login (e.g. "@myorg" -> URL)
+    #[serde(default)]
+    pub scopes: BTreeMap<String, String>,
+}
+
+impl Credentials {
+    /// Location of the credentials file (`VELOCITY_CREDENTIALS_PATH` overrides it)
+    pub fn path() -> VelocityResult<PathBuf> {
+        if let Ok(path) = std::env::var("VELOCITY_CREDENTIALS_PATH") {
+            return Ok(PathBuf::from(path));
+        }
+        let project_dirs = ProjectDirs::from("com", "velocity", "velocity")
+            .ok_or_else(|| VelocityError::config("Could not determine config directory"))?;
+        Ok(project_dirs.config_dir().join("credentials.toml"))
+    }
+
+    /// Load stored credentials; missing file means none
+    pub fn load() -> VelocityResult<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    /// Write the credentials file, readable by the current user only
+    pub fn save(&self) -> VelocityResult<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path)?;
+        #[cfg(unix)]
+        {
+            // The mode above only applies to newly created files
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(toml::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// Store a token for a registry, in the keychain if asked
+    pub fn set(&mut self, registry: &str, token: &str, username: Option<String>, keychain: bool) -> VelocityResult<()> {
+        let registry = normalize(registry);
+        if keychain {
+            keychain_store(&registry, token)?;
+        } else if self.registries.get(&registry).is_some_and(|c| c.keychain) {
+            keychain_delete(&registry)?;
+        }
+
+        self.registries.insert(registry, Credential {
+            token: (!keychain).then(|| token.to_string()),
+            keychain,
+            username,
+        });
+        Ok(())
+    }
+
+    /// Forget a registry's credential, returning the token it held
+    pub fn remove(&mut self, registry: &str) -> VelocityResult<Option<String>> {
+        let registry = normalize(registry);
+        let Some(credential) = self.registries.remove(&registry) else {
+            return Ok(None);
+        };
+        self.scopes.retain(|_, url| normalize(url) != registry);
+
+        if credential.keychain {
+            let token = keychain_lookup(&registry).ok();
+            keychain_delete(&registry)?;
+            return Ok(token);
+        }
+        Ok(credential.token)
+    }
+
+    /// Tokens stored in the credentials file, by registry URL
+    pub fn tokens(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        self.registries
+            .iter()
+            .filter_map(|(registry, credential)| Some((registry.clone(), credential.token.clone()?)))
+    }
+
+    /// Registries whose token is in the OS keychain, to be read when first needed
+    pub fn keychain_tokens(&self) -> KeychainTokens {
+        KeychainTokens {
+            entries: self
+                .registries
+                .iter()
+                .filter(|(_, credential)| credential.token.is_none() && credential.keychain)
+                .map(|(registry, _)| (registry.clone(), Arc::default()))
+                .collect(),
+        }
+    }
+}
+
+/// Keychain-stored tokens, each looked up the first time it is asked for
+///
+/// Reading the keychain runs `security` or `secret-tool`, so commands that
+/// never authenticate never pay for it. Clones share the looked-up tokens.
+#[derive(Debug, Clone, Default)]
+pub struct KeychainTokens {
+    entries: Vec<(String, Arc<OnceLock<Option<String>>>)>,
+}
+
+impl KeychainTokens {
+    /// Registry URLs with a token in the keychain
+    pub fn registries(&self) -> impl Iterator<Item = &str> + '_ {
+        self.entries.iter().map(|(registry, _)| registry.as_str())
+    }
+
+    /// The token for a registry, reading the keychain on first use
+    pub fn get(&self, registry: &str) -> Option<&String> {
+        let registry = registry.trim_end_matches('/');
+        let (_, token) = self.entries.iter().find(|(entry, _)| entry == registry)?;
+        token
+            .get_or_init(|| {
+                keychain_lookup(registry)
+                    .map_err(|e| tracing::warn!("Could not read the {} token from the keychain: {}", registry, e))
+                    .ok()
+            })
+            .as_ref()
+    }
+}
+
+/// Registry URLs are stored without a trailing slash
+pub fn normalize(registry: &str) -> String {
+    registry.trim_end_matches('/').to_string()
+}
+
+fn keychain_store(registry: &str, token: &str) -> VelocityResult<()> {
+    if cfg!(target_os = "macos") {
+        // A trailing `-w` makes `security` prompt for the token, keeping it out of argv
+        let input = format!("{token}\n{token}\n");
+        run_keychain(
+            "security",
+            &["add-generic-password", "-U", "-s", KEYCHAIN_SERVICE, "-a", registry, "-w"],
+            Some(&input),
+        )?;
+    } else {
+        let label = format!("velocity token for {}", registry);
+        run_keychain(
+            "secret-tool",
+            &["store", "--label", &label, "service", KEYCHAIN_SERVICE, "registry", registry],
+            Some(token),
+        )?;
+    }
+    Ok(())
+}
+
+fn keychain_lookup(registry: &str) -> VelocityResult<String> {
+    let token = if cfg!(target_os = "macos") {
+        run_keychain("security", &["find-generic-password", "-s", KEYCHAIN_SERVICE, "-a", registry, "-w"], None)?
+    } else {
+        run_keychain("secret-tool", &["lookup", "service", KEYCHAIN_SERVICE, "registry", registry], None)?
+    };
+    Ok(token.trim().to_string())
+}
+
+fn keychain_delete(registry: &str) -> VelocityResult<()> {
+    if cfg!(target_os = "macos") {
+        run_keychain("security", &["delete-generic-password", "-s", KEYCHAIN_SERVICE, "-a", registry], None)?;
+    } else {
+        run_keychain("secret-tool", &["clear", "service", KEYCHAIN_SERVICE, "registry", registry], None)?;
+    }
+    Ok(())
+}
+
+/// Run a keychain helper, feeding it `input` on stdin, and return its stdout
+fn run_keychain(program: &str, args: &[&str], input: Option<&str>) -> VelocityResult<String> {
+    if cfg!(windows) {
+        return Err(VelocityError::UnsupportedPlatform("OS keychain storage is not supported on Windows".to_string()));
+    }
+    let program_path = which::which(program)
+        .map_err(|_| VelocityError::config(format!("Keychain storage needs '{}', which was not found", program)))?;
+
+    let mut child = Command::new(program_path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(VelocityError::config(format!(
+            "'{}' failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_remove() {
+        let mut credentials = Credentials::default();
+        credentials.set("https://npm.corp.example/", "secret", Some("ana".to_string()), false).unwrap();
+        credentials.scopes.insert("@corp".to_string(), "https://npm.corp.example".to_string());
+
+        let content = toml::to_string_pretty(&credentials).unwrap();
+        let loaded: Credentials = toml::from_str(&content).unwrap();
+        assert_eq!(
+            loaded.tokens().collect::<Vec<_>>(),
+            vec![("https://npm.corp.example".to_string(), "secret".to_string())]
+        );
+
+        let mut loaded = loaded;
+        assert_eq!(loaded.remove("https://npm.corp.example").unwrap().as_deref(), Some("secret"));
+        assert!(loaded.registries.is_empty());
+        assert!(loaded.scopes.is_empty());
+    }
+
+    #[test]
+    fn test_keychain_tokens_not_read_up_front() {
+        let mut credentials = Credentials::default();
+        credentials.registries.insert(
+            "https://npm.corp.example".to_string(),
+            Credential { token: None, keychain: true, username: None },
+        );
+        credentials.set("https://registry.npmjs.org", "plain", None, false).unwrap();
+
+        assert_eq!(credentials.tokens().count(), 1);
+        let keychain = credentials.keychain_tokens();
+        assert_eq!(keychain.registries().collect::<Vec<_>>(), vec!["https://npm.corp.example"]);
+        assert!(keychain.entries.iter().all(|(_, token)| token.get().is_none()));
+        assert!(keychain.get("https://registry.npmjs.org").is_none());
+    }
+}
@@ -6,10 +6,12 @@ use std::sync::Arc;
 use crate::cache::CacheManager;
 use crate::core::{Config, Lockfile, PackageJson, VelocityError, VelocityResult};
 use crate::installer::Installer;
+use crate::registry::http::OptimizedHttpClient;
 use crate::registry::RegistryClient;
-use crate::resolver::Resolver;
-use crate::security::SecurityManager;
-use crate::workspace::WorkspaceManager;
+use crate::resolver::{Resolver, WorkspaceMembers};
+use crate::security::{Policy, SecurityManager};
+use crate::utils::PerformanceMetrics;
+use crate::workspace::{self, WorkspaceManager};
 
 /// Main engine for Velocity operations
 pub struct Engine {
@@ -19,6 +21,9 @@ pub struct Engine {
     /// Configuration
     pub config: Config,
 
+    /// HTTP client shared by every request of the run
+    pub http: OptimizedHttpClient,
+
     /// Registry client
     pub registry: Arc<RegistryClient>,
 
@@ -28,6 +33,9 @@ pub struct Engine {
     /// Security manager
     pub security: Arc<SecurityManager>,
 
+    /// Package policy
+    pub policy: Arc<Policy>,
+
     /// Workspace manager (if applicable)
     pub workspace: Option<WorkspaceManager>,
 }
@@ -37,13 +45,26 @@ impl Engine {
     pub async fn new(project_dir: &Path) -> VelocityResult<Self> {
         let project_dir = project_dir.canonicalize().unwrap_or_else(|_| project_dir.to_path_buf());
         let config = Config::load(&project_dir)?;
+        Self::with_config(&project_dir, config).await
+    }
+
+    /// Create an engine with an already loaded (and possibly adjusted) configuration
+    pub async fn with_config(project_dir: &Path, config: Config) -> VelocityResult<Self> {
+        let project_dir = project_dir.canonicalize().unwrap_or_else(|_| project_dir.to_path_buf());
 
         let cache_dir = config.cache_dir()?;
         let cache = Arc::new(CacheManager::new(&cache_dir, &config.cache)?);
 
-        let registry = Arc::new(RegistryClient::new(&config.registry, cache.clone())?);
+        let http = OptimizedHttpClient::new(&config.network, Arc::new(PerformanceMetrics::new()))?;
+
+        // Publish times for the release cooldown are only in full packuments
+        let registry = Arc::new(
+            RegistryClient::new(&config.registry, &config.network, http.clone(), cache.clone())?
+                .with_full_metadata(config.security.release_cooldown_hours > 0),
+        );
 
         let security = Arc::new(SecurityManager::new(&config.security));
+        let policy = Arc::new(Policy::load(&config, &cache_dir).await?);
 
         // Check for workspace
         let workspace = if let Ok(pkg) = PackageJson::load(&project_dir) {
@@ -59,9 +80,11 @@ impl Engine {
         Ok(Self {
             project_dir,
             config,
+            http,
             registry,
             cache,
             security,
+            policy,
             workspace,
         })
     }
@@ -76,29 +99,72 @@ impl Engine {
         PackageJson::load(&self.project_dir)
     }
 
+    /// Where this project's lockfile lives
+    pub fn lockfile_path(&self) -> PathBuf {
+        self.config.lockfile_path(&self.project_dir)
+    }
+
     /// Get the lockfile for this project
     pub fn lockfile(&self) -> VelocityResult<Option<Lockfile>> {
-        Lockfile::load(&self.project_dir)
+        Lockfile::load(&self.lockfile_path())
     }
 
-    /// Create a dependency resolver
+    /// Create a dependency resolver, aware of the workspace's members
     pub fn resolver(&self) -> Resolver {
-        Resolver::new(self.registry.clone(), self.cache.clone())
+        let resolver = Resolver::new(self.registry.clone(), self.cache.clone())
+            .with_metadata_concurrency(self.config.network.metadata_concurrency)
+            .with_cooldown(self.security.release_cooldown())
+            .with_policy(self.policy.clone())
+            .with_base_dir(self.project_dir.clone());
+        let Some(ref workspace) = self.workspace else {
+            return resolver;
+        };
+        match workspace.package_jsons() {
+            Ok(members) => resolver.with_workspace(WorkspaceMembers::new(workspace.root(), members)),
+            Err(e) => {
+                tracing::warn!("Could not read workspace packages: {}", e);
+                resolver
+            }
+        }
     }
 
     /// Create an installer
+    ///
+    /// At the root of a workspace with a shared lockfile, it links every
+    /// member's dependencies along with the root's.
     pub fn installer(&self) -> Installer {
-        Installer::new(
+        let installer = Installer::new(
             self.project_dir.clone(),
             self.cache.clone(),
             self.security.clone(),
-            self.config.network.concurrency,
+            self.config.network.download_concurrency,
         )
+        .with_install_config(self.config.install.clone())
+        .with_network_config(self.config.network.clone())
+        .with_registry_config(self.config.registry.clone())
+        .with_http_client(self.http.clone());
+
+        match self.workspace {
+            Some(ref workspace) if workspace.shared_lockfile() => match workspace.package_jsons() {
+                Ok(members) => installer.with_workspace(members, workspace.should_hoist()),
+                Err(e) => {
+                    tracing::warn!("Could not read workspace packages: {}", e);
+                    installer
+                }
+            },
+            _ => installer,
+        }
+    }
+
+    /// Whether installs here cover the whole workspace: this is its root and
+    /// the members share its lockfile
+    pub fn installs_workspace(&self) -> bool {
+        self.workspace.as_ref().is_some_and(|workspace| workspace.shared_lockfile())
     }
 
     /// Get node_modules path
     pub fn node_modules_path(&self) -> PathBuf {
-        self.project_dir.join("node_modules")
+        self.config.install.modules_dir(&self.project_dir)
     }
 
     /// Check if node_modules exists
@@ -125,6 +191,50 @@ impl Engine {
         }
     }
 
+    /// The root project and workspace members picked by `--filter` selectors
+    /// (see [`workspace::select_projects`]); all of them when there are none
+    pub fn select_projects(&self, filters: &[String]) -> VelocityResult<Vec<(PathBuf, PackageJson)>> {
+        let mut projects = vec![(self.project_dir.clone(), self.package_json()?)];
+        if let Some(ref workspace) = self.workspace {
+            projects.extend(workspace.package_jsons()?);
+        }
+        workspace::select_projects(&self.project_dir, projects, filters)
+    }
+
+    /// Direct dependencies of every package.json sharing the project lockfile
+    ///
+    /// These are the roots lockfile pruning walks from: the project itself plus,
+    /// for workspaces with a shared lockfile, every member.
+    pub fn importers(&self) -> VelocityResult<Vec<(String, String)>> {
+        self.importers_with(PackageJson::all_dependencies)
+    }
+
+    /// Production dependencies of every package.json sharing the project lockfile
+    ///
+    /// Locked packages these do not reach are marked dev-only.
+    pub fn production_importers(&self) -> VelocityResult<Vec<(String, String)>> {
+        self.importers_with(PackageJson::production_dependencies)
+    }
+
+    fn importers_with(
+        &self,
+        dependencies: fn(&PackageJson) -> std::collections::HashMap<String, String>,
+    ) -> VelocityResult<Vec<(String, String)>> {
+        let mut importers: Vec<(String, String)> = dependencies(&self.package_json()?).into_iter().collect();
+
+        if let Some(ref workspace) = self.workspace {
+            if workspace.shared_lockfile() {
+                for (_, pkg) in workspace.package_jsons()? {
+                    importers.extend(dependencies(&pkg));
+                }
+            }
+        }
+
+        importers.sort();
+        importers.dedup();
+        Ok(importers)
+    }
+
     /// Ensure project is initialized
     pub fn ensure_initialized(&self) -> VelocityResult<()> {
         if !self.is_initialized() {
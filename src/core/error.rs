@@ -40,6 +40,9 @@ pub enum VelocityError {
         found: String,
     },
 
+    #[error("Unable to resolve {package}; no version satisfies every requirement:{details}")]
+    ResolutionConflict { package: String, details: String },
+
     #[error("Circular dependency detected: {0}")]
     CircularDependency(String),
 
@@ -50,15 +53,40 @@ pub enum VelocityError {
         actual: String,
     },
 
+    #[error("Provenance attestation of {package} does not match: {reason}")]
+    ProvenanceInvalid { package: String, reason: String },
+
+    #[error("{package} violates the package policy: {reason}")]
+    PolicyViolation { package: String, reason: String },
+
+    #[error("{package} may only come from {registry} (registry.associations), but resolved to {tarball}")]
+    RegistryAssociation { package: String, registry: String, tarball: String },
+
     #[error("Path traversal attack detected in package {package}: {path}")]
     PathTraversal { package: String, path: String },
 
+    #[error("Refusing to extract {package}: it unpacks to {details}")]
+    PackageTooLarge { package: String, details: String },
+
     #[error("Permission denied: {permission} for package {package}")]
     PermissionDenied { package: String, permission: String },
 
+    #[error("Package '{package}' shadows the Node.js core module of the same name. Add it to security.trusted_packages to allow it.")]
+    CoreModuleShadowing { package: String },
+
+    #[error("Cannot sandbox the install scripts of {package}: {restriction} isolation is not available on this system (security.script_sandbox = \"strict\")")]
+    SandboxUnavailable { package: String, restriction: String },
+
     #[error("Script execution failed: {script} in {package}")]
     ScriptFailed { package: String, script: String },
 
+    #[error("Script timed out after {seconds}s: {script} in {package}")]
+    ScriptTimeout {
+        package: String,
+        script: String,
+        seconds: u64,
+    },
+
     #[error("Configuration error: {0}")]
     Config(String),
 
@@ -86,6 +114,21 @@ pub enum VelocityError {
     #[error("Network error: {0}")]
     Network(String),
 
+    #[error("TLS error connecting to {host}: {message}")]
+    Tls { host: String, message: String },
+
+    #[error("TLS pin check failed for {host}: {details}. The key may have been rotated, or the connection is being intercepted.")]
+    TlsPinMismatch { host: String, details: String },
+
+    #[error("Refusing to use plain HTTP for {url}. Add '{origin}' to network.allow_insecure_registries to allow it.")]
+    InsecureRegistry { url: String, origin: String },
+
+    #[error("{package} is fetched over plain HTTP but has no sha512 or sha256 integrity hash to verify it")]
+    IntegrityRequired { package: String },
+
+    #[error("{package}: {resource} is not cached and offline mode is on. Run once while online, or drop --offline.")]
+    Offline { package: String, resource: String },
+
     #[error("Timeout: operation took too long")]
     Timeout,
 
@@ -153,7 +196,16 @@ impl VelocityError {
             VelocityError::PackageNotFound(_) => 2,
             VelocityError::VersionNotFound { .. } => 2,
             VelocityError::IntegrityCheckFailed { .. } => 3,
+            VelocityError::TlsPinMismatch { .. } => 3,
+            VelocityError::InsecureRegistry { .. } => 3,
+            VelocityError::IntegrityRequired { .. } => 3,
+            VelocityError::ProvenanceInvalid { .. } => 3,
             VelocityError::PermissionDenied { .. } => 4,
+            VelocityError::CoreModuleShadowing { .. } => 4,
+            VelocityError::SandboxUnavailable { .. } => 4,
+            VelocityError::PolicyViolation { .. } => 4,
+            VelocityError::PackageTooLarge { .. } => 4,
+            VelocityError::RegistryAssociation { .. } => 4,
             VelocityError::UserCancelled => 130,
             VelocityError::NotInitialized => 5,
             _ => 1,
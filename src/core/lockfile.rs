@@ -2,21 +2,31 @@
 //!
 //! Provides deterministic, tamper-resistant lockfile format.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::path::Path;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
 
 use crate::core::{VelocityError, VelocityResult};
+use crate::resolver::tarball::is_tarball_spec;
+use crate::resolver::VersionConstraint;
 
-/// Lockfile version
-pub const LOCKFILE_VERSION: u32 = 1;
+/// Lockfile version written by this release
+///
+/// Version 2 records the locked version each dependency edge resolves to;
+/// version 1 lockfiles are still read, and their edges resolve by range.
+pub const LOCKFILE_VERSION: u32 = 2;
 
 /// Lockfile filename
 pub const LOCKFILE_NAME: &str = "velocity.lock";
 
+/// Identifier of the published JSON Schema for the current lockfile version
+pub const LOCKFILE_SCHEMA_ID: &str = "https://velocity.dev/schemas/velocity.lock/v2.json";
+
 /// Main lockfile structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(title = "velocity.lock")]
 pub struct Lockfile {
     /// Lockfile format version
     pub version: u32,
@@ -35,11 +45,15 @@ pub struct Lockfile {
 }
 
 /// A locked package with resolved version and integrity
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 pub struct LockedPackage {
     /// Package name
     pub name: String,
 
+    /// Registry package installed under this name through an `npm:` alias
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias_of: Option<String>,
+
     /// Resolved version
     pub version: String,
 
@@ -49,9 +63,9 @@ pub struct LockedPackage {
     /// Integrity hash (sha512 or sha256)
     pub integrity: String,
 
-    /// Dependencies (name -> version)
+    /// Dependency edges, each with the range declared and the version locked for it
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub dependencies: Vec<String>,
+    pub dependencies: Vec<LockedDependency>,
 
     /// Peer dependencies (name -> version)
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -65,6 +79,10 @@ pub struct LockedPackage {
     #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub has_scripts: bool,
 
+    /// Hash of the install scripts approved to run for this package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approved_scripts: Option<String>,
+
     /// CPU architectures this package supports
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub cpu: Vec<String>,
@@ -72,10 +90,148 @@ pub struct LockedPackage {
     /// OS platforms this package supports
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub os: Vec<String>,
+
+    /// Only reachable through devDependencies; production installs skip it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dev: bool,
+
+    /// Source repository and commit a provenance attestation names; the
+    /// attestation's signature is not verified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<String>,
+}
+
+/// A dependency edge of a locked package
+///
+/// Written `name@range`, followed by ` => version` naming the locked package the
+/// edge resolves to. Version 1 lockfiles have no locked version.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
+pub struct LockedDependency {
+    /// Name the dependency is installed under
+    pub name: String,
+
+    /// Range the dependent declares (`*` when none was recorded)
+    pub range: String,
+
+    /// Version of the locked package the edge resolves to
+    pub version: Option<String>,
+}
+
+impl LockedDependency {
+    /// An edge not yet pinned to a locked version
+    pub fn new(name: &str, range: &str) -> Self {
+        Self { name: name.to_string(), range: range.to_string(), version: None }
+    }
+
+    /// The range as a version constraint, looking through `npm:` aliases
+    pub fn constraint(&self) -> &str {
+        crate::resolver::version::parse_alias(&self.range).map_or(&self.range, |(_, range)| range)
+    }
+}
+
+impl From<&str> for LockedDependency {
+    fn from(edge: &str) -> Self {
+        let (spec, version) = match edge.rsplit_once(" => ") {
+            Some((spec, version)) => (spec, Some(version.trim().to_string())),
+            None => (edge, None),
+        };
+        let (name, range) = crate::utils::parse_package_spec(spec.trim());
+        Self { name, range: range.unwrap_or_else(|| "*".to_string()), version }
+    }
+}
+
+impl From<String> for LockedDependency {
+    fn from(edge: String) -> Self {
+        Self::from(edge.as_str())
+    }
+}
+
+impl From<LockedDependency> for String {
+    fn from(edge: LockedDependency) -> Self {
+        edge.to_string()
+    }
+}
+
+impl std::fmt::Display for LockedDependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.name, self.range)?;
+        if let Some(ref version) = self.version {
+            write!(f, " => {}", version)?;
+        }
+        Ok(())
+    }
+}
+
+impl JsonSchema for LockedDependency {
+    fn schema_name() -> String {
+        "LockedDependency".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        let mut schema = String::json_schema(gen).into_object();
+        schema.metadata().description =
+            Some("Dependency edge: `name@range`, then ` => version` of the locked package it resolves to".to_string());
+        schema.into()
+    }
+}
+
+/// An os/cpu pair in Node.js naming (`process.platform`, `process.arch`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub os: String,
+    pub cpu: String,
+}
+
+impl Platform {
+    /// The platform velocity is running on
+    pub fn current() -> Self {
+        let os = match std::env::consts::OS {
+            "windows" => "win32",
+            "macos" => "darwin",
+            other => other,
+        };
+        let cpu = match std::env::consts::ARCH {
+            "x86_64" => "x64",
+            "aarch64" => "arm64",
+            "x86" => "ia32",
+            "powerpc64" => "ppc64",
+            other => other,
+        };
+        Self { os: os.to_string(), cpu: cpu.to_string() }
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.os, self.cpu)
+    }
+}
+
+impl LockedPackage {
+    /// Registry name of the package, looking through aliases
+    pub fn package_name(&self) -> &str {
+        self.alias_of.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Whether the package's `os` and `cpu` fields allow installing it on `platform`
+    pub fn supports(&self, platform: &Platform) -> bool {
+        allowed(&self.os, &platform.os) && allowed(&self.cpu, &platform.cpu)
+    }
+}
+
+/// npm's os/cpu matching: an empty list allows everything, `!value` excludes,
+/// and any plain value makes the list an allow-list
+fn allowed(list: &[String], value: &str) -> bool {
+    if list.iter().any(|entry| entry.strip_prefix('!') == Some(value)) {
+        return false;
+    }
+    let mut allow = list.iter().filter(|entry| !entry.starts_with('!')).peekable();
+    allow.peek().is_none() || allow.any(|entry| entry == value)
 }
 
 /// Workspace package entry
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct WorkspacePackage {
     /// Relative path to the package
     pub path: String,
@@ -105,14 +261,13 @@ impl Lockfile {
         Self::default()
     }
 
-    /// Load lockfile from a directory
-    pub fn load(dir: &Path) -> VelocityResult<Option<Self>> {
-        let path = dir.join(LOCKFILE_NAME);
+    /// Load a lockfile; `path` usually comes from `Config::lockfile_path`
+    pub fn load(path: &Path) -> VelocityResult<Option<Self>> {
         if !path.exists() {
             return Ok(None);
         }
 
-        let content = std::fs::read_to_string(&path)?;
+        let content = std::fs::read_to_string(path)?;
         let mut lockfile: Lockfile = toml::from_str(&content)?;
 
         // Verify integrity if present
@@ -126,8 +281,8 @@ impl Lockfile {
         Ok(Some(lockfile))
     }
 
-    /// Save lockfile to a directory
-    pub fn save(&mut self, dir: &Path) -> VelocityResult<()> {
+    /// Save the lockfile to `path`, creating its directory
+    pub fn save(&mut self, path: &Path) -> VelocityResult<()> {
         // Sort packages for deterministic output
         self.packages.sort_by(|a, b| {
             a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version))
@@ -138,7 +293,9 @@ impl Lockfile {
         let integrity = self.compute_integrity();
         self.integrity = Some(integrity);
 
-        let path = dir.join(LOCKFILE_NAME);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
         let content = toml::to_string_pretty(self)?;
         std::fs::write(path, content)?;
 
@@ -194,6 +351,51 @@ impl Lockfile {
         self.packages.is_empty() && self.workspaces.is_empty()
     }
 
+    /// Lock every unpinned dependency edge to the newest locked version its range accepts
+    ///
+    /// That is the version an install would link the edge to; pinning it keeps
+    /// nested trees the same when other versions of the package come and go.
+    pub fn pin_dependencies(&mut self) {
+        let pins: Vec<Vec<Option<String>>> = self
+            .packages
+            .iter()
+            .map(|pkg| {
+                pkg.dependencies
+                    .iter()
+                    .map(|dep| {
+                        if dep.version.is_some() || dep.range.starts_with("workspace:") || self.workspaces.contains_key(&dep.name) {
+                            return dep.version.clone();
+                        }
+                        self.matching_packages(&dep.name, dep.constraint())
+                            .into_iter()
+                            .filter_map(|p| semver::Version::parse(&p.version).ok())
+                            .max()
+                            .map(|v| v.to_string())
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for (pkg, versions) in self.packages.iter_mut().zip(pins) {
+            for (dep, version) in pkg.dependencies.iter_mut().zip(versions) {
+                dep.version = version;
+            }
+        }
+    }
+
+    /// Bring an older lockfile up to the current format
+    ///
+    /// Returns whether anything changed. Edges of a version 1 lockfile are pinned
+    /// to the versions an install would have linked them to.
+    pub fn upgrade(&mut self) -> bool {
+        if self.version >= LOCKFILE_VERSION {
+            return false;
+        }
+        self.pin_dependencies();
+        self.version = LOCKFILE_VERSION;
+        true
+    }
+
     /// Merge another lockfile into this one
     pub fn merge(&mut self, other: Lockfile) {
         for package in other.packages {
@@ -207,6 +409,410 @@ impl Lockfile {
         }
     }
 
+    /// Check whether lockfile text still holds git merge conflict markers
+    pub fn has_conflict_markers(content: &str) -> bool {
+        content.lines().any(|line| ConflictMarker::parse(line).is_some())
+    }
+
+    /// Parse both sides of a lockfile git left conflict markers in
+    ///
+    /// Lines outside conflict hunks belong to both sides. When every hunk is
+    /// diff3-style, the common-ancestor sections make up a third, base lockfile.
+    /// Integrity hashes are not checked, since neither side's hash covers the
+    /// text it was split out of.
+    pub fn parse_conflicted(content: &str) -> VelocityResult<ConflictSides> {
+        let (mut ours, mut theirs, mut base) = (String::new(), String::new(), String::new());
+        let (mut hunks, mut base_hunks) = (0, 0);
+        let mut section: Option<ConflictMarker> = None;
+
+        let push = |text: &mut String, line: &str| {
+            text.push_str(line);
+            text.push('\n');
+        };
+        for line in content.lines() {
+            match (ConflictMarker::parse(line), section) {
+                (Some(ConflictMarker::Ours), None) => {
+                    hunks += 1;
+                    section = Some(ConflictMarker::Ours);
+                }
+                (Some(ConflictMarker::Base), Some(ConflictMarker::Ours)) => {
+                    base_hunks += 1;
+                    section = Some(ConflictMarker::Base);
+                }
+                (Some(ConflictMarker::Theirs), Some(ConflictMarker::Ours | ConflictMarker::Base)) => {
+                    section = Some(ConflictMarker::Theirs);
+                }
+                (Some(ConflictMarker::End), Some(ConflictMarker::Theirs)) => section = None,
+                (Some(_), _) => {
+                    return Err(VelocityError::other(format!("Unbalanced conflict marker in lockfile: '{}'", line)));
+                }
+                (None, None) => {
+                    push(&mut ours, line);
+                    push(&mut theirs, line);
+                    push(&mut base, line);
+                }
+                (None, Some(ConflictMarker::Ours)) => push(&mut ours, line),
+                (None, Some(ConflictMarker::Base)) => push(&mut base, line),
+                (None, Some(_)) => push(&mut theirs, line),
+            }
+        }
+        if section.is_some() {
+            return Err(VelocityError::other("Lockfile ends inside a merge conflict"));
+        }
+
+        let parse = |text: &str, side: &str| {
+            toml::from_str::<Lockfile>(text).map_err(|e| {
+                VelocityError::other(format!("Cannot parse {} side of the lockfile conflict: {}", side, e.message()))
+            })
+        };
+        Ok(ConflictSides {
+            ours: parse(&ours, "our")?,
+            theirs: parse(&theirs, "their")?,
+            base: if hunks > 0 && base_hunks == hunks { Some(parse(&base, "base")?) } else { None },
+        })
+    }
+
+    /// Combine both sides of a lockfile conflict
+    ///
+    /// With a base to compare against, a package only one side changed keeps
+    /// that side's entries, removals included. Packages both sides changed, or
+    /// all of them without a base, keep every version either side locks. Also
+    /// returns the packages the sides dispute: both changed them, and neither
+    /// side's versions include the other's, or one version is locked with
+    /// different contents. Those need resolving again.
+    pub fn merge_conflict(sides: &ConflictSides) -> (Lockfile, BTreeSet<String>) {
+        let ConflictSides { ours, theirs, base } = sides;
+        let mut merged = ours.clone();
+        merged.integrity = None;
+        let mut disputed = BTreeSet::new();
+
+        let mut names: Vec<&str> = ours.package_names();
+        names.extend(theirs.package_names());
+        names.sort();
+        names.dedup();
+        for name in names {
+            let ours_versions = ours.find_package_versions(name);
+            let theirs_versions = theirs.find_package_versions(name);
+            if let Some(base) = base {
+                let base_versions = base.find_package_versions(name);
+                if same_entries(&theirs_versions, &base_versions) {
+                    continue;
+                }
+                if same_entries(&ours_versions, &base_versions) {
+                    merged.packages.retain(|p| p.name != name);
+                    merged.packages.extend(theirs_versions.into_iter().cloned());
+                    continue;
+                }
+            }
+            if ours_versions.is_empty() || theirs_versions.is_empty() {
+                merged.packages.extend(theirs_versions.into_iter().cloned());
+                continue;
+            }
+
+            let contains = |all: &[&LockedPackage], some: &[&LockedPackage]| {
+                some.iter().all(|p| all.iter().any(|q| q.version == p.version))
+            };
+            let diverged = !contains(&ours_versions, &theirs_versions) && !contains(&theirs_versions, &ours_versions);
+            let conflicting = ours_versions.iter().any(|p| {
+                theirs_versions.iter().any(|q| q.version == p.version && !same_contents(p, q))
+            });
+            if diverged || conflicting {
+                disputed.insert(name.to_string());
+            }
+            for package in theirs_versions {
+                if !ours_versions.iter().any(|p| p.version == package.version) {
+                    merged.packages.push(package.clone());
+                }
+            }
+        }
+
+        for (name, workspace) in &theirs.workspaces {
+            merged.workspaces.entry(name.clone()).or_insert_with(|| workspace.clone());
+        }
+        merged.pin_dependencies();
+        merged.version = LOCKFILE_VERSION.max(ours.version).max(theirs.version);
+        (merged, disputed)
+    }
+
+    /// Deterministic digest of the resolved dependency set
+    ///
+    /// Covers every package's name, version and integrity, plus workspace entries,
+    /// restricted to packages installable on `platform` (all packages when `None`).
+    /// Download URLs are left out, so mirrors of the same tarballs hash the same.
+    /// Returns the digest and the number of packages it covers.
+    pub fn digest(&self, platform: Option<&Platform>) -> (String, usize) {
+        let mut packages: Vec<&LockedPackage> = self
+            .packages
+            .iter()
+            .filter(|p| platform.is_none_or(|platform| p.supports(platform)))
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+
+        let mut workspaces: Vec<(&String, &WorkspacePackage)> = self.workspaces.iter().collect();
+        workspaces.sort_by(|a, b| a.0.cmp(b.0));
+
+        // The digest covers neither edges nor URLs, so it keeps its own format version
+        let mut content = format!(
+            "velocity-dependencies-v1\nplatform {}\n",
+            platform.map_or_else(|| "any".to_string(), |p| p.to_string())
+        );
+        for pkg in &packages {
+            content.push_str(&format!("package {}@{} {}\n", pkg.name, pkg.version, pkg.integrity));
+        }
+        for (name, workspace) in workspaces {
+            content.push_str(&format!("workspace {}@{} {}\n", name, workspace.version, workspace.path));
+        }
+
+        let hash = Sha256::digest(content.as_bytes());
+        (format!("sha256-{}", hex::encode(hash)), packages.len())
+    }
+
+    /// JSON Schema describing the lockfile format
+    pub fn json_schema() -> serde_json::Value {
+        let schema = schemars::schema_for!(Lockfile);
+        let mut value = serde_json::to_value(&schema).unwrap_or_default();
+        if let Some(obj) = value.as_object_mut() {
+            obj.insert("$id".to_string(), serde_json::json!(LOCKFILE_SCHEMA_ID));
+        }
+        value
+    }
+
+    /// Check structural validity and referential integrity
+    ///
+    /// Returns every problem found; an empty list means the lockfile is valid.
+    pub fn validate(&self) -> Vec<LockfileIssue> {
+        let mut issues = Vec::new();
+
+        if !(1..=LOCKFILE_VERSION).contains(&self.version) {
+            issues.push(LockfileIssue::new(None, format!(
+                "unsupported lockfile version {} (expected {} or older)",
+                self.version, LOCKFILE_VERSION
+            )));
+        }
+
+        if let Some(ref stored) = self.integrity {
+            if *stored != self.compute_integrity() {
+                issues.push(LockfileIssue::new(None, "integrity hash does not match content"));
+            }
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        for pkg in &self.packages {
+            let id = format!("{}@{}", pkg.name, pkg.version);
+
+            if pkg.name.is_empty() {
+                issues.push(LockfileIssue::new(Some(&id), "package name is empty"));
+            }
+            if semver::Version::parse(&pkg.version).is_err() {
+                issues.push(LockfileIssue::new(Some(&id), format!("invalid version '{}'", pkg.version)));
+            }
+            if !seen.insert(id.clone()) {
+                issues.push(LockfileIssue::new(Some(&id), "duplicate entry"));
+            }
+            if !pkg.integrity.is_empty()
+                && !["sha512-", "sha384-", "sha256-", "sha1-"].iter().any(|p| pkg.integrity.starts_with(p))
+            {
+                issues.push(LockfileIssue::new(Some(&id), format!("unrecognized integrity format '{}'", pkg.integrity)));
+            }
+
+            // Every dependency edge must resolve to a locked entry
+            for dep in &pkg.dependencies {
+                let resolves = match dep.version {
+                    Some(ref version) => self.find_package(&dep.name, version).is_some(),
+                    None => self.satisfies(&dep.name, dep.constraint()),
+                };
+                if !resolves {
+                    issues.push(LockfileIssue::new(
+                        Some(&id),
+                        format!("dependency {} does not resolve to any locked package", dep),
+                    ));
+                }
+            }
+        }
+
+        for (name, workspace) in &self.workspaces {
+            for dep in &workspace.dependencies {
+                let (dep_name, constraint) = crate::utils::parse_package_spec(dep);
+                let constraint = constraint.unwrap_or_else(|| "*".to_string());
+
+                if !self.workspaces.contains_key(&dep_name) && !self.satisfies(&dep_name, &constraint) {
+                    issues.push(LockfileIssue::new(
+                        Some(name),
+                        format!("workspace dependency {} does not resolve to any locked package", dep),
+                    ));
+                }
+            }
+        }
+
+        issues
+    }
+
+    /// Drop packages unreachable from the importers and workspaces whose directory is gone
+    ///
+    /// `importers` are the direct dependencies (name, constraint) of every package.json
+    /// that shares this lockfile; `dir` is the directory the lockfile lives in.
+    pub fn prune(&mut self, dir: &Path, importers: &[(String, String)]) -> LockfilePrune {
+        let workspaces_before = self.workspaces.len();
+        self.workspaces.retain(|_, ws| dir.join(&ws.path).join("package.json").exists());
+
+        let mut roots: Vec<(String, String)> = importers.to_vec();
+        for ws in self.workspaces.values() {
+            for dep in &ws.dependencies {
+                let (name, constraint) = crate::utils::parse_package_spec(dep);
+                roots.push((name, constraint.unwrap_or_else(|| "*".to_string())));
+            }
+        }
+        let reachable = self.reachable(roots);
+
+        let packages_before = self.packages.len();
+        self.packages.retain(|p| reachable.contains(&(p.name.clone(), p.version.clone())));
+
+        LockfilePrune {
+            packages: packages_before - self.packages.len(),
+            workspaces: workspaces_before - self.workspaces.len(),
+        }
+    }
+
+    /// Mark the packages `production_importers` do not reach as dev-only
+    ///
+    /// `production_importers` are the dependencies and optionalDependencies of
+    /// every package.json sharing the lockfile. Recording the split lets a
+    /// production install skip exactly the same packages every time.
+    pub fn mark_dev(&mut self, production_importers: &[(String, String)]) {
+        let production = self.reachable(production_importers.to_vec());
+        for pkg in &mut self.packages {
+            pkg.dev = !production.contains(&(pkg.name.clone(), pkg.version.clone()));
+        }
+    }
+
+    /// Locked (name, version) pairs reachable from `roots` along dependency edges
+    fn reachable(&self, mut queue: Vec<(String, String)>) -> std::collections::HashSet<(String, String)> {
+        let mut reachable: std::collections::HashSet<(String, String)> = std::collections::HashSet::new();
+        while let Some((name, constraint)) = queue.pop() {
+            if self.workspaces.contains_key(&name) || constraint.starts_with("workspace:") {
+                continue;
+            }
+
+            for pkg in self.matching_packages(&name, &constraint) {
+                if !reachable.insert((pkg.name.clone(), pkg.version.clone())) {
+                    continue;
+                }
+
+                for dep in &pkg.dependencies {
+                    // A pinned edge reaches exactly the version it is locked to
+                    let constraint = dep.version.as_ref().map_or_else(|| dep.range.clone(), |v| format!("={}", v));
+                    queue.push((dep.name.clone(), constraint));
+                }
+                for dep_name in pkg.optional_dependencies.iter().chain(pkg.peer_dependencies.iter()) {
+                    queue.push((dep_name.clone(), "*".to_string()));
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Explain why the lockfile no longer matches the importers, or `None` when it
+    /// can be installed as-is without resolving
+    ///
+    /// Every importer must be satisfied by a locked package or workspace, every
+    /// locked edge must resolve, and nothing may be left over from removed dependencies.
+    pub fn stale_reason(&self, dir: &Path, importers: &[(String, String)]) -> Option<String> {
+        for (name, constraint) in importers {
+            // Constraints we cannot evaluate (tags, git URLs) accept any locked version
+            let locked = self.workspaces.contains_key(name)
+                || constraint.starts_with("workspace:")
+                || self.satisfies(name, constraint);
+            if !locked {
+                return Some(format!("{}@{} is not in the lockfile", name, constraint));
+            }
+        }
+
+        if let Some(issue) = self.validate().into_iter().next() {
+            return Some(issue.to_string());
+        }
+
+        let pruned = self.clone().prune(dir, importers);
+        if pruned.total() > 0 {
+            return Some(format!("{} locked entries are no longer depended on", pruned.total()));
+        }
+
+        None
+    }
+
+    /// Locked packages named `name` whose version satisfies `constraint`
+    fn matching_packages(&self, name: &str, constraint: &str) -> Vec<&LockedPackage> {
+        let versions = self.find_package_versions(name);
+        // A tarball dependency is the package locked from that tarball
+        if is_tarball_spec(constraint) {
+            return versions.into_iter().filter(|p| p.resolved == constraint).collect();
+        }
+        let constraint = VersionConstraint::parse(constraint).unwrap_or(VersionConstraint::Any);
+
+        let matching: Vec<&LockedPackage> = versions
+            .iter()
+            .copied()
+            .filter(|p| {
+                semver::Version::parse(&p.version)
+                    .map(|v| constraint.matches(&v))
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        // Constraints we cannot evaluate (tags, git URLs) keep every locked version
+        if matching.is_empty() {
+            versions
+        } else {
+            matching
+        }
+    }
+
+    /// Check whether some locked version of `name` satisfies `constraint`
+    fn satisfies(&self, name: &str, constraint: &str) -> bool {
+        if is_tarball_spec(constraint) {
+            return self.find_package_versions(name).iter().any(|p| p.resolved == constraint);
+        }
+        let constraint = VersionConstraint::parse(constraint).unwrap_or(VersionConstraint::Any);
+        self.find_package_versions(name).iter().any(|p| {
+            semver::Version::parse(&p.version)
+                .map(|v| constraint.matches(&v))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Record the install script hashes approved for each (name, version)
+    pub fn record_script_approvals(&mut self, approved: &HashMap<(String, String), String>) {
+        for pkg in &mut self.packages {
+            pkg.approved_scripts = approved.get(&(pkg.name.clone(), pkg.version.clone())).cloned();
+        }
+    }
+
+    /// Carry over the script approvals `previous` recorded
+    ///
+    /// A new version inherits its predecessor's approval, so the next install
+    /// runs its scripts only if they are unchanged.
+    pub fn keep_script_approvals(&mut self, previous: &Lockfile) {
+        for pkg in &mut self.packages {
+            let same_version = previous.find_package(&pkg.name, &pkg.version);
+            let approved = match same_version {
+                Some(old) => old.approved_scripts.clone(),
+                None => previous.find_package_versions(&pkg.name).iter().find_map(|old| old.approved_scripts.clone()),
+            };
+            pkg.approved_scripts = approved;
+        }
+    }
+
+    /// Carry verified provenance over from `previous` for unchanged tarballs
+    pub fn keep_provenance(&mut self, previous: &Lockfile) {
+        for pkg in &mut self.packages {
+            if pkg.provenance.is_none() {
+                pkg.provenance = previous
+                    .find_package(&pkg.name, &pkg.version)
+                    .filter(|old| old.integrity == pkg.integrity)
+                    .and_then(|old| old.provenance.clone());
+            }
+        }
+    }
+
     /// Get packages that have install scripts
     pub fn packages_with_scripts(&self) -> Vec<&LockedPackage> {
         self.packages.iter().filter(|p| p.has_scripts).collect()
@@ -251,6 +857,65 @@ impl Lockfile {
     }
 }
 
+/// A problem found while validating a lockfile
+#[derive(Debug, Clone, Serialize)]
+pub struct LockfileIssue {
+    /// Package (name@version) or workspace the issue belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+
+    /// Human-readable description
+    pub message: String,
+}
+
+impl LockfileIssue {
+    fn new<S: Into<String>>(package: Option<&str>, message: S) -> Self {
+        Self {
+            package: package.map(|p| p.to_string()),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for LockfileIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.package {
+            Some(ref package) => write!(f, "{}: {}", package, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Result of pruning a lockfile
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LockfilePrune {
+    /// Number of unreachable packages removed
+    pub packages: usize,
+
+    /// Number of workspace entries removed
+    pub workspaces: usize,
+}
+
+impl LockfilePrune {
+    /// Total number of entries removed
+    pub fn total(&self) -> usize {
+        self.packages + self.workspaces
+    }
+}
+
+/// The lockfiles a conflicted lockfile was split into
+#[derive(Debug, Clone)]
+pub struct ConflictSides {
+    /// Our side of every hunk
+    pub ours: Lockfile,
+
+    /// Their side of every hunk
+    pub theirs: Lockfile,
+
+    /// The common ancestor, when every hunk is diff3-style
+    pub base: Option<Lockfile>,
+}
+
 /// Diff between two lockfiles
 #[derive(Debug, Clone)]
 pub struct LockfileDiff {
@@ -271,6 +936,53 @@ impl LockfileDiff {
     }
 }
 
+/// A git merge conflict marker line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConflictMarker {
+    /// `<<<<<<<`, opening our side
+    Ours,
+    /// `|||||||`, opening the common ancestor of a diff3-style hunk
+    Base,
+    /// `=======`, opening their side
+    Theirs,
+    /// `>>>>>>>`, closing the hunk
+    End,
+}
+
+impl ConflictMarker {
+    fn parse(line: &str) -> Option<Self> {
+        let marker = match line.get(..7)? {
+            "<<<<<<<" => ConflictMarker::Ours,
+            "|||||||" => ConflictMarker::Base,
+            "=======" => ConflictMarker::Theirs,
+            ">>>>>>>" => ConflictMarker::End,
+            _ => return None,
+        };
+        let rest = &line[7..];
+        (rest.is_empty() || rest.starts_with(' ')).then_some(marker)
+    }
+}
+
+/// Whether two sets of entries for a package lock the same versions with the same contents
+fn same_entries(a: &[&LockedPackage], b: &[&LockedPackage]) -> bool {
+    a.len() == b.len() && a.iter().all(|p| b.iter().any(|q| q.version == p.version && same_contents(p, q)))
+}
+
+/// Whether two entries for the same version lock the same tarball and edges
+///
+/// Locked edge versions are left out: they follow from the rest of the lockfile.
+fn same_contents(a: &LockedPackage, b: &LockedPackage) -> bool {
+    let edges = |p: &LockedPackage| -> BTreeSet<(String, String)> {
+        p.dependencies.iter().map(|d| (d.name.clone(), d.range.clone())).collect()
+    };
+    a.resolved == b.resolved
+        && a.integrity == b.integrity
+        && a.alias_of == b.alias_of
+        && edges(a) == edges(b)
+        && a.peer_dependencies == b.peer_dependencies
+        && a.optional_dependencies == b.optional_dependencies
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -283,20 +995,24 @@ mod tests {
         let mut lockfile = Lockfile::new();
         lockfile.add_package(LockedPackage {
             name: "test-package".to_string(),
+            alias_of: None,
             version: "1.0.0".to_string(),
             resolved: "https://registry.npmjs.org/test-package/-/test-package-1.0.0.tgz".to_string(),
             integrity: "sha512-abc123".to_string(),
-            dependencies: vec!["dep1@1.0.0".to_string()],
+            dependencies: vec!["dep1@1.0.0".into()],
             peer_dependencies: vec![],
             optional_dependencies: vec![],
             has_scripts: false,
+            approved_scripts: None,
             cpu: vec![],
             os: vec![],
+            dev: false,
+            provenance: None,
         });
 
-        lockfile.save(dir.path()).unwrap();
+        lockfile.save(&dir.path().join(LOCKFILE_NAME)).unwrap();
         
-        let loaded = Lockfile::load(dir.path()).unwrap().unwrap();
+        let loaded = Lockfile::load(&dir.path().join(LOCKFILE_NAME)).unwrap().unwrap();
         assert_eq!(loaded.packages.len(), 1);
         assert_eq!(loaded.packages[0].name, "test-package");
     }
@@ -308,6 +1024,7 @@ mod tests {
         let mut lockfile = Lockfile::new();
         lockfile.add_package(LockedPackage {
             name: "test".to_string(),
+            alias_of: None,
             version: "1.0.0".to_string(),
             resolved: "https://example.com/test.tgz".to_string(),
             integrity: "sha512-abc".to_string(),
@@ -315,11 +1032,14 @@ mod tests {
             peer_dependencies: vec![],
             optional_dependencies: vec![],
             has_scripts: false,
+            approved_scripts: None,
             cpu: vec![],
             os: vec![],
+            dev: false,
+            provenance: None,
         });
 
-        lockfile.save(dir.path()).unwrap();
+        lockfile.save(&dir.path().join(LOCKFILE_NAME)).unwrap();
         
         // Tamper with the lockfile
         let path = dir.path().join(LOCKFILE_NAME);
@@ -328,7 +1048,317 @@ mod tests {
         std::fs::write(&path, tampered).unwrap();
 
         // Should fail integrity check
-        let result = Lockfile::load(dir.path());
+        let result = Lockfile::load(&dir.path().join(LOCKFILE_NAME));
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_validate_dangling_dependency() {
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package(LockedPackage {
+            name: "app-dep".to_string(),
+            alias_of: None,
+            version: "1.0.0".to_string(),
+            resolved: "https://example.com/app-dep.tgz".to_string(),
+            integrity: "sha512-abc".to_string(),
+            dependencies: vec!["left-pad@^1.0.0".into(), "@scope/util@~2.1.0".into()],
+            peer_dependencies: vec![],
+            optional_dependencies: vec![],
+            has_scripts: false,
+            approved_scripts: None,
+            cpu: vec![],
+            os: vec![],
+            dev: false,
+            provenance: None,
+        });
+        lockfile.add_package(LockedPackage {
+            name: "left-pad".to_string(),
+            alias_of: None,
+            version: "1.3.0".to_string(),
+            resolved: "https://example.com/left-pad.tgz".to_string(),
+            integrity: "sha512-def".to_string(),
+            dependencies: vec![],
+            peer_dependencies: vec![],
+            optional_dependencies: vec![],
+            has_scripts: false,
+            approved_scripts: None,
+            cpu: vec![],
+            os: vec![],
+            dev: false,
+            provenance: None,
+        });
+
+        let issues = lockfile.validate();
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("@scope/util@~2.1.0"));
+    }
+
+    #[test]
+    fn test_prune_drops_unreachable_packages() {
+        let dir = tempdir().unwrap();
+        let locked = |name: &str, deps: &[&str]| LockedPackage {
+            name: name.to_string(),
+            alias_of: None,
+            version: "1.0.0".to_string(),
+            resolved: format!("https://example.com/{}.tgz", name),
+            integrity: "sha512-abc".to_string(),
+            dependencies: deps.iter().map(|d| (*d).into()).collect(),
+            peer_dependencies: vec![],
+            optional_dependencies: vec![],
+            has_scripts: false,
+            approved_scripts: None,
+            cpu: vec![],
+            os: vec![],
+            dev: false,
+            provenance: None,
+        };
+
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package(locked("app-dep", &["transitive@^1.0.0"]));
+        lockfile.add_package(locked("transitive", &[]));
+        lockfile.add_package(locked("removed-dep", &["removed-transitive@^1.0.0"]));
+        lockfile.add_package(locked("removed-transitive", &[]));
+        lockfile.workspaces.insert("deleted".to_string(), WorkspacePackage {
+            path: "packages/deleted".to_string(),
+            version: "1.0.0".to_string(),
+            dependencies: vec!["removed-dep@^1.0.0".to_string()],
+        });
+
+        let pruned = lockfile.prune(dir.path(), &[("app-dep".to_string(), "^1.0.0".to_string())]);
+
+        assert_eq!(pruned.packages, 2);
+        assert_eq!(pruned.workspaces, 1);
+        assert_eq!(lockfile.package_names(), vec!["app-dep", "transitive"]);
+    }
+
+    #[test]
+    fn test_mark_dev() {
+        let locked = |name: &str, deps: &[&str]| LockedPackage {
+            name: name.to_string(),
+            alias_of: None,
+            version: "1.0.0".to_string(),
+            resolved: format!("https://example.com/{}.tgz", name),
+            integrity: "sha512-abc".to_string(),
+            dependencies: deps.iter().map(|d| (*d).into()).collect(),
+            peer_dependencies: vec![],
+            optional_dependencies: vec![],
+            has_scripts: false,
+            approved_scripts: None,
+            cpu: vec![],
+            os: vec![],
+            dev: false,
+            provenance: None,
+        };
+
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package(locked("app-dep", &["shared@^1.0.0"]));
+        lockfile.add_package(locked("test-runner", &["shared@^1.0.0", "reporter@^1.0.0"]));
+        lockfile.add_package(locked("shared", &[]));
+        lockfile.add_package(locked("reporter", &[]));
+
+        lockfile.mark_dev(&[("app-dep".to_string(), "^1.0.0".to_string())]);
+
+        let dev: Vec<&str> = lockfile.packages.iter().filter(|p| p.dev).map(|p| p.name.as_str()).collect();
+        assert_eq!(dev, ["test-runner", "reporter"]);
+    }
+
+    #[test]
+    fn test_stale_reason() {
+        let dir = tempdir().unwrap();
+        let locked = |name: &str, deps: &[&str]| LockedPackage {
+            name: name.to_string(),
+            alias_of: None,
+            version: "1.2.0".to_string(),
+            resolved: format!("https://example.com/{}.tgz", name),
+            integrity: "sha512-abc".to_string(),
+            dependencies: deps.iter().map(|d| (*d).into()).collect(),
+            peer_dependencies: vec![],
+            optional_dependencies: vec![],
+            has_scripts: false,
+            approved_scripts: None,
+            cpu: vec![],
+            os: vec![],
+            dev: false,
+            provenance: None,
+        };
+
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package(locked("app-dep", &["transitive@^1.0.0"]));
+        lockfile.add_package(locked("transitive", &[]));
+
+        let importers = |spec: &str| vec![("app-dep".to_string(), spec.to_string())];
+        assert_eq!(lockfile.stale_reason(dir.path(), &importers("^1.1.0")), None);
+        assert!(lockfile.stale_reason(dir.path(), &importers("^2.0.0")).is_some());
+        assert!(lockfile.stale_reason(dir.path(), &[]).is_some());
+    }
+
+    #[test]
+    fn test_digest_filters_platform() {
+        let locked = |name: &str, os: &[&str]| LockedPackage {
+            name: name.to_string(),
+            alias_of: None,
+            version: "1.0.0".to_string(),
+            resolved: format!("https://example.com/{}.tgz", name),
+            integrity: format!("sha512-{}", name),
+            dependencies: vec![],
+            peer_dependencies: vec![],
+            optional_dependencies: vec![],
+            has_scripts: false,
+            approved_scripts: None,
+            cpu: vec![],
+            os: os.iter().map(|o| o.to_string()).collect(),
+            dev: false,
+            provenance: None,
+        };
+
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package(locked("esbuild-darwin", &["darwin"]));
+        lockfile.add_package(locked("esbuild-linux", &["linux"]));
+        lockfile.add_package(locked("not-windows", &["!win32"]));
+
+        let linux = Platform { os: "linux".to_string(), cpu: "x64".to_string() };
+        let windows = Platform { os: "win32".to_string(), cpu: "x64".to_string() };
+        assert_eq!(lockfile.digest(Some(&linux)).1, 2);
+        assert_eq!(lockfile.digest(Some(&windows)).1, 0);
+        assert_eq!(lockfile.digest(None).1, 3);
+
+        // Order and download URLs do not matter, integrity does
+        let mut mirrored = lockfile.clone();
+        mirrored.packages.reverse();
+        mirrored.packages[0].resolved = "https://mirror.example/x.tgz".to_string();
+        assert_eq!(mirrored.digest(Some(&linux)), lockfile.digest(Some(&linux)));
+        mirrored.packages[0].integrity = "sha512-other".to_string();
+        assert_ne!(mirrored.digest(None), lockfile.digest(None));
+    }
+
+    #[test]
+    fn test_json_schema_has_id() {
+        let schema = Lockfile::json_schema();
+        assert_eq!(schema["$id"], LOCKFILE_SCHEMA_ID);
+        assert!(schema["properties"]["packages"].is_object());
+    }
+
+    #[test]
+    fn test_locked_dependency_format() {
+        let v1 = LockedDependency::from("@scope/util@~2.1.0");
+        assert_eq!((v1.name.as_str(), v1.range.as_str(), v1.version), ("@scope/util", "~2.1.0", None));
+
+        let v2 = LockedDependency::from("b@>=1.0.0 <2.0.0 => 1.4.2");
+        assert_eq!(v2.range, ">=1.0.0 <2.0.0");
+        assert_eq!(v2.version.as_deref(), Some("1.4.2"));
+        assert_eq!(v2.to_string(), "b@>=1.0.0 <2.0.0 => 1.4.2");
+
+        let alias = LockedDependency::from("cjs@npm:string-width@^4.2.0 => 4.2.3");
+        assert_eq!(alias.name, "cjs");
+        assert_eq!(alias.constraint(), "^4.2.0");
+        assert_eq!(LockedDependency::from("bare").range, "*");
+    }
+
+    #[test]
+    fn test_upgrade_pins_edges() {
+        let dir = tempdir().unwrap();
+        let locked = |name: &str, version: &str, deps: &[&str]| LockedPackage {
+            name: name.to_string(),
+            alias_of: None,
+            version: version.to_string(),
+            resolved: format!("https://example.com/{}-{}.tgz", name, version),
+            integrity: "sha512-abc".to_string(),
+            dependencies: deps.iter().map(|d| (*d).into()).collect(),
+            peer_dependencies: vec![],
+            optional_dependencies: vec![],
+            has_scripts: false,
+            approved_scripts: None,
+            cpu: vec![],
+            os: vec![],
+            dev: false,
+            provenance: None,
+        };
+
+        // A version 1 lockfile, as earlier releases wrote it
+        let mut v1 = Lockfile::new();
+        v1.version = 1;
+        v1.add_package(locked("app", "1.0.0", &["b@^1.0.0", "c@^2.0.0"]));
+        v1.add_package(locked("b", "1.0.0", &[]));
+        v1.add_package(locked("b", "1.5.0", &[]));
+        v1.add_package(locked("c", "2.0.0", &["b@~1.0.0"]));
+        let path = dir.path().join(LOCKFILE_NAME);
+        v1.save(&path).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains(" => "));
+
+        let mut lockfile = Lockfile::load(&path).unwrap().unwrap();
+        assert!(lockfile.validate().is_empty());
+        assert!(lockfile.upgrade());
+        assert!(!lockfile.upgrade());
+        assert_eq!(lockfile.version, LOCKFILE_VERSION);
+
+        let edges = |name: &str| -> Vec<String> {
+            lockfile.packages.iter().find(|p| p.name == name).unwrap().dependencies.iter().map(|d| d.to_string()).collect()
+        };
+        assert_eq!(edges("app"), ["b@^1.0.0 => 1.5.0", "c@^2.0.0 => 2.0.0"]);
+        assert_eq!(edges("c"), ["b@~1.0.0 => 1.0.0"]);
+
+        lockfile.save(&path).unwrap();
+        let reloaded = Lockfile::load(&path).unwrap().unwrap();
+        assert_eq!(reloaded.packages, lockfile.packages);
+        assert!(reloaded.validate().is_empty());
+
+        // A pinned edge must name a locked version
+        let mut broken = reloaded.clone();
+        broken.packages.retain(|p| !(p.name == "b" && p.version == "1.0.0"));
+        broken.integrity = None;
+        assert_eq!(broken.validate().len(), 1);
+    }
+
+    #[test]
+    fn test_merge_conflicted_lockfile() {
+        let package = |name: &str, version: &str, integrity: &str| {
+            format!(
+                "\n[[packages]]\nname = \"{name}\"\nversion = \"{version}\"\nresolved = \"https://example.com/{name}-{version}.tgz\"\nintegrity = \"{integrity}\"\n"
+            )
+        };
+        let content = format!(
+            "version = 2\n{}<<<<<<< HEAD\n{}||||||| base\n{}=======\n{}{}>>>>>>> feature\n",
+            package("a", "1.0.0", "sha512-a"),
+            package("b", "1.1.0", "sha512-b11"),
+            package("b", "1.0.0", "sha512-b10"),
+            package("b", "1.2.0", "sha512-b12"),
+            package("c", "3.0.0", "sha512-c"),
+        );
+        assert!(Lockfile::has_conflict_markers(&content));
+        assert!(toml::from_str::<Lockfile>(&content).is_err());
+
+        let sides = Lockfile::parse_conflicted(&content).unwrap();
+        let (ours, theirs) = (sides.ours.clone(), sides.theirs.clone());
+        let versions = |lockfile: &Lockfile| -> Vec<String> {
+            lockfile.packages.iter().map(|p| format!("{}@{}", p.name, p.version)).collect()
+        };
+        assert_eq!(versions(&ours), ["a@1.0.0", "b@1.1.0"]);
+        assert_eq!(versions(&theirs), ["a@1.0.0", "b@1.2.0", "c@3.0.0"]);
+        assert_eq!(versions(sides.base.as_ref().unwrap()), ["a@1.0.0", "b@1.0.0"]);
+
+        // Both sides moved b; only theirs added c
+        let (merged, disputed) = Lockfile::merge_conflict(&sides);
+        assert_eq!(versions(&merged), ["a@1.0.0", "b@1.1.0", "b@1.2.0", "c@3.0.0"]);
+        assert_eq!(disputed.into_iter().collect::<Vec<_>>(), ["b"]);
+
+        // The same version locked with a different tarball is disputed too
+        let mut tampered = theirs.clone();
+        tampered.packages[0].integrity = "sha512-other".to_string();
+        let (_, disputed) = Lockfile::merge_conflict(&ConflictSides { ours: ours.clone(), theirs: tampered, base: None });
+        assert_eq!(disputed.into_iter().collect::<Vec<_>>(), ["a", "b"]);
+
+        // A package only one side changed takes that side's entry, removals included
+        let mut base = ours.clone();
+        base.packages[1].version = "1.0.0".to_string();
+        let mut upgraded = base.clone();
+        upgraded.packages[0].version = "1.5.0".to_string();
+        let mut dropped = base.clone();
+        dropped.packages.retain(|p| p.name != "b");
+        let (merged, disputed) = Lockfile::merge_conflict(&ConflictSides { ours: upgraded, theirs: dropped, base: Some(base) });
+        assert_eq!(versions(&merged), ["a@1.5.0"]);
+        assert!(disputed.is_empty());
+
+        assert!(!Lockfile::has_conflict_markers(&package("a", "1.0.0", "sha512-a")));
+        assert!(Lockfile::parse_conflicted("version = 2\n<<<<<<< HEAD\n").is_err());
+    }
 }
@@ -4,10 +4,14 @@
 //! and lockfile handling.
 
 pub mod config;
+pub mod credentials;
 pub mod error;
 pub mod lockfile;
 pub mod engine;
+pub mod node;
+pub mod pack;
 pub mod package;
+pub mod project;
 
 pub use config::Config;
 pub use error::{VelocityError, VelocityResult};
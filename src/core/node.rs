@@ -0,0 +1,125 @@
+//! Detecting the Node.js version a project runs on
+//!
+//! A version pinned for the project (Volta's `volta.node` in package.json,
+//! then `.nvmrc` or `.node-version` in the project or a parent directory) wins
+//! over the `node` on PATH, since it is what the project will actually run on.
+
+use std::path::Path;
+use std::process::Command;
+
+use crate::core::PackageJson;
+use crate::resolver::VersionConstraint;
+
+/// Where a detected Node.js version came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeVersionSource {
+    Volta,
+    Nvmrc,
+    NodeVersionFile,
+    Installed,
+}
+
+impl NodeVersionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeVersionSource::Volta => "volta.node",
+            NodeVersionSource::Nvmrc => ".nvmrc",
+            NodeVersionSource::NodeVersionFile => ".node-version",
+            NodeVersionSource::Installed => "node --version",
+        }
+    }
+}
+
+/// The Node.js version a project runs on
+#[derive(Debug, Clone)]
+pub struct NodeVersion {
+    pub version: semver::Version,
+    pub source: NodeVersionSource,
+}
+
+impl NodeVersion {
+    /// Detect the Node.js version for a project, or None when nothing pins one
+    /// and no `node` is on PATH
+    ///
+    /// A partial pin such as `20` resolves to the installed Node.js when that
+    /// satisfies it, and to the lowest version it allows otherwise.
+    pub fn detect(project_dir: &Path, package_json: &PackageJson) -> Option<Self> {
+        let installed = installed_version();
+
+        let volta = package_json
+            .other
+            .get("volta")
+            .and_then(|volta| volta.get("node"))
+            .and_then(|node| node.as_str())
+            .map(|pin| (pin.to_string(), NodeVersionSource::Volta));
+        let pinned = volta.or_else(|| {
+            project_dir.ancestors().find_map(|dir| {
+                [(".nvmrc", NodeVersionSource::Nvmrc), (".node-version", NodeVersionSource::NodeVersionFile)]
+                    .into_iter()
+                    .find_map(|(file, source)| {
+                        let content = std::fs::read_to_string(dir.join(file)).ok()?;
+                        let pin = content.lines().next()?.trim().to_string();
+                        (!pin.is_empty()).then_some((pin, source))
+                    })
+            })
+        });
+
+        if let Some((pin, source)) = pinned {
+            match resolve_pin(&pin, installed.as_ref()) {
+                Some(version) => return Some(NodeVersion { version, source }),
+                // Aliases such as lts/* name no version we can know offline
+                None => tracing::debug!("Ignoring Node.js version pin '{}' from {}", pin, source.as_str()),
+            }
+        }
+
+        installed.map(|version| NodeVersion { version, source: NodeVersionSource::Installed })
+    }
+}
+
+/// Version of the `node` on PATH
+fn installed_version() -> Option<semver::Version> {
+    let output = Command::new("node").arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_node_version(String::from_utf8_lossy(&output.stdout).trim())
+}
+
+/// The version a pin such as `v20.11.1`, `20.11` or `20` stands for
+fn resolve_pin(pin: &str, installed: Option<&semver::Version>) -> Option<semver::Version> {
+    let floor = parse_node_version(pin)?;
+    let full = pin.trim_start_matches('v').split('.').count() >= 3;
+    if full {
+        return Some(floor);
+    }
+    let constraint = VersionConstraint::parse(pin.trim_start_matches('v')).ok()?;
+    match installed {
+        Some(installed) if constraint.matches(installed) => Some(installed.clone()),
+        _ => Some(floor),
+    }
+}
+
+/// Parse `v20.11.1`, `20.11` or `20`, filling missing parts with zeros
+fn parse_node_version(s: &str) -> Option<semver::Version> {
+    let s = s.trim().trim_start_matches('v');
+    let mut parts = s.split('.').map(|part| part.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    Some(semver::Version::new(major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_pin() {
+        let installed = semver::Version::new(20, 11, 1);
+
+        assert_eq!(resolve_pin("v18.17.0", Some(&installed)), Some(semver::Version::new(18, 17, 0)));
+        assert_eq!(resolve_pin("20", Some(&installed)), Some(installed.clone()));
+        assert_eq!(resolve_pin("22", Some(&installed)), Some(semver::Version::new(22, 0, 0)));
+        assert_eq!(resolve_pin("lts/*", Some(&installed)), None);
+    }
+}
@@ -0,0 +1,438 @@
+//! Packing a package into the tarball a registry serves
+//!
+//! File selection follows npm: a `files` list in package.json is an
+//! allowlist (with `!` entries taking files back out), otherwise `.npmignore`
+//! (or `.gitignore` when there is none) says what to leave out. package.json,
+//! the README, the license and the `main` and `bin` files always go in;
+//! VCS metadata, node_modules and lockfiles never do.
+
+use std::io::Write;
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha512};
+
+use crate::core::{VelocityError, VelocityResult};
+use crate::security::integrity::IntegrityChecker;
+
+/// Modification time npm stamps on every entry (1985-10-26), keeping tarballs reproducible
+const TARBALL_MTIME: u64 = 499162500;
+
+/// Names never packed, wherever they appear
+const ALWAYS_IGNORED: &[&str] = &[
+    ".git",
+    ".svn",
+    ".hg",
+    "CVS",
+    "node_modules",
+    ".DS_Store",
+    ".npmrc",
+    "npm-debug.log",
+    ".lock-wscript",
+    "config.gypi",
+    "*.orig",
+    ".*.swp",
+    "._*",
+    ".wafpickle-*",
+];
+
+/// Files only ignored at the package root
+const ROOT_IGNORED: &[&str] = &[
+    "velocity.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lockb",
+];
+
+/// A file in a packed tarball
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedFile {
+    /// Path relative to the package root, with `/` separators
+    pub path: String,
+    pub size: u64,
+}
+
+/// A packed package
+#[derive(Debug, Clone)]
+pub struct Tarball {
+    pub name: String,
+    pub version: String,
+    pub files: Vec<PackedFile>,
+    /// Gzipped tarball
+    pub data: Vec<u8>,
+    /// Subresource integrity of `data` (sha512)
+    pub integrity: String,
+}
+
+impl Tarball {
+    /// Total size of the packed files
+    pub fn unpacked_size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+
+    /// Hex sha512 of the tarball, as used in attestation subjects
+    pub fn sha512_hex(&self) -> String {
+        hex::encode(Sha512::digest(&self.data))
+    }
+
+    /// File name npm gives the tarball: `scope-name-1.0.0.tgz` for `@scope/name`
+    pub fn filename(&self) -> String {
+        format!("{}-{}.tgz", self.name.trim_start_matches('@').replace('/', "-"), self.version)
+    }
+}
+
+/// Pack the package in `dir`, writing `manifest` as its package.json
+///
+/// The manifest is passed in rather than read so callers can rewrite it
+/// first (e.g. resolving `workspace:` ranges before publishing).
+pub fn pack(dir: &Path, manifest: &serde_json::Value) -> VelocityResult<Tarball> {
+    let field = |key: &str| manifest.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    let (name, version) = (field("name"), field("version"));
+    if name.is_empty() || version.is_empty() {
+        return Err(VelocityError::other("package.json needs a name and a version to be packed"));
+    }
+    if semver::Version::parse(&version).is_err() {
+        return Err(VelocityError::other(format!("'{}' in package.json is not a valid semver version", version)));
+    }
+
+    let package_json = format!("{}\n", serde_json::to_string_pretty(manifest)?);
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let mut files = Vec::new();
+
+    for path in package_files(dir, manifest)? {
+        let (data, mode) = if path == "package.json" {
+            (package_json.clone().into_bytes(), 0o644)
+        } else {
+            let full = dir.join(&path);
+            let mode = if is_executable(&full)? { 0o755 } else { 0o644 };
+            (std::fs::read(&full)?, mode)
+        };
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(mode);
+        header.set_mtime(TARBALL_MTIME);
+        header.set_entry_type(tar::EntryType::Regular);
+        builder.append_data(&mut header, format!("package/{}", path), data.as_slice())?;
+        files.push(PackedFile { path, size: data.len() as u64 });
+    }
+
+    let mut encoder = builder.into_inner()?;
+    encoder.flush()?;
+    let data = encoder.finish()?;
+    let integrity = IntegrityChecker::compute(&data, "sha512");
+
+    Ok(Tarball { name, version, files, data, integrity })
+}
+
+/// Clean up a manifest the way npm does before packing it
+///
+/// `main`, `bin` and `files` paths lose their `./` prefixes, a string `bin`
+/// becomes `{ "<unscoped name>": path }`, bins pointing outside the package
+/// are dropped, a `v` in front of the version goes, and fields starting with
+/// `_` (left behind by installs) are removed.
+pub fn normalize_manifest(manifest: &mut serde_json::Value) {
+    let Some(object) = manifest.as_object_mut() else {
+        return;
+    };
+    object.retain(|key, _| !key.starts_with('_'));
+
+    if let Some(version) = object.get("version").and_then(|v| v.as_str()) {
+        let cleaned = version.trim().trim_start_matches(['v', '=']).to_string();
+        if semver::Version::parse(&cleaned).is_ok() {
+            object.insert("version".to_string(), cleaned.into());
+        }
+    }
+
+    if let Some(main) = object.get("main").and_then(|main| main.as_str()) {
+        let main = normalize(main);
+        object.insert("main".to_string(), main.into());
+    }
+
+    let name = object.get("name").and_then(|name| name.as_str()).unwrap_or_default();
+    let unscoped = name.rsplit('/').next().unwrap_or(name).to_string();
+    let bins = match object.get("bin") {
+        Some(serde_json::Value::String(path)) => Some(vec![(unscoped, path.clone())]),
+        Some(serde_json::Value::Object(bins)) => Some(
+            bins.iter().filter_map(|(name, path)| Some((name.clone(), path.as_str()?.to_string()))).collect(),
+        ),
+        _ => None,
+    };
+    if let Some(bins) = bins {
+        let bins: serde_json::Map<String, serde_json::Value> = bins
+            .into_iter()
+            .filter_map(|(name, path)| {
+                let name = name.rsplit('/').next().unwrap_or(&name).to_string();
+                let path = normalize(&path);
+                let escapes = path.split('/').any(|part| part == "..") || path.starts_with('/');
+                (!name.is_empty() && !path.is_empty() && !escapes).then(|| (name, path.into()))
+            })
+            .collect();
+        object.insert("bin".to_string(), bins.into());
+    }
+
+    if let Some(files) = object.get_mut("files").and_then(|files| files.as_array_mut()) {
+        for file in files.iter_mut() {
+            if let Some(path) = file.as_str() {
+                let (negation, path) = match path.strip_prefix('!') {
+                    Some(path) => ("!", path),
+                    None => ("", path),
+                };
+                *file = format!("{}{}", negation, normalize(path).trim_start_matches('/')).into();
+            }
+        }
+    }
+}
+
+/// Files that go into the package in `dir`, relative and sorted
+pub fn package_files(dir: &Path, manifest: &serde_json::Value) -> VelocityResult<Vec<String>> {
+    let allowlist: Option<Vec<Rule>> = manifest.get("files").and_then(|files| files.as_array()).map(|files| {
+        files
+            .iter()
+            .filter_map(|entry| entry.as_str())
+            // `files` entries are relative to the package root
+            .filter_map(|entry| {
+                let (negation, path) = match entry.strip_prefix('!') {
+                    Some(path) => ("!", path),
+                    None => ("", entry),
+                };
+                Rule::parse(&format!("{}/{}", negation, path.trim_start_matches("./").trim_start_matches('/')))
+            })
+            .collect()
+    });
+    let ignore = match allowlist {
+        Some(_) => Vec::new(),
+        None => [".npmignore", ".gitignore"]
+            .iter()
+            .find_map(|file| std::fs::read_to_string(dir.join(file)).ok())
+            .map(|content| content.lines().filter_map(Rule::parse).collect())
+            .unwrap_or_default(),
+    };
+    let always = ALWAYS_IGNORED.iter().filter_map(|pattern| Rule::parse(pattern)).collect::<Vec<_>>();
+    let root_ignored = ROOT_IGNORED.iter().filter_map(|pattern| Rule::parse(&format!("/{}", pattern))).collect::<Vec<_>>();
+    let required = required_files(manifest);
+
+    let mut files = Vec::new();
+    let walker = walkdir::WalkDir::new(dir).min_depth(1).follow_links(false).into_iter();
+    let entries = walker.filter_entry(|entry| {
+        let Some(relative) = relative_path(dir, entry.path()) else {
+            return false;
+        };
+        let is_dir = entry.file_type().is_dir();
+        if always.iter().any(|rule| rule.matches(&relative, is_dir)) {
+            return false;
+        }
+        // Directories are pruned only by the ignore file; an allowlist may still want something inside them
+        !is_dir || !ignored(&ignore, &relative, true)
+    });
+
+    for entry in entries {
+        let entry = entry.map_err(|e| VelocityError::other(format!("Could not read {}: {}", dir.display(), e)))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Some(relative) = relative_path(dir, entry.path()) else {
+            continue;
+        };
+        if root_ignored.iter().any(|rule| rule.matches(&relative, false)) {
+            continue;
+        }
+
+        let included = if is_required(&required, &relative) {
+            true
+        } else if let Some(ref allowlist) = allowlist {
+            allowed(allowlist, &relative)
+        } else {
+            !ignored(&ignore, &relative, false)
+        };
+        if included {
+            files.push(relative);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Files packed whatever `files` or the ignore file say
+fn required_files(manifest: &serde_json::Value) -> Vec<String> {
+    let mut required = vec!["package.json".to_string()];
+    if let Some(main) = manifest.get("main").and_then(|main| main.as_str()) {
+        required.push(normalize(main));
+    }
+    match manifest.get("bin") {
+        Some(serde_json::Value::String(bin)) => required.push(normalize(bin)),
+        Some(serde_json::Value::Object(bins)) => {
+            required.extend(bins.values().filter_map(|bin| bin.as_str()).map(normalize));
+        }
+        _ => {}
+    }
+    required
+}
+
+fn is_required(required: &[String], path: &str) -> bool {
+    if required.iter().any(|file| file == path) {
+        return true;
+    }
+    // README, LICENSE and LICENCE at the root, with any extension or case
+    !path.contains('/') && {
+        let upper = path.to_ascii_uppercase();
+        let stem = upper.split('.').next().unwrap_or_default();
+        matches!(stem, "README" | "LICENSE" | "LICENCE" | "COPYING")
+    }
+}
+
+/// Whether the ignore rules leave `path` out; the last matching rule wins
+fn ignored(rules: &[Rule], path: &str, is_dir: bool) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(path, is_dir))
+        .is_some_and(|rule| !rule.negated)
+}
+
+/// Whether the `files` allowlist takes `path`, itself or through a directory above it
+fn allowed(rules: &[Rule], path: &str) -> bool {
+    let mut included = false;
+    for rule in rules {
+        let matched = rule.matches(path, false)
+            || path.match_indices('/').any(|(end, _)| rule.matches(&path[..end], true));
+        if matched {
+            included = !rule.negated;
+        }
+    }
+    included
+}
+
+/// One line of an ignore file or `files` entry
+struct Rule {
+    pattern: glob::Pattern,
+    negated: bool,
+    /// Matched against the whole path rather than any single name in it
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+        let pattern = glob::Pattern::new(line.trim_start_matches('/')).ok()?;
+        Some(Self { pattern, negated, anchored, dir_only })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+        if self.anchored {
+            self.pattern.matches_with(path, options)
+        } else {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            self.pattern.matches_with(name, options)
+        }
+    }
+}
+
+fn relative_path(dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(dir).ok()?;
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+/// `./bin/cli.js` -> `bin/cli.js`
+fn normalize(path: &str) -> String {
+    path.trim_start_matches("./").replace('\\', "/")
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> std::io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(std::fs::metadata(path)?.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(files: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for file in files {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, file).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_files_allowlist() {
+        let dir = project(&[
+            "package.json", "README.md", "LICENSE", "index.js", "lib/a.js", "lib/a.test.js",
+            "src/a.ts", "bin/cli.js", "velocity.lock", "node_modules/x/index.js",
+        ]);
+        let manifest = serde_json::json!({
+            "name": "pkg", "version": "1.0.0", "main": "index.js",
+            "bin": { "pkg": "./bin/cli.js" },
+            "files": ["lib", "!lib/*.test.js"],
+        });
+
+        assert_eq!(package_files(dir.path(), &manifest).unwrap(), [
+            "LICENSE", "README.md", "bin/cli.js", "index.js", "lib/a.js", "package.json",
+        ]);
+    }
+
+    #[test]
+    fn test_normalize_manifest() {
+        let mut manifest = serde_json::json!({
+            "name": "@acme/tool", "version": "v1.2.3", "main": "./lib/index.js",
+            "bin": "./bin/tool.js", "files": ["./lib", "!./lib/*.map"], "_resolved": "x",
+        });
+        normalize_manifest(&mut manifest);
+        assert_eq!(manifest, serde_json::json!({
+            "name": "@acme/tool", "version": "1.2.3", "main": "lib/index.js",
+            "bin": { "tool": "bin/tool.js" }, "files": ["lib", "!lib/*.map"],
+        }));
+
+        let mut manifest = serde_json::json!({ "name": "x", "bin": { "ok": "cli.js", "bad": "../outside.js" } });
+        normalize_manifest(&mut manifest);
+        assert_eq!(manifest["bin"], serde_json::json!({ "ok": "cli.js" }));
+    }
+
+    #[test]
+    fn test_npmignore() {
+        let dir = project(&[
+            "package.json", "index.js", "test/a.js", "docs/guide.md", "docs/keep.md",
+            "coverage/lcov.info", ".git/HEAD", "a.js.orig",
+        ]);
+        std::fs::write(dir.path().join(".npmignore"), "test/\n/coverage\ndocs/*\n!docs/keep.md\n").unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "index.js\n").unwrap();
+        let manifest = serde_json::json!({ "name": "pkg", "version": "1.0.0" });
+
+        assert_eq!(package_files(dir.path(), &manifest).unwrap(), [
+            ".gitignore", ".npmignore", "docs/keep.md", "index.js", "package.json",
+        ]);
+
+        let tarball = pack(dir.path(), &manifest).unwrap();
+        assert_eq!(tarball.filename(), "pkg-1.0.0.tgz");
+        assert!(tarball.integrity.starts_with("sha512-"));
+        assert!(IntegrityChecker::verify(&tarball.data, &tarball.integrity).unwrap());
+    }
+}
@@ -0,0 +1,96 @@
+//! Project root discovery
+//!
+//! Every command locates its project the same way, so running from a
+//! subdirectory (or with the global `--cwd`) behaves like running at the root.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::core::{PackageJson, VelocityResult};
+
+/// Find the project root for a directory: the nearest ancestor containing package.json
+///
+/// Falls back to `start` itself when no package.json exists above it, so commands
+/// report "not initialized" for the directory the user is actually in.
+pub fn find_project_root(start: &Path) -> PathBuf {
+    start
+        .ancestors()
+        .find(|dir| dir.join("package.json").is_file())
+        .unwrap_or(start)
+        .to_path_buf()
+}
+
+/// Find the enclosing workspace root: the nearest ancestor whose package.json declares workspaces
+pub fn find_workspace_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .filter(|dir| dir.join("package.json").is_file())
+        .find(|dir| {
+            PackageJson::load(dir)
+                .map(|pkg| pkg.is_workspace_root())
+                .unwrap_or(false)
+        })
+        .map(Path::to_path_buf)
+}
+
+/// Project root for the current directory
+pub fn current_project_root() -> VelocityResult<PathBuf> {
+    Ok(find_project_root(&env::current_dir()?))
+}
+
+/// Workspace root for the current directory, or the project root outside a workspace
+pub fn current_workspace_root() -> VelocityResult<PathBuf> {
+    let cwd = env::current_dir()?;
+    Ok(find_workspace_root(&cwd).unwrap_or_else(|| find_project_root(&cwd)))
+}
+
+/// Project root for a path argument; relative paths start at the current directory
+pub fn project_root_for(path: &Path) -> VelocityResult<PathBuf> {
+    let start = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+
+    Ok(find_project_root(&start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write_package(dir: &Path, content: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("package.json"), content).unwrap();
+    }
+
+    #[test]
+    fn test_project_root_from_subdirectory() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write_package(root, r#"{"name": "app", "version": "1.0.0"}"#);
+
+        let nested = root.join("src").join("components");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        assert_eq!(find_project_root(&nested), root);
+        assert_eq!(find_project_root(root), root);
+    }
+
+    #[test]
+    fn test_workspace_root_from_member() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write_package(root, r#"{"name": "root", "version": "1.0.0", "workspaces": ["packages/*"]}"#);
+
+        let member = root.join("packages").join("ui");
+        write_package(&member, r#"{"name": "ui", "version": "1.0.0"}"#);
+        let member_src = member.join("src");
+        std::fs::create_dir_all(&member_src).unwrap();
+
+        // Package-level commands see the member, workspace commands see the root
+        assert_eq!(find_project_root(&member_src), member);
+        assert_eq!(find_workspace_root(&member_src).as_deref(), Some(root));
+    }
+}
@@ -1,140 +1,251 @@
 //! Parallel package downloader
 
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use futures::stream::{self, StreamExt};
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+use tokio::io::AsyncWriteExt;
 
 use crate::cache::CacheManager;
+use crate::core::config::{NetworkConfig, RegistryConfig};
 use crate::core::{VelocityError, VelocityResult};
-use crate::resolver::ResolvedPackage;
+use crate::registry::http::{self, InsecureOrigins, OptimizedHttpClient, RetryPolicy};
+use crate::resolver::{tarball, ResolvedPackage};
+use crate::utils::{NoProgress, Phase, Progress};
 
 /// Parallel package downloader
 pub struct Downloader {
     /// Cache manager
     cache: Arc<CacheManager>,
 
-    /// HTTP client
-    client: reqwest::Client,
+    /// Shared HTTP client
+    http: OptimizedHttpClient,
+
+    /// Plain-HTTP origins allowed for tarballs
+    insecure: InsecureOrigins,
+
+    /// Registry settings used to pick auth tokens for tarballs
+    registry: RegistryConfig,
+
+    /// Retries for failed downloads, each resuming where the last one stopped
+    retry: RetryPolicy,
 
     /// Maximum concurrent downloads
     concurrency: usize,
+
+    /// Where download progress is reported
+    progress: Arc<dyn Progress>,
+
+    /// Directory `file:` tarballs are relative to
+    base_dir: PathBuf,
 }
 
 impl Downloader {
-    /// Create a new downloader
-    pub fn new(cache: Arc<CacheManager>, concurrency: usize) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(300))
-            .gzip(true)
-            .brotli(true)
-            .build()
-            .expect("Failed to create HTTP client");
-
-        Self {
+    /// Create a new downloader sending its requests through `http`
+    pub fn new(
+        cache: Arc<CacheManager>,
+        http: OptimizedHttpClient,
+        concurrency: usize,
+        network: &NetworkConfig,
+    ) -> VelocityResult<Self> {
+        Ok(Self {
             cache,
-            client,
+            http,
+            insecure: InsecureOrigins::from_config(&network.allow_insecure_registries),
+            registry: RegistryConfig::default(),
+            retry: RetryPolicy::from_config(network),
             concurrency,
-        }
+            progress: Arc::new(NoProgress),
+            base_dir: PathBuf::from("."),
+        })
+    }
+
+    /// Authenticate tarball requests with the given registry settings
+    pub fn with_registry_config(mut self, registry: RegistryConfig) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Report download progress to `progress`
+    pub fn with_progress(mut self, progress: Arc<dyn Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Read `file:` tarballs relative to `dir`, the project's directory
+    pub fn with_base_dir(mut self, dir: PathBuf) -> Self {
+        self.base_dir = dir;
+        self
     }
 
     /// Download a single package
+    #[tracing::instrument(
+        level = "debug",
+        name = "download",
+        skip_all,
+        fields(package = %package.name, version = %package.version)
+    )]
     pub async fn download(&self, package: &ResolvedPackage, prefer_offline: bool) -> VelocityResult<u64> {
-        // Check cache first
-        if prefer_offline {
-            if self.cache.has_package(&package.name, &package.version)? {
-                return Ok(0);
-            }
+        if let Some(path) = tarball::local_path(&package.tarball_url, &self.base_dir) {
+            return self.copy_local(package, &path);
         }
 
-        // Download tarball
-        let response = self.client
-            .get(&package.tarball_url)
-            .send()
-            .await
-            .map_err(|e| VelocityError::Network(e.to_string()))?;
-
-        if !response.status().is_success() {
-            return Err(VelocityError::Network(format!(
-                "Failed to download {}: HTTP {}",
-                package.name,
-                response.status()
-            )));
+        // Check cache first; a cached tarball only counts while it still matches the integrity
+        if (prefer_offline || self.cache.is_offline())
+            && (self.cache.has_package(&package.name, &package.version)? || self.has_verified_tarball(package))
+        {
+            self.progress.package_done(Phase::Download, &format!("{}@{}", package.name, package.version));
+            return Ok(0);
         }
-
-        let content_length = response.content_length().unwrap_or(0);
-
-        // Get the bytes
-        let bytes = response.bytes().await
-            .map_err(|e| VelocityError::Network(e.to_string()))?;
-
-        // Verify integrity if provided
-        if !package.integrity.is_empty() {
-            self.verify_integrity(&bytes, &package.integrity, &package.name)?;
+        if self.cache.is_offline() {
+            return Err(VelocityError::Offline {
+                package: format!("{}@{}", package.name, package.version),
+                resource: "the package tarball".to_string(),
+            });
         }
 
-        // Save to cache
-        self.cache.store_tarball(&package.name, &package.version, &bytes)?;
+        self.check_transport(package, &package.tarball_url)?;
+
+        let id = format!("{}@{}", package.name, package.version);
+        let size = self.retry.run(|| self.fetch(package, &id)).await?;
+        self.progress.package_done(Phase::Download, &id);
 
-        Ok(content_length)
+        Ok(size)
     }
 
-    /// Download multiple packages in parallel
-    pub async fn download_all(&self, packages: &[ResolvedPackage]) -> VelocityResult<u64> {
-        let total_bytes = Arc::new(std::sync::atomic::AtomicU64::new(0));
+    /// One attempt at downloading a tarball into the cache
+    ///
+    /// The body streams into a `.part` file next to the tarball. When a verifiable
+    /// integrity hash is known and an earlier attempt left a partial file, only the
+    /// rest is requested with a Range header; the hash is checked once the file is
+    /// complete, so a bad resume costs a restart rather than a corrupt tarball.
+    async fn fetch(&self, package: &ResolvedPackage, id: &str) -> VelocityResult<u64> {
+        let part = self.cache.get_partial_tarball_path(&package.name, &package.version);
+        let offset = if is_verifiable(&package.integrity) {
+            std::fs::metadata(&part).map(|meta| meta.len()).unwrap_or(0)
+        } else {
+            0
+        };
+
+        // Byte ranges must refer to the tarball as stored, not a re-encoded body
+        let mut request = self.http.client().get(&package.tarball_url).header(ACCEPT_ENCODING, "identity");
+        if let Some(token) = self.registry.auth_token_for_tarball(package.package_name(), &package.tarball_url) {
+            request = request.bearer_auth(token);
+        }
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        let response = self.http.send(request).await.map_err(http::send_error)?;
+        // An https tarball URL may redirect to plain HTTP
+        self.check_transport(package, response.url().as_str())?;
+
+        let status = response.status();
+        if status == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+            // Nothing left to fetch: the partial file is whole, or not this tarball at all
+            return self.complete(package, &part, true);
+        }
+        if !status.is_success() {
+            let message = format!("Failed to download {}: HTTP {}", package.name, status);
+            return Err(if http::is_transient_status(status) {
+                VelocityError::Network(message)
+            } else {
+                VelocityError::Registry(message)
+            });
+        }
 
-        let results: Vec<VelocityResult<()>> = stream::iter(packages.iter())
-            .map(|pkg| {
-                let client = self.client.clone();
-                let cache = self.cache.clone();
-                let total = total_bytes.clone();
-                let pkg = pkg.clone();
+        // Servers that ignore the range send the whole tarball
+        let resumed = offset > 0 && status == StatusCode::PARTIAL_CONTENT && range_start(&response) == Some(offset);
+        let mut written = if resumed { offset } else { 0 };
+        if resumed {
+            tracing::debug!("Resuming {} at byte {}", id, offset);
+        }
 
-                async move {
-                    // Check cache
-                    if cache.has_package(&pkg.name, &pkg.version)? {
-                        return Ok(());
-                    }
+        if let Some(parent) = part.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&part)
+            .await?;
+
+        self.progress.download_started(id, response.content_length().map(|len| len + written));
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| VelocityError::Network(e.to_string()))?;
+            file.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+            self.http.metrics().add_downloaded(chunk.len() as u64);
+            self.progress.download_progress(id, written);
+        }
+        file.flush().await?;
+        drop(file);
 
-                    // Download
-                    let response = client
-                        .get(&pkg.tarball_url)
-                        .send()
-                        .await
-                        .map_err(|e| VelocityError::Network(e.to_string()))?;
-
-                    if !response.status().is_success() {
-                        return Err(VelocityError::Network(format!(
-                            "Failed to download {}: HTTP {}",
-                            pkg.name,
-                            response.status()
-                        )));
-                    }
+        self.complete(package, &part, resumed)
+    }
 
-                    let bytes = response.bytes().await
-                        .map_err(|e| VelocityError::Network(e.to_string()))?;
+    /// Copy a `file:` tarball into the cache, unless resolving already put it there
+    fn copy_local(&self, package: &ResolvedPackage, path: &Path) -> VelocityResult<u64> {
+        let id = format!("{}@{}", package.name, package.version);
+        if self.has_verified_tarball(package) {
+            self.progress.package_done(Phase::Download, &id);
+            return Ok(0);
+        }
 
-                    // Verify integrity
-                    if !pkg.integrity.is_empty() {
-                        verify_integrity_static(&bytes, &pkg.integrity, &pkg.name)?;
-                    }
+        let data = std::fs::read(path)
+            .map_err(|e| VelocityError::other(format!("Cannot read tarball {}: {}", path.display(), e)))?;
+        if !package.integrity.is_empty() {
+            self.verify_integrity(&data, &package.integrity, &package.name)?;
+        }
+        self.cache.store_tarball(&package.name, &package.version, &data)?;
+        self.progress.package_done(Phase::Download, &id);
+        Ok(data.len() as u64)
+    }
+
+    /// Refuse plain HTTP from origins not allowed to use it, and without an integrity hash
+    fn check_transport(&self, package: &ResolvedPackage, url: &str) -> VelocityResult<()> {
+        let plain_http = self.insecure.check(url)?;
+        require_integrity(package, plain_http)
+    }
 
-                    // Store
-                    cache.store_tarball(&pkg.name, &pkg.version, &bytes)?;
+    /// Verify a finished `.part` file and move it into place as the cached tarball
+    fn complete(&self, package: &ResolvedPackage, part: &Path, resumed: bool) -> VelocityResult<u64> {
+        let bytes = std::fs::read(part)?;
+        if !package.integrity.is_empty() {
+            if let Err(e) = self.verify_integrity(&bytes, &package.integrity, &package.name) {
+                std::fs::remove_file(part).ok();
+                // A resume can splice two different files; the retry starts over
+                return Err(if resumed {
+                    VelocityError::Network(format!("Resumed download of {} did not verify; restarting", package.name))
+                } else {
+                    e
+                });
+            }
+        }
 
-                    total.fetch_add(bytes.len() as u64, std::sync::atomic::Ordering::Relaxed);
+        self.cache.commit_partial_tarball(&package.name, &package.version)?;
+        Ok(bytes.len() as u64)
+    }
 
-                    Ok(())
-                }
-            })
-            .buffer_unordered(self.concurrency)
+    /// Download several packages, up to the download concurrency at once
+    pub async fn download_all(&self, packages: &[&ResolvedPackage], prefer_offline: bool) -> VelocityResult<u64> {
+        let sizes: Vec<VelocityResult<u64>> = stream::iter(packages)
+            .map(|pkg| self.download(pkg, prefer_offline))
+            .buffer_unordered(self.concurrency.max(1))
             .collect()
             .await;
 
-        // Check for errors
-        for result in results {
-            result?;
-        }
+        sizes.into_iter().sum()
+    }
 
-        Ok(total_bytes.load(std::sync::atomic::Ordering::Relaxed))
+    /// Whether the cache holds this package's tarball, matching its integrity
+    fn has_verified_tarball(&self, package: &ResolvedPackage) -> bool {
+        is_verifiable(&package.integrity)
+            && std::fs::read(self.cache.get_tarball_path(&package.name, &package.version))
+                .is_ok_and(|data| verify_integrity_static(&data, &package.integrity, &package.name).is_ok())
     }
 
     /// Verify package integrity
@@ -143,8 +254,30 @@ impl Downloader {
     }
 }
 
+/// Plain-HTTP downloads are only trusted with a hash to check them against
+fn require_integrity(package: &ResolvedPackage, plain_http: bool) -> VelocityResult<()> {
+    if plain_http && !is_verifiable(&package.integrity) {
+        return Err(VelocityError::IntegrityRequired { package: package.name.clone() });
+    }
+    Ok(())
+}
+
+/// Whether an integrity string names a hash this downloader can check
+fn is_verifiable(integrity: &str) -> bool {
+    integrity.starts_with("sha512-") || integrity.starts_with("sha256-")
+}
+
+/// First byte of a 206 response, from `Content-Range: bytes <start>-<end>/<total>`
+fn range_start(response: &reqwest::Response) -> Option<u64> {
+    parse_range_start(response.headers().get(CONTENT_RANGE)?.to_str().ok()?)
+}
+
+fn parse_range_start(content_range: &str) -> Option<u64> {
+    content_range.strip_prefix("bytes ")?.split('-').next()?.trim().parse().ok()
+}
+
 /// Static integrity verification function
-fn verify_integrity_static(data: &[u8], integrity: &str, package: &str) -> VelocityResult<()> {
+pub(crate) fn verify_integrity_static(data: &[u8], integrity: &str, package: &str) -> VelocityResult<()> {
     use sha2::{Sha256, Sha512, Digest};
 
     let (algorithm, expected_hash) = if let Some(hash) = integrity.strip_prefix("sha512-") {
@@ -181,3 +314,182 @@ fn verify_integrity_static(data: &[u8], integrity: &str, package: &str) -> Veloc
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::CacheConfig;
+    use crate::resolver::test_support;
+    use crate::utils::PerformanceMetrics;
+
+    fn downloader(cache_dir: &Path, network: &NetworkConfig) -> Downloader {
+        let cache = Arc::new(CacheManager::new(cache_dir, &CacheConfig::default()).unwrap());
+        let http = OptimizedHttpClient::new(network, Arc::new(PerformanceMetrics::new())).unwrap();
+        Downloader::new(cache, http, 4, network).unwrap()
+    }
+
+    fn package(tarball_url: &str, integrity: &str) -> ResolvedPackage {
+        ResolvedPackage {
+            tarball_url: tarball_url.to_string(),
+            integrity: integrity.to_string(),
+            ..test_support::package("pkg", "1.0.0", &[])
+        }
+    }
+
+    #[test]
+    fn test_redirect_to_plain_http_needs_integrity() {
+        let dir = tempfile::tempdir().unwrap();
+        let network = NetworkConfig {
+            allow_insecure_registries: vec!["http://mirror.internal".to_string()],
+            ..Default::default()
+        };
+        let downloader = downloader(dir.path(), &network);
+        let unhashed = package("https://registry.example/pkg.tgz", "");
+
+        // Where an https tarball URL ends up is checked like the URL itself
+        assert!(downloader.check_transport(&unhashed, "https://cdn.example/pkg.tgz").is_ok());
+        assert!(matches!(
+            downloader.check_transport(&unhashed, "http://mirror.internal/pkg.tgz"),
+            Err(VelocityError::IntegrityRequired { .. })
+        ));
+        assert!(matches!(
+            downloader.check_transport(&unhashed, "http://elsewhere.example/pkg.tgz"),
+            Err(VelocityError::InsecureRegistry { .. })
+        ));
+        let hashed = package("https://registry.example/pkg.tgz", "sha512-abc");
+        assert!(downloader.check_transport(&hashed, "http://mirror.internal/pkg.tgz").is_ok());
+    }
+
+    /// How the test server answers a request with the given Range start
+    type Respond = fn(Option<u64>, &[u8]) -> Vec<u8>;
+
+    /// Serve `body` over plain HTTP, recording each request's Range start
+    async fn serve(body: &'static [u8], respond: Respond) -> (String, Arc<std::sync::Mutex<Vec<Option<u64>>>>) {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let origin = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        let ranges = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let seen = ranges.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let range = String::from_utf8_lossy(&request)
+                    .lines()
+                    .find_map(|line| line.to_ascii_lowercase().strip_prefix("range: bytes=")?.strip_suffix('-')?.parse().ok());
+                seen.lock().unwrap().push(range);
+                let _ = stream.write_all(&respond(range, body)).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        (origin, ranges)
+    }
+
+    fn response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            headers,
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    /// Honors Range requests with a 206
+    fn ranged(range: Option<u64>, body: &[u8]) -> Vec<u8> {
+        match range {
+            Some(start) if start as usize >= body.len() => response("416 Range Not Satisfiable", "", b""),
+            Some(start) => response(
+                "206 Partial Content",
+                &format!("Content-Range: bytes {}-{}/{}\r\n", start, body.len() - 1, body.len()),
+                &body[start as usize..],
+            ),
+            None => response("200 OK", "", body),
+        }
+    }
+
+    /// Ignores Range requests and always sends the whole body
+    fn whole(_: Option<u64>, body: &[u8]) -> Vec<u8> {
+        response("200 OK", "", body)
+    }
+
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz0123456789abcdefghijklmnopqrstuvwxyz";
+
+    /// Download from a server answering with `respond`, after an earlier attempt left `part`
+    async fn resume(respond: Respond, part: &[u8]) -> (VelocityResult<u64>, Vec<Option<u64>>, Vec<u8>) {
+        use sha2::{Digest, Sha512};
+
+        let (origin, ranges) = serve(BODY, respond).await;
+        let dir = tempfile::tempdir().unwrap();
+        let network = NetworkConfig { allow_insecure_registries: vec![origin.clone()], ..Default::default() };
+        let downloader = downloader(dir.path(), &network);
+        let integrity = format!(
+            "sha512-{}",
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, Sha512::digest(BODY))
+        );
+        let package = package(&format!("{}/pkg.tgz", origin), &integrity);
+
+        let part_path = downloader.cache.get_partial_tarball_path("pkg", "1.0.0");
+        std::fs::create_dir_all(part_path.parent().unwrap()).unwrap();
+        std::fs::write(&part_path, part).unwrap();
+
+        let result = downloader.download(&package, false).await;
+        let tarball = std::fs::read(downloader.cache.get_tarball_path("pkg", "1.0.0")).unwrap_or_default();
+        let ranges = ranges.lock().unwrap().clone();
+        (result, ranges, tarball)
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_partial_file() {
+        let (result, ranges, tarball) = resume(ranged, &BODY[..20]).await;
+        assert_eq!(result.unwrap(), BODY.len() as u64);
+        assert_eq!(ranges, [Some(20)]);
+        assert_eq!(tarball, BODY);
+    }
+
+    #[tokio::test]
+    async fn test_download_ignored_range_starts_over() {
+        // A 200 answer to a Range request replaces the partial file
+        let (result, ranges, tarball) = resume(whole, &BODY[..20]).await;
+        assert_eq!(result.unwrap(), BODY.len() as u64);
+        assert_eq!(ranges, [Some(20)]);
+        assert_eq!(tarball, BODY);
+    }
+
+    #[tokio::test]
+    async fn test_download_complete_partial_file() {
+        // Nothing left to fetch: the 416 leaves the partial file to be verified as is
+        let (result, ranges, tarball) = resume(ranged, BODY).await;
+        assert_eq!(result.unwrap(), BODY.len() as u64);
+        assert_eq!(ranges, [Some(BODY.len() as u64)]);
+        assert_eq!(tarball, BODY);
+    }
+
+    #[tokio::test]
+    async fn test_download_mismatched_resume_restarts() {
+        // The partial file is from other bytes; the spliced file fails and the retry starts over
+        let (result, ranges, tarball) = resume(ranged, &[b'x'; 20]).await;
+        assert_eq!(result.unwrap(), BODY.len() as u64);
+        assert_eq!(ranges, [Some(20), None]);
+        assert_eq!(tarball, BODY);
+    }
+
+    #[test]
+    fn test_parse_range_start() {
+        assert_eq!(parse_range_start("bytes 1024-4095/4096"), Some(1024));
+        assert_eq!(parse_range_start("bytes 0-99/*"), Some(0));
+        assert_eq!(parse_range_start("bytes */4096"), None);
+        assert_eq!(parse_range_start("items 0-1/2"), None);
+        assert!(is_verifiable("sha512-abc") && !is_verifiable("sha1-abc") && !is_verifiable(""));
+    }
+}
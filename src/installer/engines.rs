@@ -0,0 +1,75 @@
+//! engines.node checks against the Node.js version the project runs on
+
+use std::fmt;
+
+use crate::resolver::VersionConstraint;
+
+/// A package whose `engines.node` range excludes the project's Node.js version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineIssue {
+    pub package: String,
+    pub version: String,
+
+    /// The package's `engines.node` range
+    pub required: String,
+
+    /// Node.js version in use
+    pub node: String,
+}
+
+impl EngineIssue {
+    /// JSON representation used by `--json` output
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "package": self.package,
+            "version": self.version,
+            "required": self.required,
+            "node": self.node,
+        })
+    }
+}
+
+impl fmt::Display for EngineIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{} requires node {}, but {} is in use", self.package, self.version, self.required, self.node)
+    }
+}
+
+/// Check a package manifest's `engines.node` against `node`
+///
+/// Pre-release Node.js builds are judged by their release version, and
+/// manifests without a string `engines.node` (including npm's long-gone
+/// array form) never conflict.
+pub fn check_engine(name: &str, version: &str, manifest: &serde_json::Value, node: &semver::Version) -> Option<EngineIssue> {
+    let required = manifest.get("engines")?.get("node")?.as_str()?.trim();
+    let constraint = VersionConstraint::parse(required).ok()?;
+    let release = semver::Version::new(node.major, node.minor, node.patch);
+    if constraint.matches(&release) {
+        return None;
+    }
+    Some(EngineIssue {
+        package: name.to_string(),
+        version: version.to_string(),
+        required: required.to_string(),
+        node: node.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_engine() {
+        let node = semver::Version::new(18, 19, 0);
+        let manifest = |engines: serde_json::Value| serde_json::json!({ "name": "pkg", "engines": engines });
+
+        assert_eq!(check_engine("pkg", "1.0.0", &manifest(serde_json::json!({ "node": ">=16" })), &node), None);
+        assert_eq!(check_engine("pkg", "1.0.0", &manifest(serde_json::json!({ "node": "^14 || ^16 || >=18" })), &node), None);
+        assert_eq!(check_engine("pkg", "1.0.0", &manifest(serde_json::json!(["node >= 0.4"])), &node), None);
+        assert_eq!(check_engine("pkg", "1.0.0", &serde_json::json!({ "name": "pkg" }), &node), None);
+
+        let issue = check_engine("pkg", "2.0.0", &manifest(serde_json::json!({ "node": ">=20.0.0" })), &node).unwrap();
+        assert_eq!(issue.to_string(), "pkg@2.0.0 requires node >=20.0.0, but 18.19.0 is in use");
+    }
+}
@@ -1,7 +1,7 @@
 //! Package tarball extractor with security checks
 
 use std::io::Read;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
 use flate2::read::GzDecoder;
@@ -12,7 +12,10 @@ use crate::core::{VelocityError, VelocityResult};
 use crate::resolver::ResolvedPackage;
 use crate::security::SecurityManager;
 
+use super::downloader::verify_integrity_static;
+
 /// Package extractor
+#[derive(Clone)]
 pub struct Extractor {
     /// Cache manager
     cache: Arc<CacheManager>,
@@ -28,7 +31,10 @@ impl Extractor {
     }
 
     /// Extract a package from its tarball
-    pub async fn extract(&self, package: &ResolvedPackage) -> VelocityResult<PathBuf> {
+    ///
+    /// This blocks on disk I/O and decompression; async callers should run it
+    /// on the blocking pool.
+    pub fn extract(&self, package: &ResolvedPackage) -> VelocityResult<PathBuf> {
         let tarball_path = self.cache.get_tarball_path(&package.name, &package.version);
 
         if !tarball_path.exists() {
@@ -38,97 +44,364 @@ impl Extractor {
             )));
         }
 
-        let extract_dir = self.cache.get_package_dir(&package.name, &package.version);
+        // Skip if already extracted from the same tarball
+        if self.cache.has_package(&package.name, &package.version)? {
+            let marker = self.cache.extraction_marker(&package.name, &package.version)?;
+            let same_tarball = !package.integrity.starts_with("sha512-")
+                || marker.as_deref() == Some(package.integrity.as_str());
+            if same_tarball {
+                return Ok(self.cache.get_package_dir(&package.name, &package.version));
+            }
+        }
 
-        // Skip if already extracted
-        if extract_dir.exists() {
-            return Ok(extract_dir);
+        // The cached tarball must still be the one the lockfile names
+        let tarball_data = std::fs::read(&tarball_path)?;
+        if package.integrity.starts_with("sha512-") || package.integrity.starts_with("sha256-") {
+            verify_integrity_static(&tarball_data, &package.integrity, &package.name)?;
         }
 
-        // Create extraction directory
-        std::fs::create_dir_all(&extract_dir)?;
+        // Extract into a temporary directory and move it into place once complete,
+        // so an interrupted extraction never looks like a cached package
+        let temp_dir = self.cache.temp_extract_dir(&package.name, &package.version)?;
+        if let Err(e) = self.unpack(&tarball_data, &temp_dir, &package.name) {
+            std::fs::remove_dir_all(&temp_dir).ok();
+            return Err(e);
+        }
 
-        // Read tarball
-        let tarball_data = std::fs::read(&tarball_path)?;
+        self.cache.commit_extraction(
+            &package.name,
+            &package.version,
+            &temp_dir,
+            &tarball_integrity(&tarball_data),
+        )
+    }
+
+    /// Unpack a tarball into a directory
+    ///
+    /// Entries may not leave `extract_dir`: paths with `..` or a root are
+    /// refused, and so are symlinks that resolve outside it. Symlinks are only
+    /// created once every file is written, so no write can go through one.
+    /// Files lose setuid, setgid and sticky bits, and the configured file count
+    /// and unpacked size limits bound what a tarball can expand to.
+    fn unpack(&self, tarball_data: &[u8], extract_dir: &Path, package: &str) -> VelocityResult<()> {
+        let max_files = self.security.max_package_files();
+        let max_size = self.security.max_package_size();
+        let too_large = |details: String| VelocityError::PackageTooLarge {
+            package: package.to_string(),
+            details,
+        };
 
         // Decompress
-        let decoder = GzDecoder::new(&tarball_data[..]);
+        let decoder = GzDecoder::new(tarball_data);
         let mut archive = Archive::new(decoder);
 
-        // Extract with security checks
+        let mut files = 0usize;
+        let mut unpacked = 0u64;
+        let mut symlinks = Vec::new();
+
         for entry in archive.entries()? {
             let mut entry = entry?;
             let entry_path = entry.path()?.into_owned();
 
             // Security check: path traversal protection
-            self.check_path_traversal(&entry_path, &package.name)?;
+            self.check_path_traversal(&entry_path, package)?;
 
             // npm packages have a "package/" prefix
             let relative_path = entry_path
                 .strip_prefix("package/")
                 .or_else(|_| entry_path.strip_prefix("package"))
-                .unwrap_or(&entry_path);
+                .unwrap_or(&entry_path)
+                .to_path_buf();
 
-            let target_path = extract_dir.join(relative_path);
+            let target_path = extract_dir.join(&relative_path);
+            let entry_type = entry.header().entry_type();
 
-            // Ensure parent directory exists
-            if let Some(parent) = target_path.parent() {
-                std::fs::create_dir_all(parent)?;
-            }
+            if entry_type.is_file() {
+                files += 1;
+                if max_files.is_some_and(|max| files > max) {
+                    return Err(too_large(format!(
+                        "more than {} files (security.max_package_files)",
+                        files - 1
+                    )));
+                }
 
-            // Extract file
-            if entry.header().entry_type().is_file() {
+                // The header's size can't be trusted, so what is read is counted
+                let remaining = max_size.map_or(u64::MAX, |max| max.saturating_sub(unpacked));
                 let mut content = Vec::new();
-                entry.read_to_end(&mut content)?;
+                (&mut entry).take(remaining.saturating_add(1)).read_to_end(&mut content)?;
+                unpacked += content.len() as u64;
+                if let Some(max) = max_size.filter(|max| unpacked > *max) {
+                    return Err(too_large(format!(
+                        "more than {} bytes unpacked (security.max_package_size)",
+                        max
+                    )));
+                }
+
+                if let Some(parent) = target_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
                 std::fs::write(&target_path, content)?;
 
-                // Set permissions on Unix
+                // Set permissions on Unix, without setuid, setgid or sticky bits
                 #[cfg(unix)]
                 {
                     use std::os::unix::fs::PermissionsExt;
                     if let Ok(mode) = entry.header().mode() {
                         let _ = std::fs::set_permissions(
                             &target_path,
-                            std::fs::Permissions::from_mode(mode),
+                            std::fs::Permissions::from_mode(mode & 0o777),
                         );
                     }
                 }
-            } else if entry.header().entry_type().is_dir() {
+            } else if entry_type.is_dir() {
                 std::fs::create_dir_all(&target_path)?;
+            } else if entry_type.is_symlink() {
+                let Some(link_target) = entry.link_name()? else {
+                    continue;
+                };
+                let link_target = link_target.into_owned();
+                if !stays_inside(&relative_path, &link_target) {
+                    return Err(symlink_escape(package, &relative_path, &link_target));
+                }
+                symlinks.push((relative_path, link_target));
+            } else {
+                tracing::debug!("Skipping {:?} entry {} in {}", entry_type, entry_path.display(), package);
+            }
+        }
+
+        for (path, link_target) in &symlinks {
+            let link_path = extract_dir.join(path);
+            if let Some(parent) = link_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            if std::fs::symlink_metadata(&link_path).is_ok() {
+                continue;
             }
+            create_symlink(link_target, &link_path)?;
         }
 
-        Ok(extract_dir)
+        // Links through other links can escape even when each looks inside on its own
+        let root = extract_dir.canonicalize()?;
+        for (path, link_target) in &symlinks {
+            if let Ok(resolved) = extract_dir.join(path).canonicalize() {
+                if !resolved.starts_with(&root) {
+                    return Err(symlink_escape(package, path, link_target));
+                }
+            }
+        }
+
+        Ok(())
     }
 
     /// Check for path traversal attacks
     fn check_path_traversal(&self, path: &Path, package: &str) -> VelocityResult<()> {
         let path_str = path.to_string_lossy();
 
-        // Check for ..
-        if path_str.contains("..") {
+        // Check for suspicious characters
+        if path_str.contains('\0') {
             return Err(VelocityError::PathTraversal {
                 package: package.to_string(),
-                path: path_str.to_string(),
+                path: "null byte in path".to_string(),
             });
         }
 
-        // Check for absolute paths
-        if path.is_absolute() {
+        // Check for absolute paths and `..` components
+        let escapes = path.is_absolute()
+            || path
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+        if escapes {
             return Err(VelocityError::PathTraversal {
                 package: package.to_string(),
                 path: path_str.to_string(),
             });
         }
 
-        // Check for suspicious characters
-        if path_str.contains('\0') {
-            return Err(VelocityError::PathTraversal {
-                package: package.to_string(),
-                path: "null byte in path".to_string(),
-            });
+        Ok(())
+    }
+}
+
+/// Whether a symlink at `link` (relative to the package root) pointing at `target` stays inside the root
+fn stays_inside(link: &Path, target: &Path) -> bool {
+    if target.is_absolute() {
+        return false;
+    }
+    let mut depth = link.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn symlink_escape(package: &str, link: &Path, target: &Path) -> VelocityError {
+    VelocityError::PathTraversal {
+        package: package.to_string(),
+        path: format!("symlink {} -> {} points outside the package", link.display(), target.display()),
+    }
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    // Creating symlinks needs a privilege most Windows users lack, so they are left out
+    let _ = (target, link);
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// sha512 integrity string of a tarball, recorded as the extraction marker
+pub(crate) fn tarball_integrity(data: &[u8]) -> String {
+    use sha2::{Digest, Sha512};
+
+    let mut hasher = Sha512::new();
+    hasher.update(data);
+    format!(
+        "sha512-{}",
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, hasher.finalize())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::{CacheConfig, SecurityConfig};
+    use flate2::write::GzEncoder;
+    use tar::{EntryType, Header};
+    use tempfile::tempdir;
+
+    /// An entry of a crafted tarball: path, type, content or link target, mode
+    struct Entry(&'static str, EntryType, &'static str, u32);
+
+    /// Build a gzipped tarball without the `tar` crate's own path checks
+    fn tarball(entries: &[Entry]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::fast()));
+        for Entry(path, entry_type, data, mode) in entries {
+            let mut header = Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(*mode);
+            if entry_type.is_symlink() {
+                header.set_link_name(data).unwrap();
+                header.set_size(0);
+                header.set_cksum();
+                builder.append(&header, std::io::empty()).unwrap();
+            } else {
+                header.set_size(data.len() as u64);
+                header.set_cksum();
+                builder.append(&header, data.as_bytes()).unwrap();
+            }
         }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
 
-        Ok(())
+    fn extractor(config: SecurityConfig) -> (tempfile::TempDir, Extractor) {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(CacheManager::new(&dir.path().join("cache"), &CacheConfig::default()).unwrap());
+        (dir, Extractor::new(cache, Arc::new(SecurityManager::new(&config))))
+    }
+
+    fn unpack(extractor: &Extractor, dir: &Path, entries: &[Entry]) -> VelocityResult<PathBuf> {
+        let target = tempfile::tempdir_in(dir).unwrap().keep();
+        extractor.unpack(&tarball(entries), &target, "pkg").map(|_| target)
+    }
+
+    #[test]
+    fn test_rejects_escaping_paths() {
+        let (dir, extractor) = extractor(SecurityConfig::default());
+        let file = EntryType::Regular;
+
+        for path in ["package/../../evil.js", "/etc/evil.js", "package/lib/../../../evil.js"] {
+            let result = unpack(&extractor, dir.path(), &[Entry(path, file, "x", 0o644)]);
+            assert!(matches!(result, Err(VelocityError::PathTraversal { .. })), "{}", path);
+        }
+        assert!(!dir.path().join("evil.js").exists());
+
+        // `..` inside a name is not a parent directory
+        let out = unpack(&extractor, dir.path(), &[Entry("package/a..b.js", file, "x", 0o644)]).unwrap();
+        assert!(out.join("a..b.js").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_stay_inside() {
+        let (dir, extractor) = extractor(SecurityConfig::default());
+        let (file, link) = (EntryType::Regular, EntryType::Symlink);
+
+        let out = unpack(&extractor, dir.path(), &[
+            Entry("package/lib/index.js", file, "module.exports = 1;", 0o644),
+            Entry("package/index.js", link, "lib/index.js", 0o777),
+            Entry("package/lib/self.js", link, "../lib/./index.js", 0o777),
+        ])
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(out.join("index.js")).unwrap(), "module.exports = 1;");
+        assert!(out.join("lib/self.js").is_symlink());
+
+        for target in ["/etc/passwd", "../../outside", "lib/../../outside"] {
+            let result = unpack(&extractor, dir.path(), &[Entry("package/evil", link, target, 0o777)]);
+            assert!(matches!(result, Err(VelocityError::PathTraversal { .. })), "{}", target);
+        }
+
+        // Each link looks inside on its own, but `here/..` leaves through the first
+        let result = unpack(&extractor, dir.path(), &[
+            Entry("package/here", link, ".", 0o777),
+            Entry("package/up", link, "here/..", 0o777),
+            Entry("package/x", file, "", 0o644),
+        ]);
+        assert!(matches!(result, Err(VelocityError::PathTraversal { .. })));
+
+        // A file written under a link's name goes into the package, never through the link
+        let out = unpack(&extractor, dir.path(), &[
+            Entry("package/dir", link, "sub", 0o777),
+            Entry("package/dir/file.js", file, "x", 0o644),
+        ])
+        .unwrap();
+        assert!(out.join("dir").is_dir() && !out.join("dir").is_symlink());
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let limited = SecurityConfig { max_package_files: 2, max_package_size: 10, ..Default::default() };
+        let (dir, extractor) = extractor(limited);
+        let file = EntryType::Regular;
+
+        let result = unpack(&extractor, dir.path(), &[
+            Entry("package/a", file, "", 0o644),
+            Entry("package/b", file, "", 0o644),
+            Entry("package/c", file, "", 0o644),
+        ]);
+        assert!(matches!(result, Err(VelocityError::PackageTooLarge { .. })));
+
+        let result = unpack(&extractor, dir.path(), &[
+            Entry("package/a", file, "123456", 0o644),
+            Entry("package/b", file, "123456", 0o644),
+        ]);
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("security.max_package_size"), "{}", message);
+
+        assert!(unpack(&extractor, dir.path(), &[Entry("package/a", file, "1234567890", 0o644)]).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_strips_special_mode_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, extractor) = extractor(SecurityConfig::default());
+        let out = unpack(&extractor, dir.path(), &[Entry("package/bin/tool", EntryType::Regular, "#!/bin/sh", 0o6755)]).unwrap();
+        let mode = std::fs::metadata(out.join("bin/tool")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o755);
     }
 }
@@ -0,0 +1,370 @@
+//! node_modules layout planning
+//!
+//! A resolution may contain several versions of the same package. These
+//! helpers decide which version each dependency edge uses and, for the
+//! hoisted layout, where every copy goes: one version per name at the root,
+//! others nested under the dependents that need them. In a workspace-wide
+//! install, members needing another version than the root's get their own
+//! copy in the member's node_modules.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use crate::resolver::{ResolvedPackage, VersionConstraint};
+
+/// Deepest nesting accepted before a dependency is left to hoisting
+const MAX_DEPTH: usize = 64;
+
+/// Resolved packages indexed by name
+pub struct PackageIndex<'a> {
+    /// Versions per package, newest first
+    by_name: BTreeMap<&'a str, Vec<(semver::Version, &'a ResolvedPackage)>>,
+}
+
+impl<'a> PackageIndex<'a> {
+    /// Index a set of resolved packages
+    pub fn new(packages: &[&'a ResolvedPackage]) -> Self {
+        let mut by_name: BTreeMap<&'a str, Vec<(semver::Version, &'a ResolvedPackage)>> = BTreeMap::new();
+        for package in packages {
+            let version = semver::Version::parse(&package.version)
+                .unwrap_or_else(|_| semver::Version::new(0, 0, 0));
+            by_name.entry(package.name.as_str()).or_default().push((version, *package));
+        }
+        for versions in by_name.values_mut() {
+            versions.sort_by(|a, b| b.0.cmp(&a.0));
+        }
+
+        Self { by_name }
+    }
+
+    /// The version a dependency edge uses: the newest one satisfying the spec
+    ///
+    /// Specs that match nothing (tags, URLs) fall back to the newest version.
+    pub fn resolve(&self, name: &str, spec: &str) -> Option<&'a ResolvedPackage> {
+        let versions = self.by_name.get(name)?;
+        let constraint = VersionConstraint::parse(spec).unwrap_or(VersionConstraint::Any);
+
+        versions
+            .iter()
+            .find(|(v, _)| constraint.matches(v))
+            .or_else(|| versions.first())
+            .map(|(_, package)| *package)
+    }
+
+    /// Dependency edges of a package that point at indexed packages
+    ///
+    /// Edges the lockfile pins go to that exact version while it is indexed.
+    pub fn dependencies(&self, package: &ResolvedPackage) -> Vec<&'a ResolvedPackage> {
+        let mut deps: Vec<(&String, &String)> = package
+            .dependencies
+            .iter()
+            .chain(package.optional_dependencies.iter())
+            .collect();
+        deps.sort();
+
+        deps.into_iter()
+            .filter_map(|(name, spec)| {
+                let pinned = package.locked_dependencies.get(name).and_then(|version| self.version(name, version));
+                pinned.or_else(|| self.resolve(name, spec))
+            })
+            .collect()
+    }
+
+    /// The indexed package `name` at exactly `version`
+    pub fn version(&self, name: &str, version: &str) -> Option<&'a ResolvedPackage> {
+        self.by_name.get(name)?.iter().map(|(_, package)| *package).find(|package| package.version == version)
+    }
+
+    /// Package names in the index
+    pub fn names(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.by_name.keys().copied()
+    }
+
+    /// Packages reachable from a set of dependency edges
+    pub fn reachable(&self, dependencies: &HashMap<String, String>) -> Vec<&'a ResolvedPackage> {
+        let mut seen: HashSet<(&str, &str)> = HashSet::new();
+        let mut reached = Vec::new();
+        let mut queue: VecDeque<&'a ResolvedPackage> = dependencies
+            .iter()
+            .filter_map(|(name, spec)| self.resolve(name, spec))
+            .collect();
+
+        while let Some(package) = queue.pop_front() {
+            if !seen.insert((package.name.as_str(), package.version.as_str())) {
+                continue;
+            }
+            reached.push(package);
+            queue.extend(self.dependencies(package));
+        }
+
+        reached
+    }
+
+    /// All indexed versions of a package, newest first
+    pub fn versions(&self, name: &str) -> Vec<&'a ResolvedPackage> {
+        self.by_name
+            .get(name)
+            .map(|versions| versions.iter().map(|(_, p)| *p).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// A package copy at a position in a node_modules tree
+#[derive(Debug, Clone)]
+pub struct Placement<'a> {
+    /// Workspace member whose own node_modules holds this copy, if not the root's
+    pub workspace: Option<String>,
+
+    /// Packages this copy is nested under, outermost first
+    pub parents: Vec<String>,
+
+    /// The package placed there
+    pub package: &'a ResolvedPackage,
+}
+
+impl Placement<'_> {
+    /// Directory of this copy below `node_modules`, the root's or the member's
+    pub fn path(&self, node_modules: &Path) -> PathBuf {
+        let mut path = node_modules.to_path_buf();
+        for parent in &self.parents {
+            path = path.join(parent).join("node_modules");
+        }
+        path.join(&self.package.name)
+    }
+
+    /// Location key: the member, the parents and then the package name
+    pub fn location(&self) -> Vec<String> {
+        let mut location: Vec<String> = self.workspace.iter().chain(self.parents.iter()).cloned().collect();
+        location.push(self.package.name.clone());
+        location
+    }
+}
+
+/// A workspace member taking part in a workspace-wide install
+pub struct MemberDependencies<'m> {
+    pub name: &'m str,
+
+    /// The member's registry dependencies
+    pub dependencies: &'m HashMap<String, String>,
+}
+
+/// Plan a hoisted node_modules tree for a project and any workspace members
+///
+/// Direct dependencies get the version package.json asks for at the root;
+/// other packages hoist the version most dependents use. Any dependent that
+/// would see the wrong version gets its own copy nested beneath it.
+///
+/// With `hoist`, members share the root's tree: a version goes to the root
+/// unless the root's own dependencies want another one, and a member whose
+/// dependency resolves differently from what the root holds gets that version
+/// in its own node_modules. Without it, the root and every member get a
+/// separate tree of just what they depend on.
+pub fn hoisted_layout<'a>(
+    index: &PackageIndex<'a>,
+    root_deps: &HashMap<String, String>,
+    members: &[MemberDependencies<'_>],
+    hoist: bool,
+) -> Vec<Placement<'a>> {
+    if hoist {
+        return shared_layout(index, root_deps, members);
+    }
+
+    let mut member_packages: HashSet<(&str, &str)> = HashSet::new();
+    let mut placements = Vec::new();
+    for member in members {
+        let packages = index.reachable(member.dependencies);
+        member_packages.extend(packages.iter().map(|p| (p.name.as_str(), p.version.as_str())));
+        let member_index = PackageIndex::new(&packages);
+        for mut placement in shared_layout(&member_index, member.dependencies, &[]) {
+            placement.workspace = Some(member.name.to_string());
+            placements.push(placement);
+        }
+    }
+
+    // The root keeps what it depends on, plus anything no member reaches (e.g. auto-installed peers)
+    let mut root_packages = index.reachable(root_deps);
+    let reached: HashSet<(&str, &str)> = root_packages.iter().map(|p| (p.name.as_str(), p.version.as_str())).collect();
+    for name in index.names() {
+        for package in index.versions(name) {
+            let key = (package.name.as_str(), package.version.as_str());
+            if !reached.contains(&key) && !member_packages.contains(&key) {
+                root_packages.push(package);
+            }
+        }
+    }
+    let root_index = PackageIndex::new(&root_packages);
+    placements.extend(shared_layout(&root_index, root_deps, &[]));
+
+    placements
+}
+
+/// One tree for the root, with member-local copies only where they conflict
+fn shared_layout<'a>(
+    index: &PackageIndex<'a>,
+    root_deps: &HashMap<String, String>,
+    members: &[MemberDependencies<'_>],
+) -> Vec<Placement<'a>> {
+    // How many edges point at each version
+    let mut usage: HashMap<(&str, &str), usize> = HashMap::new();
+    for name in index.names() {
+        for package in index.versions(name) {
+            for dep in index.dependencies(package) {
+                *usage.entry((dep.name.as_str(), dep.version.as_str())).or_default() += 1;
+            }
+        }
+    }
+    for member in members {
+        for (name, spec) in member.dependencies {
+            if let Some(dep) = index.resolve(name, spec) {
+                *usage.entry((dep.name.as_str(), dep.version.as_str())).or_default() += 1;
+            }
+        }
+    }
+
+    let mut tree: HashMap<Vec<String>, &'a ResolvedPackage> = HashMap::new();
+    let mut placements = Vec::new();
+
+    for name in index.names() {
+        let hoisted = match root_deps.get(name) {
+            Some(spec) => index.resolve(name, spec),
+            // Versions are newest first, so ties go to the newest
+            None => index
+                .versions(name)
+                .into_iter()
+                .rev()
+                .max_by_key(|p| usage.get(&(p.name.as_str(), p.version.as_str())).copied().unwrap_or(0)),
+        };
+
+        if let Some(package) = hoisted {
+            let placement = Placement { workspace: None, parents: Vec::new(), package };
+            tree.insert(placement.location(), package);
+            placements.push(placement);
+        }
+    }
+
+    // Members see the root's tree; each direct dependency it holds at the wrong
+    // version gets a copy in the member's node_modules
+    for member in members {
+        let mut deps: Vec<(&String, &String)> = member.dependencies.iter().collect();
+        deps.sort();
+        for dep in deps.into_iter().filter_map(|(name, spec)| index.resolve(name, spec)) {
+            if tree.get(std::slice::from_ref(&dep.name)).is_some_and(|p| p.version == dep.version) {
+                continue;
+            }
+            let placement = Placement { workspace: Some(member.name.to_string()), parents: Vec::new(), package: dep };
+            tree.insert(placement.location(), dep);
+            placements.push(placement);
+        }
+    }
+
+    // Nest copies wherever the visible version differs from the one required
+    let mut queue: VecDeque<usize> = (0..placements.len()).collect();
+    while let Some(i) = queue.pop_front() {
+        let location = placements[i].location();
+        if location.len() > MAX_DEPTH {
+            continue;
+        }
+
+        for dep in index.dependencies(placements[i].package) {
+            let visible = (0..=location.len()).rev().find_map(|depth| {
+                let mut candidate = location[..depth].to_vec();
+                candidate.push(dep.name.clone());
+                tree.get(&candidate)
+            });
+
+            if visible.map(|p| p.version == dep.version).unwrap_or(false) {
+                continue;
+            }
+
+            let mut parents = placements[i].parents.clone();
+            parents.push(placements[i].package.name.clone());
+            let placement = Placement { workspace: placements[i].workspace.clone(), parents, package: dep };
+            tree.insert(placement.location(), dep);
+            placements.push(placement);
+            queue.push_back(placements.len() - 1);
+        }
+    }
+
+    placements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::test_support::package;
+
+    #[test]
+    fn test_conflicting_versions_are_nested() {
+        let react17 = package("react", "17.0.2", &[]);
+        let react18 = package("react", "18.2.0", &[]);
+        let legacy = package("legacy-ui", "1.0.0", &[("react", "^17.0.0")]);
+        let modern = package("modern-ui", "1.0.0", &[("react", "^18.0.0")]);
+
+        let index = PackageIndex::new(&[&react17, &react18, &legacy, &modern]);
+        let root: HashMap<String, String> = [
+            ("react".to_string(), "^18.0.0".to_string()),
+            ("legacy-ui".to_string(), "^1.0.0".to_string()),
+            ("modern-ui".to_string(), "^1.0.0".to_string()),
+        ].into_iter().collect();
+
+        let layout = hoisted_layout(&index, &root, &[], true);
+        let node_modules = Path::new("node_modules");
+        let found: Vec<(PathBuf, &str)> = layout
+            .iter()
+            .map(|p| (p.path(node_modules), p.package.version.as_str()))
+            .collect();
+
+        assert!(found.contains(&(node_modules.join("react"), "18.2.0")));
+        assert!(found.contains(&(node_modules.join("legacy-ui/node_modules/react"), "17.0.2")));
+        assert_eq!(found.len(), 4);
+    }
+
+    #[test]
+    fn test_member_conflicts_stay_in_the_member() {
+        let react17 = package("react", "17.0.2", &[]);
+        let react18 = package("react", "18.2.0", &[("loose-envify", "^1.1.0")]);
+        let envify = package("loose-envify", "1.4.0", &[]);
+        let lodash = package("lodash", "4.17.21", &[]);
+
+        let index = PackageIndex::new(&[&react17, &react18, &envify, &lodash]);
+        let root: HashMap<String, String> = [("lodash".to_string(), "^4.17.0".to_string())].into_iter().collect();
+        let web: HashMap<String, String> = [("react".to_string(), "^18.0.0".to_string())].into_iter().collect();
+        let legacy: HashMap<String, String> = [
+            ("react".to_string(), "^17.0.0".to_string()),
+            ("lodash".to_string(), "^4.17.21".to_string()),
+        ].into_iter().collect();
+        let members = [
+            MemberDependencies { name: "web", dependencies: &web },
+            MemberDependencies { name: "legacy", dependencies: &legacy },
+        ];
+
+        let found = |hoist: bool| -> Vec<(Option<String>, PathBuf, String)> {
+            let mut found: Vec<_> = hoisted_layout(&index, &root, &members, hoist)
+                .iter()
+                .map(|p| (p.workspace.clone(), p.path(Path::new("node_modules")), p.package.version.clone()))
+                .collect();
+            found.sort();
+            found
+        };
+        let at = |member: Option<&str>, path: &str, version: &str| {
+            (member.map(str::to_string), PathBuf::from(path), version.to_string())
+        };
+
+        // Shared versions go to the root, the conflicting one to its member
+        assert_eq!(found(true), [
+            at(None, "node_modules/lodash", "4.17.21"),
+            at(None, "node_modules/loose-envify", "1.4.0"),
+            at(None, "node_modules/react", "18.2.0"),
+            at(Some("legacy"), "node_modules/react", "17.0.2"),
+        ]);
+
+        // Without hoisting, the root and each member only hold their own dependencies
+        assert_eq!(found(false), [
+            at(None, "node_modules/lodash", "4.17.21"),
+            at(Some("legacy"), "node_modules/lodash", "4.17.21"),
+            at(Some("legacy"), "node_modules/react", "17.0.2"),
+            at(Some("web"), "node_modules/loose-envify", "1.4.0"),
+            at(Some("web"), "node_modules/react", "18.2.0"),
+        ]);
+    }
+}
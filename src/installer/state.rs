@@ -0,0 +1,114 @@
+//! Install state: what node_modules was last installed from
+//!
+//! Written after every complete install to `node_modules/.velocity/install-state.json`.
+//! When the lockfile and install settings hash the same and every package
+//! directory it lists is still there, the next install has nothing to do.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::core::VelocityResult;
+use crate::installer::linker::VIRTUAL_STORE_DIR;
+
+/// File name below `node_modules/.velocity`
+const STATE_FILE: &str = "install-state.json";
+
+/// Bumped when the format changes, so older states are never trusted
+const STATE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallState {
+    version: u32,
+
+    /// sha256 of the lockfile the tree was installed from
+    pub lockfile: String,
+
+    /// sha256 of the settings that shape node_modules
+    pub settings: String,
+
+    /// Package directories relative to the project, with the `name@version` placed there
+    pub layout: BTreeMap<String, String>,
+}
+
+impl InstallState {
+    pub fn new(lockfile: &[u8], settings: &serde_json::Value, layout: BTreeMap<String, String>) -> Self {
+        Self {
+            version: STATE_VERSION,
+            lockfile: digest(lockfile),
+            settings: digest(settings.to_string().as_bytes()),
+            layout,
+        }
+    }
+
+    /// Where the state of `node_modules` is kept
+    pub fn path(node_modules: &Path) -> PathBuf {
+        node_modules.join(VIRTUAL_STORE_DIR).join(STATE_FILE)
+    }
+
+    /// The recorded state, if there is a readable one of this format
+    pub fn load(node_modules: &Path) -> Option<Self> {
+        let content = std::fs::read(Self::path(node_modules)).ok()?;
+        let state: Self = serde_json::from_slice(&content).ok()?;
+        (state.version == STATE_VERSION).then_some(state)
+    }
+
+    pub fn save(&self, node_modules: &Path) -> VelocityResult<()> {
+        let path = Self::path(node_modules);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Forget the state before node_modules changes, so an interrupted install is redone
+    pub fn clear(node_modules: &Path) {
+        let _ = std::fs::remove_file(Self::path(node_modules));
+    }
+
+    /// Whether this state was recorded for the same lockfile and settings, with
+    /// every package directory still in place under `project_dir`
+    pub fn is_current(&self, lockfile: &[u8], settings: &serde_json::Value, project_dir: &Path) -> bool {
+        self.lockfile == digest(lockfile)
+            && self.settings == digest(settings.to_string().as_bytes())
+            && self.layout.keys().all(|dir| project_dir.join(dir).join("package.json").exists())
+    }
+}
+
+fn digest(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_state_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_modules = dir.path().join("node_modules");
+        std::fs::create_dir_all(node_modules.join("a")).unwrap();
+        std::fs::write(node_modules.join("a/package.json"), "{}").unwrap();
+
+        let settings = serde_json::json!({ "omit_dev": false });
+        let layout = BTreeMap::from([("node_modules/a".to_string(), "a@1.0.0".to_string())]);
+        let state = InstallState::new(b"lockfile", &settings, layout);
+        state.save(&node_modules).unwrap();
+
+        let recorded = InstallState::load(&node_modules).unwrap();
+        assert_eq!(recorded, state);
+        assert!(recorded.is_current(b"lockfile", &settings, dir.path()));
+        assert!(!recorded.is_current(b"changed", &settings, dir.path()));
+        assert!(!recorded.is_current(b"lockfile", &serde_json::json!({ "omit_dev": true }), dir.path()));
+
+        // A package deleted by hand makes the tree stale
+        std::fs::remove_dir_all(node_modules.join("a")).unwrap();
+        assert!(!recorded.is_current(b"lockfile", &settings, dir.path()));
+
+        InstallState::clear(&node_modules);
+        assert!(InstallState::load(&node_modules).is_none());
+    }
+}
@@ -0,0 +1,287 @@
+//! Lockfile-driven vendoring of package tarballs
+//!
+//! `velocity vendor` copies every tarball referenced by the lockfile into a
+//! directory inside the repository, alongside a manifest. Installs with
+//! `--vendored` then read tarballs from there instead of the registry.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheManager;
+use crate::core::{Lockfile, VelocityError, VelocityResult};
+use crate::resolver::ResolvedPackage;
+
+use super::downloader::{verify_integrity_static, Downloader};
+
+/// Default vendor directory name
+pub const VENDOR_DIR: &str = "vendor";
+
+/// Vendor manifest file name
+pub const VENDOR_MANIFEST: &str = "velocity-vendor.json";
+
+/// Current vendor manifest format version
+pub const VENDOR_MANIFEST_VERSION: u32 = 1;
+
+/// Manifest describing the contents of a vendor directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VendorManifest {
+    /// Manifest format version
+    pub version: u32,
+
+    /// Integrity of the lockfile the vendor directory was built from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lockfile_integrity: Option<String>,
+
+    /// Vendored packages
+    pub packages: Vec<VendoredPackage>,
+}
+
+/// A single vendored tarball
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VendoredPackage {
+    /// Package name
+    pub name: String,
+
+    /// Package version
+    pub version: String,
+
+    /// Tarball file name, relative to the vendor directory
+    pub file: String,
+
+    /// Integrity hash from the lockfile
+    pub integrity: String,
+}
+
+/// Result of populating a vendor directory
+pub struct VendorResult {
+    /// Number of tarballs written
+    pub vendored: usize,
+
+    /// Number of tarballs that had to be downloaded first
+    pub downloaded: usize,
+
+    /// Number of stale tarballs removed
+    pub removed: usize,
+}
+
+impl VendorManifest {
+    /// Load the manifest from a vendor directory
+    pub fn load(vendor_dir: &Path) -> VelocityResult<Option<Self>> {
+        let path = vendor_dir.join(VENDOR_MANIFEST);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    /// Save the manifest to a vendor directory
+    pub fn save(&self, vendor_dir: &Path) -> VelocityResult<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(vendor_dir.join(VENDOR_MANIFEST), content)?;
+        Ok(())
+    }
+
+    /// Find a vendored package
+    pub fn find(&self, name: &str, version: &str) -> Option<&VendoredPackage> {
+        self.packages.iter().find(|p| p.name == name && p.version == version)
+    }
+}
+
+/// Tarball file name for a package inside the vendor directory
+pub fn vendor_file_name(name: &str, version: &str) -> String {
+    let safe_name = name.replace('/', "+").replace('@', "");
+    format!("{}-{}.tgz", safe_name, version)
+}
+
+/// Vendor directory manager
+pub struct Vendor {
+    /// Vendor directory
+    dir: PathBuf,
+
+    /// Cache manager
+    cache: Arc<CacheManager>,
+}
+
+impl Vendor {
+    /// Create a vendor manager for a directory
+    pub fn new(dir: PathBuf, cache: Arc<CacheManager>) -> Self {
+        Self { dir, cache }
+    }
+
+    /// Vendor directory path
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Copy every tarball referenced by the lockfile into the vendor directory
+    pub async fn populate(&self, lockfile: &Lockfile, downloader: &Downloader) -> VelocityResult<VendorResult> {
+        std::fs::create_dir_all(&self.dir)?;
+
+        let mut packages = Vec::new();
+        let mut downloaded = 0;
+
+        for locked in &lockfile.packages {
+            let tarball_path = self.cache.get_tarball_path(&locked.name, &locked.version);
+
+            if !tarball_path.exists() {
+                if locked.resolved.is_empty() {
+                    return Err(VelocityError::other(format!(
+                        "{}@{} has no resolved tarball URL in the lockfile",
+                        locked.name, locked.version
+                    )));
+                }
+
+                let pkg = ResolvedPackage {
+                    name: locked.name.clone(),
+                    alias_of: locked.alias_of.clone(),
+                    version: locked.version.clone(),
+                    tarball_url: locked.resolved.clone(),
+                    integrity: locked.integrity.clone(),
+                    dependencies: Default::default(),
+                    peer_dependencies: Default::default(),
+                    optional_dependencies: Default::default(),
+                    locked_dependencies: Default::default(),
+                    has_scripts: locked.has_scripts,
+                };
+                downloader.download(&pkg, false).await?;
+                downloaded += 1;
+            }
+
+            let data = std::fs::read(&tarball_path)?;
+            if !locked.integrity.is_empty() {
+                verify_integrity_static(&data, &locked.integrity, &locked.name)?;
+            }
+
+            let file = vendor_file_name(&locked.name, &locked.version);
+            std::fs::write(self.dir.join(&file), &data)?;
+
+            packages.push(VendoredPackage {
+                name: locked.name.clone(),
+                version: locked.version.clone(),
+                file,
+                integrity: locked.integrity.clone(),
+            });
+        }
+
+        packages.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+
+        // Drop tarballs the last run vendored that the lockfile no longer needs;
+        // anything else in the directory is not ours to delete
+        let keep: HashSet<&str> = packages.iter().map(|p| p.file.as_str()).collect();
+        let previous = VendorManifest::load(&self.dir).ok().flatten();
+        let removed = remove_stale(&self.dir, previous.as_ref(), &keep)?;
+
+        let manifest = VendorManifest {
+            version: VENDOR_MANIFEST_VERSION,
+            lockfile_integrity: lockfile.integrity.clone(),
+            packages,
+        };
+        manifest.save(&self.dir)?;
+
+        Ok(VendorResult {
+            vendored: manifest.packages.len(),
+            downloaded,
+            removed,
+        })
+    }
+
+    /// Load the manifest and make sure it matches the lockfile
+    pub fn manifest_for(&self, lockfile: &Lockfile) -> VelocityResult<VendorManifest> {
+        let manifest = VendorManifest::load(&self.dir)?.ok_or_else(|| {
+            VelocityError::other(format!(
+                "No vendor manifest found in {}. Run 'velocity vendor' first.",
+                self.dir.display()
+            ))
+        })?;
+
+        if manifest.lockfile_integrity.is_some() && manifest.lockfile_integrity != lockfile.integrity {
+            return Err(VelocityError::other(
+                "Vendor directory is out of date with velocity.lock. Run 'velocity vendor' to refresh it.",
+            ));
+        }
+
+        Ok(manifest)
+    }
+
+    /// Place a vendored tarball into the cache so it can be extracted
+    pub fn import(&self, manifest: &VendorManifest, package: &ResolvedPackage) -> VelocityResult<u64> {
+        let vendored = manifest.find(&package.name, &package.version).ok_or_else(|| {
+            VelocityError::other(format!(
+                "{}@{} is not vendored. Run 'velocity vendor' to refresh the vendor directory.",
+                package.name, package.version
+            ))
+        })?;
+
+        let data = std::fs::read(self.dir.join(&vendored.file))?;
+        if !package.integrity.is_empty() {
+            verify_integrity_static(&data, &package.integrity, &package.name)?;
+        }
+
+        self.cache.store_tarball(&package.name, &package.version, &data)?;
+        Ok(data.len() as u64)
+    }
+}
+
+/// Delete the tarballs `previous` lists that are not in `keep`, returning how many
+fn remove_stale(dir: &Path, previous: Option<&VendorManifest>, keep: &HashSet<&str>) -> VelocityResult<usize> {
+    let mut removed = 0;
+    for package in previous.map(|m| m.packages.as_slice()).unwrap_or_default() {
+        // A hand-edited manifest must not reach outside the directory
+        let plain_name = Path::new(&package.file).file_name().is_some_and(|name| name == package.file.as_str());
+        let path = dir.join(&package.file);
+        if plain_name && !keep.contains(package.file.as_str()) && path.is_file() {
+            std::fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vendor_file_name_flattens_scopes() {
+        assert_eq!(vendor_file_name("lodash", "4.17.21"), "lodash-4.17.21.tgz");
+        assert_eq!(vendor_file_name("@types/node", "20.1.0"), "types+node-20.1.0.tgz");
+    }
+
+    #[test]
+    fn test_remove_stale_keeps_unlisted_files() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("vendor");
+        std::fs::create_dir(&dir).unwrap();
+        for file in ["lodash-4.17.20.tgz", "lodash-4.17.21.tgz", "my-own-build.tgz"] {
+            std::fs::write(dir.join(file), b"data").unwrap();
+        }
+        std::fs::write(root.path().join("outside.tgz"), b"data").unwrap();
+
+        let vendored = |file: &str| VendoredPackage {
+            name: "lodash".to_string(),
+            version: "0".to_string(),
+            file: file.to_string(),
+            integrity: String::new(),
+        };
+        let previous = VendorManifest {
+            version: VENDOR_MANIFEST_VERSION,
+            lockfile_integrity: None,
+            packages: vec![vendored("lodash-4.17.20.tgz"), vendored("lodash-4.17.21.tgz"), vendored("../outside.tgz")],
+        };
+
+        let keep: HashSet<&str> = ["lodash-4.17.21.tgz"].into_iter().collect();
+        assert_eq!(remove_stale(&dir, Some(&previous), &keep).unwrap(), 1);
+        assert!(!dir.join("lodash-4.17.20.tgz").exists());
+        assert!(dir.join("lodash-4.17.21.tgz").exists());
+        assert!(dir.join("my-own-build.tgz").exists());
+        assert!(root.path().join("outside.tgz").exists());
+
+        // Without a previous manifest nothing is removed
+        assert_eq!(remove_stale(&dir, None, &HashSet::new()).unwrap(), 0);
+    }
+}
@@ -0,0 +1,106 @@
+//! Checks that node_modules holds what the lockfile describes
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A difference between node_modules and the lockfile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutIssue {
+    /// A locked package has no directory (or no package.json) where it belongs
+    Missing { package: String, version: String, path: PathBuf },
+
+    /// The directory holds another version than the lockfile pins
+    WrongVersion { package: String, version: String, found: String, path: PathBuf },
+
+    /// A package directory the lockfile does not account for
+    Unexpected { path: PathBuf },
+}
+
+impl LayoutIssue {
+    pub fn path(&self) -> &Path {
+        match self {
+            LayoutIssue::Missing { path, .. } | LayoutIssue::WrongVersion { path, .. } | LayoutIssue::Unexpected { path } => path,
+        }
+    }
+
+    /// JSON representation used by `--json` output, with `path` relative to `project_dir`
+    pub fn to_json(&self, project_dir: &Path) -> serde_json::Value {
+        let path = self.path().strip_prefix(project_dir).unwrap_or(self.path());
+        match self {
+            LayoutIssue::Missing { package, version, .. } => serde_json::json!({
+                "kind": "missing",
+                "package": package,
+                "version": version,
+                "path": path,
+            }),
+            LayoutIssue::WrongVersion { package, version, found, .. } => serde_json::json!({
+                "kind": "wrong_version",
+                "package": package,
+                "version": version,
+                "found": found,
+                "path": path,
+            }),
+            LayoutIssue::Unexpected { .. } => serde_json::json!({
+                "kind": "unexpected",
+                "path": path,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for LayoutIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutIssue::Missing { package, version, path } => {
+                write!(f, "{}@{} is missing from {}", package, version, path.display())
+            }
+            LayoutIssue::WrongVersion { package, version, found, path } => {
+                write!(f, "{} at {} is {}, but the lockfile has {}", package, path.display(), found, version)
+            }
+            LayoutIssue::Unexpected { path } => write!(f, "{} is not in the lockfile", path.display()),
+        }
+    }
+}
+
+/// Check the package directory `dir` against the locked `version` of `package`
+pub fn check_package_dir(package: &str, version: &str, dir: &Path) -> Option<LayoutIssue> {
+    let missing = || LayoutIssue::Missing {
+        package: package.to_string(),
+        version: version.to_string(),
+        path: dir.to_path_buf(),
+    };
+    let Ok(content) = std::fs::read_to_string(dir.join("package.json")) else {
+        return Some(missing());
+    };
+
+    let manifest: serde_json::Value = serde_json::from_str(&content).unwrap_or_default();
+    let found = manifest.get("version").and_then(|v| v.as_str()).unwrap_or("no version");
+    (found != version).then(|| LayoutIssue::WrongVersion {
+        package: package.to_string(),
+        version: version.to_string(),
+        found: found.to_string(),
+        path: dir.to_path_buf(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_check_package_dir() {
+        let dir = tempdir().unwrap();
+        let lodash = dir.path().join("node_modules/lodash");
+        assert!(matches!(check_package_dir("lodash", "4.17.21", &lodash), Some(LayoutIssue::Missing { .. })));
+
+        std::fs::create_dir_all(&lodash).unwrap();
+        std::fs::write(lodash.join("package.json"), r#"{"name":"lodash","version":"4.17.20"}"#).unwrap();
+        let issue = check_package_dir("lodash", "4.17.21", &lodash).unwrap();
+        assert!(issue.to_string().contains("is 4.17.20, but the lockfile has 4.17.21"));
+        assert_eq!(issue.to_json(dir.path())["path"], "node_modules/lodash");
+
+        std::fs::write(lodash.join("package.json"), r#"{"name":"lodash","version":"4.17.21"}"#).unwrap();
+        assert_eq!(check_package_dir("lodash", "4.17.21", &lodash), None);
+    }
+}
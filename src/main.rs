@@ -17,23 +17,62 @@ mod permissions;
 mod utils;
 
 use clap::Parser;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
+use cli::output::{OutputContext, Verbosity};
 use cli::{Cli, Commands};
 use core::VelocityResult;
 
 #[tokio::main]
 async fn main() -> VelocityResult<()> {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")))
-        .with(tracing_subscriber::fmt::layer().with_target(false))
-        .init();
+    // Isolated install scripts are started through velocity itself
+    #[cfg(windows)]
+    if let Some(code) = security::isolation::launch_from_args() {
+        std::process::exit(code);
+    }
 
     let cli = Cli::parse();
 
     // Set up output mode
-    let json_output = cli.json;
+    let verbosity = Verbosity::from_flags(cli.quiet, cli.verbose);
+    cli::output::init(OutputContext {
+        verbosity,
+        json: cli.json || cli.json_stream,
+        json_stream: cli.json_stream,
+        color: cli.color,
+    });
+    let json_output = cli::output::context().json;
+    let start_time = std::time::Instant::now();
+
+    // Initialize tracing; a trace file records velocity's own spans regardless of RUST_LOG,
+    // and RUST_LOG overrides the level --verbose and --quiet pick
+    let (trace, trace_layer) = match cli.trace_output {
+        Some(ref path) => {
+            let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+            let (trace, layer) = utils::ChromeTrace::new(path);
+            let filter = Targets::new().with_target("velocity", LevelFilter::DEBUG);
+            (Some(trace), Some(layer.with_filter(filter)))
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_writer(std::io::stderr)
+                .with_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(verbosity.log_filter()))),
+        )
+        .with(trace_layer)
+        .init();
+
+    // Every command resolves its project relative to the working directory
+    if let Some(ref dir) = cli.cwd {
+        if let Err(e) = std::env::set_current_dir(dir) {
+            let e = core::VelocityError::other(format!("Cannot change to directory {}: {}", dir.display(), e));
+            exit_with_error(&e);
+        }
+    }
 
     // Execute command
     let result = match cli.command {
@@ -41,29 +80,70 @@ async fn main() -> VelocityResult<()> {
         Commands::Install(args) => cli::commands::install::execute(args, json_output).await,
         Commands::Add(args) => cli::commands::add::execute(args, json_output).await,
         Commands::Remove(args) => cli::commands::remove::execute(args, json_output).await,
+        Commands::Prune(args) => cli::commands::prune::execute(args, json_output).await,
+        Commands::Check(args) => cli::commands::check::execute(args, json_output).await,
         Commands::Update(args) => cli::commands::update::execute(args, json_output).await,
+        Commands::List(args) => cli::commands::list::execute(args, json_output).await,
+        Commands::Why(args) => cli::commands::why::execute(args, json_output).await,
         Commands::Run(args) => cli::commands::run::execute(args, json_output).await,
+        Commands::Exec(args) => cli::commands::exec::execute(args, json_output).await,
+        Commands::Dlx(args) => cli::commands::dlx::execute(args, json_output).await,
+        Commands::Info(args) => cli::commands::info::execute(args, json_output).await,
+        Commands::Search(args) => cli::commands::search::execute(args, json_output).await,
         Commands::Doctor(args) => cli::commands::doctor::execute(args, json_output).await,
         Commands::Audit(args) => cli::commands::audit::execute(args, json_output).await,
+        Commands::Licenses(args) => cli::commands::licenses::execute(args, json_output).await,
+        Commands::Outdated(args) => cli::commands::outdated::execute(args, json_output).await,
+        Commands::Health(args) => cli::commands::health::execute(args, json_output).await,
         Commands::Cache(args) => cli::commands::cache::execute(args, json_output).await,
+        Commands::Config(args) => cli::commands::config::execute(args, json_output).await,
+        Commands::Completions(args) => cli::commands::completions::execute(args, json_output).await,
         Commands::Migrate(args) => cli::commands::migrate::execute(args, json_output).await,
+        Commands::Export(args) => cli::commands::export::execute(args, json_output).await,
         Commands::Upgrade(args) => cli::commands::upgrade::execute(args, json_output).await,
         Commands::Create(args) => cli::commands::create::execute(args, json_output).await,
         Commands::Workspace(args) => cli::commands::workspace::execute(args, json_output).await,
+        Commands::Lockfile(args) => cli::commands::lockfile::execute(args, json_output).await,
+        Commands::Hash(args) => cli::commands::hash::execute(args, json_output).await,
+        Commands::Vendor(args) => cli::commands::vendor::execute(args, json_output).await,
+        Commands::LspLite(args) => cli::commands::lsp_lite::execute(args, json_output).await,
+        Commands::Login(args) => cli::commands::login::execute(args, json_output).await,
+        Commands::Logout(args) => cli::commands::logout::execute(args, json_output).await,
+        Commands::Pack(args) => cli::commands::pack::execute(args, json_output).await,
+        Commands::Publish(args) => cli::commands::publish::execute(args, json_output).await,
     };
 
-    if let Err(ref e) = result {
-        if json_output {
-            let error_json = serde_json::json!({
-                "error": true,
-                "message": e.to_string()
-            });
-            eprintln!("{}", serde_json::to_string_pretty(&error_json).unwrap());
-        } else {
-            eprintln!("{} {}", console::style("error:").red().bold(), e);
+    if let Some(ref trace) = trace {
+        if let Err(e) = trace.write() {
+            tracing::warn!("Could not write trace: {}", e);
         }
-        std::process::exit(1);
+    }
+
+    cli::output::debug(&format!(
+        "Finished in {}",
+        cli::output::format_duration(start_time.elapsed().as_millis())
+    ));
+
+    if let Err(ref e) = result {
+        exit_with_error(e);
     }
 
     Ok(())
 }
+
+/// Print an error in the requested output mode and exit
+fn exit_with_error(e: &core::VelocityError) -> ! {
+    let context = cli::output::context();
+    if context.json_stream {
+        cli::output::event("error", serde_json::json!({ "message": e.to_string() }));
+    } else if context.json {
+        let error_json = serde_json::json!({
+            "error": true,
+            "message": e.to_string()
+        });
+        eprintln!("{}", serde_json::to_string_pretty(&error_json).unwrap());
+    } else {
+        eprintln!("{} {}", cli::output::bad("error:").for_stderr().bold(), e);
+    }
+    std::process::exit(1);
+}
@@ -1,70 +1,146 @@
 //! Registry HTTP client
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::cache::CacheManager;
 use crate::core::{VelocityResult, VelocityError};
-use crate::core::config::RegistryConfig;
+use crate::core::config::{NetworkConfig, RegistryConfig};
+use crate::registry::http::{self, InsecureOrigins, OptimizedHttpClient, RetryPolicy};
+use crate::registry::search::{ObjectSplitter, SearchObject};
 use crate::registry::types::PackageMetadata;
 
+/// Registry whose packages have download counts, and the API that serves them
+const NPM_REGISTRY: &str = "https://registry.npmjs.org";
+const NPM_DOWNLOADS_API: &str = "https://api.npmjs.org/downloads/point/last-week";
+
+/// Limit for one registry request; tarball downloads get longer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What registry requests accept unless they ask for something else
+const DEFAULT_ACCEPT: &str = "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8";
+
 /// npm registry client
 pub struct RegistryClient {
-    /// HTTP client
+    /// Shared HTTP client, which sends every request
+    http: OptimizedHttpClient,
+    /// The shared client's request builder
     client: reqwest::Client,
     /// Registry configuration
     config: RegistryConfig,
+    /// Plain-HTTP origins allowed for registries
+    insecure: InsecureOrigins,
+    /// Backoff for transient failures
+    retry: RetryPolicy,
     /// Cache manager
     cache: Arc<CacheManager>,
+    /// Request full packuments, which carry publish times
+    full_metadata: bool,
 }
 
 impl RegistryClient {
-    /// Create a new registry client
-    pub fn new(config: &RegistryConfig, cache: Arc<CacheManager>) -> VelocityResult<Self> {
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            reqwest::header::ACCEPT,
-            "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8"
-                .parse()
-                .unwrap(),
-        );
-        headers.insert(
-            reqwest::header::USER_AGENT,
-            format!("velocity/{}", env!("CARGO_PKG_VERSION"))
-                .parse()
-                .unwrap(),
-        );
-
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .timeout(std::time::Duration::from_secs(30))
-            .gzip(true)
-            .brotli(true)
-            .build()
-            .map_err(|e| VelocityError::Network(e.to_string()))?;
-
+    /// Create a new registry client sending its requests through `http`
+    pub fn new(
+        config: &RegistryConfig,
+        network: &NetworkConfig,
+        http: OptimizedHttpClient,
+        cache: Arc<CacheManager>,
+    ) -> VelocityResult<Self> {
+        let client = http.client().clone();
         Ok(Self {
+            http,
             client,
             config: config.clone(),
+            insecure: InsecureOrigins::from_config(&network.allow_insecure_registries),
+            retry: RetryPolicy::from_config(network),
             cache,
+            full_metadata: false,
         })
     }
 
+    /// Fetch full packuments instead of the abbreviated install format
+    ///
+    /// Only full packuments have the `time` field with each version's publish date.
+    pub fn with_full_metadata(mut self, full: bool) -> Self {
+        self.full_metadata = full;
+        self
+    }
+
     /// Get package metadata from the registry
     pub async fn get_package_metadata(&self, name: &str) -> VelocityResult<PackageMetadata> {
+        if self.cache.is_offline() {
+            return self.get_offline_metadata(name);
+        }
+
         // Check cache first
         if let Some(cached) = self.cache.get_metadata(name)? {
-            let metadata: PackageMetadata = serde_json::from_str(&cached.data)?;
-            return Ok(metadata);
+            let metadata = decode_metadata(&cached.data)?;
+            if self.is_complete(&metadata) {
+                self.http.metrics().cache_hit();
+                return Ok(metadata);
+            }
+        }
+
+        self.fetch_package_metadata(name).await
+    }
+
+    /// Get package metadata, accepting cached copies no older than `ttl` seconds
+    ///
+    /// A TTL of 0 always goes to the registry.
+    pub async fn get_package_metadata_with_ttl(&self, name: &str, ttl: u64) -> VelocityResult<PackageMetadata> {
+        if self.cache.is_offline() {
+            return self.get_offline_metadata(name);
+        }
+
+        if let Some(cached) = self.cache.get_metadata_with_ttl(name, ttl)? {
+            let metadata = decode_metadata(&cached.data)?;
+            if self.is_complete(&metadata) {
+                self.http.metrics().cache_hit();
+                return Ok(metadata);
+            }
         }
 
+        self.fetch_package_metadata(name).await
+    }
+
+    /// Fetch package metadata from the registry and refresh the cache
+    ///
+    /// An expired cache entry is revalidated with `If-None-Match` /
+    /// `If-Modified-Since`, so an unchanged packument costs a 304 instead of
+    /// a full download.
+    async fn fetch_package_metadata(&self, name: &str) -> VelocityResult<PackageMetadata> {
+        self.http.metrics().cache_miss();
+
+        // An abbreviated copy cannot be revalidated into a full one
+        let stale = self.cache.get_stale_metadata(name)?.filter(|cached| {
+            !self.full_metadata || decode_metadata(&cached.data).is_ok_and(|metadata| self.is_complete(&metadata))
+        });
+
         // Fetch from registry
-        let url = self.get_package_url(name);
+        let primary = self.get_registry_for_package(name);
+        let response = self.send(primary, |registry| {
+            let mut request = self.client.get(package_url(registry, name));
+            if self.full_metadata {
+                request = request.header(reqwest::header::ACCEPT, "application/json");
+            }
+            if let Some(ref cached) = stale {
+                if let Some(ref etag) = cached.etag {
+                    request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+                }
+                if let Some(ref last_modified) = cached.last_modified {
+                    request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            self.authorize(request, registry, Some(name))
+        }).await?;
 
-        let response = self.client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| VelocityError::Network(e.to_string()))?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            if let Some(cached) = stale {
+                tracing::debug!("Metadata for {} not modified", name);
+                self.cache.touch_metadata(name)?;
+                return decode_metadata(&cached.data);
+            }
+        }
 
         if !response.status().is_success() {
             if response.status() == reqwest::StatusCode::NOT_FOUND {
@@ -77,72 +153,262 @@ impl RegistryClient {
             )));
         }
 
+        let header = |name: reqwest::header::HeaderName| {
+            response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+        };
+        let etag = header(reqwest::header::ETAG);
+        let last_modified = header(reqwest::header::LAST_MODIFIED);
+
         let text = response.text().await
             .map_err(|e| VelocityError::Network(e.to_string()))?;
 
         // Parse and validate
         let metadata: PackageMetadata = serde_json::from_str(&text)?;
 
-        // Cache the response
-        self.cache.store_metadata(name, &text)?;
+        // Cache only the fields velocity reads, in a compact binary encoding
+        self.cache.store_metadata(name, &encode_metadata(&metadata)?, etag.as_deref(), last_modified.as_deref())?;
 
         Ok(metadata)
     }
 
-    /// Get the URL for a package
-    fn get_package_url(&self, name: &str) -> String {
-        let registry = self.get_registry_for_package(name);
-        
-        // Handle scoped packages
-        let encoded_name = if name.starts_with('@') {
-            name.replace('/', "%2f")
-        } else {
-            name.to_string()
-        };
+    /// Send a request to a registry, retrying transient failures
+    ///
+    /// When the default registry stays unavailable, each configured mirror is
+    /// tried in turn. Registries configured for a scope have no fallback, so
+    /// private package names never reach a public mirror.
+    async fn send<F>(&self, primary: &str, build: F) -> VelocityResult<reqwest::Response>
+    where
+        F: Fn(&str) -> reqwest::RequestBuilder,
+    {
+        let mut registries = vec![primary];
+        if primary.trim_end_matches('/') == self.config.url.trim_end_matches('/') {
+            registries.extend(self.config.mirrors.iter().map(String::as_str));
+        }
 
-        format!("{}/{}", registry, encoded_name)
-    }
+        let mut last_error = None;
+        for registry in registries {
+            let result = self.retry.run(|| async {
+                let mut request = build(registry).build().map_err(http::send_error)?;
+                self.insecure.check(request.url().as_str())?;
+                request.timeout_mut().get_or_insert(REQUEST_TIMEOUT);
+                if !request.headers().contains_key(reqwest::header::ACCEPT) {
+                    request.headers_mut().insert(reqwest::header::ACCEPT, reqwest::header::HeaderValue::from_static(DEFAULT_ACCEPT));
+                }
 
-    /// Get the registry URL for a package (handles scoped overrides)
-    fn get_registry_for_package(&self, name: &str) -> &str {
-        if name.starts_with('@') {
-            if let Some(scope) = name.split('/').next() {
-                if let Some(registry) = self.config.scopes.get(scope) {
-                    return registry;
+                let response = self.http.execute(request).await.map_err(http::send_error)?;
+                if http::is_transient_status(response.status()) {
+                    return Err(VelocityError::Network(format!(
+                        "{} returned HTTP {}",
+                        response.url(),
+                        response.status()
+                    )));
                 }
+                Ok(response)
+            }).await;
+
+            match result {
+                Err(e) if http::is_transient(&e) => {
+                    tracing::warn!("Registry {} unavailable: {}", registry, e);
+                    last_error = Some(e);
+                }
+                result => return result,
             }
         }
 
-        &self.config.url
+        Err(last_error.unwrap_or_else(|| VelocityError::Network("No registry configured".to_string())))
+    }
+
+    /// Whether cached metadata has every field this client was asked for
+    fn is_complete(&self, metadata: &PackageMetadata) -> bool {
+        !self.full_metadata || !metadata.time.is_empty()
+    }
+
+    /// Check that a package associated with a registry resolved to a tarball on it
+    pub fn check_association(&self, name: &str, tarball_url: &str) -> VelocityResult<()> {
+        self.config.check_association(name, tarball_url)
+    }
+
+    /// Get the registry URL for a package (handles scoped overrides)
+    pub fn get_registry_for_package(&self, name: &str) -> &str {
+        self.config.registry_for(name)
+    }
+
+    /// Cached metadata of any age; offline mode never reaches the registry
+    fn get_offline_metadata(&self, name: &str) -> VelocityResult<PackageMetadata> {
+        let cached = self.cache.get_metadata_with_ttl(name, u64::MAX)?.ok_or_else(|| VelocityError::Offline {
+            package: name.to_string(),
+            resource: "registry metadata".to_string(),
+        })?;
+        decode_metadata(&cached.data)
+    }
+
+    /// Fail with an offline error naming `package` when offline mode is on
+    fn ensure_online(&self, package: &str, resource: &str) -> VelocityResult<()> {
+        if self.cache.is_offline() {
+            return Err(VelocityError::Offline {
+                package: package.to_string(),
+                resource: resource.to_string(),
+            });
+        }
+        Ok(())
     }
 
     /// Check if a package exists
     pub async fn package_exists(&self, name: &str) -> VelocityResult<bool> {
-        let url = self.get_package_url(name);
-
-        let response = self.client
-            .head(&url)
-            .send()
-            .await
-            .map_err(|e| VelocityError::Network(e.to_string()))?;
+        self.ensure_online(name, "registry metadata")?;
+        let primary = self.get_registry_for_package(name);
+        let response = self.send(primary, |registry| {
+            self.authorize(self.client.head(package_url(registry, name)), registry, Some(name))
+        }).await?;
 
         Ok(response.status().is_success())
     }
 
-    /// Get authentication token for a registry
-    pub fn get_auth_token(&self, registry: &str) -> Option<&String> {
-        self.config.auth_tokens.get(registry)
+    /// Fetch a package's full packument as the registry sends it
+    ///
+    /// Unlike [`get_package_metadata`](Self::get_package_metadata), which keeps
+    /// only what resolution needs, this has every field (maintainers,
+    /// repository, readme, ...). It is not cached.
+    pub async fn get_packument(&self, name: &str) -> VelocityResult<serde_json::Value> {
+        self.ensure_online(name, "registry metadata")?;
+        let primary = self.get_registry_for_package(name);
+        let response = self.send(primary, |registry| {
+            let request = self.client.get(package_url(registry, name)).header(reqwest::header::ACCEPT, "application/json");
+            self.authorize(request, registry, Some(name))
+        }).await?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Err(VelocityError::PackageNotFound(name.to_string())),
+            status if status.is_success() => {
+                let text = response.text().await.map_err(|e| VelocityError::Network(e.to_string()))?;
+                Ok(serde_json::from_str(&text)?)
+            }
+            status => Err(VelocityError::Registry(format!("Failed to fetch {}: HTTP {}", name, status))),
+        }
     }
 
-    /// Search packages
-    pub async fn search(&self, query: &str, limit: usize) -> VelocityResult<Vec<SearchResult>> {
-        let url = format!("{}/-/v1/search?text={}&size={}", self.config.url, query, limit);
+    /// Downloads of a package in the last week
+    ///
+    /// Only packages from the public npm registry have counts; others, and
+    /// failed lookups, give `None`.
+    pub async fn weekly_downloads(&self, name: &str) -> Option<u64> {
+        if self.get_registry_for_package(name).trim_end_matches('/') != NPM_REGISTRY || self.cache.is_offline() {
+            return None;
+        }
+        let request = self.client.get(format!("{}/{}", NPM_DOWNLOADS_API, name)).timeout(REQUEST_TIMEOUT);
+        let response = self.http.send(request).await.ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+        let body: serde_json::Value = response.json().await.ok()?;
+        body.get("downloads")?.as_u64()
+    }
 
-        let response = self.client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| VelocityError::Network(e.to_string()))?;
+    /// Fetch the attestations of a version of `name` from `url` (its `dist.attestations.url`)
+    ///
+    /// Returns `None` when the registry has none for it.
+    pub async fn get_attestations(&self, name: &str, url: &str) -> VelocityResult<Option<serde_json::Value>> {
+        self.ensure_online(name, "attestations")?;
+        let primary = self.get_registry_for_package(name);
+        let response = self.send(primary, |registry| {
+            self.authorize(self.client.get(url), registry, Some(name))
+        }).await?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let text = response.text().await.map_err(|e| VelocityError::Network(e.to_string()))?;
+                Ok(Some(serde_json::from_str(&text)?))
+            }
+            status => Err(VelocityError::Registry(format!("Failed to fetch attestations for {}: HTTP {}", name, status))),
+        }
+    }
+
+    /// Download a tarball a dependency names by URL instead of through the registry
+    pub async fn get_tarball(&self, url: &str) -> VelocityResult<Vec<u8>> {
+        self.ensure_online(url, "the package tarball")?;
+        self.insecure.check(url)?;
+        let response = self.http.send(self.client.get(url)).await.map_err(http::send_error)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(VelocityError::Registry(format!("Failed to download {}: HTTP {}", url, status)));
+        }
+        let bytes = response.bytes().await.map_err(http::send_error)?;
+        Ok(bytes.to_vec())
+    }
+
+    /// Upload a publish document (manifest plus base64 tarball attachments)
+    ///
+    /// `otp` is the one-time password for accounts with two-factor auth.
+    pub async fn publish(&self, name: &str, document: &serde_json::Value, otp: Option<&str>) -> VelocityResult<()> {
+        self.ensure_online(name, "publish")?;
+        let primary = self.get_registry_for_package(name);
+        let response = self.send(primary, |registry| {
+            let mut request = self.client.put(package_url(registry, name)).json(document);
+            if let Some(otp) = otp {
+                request = request.header("npm-otp", otp);
+            }
+            self.authorize(request, registry, Some(name))
+        }).await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = response.text().await.unwrap_or_default();
+        let reason = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v.get("error").or_else(|| v.get("reason")).and_then(|e| e.as_str()).map(str::to_string))
+            .unwrap_or(body);
+        let message = match status.as_u16() {
+            401 if reason.to_lowercase().contains("otp") => {
+                format!("Publishing {} needs a one-time password; pass it with --otp", name)
+            }
+            401 | 403 => format!("Not allowed to publish {} to {}: {}", name, primary, reason),
+            409 => format!("{}: {}", name, if reason.is_empty() { "this version is already published".to_string() } else { reason }),
+            _ => format!("Publishing {} failed: HTTP {} {}", name, status, reason),
+        };
+        Err(VelocityError::registry(message.trim_end().to_string()))
+    }
+
+    /// Attach the bearer token for a request to `registry`. The package's
+    /// scope token only applies on the registry that serves the package;
+    /// mirrors and other registries use their own tokens.
+    fn authorize(&self, request: reqwest::RequestBuilder, registry: &str, package: Option<&str>) -> reqwest::RequestBuilder {
+        let token = package
+            .filter(|name| self.get_registry_for_package(name) == registry)
+            .and_then(|name| self.get_auth_token(name))
+            .or_else(|| self.config.auth_token_for_registry(registry));
+        match token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Get the authentication token for a package (scope token, then registry token)
+    pub fn get_auth_token(&self, name: &str) -> Option<&String> {
+        self.config.auth_token_for(name)
+    }
+
+    /// Fetch one page of search results from `registry`, handing each hit to `on_hit` as it is parsed
+    ///
+    /// Returns the number of hits on the page; fewer than `size` means it was the last.
+    pub async fn search_page<F>(&self, registry: &str, query: &str, from: usize, size: usize, mut on_hit: F) -> VelocityResult<usize>
+    where
+        F: FnMut(SearchObject),
+    {
+        use futures::StreamExt;
+
+        self.ensure_online(query, "search")?;
+        let response = self.send(registry, |registry| {
+            let request = self.client.get(format!("{}/-/v1/search", registry.trim_end_matches('/'))).query(&[
+                ("text", query.to_string()),
+                ("size", size.to_string()),
+                ("from", from.to_string()),
+            ]);
+            self.authorize(request, registry, None)
+        }).await?;
 
         if !response.status().is_success() {
             return Err(VelocityError::Registry(format!(
@@ -151,29 +417,76 @@ impl RegistryClient {
             )));
         }
 
-        let data: SearchResponse = response.json().await
-            .map_err(|e| VelocityError::Network(e.to_string()))?;
+        let mut splitter = ObjectSplitter::new();
+        let mut count = 0;
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| VelocityError::Network(e.to_string()))?;
+            for element in splitter.push(&chunk) {
+                on_hit(serde_json::from_slice(&element)?);
+                count += 1;
+            }
+        }
 
-        Ok(data.objects.into_iter().map(|o| o.package).collect())
+        Ok(count)
     }
 }
 
-/// Search response from npm registry
-#[derive(Debug, serde::Deserialize)]
-struct SearchResponse {
-    objects: Vec<SearchObject>,
+/// Encode metadata for the cache
+fn encode_metadata(metadata: &PackageMetadata) -> VelocityResult<Vec<u8>> {
+    rmp_serde::to_vec_named(metadata).map_err(|e| VelocityError::Cache(format!("Could not encode metadata: {}", e)))
+}
+
+/// Decode cached metadata; entries written by older versions are JSON
+fn decode_metadata(data: &[u8]) -> VelocityResult<PackageMetadata> {
+    if data.first() == Some(&b'{') {
+        return Ok(serde_json::from_slice(data)?);
+    }
+    rmp_serde::from_slice(data).map_err(|e| VelocityError::Cache(format!("Corrupt cached metadata: {}", e)))
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct SearchObject {
-    package: SearchResult,
+/// Metadata URL of a package on a registry (scoped names are escaped)
+fn package_url(registry: &str, name: &str) -> String {
+    format!("{}/{}", registry.trim_end_matches('/'), name.replace('/', "%2f"))
 }
 
-/// Search result
-#[derive(Debug, serde::Deserialize)]
-pub struct SearchResult {
-    pub name: String,
-    pub version: String,
-    #[serde(default)]
-    pub description: String,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::CacheConfig;
+
+    #[tokio::test]
+    async fn test_offline_uses_stale_metadata_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_config = CacheConfig { metadata_ttl: 0, offline: true, ..Default::default() };
+        let cache = Arc::new(CacheManager::new(dir.path(), &cache_config).unwrap());
+        cache
+            .store_metadata("left-pad", br#"{"name":"left-pad","dist-tags":{"latest":"1.3.0"},"versions":{}}"#, None, None)
+            .unwrap();
+
+        let network = NetworkConfig::default();
+        let http = OptimizedHttpClient::new(&network, Default::default()).unwrap();
+        let client = RegistryClient::new(&RegistryConfig::default(), &network, http, cache).unwrap();
+        let metadata = client.get_package_metadata("left-pad").await.unwrap();
+        assert_eq!(metadata.dist_tags["latest"], "1.3.0");
+
+        let missing = client.get_package_metadata("right-pad").await.unwrap_err();
+        assert!(matches!(missing, VelocityError::Offline { ref package, .. } if package == "right-pad"));
+        assert!(matches!(client.package_exists("left-pad").await, Err(VelocityError::Offline { .. })));
+    }
+
+    #[test]
+    fn test_metadata_round_trips_through_the_cache_encoding() {
+        let json = r#"{"name":"a","dist-tags":{"latest":"1.0.0"},"readme":"long text","versions":{"1.0.0":{
+            "name":"a","version":"1.0.0","dist":{"tarball":"https://r/a-1.0.0.tgz","integrity":"sha512-x"},
+            "license":{"type":"MIT"},"dependencies":{"b":"^1.0.0"}}}}"#;
+        let metadata: PackageMetadata = serde_json::from_str(json).unwrap();
+
+        let encoded = encode_metadata(&metadata).unwrap();
+        assert!(encoded.len() < json.len());
+        let decoded = decode_metadata(&encoded).unwrap();
+        assert_eq!(decoded.versions["1.0.0"].dependencies["b"], "^1.0.0");
+        assert_eq!(decoded.versions["1.0.0"].license_name(), Some("MIT"));
+        assert_eq!(decode_metadata(json.as_bytes()).unwrap().dist_tags["latest"], "1.0.0");
+    }
 }
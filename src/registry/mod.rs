@@ -1,6 +1,8 @@
 //! npm registry client
 
 pub mod client;
+pub mod http;
+pub mod search;
 pub mod types;
 
 pub use client::RegistryClient;
@@ -0,0 +1,162 @@
+//! Registry search results and incremental response parsing
+//!
+//! Search pages can hold hundreds of entries with full maintainer and link
+//! data. Rather than buffering a page and parsing it whole, [`ObjectSplitter`]
+//! is fed the body chunk by chunk and hands back each element of the top-level
+//! `objects` array as soon as it is complete.
+
+/// A single search hit
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SearchObject {
+    pub package: SearchResult,
+
+    #[serde(default)]
+    pub score: SearchScore,
+
+    /// Download counts, when the registry reports them
+    #[serde(default)]
+    pub downloads: Option<SearchDownloads>,
+}
+
+/// Package summary in a search hit
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SearchResult {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Publish time of this version (RFC 3339)
+    #[serde(default)]
+    pub date: Option<String>,
+}
+
+/// Registry ranking scores
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct SearchScore {
+    #[serde(default, rename = "final")]
+    pub overall: f64,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct SearchDownloads {
+    #[serde(default)]
+    pub weekly: u64,
+    #[serde(default)]
+    pub monthly: u64,
+}
+
+impl SearchResult {
+    /// Scope without the leading `@`, if the package is scoped
+    pub fn scope(&self) -> Option<&str> {
+        self.name.strip_prefix('@').and_then(|rest| rest.split('/').next())
+    }
+}
+
+/// Splits the top-level `objects` array of a search response into elements
+#[derive(Debug, Default)]
+pub struct ObjectSplitter {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// Last string completed at the top level; the key when a value follows
+    key: Vec<u8>,
+    capturing_key: bool,
+    in_objects: bool,
+    /// The element being collected
+    element: Vec<u8>,
+}
+
+impl ObjectSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the next chunk of the body; returns the elements it completed
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Vec<u8>> {
+        let mut complete = Vec::new();
+
+        for &byte in chunk {
+            let collecting = self.in_objects && self.depth >= 2;
+            if collecting {
+                self.element.push(byte);
+            }
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                    self.capturing_key = false;
+                } else if self.capturing_key {
+                    self.key.push(byte);
+                }
+                continue;
+            }
+
+            match byte {
+                b'"' => {
+                    self.in_string = true;
+                    if self.depth == 1 {
+                        self.capturing_key = true;
+                        self.key.clear();
+                    }
+                }
+                b'{' | b'[' => {
+                    if self.depth == 1 && byte == b'[' && self.key == b"objects" {
+                        self.in_objects = true;
+                    }
+                    self.depth += 1;
+                    if self.in_objects && self.depth == 3 {
+                        self.element.clear();
+                        self.element.push(byte);
+                    }
+                }
+                b'}' | b']' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.in_objects && self.depth == 2 && !self.element.is_empty() {
+                        complete.push(std::mem::take(&mut self.element));
+                    } else if self.in_objects && self.depth == 1 {
+                        self.in_objects = false;
+                        self.element.clear();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        complete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splitter_across_chunks() {
+        let body = br#"{"objects":[
+            {"package":{"name":"left-pad","version":"1.3.0","description":"pads \"left\" {"}},
+            {"package":{"name":"@scope/pad","version":"2.0.0","keywords":["a]"]},"downloads":{"weekly":5}}
+        ],"total":2,"time":"now"}"#;
+
+        let mut splitter = ObjectSplitter::new();
+        let mut elements = Vec::new();
+        for chunk in body.chunks(7) {
+            elements.extend(splitter.push(chunk));
+        }
+
+        assert_eq!(elements.len(), 2);
+        let hits: Vec<SearchObject> = elements
+            .iter()
+            .map(|e| serde_json::from_slice(e).unwrap())
+            .collect();
+        assert_eq!(hits[0].package.name, "left-pad");
+        assert_eq!(hits[0].package.description, "pads \"left\" {");
+        assert_eq!(hits[1].package.scope(), Some("scope"));
+        assert_eq!(hits[1].downloads.as_ref().map(|d| d.weekly), Some(5));
+    }
+}
@@ -14,36 +14,54 @@ pub struct PackageMetadata {
     pub description: String,
 
     /// Distribution tags (latest, next, etc.)
-    #[serde(default, rename = "dist-tags")]
+    #[serde(default, rename = "dist-tags", skip_serializing_if = "HashMap::is_empty")]
     pub dist_tags: HashMap<String, String>,
 
     /// All versions metadata
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub versions: HashMap<String, VersionMetadata>,
 
     /// Modification time
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub time: HashMap<String, String>,
+}
 
-    /// Repository info
-    #[serde(default)]
-    pub repository: Option<Repository>,
-
-    /// Author
-    #[serde(default)]
-    pub author: Option<Person>,
-
-    /// Maintainers
-    #[serde(default)]
-    pub maintainers: Vec<Person>,
-
-    /// Keywords
-    #[serde(default)]
-    pub keywords: Vec<String>,
+impl PackageMetadata {
+    /// Remove `removed` from the versions
+    ///
+    /// Dist-tags on a removed version move to the newest older version that is
+    /// left (a stable one for stable tags), or disappear.
+    pub fn remove_versions(&mut self, removed: &[String]) {
+        if removed.is_empty() {
+            return;
+        }
+        for version in removed {
+            self.versions.remove(version);
+        }
 
-    /// License
-    #[serde(default)]
-    pub license: Option<String>,
+        let mut remaining: Vec<semver::Version> =
+            self.versions.keys().filter_map(|v| semver::Version::parse(v).ok()).collect();
+        remaining.sort();
+        self.dist_tags.retain(|_, tagged| {
+            if !removed.contains(tagged) {
+                return true;
+            }
+            let Ok(target) = semver::Version::parse(tagged) else {
+                return false;
+            };
+            let fallback = remaining
+                .iter()
+                .rev()
+                .find(|v| **v < target && (v.pre.is_empty() || !target.pre.is_empty()));
+            match fallback {
+                Some(version) => {
+                    *tagged = version.to_string();
+                    true
+                }
+                None => false,
+            }
+        });
+    }
 }
 
 /// Version-specific metadata
@@ -55,71 +73,68 @@ pub struct VersionMetadata {
     /// Version string
     pub version: String,
 
-    /// Description
-    #[serde(default)]
-    pub description: String,
-
-    /// Main entry point
-    #[serde(default)]
-    pub main: Option<String>,
-
-    /// Module entry point
-    #[serde(default)]
-    pub module: Option<String>,
-
     /// Distribution info
     pub dist: DistInfo,
 
     /// Dependencies
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub dependencies: HashMap<String, String>,
 
-    /// Dev dependencies
-    #[serde(default, rename = "devDependencies")]
-    pub dev_dependencies: HashMap<String, String>,
-
     /// Peer dependencies
-    #[serde(default, rename = "peerDependencies")]
+    #[serde(default, rename = "peerDependencies", skip_serializing_if = "HashMap::is_empty")]
     pub peer_dependencies: HashMap<String, String>,
 
     /// Optional dependencies
-    #[serde(default, rename = "optionalDependencies")]
+    #[serde(default, rename = "optionalDependencies", skip_serializing_if = "HashMap::is_empty")]
     pub optional_dependencies: HashMap<String, String>,
 
     /// Peer dependencies meta
-    #[serde(default, rename = "peerDependenciesMeta")]
+    #[serde(default, rename = "peerDependenciesMeta", skip_serializing_if = "HashMap::is_empty")]
     pub peer_dependencies_meta: HashMap<String, PeerDependencyMeta>,
 
     /// Engines
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub engines: HashMap<String, String>,
 
     /// OS requirements
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub os: Vec<String>,
 
     /// CPU requirements
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub cpu: Vec<String>,
 
     /// Scripts
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub scripts: HashMap<String, String>,
 
     /// Binary executables
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub bin: Option<serde_json::Value>,
 
     /// Deprecated message
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub deprecated: Option<String>,
 
+    /// SPDX license expression, or a legacy `{ "type": ... }` object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<serde_json::Value>,
+
     /// Has install scripts
-    #[serde(default, rename = "hasInstallScript")]
+    #[serde(default, rename = "hasInstallScript", skip_serializing_if = "Option::is_none")]
     pub has_install_script: Option<bool>,
 }
 
 impl VersionMetadata {
+    /// The license as an SPDX expression, if one is declared
+    pub fn license_name(&self) -> Option<&str> {
+        match self.license.as_ref()? {
+            serde_json::Value::String(license) => Some(license),
+            serde_json::Value::Object(object) => object.get("type")?.as_str(),
+            _ => None,
+        }
+    }
+
     /// Check if this version has install scripts
     pub fn has_install_scripts(&self) -> bool {
         if let Some(has_script) = self.has_install_script {
@@ -141,28 +156,34 @@ pub struct DistInfo {
     pub tarball: String,
 
     /// SHA-512 integrity hash
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub integrity: Option<String>,
 
     /// SHA-1 hash (legacy)
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub shasum: Option<String>,
 
     /// Number of files
-    #[serde(default, rename = "fileCount")]
+    #[serde(default, rename = "fileCount", skip_serializing_if = "Option::is_none")]
     pub file_count: Option<u32>,
 
     /// Unpacked size
-    #[serde(default, rename = "unpackedSize")]
+    #[serde(default, rename = "unpackedSize", skip_serializing_if = "Option::is_none")]
     pub unpacked_size: Option<u64>,
 
-    /// npm signature
-    #[serde(default, rename = "npm-signature")]
-    pub npm_signature: Option<String>,
-
     /// Signatures
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub signatures: Vec<Signature>,
+
+    /// Attestations published with this version (npm provenance)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestations: Option<Attestations>,
+}
+
+/// Where a version's attestations are served
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Attestations {
+    pub url: String,
 }
 
 /// Package signature
@@ -178,28 +199,3 @@ pub struct PeerDependencyMeta {
     #[serde(default)]
     pub optional: bool,
 }
-
-/// Repository info
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(untagged)]
-pub enum Repository {
-    String(String),
-    Object {
-        #[serde(rename = "type")]
-        repo_type: Option<String>,
-        url: String,
-        directory: Option<String>,
-    },
-}
-
-/// Person (author/maintainer)
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(untagged)]
-pub enum Person {
-    String(String),
-    Object {
-        name: Option<String>,
-        email: Option<String>,
-        url: Option<String>,
-    },
-}
@@ -1,19 +1,36 @@
 //! Dependency resolver for Velocity
 //!
-//! Implements deterministic version resolution with conflict detection.
+//! Implements deterministic version resolution with backtracking on conflicts.
 
 pub mod version;
 pub mod graph;
+pub mod solver;
+pub mod peers;
+pub mod tarball;
+pub mod workspace;
 
-use std::collections::HashMap;
+#[cfg(test)]
+pub(crate) mod test_support;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
 
+use futures::stream::{self, StreamExt};
+
 use crate::cache::CacheManager;
-use crate::core::{Lockfile, lockfile::LockedPackage, VelocityError, VelocityResult};
+use crate::core::{Lockfile, lockfile::{LockedDependency, LockedPackage}, VelocityError, VelocityResult};
 use crate::registry::RegistryClient;
+use crate::registry::types::PackageMetadata;
+use crate::security::{Policy, ReleaseCooldown};
+use crate::utils::{NoProgress, Phase, Progress};
 
 pub use graph::DependencyGraph;
+pub use peers::{PeerIssue, PeerRequirement};
+pub use solver::{PackageVersion, Solver, Step};
+pub use tarball::TarballPackage;
 pub use version::VersionConstraint;
+pub use workspace::{WorkspaceLink, WorkspaceMembers};
 
 /// Resolution result containing the dependency graph and lockfile
 pub struct Resolution {
@@ -28,142 +45,353 @@ pub struct Resolution {
 
     /// Packages available in cache
     pub from_cache: Vec<ResolvedPackage>,
+
+    /// Declared peer dependencies the resolved tree does not satisfy
+    pub peer_issues: Vec<PeerIssue>,
+
+    /// `workspace:` dependencies, linked to the members' sources
+    pub workspace_links: Vec<WorkspaceLink>,
+}
+
+impl Resolution {
+    /// Leave out the packages the lockfile marks dev-only
+    ///
+    /// Linking the result against the previous lockfile removes them from node_modules.
+    pub fn omit_dev(&mut self) {
+        let dev: HashSet<(String, String)> = self
+            .lockfile
+            .packages
+            .iter()
+            .filter(|p| p.dev)
+            .map(|p| (p.name.clone(), p.version.clone()))
+            .collect();
+        let production = |p: &ResolvedPackage| !dev.contains(&(p.name.clone(), p.version.clone()));
+
+        self.lockfile.packages.retain(|p| !p.dev);
+        self.to_install.retain(production);
+        self.from_cache.retain(production);
+    }
 }
 
 /// A resolved package with all metadata
 #[derive(Debug, Clone)]
 pub struct ResolvedPackage {
+    /// Name the package is installed under (the alias for `npm:` aliases)
     pub name: String,
+    /// Real registry package when installed under an alias
+    pub alias_of: Option<String>,
     pub version: String,
     pub tarball_url: String,
     pub integrity: String,
     pub dependencies: HashMap<String, String>,
     pub peer_dependencies: HashMap<String, String>,
     pub optional_dependencies: HashMap<String, String>,
+    /// Version each dependency edge is locked to, where the lockfile records one
+    pub locked_dependencies: HashMap<String, String>,
     pub has_scripts: bool,
 }
 
+impl ResolvedPackage {
+    /// Registry name of the package, looking through aliases
+    pub fn package_name(&self) -> &str {
+        self.alias_of.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Options controlling how much of earlier resolutions is reused
+#[derive(Debug, Clone, Default)]
+pub struct ResolveOptions {
+    /// Versions from the existing lockfile, preferred while they still satisfy constraints
+    pub locked: Vec<(String, String)>,
+
+    /// Packages to re-resolve fresh from the registry
+    pub force: ForceResolve,
+
+    /// Metadata cache TTL override in seconds
+    pub metadata_ttl: Option<u64>,
+
+    /// Add missing required peer dependencies to the resolution
+    pub auto_install_peers: bool,
+}
+
+/// Which packages bypass cached metadata and locked versions
+#[derive(Debug, Clone, Default)]
+pub enum ForceResolve {
+    /// Reuse cached metadata and locked versions
+    #[default]
+    None,
+    /// Re-resolve every package
+    All,
+    /// Re-resolve only the named packages
+    Packages(HashSet<String>),
+}
+
+impl ForceResolve {
+    /// Build from a `--force-resolve [PACKAGES]` flag (no names means all)
+    pub fn from_flag(packages: Option<Vec<String>>) -> Self {
+        match packages {
+            None => ForceResolve::None,
+            Some(names) if names.is_empty() => ForceResolve::All,
+            Some(names) => ForceResolve::Packages(names.into_iter().collect()),
+        }
+    }
+
+    /// Check whether a package must be re-resolved
+    pub fn includes(&self, name: &str) -> bool {
+        match self {
+            ForceResolve::None => false,
+            ForceResolve::All => true,
+            ForceResolve::Packages(names) => names.contains(name),
+        }
+    }
+}
+
+impl ResolveOptions {
+    /// Prefer the versions pinned by a lockfile
+    pub fn with_lockfile(mut self, lockfile: Option<&Lockfile>) -> Self {
+        if let Some(lockfile) = lockfile {
+            self.locked = lockfile.packages
+                .iter()
+                .map(|p| (p.name.clone(), p.version.clone()))
+                .collect();
+        }
+        self
+    }
+}
+
 /// Dependency resolver
 pub struct Resolver {
     registry: Arc<RegistryClient>,
     cache: Arc<CacheManager>,
+    options: ResolveOptions,
+    /// Concurrent metadata requests
+    metadata_concurrency: usize,
+    /// Workspace members `workspace:` dependencies resolve to
+    workspace: WorkspaceMembers,
+    /// Where each fetched package is reported
+    progress: Arc<dyn Progress>,
+    /// Versions too new to install
+    cooldown: Option<ReleaseCooldown>,
+    /// Packages, versions and registries that may not be installed
+    policy: Arc<Policy>,
+    /// Why versions were left out, per package, for resolution errors
+    left_out: parking_lot::Mutex<BTreeMap<String, Vec<String>>>,
+    /// Directory `file:` tarball dependencies are relative to
+    base_dir: PathBuf,
 }
 
 impl Resolver {
     /// Create a new resolver
     pub fn new(registry: Arc<RegistryClient>, cache: Arc<CacheManager>) -> Self {
-        Self { registry, cache }
+        Self {
+            registry,
+            cache,
+            options: ResolveOptions::default(),
+            metadata_concurrency: 1,
+            workspace: WorkspaceMembers::default(),
+            progress: Arc::new(NoProgress),
+            cooldown: None,
+            policy: Arc::new(Policy::default()),
+            left_out: parking_lot::Mutex::new(BTreeMap::new()),
+            base_dir: PathBuf::from("."),
+        }
+    }
+
+    /// Read `file:` tarball dependencies relative to `dir`, the package.json's directory
+    pub fn with_base_dir(mut self, dir: PathBuf) -> Self {
+        self.base_dir = dir;
+        self
+    }
+
+    /// Leave out versions published within the cooldown (`None` allows every version)
+    pub fn with_cooldown(mut self, cooldown: Option<ReleaseCooldown>) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Leave out versions the package policy refuses, and reject trees it does not allow
+    pub fn with_policy(mut self, policy: Arc<Policy>) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Resolve `workspace:` dependencies to these workspace members
+    pub fn with_workspace(mut self, members: WorkspaceMembers) -> Self {
+        self.workspace = members;
+        self
+    }
+
+    /// Fetch up to `limit` packages' metadata at once
+    pub fn with_metadata_concurrency(mut self, limit: usize) -> Self {
+        self.metadata_concurrency = limit.max(1);
+        self
+    }
+
+    /// Report each package whose metadata arrives to `progress`
+    pub fn with_progress(mut self, progress: Arc<dyn Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Use the given resolution options
+    pub fn with_options(mut self, options: ResolveOptions) -> Self {
+        self.options = options;
+        self
     }
 
     /// Resolve dependencies from a dependency map
+    ///
+    /// Versions are chosen by the backtracking [`Solver`]; this method feeds it
+    /// registry metadata on demand and turns its solution into a graph and lockfile.
     pub async fn resolve(
         &self,
         dependencies: &HashMap<String, String>,
     ) -> VelocityResult<Resolution> {
-        let mut graph = DependencyGraph::new();
-        let mut lockfile = Lockfile::new();
-        let mut to_install = Vec::new();
-        let mut from_cache = Vec::new();
-        let mut resolved_versions: HashMap<String, String> = HashMap::new();
+        let (workspace_links, requested) = self.workspace.split(dependencies)?;
+        self.resolve_requested(requested, workspace_links).await
+    }
 
-        // Queue of (name, constraint, depth)
-        let mut queue: Vec<(String, String, usize)> = dependencies
-            .iter()
-            .map(|(n, v)| (n.clone(), v.clone(), 0))
-            .collect();
+    /// Resolve the root's dependencies together with every workspace member's
+    ///
+    /// The result is one tree and one lockfile for the whole workspace. Every
+    /// member is linked, whether or not another package depends on it.
+    pub async fn resolve_workspace(
+        &self,
+        dependencies: &HashMap<String, String>,
+    ) -> VelocityResult<Resolution> {
+        let members = self.workspace.dependencies();
+        let all = dependencies.iter().chain(members.iter().map(|(name, spec)| (name, spec)));
+        let (_, requested) = self.workspace.split(all)?;
+        self.resolve_requested(requested, self.workspace.all_links()).await
+    }
 
-        let mut visited: std::collections::HashSet<String> = std::collections::HashSet::new();
+    #[tracing::instrument(name = "resolve", skip_all, fields(dependencies = requested.len()))]
+    async fn resolve_requested(
+        &self,
+        mut requested: Vec<(String, String)>,
+        workspace_links: Vec<WorkspaceLink>,
+    ) -> VelocityResult<Resolution> {
+        // Tarball dependencies bring their own metadata, read from the tarball,
+        // which goes into the cache for the installer
+        let mut metadata: HashMap<String, PackageMetadata> = HashMap::new();
+        let mut tarballs: HashSet<String> = HashSet::new();
+        for (name, spec) in requested.iter().filter(|(_, spec)| tarball::is_tarball_spec(spec)) {
+            let package = TarballPackage::load(spec, &self.base_dir, &self.registry).await?;
+            self.cache.store_tarball(name, &package.manifest.version, &package.data)?;
+            metadata.insert(name.clone(), package.metadata(name));
+            tarballs.insert(name.clone());
+        }
 
-        while let Some((name, constraint_str, depth)) = queue.pop() {
-            let cache_key = format!("{}@{}", name, constraint_str);
-            if visited.contains(&cache_key) {
-                continue;
-            }
-            visited.insert(cache_key);
-
-            // Get package metadata from registry
-            let metadata = self.registry.get_package_metadata(&name).await?;
-
-            // Parse constraint and find best matching version
-            let constraint = VersionConstraint::parse(&constraint_str)?;
-            let matching_version = self.find_matching_version(&metadata.versions, &constraint)?;
-
-            // Check for conflicts
-            if let Some(existing) = resolved_versions.get(&name) {
-                if *existing != matching_version {
-                    // Try to find a version that satisfies both
-                    // For now, use the higher version
-                    let existing_semver = semver::Version::parse(existing).ok();
-                    let new_semver = semver::Version::parse(&matching_version).ok();
-
-                    match (existing_semver, new_semver) {
-                        (Some(e), Some(n)) if e >= n => continue,
-                        _ => {}
-                    }
+        let mut solution = self.solve(&requested, &mut metadata).await?;
+        let mut peer_issues = peers::check_peers(&peer_requirements(&solution, &metadata), &solution);
+
+        // Missing peers join the requested set until the tree stops growing;
+        // each round can only add names, so this terminates
+        if self.options.auto_install_peers {
+            loop {
+                let missing: Vec<(String, String)> = peers::missing_peers(&peer_issues)
+                    .into_iter()
+                    .filter(|(name, _)| !requested.iter().any(|(requested, _)| requested == name))
+                    .collect();
+                if missing.is_empty() {
+                    break;
                 }
+
+                requested.extend(missing);
+                solution = self.solve(&requested, &mut metadata).await?;
+                peer_issues = peers::check_peers(&peer_requirements(&solution, &metadata), &solution);
             }
+        }
 
-            resolved_versions.insert(name.clone(), matching_version.clone());
+        let mut graph = DependencyGraph::new();
+        let mut lockfile = Lockfile::new();
+        let mut to_install = Vec::new();
+        let mut from_cache = Vec::new();
 
-            // Get version-specific metadata
-            let version_meta = metadata.versions.get(&matching_version)
+        let chosen = solution
+            .iter()
+            .flat_map(|(name, versions)| versions.iter().map(move |v| (name, v.to_string())));
+
+        for (name, version) in chosen {
+            let package_meta = metadata.get(name);
+            // Aliases are stored under their own name but carry the real package's metadata
+            let alias_of = package_meta.map(|meta| &meta.name).filter(|real| *real != name).cloned();
+            let version_meta = package_meta
+                .and_then(|meta| meta.versions.get(&version))
                 .ok_or_else(|| VelocityError::VersionNotFound {
                     package: name.clone(),
-                    version: matching_version.clone(),
+                    version: version.clone(),
                 })?;
+            let real_name = alias_of.as_deref().unwrap_or(name);
+            // A tarball named in package.json is not registry metadata to vouch for
+            if !tarballs.contains(name) {
+                self.registry.check_association(real_name, &version_meta.dist.tarball)?;
+            }
+            self.policy.check(real_name, &version, &version_meta.dist.tarball)?;
+
+            // Optional dependencies the solver left out are not part of the tree
+            let optional_dependencies: HashMap<String, String> = version_meta
+                .optional_dependencies
+                .iter()
+                .filter(|(dep, _)| solution.contains_key(dep.as_str()))
+                .map(|(dep, spec)| (dep.clone(), spec.clone()))
+                .collect();
 
             let resolved = ResolvedPackage {
                 name: name.clone(),
-                version: matching_version.clone(),
+                alias_of,
+                version: version.clone(),
                 tarball_url: version_meta.dist.tarball.clone(),
                 integrity: version_meta.dist.integrity.clone().unwrap_or_default(),
                 dependencies: version_meta.dependencies.clone(),
                 peer_dependencies: version_meta.peer_dependencies.clone(),
-                optional_dependencies: version_meta.optional_dependencies.clone(),
+                optional_dependencies,
+                locked_dependencies: HashMap::new(),
                 has_scripts: version_meta.has_install_scripts(),
             };
 
             // Add to graph
-            graph.add_package(&name, &matching_version);
-            for (dep_name, _) in &resolved.dependencies {
-                graph.add_dependency(&name, dep_name);
+            graph.add_package(name, &version);
+            for dep_name in resolved.dependencies.keys() {
+                graph.add_dependency(name, dep_name);
             }
 
             // Check cache
-            if self.cache.has_package(&name, &matching_version)? {
+            if self.cache.has_package(name, &version)? {
                 from_cache.push(resolved.clone());
             } else {
                 to_install.push(resolved.clone());
             }
 
-            // Add to lockfile
+            // Add to lockfile; edges are pinned once every package is in
+            let mut dependencies: Vec<LockedDependency> = resolved
+                .dependencies
+                .iter()
+                .map(|(dep, spec)| LockedDependency::new(dep, spec))
+                .collect();
+            dependencies.sort();
             lockfile.add_package(LockedPackage {
                 name: name.clone(),
-                version: matching_version.clone(),
+                alias_of: resolved.alias_of.clone(),
+                version: version.clone(),
                 resolved: resolved.tarball_url.clone(),
                 integrity: resolved.integrity.clone(),
-                dependencies: resolved.dependencies.keys().map(|k| {
-                    format!("{}@{}", k, resolved.dependencies.get(k).unwrap())
-                }).collect(),
+                dependencies,
                 peer_dependencies: resolved.peer_dependencies.keys().cloned().collect(),
                 optional_dependencies: resolved.optional_dependencies.keys().cloned().collect(),
                 has_scripts: resolved.has_scripts,
+                approved_scripts: None,
                 cpu: vec![],
                 os: vec![],
+                dev: false,
+                provenance: None,
             });
+        }
 
-            // Queue dependencies (limit depth to prevent infinite loops)
-            if depth < 100 {
-                for (dep_name, dep_constraint) in &resolved.dependencies {
-                    queue.push((dep_name.clone(), dep_constraint.clone(), depth + 1));
-                }
-
-                // Optional dependencies are best-effort
-                for (dep_name, dep_constraint) in &resolved.optional_dependencies {
-                    queue.push((dep_name.clone(), dep_constraint.clone(), depth + 1));
-                }
-            }
+        for link in &workspace_links {
+            lockfile.workspaces.insert(link.name.clone(), self.workspace.lockfile_entry(link));
         }
+        lockfile.pin_dependencies();
 
         // Check for cycles
         if graph.has_cycle() {
@@ -176,27 +404,273 @@ impl Resolver {
             lockfile,
             to_install,
             from_cache,
+            peer_issues,
+            workspace_links,
         })
     }
 
-    /// Find the best matching version for a constraint
-    fn find_matching_version(
+    /// Run the solver over `dependencies`, fetching metadata it has not seen yet
+    async fn solve(
         &self,
-        versions: &HashMap<String, crate::registry::types::VersionMetadata>,
-        constraint: &VersionConstraint,
-    ) -> VelocityResult<String> {
-        let mut matching: Vec<semver::Version> = versions
-            .keys()
-            .filter_map(|v| semver::Version::parse(v).ok())
-            .filter(|v| constraint.matches(v))
-            .collect();
+        dependencies: &[(String, String)],
+        metadata: &mut HashMap<String, PackageMetadata>,
+    ) -> VelocityResult<BTreeMap<String, BTreeSet<semver::Version>>> {
+        let locked = self.options.locked.iter().filter(|(name, _)| !self.options.force.includes(name));
+        let mut solver = Solver::new(dependencies.iter().map(|(name, spec)| (name, spec)))?
+            .with_preferred(locked.map(|(name, version)| (name.as_str(), version.as_str())));
+
+        // Every direct dependency is needed, so fetch them all up front
+        let direct = dependencies.iter().map(|(name, spec)| {
+            let source = version::parse_alias(spec).map_or(name.as_str(), |(target, _)| target);
+            (name.as_str(), source)
+        });
+        self.prefetch(direct, metadata).await;
+
+        loop {
+            match solver.run().map_err(|e| self.explain_conflict(e))? {
+                Step::Done(solution) => return Ok(solution),
+                Step::NeedMetadata(name) => {
+                    if let Some(meta) = metadata.get(&name) {
+                        solver.add_package(&name, package_versions(meta));
+                        solver.add_tags(&name, &meta.dist_tags);
+                        continue;
+                    }
 
-        matching.sort();
-        matching.reverse();
+                    // Fetch everything the solver is waiting on at once; the
+                    // solution does not depend on the order responses arrive in
+                    let pending: Vec<(String, String)> = solver
+                        .pending_metadata()
+                        .into_iter()
+                        .map(|pending| {
+                            let source = solver.alias_of(&pending).unwrap_or_else(|| pending.clone());
+                            (pending, source)
+                        })
+                        .collect();
+                    self.prefetch(pending.iter().map(|(name, source)| (name.as_str(), source.as_str())), metadata).await;
+                    if let Some(meta) = metadata.get(&name) {
+                        solver.add_package(&name, package_versions(meta));
+                        solver.add_tags(&name, &meta.dist_tags);
+                        continue;
+                    }
 
-        matching
-            .first()
-            .map(|v| v.to_string())
-            .ok_or_else(|| VelocityError::InvalidVersionConstraint(constraint.to_string()))
+                    // Fetched again on its own to handle the failure
+                    let source = solver.alias_of(&name).unwrap_or_else(|| name.clone());
+                    match self.fetch_metadata(&source).await {
+                        Ok(meta) => {
+                            solver.add_package(&name, package_versions(&meta));
+                            solver.add_tags(&name, &meta.dist_tags);
+                            metadata.insert(name, meta);
+                        }
+                        // Optional dependencies that do not exist are left out
+                        Err(VelocityError::PackageNotFound(_)) if solver.is_optional(&name) => {
+                            solver.mark_missing(&name);
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fetch metadata for several packages concurrently, keeping what arrives
+    ///
+    /// Each entry is the name to store the metadata under and the package to
+    /// fetch, which differ for aliases. Failures are dropped here; the solver
+    /// asks for the package again and handles the error then (e.g. leaving out
+    /// a missing optional dependency).
+    async fn prefetch<'a, I>(&self, names: I, metadata: &mut HashMap<String, PackageMetadata>)
+    where
+        I: Iterator<Item = (&'a str, &'a str)>,
+    {
+        let missing: Vec<(&str, &str)> = names.filter(|(name, _)| !metadata.contains_key(*name)).collect();
+
+        let fetched: Vec<(&str, VelocityResult<PackageMetadata>)> = stream::iter(missing)
+            .map(|(name, source)| async move { (name, self.fetch_metadata(source).await) })
+            .buffer_unordered(self.metadata_concurrency)
+            .collect()
+            .await;
+
+        for (name, result) in fetched {
+            if let Ok(meta) = result {
+                metadata.insert(name.to_string(), meta);
+            }
+        }
+    }
+
+    /// Get package metadata; forced packages skip the cache
+    #[tracing::instrument(level = "debug", name = "metadata", skip_all, fields(package = name))]
+    async fn fetch_metadata(&self, name: &str) -> VelocityResult<PackageMetadata> {
+        let forced = self.options.force.includes(name);
+        let mut metadata = match (forced, self.options.metadata_ttl) {
+            (true, _) => self.registry.get_package_metadata_with_ttl(name, 0).await?,
+            (false, Some(ttl)) => self.registry.get_package_metadata_with_ttl(name, ttl).await?,
+            (false, None) => self.registry.get_package_metadata(name).await?,
+        };
+        self.progress.package_done(Phase::Resolve, name);
+
+        let mut left_out = Vec::new();
+
+        // Locked versions were accepted before and stay usable
+        if let Some(ref cooldown) = self.cooldown {
+            let locked: Vec<&str> = self
+                .options
+                .locked
+                .iter()
+                .filter(|(locked, _)| locked == name)
+                .map(|(_, version)| version.as_str())
+                .collect();
+            let held_back = cooldown.apply(&mut metadata, &locked);
+            if !held_back.is_empty() {
+                tracing::debug!("Holding back {} {} (release cooldown)", name, held_back.join(", "));
+                left_out.push(format!(
+                    "held back {} {}: published in the last {} hours (security.release_cooldown_hours); --ignore-cooldown allows {}",
+                    name,
+                    held_back.join(", "),
+                    cooldown.hours(),
+                    if held_back.len() == 1 { "it" } else { "them" }
+                ));
+            }
+        }
+
+        // Refused versions are left out so ranges settle on an allowed one
+        let mut refused: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for version in metadata.versions.keys().filter(|_| !self.policy.is_empty()) {
+            if let Some(reason) = self.policy.refusal(&metadata.name, version) {
+                refused.entry(reason).or_default().push(version.clone());
+            }
+        }
+        for (reason, mut versions) in refused {
+            versions.sort();
+            tracing::debug!("Leaving out {} {} ({})", name, versions.join(", "), reason);
+            metadata.remove_versions(&versions);
+            left_out.push(format!("left out {} {}: {}", name, versions.join(", "), reason));
+        }
+
+        if !left_out.is_empty() {
+            self.left_out.lock().insert(name.to_string(), left_out);
+        }
+        Ok(metadata)
+    }
+
+    /// Point out versions that were left out when they might have satisfied `error`
+    fn explain_conflict(&self, error: VelocityError) -> VelocityError {
+        match error {
+            VelocityError::ResolutionConflict { package, mut details } => {
+                if let Some(notes) = self.left_out.lock().get(&package) {
+                    for note in notes {
+                        details.push_str("\n  ");
+                        details.push_str(note);
+                    }
+                }
+                VelocityError::ResolutionConflict { package, details }
+            }
+            error => error,
+        }
     }
+
+    /// Build a resolution straight from a lockfile, without touching the registry
+    pub fn resolve_from_lockfile(&self, lockfile: &Lockfile) -> VelocityResult<Resolution> {
+        let mut graph = DependencyGraph::new();
+        let mut to_install = Vec::new();
+        let mut from_cache = Vec::new();
+
+        self.policy.check_lockfile(lockfile)?;
+        for locked in &lockfile.packages {
+            self.registry.check_association(locked.package_name(), &locked.resolved)?;
+            let dependencies: HashMap<String, String> = locked.dependencies
+                .iter()
+                .map(|dep| (dep.name.clone(), dep.range.clone()))
+                .collect();
+            let locked_dependencies: HashMap<String, String> = locked.dependencies
+                .iter()
+                .filter_map(|dep| Some((dep.name.clone(), dep.version.clone()?)))
+                .collect();
+
+            let resolved = ResolvedPackage {
+                name: locked.name.clone(),
+                alias_of: locked.alias_of.clone(),
+                version: locked.version.clone(),
+                tarball_url: locked.resolved.clone(),
+                integrity: locked.integrity.clone(),
+                dependencies,
+                peer_dependencies: locked.peer_dependencies
+                    .iter()
+                    .map(|name| (name.clone(), "*".to_string()))
+                    .collect(),
+                optional_dependencies: locked.optional_dependencies
+                    .iter()
+                    .map(|name| (name.clone(), "*".to_string()))
+                    .collect(),
+                locked_dependencies,
+                has_scripts: locked.has_scripts,
+            };
+
+            graph.add_package(&resolved.name, &resolved.version);
+            for dep_name in resolved.dependencies.keys() {
+                graph.add_dependency(&resolved.name, dep_name);
+            }
+
+            if self.cache.has_package(&resolved.name, &resolved.version)? {
+                from_cache.push(resolved);
+            } else {
+                to_install.push(resolved);
+            }
+        }
+
+        let mut workspace_links: Vec<WorkspaceLink> =
+            lockfile.workspaces.keys().filter_map(|name| self.workspace.link(name)).collect();
+        workspace_links.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // The lockfile records peer names but not their ranges, so there is nothing to check
+        Ok(Resolution {
+            graph,
+            lockfile: lockfile.clone(),
+            to_install,
+            from_cache,
+            peer_issues: Vec::new(),
+            workspace_links,
+        })
+    }
+}
+
+/// Peer dependencies declared by every chosen version
+fn peer_requirements(
+    solution: &BTreeMap<String, BTreeSet<semver::Version>>,
+    metadata: &HashMap<String, PackageMetadata>,
+) -> Vec<PeerRequirement> {
+    let mut requirements = Vec::new();
+    for (name, versions) in solution {
+        for version in versions {
+            let version = version.to_string();
+            let Some(meta) = metadata.get(name).and_then(|m| m.versions.get(&version)) else {
+                continue;
+            };
+
+            for (peer, range) in &meta.peer_dependencies {
+                requirements.push(PeerRequirement {
+                    package: name.clone(),
+                    version: version.clone(),
+                    peer: peer.clone(),
+                    range: range.clone(),
+                    optional: meta.peer_dependencies_meta.get(peer).map(|m| m.optional).unwrap_or(false),
+                });
+            }
+        }
+    }
+    requirements
+}
+
+/// Published versions of a package in the form the solver works with
+fn package_versions(metadata: &PackageMetadata) -> Vec<PackageVersion> {
+    metadata
+        .versions
+        .iter()
+        .filter_map(|(version, meta)| {
+            semver::Version::parse(version).ok().map(|version| PackageVersion {
+                version,
+                dependencies: meta.dependencies.clone(),
+                optional_dependencies: meta.optional_dependencies.clone(),
+            })
+        })
+        .collect()
 }
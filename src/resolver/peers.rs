@@ -0,0 +1,155 @@
+//! Peer dependency validation
+//!
+//! Peers are not installed on behalf of the package declaring them; they must
+//! already be in the tree. After solving, every declared peer is checked
+//! against the versions the solution picked.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::VersionConstraint;
+
+/// A peer dependency declared by a resolved package
+#[derive(Debug, Clone)]
+pub struct PeerRequirement {
+    /// Package declaring the peer
+    pub package: String,
+    pub version: String,
+
+    /// Peer package name and accepted range
+    pub peer: String,
+    pub range: String,
+
+    /// Marked optional in peerDependenciesMeta
+    pub optional: bool,
+}
+
+/// A peer dependency the resolved tree does not satisfy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerIssue {
+    /// Package declaring the peer
+    pub package: String,
+    pub version: String,
+
+    /// Peer package name and accepted range
+    pub peer: String,
+    pub range: String,
+
+    /// Versions of the peer in the tree; empty when it is missing
+    pub found: Vec<String>,
+}
+
+impl PeerIssue {
+    /// Whether the peer is absent rather than present at the wrong version
+    pub fn is_missing(&self) -> bool {
+        self.found.is_empty()
+    }
+
+    /// JSON representation used by `--json` output
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "package": self.package,
+            "version": self.version,
+            "peer": self.peer,
+            "range": self.range,
+            "found": self.found,
+        })
+    }
+}
+
+impl fmt::Display for PeerIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{} requires peer {}@{}, ", self.package, self.version, self.peer, self.range)?;
+        if self.is_missing() {
+            write!(f, "but it is not installed")
+        } else {
+            write!(f, "but found {}", self.found.join(", "))
+        }
+    }
+}
+
+/// Check peer requirements against the versions chosen for each package
+///
+/// Optional peers are only checked when present. A peer is satisfied when
+/// any chosen version of it matches the declared range.
+pub fn check_peers(
+    requirements: &[PeerRequirement],
+    solution: &BTreeMap<String, BTreeSet<semver::Version>>,
+) -> Vec<PeerIssue> {
+    let mut issues: Vec<PeerIssue> = requirements
+        .iter()
+        .filter_map(|req| {
+            let versions = solution.get(&req.peer).filter(|v| !v.is_empty());
+            let found: Vec<String> = match versions {
+                Some(versions) => {
+                    let constraint = VersionConstraint::parse(&req.range).unwrap_or(VersionConstraint::Any);
+                    if versions.iter().any(|v| constraint.matches(v)) {
+                        return None;
+                    }
+                    versions.iter().map(|v| v.to_string()).collect()
+                }
+                None if req.optional => return None,
+                None => Vec::new(),
+            };
+
+            Some(PeerIssue {
+                package: req.package.clone(),
+                version: req.version.clone(),
+                peer: req.peer.clone(),
+                range: req.range.clone(),
+                found,
+            })
+        })
+        .collect();
+
+    issues.sort_by(|a, b| (&a.package, &a.version, &a.peer).cmp(&(&b.package, &b.version, &b.peer)));
+    issues
+}
+
+/// Missing required peers, with the range to add for each (first declaration wins)
+pub fn missing_peers(issues: &[PeerIssue]) -> BTreeMap<String, String> {
+    let mut missing = BTreeMap::new();
+    for issue in issues.iter().filter(|i| i.is_missing()) {
+        missing.entry(issue.peer.clone()).or_insert_with(|| issue.range.clone());
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requirement(package: &str, peer: &str, range: &str, optional: bool) -> PeerRequirement {
+        PeerRequirement {
+            package: package.to_string(),
+            version: "1.0.0".to_string(),
+            peer: peer.to_string(),
+            range: range.to_string(),
+            optional,
+        }
+    }
+
+    #[test]
+    fn test_check_peers() {
+        let solution: BTreeMap<String, BTreeSet<semver::Version>> = [
+            ("react".to_string(), [semver::Version::new(17, 0, 2)].into_iter().collect()),
+        ].into_iter().collect();
+
+        let requirements = vec![
+            requirement("react-dom", "react", "^17.0.0", false),
+            requirement("modern-ui", "react", "^18.0.0", false),
+            requirement("styled", "react-is", ">=16", false),
+            requirement("plugin", "typescript", "*", true),
+        ];
+
+        let issues = check_peers(&requirements, &solution);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].package, "modern-ui");
+        assert_eq!(issues[0].found, vec!["17.0.2".to_string()]);
+        assert!(issues[1].is_missing());
+
+        let missing = missing_peers(&issues);
+        assert_eq!(missing.get("react-is").map(String::as_str), Some(">=16"));
+        assert!(!missing.contains_key("react"));
+    }
+}
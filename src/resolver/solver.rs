@@ -0,0 +1,649 @@
+//! Backtracking version solver
+//!
+//! Requirements on a package are grouped into slots by semver-compatible
+//! family (`^17` and `^18` land in different slots), and the solver picks one
+//! version per slot, so incompatible majors can coexist in the tree while
+//! compatible requirements share a single copy. Open-ended ranges such as
+//! `>=16` share a slot of their own that prefers versions already chosen.
+//!
+//! When a slot has no version left that fits, the solver jumps back to the most
+//! recent decision that contributed to the conflict and tries that package's
+//! next candidate (conflict-directed backjumping). If no alternative remains,
+//! it fails with the requirement chains that clash.
+//!
+//! The solver never talks to the registry itself: it asks for metadata with
+//! [`Step::NeedMetadata`] and the caller feeds it back via [`Solver::add_package`].
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::core::{VelocityError, VelocityResult};
+
+use super::version::parse_alias;
+use super::VersionConstraint;
+
+/// Upper bound on candidate attempts before the solver gives up
+const MAX_ATTEMPTS: usize = 100_000;
+
+/// A published version and the requirements it brings in
+#[derive(Debug, Clone)]
+pub struct PackageVersion {
+    pub version: semver::Version,
+    pub dependencies: HashMap<String, String>,
+    pub optional_dependencies: HashMap<String, String>,
+}
+
+/// What the solver needs next
+#[derive(Debug)]
+pub enum Step {
+    /// Metadata for this package is required to continue
+    NeedMetadata(String),
+    /// Every requirement is satisfied; the chosen versions per package
+    Done(BTreeMap<String, BTreeSet<semver::Version>>),
+}
+
+/// Which copy of a package a requirement belongs to
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Slot {
+    /// A semver-compatible family such as `18` or `0.3`
+    Compatible(String),
+    /// Ranges spanning families
+    Open,
+}
+
+/// A package slot: one version is chosen per key
+type Key = (String, Slot);
+
+/// A requirement placed on a slot by the root or by a decided package
+#[derive(Debug, Clone)]
+struct Requirement {
+    constraint: VersionConstraint,
+    spec: String,
+    /// Slot whose decision introduced the requirement (None for package.json)
+    from: Option<Key>,
+    /// Chain of decided packages leading to the requirer
+    chain: Vec<String>,
+    optional: bool,
+}
+
+/// A version chosen for a slot, with the alternatives still untried
+#[derive(Debug)]
+struct Decision {
+    key: Key,
+    version: semver::Version,
+    remaining: Vec<semver::Version>,
+    /// Decisions implicated in failures of earlier candidates
+    conflict: HashSet<Key>,
+    chain: Vec<String>,
+}
+
+/// Backtracking dependency solver
+pub struct Solver {
+    /// Known versions per package, newest first
+    packages: HashMap<String, Vec<PackageVersion>>,
+    /// Dist-tags per package, for requirements such as `next`
+    tags: HashMap<String, HashMap<String, semver::Version>>,
+    /// Packages the registry does not know
+    missing: HashSet<String>,
+    /// Versions to try first when they still fit (e.g. from the lockfile)
+    preferred: HashMap<String, Vec<semver::Version>>,
+    requirements: BTreeMap<Key, Vec<Requirement>>,
+    decisions: Vec<Decision>,
+    /// Optional-only slots that currently cannot be satisfied
+    skipped: HashSet<Key>,
+    /// Description of the most recent dead end, reported if solving fails
+    last_conflict: Option<(String, Vec<String>)>,
+    attempts: usize,
+}
+
+impl Solver {
+    /// Create a solver for the root dependency map
+    pub fn new<'a, I>(root: I) -> VelocityResult<Self>
+    where
+        I: IntoIterator<Item = (&'a String, &'a String)>,
+    {
+        let mut requirements: BTreeMap<Key, Vec<Requirement>> = BTreeMap::new();
+        for (name, spec) in root {
+            let constraint = VersionConstraint::parse(spec)?;
+            requirements.entry(key_for(name, &constraint)).or_default().push(Requirement {
+                constraint,
+                spec: spec.clone(),
+                from: None,
+                chain: Vec::new(),
+                optional: false,
+            });
+        }
+
+        Ok(Self {
+            packages: HashMap::new(),
+            tags: HashMap::new(),
+            missing: HashSet::new(),
+            preferred: HashMap::new(),
+            requirements,
+            decisions: Vec::new(),
+            skipped: HashSet::new(),
+            last_conflict: None,
+            attempts: 0,
+        })
+    }
+
+    /// Try these versions first while they satisfy every requirement
+    pub fn with_preferred<'a, I>(mut self, preferred: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        for (name, version) in preferred {
+            if let Ok(version) = semver::Version::parse(version) {
+                self.preferred.entry(name.to_string()).or_default().push(version);
+            }
+        }
+        self
+    }
+
+    /// Provide the published versions of a package
+    pub fn add_package(&mut self, name: &str, mut versions: Vec<PackageVersion>) {
+        versions.sort_by(|a, b| b.version.cmp(&a.version));
+        self.packages.insert(name.to_string(), versions);
+    }
+
+    /// Provide a package's dist-tags (tag -> version)
+    pub fn add_tags(&mut self, name: &str, tags: &HashMap<String, String>) {
+        let tags = tags
+            .iter()
+            .filter_map(|(tag, version)| Some((tag.clone(), semver::Version::parse(version).ok()?)))
+            .collect();
+        self.tags.insert(name.to_string(), tags);
+    }
+
+    /// Record that a package does not exist in the registry
+    pub fn mark_missing(&mut self, name: &str) {
+        self.missing.insert(name.to_string());
+    }
+
+    /// Check whether every current requirement on a package is optional
+    pub fn is_optional(&self, name: &str) -> bool {
+        self.requirements
+            .iter()
+            .filter(|((n, _), _)| n == name)
+            .all(|(_, reqs)| reqs.iter().all(|r| r.optional))
+    }
+
+    /// Real package a name stands for when it is required through an `npm:` alias
+    pub fn alias_of(&self, name: &str) -> Option<String> {
+        self.requirements
+            .iter()
+            .filter(|((n, _), _)| n == name)
+            .flat_map(|(_, reqs)| reqs.iter())
+            .find_map(|r| parse_alias(&r.spec).map(|(target, _)| target.to_string()))
+    }
+
+    /// Packages whose metadata open slots are waiting on, in name order
+    ///
+    /// Callers fetch these together when [`Step::NeedMetadata`] comes up,
+    /// instead of one request per step.
+    pub fn pending_metadata(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .requirements
+            .iter()
+            .filter(|(key, reqs)| {
+                !reqs.is_empty()
+                    && !self.skipped.contains(*key)
+                    && !self.packages.contains_key(&key.0)
+                    && !self.missing.contains(&key.0)
+            })
+            .map(|(key, _)| key.0.clone())
+            .collect();
+        names.dedup();
+        names
+    }
+
+    /// Advance until metadata is needed, a solution is found, or solving fails
+    pub fn run(&mut self) -> VelocityResult<Step> {
+        loop {
+            let Some(key) = self.next_undecided() else {
+                let mut solution: BTreeMap<String, BTreeSet<semver::Version>> = BTreeMap::new();
+                for decision in &self.decisions {
+                    solution.entry(decision.key.0.clone()).or_default().insert(decision.version.clone());
+                }
+                return Ok(Step::Done(solution));
+            };
+
+            if self.missing.contains(&key.0) {
+                if self.slot_is_optional(&key) {
+                    self.skipped.insert(key);
+                    continue;
+                }
+                return Err(VelocityError::PackageNotFound(key.0));
+            }
+
+            if !self.packages.contains_key(&key.0) {
+                return Ok(Step::NeedMetadata(key.0));
+            }
+
+            let candidates = self.candidates(&key);
+            if candidates.is_empty() {
+                if self.slot_is_optional(&key) {
+                    self.skipped.insert(key);
+                    continue;
+                }
+
+                self.last_conflict = Some((key.0.clone(), self.describe(&key)));
+                let conflict = self.requirers(&key);
+                self.backjump(conflict)?;
+                continue;
+            }
+
+            let chain = self.chain_for(&key);
+            let mut decision = Decision {
+                key,
+                version: candidates[0].clone(),
+                remaining: candidates,
+                conflict: HashSet::new(),
+                chain,
+            };
+
+            if self.advance(&mut decision)? {
+                self.decisions.push(decision);
+            } else {
+                let mut conflict = decision.conflict;
+                conflict.extend(self.requirers(&decision.key));
+                conflict.remove(&decision.key);
+                self.backjump(conflict)?;
+            }
+        }
+    }
+
+    /// Next slot with requirements but no decision, in name order
+    fn next_undecided(&self) -> Option<Key> {
+        self.requirements
+            .iter()
+            .find(|(key, reqs)| {
+                !reqs.is_empty()
+                    && !self.skipped.contains(*key)
+                    && !self.decisions.iter().any(|d| &d.key == *key)
+            })
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Check whether every requirement on a slot is optional
+    fn slot_is_optional(&self, key: &Key) -> bool {
+        self.requirements
+            .get(key)
+            .map(|reqs| reqs.iter().all(|r| r.optional))
+            .unwrap_or(true)
+    }
+
+    /// Versions that satisfy every requirement of a slot, best first
+    ///
+    /// Open slots try versions other slots already chose, so a `>=16` range
+    /// reuses an existing copy; locked versions come next, then newest first.
+    fn candidates(&self, key: &Key) -> Vec<semver::Version> {
+        let reqs = self.requirements.get(key).map(Vec::as_slice).unwrap_or_default();
+        let versions = self.packages.get(&key.0).map(Vec::as_slice).unwrap_or_default();
+
+        let fits = |v: &semver::Version, include_optional: bool| {
+            reqs.iter()
+                .filter(|r| include_optional || !r.optional)
+                .all(|r| self.satisfies(&key.0, &r.constraint, v))
+        };
+
+        // Optional requirements narrow the choice only while they can be met
+        let mut candidates: Vec<semver::Version> = versions
+            .iter()
+            .map(|pv| pv.version.clone())
+            .filter(|v| fits(v, true))
+            .collect();
+        if candidates.is_empty() && !self.slot_is_optional(key) {
+            candidates = versions
+                .iter()
+                .map(|pv| pv.version.clone())
+                .filter(|v| fits(v, false))
+                .collect();
+        }
+
+        let mut first: Vec<semver::Version> = Vec::new();
+        if key.1 == Slot::Open {
+            let mut chosen: Vec<semver::Version> = self
+                .decisions
+                .iter()
+                .filter(|d| d.key.0 == key.0)
+                .map(|d| d.version.clone())
+                .collect();
+            chosen.sort_by(|a, b| b.cmp(a));
+            first.extend(chosen);
+        }
+        if let Some(preferred) = self.preferred.get(&key.0) {
+            first.extend(preferred.iter().cloned());
+        }
+
+        for version in first.into_iter().rev() {
+            if let Some(pos) = candidates.iter().position(|v| *v == version) {
+                let v = candidates.remove(pos);
+                candidates.insert(0, v);
+            }
+        }
+
+        candidates
+    }
+
+    /// Assign the first remaining candidate compatible with current decisions
+    ///
+    /// Returns false when every candidate clashes; the decisions responsible
+    /// are collected in the decision's conflict set.
+    fn advance(&mut self, decision: &mut Decision) -> VelocityResult<bool> {
+        let name = decision.key.0.clone();
+
+        while !decision.remaining.is_empty() {
+            self.attempts += 1;
+            if self.attempts > MAX_ATTEMPTS {
+                return Err(self.failure(Some(format!(
+                    "gave up after {} attempts; pin the packages below to narrow the search",
+                    MAX_ATTEMPTS
+                ))));
+            }
+
+            let version = decision.remaining.remove(0);
+            let package = self.packages[&name]
+                .iter()
+                .find(|pv| pv.version == version)
+                .cloned()
+                .expect("candidate comes from known versions");
+
+            // A required dependency must accept the version already chosen for its slot
+            let mut clashes = Vec::new();
+            for (dep, spec) in &package.dependencies {
+                let constraint = VersionConstraint::parse(spec)?;
+                let dep_key = key_for(dep, &constraint);
+                if let Some(existing) = self.decisions.iter().find(|d| d.key == dep_key) {
+                    if !self.satisfies(dep, &constraint, &existing.version) {
+                        let mut path = vec!["package.json".to_string()];
+                        path.extend(decision.chain.iter().cloned());
+                        path.push(format!("{}@{}", name, version));
+
+                        let mut chains = self.describe(&dep_key);
+                        chains.push(format!("{} requires {}@{}", path.join(" → "), dep, spec));
+                        self.last_conflict = Some((dep.clone(), chains));
+                        clashes.push(dep_key);
+                    }
+                }
+            }
+            if !clashes.is_empty() {
+                decision.conflict.extend(clashes);
+                continue;
+            }
+
+            decision.version = version;
+            let mut chain = decision.chain.clone();
+            chain.push(format!("{}@{}", name, decision.version));
+
+            for (deps, optional) in [(&package.dependencies, false), (&package.optional_dependencies, true)] {
+                for (dep, spec) in deps {
+                    let constraint = VersionConstraint::parse(spec)?;
+                    let dep_key = key_for(dep, &constraint);
+                    if dep_key == decision.key {
+                        continue;
+                    }
+
+                    // An optional dependency that cannot fit is simply left out
+                    if optional {
+                        if let Some(existing) = self.decisions.iter().find(|d| d.key == dep_key) {
+                            if !self.satisfies(dep, &constraint, &existing.version) {
+                                continue;
+                            }
+                        }
+                    }
+
+                    self.skipped.remove(&dep_key);
+                    self.requirements.entry(dep_key).or_default().push(Requirement {
+                        constraint,
+                        spec: spec.clone(),
+                        from: Some(decision.key.clone()),
+                        chain: chain.clone(),
+                        optional,
+                    });
+                }
+            }
+
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Check a version against a constraint, looking dist-tags up for the package
+    fn satisfies(&self, name: &str, constraint: &VersionConstraint, version: &semver::Version) -> bool {
+        match constraint {
+            VersionConstraint::Tag(tag) => match self.tags.get(name).and_then(|tags| tags.get(tag)) {
+                Some(tagged) => tagged == version,
+                // Without a latest tag, latest means any version, newest first
+                None => tag == "latest",
+            },
+            _ => constraint.matches(version),
+        }
+    }
+
+    /// Undo decisions back to the latest one in the conflict set and retry it
+    fn backjump(&mut self, mut conflict: HashSet<Key>) -> VelocityResult<()> {
+        loop {
+            let Some(index) = self.decisions.iter().rposition(|d| conflict.contains(&d.key)) else {
+                return Err(self.failure(None));
+            };
+
+            while self.decisions.len() > index + 1 {
+                let undone = self.decisions.pop().expect("index is in range");
+                self.retract(&undone.key);
+            }
+
+            let mut decision = self.decisions.pop().expect("index is in range");
+            self.retract(&decision.key);
+            self.skipped.clear();
+
+            conflict.remove(&decision.key);
+            decision.conflict.extend(conflict);
+
+            if self.advance(&mut decision)? {
+                self.decisions.push(decision);
+                return Ok(());
+            }
+
+            conflict = decision.conflict;
+            conflict.extend(self.requirers(&decision.key));
+            conflict.remove(&decision.key);
+        }
+    }
+
+    /// Remove the requirements a decision introduced
+    fn retract(&mut self, key: &Key) {
+        for reqs in self.requirements.values_mut() {
+            reqs.retain(|r| r.from.as_ref() != Some(key));
+        }
+    }
+
+    /// Decisions that place required constraints on a slot
+    fn requirers(&self, key: &Key) -> HashSet<Key> {
+        self.requirements
+            .get(key)
+            .into_iter()
+            .flatten()
+            .filter(|r| !r.optional)
+            .filter_map(|r| r.from.clone())
+            .collect()
+    }
+
+    /// Chain through which a slot is first required
+    fn chain_for(&self, key: &Key) -> Vec<String> {
+        self.requirements
+            .get(key)
+            .and_then(|reqs| reqs.iter().find(|r| !r.optional).or(reqs.first()))
+            .map(|r| r.chain.clone())
+            .unwrap_or_default()
+    }
+
+    /// Human-readable requirement chains for a slot
+    fn describe(&self, key: &Key) -> Vec<String> {
+        let chains: BTreeSet<String> = self
+            .requirements
+            .get(key)
+            .into_iter()
+            .flatten()
+            .filter(|r| !r.optional)
+            .map(|r| {
+                let mut path = vec!["package.json".to_string()];
+                path.extend(r.chain.iter().cloned());
+                format!("{} requires {}@{}", path.join(" → "), key.0, r.spec)
+            })
+            .collect();
+        chains.into_iter().collect()
+    }
+
+    /// Error for an unsatisfiable set of requirements
+    fn failure(&self, note: Option<String>) -> VelocityError {
+        let (package, chains) = self
+            .last_conflict
+            .clone()
+            .unwrap_or_else(|| ("dependencies".to_string(), Vec::new()));
+
+        let mut details = chains;
+        if let Some(note) = note {
+            details.push(note);
+        }
+
+        VelocityError::ResolutionConflict {
+            package,
+            details: details.iter().map(|line| format!("\n  {}", line)).collect(),
+        }
+    }
+}
+
+/// Slot a requirement on `name` belongs to
+fn key_for(name: &str, constraint: &VersionConstraint) -> Key {
+    let slot = match constraint.compatible_family() {
+        Some(family) => Slot::Compatible(family),
+        None => Slot::Open,
+    };
+    (name.to_string(), slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(v: &str, deps: &[(&str, &str)]) -> PackageVersion {
+        PackageVersion {
+            version: semver::Version::parse(v).unwrap(),
+            dependencies: deps.iter().map(|(n, s)| (n.to_string(), s.to_string())).collect(),
+            optional_dependencies: HashMap::new(),
+        }
+    }
+
+    fn solve(solver: &mut Solver) -> VelocityResult<BTreeMap<String, BTreeSet<semver::Version>>> {
+        match solver.run()? {
+            Step::Done(solution) => Ok(solution),
+            Step::NeedMetadata(name) => panic!("unexpected metadata request for {}", name),
+        }
+    }
+
+    fn versions(solution: &BTreeMap<String, BTreeSet<semver::Version>>, name: &str) -> Vec<String> {
+        solution[name].iter().map(|v| v.to_string()).collect()
+    }
+
+    fn root(deps: &[(&str, &str)]) -> HashMap<String, String> {
+        deps.iter().map(|(n, s)| (n.to_string(), s.to_string())).collect()
+    }
+
+    #[test]
+    fn test_backtracks_to_older_version() {
+        // a@2 pins shared to 1.0.x, but b needs shared@^1.2, so a must fall back to 1.x
+        let mut solver = Solver::new(&root(&[("a", "*"), ("b", "^1.0.0")])).unwrap();
+        solver.add_package("a", vec![
+            version("1.0.0", &[("shared", "^1.0.0")]),
+            version("2.0.0", &[("shared", "~1.0.0")]),
+        ]);
+        solver.add_package("b", vec![version("1.0.0", &[("shared", "^1.2.0")])]);
+        solver.add_package("shared", vec![
+            version("1.0.0", &[]),
+            version("1.2.0", &[]),
+        ]);
+
+        let solution = solve(&mut solver).unwrap();
+        assert_eq!(versions(&solution, "a"), ["1.0.0"]);
+        assert_eq!(versions(&solution, "shared"), ["1.2.0"]);
+    }
+
+    #[test]
+    fn test_incompatible_majors_coexist() {
+        let mut solver = Solver::new(&root(&[("legacy", "^1.0.0"), ("react", "^18.0.0")])).unwrap();
+        solver.add_package("legacy", vec![version("1.0.0", &[("react", "^17.0.0")])]);
+        solver.add_package("react", vec![
+            version("17.0.2", &[]),
+            version("18.2.0", &[]),
+        ]);
+
+        let solution = solve(&mut solver).unwrap();
+        assert_eq!(versions(&solution, "react"), ["17.0.2", "18.2.0"]);
+    }
+
+    #[test]
+    fn test_reports_conflicting_chains() {
+        let mut solver = Solver::new(&root(&[("a", "^1.0.0"), ("shared", "^1.2.0")])).unwrap();
+        solver.add_package("a", vec![version("1.0.0", &[("shared", "1.0.0")])]);
+        solver.add_package("shared", vec![version("1.0.0", &[]), version("1.2.0", &[])]);
+
+        let message = solve(&mut solver).unwrap_err().to_string();
+        assert!(message.contains("package.json requires shared@^1.2.0"), "{}", message);
+        assert!(message.contains("package.json → a@1.0.0 requires shared@1.0.0"), "{}", message);
+    }
+
+    #[test]
+    fn test_alias_is_solved_under_its_own_name() {
+        let mut solver = Solver::new(&root(&[("react", "^18.0.0"), ("react-17", "npm:react@^17.0.0")])).unwrap();
+        assert_eq!(solver.alias_of("react-17").as_deref(), Some("react"));
+        assert_eq!(solver.alias_of("react"), None);
+
+        let react = vec![version("17.0.2", &[]), version("18.2.0", &[])];
+        solver.add_package("react", react.clone());
+        solver.add_package("react-17", react);
+
+        let solution = solve(&mut solver).unwrap();
+        assert_eq!(versions(&solution, "react"), ["18.2.0"]);
+        assert_eq!(versions(&solution, "react-17"), ["17.0.2"]);
+    }
+
+    #[test]
+    fn test_resolves_dist_tags() {
+        let mut solver = Solver::new(&root(&[("react", "next"), ("typescript", "latest")])).unwrap();
+        solver.add_package("react", vec![version("18.2.0", &[]), version("19.0.0-rc.1", &[])]);
+        solver.add_tags("react", &HashMap::from([
+            ("latest".to_string(), "18.2.0".to_string()),
+            ("next".to_string(), "19.0.0-rc.1".to_string()),
+        ]));
+        solver.add_package("typescript", vec![version("5.4.0", &[]), version("5.5.0-beta", &[])]);
+        solver.add_tags("typescript", &HashMap::from([("latest".to_string(), "5.4.0".to_string())]));
+
+        let solution = solve(&mut solver).unwrap();
+        assert_eq!(versions(&solution, "react"), ["19.0.0-rc.1"]);
+        assert_eq!(versions(&solution, "typescript"), ["5.4.0"]);
+    }
+
+    #[test]
+    fn test_requests_metadata_and_prefers_locked() {
+        let mut solver = Solver::new(&root(&[("a", "^1.0.0")]))
+            .unwrap()
+            .with_preferred([("a", "1.1.0")]);
+
+        assert!(matches!(solver.run().unwrap(), Step::NeedMetadata(ref n) if n == "a"));
+        solver.add_package("a", vec![version("1.1.0", &[]), version("1.2.0", &[])]);
+
+        assert_eq!(versions(&solve(&mut solver).unwrap(), "a"), ["1.1.0"]);
+    }
+
+    #[test]
+    fn test_pending_metadata_covers_every_open_slot() {
+        let mut solver = Solver::new(&root(&[("a", "^1.0.0"), ("b", "^1.0.0")])).unwrap();
+        assert_eq!(solver.pending_metadata(), ["a", "b"]);
+
+        solver.add_package("a", vec![version("1.0.0", &[("c", "^1.0.0"), ("d", "^2.0.0")])]);
+        solver.add_package("b", vec![version("1.0.0", &[("c", "^2.0.0")])]);
+        assert!(matches!(solver.run().unwrap(), Step::NeedMetadata(ref n) if n == "c"));
+        assert_eq!(solver.pending_metadata(), ["c", "d"]);
+    }
+}
@@ -0,0 +1,171 @@
+//! Dependencies on a package tarball instead of a registry range
+//!
+//! `file:libs/foo-1.2.3.tgz` names a tarball on disk, relative to the
+//! package.json declaring it; an `http(s)://` URL names one to download. Either
+//! way the tarball's own package.json stands in for registry metadata, with a
+//! single version whose dist points back at the spec.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::core::{VelocityError, VelocityResult};
+use crate::installer::extractor::tarball_integrity;
+use crate::registry::types::{PackageMetadata, VersionMetadata};
+use crate::registry::RegistryClient;
+
+/// A tarball read for resolution
+#[derive(Debug, Clone)]
+pub struct TarballPackage {
+    /// The tarball's bytes
+    pub data: Vec<u8>,
+
+    /// The package.json inside, with `dist` pointing at the spec and giving the sha512 integrity
+    pub manifest: VersionMetadata,
+}
+
+impl TarballPackage {
+    /// Read the tarball `spec` names; `file:` paths are relative to `dir`
+    pub async fn load(spec: &str, dir: &Path, registry: &RegistryClient) -> VelocityResult<Self> {
+        let data = match local_path(spec, dir) {
+            Some(path) => std::fs::read(&path)
+                .map_err(|e| VelocityError::other(format!("Cannot read tarball {}: {}", path.display(), e)))?,
+            None => registry.get_tarball(spec).await?,
+        };
+        Self::from_bytes(spec, data)
+    }
+
+    /// Parse tarball bytes downloaded or read for `spec`
+    pub fn from_bytes(spec: &str, data: Vec<u8>) -> VelocityResult<Self> {
+        let integrity = tarball_integrity(&data);
+        let mut manifest = read_manifest(&data)
+            .map_err(|e| VelocityError::other(format!("Invalid package tarball {}: {}", spec, e)))?;
+        manifest["dist"] = serde_json::json!({ "tarball": spec, "integrity": integrity });
+        let manifest: VersionMetadata = serde_json::from_value(manifest)
+            .map_err(|e| VelocityError::other(format!("Invalid package.json in {}: {}", spec, e)))?;
+        if semver::Version::parse(&manifest.version).is_err() {
+            return Err(VelocityError::other(format!(
+                "{} has version '{}', which is not valid semver",
+                spec, manifest.version
+            )));
+        }
+        Ok(Self { data, manifest })
+    }
+
+    /// Registry-style metadata offering just this version, under the name it is installed as
+    pub fn metadata(&self, name: &str) -> PackageMetadata {
+        let version = self.manifest.version.clone();
+        PackageMetadata {
+            name: name.to_string(),
+            description: String::new(),
+            dist_tags: HashMap::from([("latest".to_string(), version.clone())]),
+            versions: HashMap::from([(version, self.manifest.clone())]),
+            time: HashMap::new(),
+        }
+    }
+}
+
+/// Hosts whose plain repository URLs are git dependencies rather than tarballs
+const GIT_HOSTS: &[&str] = &["github.com", "gitlab.com", "bitbucket.org", "gist.github.com"];
+
+/// Whether a dependency spec names a tarball rather than a registry range
+///
+/// Like npm, an http(s) URL is a tarball unless it points at a git host or
+/// ends in `.git`; a tarball's extension wins either way.
+pub fn is_tarball_spec(spec: &str) -> bool {
+    if let Some(path) = spec.strip_prefix("file:") {
+        return is_tarball_path(path);
+    }
+    let Ok(url) = url::Url::parse(spec) else {
+        return false;
+    };
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    if is_tarball_path(url.path()) {
+        return true;
+    }
+    let git_host = url.host_str().is_some_and(|host| GIT_HOSTS.contains(&host.trim_start_matches("www.")));
+    !git_host && !url.path().ends_with(".git")
+}
+
+/// Whether a path has a tarball's extension
+pub fn is_tarball_path(path: &str) -> bool {
+    path.ends_with(".tgz") || path.ends_with(".tar.gz")
+}
+
+/// Path of a `file:` spec's tarball, relative to `dir`; `None` for URLs
+pub fn local_path(spec: &str, dir: &Path) -> Option<PathBuf> {
+    let path = spec.strip_prefix("file:")?;
+    Some(dir.join(path.strip_prefix("//").unwrap_or(path)))
+}
+
+/// The `package/package.json` of an npm tarball
+fn read_manifest(data: &[u8]) -> VelocityResult<serde_json::Value> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(data));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        // npm packs everything under one top-level directory, usually `package/`
+        let mut components = path.components();
+        let top_level = components.next().is_some() && components.as_path() == Path::new("package.json");
+        if top_level && entry.header().entry_type().is_file() {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            return Ok(serde_json::from_str(&content)?);
+        }
+    }
+    Err(VelocityError::other("no package.json at the top of the tarball"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+
+    fn tarball(manifest: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "package/package.json", manifest.as_bytes()).unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_tarball_specs() {
+        assert!(is_tarball_spec("file:libs/foo-1.2.3.tgz"));
+        assert!(is_tarball_spec("file:../foo.tar.gz"));
+        assert!(is_tarball_spec("https://example.com/pkg.tgz"));
+        assert!(is_tarball_spec("https://example.com/download?package=pkg"));
+        assert!(is_tarball_spec("https://github.com/u/r/archive/refs/tags/v1.0.0.tar.gz"));
+        assert!(!is_tarball_spec("https://github.com/u/r"));
+        assert!(!is_tarball_spec("https://gitlab.example/u/r.git"));
+        assert!(!is_tarball_spec("git+https://example.com/r.tgz"));
+        assert!(!is_tarball_spec("file:../foo"));
+        assert!(!is_tarball_spec("^1.2.3"));
+        assert!(!is_tarball_spec("npm:foo@1"));
+
+        let dir = Path::new("/project");
+        assert_eq!(local_path("file:libs/foo.tgz", dir), Some(dir.join("libs/foo.tgz")));
+        assert_eq!(local_path("https://example.com/pkg.tgz", dir), None);
+    }
+
+    #[test]
+    fn test_tarball_metadata() {
+        let data = tarball(r#"{"name": "foo", "version": "1.2.3", "dependencies": {"ms": "^2.1.0"}, "scripts": {"postinstall": "node setup.js"}}"#);
+        let package = TarballPackage::from_bytes("file:libs/foo-1.2.3.tgz", data).unwrap();
+        assert_eq!(package.manifest.dist.tarball, "file:libs/foo-1.2.3.tgz");
+        assert!(package.manifest.dist.integrity.as_deref().is_some_and(|i| i.starts_with("sha512-")));
+        assert!(package.manifest.has_install_scripts());
+
+        let metadata = package.metadata("my-foo");
+        assert_eq!(metadata.name, "my-foo");
+        assert_eq!(metadata.dist_tags["latest"], "1.2.3");
+        assert_eq!(metadata.versions["1.2.3"].dependencies["ms"], "^2.1.0");
+
+        let bad = tarball(r#"{"name": "foo", "version": "next"}"#);
+        assert!(TarballPackage::from_bytes("file:foo.tgz", bad).is_err());
+        assert!(TarballPackage::from_bytes("file:foo.tgz", b"not a tarball".to_vec()).is_err());
+    }
+}
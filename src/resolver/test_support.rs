@@ -0,0 +1,21 @@
+//! Fixtures for tests that work on resolved packages
+
+use std::collections::HashMap;
+
+use super::ResolvedPackage;
+
+/// A registry package `name@version` depending on `deps` as (name, range) pairs
+pub(crate) fn package(name: &str, version: &str, deps: &[(&str, &str)]) -> ResolvedPackage {
+    ResolvedPackage {
+        name: name.to_string(),
+        alias_of: None,
+        version: version.to_string(),
+        tarball_url: String::new(),
+        integrity: String::new(),
+        dependencies: deps.iter().map(|(n, s)| (n.to_string(), s.to_string())).collect(),
+        peer_dependencies: HashMap::new(),
+        optional_dependencies: HashMap::new(),
+        locked_dependencies: HashMap::new(),
+        has_scripts: false,
+    }
+}
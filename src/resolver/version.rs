@@ -2,7 +2,7 @@
 
 use crate::core::{VelocityError, VelocityResult};
 
-/// A version constraint (e.g., ^1.0.0, ~2.1.0, >=3.0.0)
+/// A version constraint (e.g., ^1.0.0, ~2.1.0, >=3.0.0 <4.0.0 || 5.x)
 #[derive(Debug, Clone)]
 pub enum VersionConstraint {
     /// Exact version (1.0.0)
@@ -21,19 +21,26 @@ pub enum VersionConstraint {
     LessThan(semver::Version),
     /// Any version (*)
     Any,
-    /// Range (>=1.0.0 <2.0.0)
-    Range(Box<VersionConstraint>, Box<VersionConstraint>),
-    /// Latest tag
-    Latest,
+    /// Comparator set; a version must satisfy all of them (>=1.0.0 <2.0.0)
+    Range(Vec<VersionConstraint>),
+    /// Union of ranges; a version must satisfy one of them (^1.0.0 || ^2.0.0)
+    Or(Vec<VersionConstraint>),
+    /// A dist-tag such as `latest`, `next` or `beta`; only the solver knows
+    /// which version it points at, so [`matches`](Self::matches) accepts any
+    Tag(String),
 }
 
 impl VersionConstraint {
     /// Parse a version constraint string
+    ///
+    /// Follows npm's range grammar: `||` unions of whitespace-separated
+    /// comparator sets, hyphen ranges, and x-ranges or partial versions
+    /// anywhere a version may appear (`1.x`, `>=1.2`, `^0.0`, `1 - 2.3`).
     pub fn parse(s: &str) -> VelocityResult<Self> {
         let s = s.trim();
 
         // Handle special cases
-        if s.is_empty() || s == "*" || s == "latest" {
+        if s.is_empty() || s == "*" {
             return Ok(VersionConstraint::Any);
         }
 
@@ -42,146 +49,333 @@ impl VersionConstraint {
             return Ok(VersionConstraint::Any);
         }
 
-        // Handle npm/file/git protocols
-        if s.starts_with("npm:") || s.starts_with("file:") || s.starts_with("git") || s.contains("://") {
-            return Ok(VersionConstraint::Any);
+        // An alias is constrained by the range it gives the real package
+        if let Some((_, range)) = parse_alias(s) {
+            return Self::parse(range);
         }
 
-        // Handle x-ranges (1.x, 1.0.x)
-        if s.contains('x') || s.contains('X') {
-            let cleaned = s.replace('x', "0").replace('X', "0");
-            if let Ok(v) = semver::Version::parse(&cleaned) {
-                return Ok(VersionConstraint::Caret(v));
-            }
+        // Handle file/git protocols
+        if s.starts_with("file:") || s.starts_with("git") || s.contains("://") {
+            return Ok(VersionConstraint::Any);
         }
 
-        // Handle range with space (>=1.0.0 <2.0.0)
-        if s.contains(' ') {
-            let parts: Vec<&str> = s.split_whitespace().collect();
-            if parts.len() == 2 {
-                let left = Self::parse(parts[0])?;
-                let right = Self::parse(parts[1])?;
-                return Ok(VersionConstraint::Range(Box::new(left), Box::new(right)));
+        match Self::parse_range(s) {
+            Ok(constraint) => Ok(constraint),
+            Err(_) if is_dist_tag(s) => Ok(VersionConstraint::Tag(s.to_string())),
+            Err(_) => {
+                // Fallback to Any for unparseable constraints
+                tracing::warn!("Could not parse version constraint: {}, treating as any", s);
+                Ok(VersionConstraint::Any)
             }
         }
+    }
 
-        // Handle || (or)
-        if s.contains("||") {
-            // For simplicity, use the first constraint
-            let first = s.split("||").next().unwrap().trim();
-            return Self::parse(first);
+    /// Parse a plain version range, failing on anything else
+    ///
+    /// Unlike [`parse`](Self::parse), which reads dependency specs and treats
+    /// protocols, tags and unparseable input as any version, this is for
+    /// configuration where a misread range would silently widen a rule.
+    pub fn parse_strict(s: &str) -> VelocityResult<Self> {
+        match s.trim() {
+            "*" => Ok(VersionConstraint::Any),
+            s => Self::parse_range(s),
         }
+    }
 
-        // Handle hyphen range (1.0.0 - 2.0.0)
-        if s.contains(" - ") {
-            let parts: Vec<&str> = s.split(" - ").collect();
-            if parts.len() == 2 {
-                let left = Self::parse_version(parts[0].trim())?;
-                let right = Self::parse_version(parts[1].trim())?;
-                return Ok(VersionConstraint::Range(
-                    Box::new(VersionConstraint::GreaterOrEqual(left)),
-                    Box::new(VersionConstraint::LessOrEqual(right)),
-                ));
+    /// Parse a `||` union of comparator sets
+    fn parse_range(s: &str) -> VelocityResult<Self> {
+        let mut alternatives = Vec::new();
+        for set in s.split("||") {
+            match Self::parse_set(set)? {
+                // One alternative admitting everything makes the rest moot
+                VersionConstraint::Any => return Ok(VersionConstraint::Any),
+                constraint => alternatives.push(constraint),
             }
         }
+        Ok(match alternatives.len() {
+            1 => alternatives.remove(0),
+            _ => VersionConstraint::Or(alternatives),
+        })
+    }
 
-        // Handle prefix operators
-        if let Some(rest) = s.strip_prefix(">=") {
-            let v = Self::parse_version(rest.trim())?;
-            return Ok(VersionConstraint::GreaterOrEqual(v));
+    /// Parse a comparator set: a hyphen range or whitespace-separated comparators
+    fn parse_set(s: &str) -> VelocityResult<Self> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        if let [from, "-", to] = tokens.as_slice() {
+            return Ok(Self::hyphen(Partial::parse(from)?, Partial::parse(to)?));
         }
 
-        if let Some(rest) = s.strip_prefix("<=") {
-            let v = Self::parse_version(rest.trim())?;
-            return Ok(VersionConstraint::LessOrEqual(v));
+        // Operators may be separated from their version (`>= 1.2.3`)
+        let mut comparators = Vec::new();
+        let mut tokens = tokens.into_iter();
+        while let Some(token) = tokens.next() {
+            let comparator = if token.chars().all(|c| matches!(c, '<' | '>' | '=' | '~' | '^')) {
+                let version = tokens
+                    .next()
+                    .ok_or_else(|| VelocityError::InvalidVersionConstraint(s.trim().to_string()))?;
+                Self::parse_comparator(&format!("{}{}", token, version))?
+            } else {
+                Self::parse_comparator(token)?
+            };
+            comparators.push(comparator);
         }
+        Ok(all_of(comparators))
+    }
 
-        if let Some(rest) = s.strip_prefix('>') {
-            let v = Self::parse_version(rest.trim())?;
-            return Ok(VersionConstraint::GreaterThan(v));
-        }
+    /// Parse a single comparator such as `^1.2`, `>=2.0.0-rc.1` or `1.x`
+    fn parse_comparator(s: &str) -> VelocityResult<Self> {
+        let (operator, rest) = ["~>", ">=", "<=", ">", "<", "=", "~", "^"]
+            .iter()
+            .find_map(|op| s.strip_prefix(op).map(|rest| (*op, rest)))
+            .unwrap_or(("", s));
+        let partial = Partial::parse(rest)?;
+        let floor = partial.floor();
 
-        if let Some(rest) = s.strip_prefix('<') {
-            let v = Self::parse_version(rest.trim())?;
-            return Ok(VersionConstraint::LessThan(v));
-        }
+        let Some(major) = partial.major else {
+            // `*` or `x`: anything, except `<*` and `>*`, which admit nothing
+            return Ok(match operator {
+                "<" | ">" => VersionConstraint::LessThan(semver::Version::new(0, 0, 0)),
+                _ => VersionConstraint::Any,
+            });
+        };
 
-        if let Some(rest) = s.strip_prefix('^') {
-            let v = Self::parse_version(rest.trim())?;
-            return Ok(VersionConstraint::Caret(v));
-        }
+        Ok(match (operator, partial.minor, partial.patch) {
+            (">=", _, _) => VersionConstraint::GreaterOrEqual(floor),
+            ("<", _, _) => VersionConstraint::LessThan(floor),
+            (">", _, Some(_)) => VersionConstraint::GreaterThan(floor),
+            (">", _, None) | ("<=", _, None) => {
+                let next = partial.next();
+                if operator == ">" {
+                    VersionConstraint::GreaterOrEqual(next)
+                } else {
+                    VersionConstraint::LessThan(next)
+                }
+            }
+            ("<=", _, Some(_)) => VersionConstraint::LessOrEqual(floor),
+            ("" | "=", Some(_), Some(_)) => VersionConstraint::Exact(floor),
+            ("~" | "~>", Some(_), _) | ("" | "=", Some(_), None) => VersionConstraint::Tilde(floor),
+            ("^", Some(_), Some(_)) => VersionConstraint::Caret(floor),
+            // ^0.0 is >=0.0.0 <0.1.0
+            ("^", Some(0), None) if major == 0 => VersionConstraint::Tilde(floor),
+            ("^", Some(_), None) => VersionConstraint::Caret(floor),
+            // A bare major allows the whole major, whatever the operator
+            _ if major == 0 => all_of(vec![
+                VersionConstraint::GreaterOrEqual(floor),
+                VersionConstraint::LessThan(partial.next()),
+            ]),
+            _ => VersionConstraint::Caret(floor),
+        })
+    }
 
-        if let Some(rest) = s.strip_prefix('~') {
-            let v = Self::parse_version(rest.trim())?;
-            return Ok(VersionConstraint::Tilde(v));
+    /// Hyphen range: partial bounds cover everything they leave open (`1 - 2.3` is `>=1.0.0 <2.4.0`)
+    fn hyphen(from: Partial, to: Partial) -> Self {
+        let mut bounds = Vec::new();
+        if from.major.is_some() {
+            bounds.push(VersionConstraint::GreaterOrEqual(from.floor()));
         }
-
-        if let Some(rest) = s.strip_prefix('=') {
-            let v = Self::parse_version(rest.trim())?;
-            return Ok(VersionConstraint::Exact(v));
+        if to.major.is_some() {
+            bounds.push(if to.patch.is_some() {
+                VersionConstraint::LessOrEqual(to.floor())
+            } else {
+                VersionConstraint::LessThan(to.next())
+            });
         }
+        all_of(bounds)
+    }
 
-        // Try parsing as exact version
-        match Self::parse_version(s) {
-            Ok(v) => Ok(VersionConstraint::Exact(v)),
-            Err(_) => {
-                // Fallback to Any for unparseable constraints
-                tracing::warn!("Could not parse version constraint: {}, treating as any", s);
-                Ok(VersionConstraint::Any)
+    /// The semver-compatible family this constraint is confined to, if any
+    ///
+    /// `^18.2.0`, `~18.2.0` and `18.2.1` all stay within `18`; open ranges
+    /// such as `>=16` or `*` span families and return None.
+    pub fn compatible_family(&self) -> Option<String> {
+        match self {
+            // ~0.0.z admits later 0.0.x patches, each a family of its own
+            VersionConstraint::Tilde(v) if v.major == 0 && v.minor == 0 => None,
+            VersionConstraint::Exact(v) | VersionConstraint::Caret(v) | VersionConstraint::Tilde(v) => {
+                Some(compatible_family(v))
+            }
+            // An intersection stays within any family one of its comparators does
+            VersionConstraint::Range(comparators) => comparators.iter().find_map(|c| c.compatible_family()),
+            VersionConstraint::Or(alternatives) => {
+                let family = alternatives.first()?.compatible_family()?;
+                alternatives
+                    .iter()
+                    .all(|alternative| alternative.compatible_family().as_ref() == Some(&family))
+                    .then_some(family)
             }
+            _ => None,
         }
     }
 
-    /// Parse a version string, handling partial versions
-    fn parse_version(s: &str) -> VelocityResult<semver::Version> {
-        let s = s.trim().trim_start_matches('v');
-
-        // Handle partial versions
-        let parts: Vec<&str> = s.split('.').collect();
-        let version_str = match parts.len() {
-            1 => format!("{}.0.0", parts[0]),
-            2 => format!("{}.{}.0", parts[0], parts[1]),
-            _ => s.to_string(),
-        };
-
-        // Remove any pre-release or build metadata for initial parse
-        let base_version = version_str.split('-').next().unwrap_or(&version_str);
-        let base_version = base_version.split('+').next().unwrap_or(base_version);
-
-        semver::Version::parse(base_version)
-            .or_else(|_| semver::Version::parse(&version_str))
-            .map_err(|_| VelocityError::InvalidVersionConstraint(s.to_string()))
-    }
-
     /// Check if a version matches this constraint
+    ///
+    /// Follows npm: a pre-release only matches when the constraint itself
+    /// names a pre-release of the same major.minor.patch, so `^1.0.0-beta.3`
+    /// admits 1.0.0-beta.4 but neither `^1.0.0` nor `*` admits 2.0.0-rc.1.
+    /// Each alternative of a `||` union is judged on its own.
     pub fn matches(&self, version: &semver::Version) -> bool {
         match self {
-            VersionConstraint::Exact(v) => version == v,
+            VersionConstraint::Tag(_) => true,
+            VersionConstraint::Or(alternatives) => alternatives.iter().any(|alternative| alternative.matches(version)),
+            _ => (version.pre.is_empty() || self.allows_prerelease_of(version)) && self.contains(version),
+        }
+    }
+
+    /// Whether the version lies within the constraint's bounds, pre-release gating aside
+    fn contains(&self, version: &semver::Version) -> bool {
+        let release = (version.major, version.minor, version.patch);
+        match self {
+            VersionConstraint::Exact(v) => version.cmp_precedence(v).is_eq(),
             VersionConstraint::Caret(v) => {
-                if v.major == 0 {
-                    if v.minor == 0 {
-                        // ^0.0.x -> >=0.0.x <0.0.(x+1)
-                        version.major == 0 && version.minor == 0 && version.patch == v.patch
-                    } else {
-                        // ^0.y.z -> >=0.y.z <0.(y+1).0
-                        version.major == 0 && version.minor == v.minor && version.patch >= v.patch
-                    }
-                } else {
-                    // ^x.y.z -> >=x.y.z <(x+1).0.0
-                    version.major == v.major && version >= v
-                }
+                // ^x.y.z -> <(x+1).0.0, ^0.y.z -> <0.(y+1).0, ^0.0.z -> <0.0.(z+1)
+                let upper = match (v.major, v.minor) {
+                    (0, 0) => (0, 0, v.patch + 1),
+                    (0, minor) => (0, minor + 1, 0),
+                    (major, _) => (major + 1, 0, 0),
+                };
+                version >= v && release < upper
             }
             VersionConstraint::Tilde(v) => {
                 // ~x.y.z -> >=x.y.z <x.(y+1).0
-                version.major == v.major && version.minor == v.minor && version.patch >= v.patch
+                version >= v && release < (v.major, v.minor + 1, 0)
             }
             VersionConstraint::GreaterOrEqual(v) => version >= v,
             VersionConstraint::GreaterThan(v) => version > v,
             VersionConstraint::LessOrEqual(v) => version <= v,
             VersionConstraint::LessThan(v) => version < v,
-            VersionConstraint::Any | VersionConstraint::Latest => true,
-            VersionConstraint::Range(left, right) => left.matches(version) && right.matches(version),
+            VersionConstraint::Any | VersionConstraint::Tag(_) => true,
+            VersionConstraint::Range(comparators) => comparators.iter().all(|c| c.contains(version)),
+            VersionConstraint::Or(alternatives) => alternatives.iter().any(|a| a.contains(version)),
+        }
+    }
+
+    /// Whether any bound names a pre-release of the version's major.minor.patch
+    fn allows_prerelease_of(&self, version: &semver::Version) -> bool {
+        match self {
+            VersionConstraint::Exact(v)
+            | VersionConstraint::Caret(v)
+            | VersionConstraint::Tilde(v)
+            | VersionConstraint::GreaterOrEqual(v)
+            | VersionConstraint::GreaterThan(v)
+            | VersionConstraint::LessOrEqual(v)
+            | VersionConstraint::LessThan(v) => {
+                !v.pre.is_empty() && (v.major, v.minor, v.patch) == (version.major, version.minor, version.patch)
+            }
+            VersionConstraint::Range(constraints) | VersionConstraint::Or(constraints) => {
+                constraints.iter().any(|c| c.allows_prerelease_of(version))
+            }
+            VersionConstraint::Any | VersionConstraint::Tag(_) => false,
+        }
+    }
+}
+
+/// Intersection of comparators, dropping those that admit anything
+fn all_of(comparators: Vec<VersionConstraint>) -> VersionConstraint {
+    let mut comparators: Vec<_> =
+        comparators.into_iter().filter(|c| !matches!(c, VersionConstraint::Any)).collect();
+    match comparators.len() {
+        0 => VersionConstraint::Any,
+        1 => comparators.remove(0),
+        _ => VersionConstraint::Range(comparators),
+    }
+}
+
+/// A possibly partial version (`1`, `1.2`, `1.x`, `1.2.3-beta.1`); missing or
+/// wildcard parts are None, and so is every part after them
+#[derive(Debug, Clone, Default)]
+struct Partial {
+    major: Option<u64>,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: semver::Prerelease,
+}
+
+impl Partial {
+    fn parse(s: &str) -> VelocityResult<Self> {
+        let invalid = || VelocityError::InvalidVersionConstraint(s.to_string());
+        let trimmed = s.trim().trim_start_matches('=').trim_start_matches('v');
+
+        // Build metadata never affects matching
+        let trimmed = trimmed.split('+').next().unwrap_or(trimmed);
+        let (release, pre) = match trimmed.split_once('-') {
+            Some((release, pre)) => (release, Some(pre)),
+            None => (trimmed, None),
+        };
+
+        let mut parts = release.split('.');
+        let mut next = || -> VelocityResult<Option<u64>> {
+            match parts.next() {
+                None | Some("x" | "X" | "*") => Ok(None),
+                Some(part) => part.parse().map(Some).map_err(|_| invalid()),
+            }
+        };
+        let mut partial = Partial { major: next()?, minor: next()?, patch: next()?, ..Default::default() };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        if partial.major.is_none() {
+            partial.minor = None;
         }
+        if partial.minor.is_none() {
+            partial.patch = None;
+        }
+
+        if let Some(pre) = pre {
+            // Only a full version can carry a pre-release
+            if partial.patch.is_none() {
+                return Err(invalid());
+            }
+            partial.pre = semver::Prerelease::new(pre).map_err(|_| invalid())?;
+        }
+        Ok(partial)
+    }
+
+    /// Lowest version the partial covers (`1.2` is 1.2.0)
+    fn floor(&self) -> semver::Version {
+        let mut version = semver::Version::new(
+            self.major.unwrap_or(0),
+            self.minor.unwrap_or(0),
+            self.patch.unwrap_or(0),
+        );
+        version.pre = self.pre.clone();
+        version
+    }
+
+    /// First version past what the partial covers (`1` is 2.0.0, `1.2` is 1.3.0)
+    fn next(&self) -> semver::Version {
+        match (self.major.unwrap_or(0), self.minor, self.patch) {
+            (major, None, _) => semver::Version::new(major + 1, 0, 0),
+            (major, Some(minor), None) => semver::Version::new(major, minor + 1, 0),
+            (major, Some(minor), Some(patch)) => semver::Version::new(major, minor, patch + 1),
+        }
+    }
+}
+
+/// Whether a spec is shaped like a dist-tag rather than a version range
+fn is_dist_tag(spec: &str) -> bool {
+    spec.starts_with(|c: char| c.is_ascii_alphabetic())
+        && spec.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Split an `npm:<package>@<range>` alias spec into the real package and its range
+///
+/// A bare `npm:<package>` allows any version.
+pub fn parse_alias(spec: &str) -> Option<(&str, &str)> {
+    let target = spec.trim().strip_prefix("npm:")?;
+    // Skip a scope's leading '@' when looking for the version separator
+    let (name, range) = match target[1.min(target.len())..].find('@') {
+        Some(at) => (&target[..at + 1], &target[at + 2..]),
+        None => (target, "*"),
+    };
+    (!name.is_empty()).then_some((name, range))
+}
+
+/// Semver-compatible family of a version: the major, or the first non-zero part below 1.0
+pub fn compatible_family(version: &semver::Version) -> String {
+    match (version.major, version.minor) {
+        (0, 0) => format!("0.0.{}", version.patch),
+        (0, minor) => format!("0.{}", minor),
+        (major, _) => major.to_string(),
     }
 }
 
@@ -196,10 +390,21 @@ impl std::fmt::Display for VersionConstraint {
             VersionConstraint::LessOrEqual(v) => write!(f, "<={}", v),
             VersionConstraint::LessThan(v) => write!(f, "<{}", v),
             VersionConstraint::Any => write!(f, "*"),
-            VersionConstraint::Latest => write!(f, "latest"),
-            VersionConstraint::Range(l, r) => write!(f, "{} {}", l, r),
+            VersionConstraint::Tag(tag) => write!(f, "{}", tag),
+            VersionConstraint::Range(comparators) => write_joined(f, comparators, " "),
+            VersionConstraint::Or(alternatives) => write_joined(f, alternatives, " || "),
+        }
+    }
+}
+
+fn write_joined(f: &mut std::fmt::Formatter<'_>, constraints: &[VersionConstraint], separator: &str) -> std::fmt::Result {
+    for (i, constraint) in constraints.iter().enumerate() {
+        if i > 0 {
+            f.write_str(separator)?;
         }
+        write!(f, "{}", constraint)?;
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -230,6 +435,122 @@ mod tests {
         assert!(!c.matches(&v3));
     }
 
+    #[test]
+    fn test_compatible_family() {
+        let family = |s: &str| VersionConstraint::parse(s).unwrap().compatible_family();
+
+        assert_eq!(family("^18.2.0").as_deref(), Some("18"));
+        assert_eq!(family("~0.3.1").as_deref(), Some("0.3"));
+        assert_eq!(family("1.0.0").as_deref(), Some("1"));
+        assert_eq!(family(">=16.0.0"), None);
+        assert_eq!(family("*"), None);
+    }
+
+    #[test]
+    fn test_parse_alias() {
+        assert_eq!(parse_alias("npm:react@^18.0.0"), Some(("react", "^18.0.0")));
+        assert_eq!(parse_alias("npm:@types/node@20"), Some(("@types/node", "20")));
+        assert_eq!(parse_alias("npm:lodash"), Some(("lodash", "*")));
+        assert_eq!(parse_alias("^18.0.0"), None);
+
+        let c = VersionConstraint::parse("npm:react@^18.0.0").unwrap();
+        assert!(c.matches(&semver::Version::new(18, 2, 0)));
+        assert!(!c.matches(&semver::Version::new(17, 0, 2)));
+    }
+
+    #[test]
+    fn test_parse_dist_tag() {
+        assert!(matches!(VersionConstraint::parse("next").unwrap(), VersionConstraint::Tag(ref t) if t == "next"));
+        assert!(matches!(VersionConstraint::parse("latest").unwrap(), VersionConstraint::Tag(ref t) if t == "latest"));
+        assert!(matches!(VersionConstraint::parse("npm:typescript@beta").unwrap(), VersionConstraint::Tag(ref t) if t == "beta"));
+        assert!(!matches!(VersionConstraint::parse("1.x").unwrap(), VersionConstraint::Tag(_)));
+        assert_eq!(VersionConstraint::parse("next").unwrap().compatible_family(), None);
+    }
+
+    #[test]
+    fn test_prerelease_matching() {
+        let v = |s: &str| semver::Version::parse(s).unwrap();
+        let matches = |c: &str, version: &str| VersionConstraint::parse(c).unwrap().matches(&v(version));
+
+        assert!(matches("^1.0.0-beta.3", "1.0.0-beta.4"));
+        assert!(matches("^1.0.0-beta.3", "1.0.0"));
+        assert!(matches("^1.0.0-beta.3", "1.5.0"));
+        assert!(!matches("^1.0.0-beta.3", "1.0.0-beta.2"));
+        assert!(!matches("^1.0.0-beta.3", "1.1.0-beta.1"));
+        assert!(!matches("^1.0.0", "2.0.0-rc.1"));
+        assert!(!matches("^1.0.0", "1.2.0-rc.1"));
+        assert!(!matches("*", "2.0.0-rc.1"));
+        assert!(!matches(">=1.0.0", "2.0.0-rc.1"));
+        assert!(matches(">=2.0.0-rc.1 <3.0.0", "2.0.0-rc.2"));
+        assert!(matches("~2.0.0-rc.1", "2.0.0-rc.3"));
+        assert!(matches("2.0.0-rc.1", "2.0.0-rc.1"));
+        assert!(!matches("2.0.0-rc.1", "2.0.0-rc.2"));
+        assert!(!matches("<2.0.0", "2.0.0-rc.1"));
+        assert!(matches("^0.2.0-alpha", "0.2.0-alpha.1"));
+        assert!(!matches("^0.2.0-alpha", "0.3.0"));
+    }
+
+    #[test]
+    fn test_npm_range_grammar() {
+        let v = |s: &str| semver::Version::parse(s).unwrap();
+        let matches = |c: &str, version: &str| VersionConstraint::parse(c).unwrap().matches(&v(version));
+
+        // Cases from npm's semver range tests
+        let cases: &[(&str, &[&str], &[&str])] = &[
+            ("^1.0.0 || ^2.0.0", &["1.4.0", "2.9.9"], &["0.9.0", "3.0.0"]),
+            ("1.2.7 || >=1.2.9 <2.0.0", &["1.2.7", "1.2.9", "1.4.6"], &["1.2.8", "2.0.0"]),
+            ("<1.0.0 || >=3.0.0 <3.1.0 || 5.x", &["0.5.0", "3.0.4", "5.8.0"], &["1.0.0", "3.1.0", "6.0.0"]),
+            (">=1.2.3 <1.5.0 >1.3.0", &["1.3.1", "1.4.9"], &["1.2.5", "1.3.0", "1.5.0"]),
+            (">= 1.0.0 <  2", &["1.0.0", "1.9.9"], &["2.0.0"]),
+            ("1.x", &["1.0.0", "1.9.9"], &["2.0.0", "0.9.0"]),
+            ("1.2.x", &["1.2.0", "1.2.9"], &["1.3.0"]),
+            ("1.2", &["1.2.0", "1.2.9"], &["1.3.0"]),
+            ("1", &["1.0.0", "1.9.9"], &["2.0.0"]),
+            ("0.x", &["0.0.1", "0.9.0"], &["1.0.0"]),
+            ("x", &["0.0.0", "9.9.9"], &[]),
+            ("^0.0", &["0.0.0", "0.0.9"], &["0.1.0"]),
+            ("^0", &["0.0.1", "0.9.9"], &["1.0.0"]),
+            ("^1.x", &["1.0.0", "1.9.9"], &["2.0.0"]),
+            ("^0.1.x", &["0.1.0", "0.1.9"], &["0.2.0"]),
+            ("~1", &["1.0.0", "1.9.9"], &["2.0.0"]),
+            ("~> 1.2", &["1.2.0", "1.2.9"], &["1.3.0"]),
+            (">1", &["2.0.0"], &["1.9.9"]),
+            (">1.2", &["1.3.0"], &["1.2.9"]),
+            ("<=1.2", &["1.2.9"], &["1.3.0"]),
+            ("<1.2", &["1.1.9"], &["1.2.0"]),
+            (">=1.2", &["1.2.0"], &["1.1.9"]),
+            ("<*", &[], &["0.0.0", "1.0.0"]),
+            ("1.0.0 - 2.0.0", &["1.0.0", "2.0.0"], &["2.0.1", "0.9.9"]),
+            ("1.2 - 2.3", &["1.2.0", "2.3.9"], &["1.1.9", "2.4.0"]),
+            ("1 - 2", &["1.0.0", "2.9.9"], &["3.0.0"]),
+            ("* - 2", &["0.0.1", "2.9.9"], &["3.0.0"]),
+            ("=v1.2.3", &["1.2.3"], &["1.2.4"]),
+            ("1.2.3+build.5", &["1.2.3"], &["1.2.4"]),
+            (">=2.0.0-rc.1 || 1.x", &["2.0.0-rc.2", "1.5.0"], &["1.6.0-beta"]),
+        ];
+        for (range, admitted, rejected) in cases {
+            for version in *admitted {
+                assert!(matches(range, version), "{} should match {}", range, version);
+            }
+            for version in *rejected {
+                assert!(!matches(range, version), "{} should not match {}", range, version);
+            }
+        }
+    }
+
+    #[test]
+    fn test_union_display_and_family() {
+        let parse = |s: &str| VersionConstraint::parse(s).unwrap();
+
+        assert_eq!(parse("^1.0.0 ||  ^2.0.0").to_string(), "^1.0.0 || ^2.0.0");
+        assert_eq!(parse(">=1.2 <1.5").to_string(), ">=1.2.0 <1.5.0");
+        assert_eq!(parse("^1.0.0 || *").to_string(), "*");
+        assert_eq!(parse("^18.0.0 || ~18.2.0").compatible_family().as_deref(), Some("18"));
+        assert_eq!(parse("^17.0.0 || ^18.0.0").compatible_family(), None);
+        assert_eq!(parse(">=18.1.0 ^18.0.0").compatible_family().as_deref(), Some("18"));
+        assert_eq!(parse("~0.0.3").compatible_family(), None);
+    }
+
     #[test]
     fn test_parse_range() {
         let c = VersionConstraint::parse(">=1.0.0 <2.0.0").unwrap();
@@ -0,0 +1,172 @@
+//! `workspace:` dependency resolution
+//!
+//! A `workspace:` specifier never reaches the registry: it names a member of
+//! the workspace, which is linked to its source directory. `workspace:*`,
+//! `workspace:^` and `workspace:~` accept whatever version the member is at;
+//! any other range must be satisfied by it.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::VersionConstraint;
+use crate::core::lockfile::WorkspacePackage;
+use crate::core::{PackageJson, VelocityError, VelocityResult};
+
+/// Members of the workspace a resolver links `workspace:` dependencies to
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceMembers {
+    /// Workspace root, which lockfile paths are relative to
+    root: PathBuf,
+    /// Member name -> (directory, package.json)
+    members: BTreeMap<String, (PathBuf, PackageJson)>,
+}
+
+/// A `workspace:` dependency, linked to the member's source directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceLink {
+    pub name: String,
+    /// Member directory
+    pub path: PathBuf,
+    pub version: String,
+}
+
+/// Workspace links and the (name, spec) registry dependencies left over
+type SplitDependencies = (Vec<WorkspaceLink>, Vec<(String, String)>);
+
+impl WorkspaceMembers {
+    pub fn new(root: &Path, members: Vec<(PathBuf, PackageJson)>) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            members: members.into_iter().map(|(path, pkg)| (pkg.name.clone(), (path, pkg))).collect(),
+        }
+    }
+
+    /// Separate `workspace:` dependencies, resolved to links, from registry ones
+    ///
+    /// The same name may come with several specs (one per importer of a
+    /// workspace-wide install); registry ones are all kept, links only once.
+    pub fn split<'a, I>(&self, dependencies: I) -> VelocityResult<SplitDependencies>
+    where
+        I: IntoIterator<Item = (&'a String, &'a String)>,
+    {
+        let mut links = Vec::new();
+        let mut registry = Vec::new();
+
+        for (name, spec) in dependencies {
+            let Some(range) = spec.strip_prefix("workspace:") else {
+                registry.push((name.clone(), spec.clone()));
+                continue;
+            };
+            let (path, pkg) = self.members.get(name).ok_or_else(|| {
+                VelocityError::workspace(format!("{}@{} does not name a workspace package", name, spec))
+            })?;
+
+            if !matches!(range, "" | "*" | "^" | "~") {
+                let constraint = VersionConstraint::parse(range)?;
+                let satisfied = semver::Version::parse(&pkg.version).is_ok_and(|v| constraint.matches(&v));
+                if !satisfied {
+                    return Err(VelocityError::workspace(format!(
+                        "{}@{} does not match the workspace package, which is at {}",
+                        name, spec, pkg.version
+                    )));
+                }
+            }
+
+            links.push(WorkspaceLink { name: name.clone(), path: path.clone(), version: pkg.version.clone() });
+        }
+
+        links.sort_by(|a, b| a.name.cmp(&b.name));
+        links.dedup_by(|a, b| a.name == b.name);
+        registry.sort();
+        registry.dedup();
+        Ok((links, registry))
+    }
+
+    /// Registry and `workspace:` dependencies of every member
+    pub fn dependencies(&self) -> Vec<(String, String)> {
+        self.members.values().flat_map(|(_, pkg)| pkg.all_dependencies()).collect()
+    }
+
+    /// Links to every member, which a workspace-wide install exposes at the root
+    pub fn all_links(&self) -> Vec<WorkspaceLink> {
+        self.members.keys().filter_map(|name| self.link(name)).collect()
+    }
+
+    /// Lockfile entry recording a linked member
+    pub fn lockfile_entry(&self, link: &WorkspaceLink) -> WorkspacePackage {
+        let relative = link.path.strip_prefix(&self.root).unwrap_or(&link.path);
+        let mut dependencies: Vec<String> = self
+            .members
+            .get(&link.name)
+            .map(|(_, pkg)| pkg.all_dependencies())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, spec)| format!("{}@{}", name, spec))
+            .collect();
+        dependencies.sort();
+
+        WorkspacePackage {
+            path: relative.to_string_lossy().replace('\\', "/"),
+            version: link.version.clone(),
+            dependencies,
+        }
+    }
+
+    /// Link to a member recorded in a lockfile, if it is still part of the workspace
+    pub fn link(&self, name: &str) -> Option<WorkspaceLink> {
+        let (path, pkg) = self.members.get(name)?;
+        Some(WorkspaceLink { name: name.to_string(), path: path.clone(), version: pkg.version.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn members() -> WorkspaceMembers {
+        let mut ui = PackageJson::new("@app/ui");
+        ui.version = "1.2.0".to_string();
+        ui.dependencies.insert("react".to_string(), "^18.2.0".to_string());
+        WorkspaceMembers::new(Path::new("/repo"), vec![(PathBuf::from("/repo/packages/ui"), ui)])
+    }
+
+    #[test]
+    fn test_split_workspace_dependencies() {
+        let members = members();
+        let deps: HashMap<String, String> = [
+            ("@app/ui".to_string(), "workspace:^".to_string()),
+            ("lodash".to_string(), "^4.17.0".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let (links, registry) = members.split(&deps).unwrap();
+        assert_eq!(links, [WorkspaceLink {
+            name: "@app/ui".to_string(),
+            path: PathBuf::from("/repo/packages/ui"),
+            version: "1.2.0".to_string(),
+        }]);
+        assert_eq!(registry, [("lodash".to_string(), "^4.17.0".to_string())]);
+
+        let entry = members.lockfile_entry(&links[0]);
+        assert_eq!(entry.path, "packages/ui");
+        assert_eq!(entry.dependencies, ["react@^18.2.0"]);
+    }
+
+    #[test]
+    fn test_workspace_ranges() {
+        let members = members();
+        let split = |spec: &str| {
+            let deps: HashMap<String, String> = [("@app/ui".to_string(), spec.to_string())].into_iter().collect();
+            members.split(&deps).map(|(links, _)| links.len())
+        };
+
+        assert_eq!(split("workspace:*").unwrap(), 1);
+        assert_eq!(split("workspace:^1.0.0").unwrap(), 1);
+        assert!(split("workspace:^2.0.0").is_err());
+
+        let deps: HashMap<String, String> = [("@app/missing".to_string(), "workspace:*".to_string())].into_iter().collect();
+        assert!(members.split(&deps).is_err());
+    }
+}
@@ -0,0 +1,570 @@
+//! Known vulnerabilities from the OSV database
+//!
+//! `velocity audit` asks OSV.dev which advisories affect each locked
+//! package@version with one batched query, then fetches the advisories it
+//! has not seen yet. Query results are cached for `cache.metadata_ttl`
+//! seconds and advisories until OSV reports them modified, so repeated audits
+//! (and offline ones) mostly stay local.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{Config, VelocityError, VelocityResult};
+use crate::registry::http::{self, InsecureOrigins};
+
+/// Queries per `querybatch` request (OSV's limit)
+const BATCH_SIZE: usize = 1000;
+
+/// Advisories fetched at once
+const FETCH_CONCURRENCY: usize = 16;
+
+/// How bad a vulnerability is, in npm's terms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Moderate,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Moderate => "moderate",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+
+    /// GitHub advisory labels (`LOW`, `MODERATE`/`MEDIUM`, `HIGH`, `CRITICAL`)
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.to_ascii_lowercase().as_str() {
+            "low" => Some(Severity::Low),
+            "moderate" | "medium" => Some(Severity::Moderate),
+            "high" => Some(Severity::High),
+            "critical" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+
+    /// Severity band of a CVSS base score
+    pub fn from_score(score: f64) -> Self {
+        match score {
+            s if s >= 9.0 => Severity::Critical,
+            s if s >= 7.0 => Severity::High,
+            s if s >= 4.0 => Severity::Moderate,
+            _ => Severity::Low,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A known vulnerability affecting a locked package
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Vulnerability {
+    pub package: String,
+    pub version: String,
+    /// OSV identifier (usually a GHSA id)
+    pub id: String,
+    /// CVE and other identifiers of the same advisory
+    pub aliases: Vec<String>,
+    pub summary: String,
+    pub severity: Severity,
+    pub url: String,
+    /// Lowest version that fixes it, when there is one
+    pub fixed_in: Option<String>,
+}
+
+impl Vulnerability {
+    /// CVE identifiers among the aliases
+    pub fn cves(&self) -> impl Iterator<Item = &str> {
+        self.aliases.iter().map(String::as_str).filter(|alias| alias.starts_with("CVE-"))
+    }
+}
+
+/// An OSV advisory, as far as auditing needs it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsvVulnerability {
+    pub id: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub details: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub severity: Vec<OsvSeverity>,
+    #[serde(default)]
+    pub affected: Vec<OsvAffected>,
+    #[serde(default)]
+    pub references: Vec<OsvReference>,
+    #[serde(default)]
+    pub database_specific: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsvSeverity {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub score: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsvAffected {
+    #[serde(default)]
+    pub package: Option<OsvPackage>,
+    #[serde(default)]
+    pub ranges: Vec<OsvRange>,
+    #[serde(default)]
+    pub database_specific: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsvPackage {
+    pub name: String,
+    pub ecosystem: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsvRange {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub events: Vec<OsvEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsvEvent {
+    #[serde(default)]
+    pub introduced: Option<String>,
+    #[serde(default)]
+    pub fixed: Option<String>,
+    #[serde(default)]
+    pub last_affected: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsvReference {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: String,
+}
+
+impl OsvVulnerability {
+    /// Severity from the GitHub advisory label, else the CVSS v3 vector
+    ///
+    /// Advisories with neither (CVSS v4 only, or none at all) count as
+    /// moderate, which is what npm reports for unrated advisories.
+    pub fn severity(&self) -> Severity {
+        let label = self
+            .database_specific
+            .iter()
+            .chain(self.affected.iter().filter_map(|affected| affected.database_specific.as_ref()))
+            .find_map(|specific| specific.get("severity")?.as_str().and_then(Severity::from_label));
+        label
+            .or_else(|| {
+                self.severity
+                    .iter()
+                    .filter(|severity| severity.kind == "CVSS_V3")
+                    .find_map(|severity| cvss3_base_score(&severity.score))
+                    .map(Severity::from_score)
+            })
+            .unwrap_or(Severity::Moderate)
+    }
+
+    /// Page describing the advisory
+    pub fn url(&self) -> String {
+        self.references
+            .iter()
+            .find(|reference| reference.kind == "ADVISORY")
+            .map(|reference| reference.url.clone())
+            .unwrap_or_else(|| format!("https://osv.dev/vulnerability/{}", self.id))
+    }
+
+    /// Lowest version fixing the range `version` of npm package `name` falls in
+    pub fn fixed_version(&self, name: &str, version: &str) -> Option<String> {
+        let version = semver::Version::parse(version).ok()?;
+        let mut fixes: Vec<semver::Version> = Vec::new();
+
+        let ranges = self
+            .affected
+            .iter()
+            .filter(|affected| affected.package.as_ref().is_some_and(|p| p.ecosystem == "npm" && p.name == name))
+            .flat_map(|affected| &affected.ranges)
+            .filter(|range| range.kind == "SEMVER" || range.kind == "ECOSYSTEM");
+        for range in ranges {
+            // Events come in order: each introduced opens an interval the next fixed closes
+            let mut introduced: Option<semver::Version> = None;
+            for event in &range.events {
+                if let Some(ref start) = event.introduced {
+                    introduced = Some(if start == "0" {
+                        semver::Version::new(0, 0, 0)
+                    } else {
+                        match semver::Version::parse(start) {
+                            Ok(start) => start,
+                            Err(_) => continue,
+                        }
+                    });
+                }
+                if let Some(fixed) = event.fixed.as_deref().and_then(|fixed| semver::Version::parse(fixed).ok()) {
+                    if introduced.as_ref().is_some_and(|start| *start <= version) && version < fixed {
+                        fixes.push(fixed);
+                    }
+                    introduced = None;
+                }
+                if event.last_affected.is_some() {
+                    introduced = None;
+                }
+            }
+        }
+
+        fixes.into_iter().min().map(|fixed| fixed.to_string())
+    }
+}
+
+/// CVSS v3.x base score of a vector such as `CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H`
+pub fn cvss3_base_score(vector: &str) -> Option<f64> {
+    let metrics: HashMap<&str, &str> = vector
+        .split('/')
+        .filter_map(|part| part.split_once(':'))
+        .collect();
+    if !metrics.get("CVSS").is_some_and(|version| version.starts_with('3')) {
+        return None;
+    }
+
+    let changed = match *metrics.get("S")? {
+        "U" => false,
+        "C" => true,
+        _ => return None,
+    };
+    let av = match *metrics.get("AV")? {
+        "N" => 0.85,
+        "A" => 0.62,
+        "L" => 0.55,
+        "P" => 0.2,
+        _ => return None,
+    };
+    let ac = match *metrics.get("AC")? {
+        "L" => 0.77,
+        "H" => 0.44,
+        _ => return None,
+    };
+    let pr = match (*metrics.get("PR")?, changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        ("H", false) => 0.27,
+        ("H", true) => 0.5,
+        _ => return None,
+    };
+    let ui = match *metrics.get("UI")? {
+        "N" => 0.85,
+        "R" => 0.62,
+        _ => return None,
+    };
+    let cia = |key: &str| match *metrics.get(key)? {
+        "H" => Some(0.56),
+        "L" => Some(0.22),
+        "N" => Some(0.0),
+        _ => None,
+    };
+    let iss: f64 = 1.0 - (1.0 - cia("C")?) * (1.0 - cia("I")?) * (1.0 - cia("A")?);
+
+    let impact = if changed {
+        7.52 * (iss - 0.029) - 3.25 * (iss - 0.02).powi(15)
+    } else {
+        6.42 * iss
+    };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability = 8.22 * av * ac * pr * ui;
+    let score = if changed { 1.08 * (impact + exploitability) } else { impact + exploitability };
+    // CVSS rounds up to one decimal
+    Some((score.min(10.0) * 10.0 - 1e-9).ceil() / 10.0)
+}
+
+/// Cached answer to "which advisories affect this package@version"
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedQuery {
+    cached_at: u64,
+    /// Advisory id -> modified timestamp
+    vulns: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    #[serde(default)]
+    results: Vec<BatchResult>,
+}
+
+#[derive(Deserialize)]
+struct BatchResult {
+    #[serde(default)]
+    vulns: Vec<BatchVuln>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BatchVuln {
+    id: String,
+    #[serde(default)]
+    modified: String,
+}
+
+/// Client for the OSV vulnerability database, with a local cache
+pub struct AdvisoryDatabase {
+    client: reqwest::Client,
+    url: String,
+    cache_dir: PathBuf,
+    /// Seconds a query result stays fresh
+    ttl: u64,
+    offline: bool,
+}
+
+impl AdvisoryDatabase {
+    pub fn new(config: &Config) -> VelocityResult<Self> {
+        let url = config.security.advisory_url.trim_end_matches('/').to_string();
+        InsecureOrigins::from_config(&config.network.allow_insecure_registries).check(&url)?;
+        let client = http::client_builder_for(&config.network, Duration::from_secs(config.network.timeout), &url)?
+            .build()
+            .map_err(|e| VelocityError::Network(e.to_string()))?;
+        let cache_dir = config.cache_dir()?.join("advisories");
+        std::fs::create_dir_all(cache_dir.join("queries"))?;
+        std::fs::create_dir_all(cache_dir.join("vulns"))?;
+
+        Ok(Self { client, url, cache_dir, ttl: config.cache.metadata_ttl, offline: config.cache.offline })
+    }
+
+    /// Vulnerabilities affecting any of `packages` (name, version), most severe first
+    pub async fn scan(&self, packages: &[(String, String)]) -> VelocityResult<Vec<Vulnerability>> {
+        let mut found: BTreeMap<(String, String), BTreeMap<String, String>> = BTreeMap::new();
+        let mut missing = Vec::new();
+        for (name, version) in packages {
+            match self.cached_query(name, version)? {
+                Some(vulns) => {
+                    found.insert((name.clone(), version.clone()), vulns);
+                }
+                None => missing.push((name.clone(), version.clone())),
+            }
+        }
+
+        if !missing.is_empty() {
+            if self.offline {
+                let (name, version) = &missing[0];
+                return Err(VelocityError::Offline {
+                    package: format!("{}@{}", name, version),
+                    resource: "vulnerability data".to_string(),
+                });
+            }
+            for chunk in missing.chunks(BATCH_SIZE) {
+                for ((name, version), vulns) in chunk.iter().zip(self.query_batch(chunk).await?) {
+                    self.store_query(name, version, &vulns)?;
+                    found.insert((name.clone(), version.clone()), vulns);
+                }
+            }
+        }
+
+        let mut ids: BTreeMap<&str, &str> = BTreeMap::new();
+        for vulns in found.values() {
+            ids.extend(vulns.iter().map(|(id, modified)| (id.as_str(), modified.as_str())));
+        }
+        let advisories: Vec<VelocityResult<OsvVulnerability>> = stream::iter(ids)
+            .map(|(id, modified)| self.advisory(id, modified))
+            .buffer_unordered(FETCH_CONCURRENCY)
+            .collect()
+            .await;
+        let advisories: HashMap<String, OsvVulnerability> = advisories
+            .into_iter()
+            .map(|advisory| advisory.map(|advisory| (advisory.id.clone(), advisory)))
+            .collect::<VelocityResult<_>>()?;
+
+        let mut vulnerabilities = Vec::new();
+        for ((name, version), vulns) in &found {
+            for id in vulns.keys() {
+                let Some(advisory) = advisories.get(id) else {
+                    continue;
+                };
+                vulnerabilities.push(Vulnerability {
+                    package: name.clone(),
+                    version: version.clone(),
+                    id: advisory.id.clone(),
+                    aliases: advisory.aliases.clone(),
+                    summary: advisory
+                        .summary
+                        .clone()
+                        .or_else(|| advisory.details.as_ref().and_then(|d| d.lines().next()).map(str::to_string))
+                        .unwrap_or_default(),
+                    severity: advisory.severity(),
+                    url: advisory.url(),
+                    fixed_in: advisory.fixed_version(name, version),
+                });
+            }
+        }
+        vulnerabilities.sort_by(|a, b| b.severity.cmp(&a.severity).then_with(|| (&a.package, &a.id).cmp(&(&b.package, &b.id))));
+        Ok(vulnerabilities)
+    }
+
+    /// Ask OSV which advisories affect each package, in order
+    async fn query_batch(&self, packages: &[(String, String)]) -> VelocityResult<Vec<BTreeMap<String, String>>> {
+        let queries: Vec<serde_json::Value> = packages
+            .iter()
+            .map(|(name, version)| {
+                serde_json::json!({ "package": { "name": name, "ecosystem": "npm" }, "version": version })
+            })
+            .collect();
+        let response = self
+            .client
+            .post(format!("{}/v1/querybatch", self.url))
+            .json(&serde_json::json!({ "queries": queries }))
+            .send()
+            .await
+            .map_err(http::send_error)?;
+        if !response.status().is_success() {
+            return Err(VelocityError::Network(format!("Vulnerability query failed: HTTP {}", response.status())));
+        }
+        let batch: BatchResponse = response.json().await?;
+        if batch.results.len() != packages.len() {
+            return Err(VelocityError::Network("Vulnerability query returned an incomplete answer".to_string()));
+        }
+
+        Ok(batch
+            .results
+            .into_iter()
+            .zip(packages)
+            .map(|(result, (name, version))| {
+                if result.next_page_token.is_some() {
+                    tracing::warn!("More advisories affect {}@{} than OSV returned at once", name, version);
+                }
+                result.vulns.into_iter().map(|vuln| (vuln.id, vuln.modified)).collect()
+            })
+            .collect())
+    }
+
+    /// An advisory from the cache when it is current, else from OSV
+    async fn advisory(&self, id: &str, modified: &str) -> VelocityResult<OsvVulnerability> {
+        let path = self.cache_dir.join("vulns").join(format!("{}.json", safe_name(id)));
+        let cached = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<OsvVulnerability>(&content).ok());
+        if let Some(advisory) = cached {
+            if advisory.modified == modified || self.offline {
+                return Ok(advisory);
+            }
+        }
+        if self.offline {
+            return Err(VelocityError::Offline { package: id.to_string(), resource: "vulnerability data".to_string() });
+        }
+
+        let response = self
+            .client
+            .get(format!("{}/v1/vulns/{}", self.url, id))
+            .send()
+            .await
+            .map_err(http::send_error)?;
+        if !response.status().is_success() {
+            return Err(VelocityError::Network(format!("Fetching advisory {} failed: HTTP {}", id, response.status())));
+        }
+        let advisory: OsvVulnerability = response.json().await?;
+        std::fs::write(&path, serde_json::to_string(&advisory)?)?;
+        Ok(advisory)
+    }
+
+    fn query_path(&self, name: &str, version: &str) -> PathBuf {
+        self.cache_dir.join("queries").join(format!("{}@{}.json", safe_name(name), version))
+    }
+
+    /// Cached query result, if fresh (or of any age offline)
+    fn cached_query(&self, name: &str, version: &str) -> VelocityResult<Option<BTreeMap<String, String>>> {
+        let Ok(content) = std::fs::read_to_string(self.query_path(name, version)) else {
+            return Ok(None);
+        };
+        let Ok(cached) = serde_json::from_str::<CachedQuery>(&content) else {
+            return Ok(None);
+        };
+        let age = now().saturating_sub(cached.cached_at);
+        if self.offline || (self.ttl > 0 && age <= self.ttl) {
+            return Ok(Some(cached.vulns));
+        }
+        Ok(None)
+    }
+
+    fn store_query(&self, name: &str, version: &str, vulns: &BTreeMap<String, String>) -> VelocityResult<()> {
+        let cached = CachedQuery { cached_at: now(), vulns: vulns.clone() };
+        std::fs::write(self.query_path(name, version), serde_json::to_string(&cached)?)?;
+        Ok(())
+    }
+}
+
+fn safe_name(name: &str) -> String {
+    name.replace('/', "+").replace('@', "")
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advisory() -> OsvVulnerability {
+        serde_json::from_value(serde_json::json!({
+            "id": "GHSA-35jh-r3h4-6jhm",
+            "modified": "2024-01-01T00:00:00Z",
+            "summary": "Command Injection in lodash",
+            "aliases": ["CVE-2021-23337"],
+            "severity": [{ "type": "CVSS_V3", "score": "CVSS:3.1/AV:N/AC:L/PR:H/UI:N/S:U/C:H/I:H/A:H" }],
+            "affected": [{
+                "package": { "name": "lodash", "ecosystem": "npm" },
+                "ranges": [{ "type": "SEMVER", "events": [
+                    { "introduced": "0" }, { "fixed": "4.17.21" },
+                    { "introduced": "5.0.0" }, { "fixed": "5.0.1" },
+                ] }],
+            }],
+            "references": [{ "type": "ADVISORY", "url": "https://nvd.nist.gov/vuln/detail/CVE-2021-23337" }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fixed_version() {
+        let advisory = advisory();
+        assert_eq!(advisory.fixed_version("lodash", "4.17.20").as_deref(), Some("4.17.21"));
+        assert_eq!(advisory.fixed_version("lodash", "5.0.0").as_deref(), Some("5.0.1"));
+        assert_eq!(advisory.fixed_version("lodash", "4.17.21"), None);
+        assert_eq!(advisory.fixed_version("underscore", "1.0.0"), None);
+        assert_eq!(advisory.url(), "https://nvd.nist.gov/vuln/detail/CVE-2021-23337");
+    }
+
+    #[test]
+    fn test_severity() {
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), Some(9.8));
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:H/UI:N/S:U/C:H/I:H/A:H"), Some(7.2));
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:R/S:C/C:L/I:L/A:N"), Some(6.1));
+        assert_eq!(cvss3_base_score("CVSS:4.0/AV:N"), None);
+        assert_eq!(advisory().severity(), Severity::High);
+
+        let mut labelled = advisory();
+        labelled.database_specific = Some(serde_json::json!({ "severity": "CRITICAL" }));
+        assert_eq!(labelled.severity(), Severity::Critical);
+    }
+}
@@ -0,0 +1,160 @@
+//! Dependency budgets: limits on what a single `velocity add` may bring in
+
+use serde::Serialize;
+
+use super::RiskLevel;
+use crate::core::config::BudgetConfig;
+use crate::utils::format_bytes;
+
+/// A package version an add would introduce
+#[derive(Debug, Clone, Serialize)]
+pub struct AddedPackage {
+    pub name: String,
+    pub version: String,
+    pub license: Option<String>,
+    /// Unpacked size, when the registry reports it
+    pub size: Option<u64>,
+    pub risk: RiskLevel,
+}
+
+/// A budget limit an add would exceed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BudgetViolation {
+    TooManyPackages { added: usize, limit: usize },
+    TooLarge { added: u64, limit: u64 },
+    License { package: String, license: String },
+    Risk { package: String, risk: RiskLevel },
+}
+
+impl std::fmt::Display for BudgetViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetViolation::TooManyPackages { added, limit } => {
+                write!(f, "adds {} packages (budget: {})", added, limit)
+            }
+            BudgetViolation::TooLarge { added, limit } => {
+                write!(f, "adds {} unpacked (budget: {})", format_bytes(*added), format_bytes(*limit))
+            }
+            BudgetViolation::License { package, license } => {
+                write!(f, "{} is licensed {}, which is disallowed", package, license)
+            }
+            BudgetViolation::Risk { package, risk } => {
+                write!(f, "{} is {:?} risk, which is disallowed", package, risk)
+            }
+        }
+    }
+}
+
+/// What an add introduces, measured against the budget
+#[derive(Debug, Default, Serialize)]
+pub struct BudgetReport {
+    pub new_packages: usize,
+    pub added_size: u64,
+    /// Packages whose size the registry does not report
+    pub unknown_size: usize,
+    pub violations: Vec<BudgetViolation>,
+}
+
+impl BudgetReport {
+    /// Measure the packages an add introduces against `budget`
+    pub fn check(budget: &BudgetConfig, added: &[AddedPackage]) -> Self {
+        let mut report = BudgetReport {
+            new_packages: added.len(),
+            added_size: added.iter().filter_map(|p| p.size).sum(),
+            unknown_size: added.iter().filter(|p| p.size.is_none()).count(),
+            violations: Vec::new(),
+        };
+
+        if let Some(limit) = budget.max_new_packages {
+            if report.new_packages > limit {
+                report.violations.push(BudgetViolation::TooManyPackages { added: report.new_packages, limit });
+            }
+        }
+        if let Some(limit) = budget.max_added_size {
+            if report.added_size > limit {
+                report.violations.push(BudgetViolation::TooLarge { added: report.added_size, limit });
+            }
+        }
+
+        for package in added {
+            let id = format!("{}@{}", package.name, package.version);
+            if let Some(ref license) = package.license {
+                if !license_allowed(license, &budget.disallowed_licenses) {
+                    report.violations.push(BudgetViolation::License { package: id.clone(), license: license.clone() });
+                }
+            }
+            if budget.disallowed_risk.contains(&package.risk) {
+                report.violations.push(BudgetViolation::Risk { package: id, risk: package.risk });
+            }
+        }
+
+        report
+    }
+
+    /// Whether the add stays within budget
+    pub fn within_budget(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Whether an SPDX expression leaves a choice free of disallowed licenses
+///
+/// `MIT OR GPL-3.0` is allowed when only GPL-3.0 is disallowed, since the
+/// MIT terms can be chosen; `MIT AND GPL-3.0` is not.
+pub fn license_allowed(expression: &str, disallowed: &[String]) -> bool {
+    if disallowed.is_empty() {
+        return true;
+    }
+    super::licenses::satisfies(expression, |term| {
+        !disallowed.iter().any(|license| super::licenses::same_license(license, term))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn added(name: &str, license: &str, size: u64) -> AddedPackage {
+        AddedPackage {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            license: Some(license.to_string()),
+            size: Some(size),
+            risk: RiskLevel::Low,
+        }
+    }
+
+    #[test]
+    fn test_license_expressions() {
+        let gpl = vec!["GPL-3.0".to_string()];
+        assert!(license_allowed("MIT", &gpl));
+        assert!(!license_allowed("GPL-3.0", &gpl));
+        assert!(license_allowed("(MIT OR GPL-3.0)", &gpl));
+        assert!(!license_allowed("MIT AND GPL-3.0", &gpl));
+    }
+
+    #[test]
+    fn test_budget_violations() {
+        let budget = BudgetConfig {
+            max_new_packages: Some(1),
+            max_added_size: Some(1000),
+            disallowed_licenses: vec!["AGPL-3.0".to_string()],
+            disallowed_risk: vec![RiskLevel::High],
+        };
+
+        let report = BudgetReport::check(&budget, &[added("a", "MIT", 400)]);
+        assert!(report.within_budget());
+
+        let report = BudgetReport::check(&budget, &[added("a", "MIT", 400), added("b", "AGPL-3.0", 800)]);
+        assert_eq!(report.added_size, 1200);
+        assert_eq!(
+            report.violations,
+            [
+                BudgetViolation::TooManyPackages { added: 2, limit: 1 },
+                BudgetViolation::TooLarge { added: 1200, limit: 1000 },
+                BudgetViolation::License { package: "b@1.0.0".to_string(), license: "AGPL-3.0".to_string() },
+            ]
+        );
+    }
+}
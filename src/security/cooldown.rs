@@ -0,0 +1,128 @@
+//! Release cooldown: hold back versions until they have been public for a while
+//!
+//! Hijacked packages are usually spotted and unpublished within hours, so
+//! versions published less than `security.release_cooldown_hours` ago are left
+//! out of resolution. Ranges settle on the newest older version, dist-tags
+//! pointing at a held-back version fall back the same way, and versions already
+//! in the lockfile stay usable. Publish times come from the packument's `time`
+//! field; versions without one are not held back.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::core::config::SecurityConfig;
+use crate::registry::types::PackageMetadata;
+
+/// Which versions are too new to install
+#[derive(Debug, Clone)]
+pub struct ReleaseCooldown {
+    /// Versions published after this are held back
+    cutoff: DateTime<Utc>,
+    /// Length of the cooldown, for messages
+    hours: u64,
+    /// Scopes whose packages are installed as soon as they are published
+    exempt_scopes: Vec<String>,
+}
+
+impl ReleaseCooldown {
+    /// The configured cooldown, or `None` when it is off
+    pub fn from_config(config: &SecurityConfig) -> Option<Self> {
+        Self::at(config, Utc::now())
+    }
+
+    /// The configured cooldown as of `now`
+    pub fn at(config: &SecurityConfig, now: DateTime<Utc>) -> Option<Self> {
+        let hours = config.release_cooldown_hours;
+        (hours > 0).then(|| Self {
+            cutoff: now - Duration::hours(i64::try_from(hours).unwrap_or(i64::MAX / 3600)),
+            hours,
+            exempt_scopes: config.release_cooldown_exempt_scopes.clone(),
+        })
+    }
+
+    /// Length of the cooldown in hours
+    pub fn hours(&self) -> u64 {
+        self.hours
+    }
+
+    /// Whether `name` is subject to the cooldown
+    pub fn applies_to(&self, name: &str) -> bool {
+        !(name.starts_with('@')
+            && name
+                .split('/')
+                .next()
+                .is_some_and(|scope| self.exempt_scopes.iter().any(|s| s == scope)))
+    }
+
+    /// Whether `version` of the package was published too recently
+    pub fn holds_back(&self, metadata: &PackageMetadata, version: &str) -> bool {
+        self.applies_to(&metadata.name)
+            && metadata
+                .time
+                .get(version)
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .is_some_and(|published| published > self.cutoff)
+    }
+
+    /// Remove the held-back versions from `metadata`, except those in `keep`
+    ///
+    /// Dist-tags move as in [`PackageMetadata::remove_versions`]. Returns the removed versions.
+    pub fn apply(&self, metadata: &mut PackageMetadata, keep: &[&str]) -> Vec<String> {
+        let mut held_back: Vec<String> = metadata
+            .versions
+            .keys()
+            .filter(|version| !keep.contains(&version.as_str()) && self.holds_back(metadata, version))
+            .cloned()
+            .collect();
+        held_back.sort();
+        metadata.remove_versions(&held_back);
+        held_back
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_cooldown() {
+        let now = DateTime::parse_from_rfc3339("2024-06-10T12:00:00Z").unwrap().with_timezone(&Utc);
+        let config = SecurityConfig {
+            release_cooldown_hours: 72,
+            release_cooldown_exempt_scopes: vec!["@acme".to_string()],
+            ..Default::default()
+        };
+        let cooldown = ReleaseCooldown::at(&config, now).unwrap();
+        assert!(ReleaseCooldown::at(&SecurityConfig::default(), now).is_none());
+
+        let mut metadata: PackageMetadata = serde_json::from_value(serde_json::json!({
+            "name": "lib",
+            "dist-tags": { "latest": "1.2.0", "next": "2.0.0-beta.1" },
+            "versions": {
+                "1.0.0": { "name": "lib", "version": "1.0.0", "dist": { "tarball": "" } },
+                "1.1.0": { "name": "lib", "version": "1.1.0", "dist": { "tarball": "" } },
+                "1.2.0": { "name": "lib", "version": "1.2.0", "dist": { "tarball": "" } },
+                "2.0.0-beta.1": { "name": "lib", "version": "2.0.0-beta.1", "dist": { "tarball": "" } },
+            },
+            "time": {
+                "1.0.0": "2024-01-01T00:00:00.000Z",
+                "1.1.0": "2024-06-09T00:00:00.000Z",
+                "1.2.0": "2024-06-10T11:00:00.000Z",
+                "2.0.0-beta.1": "2024-06-10T10:00:00.000Z",
+            },
+        }))
+        .unwrap();
+
+        assert!(cooldown.holds_back(&metadata, "1.2.0"));
+        assert!(!cooldown.holds_back(&metadata, "1.0.0"));
+
+        // 1.1.0 is locked, so only 1.2.0 and the beta go; both tags fall back
+        let held_back = cooldown.apply(&mut metadata, &["1.1.0"]);
+        assert_eq!(held_back, ["1.2.0", "2.0.0-beta.1"]);
+        assert_eq!(metadata.dist_tags["latest"], "1.1.0");
+        assert_eq!(metadata.dist_tags["next"], "1.1.0");
+
+        // Exempt scopes install right away
+        assert!(!cooldown.applies_to("@acme/tool"));
+        assert!(cooldown.applies_to("@other/tool"));
+    }
+}
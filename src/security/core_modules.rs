@@ -0,0 +1,59 @@
+//! Detection of registry packages that shadow Node.js core modules
+//!
+//! A dependency named `fs` or `child_process` is resolved from node_modules
+//! only when code asks for it explicitly, but installing one is almost always
+//! a typo or a squatted name. A handful of core names are legitimate browser
+//! polyfills and are exempt.
+
+use std::collections::HashSet;
+use once_cell::sync::Lazy;
+
+/// Node.js built-in module names
+static CORE_MODULES: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    [
+        "assert", "async_hooks", "buffer", "child_process", "cluster", "console",
+        "constants", "crypto", "dgram", "diagnostics_channel", "dns", "domain",
+        "events", "fs", "http", "http2", "https", "inspector", "module", "net",
+        "os", "path", "perf_hooks", "process", "punycode", "querystring",
+        "readline", "repl", "stream", "string_decoder", "sys", "timers", "tls",
+        "trace_events", "tty", "url", "util", "v8", "vm", "wasi",
+        "worker_threads", "zlib",
+    ].into_iter().collect()
+});
+
+/// Core names whose registry packages are maintained browser polyfills, or
+/// long-standing packages that real dependency trees pull in
+static KNOWN_POLYFILLS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    [
+        "assert", "buffer", "console", "constants", "domain", "events", "os",
+        "path", "process", "punycode", "querystring", "stream",
+        "string_decoder", "sys", "timers", "tty", "url", "util", "vm",
+    ].into_iter().collect()
+});
+
+/// Check if a name is a Node.js core module
+pub fn is_core_module(name: &str) -> bool {
+    CORE_MODULES.contains(name)
+}
+
+/// Check if a package shadows a core module without being a known polyfill
+pub fn shadows_core_module(name: &str) -> bool {
+    is_core_module(name) && !KNOWN_POLYFILLS.contains(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_module_shadowing() {
+        assert!(shadows_core_module("fs"));
+        assert!(shadows_core_module("child_process"));
+        assert!(!shadows_core_module("buffer"));
+        assert!(!shadows_core_module("path"));
+        assert!(!shadows_core_module("stream"));
+        assert!(!shadows_core_module("lodash"));
+        assert!(!shadows_core_module("@types/node"));
+        assert!(is_core_module("events"));
+    }
+}
@@ -0,0 +1,1047 @@
+//! OS-level isolation for install scripts
+//!
+//! Scripts of untrusted packages run with only the filesystem and network
+//! access their permissions allow, enforced by the operating system:
+//!
+//! - Linux: a new network namespace (inside an unprivileged user namespace
+//!   when needed) leaves only a downed loopback device, and Landlock limits
+//!   writes to the package directory, the temp directory and `/dev`, and
+//!   reads to those plus the project, the Node.js installation and system
+//!   directories, so scripts cannot read `~/.npmrc` or SSH keys. Cross-directory
+//!   renames (Landlock ABI 2) and truncation (ABI 3) count as writes on
+//!   kernels that can restrict them
+//! - macOS: the script runs under `sandbox-exec` with an equivalent profile
+//! - Windows: velocity relaunches itself to start the script in an AppContainer,
+//!   which has no network access without the internet capabilities and can only
+//!   reach files the container was granted, inside a job object that kills the
+//!   script's process tree with the launcher. Grants are added to the directories'
+//!   ACLs and stay there for later runs. An AppContainer always restricts the
+//!   filesystem, so scripts allowed the filesystem but not the network run
+//!   unrestricted (or not at all with `script_sandbox = "strict"`)
+
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::security::permissions::{Permission, PermissionDecision, PermissionManager};
+
+/// What an isolated script may do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Isolation {
+    /// Directories the script may write below, or `None` for anywhere
+    pub writable: Option<Vec<PathBuf>>,
+
+    /// Directories the script may read below, or `None` for anywhere
+    pub readable: Option<Vec<PathBuf>>,
+
+    /// Whether the script may use the network
+    pub network: bool,
+}
+
+/// System directories scripts may read: executables, libraries and configuration
+#[cfg(not(any(target_os = "macos", windows)))]
+const SYSTEM_READ_DIRS: &[&str] = &[
+    "/bin", "/sbin", "/usr", "/lib", "/lib32", "/lib64", "/etc", "/opt", "/nix", "/proc", "/sys", "/run",
+];
+
+/// System directories scripts may read: executables, libraries, configuration and Xcode
+#[cfg(target_os = "macos")]
+const SYSTEM_READ_DIRS: &[&str] = &[
+    "/bin", "/sbin", "/usr", "/System", "/Library", "/opt", "/private/etc", "/private/var/db",
+    "/Applications/Xcode.app",
+];
+
+/// AppContainers read system directories already
+#[cfg(windows)]
+const SYSTEM_READ_DIRS: &[&str] = &[];
+
+/// Device directories scripts may write
+#[cfg(unix)]
+const DEVICE_DIRS: &[&str] = &["/dev"];
+
+#[cfg(not(unix))]
+const DEVICE_DIRS: &[&str] = &[];
+
+impl Isolation {
+    /// Restrictions for scripts of `package` run in `package_dir`, or `None`
+    /// when its permissions allow everything
+    ///
+    /// Without filesystem permission, scripts may read below `project_dirs`
+    /// (the project and its node_modules) as well as where they may write.
+    /// Only an explicit `allow` lifts a restriction; scripts cannot answer a prompt.
+    pub fn for_package(
+        permissions: &PermissionManager,
+        package: &str,
+        package_dir: &Path,
+        project_dirs: &[&Path],
+    ) -> Option<Self> {
+        let allowed = |permission| permissions.check(package, permission) == PermissionDecision::Allow;
+
+        let network = allowed(Permission::Network);
+        let writable = (!allowed(Permission::Filesystem)).then(|| {
+            let mut writable = vec![package_dir.to_path_buf(), std::env::temp_dir()];
+            writable.extend(DEVICE_DIRS.iter().map(PathBuf::from));
+            writable
+        });
+        let readable = writable.as_ref().map(|writable| {
+            let mut readable = writable.clone();
+            readable.extend(project_dirs.iter().map(|dir| dir.to_path_buf()));
+            readable.extend(node_prefix());
+            readable.extend(SYSTEM_READ_DIRS.iter().map(PathBuf::from));
+            readable
+        });
+
+        (writable.is_some() || !network).then_some(Self { writable, readable, network })
+    }
+
+    /// Restrictions this system cannot enforce, by permission name
+    pub fn unsupported(&self) -> Vec<&'static str> {
+        let mut unsupported = Vec::new();
+        let restricts_filesystem = self.writable.is_some() || self.readable.is_some();
+        if !self.network && !backend::can_deny_network(restricts_filesystem) {
+            unsupported.push("network");
+        }
+        if restricts_filesystem && !backend::can_restrict_filesystem() {
+            unsupported.push("filesystem");
+        }
+        unsupported
+    }
+
+    /// A command running `program` under the restrictions this system supports
+    pub fn command(&self, program: &str) -> Command {
+        backend::command(self, program)
+    }
+}
+
+/// Installation directory of the `node` on PATH, e.g. `~/.nvm/versions/node/v20.11.0`
+fn node_prefix() -> Option<PathBuf> {
+    let node = which::which("node").ok()?.canonicalize().ok()?;
+    // node.exe sits in the installation directory itself, not in bin/
+    let bin = if cfg!(windows) { node.as_path() } else { node.parent()? };
+    Some(bin.parent()?.to_path_buf())
+}
+
+/// Run the isolated script velocity was relaunched for, if it was, returning its exit code
+#[cfg(windows)]
+pub fn launch_from_args() -> Option<i32> {
+    let mut args = std::env::args_os().skip(1);
+    if args.next()? != backend::LAUNCHER_ARG {
+        return None;
+    }
+    Some(backend::launch(args))
+}
+
+#[cfg(target_os = "linux")]
+mod backend {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::sync::OnceLock;
+    use tokio::process::Command;
+
+    use super::Isolation;
+
+    /// `landlock_create_ruleset` flag asking for the supported ABI version
+    const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1 << 0;
+
+    /// `landlock_add_rule` type for a directory hierarchy
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+    /// Landlock ABI 1 rights that change the filesystem: write file, remove
+    /// directory and file, and make char devices, directories, regular files,
+    /// sockets, FIFOs, block devices and symlinks
+    const ACCESS_FS_WRITE: u64 = (1 << 1) | (0b1_1111_1111 << 4);
+
+    /// Landlock ABI 1 rights that read the filesystem: execute, read file and read directory
+    const ACCESS_FS_READ: u64 = (1 << 0) | (1 << 2) | (1 << 3);
+
+    /// Landlock ABI 2 right to link or rename a file into another directory
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+
+    /// Landlock ABI 3 right to truncate a file
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    pub fn can_deny_network(_restricts_filesystem: bool) -> bool {
+        network_namespaces()
+    }
+
+    /// Whether this process can move a child into a new network namespace
+    fn network_namespaces() -> bool {
+        static SUPPORTED: OnceLock<bool> = OnceLock::new();
+        *SUPPORTED.get_or_init(|| {
+            // Unprivileged user namespaces are often disabled; try it in a throwaway child
+            let maps = IdMaps::current();
+            let mut probe = std::process::Command::new("sh");
+            probe
+                .args(["-c", ":"])
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null());
+            unsafe {
+                std::os::unix::process::CommandExt::pre_exec(&mut probe, move || deny_network(&maps));
+            }
+            probe.status().map(|status| status.success()).unwrap_or(false)
+        })
+    }
+
+    pub fn can_restrict_filesystem() -> bool {
+        abi_version() >= 1
+    }
+
+    /// Landlock ABI version of the running kernel, or 0 without Landlock
+    pub fn abi_version() -> i64 {
+        static VERSION: OnceLock<i64> = OnceLock::new();
+        *VERSION.get_or_init(|| unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+            .max(0)
+        })
+    }
+
+    /// Rights that change the filesystem, as far as this kernel can restrict them
+    fn write_access() -> u64 {
+        let abi = abi_version();
+        let mut access = ACCESS_FS_WRITE;
+        if abi >= 2 {
+            access |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            access |= ACCESS_FS_TRUNCATE;
+        }
+        access
+    }
+
+    pub fn command(isolation: &Isolation, program: &str) -> Command {
+        let mut command = Command::new(program);
+
+        let maps = (!isolation.network && network_namespaces()).then(IdMaps::current);
+        let paths = |dirs: &Option<Vec<std::path::PathBuf>>| -> Option<Vec<CString>> {
+            dirs.as_ref().filter(|_| can_restrict_filesystem()).map(|dirs| {
+                dirs.iter()
+                    .filter_map(|dir| CString::new(dir.as_os_str().as_bytes()).ok())
+                    .collect()
+            })
+        };
+        let writable = paths(&isolation.writable);
+        let readable = paths(&isolation.readable);
+        let write_access = write_access();
+
+        if maps.is_some() || writable.is_some() || readable.is_some() {
+            // Runs in the forked child, so everything it needs is prepared above
+            unsafe {
+                command.pre_exec(move || {
+                    if let Some(maps) = &maps {
+                        deny_network(maps)?;
+                    }
+                    if writable.is_some() || readable.is_some() {
+                        restrict_filesystem(writable.as_deref(), readable.as_deref(), write_access)?;
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        command
+    }
+
+    /// Contents for `/proc/self/{uid,gid}_map` keeping our own ids in a new user namespace
+    struct IdMaps {
+        uid: CString,
+        gid: CString,
+    }
+
+    impl IdMaps {
+        fn current() -> Self {
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            Self {
+                uid: CString::new(format!("{} {} 1", uid, uid)).unwrap_or_default(),
+                gid: CString::new(format!("{} {} 1", gid, gid)).unwrap_or_default(),
+            }
+        }
+    }
+
+    /// Move into a fresh network namespace, through a user namespace when unprivileged
+    fn deny_network(maps: &IdMaps) -> io::Result<()> {
+        if unsafe { libc::unshare(libc::CLONE_NEWNET) } == 0 {
+            return Ok(());
+        }
+        if unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        write_proc(c"/proc/self/setgroups", c"deny")?;
+        write_proc(c"/proc/self/uid_map", &maps.uid)?;
+        write_proc(c"/proc/self/gid_map", &maps.gid)
+    }
+
+    /// Allow filesystem changes (the `write_access` rights) only below `writable`
+    /// and reads only below `readable` or `writable`, for this process and its children
+    fn restrict_filesystem(
+        writable: Option<&[CString]>,
+        readable: Option<&[CString]>,
+        write_access: u64,
+    ) -> io::Result<()> {
+        let handled = writable.map_or(0, |_| write_access) | readable.map_or(0, |_| ACCESS_FS_READ);
+        let attr = RulesetAttr { handled_access_fs: handled };
+        let ruleset = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        } as libc::c_int;
+        if ruleset < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let rules = readable
+            .unwrap_or_default()
+            .iter()
+            .map(|dir| (dir, handled & ACCESS_FS_READ))
+            .chain(writable.unwrap_or_default().iter().map(|dir| (dir, handled)));
+        for (dir, allowed_access) in rules {
+            let fd = unsafe { libc::open(dir.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            if fd < 0 {
+                // Nothing to allow below a directory that does not exist
+                continue;
+            }
+            let rule = PathBeneathAttr { allowed_access, parent_fd: fd };
+            let added = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    ruleset,
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &rule as *const PathBeneathAttr,
+                    0,
+                )
+            };
+            unsafe { libc::close(fd) };
+            if added != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let restricted = unsafe {
+            libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0
+                && libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) == 0
+        };
+        let error = io::Error::last_os_error();
+        unsafe { libc::close(ruleset) };
+        if restricted { Ok(()) } else { Err(error) }
+    }
+
+    fn write_proc(path: &std::ffi::CStr, content: &std::ffi::CStr) -> io::Result<()> {
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let bytes = content.to_bytes();
+        let written = unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+        unsafe { libc::close(fd) };
+        if written == bytes.len() as isize {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod backend {
+    use std::path::Path;
+    use tokio::process::Command;
+
+    use super::Isolation;
+
+    const SANDBOX_EXEC: &str = "/usr/bin/sandbox-exec";
+
+    pub fn can_deny_network(_restricts_filesystem: bool) -> bool {
+        Path::new(SANDBOX_EXEC).exists()
+    }
+
+    pub fn can_restrict_filesystem() -> bool {
+        Path::new(SANDBOX_EXEC).exists()
+    }
+
+    pub fn command(isolation: &Isolation, program: &str) -> Command {
+        if !can_restrict_filesystem() {
+            return Command::new(program);
+        }
+        let mut command = Command::new(SANDBOX_EXEC);
+        command.arg("-p").arg(profile(isolation)).arg(program);
+        command
+    }
+
+    /// Seatbelt profile allowing everything but the restricted access
+    fn profile(isolation: &Isolation) -> String {
+        let mut profile = String::from("(version 1)\n(allow default)\n");
+        if !isolation.network {
+            profile.push_str("(deny network*)\n");
+        }
+        if let Some(writable) = &isolation.writable {
+            profile.push_str("(deny file-write*)\n(allow file-write*");
+            push_subpaths(&mut profile, writable);
+            profile.push_str(")\n");
+        }
+        if let Some(readable) = &isolation.readable {
+            // Metadata stays readable so paths still resolve through any directory
+            profile.push_str("(deny file-read-data)\n(allow file-read-data (literal \"/\")");
+            push_subpaths(&mut profile, readable);
+            profile.push_str(")\n");
+        }
+        profile
+    }
+
+    fn push_subpaths(profile: &mut String, dirs: &[std::path::PathBuf]) {
+        for dir in dirs {
+            // Seatbelt matches resolved paths (/var is really /private/var)
+            let dir = dir.canonicalize().unwrap_or_else(|_| dir.clone());
+            let dir = dir.display().to_string().replace('\\', "\\\\").replace('"', "\\\"");
+            profile.push_str(&format!(" (subpath \"{}\")", dir));
+        }
+    }
+}
+
+#[cfg(windows)]
+mod backend {
+    use std::ffi::{OsStr, OsString};
+    use std::io;
+    use std::mem::size_of;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::ptr::{null, null_mut};
+    use std::sync::OnceLock;
+    use tokio::process::Command;
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, LocalFree, SetHandleInformation, BOOL, ERROR_ALREADY_EXISTS, HANDLE, HANDLE_FLAG_INHERIT,
+        WIN32_ERROR,
+    };
+    use windows_sys::Win32::Security::Authorization::{
+        GetExplicitEntriesFromAclW, GetNamedSecurityInfoW, SetEntriesInAclW, SetNamedSecurityInfoW,
+        EXPLICIT_ACCESS_W, GRANT_ACCESS, NO_MULTIPLE_TRUSTEE, SET_ACCESS, SE_FILE_OBJECT, TRUSTEE_IS_SID,
+        TRUSTEE_IS_UNKNOWN, TRUSTEE_W,
+    };
+    use windows_sys::Win32::Security::Isolation::{
+        CreateAppContainerProfile, DeriveAppContainerSidFromAppContainerName,
+    };
+    use windows_sys::Win32::Security::{
+        CreateWellKnownSid, EqualSid, FreeSid, WinCapabilityInternetClientSid,
+        WinCapabilityPrivateNetworkClientServerSid, ACL, DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, PSID,
+        SECURITY_CAPABILITIES, SECURITY_MAX_SID_SIZE, SID_AND_ATTRIBUTES, SUB_CONTAINERS_AND_OBJECTS_INHERIT,
+        WELL_KNOWN_SID_TYPE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{FILE_ALL_ACCESS, FILE_GENERIC_EXECUTE, FILE_GENERIC_READ};
+    use windows_sys::Win32::System::Console::{
+        GetStdHandle, STD_ERROR_HANDLE, STD_HANDLE, STD_INPUT_HANDLE, STD_OUTPUT_HANDLE,
+    };
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation, SetInformationJobObject,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+    use windows_sys::Win32::System::SystemServices::SE_GROUP_ENABLED;
+    use windows_sys::Win32::System::Threading::{
+        CreateProcessW, DeleteProcThreadAttributeList, GetExitCodeProcess, InitializeProcThreadAttributeList,
+        ResumeThread, TerminateProcess, UpdateProcThreadAttribute, WaitForSingleObject, CREATE_SUSPENDED,
+        EXTENDED_STARTUPINFO_PRESENT, INFINITE, PROCESS_INFORMATION, PROC_THREAD_ATTRIBUTE_SECURITY_CAPABILITIES,
+        STARTF_USESTDHANDLES, STARTUPINFOEXW,
+    };
+
+    use super::Isolation;
+
+    /// First argument that makes velocity launch an isolated script instead of running a command
+    pub const LAUNCHER_ARG: &str = "__velocity-isolated";
+
+    /// AppContainer profile isolated scripts run in
+    const PROFILE_NAME: &str = "velocity.scripts";
+
+    /// Rights on directories scripts may read: list, read and execute
+    const READ_ACCESS: u32 = FILE_GENERIC_READ | FILE_GENERIC_EXECUTE;
+
+    /// `HRESULT_FROM_WIN32(ERROR_ALREADY_EXISTS)`, for a profile an earlier run created
+    const PROFILE_EXISTS: i32 = (0x8007_0000 | ERROR_ALREADY_EXISTS) as i32;
+
+    pub fn can_deny_network(restricts_filesystem: bool) -> bool {
+        // An AppContainer cannot leave the filesystem open, so the network only goes with it
+        restricts_filesystem && can_restrict_filesystem()
+    }
+
+    pub fn can_restrict_filesystem() -> bool {
+        static SUPPORTED: OnceLock<bool> = OnceLock::new();
+        *SUPPORTED.get_or_init(|| std::env::current_exe().is_ok() && AppContainer::open().is_ok())
+    }
+
+    pub fn command(isolation: &Isolation, program: &str) -> Command {
+        let restricts_filesystem = isolation.writable.is_some() || isolation.readable.is_some();
+        let exe = match std::env::current_exe() {
+            Ok(exe) if restricts_filesystem && can_restrict_filesystem() => exe,
+            _ => return Command::new(program),
+        };
+
+        // Creating an AppContainer process takes attributes Command cannot pass,
+        // so velocity relaunches itself to start the script
+        let mut command = Command::new(exe);
+        command.arg(LAUNCHER_ARG);
+        if isolation.network {
+            command.arg("--network");
+        }
+        for dir in isolation.writable.iter().flatten() {
+            command.arg("--write").arg(dir);
+        }
+        for dir in isolation.readable.iter().flatten() {
+            command.arg("--read").arg(dir);
+        }
+        command.arg("--").arg(program);
+        command
+    }
+
+    /// Run the script described by the launcher arguments `args`, returning its exit code
+    pub fn launch(args: impl Iterator<Item = OsString>) -> i32 {
+        match Launch::parse(args).and_then(|launch| launch.run()) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("velocity: cannot isolate script: {}", e);
+                1
+            }
+        }
+    }
+
+    /// A script to start in the AppContainer, as the launcher arguments describe it
+    #[derive(Debug, Default, PartialEq)]
+    pub(super) struct Launch {
+        pub network: bool,
+        pub writable: Vec<PathBuf>,
+        pub readable: Vec<PathBuf>,
+        pub command: Vec<OsString>,
+    }
+
+    impl Launch {
+        pub fn parse(mut args: impl Iterator<Item = OsString>) -> io::Result<Self> {
+            let mut launch = Self::default();
+            while let Some(arg) = args.next() {
+                match arg.to_str() {
+                    Some("--network") => launch.network = true,
+                    Some("--write") => launch.writable.extend(args.next().map(PathBuf::from)),
+                    Some("--read") => launch.readable.extend(args.next().map(PathBuf::from)),
+                    Some("--") => {
+                        launch.command.extend(args);
+                        break;
+                    }
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("unexpected argument {:?}", arg),
+                        ))
+                    }
+                }
+            }
+            if launch.command.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "no command to run"));
+            }
+            Ok(launch)
+        }
+
+        fn run(&self) -> io::Result<i32> {
+            let container = AppContainer::open()?;
+            for dir in self.writable.iter().filter(|dir| dir.exists()) {
+                container.grant(dir, FILE_ALL_ACCESS)?;
+            }
+            for dir in self.readable.iter().filter(|dir| dir.exists()) {
+                // Directories we may not change, like Program Files, are readable to AppContainers already
+                let _ = container.grant(dir, READ_ACCESS);
+            }
+
+            let capabilities = if self.network {
+                vec![
+                    Capability::new(WinCapabilityInternetClientSid)?,
+                    Capability::new(WinCapabilityPrivateNetworkClientServerSid)?,
+                ]
+            } else {
+                Vec::new()
+            };
+            let mut attributes: Vec<SID_AND_ATTRIBUTES> = capabilities
+                .iter()
+                .map(|capability| SID_AND_ATTRIBUTES { Sid: capability.sid(), Attributes: SE_GROUP_ENABLED as u32 })
+                .collect();
+            let security = SECURITY_CAPABILITIES {
+                AppContainerSid: container.sid,
+                Capabilities: attributes.as_mut_ptr(),
+                CapabilityCount: attributes.len() as u32,
+                Reserved: 0,
+            };
+
+            spawn(&security, &mut command_line(&self.command))
+        }
+    }
+
+    /// SID of velocity's AppContainer profile
+    struct AppContainer {
+        sid: PSID,
+    }
+
+    impl AppContainer {
+        /// Create the profile, or look up the one an earlier run created
+        fn open() -> io::Result<Self> {
+            let name = wide(OsStr::new(PROFILE_NAME));
+            let description = wide(OsStr::new("Install scripts run by velocity"));
+            let mut sid = null_mut();
+            let mut result = unsafe {
+                CreateAppContainerProfile(name.as_ptr(), name.as_ptr(), description.as_ptr(), null(), 0, &mut sid)
+            };
+            if result == PROFILE_EXISTS {
+                result = unsafe { DeriveAppContainerSidFromAppContainerName(name.as_ptr(), &mut sid) };
+            }
+            if result < 0 {
+                return Err(io::Error::from_raw_os_error(result));
+            }
+            Ok(Self { sid })
+        }
+
+        /// Give the container `access` to `dir` and everything below it
+        fn grant(&self, dir: &Path, access: u32) -> io::Result<()> {
+            let path = wide(dir.as_os_str());
+            let mut dacl: *mut ACL = null_mut();
+            let mut descriptor: PSECURITY_DESCRIPTOR = null_mut();
+            win32(unsafe {
+                GetNamedSecurityInfoW(
+                    path.as_ptr(),
+                    SE_FILE_OBJECT,
+                    DACL_SECURITY_INFORMATION,
+                    null_mut(),
+                    null_mut(),
+                    &mut dacl,
+                    null_mut(),
+                    &mut descriptor,
+                )
+            })?;
+
+            // Setting an ACL walks the whole tree, so skip directories granted by an earlier run
+            let result = if self.granted(dacl, access) { Ok(()) } else { self.add_entry(&path, dacl, access) };
+            unsafe { LocalFree(descriptor) };
+            result
+        }
+
+        /// Whether `dacl` gives the container `access` already
+        fn granted(&self, dacl: *const ACL, access: u32) -> bool {
+            let mut count = 0;
+            let mut entries: *mut EXPLICIT_ACCESS_W = null_mut();
+            if unsafe { GetExplicitEntriesFromAclW(dacl, &mut count, &mut entries) } != 0 {
+                return false;
+            }
+            let granted = (0..count as usize).any(|i| {
+                let entry = unsafe { &*entries.add(i) };
+                matches!(entry.grfAccessMode, GRANT_ACCESS | SET_ACCESS)
+                    && entry.grfAccessPermissions & access == access
+                    && entry.Trustee.TrusteeForm == TRUSTEE_IS_SID
+                    && unsafe { EqualSid(entry.Trustee.ptstrName.cast(), self.sid) } != 0
+            });
+            unsafe { LocalFree(entries.cast()) };
+            granted
+        }
+
+        /// Add an inheritable entry for the container to the DACL of `path`
+        fn add_entry(&self, path: &[u16], dacl: *const ACL, access: u32) -> io::Result<()> {
+            let entry = EXPLICIT_ACCESS_W {
+                grfAccessPermissions: access,
+                grfAccessMode: GRANT_ACCESS,
+                grfInheritance: SUB_CONTAINERS_AND_OBJECTS_INHERIT,
+                Trustee: TRUSTEE_W {
+                    pMultipleTrustee: null_mut(),
+                    MultipleTrusteeOperation: NO_MULTIPLE_TRUSTEE,
+                    TrusteeForm: TRUSTEE_IS_SID,
+                    TrusteeType: TRUSTEE_IS_UNKNOWN,
+                    ptstrName: self.sid.cast(),
+                },
+            };
+            let mut updated: *mut ACL = null_mut();
+            win32(unsafe { SetEntriesInAclW(1, &entry, dacl, &mut updated) })?;
+            let result = win32(unsafe {
+                SetNamedSecurityInfoW(
+                    path.as_ptr(),
+                    SE_FILE_OBJECT,
+                    DACL_SECURITY_INFORMATION,
+                    null_mut(),
+                    null_mut(),
+                    updated,
+                    null(),
+                )
+            });
+            unsafe { LocalFree(updated.cast()) };
+            result
+        }
+    }
+
+    impl Drop for AppContainer {
+        fn drop(&mut self) {
+            unsafe { FreeSid(self.sid) };
+        }
+    }
+
+    /// A capability SID, like internetClient
+    struct Capability(Vec<u32>);
+
+    impl Capability {
+        fn new(kind: WELL_KNOWN_SID_TYPE) -> io::Result<Self> {
+            let mut size = SECURITY_MAX_SID_SIZE;
+            let mut sid = vec![0u32; size as usize / 4];
+            check(unsafe { CreateWellKnownSid(kind, null_mut(), sid.as_mut_ptr().cast(), &mut size) })?;
+            Ok(Self(sid))
+        }
+
+        fn sid(&self) -> PSID {
+            self.0.as_ptr() as PSID
+        }
+    }
+
+    /// Start `command_line` suspended in the AppContainer, then run it to completion in a job
+    fn spawn(security: &SECURITY_CAPABILITIES, command_line: &mut [u16]) -> io::Result<i32> {
+        let mut size = 0;
+        unsafe { InitializeProcThreadAttributeList(null_mut(), 1, 0, &mut size) };
+        let mut buffer = vec![0usize; size.div_ceil(size_of::<usize>())];
+        let attributes = buffer.as_mut_ptr().cast();
+        check(unsafe { InitializeProcThreadAttributeList(attributes, 1, 0, &mut size) })?;
+
+        let mut startup: STARTUPINFOEXW = unsafe { std::mem::zeroed() };
+        startup.StartupInfo.cb = size_of::<STARTUPINFOEXW>() as u32;
+        startup.StartupInfo.dwFlags = STARTF_USESTDHANDLES;
+        startup.StartupInfo.hStdInput = inheritable(STD_INPUT_HANDLE);
+        startup.StartupInfo.hStdOutput = inheritable(STD_OUTPUT_HANDLE);
+        startup.StartupInfo.hStdError = inheritable(STD_ERROR_HANDLE);
+        startup.lpAttributeList = attributes;
+
+        let mut process: PROCESS_INFORMATION = unsafe { std::mem::zeroed() };
+        let created = check(unsafe {
+            UpdateProcThreadAttribute(
+                attributes,
+                0,
+                PROC_THREAD_ATTRIBUTE_SECURITY_CAPABILITIES as usize,
+                (security as *const SECURITY_CAPABILITIES).cast(),
+                size_of::<SECURITY_CAPABILITIES>(),
+                null_mut(),
+                null(),
+            )
+        })
+        .and_then(|()| {
+            check(unsafe {
+                CreateProcessW(
+                    null(),
+                    command_line.as_mut_ptr(),
+                    null(),
+                    null(),
+                    1,
+                    EXTENDED_STARTUPINFO_PRESENT | CREATE_SUSPENDED,
+                    null(),
+                    null(),
+                    &startup.StartupInfo,
+                    &mut process,
+                )
+            })
+        });
+        unsafe { DeleteProcThreadAttributeList(attributes) };
+        created?;
+
+        let result = run_in_job(&process);
+        unsafe {
+            CloseHandle(process.hThread);
+            CloseHandle(process.hProcess);
+        }
+        result
+    }
+
+    /// Resume `process` in a job that kills whatever it leaves running, and wait for it
+    fn run_in_job(process: &PROCESS_INFORMATION) -> io::Result<i32> {
+        let job = unsafe { CreateJobObjectW(null(), null()) };
+        let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        let assigned = check((!job.is_null()) as BOOL)
+            .and_then(|()| {
+                check(unsafe {
+                    SetInformationJobObject(
+                        job,
+                        JobObjectExtendedLimitInformation,
+                        (&limits as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION).cast(),
+                        size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                    )
+                })
+            })
+            .and_then(|()| check(unsafe { AssignProcessToJobObject(job, process.hProcess) }));
+
+        let mut code = 1;
+        let result = assigned.and_then(|()| {
+            unsafe {
+                ResumeThread(process.hThread);
+                WaitForSingleObject(process.hProcess, INFINITE);
+            }
+            check(unsafe { GetExitCodeProcess(process.hProcess, &mut code) })
+        });
+        if result.is_err() {
+            unsafe { TerminateProcess(process.hProcess, 1) };
+        }
+        if !job.is_null() {
+            unsafe { CloseHandle(job) };
+        }
+        result.map(|()| code as i32)
+    }
+
+    /// Our standard handle `which`, made inheritable so the script writes to the same pipe
+    fn inheritable(which: STD_HANDLE) -> HANDLE {
+        let handle = unsafe { GetStdHandle(which) };
+        unsafe { SetHandleInformation(handle, HANDLE_FLAG_INHERIT, HANDLE_FLAG_INHERIT) };
+        handle
+    }
+
+    /// Join `args` into a NUL-terminated command line that the C runtime splits back into `args`
+    pub(super) fn command_line(args: &[OsString]) -> Vec<u16> {
+        const QUOTE: u16 = b'"' as u16;
+        const BACKSLASH: u16 = b'\\' as u16;
+
+        let mut line = Vec::new();
+        for arg in args {
+            if !line.is_empty() {
+                line.push(b' ' as u16);
+            }
+            let arg: Vec<u16> = arg.encode_wide().collect();
+            if !arg.is_empty() && !arg.iter().any(|&c| c == b' ' as u16 || c == b'\t' as u16 || c == QUOTE) {
+                line.extend(arg);
+                continue;
+            }
+
+            // Backslashes are literal unless they precede a quote
+            line.push(QUOTE);
+            let mut backslashes = 0;
+            for c in arg {
+                if c == BACKSLASH {
+                    backslashes += 1;
+                } else {
+                    if c == QUOTE {
+                        line.extend(std::iter::repeat_n(BACKSLASH, backslashes + 1));
+                    }
+                    backslashes = 0;
+                }
+                line.push(c);
+            }
+            line.extend(std::iter::repeat_n(BACKSLASH, backslashes));
+            line.push(QUOTE);
+        }
+        line.push(0);
+        line
+    }
+
+    fn wide(s: &OsStr) -> Vec<u16> {
+        s.encode_wide().chain(Some(0)).collect()
+    }
+
+    fn win32(error: WIN32_ERROR) -> io::Result<()> {
+        match error {
+            0 => Ok(()),
+            error => Err(io::Error::from_raw_os_error(error as i32)),
+        }
+    }
+
+    fn check(ok: BOOL) -> io::Result<()> {
+        if ok != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod backend {
+    use tokio::process::Command;
+
+    use super::Isolation;
+
+    pub fn can_deny_network(_restricts_filesystem: bool) -> bool {
+        false
+    }
+
+    pub fn can_restrict_filesystem() -> bool {
+        false
+    }
+
+    pub fn command(_isolation: &Isolation, program: &str) -> Command {
+        Command::new(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::SecurityConfig;
+    use crate::security::permissions::PackagePermissions;
+
+    #[test]
+    fn test_isolation_for_package() {
+        let mut config = SecurityConfig {
+            trusted_packages: vec!["esbuild".to_string()],
+            ..Default::default()
+        };
+        config.permissions.insert(
+            "sharp".to_string(),
+            PackagePermissions { network: Some(PermissionDecision::Allow), ..Default::default() },
+        );
+        let permissions = PermissionManager::new(&config);
+        let dir = Path::new("/project/node_modules/pkg");
+        let project = [Path::new("/project")];
+
+        assert_eq!(Isolation::for_package(&permissions, "esbuild", dir, &project), None);
+
+        let sharp = Isolation::for_package(&permissions, "sharp", dir, &project).unwrap();
+        assert!(sharp.network);
+        assert!(sharp.writable.unwrap().contains(&dir.to_path_buf()));
+        let readable = sharp.readable.unwrap();
+        assert!(readable.contains(&PathBuf::from("/project")));
+        #[cfg(unix)]
+        assert!(readable.contains(&PathBuf::from("/usr")));
+
+        let other = Isolation::for_package(&permissions, "left-pad", dir, &project).unwrap();
+        assert!(!other.network);
+        assert!(other.writable.is_some());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_isolated_script_cannot_write_outside() {
+        let isolation_root = tempfile::tempdir().unwrap();
+        let package_dir = isolation_root.path().join("pkg");
+        std::fs::create_dir(&package_dir).unwrap();
+        // Somewhere outside both the package and the temp directory
+        let outside = tempfile::tempdir_in(std::env::current_dir().unwrap()).unwrap();
+
+        let isolation = Isolation {
+            writable: Some(vec![package_dir.clone(), PathBuf::from("/dev")]),
+            readable: None,
+            network: true,
+        };
+        if !isolation.unsupported().is_empty() {
+            return;
+        }
+
+        let script = format!(
+            "echo ok > '{}/inside' && echo no > '{}/outside'",
+            package_dir.display(),
+            outside.path().display()
+        );
+        let status = isolation.command("sh").arg("-c").arg(script).status().await.unwrap();
+
+        assert!(!status.success());
+        assert!(package_dir.join("inside").exists());
+        assert!(!outside.path().join("outside").exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_isolated_script_cannot_read_outside() {
+        let project = tempfile::tempdir().unwrap();
+        let package_dir = project.path().join("node_modules/pkg");
+        std::fs::create_dir_all(&package_dir).unwrap();
+        std::fs::write(project.path().join("index.js"), "ok").unwrap();
+        // Stands in for ~/.npmrc: outside the project and the temp directory
+        let home = tempfile::tempdir_in(std::env::current_dir().unwrap()).unwrap();
+        std::fs::write(home.path().join(".npmrc"), "secret").unwrap();
+
+        let mut isolation = Isolation::for_package(
+            &PermissionManager::new(&SecurityConfig::default()),
+            "pkg",
+            &package_dir,
+            &[project.path()],
+        )
+        .unwrap();
+        isolation.network = true;
+        if !isolation.unsupported().is_empty() {
+            return;
+        }
+
+        let read = |path: PathBuf| {
+            let mut command = isolation.command("cat");
+            command.arg(path).stdout(std::process::Stdio::null()).stderr(std::process::Stdio::null());
+            command
+        };
+        assert!(read(project.path().join("index.js")).status().await.unwrap().success());
+        assert!(!read(home.path().join(".npmrc")).status().await.unwrap().success());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_isolated_script_cannot_truncate_outside() {
+        // truncate(2) by path, which Landlock only restricts from ABI 3 on
+        let Ok(perl) = which::which("perl") else {
+            return;
+        };
+        // Readable but outside the temp directory, which scripts may write
+        let project = tempfile::tempdir_in(std::env::current_dir().unwrap()).unwrap();
+        let package_dir = project.path().join("node_modules/pkg");
+        std::fs::create_dir_all(&package_dir).unwrap();
+        let readable = project.path().join("index.js");
+        std::fs::write(&readable, "keep").unwrap();
+
+        let mut isolation = Isolation::for_package(
+            &PermissionManager::new(&SecurityConfig::default()),
+            "pkg",
+            &package_dir,
+            &[project.path()],
+        )
+        .unwrap();
+        isolation.network = true;
+        if !isolation.unsupported().is_empty() || backend::abi_version() < 3 {
+            return;
+        }
+
+        let status = isolation
+            .command(&perl.display().to_string())
+            .arg("-e")
+            .arg("truncate($ARGV[0], 0) or exit 1")
+            .arg(&readable)
+            .status()
+            .await
+            .unwrap();
+
+        assert!(!status.success());
+        assert_eq!(std::fs::read_to_string(&readable).unwrap(), "keep");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_launcher_arguments_round_trip() {
+        let isolation = Isolation {
+            writable: Some(vec![PathBuf::from(r"C:\project\node_modules\pkg")]),
+            readable: Some(vec![PathBuf::from(r"C:\project")]),
+            network: true,
+        };
+        let command = isolation.command("cmd.exe");
+        let args: Vec<_> = command.as_std().get_args().map(|arg| arg.to_os_string()).collect();
+        let Some(start) = args.iter().position(|arg| arg == backend::LAUNCHER_ARG) else {
+            // No AppContainer support here, so the script runs directly
+            return;
+        };
+
+        let launch = backend::Launch::parse(args[start + 1..].iter().cloned()).unwrap();
+        assert!(launch.network);
+        assert_eq!(launch.writable, vec![PathBuf::from(r"C:\project\node_modules\pkg")]);
+        assert_eq!(launch.readable, vec![PathBuf::from(r"C:\project")]);
+        assert_eq!(launch.command, vec![std::ffi::OsString::from("cmd.exe")]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_command_line_quoting() {
+        let args = ["cmd.exe", "/c", r#"node "install.js" C:\dir\"#, ""].map(std::ffi::OsString::from);
+        let line = String::from_utf16(&backend::command_line(&args)).unwrap();
+        assert_eq!(line, "cmd.exe /c \"node \\\"install.js\\\" C:\\dir\\\\\" \"\"\0");
+    }
+}
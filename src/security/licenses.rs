@@ -0,0 +1,444 @@
+//! License compliance: collecting dependency licenses, checking them against
+//! the `[licenses]` policy and exporting them as an SPDX document
+
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+
+use super::provenance::package_url;
+use crate::cache::CacheManager;
+use crate::core::config::LicenseConfig;
+use crate::core::lockfile::LockedPackage;
+use crate::registry::RegistryClient;
+
+/// License declared by a locked package
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageLicense {
+    pub name: String,
+    pub version: String,
+    /// SPDX expression, `None` when the package declares no license
+    pub license: Option<String>,
+    pub dev: bool,
+    #[serde(skip)]
+    pub resolved: String,
+    #[serde(skip)]
+    pub integrity: String,
+}
+
+/// A package whose license breaks the policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LicenseViolation {
+    pub package: String,
+    pub version: String,
+    pub license: Option<String>,
+    pub reason: String,
+}
+
+impl std::fmt::Display for LicenseViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}@{} ({}): {}",
+            self.package,
+            self.version,
+            self.license.as_deref().unwrap_or("no license"),
+            self.reason
+        )
+    }
+}
+
+/// Look up the license of every package, from registry metadata or, when
+/// that is unavailable, the package.json of the extracted copy in the cache
+pub async fn collect<'a, I>(
+    registry: &RegistryClient,
+    cache: &CacheManager,
+    packages: I,
+    concurrency: usize,
+) -> Vec<PackageLicense>
+where
+    I: IntoIterator<Item = &'a LockedPackage>,
+{
+    let mut licenses: Vec<PackageLicense> = stream::iter(packages)
+        .map(|locked| async move {
+            let real_name = locked.alias_of.as_deref().unwrap_or(&locked.name);
+            let from_registry = match registry.get_package_metadata(real_name).await {
+                Ok(metadata) => metadata
+                    .versions
+                    .get(&locked.version)
+                    .and_then(|version| version.license.as_ref())
+                    .and_then(declared_license),
+                Err(_) => None,
+            };
+            let license = from_registry.or_else(|| {
+                let manifest = cache.get_package_dir(real_name, &locked.version).join("package.json");
+                let content = std::fs::read_to_string(manifest).ok()?;
+                let manifest: serde_json::Value = serde_json::from_str(&content).ok()?;
+                manifest_license(&manifest)
+            });
+
+            PackageLicense {
+                name: locked.name.clone(),
+                version: locked.version.clone(),
+                license,
+                dev: locked.dev,
+                resolved: locked.resolved.clone(),
+                integrity: locked.integrity.clone(),
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
+    licenses.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+    licenses
+}
+
+/// License declared by a package.json, including the legacy `licenses` array
+pub fn manifest_license(manifest: &serde_json::Value) -> Option<String> {
+    if let Some(license) = manifest.get("license").and_then(declared_license) {
+        return Some(license);
+    }
+    let legacy: Vec<String> = manifest.get("licenses")?.as_array()?.iter().filter_map(declared_license).collect();
+    match legacy.len() {
+        0 => None,
+        1 => legacy.into_iter().next(),
+        _ => Some(format!("({})", legacy.join(" OR "))),
+    }
+}
+
+/// A `license` value: an SPDX string or a legacy `{ "type": ... }` object
+fn declared_license(value: &serde_json::Value) -> Option<String> {
+    let license = match value {
+        serde_json::Value::String(license) => license.as_str(),
+        serde_json::Value::Object(object) => object.get("type")?.as_str()?,
+        _ => return None,
+    };
+    let license = license.trim();
+    (!license.is_empty()).then(|| license.to_string())
+}
+
+/// Whether two SPDX identifiers name the same license, ignoring case and the
+/// `-only`/`-or-later`/`+` variants
+pub fn same_license(a: &str, b: &str) -> bool {
+    normalize_id(a) == normalize_id(b)
+}
+
+fn normalize_id(id: &str) -> String {
+    let id = id.trim().to_ascii_lowercase();
+    let id = id.strip_suffix('+').unwrap_or(&id);
+    let id = id.strip_suffix("-only").or_else(|| id.strip_suffix("-or-later")).unwrap_or(id);
+    id.to_string()
+}
+
+/// Whether an SPDX expression can be satisfied using only licenses accepted
+/// by `accept`
+///
+/// An `OR` needs one acceptable side and an `AND` needs both; `WITH`
+/// exceptions are judged by their license. Text that does not parse as an
+/// expression (such as `SEE LICENSE IN LICENSE.md`) is passed to `accept`
+/// whole.
+pub fn satisfies(expression: &str, accept: impl Fn(&str) -> bool) -> bool {
+    let tokens = tokenize(expression);
+    let mut parser = Parser { tokens: &tokens, pos: 0, accept: &accept };
+    match parser.or() {
+        Some(result) if parser.pos == tokens.len() => result,
+        _ => accept(expression.trim()),
+    }
+}
+
+/// Licenses named in an SPDX expression, without operators or exceptions
+pub fn license_ids(expression: &str) -> Vec<String> {
+    let tokens = tokenize(expression);
+    let mut ids = Vec::new();
+    let mut after_with = false;
+    for token in &tokens {
+        match token.to_ascii_uppercase().as_str() {
+            "(" | ")" | "AND" | "OR" => after_with = false,
+            "WITH" => after_with = true,
+            _ if after_with => after_with = false,
+            _ => ids.push(token.to_string()),
+        }
+    }
+    ids
+}
+
+fn tokenize(expression: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in expression.char_indices() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if let Some(s) = start.take() {
+                tokens.push(&expression[s..i]);
+            }
+            if !c.is_whitespace() {
+                tokens.push(&expression[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&expression[s..]);
+    }
+    tokens
+}
+
+struct Parser<'a, F> {
+    tokens: &'a [&'a str],
+    pos: usize,
+    accept: &'a F,
+}
+
+impl<F: Fn(&str) -> bool> Parser<'_, F> {
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let matches = self.tokens.get(self.pos).is_some_and(|t| t.eq_ignore_ascii_case(keyword));
+        if matches {
+            self.pos += 1;
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Option<bool> {
+        let mut result = self.and()?;
+        while self.keyword("OR") {
+            result |= self.and()?;
+        }
+        Some(result)
+    }
+
+    fn and(&mut self) -> Option<bool> {
+        let mut result = self.term()?;
+        while self.keyword("AND") {
+            result &= self.term()?;
+        }
+        Some(result)
+    }
+
+    fn term(&mut self) -> Option<bool> {
+        if self.keyword("(") {
+            let result = self.or()?;
+            return self.keyword(")").then_some(result);
+        }
+        let id = *self.tokens.get(self.pos)?;
+        if [")", "AND", "OR", "WITH"].iter().any(|k| id.eq_ignore_ascii_case(k)) {
+            return None;
+        }
+        self.pos += 1;
+        if self.keyword("WITH") {
+            self.tokens.get(self.pos)?;
+            self.pos += 1;
+        }
+        Some((self.accept)(id))
+    }
+}
+
+/// The `[licenses]` policy from velocity.toml
+pub struct LicensePolicy<'a> {
+    config: &'a LicenseConfig,
+}
+
+impl<'a> LicensePolicy<'a> {
+    pub fn new(config: &'a LicenseConfig) -> Self {
+        Self { config }
+    }
+
+    /// Whether a single license is acceptable on its own
+    fn accepts(&self, id: &str) -> bool {
+        let listed = |list: &[String]| list.iter().any(|license| same_license(license, id));
+        !listed(&self.config.deny) && (self.config.allow.is_empty() || listed(&self.config.allow))
+    }
+
+    /// The violation a package's license causes, if any
+    pub fn check(&self, package: &PackageLicense) -> Option<LicenseViolation> {
+        if self.config.exceptions.iter().any(|name| name == &package.name) {
+            return None;
+        }
+
+        let reason = match package.license {
+            None if self.config.deny_unknown => "declares no license".to_string(),
+            None => return None,
+            Some(ref license) if satisfies(license, |id| self.accepts(id)) => return None,
+            Some(ref license) => {
+                let denied = license_ids(license)
+                    .into_iter()
+                    .find(|id| self.config.deny.iter().any(|denied| same_license(denied, id)));
+                match denied {
+                    Some(id) => format!("{} is denied", id),
+                    None => "not in the allowed licenses".to_string(),
+                }
+            }
+        };
+
+        Some(LicenseViolation {
+            package: package.name.clone(),
+            version: package.version.clone(),
+            license: package.license.clone(),
+            reason,
+        })
+    }
+
+    /// Every violation among `packages`
+    pub fn violations(&self, packages: &[PackageLicense]) -> Vec<LicenseViolation> {
+        packages.iter().filter_map(|package| self.check(package)).collect()
+    }
+}
+
+/// SPDX 2.3 document describing a project and its dependencies
+pub fn spdx_document(project_name: &str, project_version: &str, packages: &[PackageLicense]) -> serde_json::Value {
+    let root_id = spdx_id(project_name, project_version);
+    let mut spdx_packages = vec![serde_json::json!({
+        "SPDXID": root_id,
+        "name": project_name,
+        "versionInfo": project_version,
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": false,
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": "NOASSERTION",
+        "copyrightText": "NOASSERTION",
+    })];
+    let mut relationships = vec![serde_json::json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": root_id,
+    })];
+
+    for package in packages {
+        let id = spdx_id(&package.name, &package.version);
+        let mut entry = serde_json::json!({
+            "SPDXID": id,
+            "name": package.name,
+            "versionInfo": package.version,
+            "downloadLocation": if package.resolved.is_empty() { "NOASSERTION" } else { package.resolved.as_str() },
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": package.license.as_deref().filter(|l| is_spdx_expression(l)).unwrap_or("NOASSERTION"),
+            "copyrightText": "NOASSERTION",
+            "externalRefs": [{
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": package_url(&package.name, &package.version),
+            }],
+        });
+        if let Some(checksum) = sha512_hex(&package.integrity) {
+            entry["checksums"] = serde_json::json!([{ "algorithm": "SHA512", "checksumValue": checksum }]);
+        }
+        spdx_packages.push(entry);
+        // DEV_DEPENDENCY_OF points from the dependency to the package depending on it
+        relationships.push(if package.dev {
+            serde_json::json!({ "spdxElementId": id, "relationshipType": "DEV_DEPENDENCY_OF", "relatedSpdxElement": root_id })
+        } else {
+            serde_json::json!({ "spdxElementId": root_id, "relationshipType": "DEPENDS_ON", "relatedSpdxElement": id })
+        });
+    }
+
+    serde_json::json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}@{}", project_name, project_version),
+        "documentNamespace": format!("https://velocity.dev/spdx/{}-{}", project_name.replace('/', "-"), uuid::Uuid::new_v4()),
+        "creationInfo": {
+            "created": chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            "creators": [format!("Tool: velocity-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": spdx_packages,
+        "relationships": relationships,
+    })
+}
+
+/// SPDX element identifiers allow only letters, digits, `.` and `-`
+fn spdx_id(name: &str, version: &str) -> String {
+    let clean = |s: &str| s.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '-' }).collect::<String>();
+    format!("SPDXRef-Package-{}-{}", clean(name.trim_start_matches('@')), clean(version))
+}
+
+/// Whether a declared license reads as an SPDX expression rather than free text
+fn is_spdx_expression(license: &str) -> bool {
+    let tokens = tokenize(license);
+    let mut parser = Parser { tokens: &tokens, pos: 0, accept: &|id: &str| {
+        id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+' | ':'))
+    } };
+    matches!(parser.or(), Some(true)) && parser.pos == tokens.len()
+}
+
+/// Hex digest of an `sha512-<base64>` integrity string
+fn sha512_hex(integrity: &str) -> Option<String> {
+    use base64::Engine as _;
+    let digest = integrity.split_whitespace().find_map(|hash| hash.strip_prefix("sha512-"))?;
+    base64::engine::general_purpose::STANDARD.decode(digest).ok().map(hex::encode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, license: Option<&str>) -> PackageLicense {
+        PackageLicense {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            license: license.map(str::to_string),
+            dev: false,
+            resolved: String::new(),
+            integrity: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_satisfies() {
+        let mit_only = |id: &str| same_license(id, "MIT");
+        assert!(satisfies("MIT", mit_only));
+        assert!(satisfies("(MIT OR GPL-3.0-only)", mit_only));
+        assert!(!satisfies("MIT AND GPL-3.0", mit_only));
+        assert!(satisfies("(MIT AND Apache-2.0) OR MIT", mit_only));
+        assert!(satisfies("MIT WITH LLVM-exception", mit_only));
+        assert!(!satisfies("SEE LICENSE IN LICENSE.md", mit_only));
+        assert!(same_license("GPL-3.0", "gpl-3.0-or-later"));
+        assert!(same_license("GPL-2.0+", "GPL-2.0-only"));
+        assert_eq!(license_ids("(MIT OR Apache-2.0 WITH LLVM-exception)"), ["MIT", "Apache-2.0"]);
+    }
+
+    #[test]
+    fn test_policy() {
+        let config = LicenseConfig {
+            allow: vec!["MIT".to_string(), "ISC".to_string(), "Apache-2.0".to_string()],
+            deny: vec!["GPL-3.0".to_string()],
+            exceptions: vec!["legacy".to_string()],
+            deny_unknown: true,
+            strict: false,
+        };
+        let policy = LicensePolicy::new(&config);
+
+        assert_eq!(policy.check(&package("a", Some("MIT"))), None);
+        assert_eq!(policy.check(&package("a", Some("MIT OR GPL-3.0-only"))), None);
+        assert_eq!(policy.check(&package("legacy", Some("GPL-3.0"))), None);
+
+        let violation = policy.check(&package("b", Some("GPL-3.0-or-later"))).unwrap();
+        assert_eq!(violation.reason, "GPL-3.0-or-later is denied");
+        let violation = policy.check(&package("c", Some("BSD-3-Clause"))).unwrap();
+        assert_eq!(violation.reason, "not in the allowed licenses");
+        let violation = policy.check(&package("d", None)).unwrap();
+        assert_eq!(violation.reason, "declares no license");
+    }
+
+    #[test]
+    fn test_manifest_license() {
+        let legacy = serde_json::json!({ "licenses": [{ "type": "MIT" }, { "type": "Apache-2.0" }] });
+        assert_eq!(manifest_license(&legacy).as_deref(), Some("(MIT OR Apache-2.0)"));
+        let object = serde_json::json!({ "license": { "type": "ISC", "url": "https://example.com" } });
+        assert_eq!(manifest_license(&object).as_deref(), Some("ISC"));
+        assert_eq!(manifest_license(&serde_json::json!({ "license": "" })), None);
+    }
+
+    #[test]
+    fn test_spdx_document() {
+        let mut dep = package("@scope/dep", Some("SEE LICENSE IN LICENSE"));
+        dep.integrity = "sha512-AAEC".to_string();
+        let document = spdx_document("app", "1.0.0", &[dep]);
+
+        let entry = &document["packages"][1];
+        assert_eq!(entry["SPDXID"], "SPDXRef-Package-scope-dep-1.0.0");
+        assert_eq!(entry["licenseDeclared"], "NOASSERTION");
+        assert_eq!(entry["checksums"][0]["checksumValue"], "000102");
+        assert_eq!(entry["externalRefs"][0]["referenceLocator"], "pkg:npm/%40scope/dep@1.0.0");
+        assert_eq!(document["relationships"][1]["relationshipType"], "DEPENDS_ON");
+    }
+}
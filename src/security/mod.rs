@@ -1,16 +1,28 @@
 //! Security module for Velocity
 
+pub mod advisories;
+pub mod budget;
+pub mod cooldown;
+pub mod core_modules;
 pub mod ecosystem;
 pub mod integrity;
+pub mod isolation;
+pub mod licenses;
 pub mod permissions;
+pub mod policy;
+pub mod provenance;
 pub mod sandbox;
 pub mod supply_chain;
 
-use crate::core::VelocityResult;
-use crate::core::config::SecurityConfig;
+use crate::core::{VelocityError, VelocityResult};
+use crate::core::config::{ScriptSandboxMode, SecurityConfig};
 
+pub use budget::{AddedPackage, BudgetReport};
+pub use cooldown::ReleaseCooldown;
+pub use isolation::Isolation;
 pub use ecosystem::{EcosystemAnalyzer, EcosystemCategory, SecurityLevel};
 pub use permissions::PermissionManager;
+pub use policy::Policy;
 pub use supply_chain::{SupplyChainGuard, SecurityAnalysis, RiskLevel};
 
 /// Security manager for enforcing security policies
@@ -28,6 +40,54 @@ impl SecurityManager {
         }
     }
 
+    /// Time limit for a single install script
+    pub fn script_timeout(&self) -> Option<std::time::Duration> {
+        match self.config.script_timeout {
+            0 => None,
+            secs => Some(std::time::Duration::from_secs(secs)),
+        }
+    }
+
+    /// Cap on captured output per script stream
+    pub fn script_output_limit(&self) -> Option<usize> {
+        match self.config.script_output_limit {
+            0 => None,
+            bytes => Some(bytes),
+        }
+    }
+
+    /// Cap on the number of files a package tarball unpacks to
+    pub fn max_package_files(&self) -> Option<usize> {
+        match self.config.max_package_files {
+            0 => None,
+            files => Some(files),
+        }
+    }
+
+    /// Cap on the bytes a package tarball unpacks to
+    pub fn max_package_size(&self) -> Option<u64> {
+        match self.config.max_package_size {
+            0 => None,
+            bytes => Some(bytes),
+        }
+    }
+
+    /// How install scripts of untrusted packages are isolated
+    pub fn script_sandbox(&self) -> ScriptSandboxMode {
+        self.config.script_sandbox
+    }
+
+    /// OS-level restrictions for scripts of `package` run in `package_dir`,
+    /// which may also read below `project_dirs`
+    pub fn script_isolation(
+        &self,
+        package: &str,
+        package_dir: &std::path::Path,
+        project_dirs: &[&std::path::Path],
+    ) -> Option<Isolation> {
+        Isolation::for_package(&self.permissions, package, package_dir, project_dirs)
+    }
+
     /// Check if a package is allowed to be installed
     pub fn verify_package_allowed(&self, name: &str) -> VelocityResult<()> {
         // Check trusted packages/scopes
@@ -43,6 +103,34 @@ impl SecurityManager {
         Ok(())
     }
 
+    /// Find direct dependencies that shadow Node.js core modules
+    ///
+    /// Returns the offending names so callers can warn about them, or an
+    /// error for the first one when `security.block_core_shadowing` is set.
+    /// Only names the project itself declares are checked.
+    pub fn check_core_shadowing<'a, I>(&self, names: I) -> VelocityResult<Vec<String>>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut shadowing: Vec<String> = names
+            .into_iter()
+            .filter(|name| core_modules::shadows_core_module(name) && !self.is_trusted(name))
+            .map(str::to_string)
+            .collect();
+        shadowing.sort();
+        shadowing.dedup();
+
+        if self.config.block_core_shadowing {
+            if let Some(package) = shadowing.first() {
+                return Err(VelocityError::CoreModuleShadowing {
+                    package: package.clone(),
+                });
+            }
+        }
+
+        Ok(shadowing)
+    }
+
     /// Check if a package is trusted
     pub fn is_trusted(&self, name: &str) -> bool {
         // Check exact package name
@@ -108,6 +196,16 @@ impl SecurityManager {
         Ok(false)
     }
 
+    /// Whether provenance of packages new to the lockfile is checked
+    pub fn verify_provenance(&self) -> bool {
+        self.config.verify_provenance
+    }
+
+    /// The release cooldown in effect, if any
+    pub fn release_cooldown(&self) -> Option<ReleaseCooldown> {
+        ReleaseCooldown::from_config(&self.config)
+    }
+
     /// Check if audit is required on install
     pub fn audit_on_install(&self) -> bool {
         self.config.audit_on_install
@@ -22,6 +22,7 @@ pub enum Permission {
 
 /// Permission decision
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PermissionDecision {
     /// Permission granted
     Allow,
@@ -61,7 +62,7 @@ impl PermissionManager {
     pub fn new(config: &SecurityConfig) -> Self {
         Self {
             config: config.clone(),
-            package_permissions: HashMap::new(),
+            package_permissions: config.permissions.clone(),
             cached_decisions: parking_lot::RwLock::new(HashMap::new()),
         }
     }
@@ -0,0 +1,268 @@
+//! Package policy: which packages, versions and registries installs may use
+//!
+//! Rules come from the `[policy]` table of velocity.toml and, when `policy.url`
+//! is set, a JSON document with the same fields that an organization publishes
+//! centrally. Remote rules add to the local ones. The remote document is cached
+//! for `cache.metadata_ttl` seconds; when it cannot be fetched an older copy is
+//! used, and without one the policy cannot be enforced and commands fail.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use crate::core::config::{Config, PolicyConfig};
+use crate::core::{Lockfile, VelocityError, VelocityResult};
+use crate::registry::http::{self, InsecureOrigins};
+use crate::resolver::VersionConstraint;
+use crate::utils::parse_package_spec;
+
+/// A package entry of the policy
+#[derive(Debug, Clone)]
+struct Rule {
+    /// The entry as written, for messages
+    text: String,
+    /// Where the entry comes from (velocity.toml or the policy URL)
+    source: String,
+    /// Package name, or `@scope/*`
+    name: String,
+    /// Versions the entry covers; all when `None`
+    range: Option<VersionConstraint>,
+}
+
+impl Rule {
+    fn parse(text: &str, source: &str) -> VelocityResult<Self> {
+        let (name, range) = parse_package_spec(text.trim());
+        let range = range
+            .map(|range| VersionConstraint::parse_strict(&range))
+            .transpose()
+            .map_err(|e| VelocityError::config(format!("Invalid policy entry '{}' in {}: {}", text, source, e)))?;
+        Ok(Self {
+            text: text.trim().to_string(),
+            source: source.to_string(),
+            name,
+            range,
+        })
+    }
+
+    fn covers_name(&self, name: &str) -> bool {
+        match self.name.strip_suffix("/*") {
+            Some(scope) => name.split('/').next() == Some(scope) && name.contains('/'),
+            None => self.name == name,
+        }
+    }
+
+    fn covers(&self, name: &str, version: &str) -> bool {
+        self.covers_name(name)
+            && match (&self.range, semver::Version::parse(version)) {
+                (None, _) => true,
+                (Some(range), Ok(version)) => range.matches(&version),
+                (Some(_), Err(_)) => false,
+            }
+    }
+
+    fn describe(&self) -> String {
+        format!("'{}' ({})", self.text, self.source)
+    }
+}
+
+/// The package policy in effect
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    blocked: Vec<Rule>,
+    allowed: Vec<Rule>,
+    /// Allowed registry URLs, with where each comes from
+    allowed_registries: Vec<(String, String)>,
+}
+
+impl Policy {
+    /// The rules of a `[policy]` table (or remote document) from `source`
+    pub fn from_config(config: &PolicyConfig, source: &str) -> VelocityResult<Self> {
+        let rules = |entries: &[String]| -> VelocityResult<Vec<Rule>> {
+            entries.iter().map(|entry| Rule::parse(entry, source)).collect()
+        };
+        Ok(Self {
+            blocked: rules(&config.blocked)?,
+            allowed: rules(&config.allowed)?,
+            allowed_registries: config
+                .allowed_registries
+                .iter()
+                .map(|url| (url.trim_end_matches('/').to_string(), source.to_string()))
+                .collect(),
+        })
+    }
+
+    /// The policy of `config`, including the remote document when one is configured
+    pub async fn load(config: &Config, cache_dir: &Path) -> VelocityResult<Self> {
+        let mut policy = Self::from_config(&config.policy, "velocity.toml")?;
+        if let Some(ref url) = config.policy.url {
+            let remote: PolicyConfig = serde_json::from_slice(&fetch_remote(config, url, cache_dir).await?)
+                .map_err(|e| VelocityError::config(format!("Invalid policy document at {}: {}", url, e)))?;
+            policy.extend(Self::from_config(&remote, url)?);
+        }
+        Ok(policy)
+    }
+
+    /// Add the rules of `other`
+    pub fn extend(&mut self, other: Policy) {
+        self.blocked.extend(other.blocked);
+        self.allowed.extend(other.allowed);
+        self.allowed_registries.extend(other.allowed_registries);
+    }
+
+    /// Whether there are no rules at all
+    pub fn is_empty(&self) -> bool {
+        self.blocked.is_empty() && self.allowed.is_empty() && self.allowed_registries.is_empty()
+    }
+
+    /// Why `version` of `name` may not be installed, if it may not
+    ///
+    /// Covers the blocklist and allowlist; where the tarball comes from is checked by [`check`](Self::check).
+    pub fn refusal(&self, name: &str, version: &str) -> Option<String> {
+        if let Some(rule) = self.blocked.iter().find(|rule| rule.covers(name, version)) {
+            return Some(format!("blocked by policy rule {}", rule.describe()));
+        }
+        if !self.allowed.is_empty() && !self.allowed.iter().any(|rule| rule.covers(name, version)) {
+            let sources: Vec<&str> = self.allowed.iter().map(|rule| rule.source.as_str()).collect();
+            return Some(format!("not in policy.allowed ({})", dedup(sources).join(", ")));
+        }
+        None
+    }
+
+    /// Check a package chosen for installation, downloaded from `tarball`
+    pub fn check(&self, name: &str, version: &str, tarball: &str) -> VelocityResult<()> {
+        let violation = |reason: String| VelocityError::PolicyViolation {
+            package: format!("{}@{}", name, version),
+            reason,
+        };
+
+        if let Some(reason) = self.refusal(name, version) {
+            return Err(violation(reason));
+        }
+
+        // Local tarballs (file: specs) have no registry to check
+        let remote = tarball.starts_with("http://") || tarball.starts_with("https://");
+        if remote
+            && !self.allowed_registries.is_empty()
+            && !self.allowed_registries.iter().any(|(registry, _)| is_below(tarball, registry))
+        {
+            let sources: Vec<&str> = self.allowed_registries.iter().map(|(_, source)| source.as_str()).collect();
+            return Err(violation(format!(
+                "{} is not on a registry in policy.allowed_registries ({})",
+                tarball,
+                dedup(sources).join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Check every package of a lockfile
+    pub fn check_lockfile(&self, lockfile: &Lockfile) -> VelocityResult<()> {
+        lockfile
+            .packages
+            .iter()
+            .try_for_each(|locked| self.check(locked.package_name(), &locked.version, &locked.resolved))
+    }
+}
+
+/// Whether `url` is `base` or below it
+fn is_below(url: &str, base: &str) -> bool {
+    url.strip_prefix(base).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn dedup(mut items: Vec<&str>) -> Vec<&str> {
+    items.dedup();
+    items
+}
+
+/// The remote policy document, from the cache while it is fresh
+async fn fetch_remote(config: &Config, url: &str, cache_dir: &Path) -> VelocityResult<Vec<u8>> {
+    let cached = cached_policy_path(cache_dir, url);
+    let age = std::fs::metadata(&cached)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+    let fresh = age.is_some_and(|age| age < Duration::from_secs(config.cache.metadata_ttl));
+    if fresh || (config.cache.offline && age.is_some()) {
+        return Ok(std::fs::read(&cached)?);
+    }
+    if config.cache.offline {
+        return Err(VelocityError::Offline {
+            package: "policy".to_string(),
+            resource: url.to_string(),
+        });
+    }
+
+    match download(config, url).await {
+        Ok(body) => {
+            if let Some(parent) = cached.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&cached, &body)?;
+            Ok(body)
+        }
+        Err(e) if age.is_some() => {
+            tracing::warn!("Could not refresh the policy from {} ({}); using the cached copy", url, e);
+            Ok(std::fs::read(&cached)?)
+        }
+        Err(e) => Err(VelocityError::config(format!("Could not load the policy from {}: {}", url, e))),
+    }
+}
+
+async fn download(config: &Config, url: &str) -> VelocityResult<Vec<u8>> {
+    InsecureOrigins::from_config(&config.network.allow_insecure_registries).check(url)?;
+    let client = http::client_builder_for(&config.network, Duration::from_secs(config.network.timeout), url)?
+        .build()
+        .map_err(|e| VelocityError::Network(e.to_string()))?;
+
+    let response = client.get(url).send().await.map_err(http::send_error)?;
+    if !response.status().is_success() {
+        return Err(VelocityError::Network(format!("HTTP {}", response.status())));
+    }
+    let body = response.bytes().await.map_err(|e| VelocityError::Network(e.to_string()))?;
+    Ok(body.to_vec())
+}
+
+fn cached_policy_path(cache_dir: &Path, url: &str) -> PathBuf {
+    cache_dir.join("policy").join(format!("{}.json", hex::encode(Sha256::digest(url.as_bytes()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_rules() {
+        let config = PolicyConfig {
+            blocked: vec!["event-stream".to_string(), "ua-parser-js@0.7.29 || 0.8.0".to_string(), "@evil/*".to_string()],
+            allowed_registries: vec!["https://registry.npmjs.org/".to_string()],
+            ..Default::default()
+        };
+        let policy = Policy::from_config(&config, "velocity.toml").unwrap();
+
+        assert!(policy.refusal("event-stream", "3.3.6").unwrap().contains("'event-stream' (velocity.toml)"));
+        assert!(policy.refusal("ua-parser-js", "0.7.29").is_some());
+        assert!(policy.refusal("ua-parser-js", "0.7.28").is_none());
+        assert!(policy.refusal("@evil/pkg", "1.0.0").is_some());
+        assert!(policy.refusal("@evilcorp/pkg", "1.0.0").is_none());
+
+        let tarball = "https://registry.npmjs.org/lodash/-/lodash-4.17.21.tgz";
+        assert!(policy.check("lodash", "4.17.21", tarball).is_ok());
+        let err = policy.check("lodash", "4.17.21", "https://registry.npmjs.org.evil.com/lodash.tgz").unwrap_err();
+        assert!(err.to_string().contains("policy.allowed_registries"));
+
+        // A remote allowlist restricts everything not on it
+        let remote = PolicyConfig {
+            allowed: vec!["@acme/*".to_string(), "lodash@^4".to_string()],
+            ..Default::default()
+        };
+        let mut policy = policy;
+        policy.extend(Policy::from_config(&remote, "https://policy.example.com").unwrap());
+        assert!(policy.refusal("lodash", "4.17.21").is_none());
+        assert!(policy.refusal("lodash", "3.10.1").unwrap().contains("https://policy.example.com"));
+        assert!(policy.refusal("@acme/tool", "1.0.0").is_none());
+
+        assert!(Policy::from_config(&PolicyConfig { blocked: vec!["x@not a range!".to_string()], ..Default::default() }, "velocity.toml").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Build provenance of installed packages
+//!
+//! A version whose `dist.attestations` points at a Sigstore bundle must carry
+//! a SLSA provenance statement naming exactly that tarball (its purl and
+//! sha512), and the repository and commit it names are recorded in the
+//! lockfile. velocity ships no Sigstore trust root, so neither the DSSE
+//! signature nor the certificate chain is checked: anything between the
+//! registry and the client could forge the bundle. The recorded source is
+//! therefore unverified.
+
+use base64::Engine as _;
+use futures::stream::{self, StreamExt};
+
+use crate::core::lockfile::LockedPackage;
+use crate::registry::RegistryClient;
+
+/// Payload type of an in-toto statement in a DSSE envelope
+pub const STATEMENT_MEDIA_TYPE: &str = "application/vnd.in-toto+json";
+
+/// Predicate types of SLSA provenance, any version
+const SLSA_PREDICATE_PREFIX: &str = "https://slsa.dev/provenance/";
+
+/// What checking a package's provenance found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProvenanceOutcome {
+    /// The statement names this tarball and this source (repository and
+    /// commit), but its signature is not verified
+    Unverified(String),
+    /// The version publishes no attestations
+    Unsigned,
+    /// The attestations do not vouch for this tarball
+    Invalid(String),
+    /// The attestations could not be fetched
+    Unavailable(String),
+}
+
+/// Check the provenance of each package against the registry's attestations
+pub async fn check<'a, I>(registry: &RegistryClient, packages: I, concurrency: usize) -> Vec<(String, String, ProvenanceOutcome)>
+where
+    I: IntoIterator<Item = &'a LockedPackage>,
+{
+    stream::iter(packages)
+        .map(|locked| async move {
+            let name = locked.package_name();
+            let url = match registry.get_package_metadata(name).await {
+                Ok(metadata) => metadata
+                    .versions
+                    .get(&locked.version)
+                    .and_then(|version| version.dist.attestations.as_ref())
+                    .map(|attestations| attestations.url.clone()),
+                Err(e) => return (locked.name.clone(), locked.version.clone(), ProvenanceOutcome::Unavailable(e.to_string())),
+            };
+
+            let outcome = match url {
+                None => ProvenanceOutcome::Unsigned,
+                Some(url) => match registry.get_attestations(name, &url).await {
+                    Ok(Some(attestations)) => match attested_source(name, &locked.version, &locked.integrity, &attestations) {
+                        Ok(source) => ProvenanceOutcome::Unverified(source),
+                        Err(reason) => ProvenanceOutcome::Invalid(reason),
+                    },
+                    Ok(None) => ProvenanceOutcome::Invalid("the registry lists attestations it does not serve".to_string()),
+                    Err(e) => ProvenanceOutcome::Unavailable(e.to_string()),
+                },
+            };
+            (locked.name.clone(), locked.version.clone(), outcome)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Check that `attestations` (the registry's response) hold a SLSA provenance
+/// statement for `name@version` with the given integrity
+///
+/// Returns the source the statement says the tarball was built from. Only the
+/// statement is inspected; its signature is not verified.
+pub fn attested_source(name: &str, version: &str, integrity: &str, attestations: &serde_json::Value) -> Result<String, String> {
+    let sha512 = integrity
+        .strip_prefix("sha512-")
+        .and_then(|digest| base64::engine::general_purpose::STANDARD.decode(digest).ok())
+        .map(hex::encode)
+        .ok_or_else(|| "the lockfile has no sha512 integrity to compare against".to_string())?;
+
+    let attestation = attestations["attestations"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|attestation| {
+            attestation["predicateType"].as_str().is_some_and(|kind| kind.starts_with(SLSA_PREDICATE_PREFIX))
+        })
+        .ok_or_else(|| "no SLSA provenance attestation".to_string())?;
+
+    let bundle = &attestation["bundle"];
+    let material = &bundle["verificationMaterial"];
+    let certified = ["certificate", "x509CertificateChain", "publicKey"]
+        .iter()
+        .any(|key| !material[key].is_null());
+    let envelope = &bundle["dsseEnvelope"];
+    let signed = envelope["signatures"].as_array().is_some_and(|signatures| !signatures.is_empty());
+    if !certified || !signed {
+        return Err("the provenance statement is not signed".to_string());
+    }
+    if envelope["payloadType"].as_str() != Some(STATEMENT_MEDIA_TYPE) {
+        return Err("the attestation does not hold an in-toto statement".to_string());
+    }
+
+    let statement: serde_json::Value = envelope["payload"]
+        .as_str()
+        .and_then(|payload| base64::engine::general_purpose::STANDARD.decode(payload).ok())
+        .and_then(|payload| serde_json::from_slice(&payload).ok())
+        .ok_or_else(|| "the provenance statement cannot be read".to_string())?;
+
+    let purl = package_url(name, version);
+    let subjects = statement["subject"].as_array().map(Vec::as_slice).unwrap_or_default();
+    if !subjects.iter().any(|subject| subject["name"] == purl.as_str() && subject["digest"]["sha512"] == sha512.as_str()) {
+        return Err(format!("the statement is not for this tarball of {}", purl));
+    }
+
+    // SLSA v1 lists the source under resolvedDependencies, v0.2 under materials
+    let predicate = &statement["predicate"];
+    let source = predicate["buildDefinition"]["resolvedDependencies"][0]
+        .as_object()
+        .or_else(|| predicate["materials"][0].as_object())
+        .and_then(|source| {
+            let uri = source.get("uri")?.as_str()?.trim_start_matches("git+");
+            let commit = source.get("digest").and_then(|d| d.get("gitCommit").or_else(|| d.get("sha1")));
+            Some(match commit.and_then(|c| c.as_str()) {
+                Some(commit) => format!("{}#{}", uri.split('@').next().unwrap_or(uri), commit),
+                None => uri.to_string(),
+            })
+        })
+        .ok_or_else(|| "the statement names no source".to_string())?;
+
+    Ok(source)
+}
+
+/// Package URL (purl) of an npm package version; the scope's `@` is escaped
+pub fn package_url(name: &str, version: &str) -> String {
+    format!("pkg:npm/{}@{}", name.replacen('@', "%40", usize::from(name.starts_with('@'))), version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_attestations() {
+        let base64 = base64::engine::general_purpose::STANDARD;
+        let statement_for = |sha512_hex: &str| serde_json::json!({
+            "_type": "https://in-toto.io/Statement/v1",
+            "subject": [{ "name": package_url("@acme/tools", "1.0.0"), "digest": { "sha512": sha512_hex } }],
+            "predicateType": "https://slsa.dev/provenance/v1",
+            "predicate": {
+                "buildDefinition": {
+                    "externalParameters": {
+                        "workflow": { "ref": "refs/tags/v1.0.0", "repository": "https://github.com/acme/tools" },
+                    },
+                    "resolvedDependencies": [{
+                        "uri": "git+https://github.com/acme/tools@refs/tags/v1.0.0",
+                        "digest": { "gitCommit": "0123abcd" },
+                    }],
+                },
+            },
+        });
+        let integrity = format!("sha512-{}", base64.encode([0xffu8, 0x00]));
+        let attestations = |statement: &serde_json::Value, signatures: serde_json::Value| serde_json::json!({
+            "attestations": [{
+                "predicateType": "https://slsa.dev/provenance/v1",
+                "bundle": {
+                    "verificationMaterial": { "x509CertificateChain": { "certificates": [] } },
+                    "dsseEnvelope": {
+                        "payloadType": STATEMENT_MEDIA_TYPE,
+                        "payload": base64.encode(statement.to_string()),
+                        "signatures": signatures,
+                    },
+                },
+            }],
+        });
+        let signed = serde_json::json!([{ "sig": "MEUCIQ", "keyid": "" }]);
+
+        assert_eq!(package_url("@acme/tools", "1.0.0"), "pkg:npm/%40acme/tools@1.0.0");
+        let statement = statement_for("ff00");
+        assert_eq!(
+            attested_source("@acme/tools", "1.0.0", &integrity, &attestations(&statement, signed.clone())),
+            Ok("https://github.com/acme/tools#0123abcd".to_string())
+        );
+
+        // A statement for other bytes, another version or without signatures does not count
+        let other = statement_for("00ff");
+        assert!(attested_source("@acme/tools", "1.0.0", &integrity, &attestations(&other, signed.clone())).is_err());
+        assert!(attested_source("@acme/tools", "1.0.1", &integrity, &attestations(&statement, signed)).is_err());
+        assert!(attested_source("@acme/tools", "1.0.0", &integrity, &attestations(&statement, serde_json::json!([]))).is_err());
+        assert!(attested_source("@acme/tools", "1.0.0", &integrity, &serde_json::json!({ "attestations": [] })).is_err());
+    }
+}
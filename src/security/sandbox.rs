@@ -1,11 +1,16 @@
 //! Sandboxed script execution
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use tokio::process::Command;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
 
+use crate::core::config::ScriptSandboxMode;
+use crate::core::lockfile::Platform;
 use crate::core::{VelocityResult, VelocityError};
+use crate::security::isolation::Isolation;
 use crate::security::permissions::{Permission, PermissionManager};
 
 /// Script sandbox for safe execution
@@ -16,6 +21,16 @@ pub struct ScriptSandbox {
     env: HashMap<String, String>,
     /// Permission manager
     permissions: Option<PermissionManager>,
+    /// Maximum run time before the script's process group is killed
+    timeout: Option<Duration>,
+    /// Maximum captured bytes per output stream
+    output_limit: Option<usize>,
+    /// npm's script environment
+    npm_env: NpmEnv,
+    /// OS-level restrictions on what scripts may do
+    isolation: Option<Isolation>,
+    /// Whether restrictions this system cannot enforce are an error
+    require_isolation: bool,
 }
 
 impl ScriptSandbox {
@@ -25,9 +40,39 @@ impl ScriptSandbox {
             working_dir,
             env: HashMap::new(),
             permissions: None,
+            timeout: None,
+            output_limit: None,
+            npm_env: NpmEnv::default(),
+            isolation: None,
+            require_isolation: false,
         }
     }
 
+    /// Run scripts under OS-level `isolation`, as `mode` dictates
+    pub fn with_isolation(mut self, isolation: Option<Isolation>, mode: ScriptSandboxMode) -> Self {
+        self.isolation = isolation.filter(|_| mode != ScriptSandboxMode::Off);
+        self.require_isolation = mode == ScriptSandboxMode::Strict;
+        self
+    }
+
+    /// Give scripts the variables npm sets (`npm_package_*`, `npm_lifecycle_*`, `npm_config_*`)
+    pub fn with_npm_env(mut self, npm_env: NpmEnv) -> Self {
+        self.npm_env = npm_env;
+        self
+    }
+
+    /// Kill scripts that run longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Keep at most `limit` bytes of each output stream
+    pub fn with_output_limit(mut self, limit: Option<usize>) -> Self {
+        self.output_limit = limit;
+        self
+    }
+
     /// Set environment variables
     pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
         self.env = env;
@@ -40,10 +85,11 @@ impl ScriptSandbox {
         self
     }
 
-    /// Execute a script
+    /// Execute the script for lifecycle `event` (e.g. `postinstall`)
     pub async fn execute(
         &self,
         package: &str,
+        event: &str,
         script: &str,
         args: &[String],
     ) -> VelocityResult<ScriptResult> {
@@ -88,23 +134,92 @@ impl ScriptSandbox {
         let path_separator = if cfg!(windows) { ";" } else { ":" };
         path_env = format!("{}{}{}", node_modules_bin.display(), path_separator, path_env);
 
-        // Execute
-        let output = Command::new(shell)
+        let mut command = match &self.isolation {
+            Some(isolation) => {
+                let unsupported = isolation.unsupported();
+                if let Some(restriction) = unsupported.first() {
+                    if self.require_isolation {
+                        return Err(VelocityError::SandboxUnavailable {
+                            package: package.to_string(),
+                            restriction: restriction.to_string(),
+                        });
+                    }
+                    tracing::warn!(
+                        "Running {} script for {} without {} isolation, which this system does not support",
+                        event,
+                        package,
+                        unsupported.join(" or ")
+                    );
+                }
+                isolation.command(shell)
+            }
+            None => Command::new(shell),
+        };
+
+        // Isolated scripts get a minimal environment, so registry tokens and
+        // other secrets in ours do not reach them
+        if let Some(isolation) = &self.isolation {
+            command.env_clear().envs(std::env::vars_os().filter(|(name, _)| {
+                name.to_str().is_some_and(|name| inherits(name, isolation.network))
+            }));
+        }
+
+        // Execute in its own process group so the whole tree can be killed
+        command
             .arg(shell_arg)
             .arg(&full_script)
             .current_dir(&self.working_dir)
+            .envs(self.npm_env.for_script(event, script))
             .env("PATH", &path_env)
             .envs(&self.env)
+            .stdin(Stdio::null())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .output()
-            .await?;
+            .kill_on_drop(true);
+
+        #[cfg(unix)]
+        command.process_group(0);
+
+        #[cfg(windows)]
+        {
+            const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+            command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+        }
+
+        let mut child = command.spawn()?;
+
+        // Drain both streams while waiting so a chatty script cannot block on a full pipe
+        let stdout = tokio::spawn(read_capped(child.stdout.take(), self.output_limit));
+        let stderr = tokio::spawn(read_capped(child.stderr.take(), self.output_limit));
+
+        let status = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, child.wait()).await {
+                Ok(status) => Some(status?),
+                Err(_) => {
+                    kill_process_tree(&mut child).await;
+                    None
+                }
+            },
+            None => Some(child.wait().await?),
+        };
+
+        // Orphans that escaped the group may hold the pipes open; don't wait on them forever
+        let collect = |handle: tokio::task::JoinHandle<(Vec<u8>, bool)>| async move {
+            match tokio::time::timeout(Duration::from_secs(2), handle).await {
+                Ok(Ok(captured)) => captured,
+                _ => (Vec::new(), false),
+            }
+        };
+        let (stdout, stdout_truncated) = collect(stdout).await;
+        let (stderr, stderr_truncated) = collect(stderr).await;
 
         Ok(ScriptResult {
-            success: output.status.success(),
-            exit_code: output.status.code(),
-            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            success: status.map(|s| s.success()).unwrap_or(false),
+            exit_code: status.and_then(|s| s.code()),
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            stderr: String::from_utf8_lossy(&stderr).to_string(),
+            timed_out: status.is_none(),
+            truncated: stdout_truncated || stderr_truncated,
         })
     }
 
@@ -148,6 +263,96 @@ impl ScriptSandbox {
     }
 }
 
+/// Variables isolated scripts keep from velocity's environment
+const INHERITED_ENV: &[&str] = &[
+    "HOME", "USER", "LOGNAME", "SHELL", "TERM", "LANG", "TZ", "TMPDIR", "TMP", "TEMP", "CI", "NODE_ENV",
+    "SystemRoot", "SystemDrive", "ComSpec", "PATHEXT", "WINDIR", "USERPROFILE", "APPDATA", "LOCALAPPDATA",
+];
+
+/// Proxy settings, kept only for isolated scripts that may use the network
+const PROXY_ENV: &[&str] = &["HTTP_PROXY", "HTTPS_PROXY", "NO_PROXY"];
+
+/// Whether an isolated script keeps variable `name`; names match case-insensitively as on Windows
+fn inherits(name: &str, network: bool) -> bool {
+    let listed = |list: &[&str]| list.iter().any(|var| name.eq_ignore_ascii_case(var));
+    listed(INHERITED_ENV) || name.starts_with("LC_") || (network && listed(PROXY_ENV))
+}
+
+/// Variables npm sets for scripts, so build scripts written for npm work unchanged
+///
+/// As in npm 7 and later, only the package's `name`, `version`, `config`,
+/// `engines` and `bin` fields become `npm_package_*` variables.
+#[derive(Debug, Clone, Default)]
+pub struct NpmEnv {
+    vars: HashMap<String, String>,
+}
+
+impl NpmEnv {
+    /// The variables every script gets: the user agent and velocity's own path
+    pub fn new() -> Self {
+        let platform = Platform::current();
+        let mut vars = HashMap::new();
+        vars.insert(
+            "npm_config_user_agent".to_string(),
+            format!("velocity/{} {} {}", env!("CARGO_PKG_VERSION"), platform.os, platform.cpu),
+        );
+        if let Ok(exe) = std::env::current_exe() {
+            vars.insert("npm_execpath".to_string(), exe.display().to_string());
+        }
+        Self { vars }
+    }
+
+    /// `npm_package_*` for the package whose manifest is at `manifest_path`
+    pub fn with_package(mut self, manifest_path: &Path, manifest: &serde_json::Value) -> Self {
+        self.vars.insert("npm_package_json".to_string(), manifest_path.display().to_string());
+        for field in ["name", "version", "config", "engines", "bin"] {
+            if let Some(value) = manifest.get(field) {
+                flatten_into(&mut self.vars, &format!("npm_package_{}", field), value);
+            }
+        }
+        self
+    }
+
+    /// `npm_config_*` for the registry and cache velocity uses
+    pub fn with_config(mut self, registry: &str, cache_dir: &Path) -> Self {
+        self.vars.insert("npm_config_registry".to_string(), registry.to_string());
+        self.vars.insert("npm_config_cache".to_string(), cache_dir.display().to_string());
+        self
+    }
+
+    /// Every variable for running `script` as lifecycle `event`
+    pub fn for_script(&self, event: &str, script: &str) -> HashMap<String, String> {
+        let mut vars = self.vars.clone();
+        vars.insert("npm_lifecycle_event".to_string(), event.to_string());
+        vars.insert("npm_lifecycle_script".to_string(), script.to_string());
+        vars
+    }
+}
+
+/// Add `value` under `prefix`, one variable per leaf; keys become `_`-separated
+fn flatten_into(vars: &mut HashMap<String, String>, prefix: &str, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                let key: String = key.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+                flatten_into(vars, &format!("{}_{}", prefix, key), value);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten_into(vars, &format!("{}_{}", prefix, i), value);
+            }
+        }
+        serde_json::Value::String(s) => {
+            vars.insert(prefix.to_string(), s.clone());
+        }
+        serde_json::Value::Null => {}
+        other => {
+            vars.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
+
 /// Result of script execution
 #[derive(Debug)]
 pub struct ScriptResult {
@@ -159,4 +364,130 @@ pub struct ScriptResult {
     pub stdout: String,
     /// Standard error
     pub stderr: String,
+    /// Whether the script was killed for exceeding its timeout
+    pub timed_out: bool,
+    /// Whether captured output was cut at the output limit
+    pub truncated: bool,
+}
+
+/// Read a stream to the end, keeping at most `limit` bytes
+async fn read_capped<R: AsyncRead + Unpin>(stream: Option<R>, limit: Option<usize>) -> (Vec<u8>, bool) {
+    let mut captured = Vec::new();
+    let mut truncated = false;
+
+    let Some(mut stream) = stream else {
+        return (captured, truncated);
+    };
+
+    let mut buf = [0u8; 8192];
+    loop {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let room = limit.map(|l| l.saturating_sub(captured.len())).unwrap_or(n);
+                if room < n {
+                    truncated = true;
+                }
+                captured.extend_from_slice(&buf[..n.min(room)]);
+            }
+        }
+    }
+
+    (captured, truncated)
+}
+
+/// Kill a script together with everything it spawned
+async fn kill_process_tree(child: &mut Child) {
+    if let Some(pid) = child.id() {
+        #[cfg(unix)]
+        {
+            // The child leads its own process group, so signal the whole group
+            // SAFETY: kill(2) has no memory-safety preconditions
+            unsafe {
+                libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+            }
+        }
+
+        #[cfg(windows)]
+        {
+            let _ = std::process::Command::new("taskkill")
+                .args(["/T", "/F", "/PID", &pid.to_string()])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+
+    let _ = child.kill().await;
+    let _ = child.wait().await;
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_isolated_env_allowlist() {
+        assert!(inherits("HOME", false));
+        assert!(inherits("LC_ALL", false));
+        assert!(inherits("https_proxy", true));
+        assert!(!inherits("https_proxy", false));
+        assert!(!inherits("NPM_TOKEN", true));
+        assert!(!inherits("GITHUB_TOKEN", true));
+        assert!(!inherits("AWS_SECRET_ACCESS_KEY", true));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_kills_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = ScriptSandbox::new(dir.path().to_path_buf())
+            .with_timeout(Some(Duration::from_millis(200)));
+
+        // The background sleep keeps stdout open unless the whole group dies
+        let start = std::time::Instant::now();
+        let result = sandbox.execute("slow", "install", "sleep 30 & sleep 30", &[]).await.unwrap();
+
+        assert!(result.timed_out);
+        assert!(!result.success);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_output_limit_truncates() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = ScriptSandbox::new(dir.path().to_path_buf())
+            .with_output_limit(Some(16));
+
+        let result = sandbox.execute("chatty", "install", "printf '%0100d' 0", &[]).await.unwrap();
+
+        assert!(result.success);
+        assert!(result.truncated);
+        assert_eq!(result.stdout.len(), 16);
+    }
+
+    #[tokio::test]
+    async fn test_npm_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = serde_json::json!({
+            "name": "@scope/app",
+            "version": "1.2.3",
+            "config": { "port": 8080, "db-host": "localhost" },
+            "engines": { "node": ">=18" },
+            "description": "not exported"
+        });
+        let npm_env = NpmEnv::new()
+            .with_package(&dir.path().join("package.json"), &manifest)
+            .with_config("https://registry.npmjs.org/", dir.path());
+        let sandbox = ScriptSandbox::new(dir.path().to_path_buf()).with_npm_env(npm_env);
+
+        let script = "echo $npm_package_name $npm_package_version $npm_lifecycle_event \
+            $npm_package_config_port $npm_package_config_db_host $npm_package_engines_node \
+            $npm_config_registry ${npm_package_description:-none}";
+        let result = sandbox.execute("@scope/app", "postinstall", script, &[]).await.unwrap();
+
+        assert_eq!(
+            result.stdout.trim(),
+            "@scope/app 1.2.3 postinstall 8080 localhost >=18 https://registry.npmjs.org/ none"
+        );
+    }
 }
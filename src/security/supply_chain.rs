@@ -27,14 +27,14 @@ static POPULAR_PACKAGES: Lazy<HashSet<&'static str>> = Lazy::new(|| {
 });
 
 /// Characters commonly swapped in typosquatting
-static SIMILAR_CHARS: &[(char, char)] = &[
-    ('l', '1'), ('l', 'i'), ('1', 'i'),
-    ('o', '0'), ('0', 'o'),
-    ('rn', 'm'), ('m', 'rn'),
-    ('n', 'm'),
-    ('s', '5'),
-    ('a', '4'),
-    ('e', '3'),
+static SIMILAR_CHARS: &[(&str, &str)] = &[
+    ("l", "1"), ("l", "i"), ("1", "i"),
+    ("o", "0"), ("0", "o"),
+    ("rn", "m"), ("m", "rn"),
+    ("n", "m"),
+    ("s", "5"),
+    ("a", "4"),
+    ("e", "3"),
 ];
 
 /// Suspicious package name patterns
@@ -204,7 +204,8 @@ pub struct SuspiciousNameWarning {
 }
 
 /// Risk level
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum RiskLevel {
     Low,
     Medium,
@@ -1,11 +1,15 @@
 //! Utility functions for Velocity
 
 mod performance;
+mod progress;
+mod trace;
 
 use std::path::Path;
 use sha2::{Sha256, Digest};
 
 pub use performance::*;
+pub use progress::*;
+pub use trace::*;
 
 /// Compute SHA-256 hash of data
 pub fn sha256(data: &[u8]) -> String {
@@ -20,6 +20,8 @@ pub struct PerformanceMetrics {
     pub packages_cached: AtomicUsize,
     /// Number of HTTP requests made
     pub http_requests: AtomicUsize,
+    /// Number of those answered over HTTP/2
+    pub http2_requests: AtomicUsize,
     /// Number of cache hits
     pub cache_hits: AtomicUsize,
     /// Number of cache misses
@@ -60,6 +62,10 @@ impl PerformanceMetrics {
         self.http_requests.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn inc_http2_requests(&self) {
+        self.http2_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn cache_hit(&self) {
         self.cache_hits.fetch_add(1, Ordering::Relaxed);
     }
@@ -80,6 +86,9 @@ impl PerformanceMetrics {
             packages_resolved: self.packages_resolved.load(Ordering::Relaxed),
             packages_installed: self.packages_installed.load(Ordering::Relaxed),
             packages_cached: self.packages_cached.load(Ordering::Relaxed),
+            http_requests: self.http_requests.load(Ordering::Relaxed),
+            http2_requests: self.http2_requests.load(Ordering::Relaxed),
+            cache_lookups: self.cache_hits.load(Ordering::Relaxed) + self.cache_misses.load(Ordering::Relaxed),
             cache_hit_rate: {
                 let hits = self.cache_hits.load(Ordering::Relaxed);
                 let misses = self.cache_misses.load(Ordering::Relaxed);
@@ -102,6 +111,9 @@ pub struct MetricsSummary {
     pub packages_resolved: usize,
     pub packages_installed: usize,
     pub packages_cached: usize,
+    pub http_requests: usize,
+    pub http2_requests: usize,
+    pub cache_lookups: usize,
     pub cache_hit_rate: f64,
 }
 
@@ -173,49 +185,6 @@ impl ParallelExecutor {
     }
 }
 
-/// HTTP client optimized for npm registry
-pub struct OptimizedHttpClient {
-    client: reqwest::Client,
-    metrics: Arc<PerformanceMetrics>,
-}
-
-impl OptimizedHttpClient {
-    pub fn new(metrics: Arc<PerformanceMetrics>) -> Self {
-        let client = reqwest::Client::builder()
-            // Enable HTTP/2
-            .http2_prior_knowledge()
-            // Connection pooling
-            .pool_max_idle_per_host(32)
-            .pool_idle_timeout(Duration::from_secs(90))
-            // Timeouts
-            .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(300))
-            // Compression
-            .gzip(true)
-            .brotli(true)
-            .deflate(true)
-            // User agent
-            .user_agent(format!("velocity/{}", env!("CARGO_PKG_VERSION")))
-            .build()
-            .expect("Failed to create HTTP client");
-
-        Self { client, metrics }
-    }
-
-    pub async fn get(&self, url: &str) -> Result<reqwest::Response, reqwest::Error> {
-        self.metrics.inc_http_requests();
-        self.client.get(url).send().await
-    }
-
-    pub async fn get_bytes(&self, url: &str) -> Result<bytes::Bytes, reqwest::Error> {
-        self.metrics.inc_http_requests();
-        let response = self.client.get(url).send().await?;
-        let bytes = response.bytes().await?;
-        self.metrics.add_downloaded(bytes.len() as u64);
-        Ok(bytes)
-    }
-}
-
 /// Memory-efficient string pool for deduplication
 pub struct StringPool {
     pool: dashmap::DashMap<String, Arc<str>>,
@@ -278,4 +247,21 @@ mod tests {
         assert_eq!(summary.bytes_downloaded, 1000);
         assert_eq!(summary.packages_installed, 1);
     }
+
+    #[test]
+    fn test_request_and_lookup_counts() {
+        let metrics = PerformanceMetrics::new();
+
+        metrics.inc_http_requests();
+        metrics.inc_http_requests();
+        metrics.inc_http2_requests();
+        metrics.cache_hit();
+        metrics.cache_miss();
+
+        let summary = metrics.summary();
+        assert_eq!(summary.http_requests, 2);
+        assert_eq!(summary.http2_requests, 1);
+        assert_eq!(summary.cache_lookups, 2);
+        assert_eq!(summary.cache_hit_rate, 50.0);
+    }
 }
@@ -0,0 +1,41 @@
+//! Progress events from long-running operations
+//!
+//! Resolution and installation report what they are doing to a [`Progress`]
+//! sink; the CLI decides how (or whether) to show it.
+
+/// Install phases that report per-package progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Fetching registry metadata
+    Resolve,
+    /// Downloading tarballs (or importing vendored ones)
+    Download,
+    /// Unpacking tarballs into the cache
+    Extract,
+}
+
+/// Receiver of progress events; every method defaults to doing nothing
+///
+/// Packages are identified as `name@version`, except during resolution where
+/// only the name is known.
+pub trait Progress: Send + Sync {
+    /// A phase began; `total` is the number of packages when known up front
+    fn phase_started(&self, _phase: Phase, _total: Option<usize>) {}
+
+    /// A package completed a phase
+    fn package_done(&self, _phase: Phase, _package: &str) {}
+
+    /// A tarball download began; `size` comes from Content-Length
+    fn download_started(&self, _package: &str, _size: Option<u64>) {}
+
+    /// Bytes of a tarball received so far
+    fn download_progress(&self, _package: &str, _received: u64) {}
+
+    /// Every package went through the phase
+    fn phase_finished(&self, _phase: Phase) {}
+}
+
+/// Progress sink that ignores every event
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
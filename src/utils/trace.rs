@@ -0,0 +1,255 @@
+//! Chrome trace export of tracing spans (`--trace-output`)
+//!
+//! Every span becomes an async slice covering its whole lifetime, and every
+//! stretch of time a thread spends inside it becomes a complete event on that
+//! thread's track. Loaded into chrome://tracing or Perfetto, the first shows
+//! how many downloads or extractions were in flight at once, the second where
+//! threads were busy and where they stalled.
+
+use std::cell::Cell;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use serde_json::{json, Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// Trace track of the current thread, assigned on first use
+    static THREAD: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Collected trace events, written out once the command finishes
+pub struct ChromeTrace {
+    path: PathBuf,
+    events: Arc<Mutex<Vec<Value>>>,
+}
+
+/// Layer recording spans into a [`ChromeTrace`]
+pub struct ChromeTraceLayer {
+    events: Arc<Mutex<Vec<Value>>>,
+    start: Instant,
+}
+
+/// Per-span state kept in the registry's span extensions
+struct SpanTiming {
+    args: Map<String, Value>,
+    /// Times the span was entered and not yet exited, innermost last
+    entered: Vec<f64>,
+}
+
+impl ChromeTrace {
+    /// Start a trace to be written to `path`
+    pub fn new(path: PathBuf) -> (Self, ChromeTraceLayer) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let layer = ChromeTraceLayer { events: events.clone(), start: Instant::now() };
+        (Self { path, events }, layer)
+    }
+
+    /// Write the events recorded so far in Chrome's JSON trace format
+    pub fn write(&self) -> std::io::Result<()> {
+        let events = std::mem::take(&mut *self.events.lock());
+        let trace = json!({ "traceEvents": events, "displayTimeUnit": "ms" });
+        std::fs::write(&self.path, serde_json::to_vec(&trace)?)
+    }
+}
+
+impl ChromeTraceLayer {
+    /// Microseconds since the trace started
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1_000_000.0
+    }
+
+    /// Track of the current thread, naming it in the trace the first time it is seen
+    fn thread(&self) -> u64 {
+        THREAD.with(|thread| {
+            if thread.get() == 0 {
+                let tid = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+                thread.set(tid);
+                let name = std::thread::current().name().unwrap_or("thread").to_string();
+                self.events.lock().push(json!({
+                    "name": "thread_name",
+                    "ph": "M",
+                    "pid": 1,
+                    "tid": tid,
+                    "args": { "name": format!("{} #{}", name, tid) },
+                }));
+            }
+            thread.get()
+        })
+    }
+}
+
+impl<S> Layer<S> for ChromeTraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut args = Map::new();
+        attrs.record(&mut FieldVisitor(&mut args));
+
+        let tid = self.thread();
+        self.events.lock().push(json!({
+            "name": span.name(),
+            "cat": span.metadata().target(),
+            "ph": "b",
+            "id": id.into_u64(),
+            "ts": self.now(),
+            "pid": 1,
+            "tid": tid,
+            "args": args,
+        }));
+        span.extensions_mut().insert(SpanTiming { args, entered: Vec::new() });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                values.record(&mut FieldVisitor(&mut timing.args));
+            }
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                timing.entered.push(self.now());
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(timing) = extensions.get_mut::<SpanTiming>() else {
+            return;
+        };
+        let Some(entered) = timing.entered.pop() else {
+            return;
+        };
+        let tid = self.thread();
+        let event = json!({
+            "name": span.name(),
+            "cat": span.metadata().target(),
+            "ph": "X",
+            "ts": entered,
+            "dur": self.now() - entered,
+            "pid": 1,
+            "tid": tid,
+            "args": timing.args,
+        });
+        self.events.lock().push(event);
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let args = span
+            .extensions()
+            .get::<SpanTiming>()
+            .map(|timing| Value::Object(timing.args.clone()))
+            .unwrap_or_default();
+        let tid = self.thread();
+        self.events.lock().push(json!({
+            "name": span.name(),
+            "cat": span.metadata().target(),
+            "ph": "e",
+            "id": id.into_u64(),
+            "ts": self.now(),
+            "pid": 1,
+            "tid": tid,
+            "args": args,
+        }));
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut args = Map::new();
+        event.record(&mut FieldVisitor(&mut args));
+        let name = match args.remove("message") {
+            Some(Value::String(message)) => message,
+            _ => event.metadata().name().to_string(),
+        };
+        let tid = self.thread();
+        self.events.lock().push(json!({
+            "name": name,
+            "cat": event.metadata().target(),
+            "ph": "i",
+            "s": "t",
+            "ts": self.now(),
+            "pid": 1,
+            "tid": tid,
+            "args": args,
+        }));
+    }
+}
+
+/// Collects span and event fields as JSON values
+struct FieldVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_spans_become_trace_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let (trace, layer) = ChromeTrace::new(dir.path().join("trace.json"));
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("download", package = "react");
+            span.in_scope(|| tracing::info!("fetched"));
+        });
+        trace.write().unwrap();
+
+        let written: Value = serde_json::from_slice(&std::fs::read(dir.path().join("trace.json")).unwrap()).unwrap();
+        let phases: Vec<&str> = written["traceEvents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|e| e["name"] == "download")
+            .map(|e| e["ph"].as_str().unwrap())
+            .collect();
+        assert_eq!(phases, ["b", "X", "e"]);
+        let complete = written["traceEvents"].as_array().unwrap().iter().find(|e| e["ph"] == "X").unwrap();
+        assert_eq!(complete["args"]["package"], "react");
+    }
+}
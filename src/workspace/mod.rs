@@ -1,6 +1,8 @@
 //! Workspace manager for monorepos
 
 pub mod graph;
+pub mod release;
+pub mod runner;
 
 use std::path::{Path, PathBuf};
 
@@ -89,6 +91,23 @@ impl WorkspaceManager {
         Ok(result)
     }
 
+    /// Find a member by package name, path relative to the root, or directory name
+    pub fn find_member(&self, selector: &str) -> VelocityResult<Option<(PathBuf, PackageJson)>> {
+        let by_path = self.root.join(selector);
+        let mut by_dir_name = None;
+
+        for (path, pkg) in self.package_jsons()? {
+            if pkg.name == selector || path == by_path {
+                return Ok(Some((path, pkg)));
+            }
+            if by_dir_name.is_none() && path.file_name().map(|n| n == selector).unwrap_or(false) {
+                by_dir_name = Some((path, pkg));
+            }
+        }
+
+        Ok(by_dir_name)
+    }
+
     /// Build a workspace dependency graph
     pub fn build_graph(&self) -> VelocityResult<WorkspaceGraph> {
         let mut graph = WorkspaceGraph::new();
@@ -141,3 +160,101 @@ impl WorkspaceManager {
         self.config.shared_lockfile
     }
 }
+
+/// Projects picked by `--filter` selectors, keeping their order; all of them
+/// when there are none
+///
+/// A selector is a package name, a path relative to the root, a directory
+/// name, or a glob over names and paths (`@app/*`, `packages/*`). A selector
+/// that matches nothing is an error rather than an empty run.
+pub fn select_projects(
+    root: &Path,
+    projects: Vec<(PathBuf, PackageJson)>,
+    filters: &[String],
+) -> VelocityResult<Vec<(PathBuf, PackageJson)>> {
+    if filters.is_empty() {
+        return Ok(projects);
+    }
+
+    let mut selected = vec![false; projects.len()];
+    for filter in filters {
+        let mut matched = false;
+        for (i, (path, pkg)) in projects.iter().enumerate() {
+            if member_matches(filter, root, path, &pkg.name)? {
+                selected[i] = true;
+                matched = true;
+            }
+        }
+        if !matched {
+            return Err(VelocityError::workspace(format!("No workspace package matches '{}'", filter)));
+        }
+    }
+
+    Ok(projects
+        .into_iter()
+        .zip(selected)
+        .filter_map(|(project, keep)| keep.then_some(project))
+        .collect())
+}
+
+/// Whether a `--filter` selector picks the member at `path` named `name`
+pub fn member_matches(filter: &str, root: &Path, path: &Path, name: &str) -> VelocityResult<bool> {
+    let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+    let relative = if relative.is_empty() { ".".to_string() } else { relative };
+    let filter_path = filter.trim_start_matches("./").trim_end_matches('/');
+
+    if filter.contains(['*', '?', '[']) {
+        let pattern = glob::Pattern::new(filter_path)
+            .map_err(|e| VelocityError::workspace(format!("Invalid filter '{}': {}", filter, e)))?;
+        return Ok(pattern.matches(name) || pattern.matches(&relative));
+    }
+
+    Ok(filter == name
+        || filter_path == relative
+        || path.file_name().map(|n| n == filter_path).unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_member_matches() {
+        let root = Path::new("/repo");
+        let web = Path::new("/repo/apps/web");
+
+        assert!(member_matches("@app/web", root, web, "@app/web").unwrap());
+        assert!(member_matches("./apps/web", root, web, "@app/web").unwrap());
+        assert!(member_matches("web", root, web, "@app/web").unwrap());
+        assert!(member_matches("@app/*", root, web, "@app/web").unwrap());
+        assert!(member_matches("apps/*", root, web, "@app/web").unwrap());
+        assert!(!member_matches("@lib/*", root, web, "@app/web").unwrap());
+        assert!(!member_matches("app", root, web, "@app/web").unwrap());
+        assert!(member_matches(".", root, root, "monorepo").unwrap());
+    }
+
+    #[test]
+    fn test_find_member() {
+        let root = tempfile::tempdir().unwrap();
+        for (dir, name) in [("apps/web", "@app/web"), ("libs/ui", "ui-kit")] {
+            std::fs::create_dir_all(root.path().join(dir)).unwrap();
+            std::fs::write(
+                root.path().join(dir).join("package.json"),
+                format!(r#"{{"name": "{}", "version": "1.0.0"}}"#, name),
+            )
+            .unwrap();
+        }
+        let config = WorkspaceConfig {
+            packages: vec!["apps/*".to_string(), "libs/*".to_string()],
+            ..Default::default()
+        };
+        let workspace = WorkspaceManager::new(root.path(), &config).unwrap();
+
+        let (path, pkg) = workspace.find_member("@app/web").unwrap().unwrap();
+        assert_eq!(path, root.path().join("apps/web"));
+        assert_eq!(pkg.name, "@app/web");
+        assert_eq!(workspace.find_member("libs/ui").unwrap().unwrap().1.name, "ui-kit");
+        assert_eq!(workspace.find_member("ui").unwrap().unwrap().1.name, "ui-kit");
+        assert!(workspace.find_member("missing").unwrap().is_none());
+    }
+}
@@ -0,0 +1,457 @@
+//! Coordinated versioning of workspace members (`velocity workspace version`)
+//!
+//! A release bumps the members asked for (explicitly, or from the
+//! conventional commits touching them since their last `name@version` tag),
+//! then every member whose published range on a bumped member would no longer
+//! accept the new version. Each released member gets a changelog entry and,
+//! unless told otherwise, a git tag.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use super::WorkspaceGraph;
+use crate::core::{PackageJson, VelocityError, VelocityResult};
+use crate::resolver::VersionConstraint;
+
+/// How far to move a version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bump {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl Bump {
+    /// The version after this bump; pre-release tags are dropped
+    pub fn apply(self, version: &semver::Version) -> semver::Version {
+        match self {
+            Bump::Major => semver::Version::new(version.major + 1, 0, 0),
+            Bump::Minor => semver::Version::new(version.major, version.minor + 1, 0),
+            // 1.2.0-rc.1 is released as 1.2.0 rather than skipping it
+            Bump::Patch if !version.pre.is_empty() => semver::Version::new(version.major, version.minor, version.patch),
+            Bump::Patch => semver::Version::new(version.major, version.minor, version.patch + 1),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Bump::Patch => "patch",
+            Bump::Minor => "minor",
+            Bump::Major => "major",
+        }
+    }
+}
+
+impl FromStr for Bump {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "patch" => Ok(Bump::Patch),
+            "minor" => Ok(Bump::Minor),
+            "major" => Ok(Bump::Major),
+            _ => Err(format!("unknown bump '{}' (expected patch, minor or major)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A commit message following the Conventional Commits format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConventionalCommit {
+    pub hash: String,
+    /// `feat`, `fix`, `chore`, ...
+    pub kind: String,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub summary: String,
+}
+
+impl ConventionalCommit {
+    /// Parse `type(scope)!: summary`, with `BREAKING CHANGE:` anywhere in the body
+    pub fn parse(hash: &str, message: &str) -> Option<Self> {
+        let subject = message.lines().next()?.trim();
+        let (head, summary) = subject.split_once(": ")?;
+        let (head, bang) = match head.strip_suffix('!') {
+            Some(head) => (head, true),
+            None => (head, false),
+        };
+        let (kind, scope) = match head.split_once('(') {
+            Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?.to_string())),
+            None => (head, None),
+        };
+        if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        let breaking = bang
+            || message.lines().any(|line| line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:"));
+
+        Some(Self {
+            hash: hash.to_string(),
+            kind: kind.to_ascii_lowercase(),
+            scope,
+            breaking,
+            summary: summary.trim().to_string(),
+        })
+    }
+
+    /// The release this commit calls for, if any
+    pub fn bump(&self) -> Option<Bump> {
+        if self.breaking {
+            return Some(Bump::Major);
+        }
+        match self.kind.as_str() {
+            "feat" => Some(Bump::Minor),
+            "fix" | "perf" => Some(Bump::Patch),
+            _ => None,
+        }
+    }
+}
+
+/// Why a member is released
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReleaseReason {
+    /// Named on the command line
+    Requested,
+    /// Conventional commits touched it
+    Commits,
+    /// A member it depends on moved out of its range
+    Dependency,
+}
+
+impl ReleaseReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReleaseReason::Requested => "requested",
+            ReleaseReason::Commits => "commits",
+            ReleaseReason::Dependency => "dependency",
+        }
+    }
+}
+
+/// A member to release and what goes into its release
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub name: String,
+    pub dir: PathBuf,
+    pub current: semver::Version,
+    pub next: semver::Version,
+    pub reason: ReleaseReason,
+    /// Commits listed in the changelog
+    pub commits: Vec<ConventionalCommit>,
+    /// Released members it depends on, with their new versions
+    pub dependencies: Vec<(String, semver::Version)>,
+}
+
+/// A changelog heading and the commits listed under it
+type ChangelogSection = (&'static str, fn(&ConventionalCommit) -> bool);
+
+impl Release {
+    /// Git tag marking this release
+    pub fn tag(&self) -> String {
+        format!("{}@{}", self.name, self.next)
+    }
+
+    /// JSON representation used by `--json` output
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": self.name,
+            "from": self.current.to_string(),
+            "to": self.next.to_string(),
+            "reason": self.reason.as_str(),
+            "tag": self.tag(),
+        })
+    }
+
+    /// Changelog section for this release
+    pub fn changelog_entry(&self, date: &str) -> String {
+        let mut entry = format!("## {} ({})\n", self.next, date);
+        let sections: [ChangelogSection; 4] = [
+            ("Breaking Changes", |c| c.breaking),
+            ("Features", |c| !c.breaking && c.kind == "feat"),
+            ("Bug Fixes", |c| !c.breaking && c.kind == "fix"),
+            ("Performance", |c| !c.breaking && c.kind == "perf"),
+        ];
+
+        for (title, belongs) in sections {
+            let lines: Vec<String> = self
+                .commits
+                .iter()
+                .filter(|c| belongs(c))
+                .map(|c| {
+                    let short = &c.hash[..c.hash.len().min(7)];
+                    match c.scope {
+                        Some(ref scope) => format!("- **{}:** {} ({})", scope, c.summary, short),
+                        None => format!("- {} ({})", c.summary, short),
+                    }
+                })
+                .collect();
+            if !lines.is_empty() {
+                entry.push_str(&format!("\n### {}\n\n{}\n", title, lines.join("\n")));
+            }
+        }
+
+        if !self.dependencies.is_empty() {
+            let lines: Vec<String> = self
+                .dependencies
+                .iter()
+                .map(|(name, version)| format!("- Updated {} to {}", name, version))
+                .collect();
+            entry.push_str(&format!("\n### Dependencies\n\n{}\n", lines.join("\n")));
+        }
+
+        entry
+    }
+}
+
+/// The range a dependency spec is published with: `workspace:` specs become
+/// concrete ranges on `version`, anything else stays as written
+pub fn publish_range(spec: &str, version: &str) -> String {
+    match spec.strip_prefix("workspace:") {
+        Some("*") | Some("") => version.to_string(),
+        Some("^") => format!("^{}", version),
+        Some("~") => format!("~{}", version),
+        Some(range) => range.to_string(),
+        None => spec.to_string(),
+    }
+}
+
+/// A spec rewritten to accept `next`, keeping its operator; None when it already does
+///
+/// `workspace:*`, `workspace:^` and `workspace:~` follow the member on their
+/// own and never need rewriting.
+fn updated_spec(spec: &str, next: &semver::Version) -> Option<String> {
+    let (protocol, range) = match spec.strip_prefix("workspace:") {
+        Some("*" | "" | "^" | "~") => return None,
+        Some(range) => ("workspace:", range),
+        None => ("", spec),
+    };
+    let constraint = VersionConstraint::parse(range).ok()?;
+    if constraint.matches(next) {
+        return None;
+    }
+    let operator = ["^", "~"].into_iter().find(|op| range.starts_with(op)).unwrap_or("");
+    Some(format!("{}{}{}", protocol, operator, next))
+}
+
+/// Plan the releases for `bumps`, adding the dependents they push out of range
+///
+/// Members are visited dependencies first, so a dependent released only
+/// because of a dependency can itself push its own dependents out of range.
+pub fn plan_releases(
+    members: &[(PathBuf, PackageJson)],
+    graph: &WorkspaceGraph,
+    mut bumps: BTreeMap<String, (Bump, ReleaseReason, Vec<ConventionalCommit>)>,
+) -> VelocityResult<Vec<Release>> {
+    let by_name: BTreeMap<&str, &(PathBuf, PackageJson)> =
+        members.iter().map(|member| (member.1.name.as_str(), member)).collect();
+    let mut releases: Vec<Release> = Vec::new();
+
+    for name in graph.topological_order()? {
+        let Some((dir, pkg)) = by_name.get(name.as_str()).copied() else {
+            continue;
+        };
+        let current = semver::Version::parse(&pkg.version).map_err(|e| {
+            VelocityError::workspace(format!("{} has an invalid version '{}': {}", pkg.name, pkg.version, e))
+        })?;
+
+        // Released members this one depends on, and whether its published range excludes them
+        let mut dependencies = Vec::new();
+        let mut out_of_range = false;
+        let mut deps: Vec<(String, String)> = pkg.all_dependencies().into_iter().collect();
+        deps.sort();
+        for (dep, spec) in deps {
+            let Some(release) = releases.iter().find(|r| r.name == dep) else {
+                continue;
+            };
+            let published = publish_range(&spec, &release.current.to_string());
+            let accepts = VersionConstraint::parse(&published).is_ok_and(|c| c.matches(&release.next));
+            out_of_range |= !accepts;
+            dependencies.push((dep, release.next.clone()));
+        }
+
+        let (bump, reason, commits) = match bumps.remove(&name) {
+            Some(planned) => planned,
+            None if out_of_range => (Bump::Patch, ReleaseReason::Dependency, Vec::new()),
+            None => continue,
+        };
+
+        releases.push(Release {
+            name,
+            dir: dir.clone(),
+            next: bump.apply(&current),
+            current,
+            reason,
+            commits,
+            dependencies,
+        });
+    }
+
+    Ok(releases)
+}
+
+/// Write a release: the new version, updated ranges on other released members,
+/// and the changelog entry on top of CHANGELOG.md
+///
+/// Returns the files written.
+pub fn apply_release(release: &Release, releases: &[Release], date: &str) -> VelocityResult<Vec<PathBuf>> {
+    let mut pkg = PackageJson::load(&release.dir)?;
+    pkg.version = release.next.to_string();
+
+    for deps in [
+        &mut pkg.dependencies,
+        &mut pkg.dev_dependencies,
+        &mut pkg.peer_dependencies,
+        &mut pkg.optional_dependencies,
+    ] {
+        for (name, spec) in deps.iter_mut() {
+            let Some(dep) = releases.iter().find(|r| &r.name == name) else {
+                continue;
+            };
+            if let Some(updated) = updated_spec(spec, &dep.next) {
+                *spec = updated;
+            }
+        }
+    }
+    pkg.save(&release.dir)?;
+
+    let changelog = release.dir.join("CHANGELOG.md");
+    let existing = std::fs::read_to_string(&changelog).unwrap_or_default();
+    let entry = release.changelog_entry(date);
+    let content = match existing.strip_prefix("# Changelog\n") {
+        Some(rest) => format!("# Changelog\n\n{}{}", entry, rest),
+        None if existing.is_empty() => format!("# Changelog\n\n{}", entry),
+        None => format!("# Changelog\n\n{}\n{}", entry, existing),
+    };
+    std::fs::write(&changelog, content)?;
+
+    Ok(vec![release.dir.join("package.json"), changelog])
+}
+
+/// Conventional commits touching `dir` since `tag` (all of its history if the tag does not exist)
+pub fn commits_since(root: &Path, dir: &Path, tag: &str) -> VelocityResult<Vec<ConventionalCommit>> {
+    let tagged = git(root, &["rev-parse", "-q", "--verify", &format!("refs/tags/{}", tag)]).is_ok();
+    let range = if tagged { format!("{}..HEAD", tag) } else { "HEAD".to_string() };
+    let log = git(root, &["log", "--format=%H%x1f%B%x1e", &range, "--", &dir.to_string_lossy()])?;
+
+    Ok(log
+        .split('\x1e')
+        .filter_map(|entry| {
+            let (hash, message) = entry.trim_start().split_once('\x1f')?;
+            ConventionalCommit::parse(hash, message)
+        })
+        .collect())
+}
+
+/// Commit the written files and tag every release
+pub fn commit_and_tag(root: &Path, files: &[PathBuf], releases: &[Release]) -> VelocityResult<()> {
+    let mut add = vec!["add", "--"];
+    let paths: Vec<String> = files.iter().map(|f| f.to_string_lossy().into_owned()).collect();
+    add.extend(paths.iter().map(String::as_str));
+    git(root, &add)?;
+
+    let tags: Vec<String> = releases.iter().map(Release::tag).collect();
+    let message = format!(
+        "chore(release): publish\n\n{}",
+        tags.iter().map(|tag| format!("- {}", tag)).collect::<Vec<_>>().join("\n")
+    );
+    git(root, &["commit", "-m", &message])?;
+
+    for tag in &tags {
+        git(root, &["tag", "-a", tag, "-m", tag])?;
+    }
+    Ok(())
+}
+
+/// Run git in `root`, returning its standard output
+fn git(root: &Path, args: &[&str]) -> VelocityResult<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(root)
+        .output()
+        .map_err(|e| VelocityError::workspace(format!("Could not run git: {}", e)))?;
+    if !output.status.success() {
+        return Err(VelocityError::workspace(format!(
+            "git {} failed: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(name: &str, version: &str, deps: &[(&str, &str)]) -> (PathBuf, PackageJson) {
+        let mut pkg = PackageJson::new(name);
+        pkg.version = version.to_string();
+        pkg.dependencies = deps.iter().map(|(n, s)| (n.to_string(), s.to_string())).collect();
+        (PathBuf::from(format!("/repo/packages/{}", name)), pkg)
+    }
+
+    #[test]
+    fn test_conventional_commits() {
+        let feat = ConventionalCommit::parse("abc1234", "feat(api): add retries").unwrap();
+        assert_eq!((feat.kind.as_str(), feat.scope.as_deref(), feat.bump()), ("feat", Some("api"), Some(Bump::Minor)));
+        assert_eq!(ConventionalCommit::parse("a", "fix!: drop node 16").unwrap().bump(), Some(Bump::Major));
+        assert_eq!(
+            ConventionalCommit::parse("a", "fix: x\n\nBREAKING CHANGE: y").unwrap().bump(),
+            Some(Bump::Major)
+        );
+        assert_eq!(ConventionalCommit::parse("a", "chore: deps").unwrap().bump(), None);
+        assert_eq!(ConventionalCommit::parse("a", "Merge branch 'main'"), None);
+    }
+
+    #[test]
+    fn test_dependents_follow_out_of_range_releases() {
+        let members = vec![
+            member("core", "1.2.3", &[]),
+            member("ui", "0.4.0", &[("core", "workspace:^")]),
+            member("app", "2.0.0", &[("core", "workspace:*"), ("ui", "^0.4.0")]),
+            member("docs", "1.0.0", &[]),
+        ];
+        let mut graph = WorkspaceGraph::new();
+        for (dir, pkg) in &members {
+            graph.add_package(&pkg.name, dir.clone());
+        }
+        for (_, pkg) in &members {
+            for dep in pkg.dependencies.keys() {
+                graph.add_dependency(&pkg.name, dep);
+            }
+        }
+
+        let plan = |bump: Bump| {
+            let bumps = [("core".to_string(), (bump, ReleaseReason::Requested, Vec::new()))].into_iter().collect();
+            let releases = plan_releases(&members, &graph, bumps).unwrap();
+            releases.iter().map(|r| (r.name.clone(), r.next.to_string())).collect::<Vec<_>>()
+        };
+        let release = |name: &str, version: &str| (name.to_string(), version.to_string());
+
+        // workspace:^ still accepts a minor release; workspace:* pins the exact version
+        assert_eq!(plan(Bump::Minor), [release("core", "1.3.0"), release("app", "2.0.1")]);
+        // A major release pushes ui out of range, and ui's own release pushes app
+        assert_eq!(plan(Bump::Major), [release("core", "2.0.0"), release("ui", "0.4.1"), release("app", "2.0.1")]);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let next = semver::Version::new(2, 0, 0);
+        assert_eq!(publish_range("workspace:^", "1.2.3"), "^1.2.3");
+        assert_eq!(publish_range("workspace:*", "1.2.3"), "1.2.3");
+        assert_eq!(publish_range("workspace:>=1", "1.2.3"), ">=1");
+        assert_eq!(updated_spec("^1.2.0", &next).as_deref(), Some("^2.0.0"));
+        assert_eq!(updated_spec("workspace:~1.2.0", &next).as_deref(), Some("workspace:~2.0.0"));
+        assert_eq!(updated_spec("workspace:^", &next), None);
+        assert_eq!(updated_spec(">=1.0.0", &next), None);
+    }
+}
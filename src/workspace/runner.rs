@@ -0,0 +1,349 @@
+//! Running a script across workspace members
+//!
+//! A member's script starts once the scripts of every member it depends on
+//! have finished, so `velocity workspace run build` builds libraries before
+//! the apps using them. Members with nothing left to wait for run in
+//! parallel, and their output is streamed line by line behind a
+//! `name |` prefix.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+
+use super::WorkspaceGraph;
+use crate::core::VelocityResult;
+
+/// Prefix colors, assigned to members in turn
+const COLORS: &[console::Color] = &[
+    console::Color::Cyan,
+    console::Color::Magenta,
+    console::Color::Yellow,
+    console::Color::Blue,
+    console::Color::Green,
+];
+
+/// A member to run the script in
+#[derive(Debug, Clone)]
+pub struct WorkspaceTask {
+    pub name: String,
+    pub dir: PathBuf,
+    /// The member's script, if it defines one
+    pub command: Option<String>,
+}
+
+/// How a member's run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskStatus {
+    Succeeded,
+    Failed { exit_code: Option<i32> },
+    /// The member does not define the script
+    NoScript,
+    /// Not started because a member it depends on failed
+    Skipped,
+    /// Stopped or never started because another member failed (fail-fast)
+    Cancelled,
+}
+
+impl TaskStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskStatus::Succeeded => "succeeded",
+            TaskStatus::Failed { .. } => "failed",
+            TaskStatus::NoScript => "no-script",
+            TaskStatus::Skipped => "skipped",
+            TaskStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Whether the run went fine, so dependents may run after it
+    pub fn is_success(&self) -> bool {
+        matches!(self, TaskStatus::Succeeded | TaskStatus::NoScript)
+    }
+}
+
+/// Outcome of one member's run
+#[derive(Debug, Clone)]
+pub struct TaskResult {
+    pub name: String,
+    pub status: TaskStatus,
+    pub duration: Duration,
+}
+
+impl TaskResult {
+    /// JSON representation used by `--json` output
+    pub fn to_json(&self) -> serde_json::Value {
+        let exit_code = match self.status {
+            TaskStatus::Failed { exit_code } => exit_code,
+            TaskStatus::Succeeded => Some(0),
+            _ => None,
+        };
+        serde_json::json!({
+            "package": self.name,
+            "status": self.status.as_str(),
+            "success": self.status.is_success(),
+            "exit_code": exit_code,
+            "duration_ms": self.duration.as_millis(),
+        })
+    }
+}
+
+/// Runs a script across members in dependency order
+pub struct WorkspaceRunner {
+    /// Scripts running at once
+    concurrency: usize,
+
+    /// Stop everything at the first failure instead of running what does not depend on it
+    fail_fast: bool,
+
+    /// Extra arguments appended to every script
+    args: Vec<String>,
+
+    /// Send prefixed output to stderr, keeping stdout for `--json`
+    to_stderr: bool,
+}
+
+impl WorkspaceRunner {
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            concurrency: concurrency.max(1),
+            fail_fast: true,
+            args: Vec::new(),
+            to_stderr: false,
+        }
+    }
+
+    /// Keep running members that do not depend on a failed one
+    pub fn with_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Append these arguments to every script
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Stream all script output to stderr
+    pub fn with_output_to_stderr(mut self, to_stderr: bool) -> Self {
+        self.to_stderr = to_stderr;
+        self
+    }
+
+    /// Run every task, each after the tasks of the members it depends on
+    ///
+    /// Results come back in the order tasks finished (or were given up on).
+    pub async fn run(&self, graph: &WorkspaceGraph, tasks: &[WorkspaceTask]) -> VelocityResult<Vec<TaskResult>> {
+        let order = graph.topological_order()?;
+        let by_name: HashMap<&str, &WorkspaceTask> = tasks.iter().map(|task| (task.name.as_str(), task)).collect();
+        let width = tasks.iter().map(|task| task.name.len()).max().unwrap_or(0);
+
+        // Members each task still waits for; only tasks being run count
+        let mut waiting: HashMap<&str, HashSet<String>> = tasks
+            .iter()
+            .map(|task| {
+                let deps = graph
+                    .dependencies(&task.name)
+                    .into_iter()
+                    .filter(|dep| by_name.contains_key(dep.as_str()))
+                    .collect();
+                (task.name.as_str(), deps)
+            })
+            .collect();
+        let queue: Vec<&WorkspaceTask> = order.iter().filter_map(|name| by_name.get(name.as_str()).copied()).collect();
+
+        let mut results: Vec<TaskResult> = Vec::new();
+        let mut done: HashSet<String> = HashSet::new();
+        let mut running = FuturesUnordered::new();
+        let mut started: HashSet<&str> = HashSet::new();
+        let mut stopped = false;
+
+        loop {
+            if !stopped {
+                for task in &queue {
+                    if running.len() >= self.concurrency {
+                        break;
+                    }
+                    let name = task.name.as_str();
+                    if started.contains(name) || !waiting[name].is_empty() {
+                        continue;
+                    }
+                    started.insert(name);
+                    let color = COLORS[tasks.iter().position(|t| t.name == task.name).unwrap_or(0) % COLORS.len()];
+                    running.push(self.run_task(task, width, color));
+                }
+            }
+
+            let Some(result) = running.next().await else {
+                break;
+            };
+
+            let mut finished = vec![result];
+            while let Some(result) = finished.pop() {
+                if !result.status.is_success() && self.fail_fast {
+                    stopped = true;
+                }
+                for (name, deps) in waiting.iter_mut() {
+                    if !deps.remove(&result.name) || result.status.is_success() || started.contains(name) {
+                        continue;
+                    }
+                    // Dependents of a failed member never run
+                    started.insert(*name);
+                    finished.push(TaskResult {
+                        name: name.to_string(),
+                        status: TaskStatus::Skipped,
+                        duration: Duration::ZERO,
+                    });
+                }
+                done.insert(result.name.clone());
+                results.push(result);
+            }
+
+            // Dropping the running scripts kills them
+            if stopped {
+                running.clear();
+            }
+        }
+
+        for task in &queue {
+            if !done.contains(&task.name) {
+                results.push(TaskResult {
+                    name: task.name.clone(),
+                    status: TaskStatus::Cancelled,
+                    duration: Duration::ZERO,
+                });
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Run one member's script, streaming its output behind the member's prefix
+    async fn run_task(&self, task: &WorkspaceTask, width: usize, color: console::Color) -> TaskResult {
+        let start = Instant::now();
+        let result = |status| TaskResult { name: task.name.clone(), status, duration: start.elapsed() };
+
+        let Some(ref script) = task.command else {
+            return result(TaskStatus::NoScript);
+        };
+        let command = if self.args.is_empty() {
+            script.clone()
+        } else {
+            format!("{} {}", script, self.args.join(" "))
+        };
+
+        let (shell, shell_arg) = if cfg!(windows) { ("cmd", "/c") } else { ("sh", "-c") };
+        let child = Command::new(shell)
+            .arg(shell_arg)
+            .arg(&command)
+            .current_dir(&task.dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                tracing::warn!("Could not start '{}' in {}: {}", command, task.name, e);
+                return result(TaskStatus::Failed { exit_code: None });
+            }
+        };
+
+        let prefix = console::style(format!("{:width$} |", task.name, width = width)).fg(color).to_string();
+        let stdout = child.stdout.take().map(|out| stream_lines(out, prefix.clone(), self.to_stderr));
+        let stderr = child.stderr.take().map(|err| stream_lines(err, prefix, true));
+        let (status, _, _) = tokio::join!(child.wait(), async {
+            if let Some(stdout) = stdout {
+                stdout.await;
+            }
+        }, async {
+            if let Some(stderr) = stderr {
+                stderr.await;
+            }
+        });
+
+        match status {
+            Ok(status) if status.success() => result(TaskStatus::Succeeded),
+            Ok(status) => result(TaskStatus::Failed { exit_code: status.code() }),
+            Err(_) => result(TaskStatus::Failed { exit_code: None }),
+        }
+    }
+}
+
+/// Print each line of a script's output behind `prefix`
+async fn stream_lines<R: AsyncRead + Unpin>(output: R, prefix: String, to_stderr: bool) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if to_stderr {
+            eprintln!("{} {}", prefix, line);
+        } else {
+            println!("{} {}", prefix, line);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn task(dir: &std::path::Path, name: &str, command: Option<&str>) -> WorkspaceTask {
+        WorkspaceTask { name: name.to_string(), dir: dir.to_path_buf(), command: command.map(str::to_string) }
+    }
+
+    fn graph(dir: &std::path::Path, edges: &[(&str, &str)]) -> WorkspaceGraph {
+        let mut graph = WorkspaceGraph::new();
+        for name in ["app", "lib", "utils", "docs"] {
+            graph.add_package(name, dir.to_path_buf());
+        }
+        for (from, to) in edges {
+            graph.add_dependency(from, to);
+        }
+        graph
+    }
+
+    #[tokio::test]
+    async fn test_dependencies_run_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let graph = graph(dir.path(), &[("app", "lib"), ("lib", "utils")]);
+        let tasks = [
+            task(dir.path(), "app", Some("echo app >> order")),
+            task(dir.path(), "lib", None),
+            task(dir.path(), "utils", Some("sleep 0.2; echo utils >> order")),
+            task(dir.path(), "docs", Some("echo docs >> order")),
+        ];
+
+        let results = WorkspaceRunner::new(4).run(&graph, &tasks).await.unwrap();
+        assert!(results.iter().all(|r| r.status.is_success()));
+
+        // docs waits for nothing, so it does not wait for the slow utils either
+        let order = std::fs::read_to_string(dir.path().join("order")).unwrap();
+        assert_eq!(order.lines().collect::<Vec<_>>(), ["docs", "utils", "app"]);
+    }
+
+    #[tokio::test]
+    async fn test_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let graph = graph(dir.path(), &[("app", "lib")]);
+        let tasks = [
+            task(dir.path(), "app", Some("true")),
+            task(dir.path(), "lib", Some("exit 3")),
+            task(dir.path(), "docs", Some("sleep 0.2")),
+        ];
+        let status = |results: &[TaskResult], name: &str| results.iter().find(|r| r.name == name).unwrap().status;
+
+        let results = WorkspaceRunner::new(4).with_fail_fast(false).run(&graph, &tasks).await.unwrap();
+        assert_eq!(status(&results, "lib"), TaskStatus::Failed { exit_code: Some(3) });
+        assert_eq!(status(&results, "app"), TaskStatus::Skipped);
+        assert_eq!(status(&results, "docs"), TaskStatus::Succeeded);
+
+        let results = WorkspaceRunner::new(4).run(&graph, &tasks).await.unwrap();
+        assert_eq!(status(&results, "app"), TaskStatus::Skipped);
+        assert_eq!(status(&results, "docs"), TaskStatus::Cancelled);
+    }
+}
@@ -0,0 +1,17 @@
+//! Helpers shared by the integration tests
+
+use std::path::Path;
+
+use assert_cmd::Command;
+
+/// `velocity` run in `dir`, isolated from the user's cache, config and credentials
+pub fn velocity(home: &Path, dir: &Path) -> Command {
+    let mut command = Command::cargo_bin("velocity").unwrap();
+    command
+        .current_dir(dir)
+        .env("VELOCITY_CACHE_DIR", home.join("cache"))
+        .env("VELOCITY_USER_CONFIG", home.join("config.toml"))
+        .env("VELOCITY_CREDENTIALS_PATH", home.join("credentials.toml"))
+        .env("VELOCITY_OFFLINE", "1");
+    command
+}
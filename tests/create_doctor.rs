@@ -0,0 +1,38 @@
+//! A project scaffolded for another package manager passes `velocity doctor`
+
+mod common;
+
+use common::velocity;
+
+/// The "Package managers" check from `velocity --json doctor` run in `dir`
+fn package_manager_check(home: &std::path::Path, dir: &std::path::Path, args: &[&str]) -> serde_json::Value {
+    let output = velocity(home, dir).args(["--json", "doctor"]).args(args).output().unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|check| check["name"] == "Package managers")
+        .cloned()
+        .unwrap()
+}
+
+#[test]
+fn test_create_with_npm_then_doctor() {
+    let home = tempfile::tempdir().unwrap();
+    velocity(home.path(), home.path())
+        .args(["create", "react", "--name", "app", "--pm", "npm", "--no-git", "--yes"])
+        .assert()
+        .success();
+
+    let app = home.path().join("app");
+    assert!(!app.join("velocity.lock").exists());
+    let manifest = std::fs::read_to_string(app.join("package.json")).unwrap();
+
+    let check = package_manager_check(home.path(), &app, &[]);
+    assert_eq!(check["passed"], true, "{}", check);
+
+    // --fix leaves the chosen package manager alone
+    package_manager_check(home.path(), &app, &["--fix", "--yes"]);
+    assert_eq!(std::fs::read_to_string(app.join("package.json")).unwrap(), manifest);
+}
@@ -0,0 +1,54 @@
+//! Commands run from a subdirectory act on the enclosing project
+
+mod common;
+
+use std::path::Path;
+
+use common::velocity;
+
+/// Write an npm-style tarball holding only a package.json
+fn write_tarball(path: &Path, name: &str, version: &str) {
+    let manifest = format!(r#"{{"name": "{}", "version": "{}"}}"#, name, version);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+
+    let file = std::fs::File::create(path).unwrap();
+    let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(file, flate2::Compression::default()));
+    archive.append_data(&mut header, "package/package.json", manifest.as_bytes()).unwrap();
+    archive.into_inner().unwrap().finish().unwrap();
+}
+
+#[test]
+fn test_install_add_list_from_subdirectory() {
+    let home = tempfile::tempdir().unwrap();
+    let root = home.path().join("app");
+    let nested = root.join("src").join("components");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::write(
+        root.join("package.json"),
+        r#"{"name": "app", "version": "1.0.0", "dependencies": {"left": "file:left-1.0.0.tgz"}}"#,
+    )
+    .unwrap();
+    write_tarball(&root.join("left-1.0.0.tgz"), "left", "1.0.0");
+    write_tarball(&root.join("right-2.0.0.tgz"), "right", "2.0.0");
+
+    velocity(home.path(), &nested).arg("install").assert().success();
+    assert!(root.join("velocity.lock").is_file());
+    assert!(root.join("node_modules/left/package.json").is_file());
+    assert!(!nested.join("velocity.lock").exists() && !nested.join("node_modules").exists());
+
+    velocity(home.path(), &nested).args(["add", "file:right-2.0.0.tgz"]).assert().success();
+    let manifest = std::fs::read_to_string(root.join("package.json")).unwrap();
+    assert!(manifest.contains(r#""right": "file:right-2.0.0.tgz""#), "{}", manifest);
+    assert!(!nested.join("package.json").exists());
+    assert!(root.join("node_modules/right/package.json").is_file());
+
+    let output = velocity(home.path(), &nested).args(["--json", "list"]).output().unwrap();
+    assert!(output.status.success());
+    let tree: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(tree["name"], "app");
+    let names: Vec<&str> = tree["dependencies"].as_array().unwrap().iter().filter_map(|d| d["name"].as_str()).collect();
+    assert_eq!(names, ["left", "right"]);
+}